@@ -0,0 +1,180 @@
+import type { Principal } from '@dfinity/principal';
+import type { ActorMethod } from '@dfinity/agent';
+import type { IDL } from '@dfinity/candid';
+
+export interface Answer {
+  'text' : string,
+  'diagnostics' : Diagnostics,
+  'fallback' : [] | [FallbackStrategy],
+  'sources' : Array<SearchResult>,
+  'confidence' : Confidence,
+}
+export interface AskRequest {
+  'k' : [] | [number],
+  'question' : string,
+  'namespace' : string,
+  'profile' : [] | [string],
+}
+export interface BatchWriteReport {
+  'stored' : bigint,
+  'skipped' : bigint,
+  'failed' : bigint,
+  'results' : Array<VectorWriteResult>,
+}
+export interface Chunk {
+  'document_id' : string,
+  'text' : string,
+  'index' : number,
+}
+export interface Collection {
+  'id' : string,
+  'document_ids' : Array<string>,
+  'name' : string,
+  'description' : [] | [string],
+}
+export interface Confidence {
+  'low' : boolean,
+  'self_assessment' : [] | [number],
+  'score' : number,
+  'coverage' : number,
+  'retrieval' : number,
+}
+export interface Diagnostics {
+  'routing' : [] | [RoutingDecision],
+  'namespace' : string,
+  'profile' : [] | [string],
+}
+export interface Document {
+  'id' : string,
+  'title' : string,
+  'content' : string,
+  'metadata' : Array<[string, string]>,
+  'source' : [] | [string],
+  'collection_id' : [] | [string],
+}
+export type DuplicateIdMode = { 'Skip' : null } |
+  { 'Replace' : null };
+export type FallbackStrategy = { 'Broaden' : null } |
+  { 'Refuse' : null } |
+  { 'AnswerWithoutContext' : null };
+export interface ImportVectorsRequest {
+  'on_duplicate' : [] | [DuplicateIdMode],
+  'vectors' : Array<Vector>,
+  'namespace' : string,
+}
+export interface IngestDocumentResponse {
+  'chunks' : number,
+  'namespace' : string,
+}
+export interface IngestRequest {
+  'entity_id' : string,
+  'priority' : [] | [IngestionPriority],
+  'idempotency_key' : [] | [string],
+  'namespace' : [] | [string],
+  'entity_type' : string,
+}
+export interface IngestResponse { 'job_id' : bigint }
+export type IngestionPriority = { 'Interactive' : null } |
+  { 'Bulk' : null } |
+  { 'Normal' : null };
+export type JobState = { 'Failed' : null } |
+  { 'Queued' : null } |
+  { 'Running' : null } |
+  { 'Completed' : null };
+export interface JobStatus {
+  'job_id' : bigint,
+  'chunks_stored' : number,
+  'entity_id' : string,
+  'error' : [] | [string],
+  'attempts' : number,
+  'state' : JobState,
+  'updated_at' : bigint,
+  'namespace' : string,
+  'entity_type' : string,
+}
+export interface MaintainIndexResponse { 'finished' : boolean }
+export interface PrefixStats {
+  'size_bytes' : bigint,
+  'vectors' : bigint,
+  'prefix' : string,
+  'namespaces' : bigint,
+}
+export interface RoutingDecision {
+  'method' : RoutingMethod,
+  'classifier_reply' : [] | [string],
+  'matched_keyword' : [] | [string],
+  'profile' : [] | [string],
+}
+export type RoutingMethod = { 'Default' : null } |
+  { 'Keyword' : null } |
+  { 'Classifier' : null };
+export interface SearchRequest {
+  'k' : [] | [number],
+  'allow_stale' : [] | [boolean],
+  'max_chunks_per_entity' : [] | [number],
+  'query' : string,
+  'namespace' : string,
+}
+export interface SearchResponse {
+  'stale' : boolean,
+  'namespace' : string,
+  'results' : Array<SearchResult>,
+}
+export interface SearchResult {
+  'metadata' : VectorMetadata,
+  'text' : string,
+  'score' : number,
+  'vector_id' : string,
+}
+export interface Vector {
+  'id' : string,
+  'metadata' : VectorMetadata,
+  'text' : string,
+  'embedding' : Array<number>,
+}
+export interface VectorMetadata {
+  'total_chunks' : bigint,
+  'custom' : [] | [string],
+  'ttl_seconds' : [] | [bigint],
+  'entity_id' : string,
+  'timestamp' : bigint,
+  'chunk_index' : bigint,
+  'entity_type' : string,
+}
+export interface VectorWriteResult {
+  'error' : [] | [string],
+  'vector_id' : string,
+  'duplicate_of' : [] | [string],
+}
+export interface _SERVICE {
+  'ask' : ActorMethod<[AskRequest], { 'Ok' : Answer } | { 'Err' : string }>,
+  'ingest' : ActorMethod<
+    [IngestRequest],
+    { 'Ok' : IngestResponse } |
+      { 'Err' : string }
+  >,
+  'ingest_document' : ActorMethod<
+    [Document],
+    { 'Ok' : IngestDocumentResponse } |
+      { 'Err' : string }
+  >,
+  'import_vectors' : ActorMethod<
+    [ImportVectorsRequest],
+    { 'Ok' : BatchWriteReport } |
+      { 'Err' : string }
+  >,
+  'job_status' : ActorMethod<[bigint], { 'Ok' : JobStatus } | { 'Err' : string }>,
+  'maintain_index' : ActorMethod<
+    [[] | [number]],
+    { 'Ok' : MaintainIndexResponse } |
+      { 'Err' : string }
+  >,
+  'search' : ActorMethod<
+    [SearchRequest],
+    { 'Ok' : SearchResponse } |
+      { 'Err' : string }
+  >,
+  'stats' : ActorMethod<[[] | [string]], { 'Ok' : PrefixStats } | { 'Err' : string }>,
+}
+export declare const idlFactory: IDL.InterfaceFactory;
+export declare const init: (args: { IDL: typeof IDL }) => IDL.Type[];
@@ -0,0 +1,185 @@
+export const idlFactory = ({ IDL }) => {
+  const IngestionPriority = IDL.Variant({
+    'Interactive' : IDL.Null,
+    'Bulk' : IDL.Null,
+    'Normal' : IDL.Null,
+  });
+  const IngestRequest = IDL.Record({
+    'entity_id' : IDL.Text,
+    'priority' : IDL.Opt(IngestionPriority),
+    'idempotency_key' : IDL.Opt(IDL.Text),
+    'namespace' : IDL.Opt(IDL.Text),
+    'entity_type' : IDL.Text,
+  });
+  const IngestResponse = IDL.Record({ 'job_id' : IDL.Nat64 });
+  const Document = IDL.Record({
+    'id' : IDL.Text,
+    'title' : IDL.Text,
+    'content' : IDL.Text,
+    'metadata' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
+    'source' : IDL.Opt(IDL.Text),
+    'collection_id' : IDL.Opt(IDL.Text),
+  });
+  const IngestDocumentResponse = IDL.Record({
+    'chunks' : IDL.Nat32,
+    'namespace' : IDL.Text,
+  });
+  const SearchRequest = IDL.Record({
+    'k' : IDL.Opt(IDL.Nat32),
+    'allow_stale' : IDL.Opt(IDL.Bool),
+    'max_chunks_per_entity' : IDL.Opt(IDL.Nat32),
+    'query' : IDL.Text,
+    'namespace' : IDL.Text,
+  });
+  const VectorMetadata = IDL.Record({
+    'total_chunks' : IDL.Nat64,
+    'custom' : IDL.Opt(IDL.Text),
+    'ttl_seconds' : IDL.Opt(IDL.Nat64),
+    'entity_id' : IDL.Text,
+    'timestamp' : IDL.Nat64,
+    'chunk_index' : IDL.Nat64,
+    'entity_type' : IDL.Text,
+  });
+  const SearchResult = IDL.Record({
+    'metadata' : VectorMetadata,
+    'text' : IDL.Text,
+    'score' : IDL.Float32,
+    'vector_id' : IDL.Text,
+  });
+  const SearchResponse = IDL.Record({
+    'stale' : IDL.Bool,
+    'namespace' : IDL.Text,
+    'results' : IDL.Vec(SearchResult),
+  });
+  const DuplicateIdMode = IDL.Variant({ 'Skip' : IDL.Null, 'Replace' : IDL.Null });
+  const Vector = IDL.Record({
+    'id' : IDL.Text,
+    'metadata' : VectorMetadata,
+    'text' : IDL.Text,
+    'embedding' : IDL.Vec(IDL.Float32),
+  });
+  const ImportVectorsRequest = IDL.Record({
+    'on_duplicate' : IDL.Opt(DuplicateIdMode),
+    'vectors' : IDL.Vec(Vector),
+    'namespace' : IDL.Text,
+  });
+  const VectorWriteResult = IDL.Record({
+    'error' : IDL.Opt(IDL.Text),
+    'vector_id' : IDL.Text,
+    'duplicate_of' : IDL.Opt(IDL.Text),
+  });
+  const BatchWriteReport = IDL.Record({
+    'stored' : IDL.Nat64,
+    'skipped' : IDL.Nat64,
+    'failed' : IDL.Nat64,
+    'results' : IDL.Vec(VectorWriteResult),
+  });
+  const AskRequest = IDL.Record({
+    'k' : IDL.Opt(IDL.Nat32),
+    'question' : IDL.Text,
+    'namespace' : IDL.Text,
+    'profile' : IDL.Opt(IDL.Text),
+  });
+  const FallbackStrategy = IDL.Variant({
+    'Broaden' : IDL.Null,
+    'Refuse' : IDL.Null,
+    'AnswerWithoutContext' : IDL.Null,
+  });
+  const Confidence = IDL.Record({
+    'low' : IDL.Bool,
+    'self_assessment' : IDL.Opt(IDL.Float32),
+    'score' : IDL.Float32,
+    'coverage' : IDL.Float32,
+    'retrieval' : IDL.Float32,
+  });
+  const RoutingMethod = IDL.Variant({
+    'Default' : IDL.Null,
+    'Keyword' : IDL.Null,
+    'Classifier' : IDL.Null,
+  });
+  const RoutingDecision = IDL.Record({
+    'method' : RoutingMethod,
+    'classifier_reply' : IDL.Opt(IDL.Text),
+    'matched_keyword' : IDL.Opt(IDL.Text),
+    'profile' : IDL.Opt(IDL.Text),
+  });
+  const Diagnostics = IDL.Record({
+    'routing' : IDL.Opt(RoutingDecision),
+    'namespace' : IDL.Text,
+    'profile' : IDL.Opt(IDL.Text),
+  });
+  const Answer = IDL.Record({
+    'text' : IDL.Text,
+    'diagnostics' : Diagnostics,
+    'fallback' : IDL.Opt(FallbackStrategy),
+    'sources' : IDL.Vec(SearchResult),
+    'confidence' : Confidence,
+  });
+  const PrefixStats = IDL.Record({
+    'size_bytes' : IDL.Nat64,
+    'vectors' : IDL.Nat64,
+    'prefix' : IDL.Text,
+    'namespaces' : IDL.Nat64,
+  });
+  const JobState = IDL.Variant({
+    'Failed' : IDL.Null,
+    'Queued' : IDL.Null,
+    'Running' : IDL.Null,
+    'Completed' : IDL.Null,
+  });
+  const JobStatus = IDL.Record({
+    'job_id' : IDL.Nat64,
+    'chunks_stored' : IDL.Nat32,
+    'entity_id' : IDL.Text,
+    'error' : IDL.Opt(IDL.Text),
+    'attempts' : IDL.Nat32,
+    'state' : JobState,
+    'updated_at' : IDL.Nat64,
+    'namespace' : IDL.Text,
+    'entity_type' : IDL.Text,
+  });
+  const MaintainIndexResponse = IDL.Record({ 'finished' : IDL.Bool });
+  return IDL.Service({
+    'ask' : IDL.Func(
+        [AskRequest],
+        [IDL.Variant({ 'Ok' : Answer, 'Err' : IDL.Text })],
+        [],
+      ),
+    'ingest' : IDL.Func(
+        [IngestRequest],
+        [IDL.Variant({ 'Ok' : IngestResponse, 'Err' : IDL.Text })],
+        [],
+      ),
+    'ingest_document' : IDL.Func(
+        [Document],
+        [IDL.Variant({ 'Ok' : IngestDocumentResponse, 'Err' : IDL.Text })],
+        [],
+      ),
+    'import_vectors' : IDL.Func(
+        [ImportVectorsRequest],
+        [IDL.Variant({ 'Ok' : BatchWriteReport, 'Err' : IDL.Text })],
+        [],
+      ),
+    'job_status' : IDL.Func(
+        [IDL.Nat64],
+        [IDL.Variant({ 'Ok' : JobStatus, 'Err' : IDL.Text })],
+        ['query'],
+      ),
+    'maintain_index' : IDL.Func(
+        [IDL.Opt(IDL.Nat32)],
+        [IDL.Variant({ 'Ok' : MaintainIndexResponse, 'Err' : IDL.Text })],
+        [],
+      ),
+    'search' : IDL.Func(
+        [SearchRequest],
+        [IDL.Variant({ 'Ok' : SearchResponse, 'Err' : IDL.Text })],
+        [],
+      ),
+    'stats' : IDL.Func(
+        [IDL.Opt(IDL.Text)],
+        [IDL.Variant({ 'Ok' : PrefixStats, 'Err' : IDL.Text })],
+        ['query'],
+      ),
+  });
+};
+export const init = ({ IDL }) => { return []; };
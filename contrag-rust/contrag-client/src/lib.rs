@@ -0,0 +1,224 @@
+//! Off-chain client for canisters exposing the standard contrag endpoints
+//!
+//! Wraps an [`ic_agent::Agent`] with typed methods for the endpoints in
+//! [`contrag_core::api::methods`], so backend services and the CLI don't
+//! hand-encode candid arguments.
+//!
+//! ```rust,no_run
+//! use contrag_client::ContragClient;
+//! use contrag_core::api::SearchRequest;
+//!
+//! # async fn run() -> contrag_client::Result<()> {
+//! let client = ContragClient::connect("http://127.0.0.1:4943", "bkyz2-fmaaa-aaaaa-qaaaq-cai").await?;
+//! let response = client
+//!     .search(&SearchRequest {
+//!         namespace: "User:42".to_string(),
+//!         query: "recent orders".to_string(),
+//!         k: Some(5),
+//!         max_chunks_per_entity: Some(2),
+//!         allow_stale: None,
+//!     })
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_agent::{Agent, Identity};
+use serde::de::DeserializeOwned;
+use contrag_core::api::{
+    methods, Answer, AskRequest, Document, ImportVectorsRequest, ImportVectorsResponse, IngestDocumentResponse,
+    IngestRequest, IngestResponse, JobStatus, MaintainIndexResponse, SearchRequest, SearchResponse, StatsResponse,
+    MAX_IMPORT_REQUEST_BYTES,
+};
+use contrag_core::types::{DuplicateIdMode, Vector};
+
+pub use contrag_core::api;
+
+/// Errors returned by [`ContragClient`]
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("Agent error: {0}")]
+    Agent(#[from] ic_agent::AgentError),
+
+    #[error("Candid error: {0}")]
+    Candid(#[from] candid::Error),
+
+    #[error("Invalid canister ID: {0}")]
+    InvalidCanisterId(String),
+
+    /// The canister returned `Err` from the endpoint
+    #[error("Canister error: {0}")]
+    Canister(String),
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// Typed client for one contrag canister
+#[derive(Clone)]
+pub struct ContragClient {
+    agent: Agent,
+    canister_id: Principal,
+}
+
+impl ContragClient {
+    pub fn new(agent: Agent, canister_id: Principal) -> Self {
+        Self { agent, canister_id }
+    }
+
+    /// Connect anonymously to a replica or boundary node
+    ///
+    /// Fetches the root key when the URL points at a local replica.
+    pub async fn connect(url: &str, canister_id: &str) -> Result<Self> {
+        Self::connect_with_identity(url, canister_id, ic_agent::identity::AnonymousIdentity).await
+    }
+
+    /// Connect with an identity, e.g. one loaded from a dfx PEM file
+    pub async fn connect_with_identity<I: Identity + 'static>(
+        url: &str,
+        canister_id: &str,
+        identity: I,
+    ) -> Result<Self> {
+        let canister_id = Principal::from_text(canister_id)
+            .map_err(|e| ClientError::InvalidCanisterId(e.to_string()))?;
+        let agent = Agent::builder()
+            .with_url(url)
+            .with_identity(identity)
+            .build()?;
+
+        if is_local(url) {
+            agent.fetch_root_key().await?;
+        }
+
+        Ok(Self::new(agent, canister_id))
+    }
+
+    pub fn canister_id(&self) -> Principal {
+        self.canister_id
+    }
+
+    pub fn agent(&self) -> &Agent {
+        &self.agent
+    }
+
+    /// Queue an entity for (re-)indexing
+    pub async fn ingest(&self, request: &IngestRequest) -> Result<IngestResponse> {
+        self.update(methods::INGEST, Encode!(request)?).await
+    }
+
+    /// Index a document right away, without going through the job queue
+    pub async fn ingest_document(&self, document: &Document) -> Result<IngestDocumentResponse> {
+        self.update(methods::INGEST_DOCUMENT, Encode!(document)?).await
+    }
+
+    /// Store vectors embedded off-chain, split into as many
+    /// `import_vectors` calls as their size requires
+    ///
+    /// Calls run one after another; on an error the vectors of earlier calls
+    /// stay stored, and the import can be repeated with the default
+    /// [`DuplicateIdMode::Skip`] to fill in the rest.
+    pub async fn import_vectors(
+        &self,
+        namespace: &str,
+        vectors: Vec<Vector>,
+        on_duplicate: Option<DuplicateIdMode>,
+    ) -> Result<ImportVectorsResponse> {
+        let mut report = ImportVectorsResponse::default();
+        for request in ImportVectorsRequest::split(namespace, vectors, on_duplicate, MAX_IMPORT_REQUEST_BYTES) {
+            let batch: ImportVectorsResponse = self.update(methods::IMPORT_VECTORS, Encode!(&request)?).await?;
+            report.merge(batch);
+        }
+        Ok(report)
+    }
+
+    /// Search a namespace
+    pub async fn search(&self, request: &SearchRequest) -> Result<SearchResponse> {
+        self.update(methods::SEARCH, Encode!(request)?).await
+    }
+
+    /// Answer a question from a namespace's context
+    pub async fn ask(&self, request: &AskRequest) -> Result<Answer> {
+        self.update(methods::ASK, Encode!(request)?).await
+    }
+
+    /// Size of the index, optionally restricted to a namespace prefix
+    pub async fn stats(&self, prefix: Option<String>) -> Result<StatsResponse> {
+        self.query(methods::STATS, Encode!(&prefix)?).await
+    }
+
+    /// Status of an ingestion job returned by [`ContragClient::ingest`]
+    pub async fn job_status(&self, job_id: u64) -> Result<JobStatus> {
+        self.query(methods::JOB_STATUS, Encode!(&job_id)?).await
+    }
+
+    /// Do a bounded amount of index upkeep, at most `budget` vectors' worth;
+    /// call it again until the reply is `finished`
+    pub async fn maintain_index(&self, budget: Option<u32>) -> Result<MaintainIndexResponse> {
+        self.update(methods::MAINTAIN_INDEX, Encode!(&budget)?).await
+    }
+
+    async fn query<T>(&self, method: &str, arg: Vec<u8>) -> Result<T>
+    where
+        T: CandidType + DeserializeOwned,
+    {
+        let reply = self
+            .agent
+            .query(&self.canister_id, method)
+            .with_arg(arg)
+            .call()
+            .await?;
+        decode_reply(&reply)
+    }
+
+    async fn update<T>(&self, method: &str, arg: Vec<u8>) -> Result<T>
+    where
+        T: CandidType + DeserializeOwned,
+    {
+        let reply = self
+            .agent
+            .update(&self.canister_id, method)
+            .with_arg(arg)
+            .call_and_wait()
+            .await?;
+        decode_reply(&reply)
+    }
+}
+
+/// Decode a `Result<T, String>` reply
+fn decode_reply<T>(reply: &[u8]) -> Result<T>
+where
+    T: CandidType + DeserializeOwned,
+{
+    Decode!(reply, std::result::Result<T, String>)?.map_err(ClientError::Canister)
+}
+
+fn is_local(url: &str) -> bool {
+    url.contains("127.0.0.1") || url.contains("localhost")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use contrag_core::api::JobState;
+
+    #[test]
+    fn test_decode_reply() {
+        let status = JobStatus {
+            job_id: 7,
+            state: JobState::Completed,
+            entity_type: "User".to_string(),
+            entity_id: "42".to_string(),
+            namespace: "User:42".to_string(),
+            attempts: 1,
+            chunks_stored: 3,
+            error: None,
+            updated_at: 0,
+        };
+
+        let ok = Encode!(&std::result::Result::<JobStatus, String>::Ok(status)).unwrap();
+        assert_eq!(decode_reply::<JobStatus>(&ok).unwrap().chunks_stored, 3);
+
+        let err = Encode!(&std::result::Result::<JobStatus, String>::Err("unknown job".to_string())).unwrap();
+        assert!(matches!(decode_reply::<JobStatus>(&err), Err(ClientError::Canister(m)) if m == "unknown job"));
+    }
+}
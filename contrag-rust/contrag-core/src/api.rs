@@ -0,0 +1,264 @@
+//! Request and response types of the standard contrag canister endpoints
+//!
+//! Canisters exposing contrag should use these types and the method names in
+//! [`methods`] so off-chain clients (`contrag-client`, the CLI, web dapps)
+//! can talk to any of them. Every endpoint returns `Result<T, String>`.
+//!
+//! The canonical candid interface is [`CANDID_INTERFACE`] (`contrag.did` in
+//! the crate root); TypeScript bindings are generated from it by
+//! `scripts/generate-bindings.sh`.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::queue::IngestionPriority;
+use crate::types::{BatchWriteReport, DuplicateIdMode, PrefixStats, SearchResult, Vector};
+
+pub use crate::documents::{Chunk, Collection, Document};
+pub use crate::pipeline::Answer;
+
+/// Candid interface of the endpoints below
+pub const CANDID_INTERFACE: &str = include_str!("../contrag.did");
+
+/// Canister method names
+pub mod methods {
+    /// `(IngestRequest) -> (Result<IngestResponse, String>)`, update
+    pub const INGEST: &str = "ingest";
+    /// `(SearchRequest) -> (Result<SearchResponse, String>)`, update
+    pub const SEARCH: &str = "search";
+    /// `(AskRequest) -> (Result<Answer, String>)`, update
+    pub const ASK: &str = "ask";
+    /// `(opt text) -> (Result<PrefixStats, String>)`, query
+    pub const STATS: &str = "stats";
+    /// `(nat64) -> (Result<JobStatus, String>)`, query
+    pub const JOB_STATUS: &str = "job_status";
+    /// `(Document) -> (Result<IngestDocumentResponse, String>)`, update
+    pub const INGEST_DOCUMENT: &str = "ingest_document";
+    /// `(ImportVectorsRequest) -> (Result<BatchWriteReport, String>)`, update
+    pub const IMPORT_VECTORS: &str = "import_vectors";
+    /// `(opt nat32) -> (Result<MaintainIndexResponse, String>)`, update
+    pub const MAINTAIN_INDEX: &str = "maintain_index";
+}
+
+/// Payload budget of one `import_vectors` call, below the 2 MiB limit on
+/// ingress messages
+pub const MAX_IMPORT_REQUEST_BYTES: usize = 1_800_000;
+
+/// Ask the canister to (re-)index an entity
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct IngestRequest {
+    pub entity_type: String,
+    pub entity_id: String,
+    /// Target namespace; defaults to `{entity_type}:{entity_id}`
+    pub namespace: Option<String>,
+    pub priority: Option<IngestionPriority>,
+    /// Retries with the same key return the original job instead of
+    /// queueing the entity again
+    pub idempotency_key: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct IngestResponse {
+    /// Job to poll with `job_status`
+    pub job_id: u64,
+}
+
+/// Reply of `ingest_document`, which indexes the document right away
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct IngestDocumentResponse {
+    /// Namespace the document was indexed in
+    pub namespace: String,
+    pub chunks: u32,
+}
+
+/// Store vectors embedded off-chain, see
+/// [`import_vectors`](crate::vector_store::import::import_vectors)
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct ImportVectorsRequest {
+    pub namespace: String,
+    pub vectors: Vec<Vector>,
+    /// Handling of IDs that are already stored; skipped by default
+    pub on_duplicate: Option<DuplicateIdMode>,
+}
+
+impl ImportVectorsRequest {
+    /// Split an import into requests of at most `max_bytes` each (see
+    /// [`MAX_IMPORT_REQUEST_BYTES`]), to send one per update call
+    ///
+    /// Sizes are estimated; a vector larger than `max_bytes` gets a request
+    /// of its own.
+    pub fn split(
+        namespace: &str,
+        vectors: Vec<Vector>,
+        on_duplicate: Option<DuplicateIdMode>,
+        max_bytes: usize,
+    ) -> Vec<Self> {
+        let mut requests = vec![];
+        let mut batch = vec![];
+        let mut batch_bytes = 0;
+        for vector in vectors {
+            let bytes = encoded_len(&vector);
+            if !batch.is_empty() && batch_bytes + bytes > max_bytes {
+                requests.push(std::mem::take(&mut batch));
+                batch_bytes = 0;
+            }
+            batch_bytes += bytes;
+            batch.push(vector);
+        }
+        if !batch.is_empty() {
+            requests.push(batch);
+        }
+
+        requests
+            .into_iter()
+            .map(|vectors| Self {
+                namespace: namespace.to_string(),
+                vectors,
+                on_duplicate,
+            })
+            .collect()
+    }
+}
+
+/// Upper estimate of a vector's candid encoding
+fn encoded_len(vector: &Vector) -> usize {
+    let metadata = &vector.metadata;
+    vector.id.len()
+        + vector.text.len()
+        + vector.embedding.len() * std::mem::size_of::<f32>()
+        + metadata.entity_type.len()
+        + metadata.entity_id.len()
+        + metadata.custom.as_ref().map_or(0, String::len)
+        // Length prefixes, fixed-size fields and option tags
+        + 96
+}
+
+/// Reply of `import_vectors`
+pub type ImportVectorsResponse = BatchWriteReport;
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct SearchRequest {
+    pub namespace: String,
+    pub query: String,
+    pub k: Option<u32>,
+    /// Cap on hits per entity; overrides the pipeline config
+    pub max_chunks_per_entity: Option<u32>,
+    /// Serve cached results immediately and refresh them in the background
+    pub allow_stale: Option<bool>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct SearchResponse {
+    pub namespace: String,
+    pub results: Vec<SearchResult>,
+    /// Results came from a stale cache entry that is being refreshed
+    pub stale: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct AskRequest {
+    pub namespace: String,
+    pub question: String,
+    pub k: Option<u32>,
+    /// Retrieval profile from the pipeline config; `None` lets the
+    /// configured router pick one
+    pub profile: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+pub enum JobState {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct JobStatus {
+    pub job_id: u64,
+    pub state: JobState,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub namespace: String,
+    pub attempts: u32,
+    pub chunks_stored: u32,
+    pub error: Option<String>,
+    pub updated_at: u64,
+}
+
+/// Reply of the `stats` endpoint
+pub type StatsResponse = PrefixStats;
+
+/// Reply of `maintain_index`, which does a bounded amount of index upkeep
+/// (see [`VectorStore::maintain`](crate::vector_store::VectorStore::maintain))
+/// per call; the argument caps the vectors processed
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct MaintainIndexResponse {
+    /// No upkeep is left; call again until it is set
+    pub finished: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::test_vector;
+
+    /// Service of [`methods`] built from their Rust argument and reply types
+    fn rust_interface() -> String {
+        use candid::types::internal::TypeContainer;
+        use candid::types::{FuncMode, Function, Type, TypeInner};
+
+        let mut env = TypeContainer::new();
+        let mut service = Vec::<(String, Type)>::new();
+        macro_rules! method {
+            ($name:expr, $arg:ty, $reply:ty $(, $mode:expr)?) => {
+                let func = Function {
+                    args: vec![env.add::<$arg>()],
+                    rets: vec![env.add::<std::result::Result<$reply, String>>()],
+                    modes: vec![$($mode)?],
+                };
+                service.push(($name.to_string(), TypeInner::Func(func).into()));
+            };
+        }
+
+        method!(methods::INGEST, IngestRequest, IngestResponse);
+        method!(methods::INGEST_DOCUMENT, Document, IngestDocumentResponse);
+        method!(methods::IMPORT_VECTORS, ImportVectorsRequest, ImportVectorsResponse);
+        method!(methods::SEARCH, SearchRequest, SearchResponse);
+        method!(methods::ASK, AskRequest, Answer);
+        method!(methods::STATS, Option<String>, StatsResponse, FuncMode::Query);
+        method!(methods::JOB_STATUS, u64, JobStatus, FuncMode::Query);
+        method!(methods::MAINTAIN_INDEX, Option<u32>, MaintainIndexResponse);
+
+        service.sort_unstable_by_key(|(name, _)| name.clone());
+        candid::pretty::candid::compile(&env.env, &Some(TypeInner::Service(service).into()))
+    }
+
+    #[test]
+    fn test_candid_interface_matches_api_types() {
+        use candid_parser::utils::{service_equal, CandidSource};
+
+        let rust = rust_interface();
+        if let Err(e) = service_equal(CandidSource::Text(&rust), CandidSource::Text(CANDID_INTERFACE)) {
+            panic!("contrag.did doesn't match the api types: {}\nExpected:\n{}", e, rust);
+        }
+    }
+
+    #[test]
+    fn test_split_import_requests() {
+        let vector = |id: usize| Vector {
+            text: "x".repeat(100),
+            ..test_vector(&id.to_string(), vec![0.0; 100])
+        };
+
+        let replace = Some(DuplicateIdMode::Replace);
+        let requests = ImportVectorsRequest::split("docs", (0..10).map(vector).collect(), replace, 2000);
+        assert!(requests.len() > 1);
+        assert!(requests.iter().all(|r| r.namespace == "docs" && !r.vectors.is_empty()));
+        assert!(requests.iter().all(|r| r.on_duplicate == replace));
+        assert!(requests.iter().all(|r| r.vectors.iter().map(encoded_len).sum::<usize>() <= 2000));
+        assert_eq!(requests.iter().map(|r| r.vectors.len()).sum::<usize>(), 10);
+
+        assert_eq!(ImportVectorsRequest::split("docs", vec![vector(0)], None, 10).len(), 1);
+        assert!(ImportVectorsRequest::split("docs", vec![], None, 10).is_empty());
+    }
+}
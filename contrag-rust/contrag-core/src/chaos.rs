@@ -0,0 +1,176 @@
+//! Fault injection for resilience testing
+//!
+//! Only compiled with the `chaos` feature. Never enable it in production
+//! builds: once [`enable`] is called, `HttpClient` and `CanisterStateSource`
+//! randomly fail or slow down calls according to the configured rates.
+
+use std::cell::RefCell;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::error::{ContragError, Result};
+
+/// Fault injection rates (each in `0.0..=1.0`)
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct ChaosConfig {
+    /// Probability that an HTTP outcall fails before being sent
+    pub outcall_failure_rate: f64,
+
+    /// Probability that an inter-canister call is delayed
+    pub slow_call_rate: f64,
+
+    /// Extra consensus rounds to wait when a call is delayed
+    pub slow_call_rounds: u32,
+
+    /// Probability that a single entity in a batch read fails
+    pub batch_item_failure_rate: f64,
+
+    /// Seed for the deterministic random generator
+    pub seed: u64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self {
+            outcall_failure_rate: 0.1,
+            slow_call_rate: 0.1,
+            slow_call_rounds: 2,
+            batch_item_failure_rate: 0.1,
+            seed: 42,
+        }
+    }
+}
+
+/// Counters of injected faults
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct ChaosStats {
+    pub outcall_failures: u64,
+    pub slow_calls: u64,
+    pub batch_item_failures: u64,
+}
+
+struct ChaosState {
+    config: ChaosConfig,
+    rng: u64,
+    stats: ChaosStats,
+}
+
+impl ChaosState {
+    /// xorshift64* step mapped to `0.0..1.0`
+    fn roll(&mut self) -> f64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let value = self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (value >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+thread_local! {
+    static STATE: RefCell<Option<ChaosState>> = RefCell::new(None);
+}
+
+/// Start injecting faults with the given configuration
+pub fn enable(config: ChaosConfig) {
+    let rng = config.seed.max(1);
+    STATE.with(|s| {
+        *s.borrow_mut() = Some(ChaosState {
+            config,
+            rng,
+            stats: ChaosStats::default(),
+        });
+    });
+}
+
+/// Stop injecting faults
+pub fn disable() {
+    STATE.with(|s| *s.borrow_mut() = None);
+}
+
+/// Faults injected since the last [`enable`]
+pub fn stats() -> ChaosStats {
+    STATE.with(|s| {
+        s.borrow()
+            .as_ref()
+            .map(|state| state.stats.clone())
+            .unwrap_or_default()
+    })
+}
+
+/// Roll against `rate`, bumping the matching counter on a hit
+fn inject(rate: impl Fn(&ChaosConfig) -> f64, count: impl Fn(&mut ChaosStats)) -> bool {
+    STATE.with(|s| match s.borrow_mut().as_mut() {
+        Some(state) => {
+            let hit = state.roll() < rate(&state.config);
+            if hit {
+                count(&mut state.stats);
+            }
+            hit
+        }
+        None => false,
+    })
+}
+
+/// Possibly fail an HTTP outcall to `url`
+pub(crate) fn outcall(url: &str) -> Result<()> {
+    if inject(|c| c.outcall_failure_rate, |s| s.outcall_failures += 1) {
+        return Err(ContragError::HttpOutcallError(format!(
+            "chaos: injected outcall failure for {}",
+            url
+        )));
+    }
+    Ok(())
+}
+
+/// Possibly fail one item of a batch read
+pub(crate) fn batch_item(entity_type: &str, entity_id: &str) -> Result<()> {
+    if inject(|c| c.batch_item_failure_rate, |s| s.batch_item_failures += 1) {
+        return Err(ContragError::CanisterCallError(format!(
+            "chaos: injected failure for {} {}",
+            entity_type, entity_id
+        )));
+    }
+    Ok(())
+}
+
+/// Possibly delay an inter-canister call by a few consensus rounds
+pub(crate) async fn slow_call() {
+    let rounds = STATE.with(|s| {
+        s.borrow()
+            .as_ref()
+            .map(|state| state.config.slow_call_rounds)
+            .unwrap_or(0)
+    });
+
+    if rounds == 0 || !inject(|c| c.slow_call_rate, |s| s.slow_calls += 1) {
+        return;
+    }
+
+    #[cfg(target_family = "wasm")]
+    {
+        // Each management canister call completes in a later round
+        for _ in 0..rounds {
+            let _ = ic_cdk::api::management_canister::main::raw_rand().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_injection_rates() {
+        enable(ChaosConfig {
+            outcall_failure_rate: 1.0,
+            batch_item_failure_rate: 0.0,
+            ..ChaosConfig::default()
+        });
+
+        assert!(outcall("https://example.com").is_err());
+        assert!(batch_item("User", "1").is_ok());
+        assert_eq!(stats().outcall_failures, 1);
+
+        disable();
+        assert!(outcall("https://example.com").is_ok());
+    }
+}
@@ -0,0 +1,97 @@
+use std::cell::RefCell;
+use crate::config::ConcurrencyConfig;
+use crate::error::{ContragError, Result};
+
+/// Global cap on outstanding outcalls
+///
+/// Canisters have limits on concurrent HTTP outcalls and on the memory held
+/// by open call contexts. Every embedder request and inter-canister fetch
+/// takes a permit from this shared counter, and fails fast with
+/// `ContragError::ConcurrencyLimitReached` once the cap is hit, so bulk jobs
+/// can requeue work instead of running into `SysTransient` rejections.
+struct CallLimiter {
+    max_in_flight: usize,
+    in_flight: usize,
+}
+
+thread_local! {
+    static LIMITER: RefCell<CallLimiter> = RefCell::new(CallLimiter {
+        max_in_flight: ConcurrencyConfig::default().max_concurrent_calls,
+        in_flight: 0,
+    });
+}
+
+/// Apply concurrency limits from configuration
+///
+/// Call this during canister init, post_upgrade, or whenever the config changes.
+/// Calls already in flight keep their permits.
+pub fn configure(config: &ConcurrencyConfig) {
+    LIMITER.with(|l| l.borrow_mut().max_in_flight = config.max_concurrent_calls.max(1));
+}
+
+/// Acquire a permit for one outgoing call
+///
+/// The permit is released when dropped, including when the awaiting future is
+/// cancelled or the call traps after an await point.
+pub fn acquire() -> Result<CallPermit> {
+    LIMITER.with(|l| {
+        let mut limiter = l.borrow_mut();
+        if limiter.in_flight >= limiter.max_in_flight {
+            return Err(ContragError::ConcurrencyLimitReached {
+                limit: limiter.max_in_flight,
+            });
+        }
+        limiter.in_flight += 1;
+        Ok(CallPermit { _private: () })
+    })
+}
+
+/// Number of calls currently holding a permit
+pub fn in_flight() -> usize {
+    LIMITER.with(|l| l.borrow().in_flight)
+}
+
+/// Number of permits still available
+pub fn available() -> usize {
+    LIMITER.with(|l| {
+        let limiter = l.borrow();
+        limiter.max_in_flight.saturating_sub(limiter.in_flight)
+    })
+}
+
+/// RAII guard for an in-flight call
+pub struct CallPermit {
+    _private: (),
+}
+
+impl Drop for CallPermit {
+    fn drop(&mut self) {
+        LIMITER.with(|l| {
+            let mut limiter = l.borrow_mut();
+            limiter.in_flight = limiter.in_flight.saturating_sub(1);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permits_are_capped_and_released() {
+        configure(&ConcurrencyConfig {
+            max_concurrent_calls: 2,
+        });
+
+        let first = acquire().unwrap();
+        let _second = acquire().unwrap();
+        assert!(matches!(
+            acquire(),
+            Err(ContragError::ConcurrencyLimitReached { limit: 2 })
+        ));
+
+        drop(first);
+        assert_eq!(available(), 1);
+        assert!(acquire().is_ok());
+    }
+}
@@ -1,5 +1,11 @@
+use candid::CandidType;
 use serde::{Deserialize, Serialize};
+use crate::embedders::batching::BatcherConfig;
+use crate::embedders::http_client::{ReplicationMode, MAX_OUTCALL_BYTES};
+use crate::embedders::usage_ledger::RateLimit;
 use crate::error::{ContragError, Result};
+use crate::storage::memory::MEMORY_ID_COUNT;
+use crate::types::DedupMode;
 
 /// Main configuration for ContRAG
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -18,6 +24,55 @@ pub struct ContragConfig {
     
     /// Optional system prompt for context generation
     pub system_prompt: Option<String>,
+
+    /// Ingestion queue configuration
+    #[serde(default)]
+    pub queue: IngestionQueueConfig,
+
+    /// Outcall concurrency limits
+    #[serde(default)]
+    pub concurrency: ConcurrencyConfig,
+
+    /// Heap and stable memory watermark monitoring
+    #[serde(default)]
+    pub monitoring: MemoryMonitorConfig,
+
+    /// Structured log capture for indexing logs as entities
+    #[serde(default)]
+    pub logs: LogConfig,
+
+    /// Scheduled golden-query regression checks
+    #[serde(default)]
+    pub eval: EvalConfig,
+
+    /// Per-entity-type retention policies
+    #[serde(default)]
+    pub retention: RetentionConfig,
+
+    /// Latency objectives per operation
+    #[serde(default)]
+    pub slo: SloConfig,
+
+    /// Query pipeline configuration
+    #[serde(default)]
+    pub pipeline: PipelineConfig,
+
+    /// HTTP gateway routes
+    #[serde(default)]
+    pub gateway: GatewayConfig,
+
+    /// Stable memory regions used by contrag
+    #[serde(default)]
+    pub stable_memory: StableMemoryConfig,
+
+    /// Cycles budgeting of HTTP outcalls
+    #[serde(default)]
+    pub outcalls: OutcallConfig,
+
+    /// LLM for answers, summaries and query routing; the embedder's
+    /// `generate_with_prompt` is used when unset
+    #[serde(default)]
+    pub generator: Option<GeneratorConfigDef>,
 }
 
 /// Entity configuration
@@ -40,6 +95,44 @@ pub struct EntityConfig {
     
     /// Whether to include this entity in automatic context building
     pub auto_include: bool,
+
+    /// How `fetch_many_method` pages through the whole collection
+    #[serde(default)]
+    pub pagination: Option<PaginationConfig>,
+
+    /// Context map field holding the entity's last update time, used by
+    /// differential syncs to find changed entities (e.g. "updated_at")
+    #[serde(default)]
+    pub updated_at_field: Option<String>,
+}
+
+/// Paging of a collection through `fetch_many_method`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaginationConfig {
+    pub style: PaginationStyle,
+
+    /// Entities requested per call
+    pub page_size: u32,
+}
+
+impl Default for PaginationConfig {
+    fn default() -> Self {
+        Self {
+            style: PaginationStyle::Offset,
+            page_size: 100,
+        }
+    }
+}
+
+/// Signature of a paginated `fetch_many_method`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaginationStyle {
+    /// `(offset : nat64, limit : nat32) -> (vec T)`; a short page ends the collection
+    Offset,
+    /// `(cursor : opt text, limit : nat32) -> (record { items : vec T; next_cursor : opt text })`
+    Cursor,
 }
 
 /// Relationship configuration
@@ -58,7 +151,8 @@ pub struct RelationshipConfig {
 /// Embedder provider configuration (from config file)
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct EmbedderConfigDef {
-    /// Provider: "openai" or "gemini"
+    /// Provider: "openai", "azure_openai", "gemini", "cohere", "jina" or
+    /// "ollama"
     pub provider: String,
     
     /// Model name
@@ -66,9 +160,100 @@ pub struct EmbedderConfigDef {
     
     /// Expected dimensions
     pub dimensions: usize,
+
+    /// Ask the provider for embeddings shortened to this many dimensions
+    /// (OpenAI `text-embedding-3-*` and Gemini `text-embedding-004`)
+    #[serde(default)]
+    pub target_dimensions: Option<usize>,
     
     /// API endpoint (optional, uses default if not provided)
     pub api_endpoint: Option<String>,
+
+    /// Header carrying the API key for OpenAI-compatible providers
+    /// (defaults to "Authorization" with a Bearer token)
+    #[serde(default)]
+    pub auth_header: Option<String>,
+
+    /// Replication mode of embedding outcalls ("replicated" or
+    /// "non_replicated"); generation stays replicated
+    #[serde(default)]
+    pub embedding_replication: ReplicationMode,
+
+    /// Header carrying a deterministic idempotency key on every request
+    /// (e.g. "Idempotency-Key"), so retried outcalls aren't billed twice
+    /// by providers that honor it
+    #[serde(default)]
+    pub idempotency_header: Option<String>,
+
+    /// Azure OpenAI deployment, used by the "azure_openai" provider
+    #[serde(default)]
+    pub azure: Option<AzureOpenAIConfig>,
+
+    /// Task and late chunking options of the "jina" provider
+    #[serde(default)]
+    pub jina: Option<JinaConfig>,
+
+    /// Client-side requests/tokens per minute, applied by wrapping the
+    /// embedder in a [`RateLimitedEmbedder`](crate::embedders::usage_ledger::RateLimitedEmbedder)
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+
+    /// Batch sizes and per-request token/byte limits, applied by wrapping
+    /// the embedder in an [`AdaptiveBatcher`](crate::embedders::batching::AdaptiveBatcher)
+    #[serde(default)]
+    pub batching: BatcherConfig,
+
+    /// L2-normalize embeddings before they are stored or searched, applied
+    /// by wrapping the embedder in a [`NormalizedEmbedder`](crate::embedders::normalize::NormalizedEmbedder)
+    #[serde(default)]
+    pub normalize: bool,
+
+    /// Entries kept by the [persistent embedding cache](crate::embedders::cache)
+    /// (10,000 when unset)
+    #[serde(default)]
+    pub cache_max_entries: Option<usize>,
+}
+
+/// Azure OpenAI resource and deployments
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AzureOpenAIConfig {
+    /// Resource endpoint, e.g. "https://my-resource.openai.azure.com"
+    pub endpoint: String,
+
+    /// Deployment of the embedding model
+    pub deployment: String,
+
+    /// Value of the `api-version` query parameter
+    pub api_version: String,
+
+    /// Deployment of a chat model, needed for answer generation
+    pub chat_deployment: Option<String>,
+}
+
+impl Default for AzureOpenAIConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: String::new(),
+            deployment: String::new(),
+            api_version: "2024-02-01".to_string(),
+            chat_deployment: None,
+        }
+    }
+}
+
+/// Jina embedding options
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JinaConfig {
+    /// Task of both documents and queries (e.g. "text-matching"); by default
+    /// documents use "retrieval.passage" and queries "retrieval.query"
+    pub task: Option<String>,
+
+    /// Embed the texts of one `embed` call as chunks of a single document,
+    /// so each chunk's embedding carries context from its neighbours; the
+    /// batcher and embedding caches then pass document embeddings on whole
+    pub late_chunking: bool,
 }
 
 /// Chunking configuration
@@ -94,17 +279,78 @@ impl Default for ChunkingConfig {
     }
 }
 
+/// Values of [`VectorStoreConfig::storage_type`]
+pub const STORAGE_TYPES: [&str; 4] = ["stable_memory", "hybrid", "hnsw", "ivf"];
+
 /// Vector store configuration
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct VectorStoreConfig {
-    /// Storage type: "stable_memory" or "hybrid"
+    /// Storage type: "stable_memory", "hybrid", "hnsw" or "ivf" (see
+    /// [`vector_store::from_config`](crate::vector_store::from_config))
     pub storage_type: String,
     
-    /// Maximum vectors to keep in hot storage (for hybrid mode)
+    /// Maximum vectors to keep on the heap in hybrid mode; the rest live in
+    /// stable memory (see [`HybridVectorStore`](crate::vector_store::hybrid::HybridVectorStore))
     pub max_hot_vectors: Option<usize>,
     
     /// Whether to enable caching
     pub enable_cache: bool,
+
+    /// HNSW index parameters (for hnsw mode)
+    #[serde(default)]
+    pub hnsw: HnswConfig,
+
+    /// IVF clustering parameters (for ivf mode)
+    #[serde(default)]
+    pub ivf: IvfConfig,
+
+    /// How query and stored embeddings are compared
+    #[serde(default)]
+    pub distance_metric: DistanceMetric,
+
+    /// Binary-quantized pre-filter for large namespaces
+    #[serde(default)]
+    pub quantization: QuantizationConfig,
+
+    /// Default lifetime of stored vectors in seconds, for vectors without
+    /// their own `ttl_seconds`; `None` keeps them until deleted
+    #[serde(default)]
+    pub ttl_seconds: Option<u64>,
+
+    /// Size limits applied to every namespace
+    #[serde(default)]
+    pub quota: NamespaceQuota,
+
+    /// Maintain a BM25 keyword index of chunk text for `keyword_search`
+    #[serde(default)]
+    pub keyword_index: bool,
+
+    /// Deflate chunk text of stored vectors
+    #[serde(default)]
+    pub text_compression: TextCompressionConfig,
+
+    /// Search results scoring below this are dropped; `None` keeps all
+    #[serde(default)]
+    pub min_score: Option<f32>,
+
+    /// Report scores mapped into 0..=1 so they are comparable across
+    /// distance metrics (see `vector_store::normalized_similarity`)
+    #[serde(default)]
+    pub normalize_scores: bool,
+
+    /// Width of stored embeddings in the stable memory store
+    #[serde(default)]
+    pub precision: EmbeddingPrecision,
+
+    /// Truncate longer embeddings (and queries) to this many dimensions
+    /// and renormalize them, for Matryoshka-trained models
+    #[serde(default)]
+    pub target_dimensions: Option<usize>,
+
+    /// Keep replaced and deleted vectors as versions, so deletes can be
+    /// undone and searches can run as of an earlier version
+    #[serde(default)]
+    pub versioning: bool,
 }
 
 impl Default for VectorStoreConfig {
@@ -113,6 +359,707 @@ impl Default for VectorStoreConfig {
             storage_type: "stable_memory".to_string(),
             max_hot_vectors: Some(10000),
             enable_cache: true,
+            hnsw: HnswConfig::default(),
+            ivf: IvfConfig::default(),
+            distance_metric: DistanceMetric::default(),
+            quantization: QuantizationConfig::default(),
+            ttl_seconds: None,
+            quota: NamespaceQuota::default(),
+            keyword_index: false,
+            text_compression: TextCompressionConfig::default(),
+            min_score: None,
+            normalize_scores: false,
+            precision: EmbeddingPrecision::default(),
+            target_dimensions: None,
+            versioning: false,
+        }
+    }
+}
+
+/// Similarity measure used to rank search results
+///
+/// Every metric yields a score where higher means more similar, so results
+/// stay sorted the same way whichever is configured.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceMetric {
+    /// Cosine similarity, in -1..=1; ignores embedding magnitude
+    #[default]
+    Cosine,
+    /// Raw dot product; equals cosine for unit-length embeddings (e.g.
+    /// OpenAI's) and skips the normalization
+    DotProduct,
+    /// `1 / (1 + d)` for the euclidean distance `d`, in 0..=1
+    Euclidean,
+}
+
+/// Floating-point width of stored embeddings
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbeddingPrecision {
+    /// Embeddings are stored as given
+    #[default]
+    F32,
+    /// Half precision: half the bytes, about three significant digits,
+    /// which barely moves cosine rankings
+    F16,
+}
+
+/// Per-namespace size limits
+///
+/// A write that would take a namespace over either limit is rejected or
+/// makes room by evicting existing vectors, depending on `eviction`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+#[serde(default)]
+pub struct NamespaceQuota {
+    pub max_vectors: Option<usize>,
+
+    /// Limit on embedding, text and ID bytes
+    pub max_bytes: Option<u64>,
+
+    pub eviction: EvictionPolicy,
+}
+
+impl NamespaceQuota {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_vectors.is_none() && self.max_bytes.is_none()
+    }
+}
+
+/// What happens when a write exceeds a namespace quota
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionPolicy {
+    /// Refuse the vectors that do not fit
+    #[default]
+    Reject,
+    /// Evict the vectors least recently stored or returned by a search
+    Lru,
+    /// Evict the vectors with the oldest timestamps
+    OldestFirst,
+}
+
+/// Binary quantization of stored embeddings
+///
+/// When enabled, search first ranks a namespace by the Hamming distance of
+/// 1-bit embedding codes and only scores the closest candidates at full
+/// precision. Sign codes track angles, so this suits cosine and dot product
+/// best.
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+#[serde(default)]
+pub struct QuantizationConfig {
+    pub enabled: bool,
+
+    /// Candidates reranked at full precision per requested result
+    pub rerank_factor: usize,
+
+    /// Namespaces smaller than this are scored exactly instead
+    pub min_vectors: usize,
+}
+
+impl Default for QuantizationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rerank_factor: 10,
+            min_vectors: 1000,
+        }
+    }
+}
+
+/// Compression of the chunk text kept with stored vectors
+///
+/// Chunk text often repeats entity content and outweighs the embedding.
+/// Compressed text is inflated again whenever it is read, so this trades
+/// instructions on search and export for storage.
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+#[serde(default)]
+pub struct TextCompressionConfig {
+    pub enabled: bool,
+
+    /// Shorter texts are stored as-is; deflate rarely pays off below this
+    pub min_bytes: usize,
+}
+
+impl Default for TextCompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_bytes: 256,
+        }
+    }
+}
+
+/// HNSW approximate nearest neighbor index parameters
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+#[serde(default)]
+pub struct HnswConfig {
+    /// Links per node on the upper layers (twice as many on layer 0)
+    pub m: usize,
+
+    /// Candidate list size while inserting; higher builds a better graph
+    /// at a higher instruction cost
+    pub ef_construction: usize,
+
+    /// Candidate list size while searching; higher trades speed for recall
+    pub ef_search: usize,
+
+    /// Namespaces smaller than this are scanned exactly instead
+    pub exact_search_below: usize,
+
+    /// Vectors re-inserted per write while a namespace's graph is rebuilt
+    /// without its tombstones
+    pub rebuild_batch: usize,
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 100,
+            ef_search: 64,
+            exact_search_below: 1000,
+            rebuild_batch: 200,
+        }
+    }
+}
+
+/// IVF (inverted file) index parameters
+///
+/// Vectors are clustered by k-means into `lists` lists; a search scores
+/// only the vectors of the `nprobe` lists whose centroids are closest to
+/// the query. Cheaper to maintain than HNSW, at some cost in recall.
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+#[serde(default)]
+pub struct IvfConfig {
+    /// Number of clusters per namespace
+    pub lists: usize,
+
+    /// Clusters scanned per search; higher trades speed for recall
+    pub nprobe: usize,
+
+    /// k-means iterations per rebuild
+    pub iterations: usize,
+
+    /// Vectors the centroids are trained on; larger namespaces are sampled
+    pub training_sample: usize,
+
+    /// Namespaces smaller than this are scanned exactly instead
+    pub exact_search_below: usize,
+}
+
+impl Default for IvfConfig {
+    fn default() -> Self {
+        Self {
+            lists: 64,
+            nprobe: 8,
+            iterations: 10,
+            training_sample: 4096,
+            exact_search_below: 1000,
+        }
+    }
+}
+
+/// Ingestion queue configuration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IngestionQueueConfig {
+    /// Scheduling weight of the interactive lane
+    pub interactive_weight: u32,
+
+    /// Scheduling weight of the normal lane
+    pub normal_weight: u32,
+
+    /// Scheduling weight of the bulk lane
+    pub bulk_weight: u32,
+
+    /// Maximum jobs processed per timer tick
+    pub batch_size: usize,
+
+    /// Interval between timer ticks in seconds
+    pub process_interval_secs: u64,
+}
+
+impl Default for IngestionQueueConfig {
+    fn default() -> Self {
+        Self {
+            interactive_weight: 8,
+            normal_weight: 3,
+            bulk_weight: 1,
+            batch_size: 10,
+            process_interval_secs: 5,
+        }
+    }
+}
+
+/// Concurrency limits for outgoing calls
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConcurrencyConfig {
+    /// Maximum HTTP outcalls and inter-canister calls in flight at once
+    pub max_concurrent_calls: usize,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_calls: 20,
+        }
+    }
+}
+
+/// Memory watermark monitoring configuration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MemoryMonitorConfig {
+    /// Heap usage in bytes that triggers a warning alert
+    pub heap_warning_bytes: u64,
+
+    /// Heap usage in bytes that triggers protective mode
+    pub heap_critical_bytes: u64,
+
+    /// Stable memory usage in bytes that triggers a warning alert
+    pub stable_warning_bytes: u64,
+
+    /// Stable memory usage in bytes that triggers protective mode
+    pub stable_critical_bytes: u64,
+
+    /// Interval between checks in seconds
+    pub check_interval_secs: u64,
+
+    /// Whether to switch into read-only protective mode automatically
+    pub auto_protect: bool,
+}
+
+impl Default for MemoryMonitorConfig {
+    fn default() -> Self {
+        const GIB: u64 = 1024 * 1024 * 1024;
+        Self {
+            heap_warning_bytes: 3 * GIB,
+            heap_critical_bytes: 3 * GIB + GIB / 2,
+            stable_warning_bytes: 300 * GIB,
+            stable_critical_bytes: 380 * GIB,
+            check_interval_secs: 60,
+            auto_protect: true,
+        }
+    }
+}
+
+/// Structured log capture configuration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LogConfig {
+    /// Whether log events are captured at all
+    pub enabled: bool,
+
+    /// Maximum events kept in the in-memory buffer
+    pub max_buffered_events: usize,
+
+    /// Days a log event stays searchable
+    pub retention_days: u64,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_buffered_events: 1000,
+            retention_days: 30,
+        }
+    }
+}
+
+/// Golden-query regression monitoring configuration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EvalConfig {
+    /// Interval between golden-query runs in seconds
+    pub check_interval_secs: u64,
+
+    /// Allowed drop in recall@k against the baseline before alerting
+    pub regression_tolerance: f64,
+
+    /// Optional URL that receives a JSON POST for every regression
+    pub webhook_url: Option<String>,
+}
+
+impl Default for EvalConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_secs: 3600,
+            regression_tolerance: 0.05,
+            webhook_url: None,
+        }
+    }
+}
+
+/// Latency objective for one operation, e.g. p95 search under 2s
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SloTarget {
+    /// Operation name, e.g. "search", "answer" or "ingest"
+    pub operation: String,
+
+    /// Percentile that must stay under the threshold, e.g. 95.0
+    pub percentile: f64,
+
+    /// Latency threshold in milliseconds, including outcalls
+    pub threshold_ms: u64,
+}
+
+/// Latency SLO tracking configuration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SloConfig {
+    pub targets: Vec<SloTarget>,
+
+    /// Length of the rolling window the percentiles are computed over
+    pub window_secs: u64,
+
+    /// Fewest samples in the window before a target can be breached
+    pub min_samples: usize,
+
+    /// Interval between SLO checks in seconds
+    pub check_interval_secs: u64,
+
+    /// Optional URL that receives a JSON POST for every breach
+    pub webhook_url: Option<String>,
+}
+
+impl Default for SloConfig {
+    fn default() -> Self {
+        Self {
+            targets: vec![],
+            window_secs: 3600,
+            min_samples: 20,
+            check_interval_secs: 300,
+            webhook_url: None,
+        }
+    }
+}
+
+/// Retention policy for one entity type
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// Entity type the policy applies to (e.g. "Session")
+    pub entity_type: String,
+
+    /// Days a vector stays searchable after it was stored
+    pub max_age_days: u64,
+}
+
+/// Retention configuration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RetentionConfig {
+    /// Policies by entity type; types without a policy are kept forever
+    pub policies: Vec<RetentionPolicy>,
+
+    /// Interval between purge runs in seconds
+    pub purge_interval_secs: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            policies: vec![],
+            purge_interval_secs: 3600,
+        }
+    }
+}
+
+/// Query pipeline configuration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PipelineConfig {
+    /// Number of results returned when a query does not specify k
+    pub default_k: usize,
+
+    /// How long a query embedding stays cached in seconds (0 disables caching)
+    pub query_cache_ttl_secs: u64,
+
+    /// Maximum number of cached query embeddings
+    pub query_cache_max_entries: usize,
+
+    /// Answers scoring below this confidence are flagged as low confidence
+    pub min_confidence: f32,
+
+    /// Ask the model to grade its own answers (one extra generation call)
+    pub self_assess: bool,
+
+    /// What to do when retrieval finds nothing
+    pub fallback: FallbackStrategy,
+
+    /// Namespaces searched by the `broaden` fallback, in order
+    pub fallback_namespaces: Vec<String>,
+
+    /// Answer returned by the `refuse` fallback
+    pub refusal_message: String,
+
+    /// Prefix of answers generated by the `answer_without_context` fallback
+    pub no_context_disclaimer: String,
+
+    /// Context layout used when no retrieval profile is selected
+    pub context_format: ContextFormat,
+
+    /// Named retrieval profiles selectable per query
+    pub profiles: Vec<RetrievalProfile>,
+
+    /// How long results of idempotent writes are remembered in seconds
+    pub idempotency_ttl_secs: u64,
+
+    /// Maximum number of remembered idempotency keys
+    pub idempotency_max_entries: usize,
+
+    /// Cap on search hits per (entity_type, entity_id); `None` disables it
+    pub max_chunks_per_entity: Option<usize>,
+
+    /// Picks a retrieval profile per query; `None` disables routing
+    pub router: Option<RouterConfig>,
+
+    /// How long cached search results are served as fresh in seconds
+    pub result_cache_fresh_secs: u64,
+
+    /// How long stale results may still be served while they are refreshed
+    pub result_cache_max_stale_secs: u64,
+
+    /// Maximum number of cached search results (0 disables stale-while-revalidate)
+    pub result_cache_max_entries: usize,
+
+    /// Skip or overwrite ingested chunks whose entity already has their
+    /// text; `None` stores every chunk
+    pub dedup: Option<DedupMode>,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            default_k: 5,
+            query_cache_ttl_secs: 300,
+            query_cache_max_entries: 1000,
+            min_confidence: 0.35,
+            self_assess: false,
+            fallback: FallbackStrategy::Refuse,
+            fallback_namespaces: vec![],
+            refusal_message: "I couldn't find any information to answer that.".to_string(),
+            no_context_disclaimer: "I couldn't find relevant data, so this answer is based on general knowledge only:".to_string(),
+            context_format: ContextFormat::Numbered,
+            profiles: vec![],
+            idempotency_ttl_secs: 86400,
+            idempotency_max_entries: 10_000,
+            max_chunks_per_entity: None,
+            router: None,
+            result_cache_fresh_secs: 30,
+            result_cache_max_stale_secs: 600,
+            result_cache_max_entries: 500,
+            dedup: None,
+        }
+    }
+}
+
+/// Layout of the retrieved context in generation prompts
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextFormat {
+    /// `[1] text` blocks
+    Numbered,
+    /// `<source id=".." ...>` elements inside `<sources>`
+    Xml,
+    /// `## [1] Type id` sections with citation instructions
+    Markdown,
+}
+
+/// Named retrieval and prompt settings, e.g. one per model or use case
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RetrievalProfile {
+    pub name: String,
+
+    /// Number of chunks to retrieve (defaults to the pipeline's default_k)
+    #[serde(default)]
+    pub k: Option<usize>,
+
+    /// Context layout for this profile
+    pub context_format: ContextFormat,
+
+    /// Upper bound on the context size in characters
+    #[serde(default)]
+    pub max_context_chars: Option<usize>,
+
+    /// System prompt overriding the pipeline's
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+
+    /// Namespace searched instead of the caller's, e.g. "docs:billing"
+    #[serde(default)]
+    pub namespace: Option<String>,
+
+    /// What questions this profile is for; shown to the routing classifier
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Query routing across retrieval profiles
+///
+/// Keyword rules are checked first, in order. If none matches and
+/// `llm_fallback` is set, the model picks a profile from their names and
+/// descriptions. Otherwise `default_profile` is used.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RouterConfig {
+    pub rules: Vec<RoutingRule>,
+    pub llm_fallback: bool,
+    pub default_profile: Option<String>,
+}
+
+/// Route queries containing any of the keywords to a profile
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RoutingRule {
+    pub profile: String,
+    /// Words or phrases, matched case-insensitively on word boundaries
+    pub keywords: Vec<String>,
+}
+
+/// Behavior of the answer pipeline when retrieval returns nothing
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+#[serde(rename_all = "snake_case")]
+pub enum FallbackStrategy {
+    /// Return the configured refusal message without calling the model
+    Refuse,
+    /// Let the model answer without context, prefixed with a disclaimer
+    AnswerWithoutContext,
+    /// Retry retrieval in the configured fallback namespaces, then refuse
+    Broaden,
+}
+
+/// HTTP gateway configuration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GatewayConfig {
+    /// Serve the OpenAI-compatible `/v1` routes
+    ///
+    /// `/v1/embeddings` spends the canister's API key and cycles, so these
+    /// routes are off by default and only answer requests carrying a Bearer
+    /// token listed in `api_key_hashes`.
+    pub openai_routes: bool,
+
+    /// Hex SHA-256 hashes of the Bearer tokens accepted by the `/v1` routes
+    ///
+    /// Hashes rather than the tokens themselves, since canister configs are
+    /// usually public; `echo -n "$TOKEN" | sha256sum` prints one.
+    pub api_key_hashes: Vec<String>,
+
+    /// Serve the MCP endpoint at `/mcp`
+    ///
+    /// Tool calls search any namespace and spend the canister's API key and
+    /// cycles, so the endpoint is off by default and only runs tools for
+    /// requests carrying a Bearer token listed in `api_key_hashes`.
+    pub mcp: bool,
+
+    /// Serve combined entity and retrieval queries at `/query`
+    ///
+    /// Queries run loaders and an embedding outcall, so the route is off by
+    /// default and, like the `/v1` routes, requires a listed Bearer token.
+    pub entity_query: bool,
+
+    /// Serve vector exports at `/export`
+    ///
+    /// Exports include the stored text and embeddings and HTTP requests are
+    /// anonymous, so this is off by default.
+    pub export: bool,
+
+    /// Allow writes (e.g. the `ingest_entity` MCP tool) over HTTP
+    ///
+    /// HTTP requests are anonymous, so only enable this for canisters whose
+    /// index may be written by anyone.
+    pub allow_ingest: bool,
+}
+
+impl Default for GatewayConfig {
+    fn default() -> Self {
+        Self {
+            openai_routes: false,
+            api_key_hashes: vec![],
+            mcp: false,
+            entity_query: false,
+            export: false,
+            allow_ingest: false,
+        }
+    }
+}
+
+/// Stable memory configuration
+///
+/// contrag takes [`MEMORY_ID_COUNT`] consecutive `MemoryId`s of the host's
+/// `MemoryManager`, starting at `first_memory_id`; see
+/// [`crate::storage::memory`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StableMemoryConfig {
+    /// First `MemoryId` used by contrag
+    pub first_memory_id: u8,
+}
+
+impl Default for StableMemoryConfig {
+    fn default() -> Self {
+        Self {
+            // High enough to stay clear of hosts numbering their own
+            // structures from 0
+            first_memory_id: 200,
+        }
+    }
+}
+
+/// Text generation provider configuration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GeneratorConfigDef {
+    /// Provider: "openai", "gemini" or "anthropic"
+    pub provider: String,
+
+    /// Model name, e.g. "claude-3-5-haiku-latest"
+    pub model: String,
+
+    /// Sampling temperature (defaults to 0.7)
+    #[serde(default)]
+    pub temperature: Option<f32>,
+
+    /// Most output tokens per reply (defaults to 1000)
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+
+    /// API endpoint (optional, uses default if not provided)
+    #[serde(default)]
+    pub api_endpoint: Option<String>,
+}
+
+/// HTTP outcall cycles and size configuration
+///
+/// Each outcall is paid for with cycles estimated from its request size,
+/// `max_response_bytes` and the subnet size; unused cycles are refunded.
+/// Embedders split batches so each request and response fits the limits.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutcallConfig {
+    /// Nodes in the canister's subnet (13 on most application subnets)
+    pub subnet_nodes: u64,
+
+    /// Largest response accepted, which outcalls pay for in full (at most
+    /// 2 MB on the IC)
+    pub max_response_bytes: u64,
+
+    /// Largest request (URL, headers and body) sent (at most 2 MB on the IC)
+    pub max_request_bytes: u64,
+
+    /// Most cycles attached to one outcall; larger outcalls fail instead
+    /// of being sent. `None` means no ceiling.
+    pub max_cycles_per_call: Option<u64>,
+
+    /// Query method of the canister that transforms outcall responses
+    /// (usually "contrag_transform", forwarding to
+    /// `http_client::transform`). `None` sends outcalls without one.
+    pub transform_method: Option<String>,
+}
+
+impl Default for OutcallConfig {
+    fn default() -> Self {
+        Self {
+            subnet_nodes: 13,
+            max_response_bytes: 2_000_000,
+            max_request_bytes: 2_000_000,
+            max_cycles_per_call: None,
+            transform_method: None,
         }
     }
 }
@@ -141,12 +1088,94 @@ pub fn validate_config(config: &ContragConfig) -> Result<()> {
         ));
     }
 
+    for entity in &config.entities {
+        if let Some(pagination) = &entity.pagination {
+            if entity.fetch_many_method.is_none() {
+                return Err(ContragError::InvalidConfig(format!(
+                    "Entity {} has pagination but no fetch_many_method",
+                    entity.name
+                )));
+            }
+            if pagination.page_size == 0 {
+                return Err(ContragError::InvalidConfig(format!(
+                    "Page size of entity {} must be greater than 0",
+                    entity.name
+                )));
+            }
+        }
+    }
+
+    if let Some(router) = &config.pipeline.router {
+        let known = |name: &str| config.pipeline.profiles.iter().any(|p| p.name == name);
+        let routed = router
+            .rules
+            .iter()
+            .map(|r| r.profile.as_str())
+            .chain(router.default_profile.as_deref());
+
+        for profile in routed {
+            if !known(profile) {
+                return Err(ContragError::InvalidConfig(format!(
+                    "Router refers to unknown retrieval profile: {}",
+                    profile
+                )));
+            }
+        }
+    }
+
+    if config.pipeline.max_chunks_per_entity == Some(0) {
+        return Err(ContragError::InvalidConfig(
+            "Max chunks per entity must be greater than 0".to_string(),
+        ));
+    }
+
     if config.embedder.dimensions == 0 {
         return Err(ContragError::InvalidConfig(
             "Embedder dimensions must be greater than 0".to_string(),
         ));
     }
 
+    let embedded_dimensions = config.embedder.target_dimensions.unwrap_or(config.embedder.dimensions);
+    if config.embedder.target_dimensions.is_some_and(|target| target == 0 || target > config.embedder.dimensions)
+        || config
+            .vector_store
+            .target_dimensions
+            .is_some_and(|target| target == 0 || target > embedded_dimensions)
+    {
+        return Err(ContragError::InvalidConfig(
+            "Target dimensions must be greater than 0 and at most the embedding dimensions".to_string(),
+        ));
+    }
+
+    if config
+        .embedder
+        .azure
+        .as_ref()
+        .is_some_and(|azure| azure.endpoint.is_empty() || azure.deployment.is_empty())
+    {
+        return Err(ContragError::InvalidConfig(
+            "Azure OpenAI needs an endpoint and a deployment".to_string(),
+        ));
+    }
+
+    let batching = &config.embedder.batching;
+    if batching.min_batch_size > batching.max_batch_size
+        || batching.max_batch_tokens == Some(0)
+        || batching.max_batch_bytes == Some(0)
+    {
+        return Err(ContragError::InvalidConfig(
+            "Embedder batching needs min_batch_size <= max_batch_size and non-zero request limits".to_string(),
+        ));
+    }
+
+    if config.embedder.rate_limit.as_ref().is_some_and(|limit| {
+        limit.requests_per_minute == Some(0) || limit.tokens_per_minute == Some(0)
+    }) {
+        return Err(ContragError::InvalidConfig(
+            "Embedder rate limits must be greater than 0; omit them for no limit".to_string(),
+        ));
+    }
+
     if config.chunking.chunk_size == 0 {
         return Err(ContragError::InvalidConfig(
             "Chunk size must be greater than 0".to_string(),
@@ -159,6 +1188,144 @@ pub fn validate_config(config: &ContragConfig) -> Result<()> {
         ));
     }
 
+    if !STORAGE_TYPES.contains(&config.vector_store.storage_type.as_str()) {
+        return Err(ContragError::InvalidConfig(format!(
+            "Unknown storage type '{}', expected one of {}",
+            config.vector_store.storage_type,
+            STORAGE_TYPES.join(", ")
+        )));
+    }
+
+    let hnsw = &config.vector_store.hnsw;
+    if hnsw.m < 2 || hnsw.ef_search == 0 || hnsw.rebuild_batch == 0 {
+        return Err(ContragError::InvalidConfig(
+            "HNSW needs m of at least 2 and ef_search and rebuild_batch greater than 0".to_string(),
+        ));
+    }
+
+    if config.vector_store.min_score.is_some_and(|min_score| !min_score.is_finite()) {
+        return Err(ContragError::InvalidConfig(
+            "min_score must be a finite number".to_string(),
+        ));
+    }
+
+    if config.vector_store.ivf.lists == 0 || config.vector_store.ivf.nprobe == 0 {
+        return Err(ContragError::InvalidConfig(
+            "IVF needs lists and nprobe greater than 0".to_string(),
+        ));
+    }
+
+    if config.vector_store.quota.max_vectors == Some(0) || config.vector_store.quota.max_bytes == Some(0) {
+        return Err(ContragError::InvalidConfig(
+            "Namespace quotas must be greater than 0".to_string(),
+        ));
+    }
+
+    if config.vector_store.quantization.rerank_factor == 0 {
+        return Err(ContragError::InvalidConfig(
+            "Quantization rerank_factor must be greater than 0".to_string(),
+        ));
+    }
+
+    if config.queue.interactive_weight == 0
+        || config.queue.normal_weight == 0
+        || config.queue.bulk_weight == 0
+    {
+        return Err(ContragError::InvalidConfig(
+            "Queue lane weights must be greater than 0".to_string(),
+        ));
+    }
+
+    if config.concurrency.max_concurrent_calls == 0 {
+        return Err(ContragError::InvalidConfig(
+            "Max concurrent calls must be greater than 0".to_string(),
+        ));
+    }
+
+    if config.monitoring.heap_warning_bytes > config.monitoring.heap_critical_bytes
+        || config.monitoring.stable_warning_bytes > config.monitoring.stable_critical_bytes
+    {
+        return Err(ContragError::InvalidConfig(
+            "Memory warning thresholds must not exceed critical thresholds".to_string(),
+        ));
+    }
+
+    if config.stable_memory.first_memory_id > u8::MAX - MEMORY_ID_COUNT {
+        return Err(ContragError::InvalidConfig(format!(
+            "stable_memory.first_memory_id must be at most {}; MemoryId {} is reserved",
+            u8::MAX - MEMORY_ID_COUNT,
+            u8::MAX
+        )));
+    }
+
+    if config.outcalls.subnet_nodes == 0 {
+        return Err(ContragError::InvalidConfig(
+            "outcalls.subnet_nodes must be greater than 0".to_string(),
+        ));
+    }
+
+    for (name, bytes) in [
+        ("max_response_bytes", config.outcalls.max_response_bytes),
+        ("max_request_bytes", config.outcalls.max_request_bytes),
+    ] {
+        if bytes == 0 || bytes > MAX_OUTCALL_BYTES {
+            return Err(ContragError::InvalidConfig(format!(
+                "outcalls.{} must be between 1 and {}",
+                name, MAX_OUTCALL_BYTES
+            )));
+        }
+    }
+
+    if let Some(generator) = &config.generator {
+        if !["openai", "gemini", "anthropic"].contains(&generator.provider.as_str()) {
+            return Err(ContragError::InvalidConfig(format!(
+                "Unknown generator provider '{}'; expected openai, gemini or anthropic",
+                generator.provider
+            )));
+        }
+        if generator.max_tokens == Some(0) {
+            return Err(ContragError::InvalidConfig(
+                "generator.max_tokens must be greater than 0".to_string(),
+            ));
+        }
+    }
+
+    for target in &config.slo.targets {
+        if !(target.percentile > 0.0 && target.percentile <= 100.0) || target.threshold_ms == 0 {
+            return Err(ContragError::InvalidConfig(format!(
+                "SLO target for {} needs a percentile in (0, 100] and a non-zero threshold",
+                target.operation
+            )));
+        }
+    }
+
+    if config.slo.window_secs == 0 {
+        return Err(ContragError::InvalidConfig(
+            "slo.window_secs must be greater than 0".to_string(),
+        ));
+    }
+
+    if config.gateway.openai_routes && config.gateway.api_key_hashes.is_empty() {
+        return Err(ContragError::InvalidConfig(
+            "gateway.openai_routes requires at least one entry in gateway.api_key_hashes".to_string(),
+        ));
+    }
+
+    if !(0.0..=1.0).contains(&config.eval.regression_tolerance) {
+        return Err(ContragError::InvalidConfig(
+            "Regression tolerance must be between 0 and 1".to_string(),
+        ));
+    }
+
+    for policy in &config.retention.policies {
+        if policy.max_age_days == 0 {
+            return Err(ContragError::InvalidConfig(format!(
+                "Retention for entity type '{}' must be at least one day",
+                policy.entity_type
+            )));
+        }
+    }
+
     // Validate entity configurations
     for entity in &config.entities {
         if entity.name.is_empty() {
@@ -184,11 +1351,33 @@ pub fn create_default_config() -> ContragConfig {
             provider: "openai".to_string(),
             model: "text-embedding-3-small".to_string(),
             dimensions: 1536,
+            target_dimensions: None,
             api_endpoint: None,
+            auth_header: None,
+            embedding_replication: ReplicationMode::Replicated,
+            idempotency_header: None,
+            azure: None,
+            jina: None,
+            rate_limit: None,
+            batching: BatcherConfig::default(),
+            normalize: false,
+            cache_max_entries: None,
         },
         chunking: ChunkingConfig::default(),
         vector_store: VectorStoreConfig::default(),
         system_prompt: None,
+        queue: IngestionQueueConfig::default(),
+        concurrency: ConcurrencyConfig::default(),
+        monitoring: MemoryMonitorConfig::default(),
+        logs: LogConfig::default(),
+        eval: EvalConfig::default(),
+        retention: RetentionConfig::default(),
+        slo: SloConfig::default(),
+        pipeline: PipelineConfig::default(),
+        gateway: GatewayConfig::default(),
+        stable_memory: StableMemoryConfig::default(),
+        outcalls: OutcallConfig::default(),
+        generator: None,
     }
 }
 
@@ -0,0 +1,434 @@
+use std::collections::BTreeMap;
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use crate::concurrency;
+use crate::config::EntityConfig;
+use crate::entity::{EntityRelationship, RagEntity, RelationshipType};
+use crate::error::{ContragError, Result};
+
+/// Query exported by canisters built with `ic_cdk::export_candid!()`
+const CANDID_INTERFACE_METHOD: &str = "__get_candid_interface_tmp_hack";
+
+/// A method of a canister's public interface
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct CandidMethod {
+    pub canister_id: String,
+    pub name: String,
+    /// Argument types as written in the interface, without parentheses
+    pub args: String,
+    /// Return types as written in the interface, without parentheses
+    pub returns: String,
+    /// "query", "composite_query", "oneway" or "update"
+    pub mode: String,
+    /// Named types used in the signature
+    pub referenced_types: Vec<String>,
+}
+
+impl CandidMethod {
+    pub fn is_query(&self) -> bool {
+        self.mode == "query" || self.mode == "composite_query"
+    }
+}
+
+/// A named type definition of a canister's interface
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct CandidTypeDef {
+    pub canister_id: String,
+    pub name: String,
+    pub definition: String,
+}
+
+impl RagEntity for CandidMethod {
+    fn entity_type() -> &'static str {
+        "CandidMethod"
+    }
+
+    fn entity_id(&self) -> String {
+        format!("{}.{}", self.canister_id, self.name)
+    }
+
+    fn to_context_map(&self) -> Vec<(String, String)> {
+        vec![
+            ("canister".to_string(), self.canister_id.clone()),
+            ("method".to_string(), self.name.clone()),
+            ("arguments".to_string(), format!("({})", self.args)),
+            ("returns".to_string(), format!("({})", self.returns)),
+            ("mode".to_string(), self.mode.clone()),
+        ]
+    }
+
+    fn relationships(&self) -> Vec<EntityRelationship> {
+        self.referenced_types
+            .iter()
+            .map(|name| EntityRelationship {
+                field_name: "types".to_string(),
+                target_entity_type: CandidTypeDef::entity_type().to_string(),
+                target_id: format!("{}.{}", self.canister_id, name),
+                relationship_type: RelationshipType::ManyToMany,
+            })
+            .collect()
+    }
+}
+
+impl RagEntity for CandidTypeDef {
+    fn entity_type() -> &'static str {
+        "CandidTypeDef"
+    }
+
+    fn entity_id(&self) -> String {
+        format!("{}.{}", self.canister_id, self.name)
+    }
+
+    fn to_context_map(&self) -> Vec<(String, String)> {
+        vec![
+            ("canister".to_string(), self.canister_id.clone()),
+            ("type".to_string(), self.name.clone()),
+            ("definition".to_string(), self.definition.clone()),
+        ]
+    }
+
+    fn relationships(&self) -> Vec<EntityRelationship> {
+        vec![]
+    }
+}
+
+/// Parsed public interface of a canister
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct CandidInterface {
+    pub canister_id: String,
+    pub methods: Vec<CandidMethod>,
+    pub types: Vec<CandidTypeDef>,
+}
+
+impl CandidInterface {
+    /// Parse a `.did` service description
+    ///
+    /// Only the top-level structure is interpreted: type definitions are kept
+    /// as text and method signatures are split into arguments, results and
+    /// mode. `import` statements are ignored.
+    pub fn parse(canister_id: &str, did: &str) -> Result<Self> {
+        let source = strip_comments(did);
+        let mut types = BTreeMap::new();
+        let mut service = None;
+
+        for statement in split_top_level(&source, ';') {
+            if let Some(rest) = statement.strip_prefix("type ") {
+                let (name, definition) = rest.split_once('=').ok_or_else(|| {
+                    ContragError::DataSourceError(format!("Invalid type definition: {}", statement))
+                })?;
+                types.insert(unquote(name.trim()), definition.trim().to_string());
+            } else if statement.starts_with("service") {
+                service = Some(statement);
+            }
+        }
+
+        let service = service.ok_or_else(|| {
+            ContragError::DataSourceError("Candid interface has no service definition".to_string())
+        })?;
+
+        // `service : (init) -> { ... }`, `service : { ... }` or `service : Name`
+        let body = match (service.find('{'), service.rfind('}')) {
+            (Some(start), Some(end)) if start < end => service[start + 1..end].to_string(),
+            _ => {
+                let name = match service.rfind("->") {
+                    Some(pos) => &service[pos + 2..],
+                    None => service.split_once(':').map_or("", |(_, name)| name),
+                }
+                .trim();
+                let definition = types.get(name).ok_or_else(|| {
+                    ContragError::DataSourceError(format!("Unknown service type: {}", name))
+                })?;
+                match (definition.find('{'), definition.rfind('}')) {
+                    (Some(start), Some(end)) if start < end => definition[start + 1..end].to_string(),
+                    _ => String::new(),
+                }
+            }
+        };
+
+        let mut methods = vec![];
+        for entry in split_top_level(&body, ';') {
+            let (name, signature) = entry.split_once(':').ok_or_else(|| {
+                ContragError::DataSourceError(format!("Invalid method definition: {}", entry))
+            })?;
+            let (args, returns, mode) = parse_signature(signature.trim());
+            let referenced_types = referenced_types(&format!("{} {}", args, returns), &types);
+
+            methods.push(CandidMethod {
+                canister_id: canister_id.to_string(),
+                name: unquote(name.trim()),
+                args,
+                returns,
+                mode,
+                referenced_types,
+            });
+        }
+
+        Ok(Self {
+            canister_id: canister_id.to_string(),
+            methods,
+            types: types
+                .into_iter()
+                .map(|(name, definition)| CandidTypeDef {
+                    canister_id: canister_id.to_string(),
+                    name,
+                    definition,
+                })
+                .collect(),
+        })
+    }
+
+    /// Look up a method by name
+    pub fn method(&self, name: &str) -> Option<&CandidMethod> {
+        self.methods.iter().find(|m| m.name == name)
+    }
+
+    /// Check that the fetch methods of an entity config exist on this canister
+    ///
+    /// Returns a list of human-readable problems; empty when the config matches.
+    pub fn validate_entity_config(&self, config: &EntityConfig) -> Vec<String> {
+        let mut problems = vec![];
+
+        let methods = std::iter::once(&config.fetch_method).chain(config.fetch_many_method.as_ref());
+        for method in methods {
+            match self.method(method) {
+                None => problems.push(format!(
+                    "Entity '{}': method '{}' not found on canister {}",
+                    config.name, method, self.canister_id
+                )),
+                Some(m) if m.args.trim().is_empty() && method == &config.fetch_method => {
+                    problems.push(format!(
+                        "Entity '{}': method '{}' takes no arguments but is called with an entity ID",
+                        config.name, method
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+
+        problems
+    }
+}
+
+/// Data source that introspects a canister's Candid interface
+///
+/// The target canister must export its interface (e.g. with
+/// `ic_cdk::export_candid!()`).
+pub struct CandidMetadataSource {
+    canister_id: Principal,
+}
+
+impl CandidMetadataSource {
+    pub fn new(canister_id: Principal) -> Self {
+        Self { canister_id }
+    }
+
+    /// Fetch the raw `.did` text of the target canister
+    pub async fn fetch_did(&self) -> Result<String> {
+        let _permit = concurrency::acquire()?;
+
+        #[cfg(target_family = "wasm")]
+        {
+            let (did,): (String,) = ic_cdk::call(self.canister_id, CANDID_INTERFACE_METHOD, ())
+                .await
+                .map_err(|(code, msg)| {
+                    ContragError::CanisterCallError(format!(
+                        "{} failed: {:?} - {}",
+                        CANDID_INTERFACE_METHOD, code, msg
+                    ))
+                })?;
+            Ok(did)
+        }
+
+        #[cfg(not(target_family = "wasm"))]
+        {
+            Err(ContragError::CanisterCallError(format!(
+                "Cannot call {} on {} outside of WASM",
+                CANDID_INTERFACE_METHOD, self.canister_id
+            )))
+        }
+    }
+
+    /// Fetch and parse the target canister's interface
+    pub async fn fetch_interface(&self) -> Result<CandidInterface> {
+        let did = self.fetch_did().await?;
+        CandidInterface::parse(&self.canister_id.to_text(), &did)
+    }
+}
+
+/// Validate the fetch methods of entity configs against their canisters
+///
+/// Each distinct canister is introspected once. Canisters whose interface
+/// cannot be fetched are reported as problems too.
+pub async fn validate_fetch_methods(configs: &[EntityConfig]) -> Vec<String> {
+    let mut interfaces: BTreeMap<String, std::result::Result<CandidInterface, String>> = BTreeMap::new();
+    let mut problems = vec![];
+
+    for config in configs {
+        if !interfaces.contains_key(&config.canister_id) {
+            let interface = match Principal::from_text(&config.canister_id) {
+                Ok(id) => CandidMetadataSource::new(id)
+                    .fetch_interface()
+                    .await
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(format!("Invalid canister ID: {}", e)),
+            };
+            interfaces.insert(config.canister_id.clone(), interface);
+        }
+
+        match &interfaces[&config.canister_id] {
+            Ok(interface) => problems.extend(interface.validate_entity_config(config)),
+            Err(e) => problems.push(format!("Entity '{}': {}", config.name, e)),
+        }
+    }
+
+    problems
+}
+
+fn strip_comments(source: &str) -> String {
+    source
+        .lines()
+        .map(|line| match line.find("//") {
+            Some(pos) => &line[..pos],
+            None => line,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Split on `separator` outside of brackets and quotes, dropping empty parts
+fn split_top_level(source: &str, separator: char) -> Vec<String> {
+    let mut parts = vec![];
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut current = String::new();
+
+    for c in source.chars() {
+        match c {
+            '"' => in_string = !in_string,
+            '{' | '(' if !in_string => depth += 1,
+            '}' | ')' if !in_string => depth -= 1,
+            _ => {}
+        }
+
+        if c == separator && depth == 0 && !in_string {
+            parts.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+    parts.push(current);
+
+    parts
+        .into_iter()
+        .map(|p| p.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// Split `(args) -> (returns) mode` into its parts
+fn parse_signature(signature: &str) -> (String, String, String) {
+    let (args, rest) = match signature.find("->") {
+        Some(pos) => (&signature[..pos], &signature[pos + 2..]),
+        // A reference to a named func type
+        None => return (String::new(), signature.to_string(), "update".to_string()),
+    };
+
+    let rest = rest.trim();
+    let (returns, mode) = match rest.strip_prefix('(').and_then(|r| matching_paren(r).map(|end| (r, end))) {
+        Some((r, end)) => (&r[..end], r[end + 1..].trim()),
+        None => (rest, ""),
+    };
+
+    let mode = if mode.is_empty() { "update" } else { mode };
+    (strip_parens(args.trim()), returns.trim().to_string(), mode.to_string())
+}
+
+/// Byte offset of the `)` closing an already opened parenthesis
+fn matching_paren(source: &str) -> Option<usize> {
+    let mut depth = 1;
+    for (i, c) in source.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn strip_parens(source: &str) -> String {
+    source
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .unwrap_or(source)
+        .trim()
+        .to_string()
+}
+
+fn unquote(name: &str) -> String {
+    name.trim_matches('"').to_string()
+}
+
+fn referenced_types(signature: &str, types: &BTreeMap<String, String>) -> Vec<String> {
+    let mut found: Vec<String> = signature
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| types.contains_key(*word))
+        .map(|word| word.to_string())
+        .collect();
+    found.sort();
+    found.dedup();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DID: &str = r#"
+        // User service
+        type User = record { id : text; name : text; email : text };
+        type Result = variant { Ok : User; Err : text };
+        service : (opt text) -> {
+            get_user : (text) -> (Result) query;
+            list_users : () -> (vec User) query;
+            "create_user" : (record { name : text }) -> (Result);
+            notify : (text) -> () oneway;
+        }
+    "#;
+
+    #[test]
+    fn test_parse_interface() {
+        let interface = CandidInterface::parse("aaaaa-aa", DID).unwrap();
+
+        assert_eq!(interface.types.len(), 2);
+        assert_eq!(interface.methods.len(), 4);
+
+        let get_user = interface.method("get_user").unwrap();
+        assert_eq!(get_user.args, "text");
+        assert_eq!(get_user.returns, "Result");
+        assert!(get_user.is_query());
+        assert_eq!(get_user.referenced_types, vec!["Result"]);
+
+        let create = interface.method("create_user").unwrap();
+        assert_eq!(create.args, "record { name : text }");
+        assert_eq!(create.mode, "update");
+        assert_eq!(interface.method("notify").unwrap().mode, "oneway");
+
+        let config = EntityConfig {
+            name: "User".to_string(),
+            canister_id: "aaaaa-aa".to_string(),
+            fetch_method: "list_users".to_string(),
+            fetch_many_method: Some("get_users".to_string()),
+            relationships: vec![],
+            auto_include: true,
+            pagination: None,
+            updated_at_field: None,
+        };
+        assert_eq!(interface.validate_entity_config(&config).len(), 2);
+    }
+}
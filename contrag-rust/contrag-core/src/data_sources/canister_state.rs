@@ -1,5 +1,8 @@
-use candid::{CandidType, Principal, encode_one};
-use crate::data_sources::DataSource;
+use candid::{CandidType, Principal, Reserved, decode_one, encode_args, encode_one};
+use serde::de::DeserializeOwned;
+use crate::concurrency;
+use crate::config::PaginationStyle;
+use crate::data_sources::{DataSource, EntityPage};
 use crate::entity::RagEntity;
 use crate::error::{ContragError, Result};
 use crate::config::EntityConfig;
@@ -30,36 +33,54 @@ impl CanisterStateSource {
             })
     }
 
+    /// Reply bytes of an inter-canister call
+    ///
+    /// Note: calls only work in a canister (WASM); elsewhere this returns
+    /// an error.
+    async fn call_reply(&self, canister_id: Principal, method: &str, args: Vec<u8>) -> Result<Vec<u8>> {
+        let _permit = concurrency::acquire()?;
+
+        #[cfg(feature = "chaos")]
+        crate::chaos::slow_call().await;
+
+        #[cfg(target_family = "wasm")]
+        {
+            use ic_cdk::api::call::call_raw;
+
+            call_raw(canister_id, method, args, 0)
+                .await
+                .map_err(|(code, msg)| {
+                    ContragError::CanisterCallError(format!("Call failed: {:?} - {}", code, msg))
+                })
+        }
+
+        #[cfg(not(target_family = "wasm"))]
+        {
+            Err(ContragError::CanisterCallError(
+                "Canister calls only work in WASM environment".to_string()
+            ))
+        }
+    }
+
     /// Make inter-canister call to fetch entity
-    /// 
-    /// Note: This is a placeholder for the actual ICP inter-canister call.
-    /// In a real canister, you would use ic_cdk::call
     async fn call_canister<T: CandidType>(
         &self,
         canister_id: Principal,
         method: &str,
         args: Vec<u8>,
     ) -> Result<T> {
-        // This would be implemented using ic_cdk::call in actual canister code
-        // For now, this is a placeholder that will be replaced in the canister implementation
-        
+        let reply = self.call_reply(canister_id, method, args).await?;
+
         #[cfg(target_family = "wasm")]
         {
-            use ic_cdk::api::call::call_raw;
-            
-            let result = call_raw(canister_id, method, args, 0)
-                .await
-                .map_err(|(code, msg)| {
-                    ContragError::CanisterCallError(format!("Call failed: {:?} - {}", code, msg))
-                })?;
-            
-            decode_one(&result).map_err(|e| {
+            decode_one(&reply).map_err(|e| {
                 ContragError::CanisterCallError(format!("Failed to decode response: {}", e))
             })
         }
-        
+
         #[cfg(not(target_family = "wasm"))]
         {
+            let _ = reply;
             Err(ContragError::CanisterCallError(
                 "Canister calls only work in WASM environment".to_string()
             ))
@@ -67,6 +88,28 @@ impl CanisterStateSource {
     }
 }
 
+/// Whether the reply of a fetch method holds an entity of type `T`
+///
+/// `null`, from a method returning `opt T`, means the entity is gone. Any
+/// other reply must decode as `T` or `opt T`; since candid decodes a
+/// mismatched `opt` value as `null`, a reply of another shape (a changed
+/// record, a `Result` variant) is an error, never a missing entity.
+fn reply_holds_entity<T: CandidType + DeserializeOwned>(reply: &[u8]) -> Result<bool> {
+    let value: Option<Reserved> = decode_one(reply).map_err(|e| {
+        ContragError::CanisterCallError(format!("Failed to decode response: {}", e))
+    })?;
+    if value.is_none() {
+        return Ok(false);
+    }
+
+    if decode_one::<T>(reply).is_ok() || matches!(decode_one::<Option<T>>(reply), Ok(Some(_))) {
+        return Ok(true);
+    }
+    Err(ContragError::CanisterCallError(
+        "Fetch reply is neither the entity type nor an opt of it".to_string(),
+    ))
+}
+
 #[async_trait::async_trait]
 impl DataSource for CanisterStateSource {
     async fn read_entity<T: RagEntity + CandidType>(
@@ -87,6 +130,25 @@ impl DataSource for CanisterStateSource {
             .await
     }
 
+    /// A `null` reply (the fetch method returns `opt T`) means missing; a
+    /// reply that decodes as neither `T` nor `opt T` is an error
+    async fn entity_exists<T: RagEntity + CandidType + DeserializeOwned + Send>(
+        &self,
+        entity_type: &str,
+        entity_id: &str,
+    ) -> Result<bool> {
+        let config = self.get_config(entity_type)?;
+
+        let canister_id = Principal::from_text(&config.canister_id)
+            .map_err(|e| ContragError::ConfigError(format!("Invalid canister ID: {}", e)))?;
+
+        let args = encode_one(&entity_id)
+            .map_err(|e| ContragError::SerializationError(format!("Failed to encode args: {}", e)))?;
+
+        let reply = self.call_reply(canister_id, &config.fetch_method, args).await?;
+        reply_holds_entity::<T>(&reply)
+    }
+
     async fn read_entities<T: RagEntity + CandidType + Send>(
         &self,
         entity_type: &str,
@@ -97,6 +159,14 @@ impl DataSource for CanisterStateSource {
         // Fetch entities one by one
         // TODO: Optimize with batch fetch if fetch_many_method is configured
         for id in entity_ids {
+            #[cfg(feature = "chaos")]
+            {
+                if let Err(e) = crate::chaos::batch_item(entity_type, &id) {
+                    ic_cdk::println!("Failed to fetch entity {} of type {}: {:?}", id, entity_type, e);
+                    continue;
+                }
+            }
+
             match self.read_entity(entity_type, &id).await {
                 Ok(entity) => entities.push(entity),
                 Err(e) => {
@@ -129,9 +199,87 @@ impl DataSource for CanisterStateSource {
             Ok(vec![])
         }
     }
+
+    async fn read_page<T: RagEntity + CandidType + Send>(
+        &self,
+        entity_type: &str,
+        cursor: Option<String>,
+    ) -> Result<EntityPage<T>> {
+        let config = self.get_config(entity_type)?;
+
+        let (Some(method), Some(pagination)) = (&config.fetch_many_method, &config.pagination) else {
+            return Err(ContragError::ConfigError(format!(
+                "Entity type {} needs fetch_many_method and pagination to be paged",
+                entity_type
+            )));
+        };
+
+        let canister_id = Principal::from_text(&config.canister_id)
+            .map_err(|e| ContragError::ConfigError(format!("Invalid canister ID: {}", e)))?;
+        let limit = pagination.page_size;
+
+        match pagination.style {
+            PaginationStyle::Offset => {
+                // The cursor of an offset collection is the offset itself
+                let offset: u64 = match &cursor {
+                    Some(cursor) => cursor.parse().map_err(|_| {
+                        ContragError::DataSourceError(format!("Invalid offset cursor: {}", cursor))
+                    })?,
+                    None => 0,
+                };
+
+                let args = encode_args((offset, limit))
+                    .map_err(|e| ContragError::SerializationError(format!("Failed to encode args: {}", e)))?;
+                let items: Vec<T> = self.call_canister(canister_id, method, args).await?;
+
+                let next_cursor = if items.len() < limit as usize {
+                    None
+                } else {
+                    Some((offset + items.len() as u64).to_string())
+                };
+                Ok(EntityPage { items, next_cursor })
+            }
+            PaginationStyle::Cursor => {
+                let args = encode_args((cursor, limit))
+                    .map_err(|e| ContragError::SerializationError(format!("Failed to encode args: {}", e)))?;
+                self.call_canister(canister_id, method, args).await
+            }
+        }
+    }
 }
 
 /// Helper to create a canister state source from config
 pub fn create_from_config(entity_configs: Vec<EntityConfig>) -> CanisterStateSource {
     CanisterStateSource::new(entity_configs)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(CandidType, Serialize, Deserialize)]
+    struct User {
+        name: String,
+    }
+
+    #[derive(CandidType, Serialize)]
+    struct Renamed {
+        full_name: String,
+    }
+
+    #[test]
+    fn test_reply_holds_entity() {
+        let user = || User { name: "alice".to_string() };
+
+        assert!(reply_holds_entity::<User>(&encode_one(user()).unwrap()).unwrap());
+        assert!(reply_holds_entity::<User>(&encode_one(Some(user())).unwrap()).unwrap());
+        assert!(!reply_holds_entity::<User>(&encode_one(None::<User>).unwrap()).unwrap());
+
+        // Replies of another shape must not look like deleted entities
+        let result: std::result::Result<User, String> = Ok(user());
+        assert!(reply_holds_entity::<User>(&encode_one(result).unwrap()).is_err());
+        let renamed = Some(Renamed { full_name: "alice".to_string() });
+        assert!(reply_holds_entity::<User>(&encode_one(renamed).unwrap()).is_err());
+    }
+}
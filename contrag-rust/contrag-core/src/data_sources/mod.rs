@@ -1,9 +1,20 @@
+pub mod candid_metadata;
 pub mod canister_state;
 pub mod stable_memory;
 
 use candid::CandidType;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
 use crate::entity::RagEntity;
-use crate::error::Result;
+use crate::error::{ContragError, Result};
+
+/// One page of a collection read with [`DataSource::read_page`]
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct EntityPage<T> {
+    pub items: Vec<T>,
+    /// Cursor of the next page; `None` on the last page
+    pub next_cursor: Option<String>,
+}
 
 /// Trait for data sources that can provide entities
 /// 
@@ -24,6 +35,23 @@ pub trait DataSource: Send + Sync {
         entity_ids: Vec<String>,
     ) -> Result<Vec<T>>;
 
+    /// Whether an entity still exists at the source
+    ///
+    /// The default reads it and treats [`ContragError::EntityNotFound`] as
+    /// missing. Any other error is passed on, so an unreachable source
+    /// never makes an entity look deleted.
+    async fn entity_exists<T: RagEntity + CandidType + DeserializeOwned + Send>(
+        &self,
+        entity_type: &str,
+        entity_id: &str,
+    ) -> Result<bool> {
+        match self.read_entity::<T>(entity_type, entity_id).await {
+            Ok(_) => Ok(true),
+            Err(ContragError::EntityNotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Query entities with optional filtering
     /// This is optional and can be implemented for more advanced querying
     async fn query_entities<T: RagEntity + CandidType>(
@@ -34,4 +62,20 @@ pub trait DataSource: Send + Sync {
         // Default implementation returns empty
         Ok(vec![])
     }
+
+    /// Read one page of the whole collection of an entity type
+    ///
+    /// Pass `None` for the first page and the returned `next_cursor` after
+    /// that. Sources that can't enumerate a collection keep the default,
+    /// which returns an error.
+    async fn read_page<T: RagEntity + CandidType + Send>(
+        &self,
+        entity_type: &str,
+        _cursor: Option<String>,
+    ) -> Result<EntityPage<T>> {
+        Err(ContragError::DataSourceError(format!(
+            "Data source cannot page through {} entities",
+            entity_type
+        )))
+    }
 }
@@ -0,0 +1,226 @@
+//! Built-in entity types for unstructured content
+//!
+//! [`Document`], [`Chunk`] and [`Collection`] cover knowledge bases, notes
+//! and other free text without defining a domain entity. The pipeline
+//! ingests them natively ([`RagPipeline::ingest_document`] and
+//! [`RagPipeline::ingest_chunks`]), skipping the field-by-field context
+//! used for structured entities.
+//!
+//! [`RagPipeline::ingest_document`]: crate::pipeline::RagPipeline::ingest_document
+//! [`RagPipeline::ingest_chunks`]: crate::pipeline::RagPipeline::ingest_chunks
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::entity::{EntityRelationship, RagEntity, RelationshipType};
+use crate::error::Result;
+use crate::namespace::Namespace;
+
+/// A piece of unstructured content, e.g. a page, article or file
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct Document {
+    pub id: String,
+    pub title: String,
+    pub content: String,
+    /// Collection the document belongs to, if any
+    pub collection_id: Option<String>,
+    /// Where the content came from, e.g. a URL or file name
+    pub source: Option<String>,
+    /// Extra fields indexed with the content
+    pub metadata: Vec<(String, String)>,
+}
+
+impl Document {
+    pub fn new(id: impl Into<String>, title: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            title: title.into(),
+            content: content.into(),
+            collection_id: None,
+            source: None,
+            metadata: vec![],
+        }
+    }
+
+    pub fn with_collection(mut self, collection_id: impl Into<String>) -> Self {
+        self.collection_id = Some(collection_id.into());
+        self
+    }
+
+    pub fn with_source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.push((key.into(), value.into()));
+        self
+    }
+
+    /// Namespace the document is indexed in
+    ///
+    /// Documents of a collection share the collection's namespace so the
+    /// whole collection is searched at once; others get `Document:<id>`.
+    pub fn namespace(&self) -> Result<Namespace> {
+        match &self.collection_id {
+            Some(collection_id) => Namespace::entity::<Collection>(collection_id),
+            None => Namespace::entity::<Document>(&self.id),
+        }
+    }
+}
+
+impl RagEntity for Document {
+    fn entity_type() -> &'static str {
+        "Document"
+    }
+
+    fn entity_id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn to_context_map(&self) -> Vec<(String, String)> {
+        let mut map = vec![("title".to_string(), self.title.clone())];
+        if let Some(source) = &self.source {
+            map.push(("source".to_string(), source.clone()));
+        }
+        map.extend(self.metadata.iter().cloned());
+        map.push(("content".to_string(), self.content.clone()));
+        map
+    }
+
+    fn relationships(&self) -> Vec<EntityRelationship> {
+        self.collection_id
+            .iter()
+            .map(|collection_id| EntityRelationship {
+                field_name: "collection_id".to_string(),
+                target_entity_type: Collection::entity_type().to_string(),
+                target_id: collection_id.clone(),
+                relationship_type: RelationshipType::ManyToOne,
+            })
+            .collect()
+    }
+
+    /// The title and metadata as a short header, then the content as is
+    fn to_text(&self) -> String {
+        let mut lines = vec![format!("Title: {}", self.title)];
+        if let Some(source) = &self.source {
+            lines.push(format!("Source: {}", source));
+        }
+        for (key, value) in &self.metadata {
+            lines.push(format!("{}: {}", key, value));
+        }
+        lines.push(String::new());
+        lines.push(self.content.clone());
+
+        lines.join("\n")
+    }
+}
+
+/// A pre-split piece of a document
+///
+/// For content already chunked upstream (by a parser, or per page or
+/// paragraph); the pipeline stores chunks as they are instead of
+/// re-chunking, indexed as chunks of their document.
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct Chunk {
+    pub document_id: String,
+    /// Position within the document, from 0
+    pub index: u32,
+    pub text: String,
+}
+
+impl Chunk {
+    pub fn new(document_id: impl Into<String>, index: u32, text: impl Into<String>) -> Self {
+        Self {
+            document_id: document_id.into(),
+            index,
+            text: text.into(),
+        }
+    }
+}
+
+impl RagEntity for Chunk {
+    fn entity_type() -> &'static str {
+        "Chunk"
+    }
+
+    fn entity_id(&self) -> String {
+        format!("{}-{}", self.document_id, self.index)
+    }
+
+    fn to_context_map(&self) -> Vec<(String, String)> {
+        vec![
+            ("document_id".to_string(), self.document_id.clone()),
+            ("index".to_string(), self.index.to_string()),
+            ("text".to_string(), self.text.clone()),
+        ]
+    }
+
+    fn relationships(&self) -> Vec<EntityRelationship> {
+        vec![EntityRelationship {
+            field_name: "document_id".to_string(),
+            target_entity_type: Document::entity_type().to_string(),
+            target_id: self.document_id.clone(),
+            relationship_type: RelationshipType::ManyToOne,
+        }]
+    }
+
+    fn to_text(&self) -> String {
+        self.text.clone()
+    }
+}
+
+/// A named group of documents searched together
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct Collection {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub document_ids: Vec<String>,
+}
+
+impl Collection {
+    pub fn new(id: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            description: None,
+            document_ids: vec![],
+        }
+    }
+
+    /// Namespace shared by the collection's documents
+    pub fn namespace(&self) -> Result<Namespace> {
+        Namespace::entity::<Collection>(&self.id)
+    }
+}
+
+impl RagEntity for Collection {
+    fn entity_type() -> &'static str {
+        "Collection"
+    }
+
+    fn entity_id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn to_context_map(&self) -> Vec<(String, String)> {
+        let mut map = vec![("name".to_string(), self.name.clone())];
+        if let Some(description) = &self.description {
+            map.push(("description".to_string(), description.clone()));
+        }
+        map.push(("documents".to_string(), self.document_ids.len().to_string()));
+        map
+    }
+
+    fn relationships(&self) -> Vec<EntityRelationship> {
+        self.document_ids
+            .iter()
+            .map(|document_id| EntityRelationship {
+                field_name: "document_ids".to_string(),
+                target_entity_type: Document::entity_type().to_string(),
+                target_id: document_id.clone(),
+                relationship_type: RelationshipType::OneToMany,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,282 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::config::EmbedderConfigDef;
+use crate::embedders::http_client::{batch_end, BatchLimits};
+use crate::embedders::Embedder;
+use crate::error::{ContragError, ProviderErrorKind, Result};
+use crate::types::ConnectionTestResult;
+
+/// Bounds and growth policy for adaptive batching
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+#[serde(default)]
+pub struct BatcherConfig {
+    /// Batch size used before anything has been learned
+    pub initial_batch_size: usize,
+
+    /// Smallest batch size to back off to
+    pub min_batch_size: usize,
+
+    /// Largest batch size to grow to
+    pub max_batch_size: usize,
+
+    /// Consecutive successful requests required before growing
+    pub grow_after_successes: u32,
+
+    /// Estimated tokens allowed in one request; `None` means unlimited
+    pub max_batch_tokens: Option<usize>,
+
+    /// Estimated bytes allowed in one request body; `None` leaves it to the
+    /// outcall size limits, which the built-in providers already split
+    /// batches by
+    pub max_batch_bytes: Option<usize>,
+}
+
+impl Default for BatcherConfig {
+    fn default() -> Self {
+        Self {
+            initial_batch_size: 8,
+            min_batch_size: 1,
+            max_batch_size: 256,
+            grow_after_successes: 3,
+            // OpenAI caps a request at 300k tokens
+            max_batch_tokens: Some(250_000),
+            max_batch_bytes: None,
+        }
+    }
+}
+
+/// Learned batch size for one provider
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct BatchTuning {
+    pub batch_size: usize,
+    pub consecutive_successes: u32,
+    pub backoffs: u64,
+}
+
+thread_local! {
+    static TUNING: RefCell<BTreeMap<String, BatchTuning>> = RefCell::new(BTreeMap::new());
+}
+
+/// Batch sizes learned so far, by provider name
+///
+/// Keep them in the canister's own upgrade snapshot so a new wasm resumes at
+/// the learned sizes instead of backing off from the conservative start again.
+pub fn export_tuning() -> Vec<(String, BatchTuning)> {
+    TUNING.with(|t| t.borrow().iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+}
+
+/// Reload sizes returned by [`export_tuning`], replacing what this instance
+/// learned for the same providers
+pub fn import_tuning(entries: Vec<(String, BatchTuning)>) {
+    TUNING.with(|t| t.borrow_mut().extend(entries));
+}
+
+/// Embedder wrapper that splits input into adaptively sized batches
+///
+/// Starts at a conservative batch size, doubles it after a run of successful
+/// requests and halves it (retrying the same texts) when the provider or the
+/// IC rejects a request as too large or times out. Learned sizes are shared
+/// per provider name across all wrapped instances. Independently of the
+/// learned size, a batch is cut before it exceeds `max_batch_tokens` or
+/// `max_batch_bytes`, so long texts don't need a backoff to fit.
+pub struct AdaptiveBatcher<E: Embedder> {
+    embedder: E,
+    config: BatcherConfig,
+}
+
+impl<E: Embedder> AdaptiveBatcher<E> {
+    pub fn new(embedder: E, config: BatcherConfig) -> Self {
+        Self { embedder, config }
+    }
+
+    /// Apply the `batching` settings of an embedder config
+    pub fn from_config(embedder: E, config: &EmbedderConfigDef) -> Self {
+        Self::new(embedder, config.batching.clone())
+    }
+
+    /// End of the batch starting at `start`: at most `batch_size` texts and
+    /// within the token and byte limits, but always at least one text
+    fn batch_end(&self, texts: &[String], start: usize, batch_size: usize) -> usize {
+        let limits = BatchLimits {
+            max_texts: batch_size,
+            max_tokens: self.config.max_batch_tokens,
+            max_request_bytes: self.config.max_batch_bytes.map_or(u64::MAX, |bytes| bytes as u64),
+            ..BatchLimits::default()
+        };
+        batch_end(texts, start, &limits)
+    }
+
+    /// Batch size currently used for the wrapped provider
+    pub fn current_batch_size(&self) -> usize {
+        self.with_tuning(|t| t.batch_size)
+    }
+
+    fn with_tuning<R>(&self, f: impl FnOnce(&mut BatchTuning) -> R) -> R {
+        let initial = self
+            .config
+            .initial_batch_size
+            .clamp(self.config.min_batch_size.max(1), self.config.max_batch_size.max(1));
+
+        TUNING.with(|t| {
+            let mut tuning = t.borrow_mut();
+            let entry = tuning
+                .entry(self.embedder.name().to_string())
+                .or_insert_with(|| BatchTuning {
+                    batch_size: initial,
+                    consecutive_successes: 0,
+                    backoffs: 0,
+                });
+            f(entry)
+        })
+    }
+
+    fn record_success(&self) {
+        let config = &self.config;
+        self.with_tuning(|t| {
+            t.consecutive_successes += 1;
+            if t.consecutive_successes >= config.grow_after_successes {
+                t.batch_size = (t.batch_size * 2).min(config.max_batch_size.max(1));
+                t.consecutive_successes = 0;
+            }
+        });
+    }
+
+    fn record_backoff(&self) {
+        let min = self.config.min_batch_size.max(1);
+        self.with_tuning(|t| {
+            t.batch_size = (t.batch_size / 2).max(min);
+            t.consecutive_successes = 0;
+            t.backoffs += 1;
+        });
+    }
+}
+
+/// Whether an error means the request was too large or too slow
+fn is_oversize(error: &ContragError) -> bool {
+    match error {
+        ContragError::EmbedderError(failure) => failure.provider.as_ref().is_some_and(|e| {
+            matches!(
+                e.kind,
+                ProviderErrorKind::PayloadTooLarge | ProviderErrorKind::ContextLengthExceeded
+            )
+        }),
+        ContragError::HttpOutcallError(msg) => {
+            let msg = msg.to_lowercase();
+            ["size limit", "too large", "timeout", "timed out"]
+                .iter()
+                .any(|needle| msg.contains(needle))
+        }
+        _ => false,
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: Embedder> Embedder for AdaptiveBatcher<E> {
+    fn name(&self) -> &str {
+        self.embedder.name()
+    }
+
+    /// Calls whose embeddings depend on each other are sent unsplit
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        if self.embedder.embeds_in_context() {
+            return self.embedder.embed(texts).await;
+        }
+
+        let mut embeddings = Vec::with_capacity(texts.len());
+        let mut start = 0;
+
+        while start < texts.len() {
+            let batch_size = self.current_batch_size();
+            let end = self.batch_end(&texts, start, batch_size);
+
+            match self.embedder.embed(texts[start..end].to_vec()).await {
+                Ok(batch) => {
+                    self.record_success();
+                    embeddings.extend(batch);
+                    start = end;
+                }
+                Err(e) if is_oversize(&e) && batch_size > self.config.min_batch_size.max(1) => {
+                    self.record_backoff();
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(embeddings)
+    }
+
+    /// Queries are few and short, so they go out unbatched
+    async fn embed_queries(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.embedder.embed_queries(texts).await
+    }
+
+    fn dimensions(&self) -> usize {
+        self.embedder.dimensions()
+    }
+
+    fn embeds_in_context(&self) -> bool {
+        self.embedder.embeds_in_context()
+    }
+
+    async fn test_connection(&self) -> Result<ConnectionTestResult> {
+        self.embedder.test_connection().await
+    }
+
+    async fn generate_with_prompt(&self, text: String, system_prompt: String) -> Result<String> {
+        self.embedder.generate_with_prompt(text, system_prompt).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedders::mock::MockEmbedder;
+
+    #[tokio::test]
+    async fn test_backs_off_and_preserves_order() {
+        let batcher = AdaptiveBatcher::new(MockEmbedder::new(1).named("limited").with_max_batch(3), BatcherConfig::default());
+        let texts: Vec<String> = (0..20).map(|i| "x".repeat(i)).collect();
+
+        let embeddings = batcher.embed(texts.clone()).await.unwrap();
+
+        assert_eq!(embeddings.len(), 20);
+        for (text, embedding) in texts.iter().zip(&embeddings) {
+            assert_eq!(embedding[0], text.len() as f32);
+        }
+
+        let (_, tuning) = export_tuning()
+            .into_iter()
+            .find(|(name, _)| name == "limited")
+            .unwrap();
+        assert!(tuning.backoffs >= 2);
+    }
+
+    #[tokio::test]
+    async fn test_passes_context_dependent_calls_whole() {
+        let batcher = AdaptiveBatcher::new(MockEmbedder::new(1).with_max_batch(2).in_context(), BatcherConfig::default());
+
+        // Splitting would succeed but lose the shared context
+        assert!(batcher.embed(vec!["a".to_string(); 5]).await.is_err());
+        assert_eq!(batcher.embedder.calls(), 1);
+    }
+
+    #[test]
+    fn test_batches_cut_at_token_and_byte_limits() {
+        let config = BatcherConfig {
+            max_batch_tokens: Some(10),
+            max_batch_bytes: Some(200),
+            ..BatcherConfig::default()
+        };
+        let batcher = AdaptiveBatcher::new(MockEmbedder::new(1).with_max_batch(100), config);
+        let texts = vec!["x".repeat(16), "x".repeat(16), "x".repeat(16), "x".repeat(200)];
+
+        // 4 tokens and 80 bytes each: two fit under 10 tokens, the third doesn't
+        assert_eq!(batcher.batch_end(&texts, 0, 8), 2);
+        // An oversized text still goes out alone
+        assert_eq!(batcher.batch_end(&texts, 3, 8), 4);
+        assert_eq!(batcher.batch_end(&texts, 2, 8), 3);
+        assert_eq!(batcher.batch_end(&texts, 0, 1), 1);
+    }
+}
@@ -0,0 +1,473 @@
+//! Embedding cache that survives upgrades
+//!
+//! Unlike the heap-only [`EmbeddingCache`](super::EmbeddingCache), entries
+//! live in a [`StableBTreeMap`] in the [`ContragMemory::EmbeddingCache`]
+//! region, so they survive upgrades and traps without a pre_upgrade step,
+//! and re-indexing unchanged entities after an upgrade never calls the API.
+//! Entries are keyed by the SHA-256 of the provider, model, dimensions, task
+//! and text, so cached texts aren't kept and a change to any of them never
+//! serves stale embeddings. The cache is shared by every
+//! [`PersistentCachedEmbedder`] in the canister; open it with [`init_cache`]
+//! in init and post_upgrade.
+//!
+//! [`ContragMemory::EmbeddingCache`]: crate::storage::memory::ContragMemory::EmbeddingCache
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use candid::CandidType;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{Memory, StableBTreeMap, Storable};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::config::EmbedderConfigDef;
+use crate::embedders::Embedder;
+use crate::error::{ContragError, Result};
+use crate::types::ConnectionTestResult;
+
+/// Entries kept when the config doesn't set `cache_max_entries`
+pub const DEFAULT_CACHE_ENTRIES: usize = 10_000;
+
+/// Size and hit counters of an embedding cache
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, CandidType)]
+pub struct EmbeddingCacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl EmbeddingCacheStats {
+    /// Share of lookups served from the cache, 0 before any lookup
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+/// Embeddings with least-recently-used eviction
+pub(crate) struct Lru<K> {
+    entries: HashMap<K, (Vec<f32>, u64)>,
+    /// Keys by last use, oldest first
+    by_use: BTreeMap<u64, K>,
+    clock: u64,
+    stats: EmbeddingCacheStats,
+}
+
+impl<K> Default for Lru<K> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            by_use: BTreeMap::new(),
+            clock: 0,
+            stats: EmbeddingCacheStats::default(),
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash> Lru<K> {
+    /// Look up an entry, marking it as most recently used
+    pub(crate) fn get(&mut self, key: &K) -> Option<Vec<f32>> {
+        self.clock += 1;
+        let Some((embedding, last_used)) = self.entries.get_mut(key) else {
+            self.stats.misses += 1;
+            return None;
+        };
+
+        self.by_use.remove(last_used);
+        *last_used = self.clock;
+        self.by_use.insert(self.clock, key.clone());
+        self.stats.hits += 1;
+        Some(embedding.clone())
+    }
+
+    /// Insert an entry, evicting the least recently used ones beyond
+    /// `max_entries`
+    pub(crate) fn insert(&mut self, key: K, embedding: Vec<f32>, max_entries: usize) {
+        if max_entries == 0 {
+            return;
+        }
+        self.clock += 1;
+        if let Some((_, last_used)) = self.entries.remove(&key) {
+            self.by_use.remove(&last_used);
+        }
+        while self.entries.len() >= max_entries {
+            let Some((_, oldest)) = self.by_use.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+
+        self.by_use.insert(self.clock, key.clone());
+        self.entries.insert(key, (embedding, self.clock));
+    }
+
+    pub(crate) fn stats(&self) -> EmbeddingCacheStats {
+        EmbeddingCacheStats {
+            entries: self.entries.len(),
+            ..self.stats.clone()
+        }
+    }
+}
+
+/// Cached embedding: the cache clock at insertion, then the values as
+/// little-endian f32s
+struct CachedEmbedding {
+    inserted: u64,
+    embedding: Vec<f32>,
+}
+
+impl Storable for CachedEmbedding {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        let mut bytes = Vec::with_capacity(8 + self.embedding.len() * 4);
+        bytes.extend(self.inserted.to_le_bytes());
+        bytes.extend(self.embedding.iter().flat_map(|x| x.to_le_bytes()));
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let (inserted, values) = bytes.split_at(8);
+        Self {
+            inserted: u64::from_le_bytes(inserted.try_into().expect("Split at 8 bytes")),
+            embedding: values
+                .chunks_exact(4)
+                .map(|x| f32::from_le_bytes(x.try_into().expect("Chunks of 4 bytes")))
+                .collect(),
+        }
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// Memory the cache was opened over, whatever its type
+struct CacheMemory(Box<dyn Memory>);
+
+impl Memory for CacheMemory {
+    fn size(&self) -> u64 {
+        self.0.size()
+    }
+
+    fn grow(&self, pages: u64) -> i64 {
+        self.0.grow(pages)
+    }
+
+    fn read(&self, offset: u64, dst: &mut [u8]) {
+        self.0.read(offset, dst)
+    }
+
+    fn write(&self, offset: u64, src: &[u8]) {
+        self.0.write(offset, src)
+    }
+}
+
+/// Embeddings in stable memory with least-recently-used eviction
+///
+/// Only the use order is kept on the heap. It is rebuilt from insertion
+/// order when the cache is opened, so entries used since their insertion
+/// count as unused again after an upgrade.
+struct StableCache {
+    entries: StableBTreeMap<String, CachedEmbedding, CacheMemory>,
+    /// Keys by last use, oldest first
+    by_use: BTreeMap<u64, String>,
+    last_use: HashMap<String, u64>,
+    clock: u64,
+    stats: EmbeddingCacheStats,
+}
+
+impl StableCache {
+    fn open(memory: CacheMemory) -> Self {
+        let entries: StableBTreeMap<String, CachedEmbedding, CacheMemory> = StableBTreeMap::init(memory);
+        let mut cache = Self {
+            entries,
+            by_use: BTreeMap::new(),
+            last_use: HashMap::new(),
+            clock: 0,
+            stats: EmbeddingCacheStats::default(),
+        };
+        for (key, cached) in cache.entries.iter() {
+            cache.by_use.insert(cached.inserted, key.clone());
+            cache.last_use.insert(key, cached.inserted);
+            cache.clock = cache.clock.max(cached.inserted);
+        }
+        cache
+    }
+
+    fn touch(&mut self, key: &str) {
+        self.clock += 1;
+        if let Some(last_used) = self.last_use.insert(key.to_string(), self.clock) {
+            self.by_use.remove(&last_used);
+        }
+        self.by_use.insert(self.clock, key.to_string());
+    }
+
+    fn get(&mut self, key: &str) -> Option<Vec<f32>> {
+        let Some(cached) = self.entries.get(&key.to_string()) else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.touch(key);
+        self.stats.hits += 1;
+        Some(cached.embedding)
+    }
+
+    fn insert(&mut self, key: String, embedding: Vec<f32>, max_entries: usize) {
+        if max_entries == 0 {
+            return;
+        }
+        while !self.last_use.contains_key(&key) && self.entries.len() as usize >= max_entries {
+            let Some((_, oldest)) = self.by_use.pop_first() else {
+                break;
+            };
+            self.last_use.remove(&oldest);
+            self.entries.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+
+        self.touch(&key);
+        self.entries.insert(key, CachedEmbedding { inserted: self.clock, embedding });
+    }
+
+    fn clear(&mut self) {
+        for key in std::mem::take(&mut self.last_use).into_keys() {
+            self.entries.remove(&key);
+        }
+        self.by_use.clear();
+        self.stats = EmbeddingCacheStats::default();
+    }
+
+    fn stats(&self) -> EmbeddingCacheStats {
+        EmbeddingCacheStats {
+            entries: self.entries.len() as usize,
+            ..self.stats.clone()
+        }
+    }
+}
+
+thread_local! {
+    static CACHE: RefCell<Option<StableCache>> = const { RefCell::new(None) };
+}
+
+/// Open the cache over `memory`, the
+/// [`ContragMemory::EmbeddingCache`](crate::storage::memory::ContragMemory::EmbeddingCache)
+/// region, keeping the entries already stored there
+///
+/// Call it in init and post_upgrade. Until it is called,
+/// [`PersistentCachedEmbedder`]s send every text to the provider.
+pub fn init_cache(memory: impl Memory + 'static) {
+    let cache = StableCache::open(CacheMemory(Box::new(memory)));
+    CACHE.with(|c| *c.borrow_mut() = Some(cache));
+}
+
+/// Cache key of `text` embedded by `provider`'s `model` at `dimensions`
+/// for `task`: hex SHA-256 of all of them
+pub fn cache_key(provider: &str, model: &str, dimensions: usize, task: Option<&str>, text: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [provider, model, &dimensions.to_string(), task.unwrap_or_default(), text] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Current size and hit counters
+pub fn cache_stats() -> EmbeddingCacheStats {
+    CACHE.with(|c| c.borrow().as_ref().map(StableCache::stats).unwrap_or_default())
+}
+
+/// Drop every entry and reset the counters
+pub fn clear_cache() {
+    CACHE.with(|c| {
+        if let Some(cache) = c.borrow_mut().as_mut() {
+            cache.clear();
+        }
+    });
+}
+
+/// Embedder wrapper that serves document embeddings from the persistent
+/// cache and only sends cache misses to the provider
+///
+/// Query embeddings pass through uncached; the pipeline has its own
+/// [`QueryEmbeddingCache`](crate::pipeline::query_cache::QueryEmbeddingCache).
+pub struct PersistentCachedEmbedder<E: Embedder> {
+    embedder: E,
+    model: String,
+    task: Option<String>,
+    max_entries: usize,
+}
+
+impl<E: Embedder> PersistentCachedEmbedder<E> {
+    /// Cache embeddings of `model` from `embedder`, keeping at most
+    /// `max_entries` (least recently used entries are evicted first)
+    ///
+    /// Entries are also keyed by the embedder's name and dimensions.
+    pub fn new(embedder: E, model: impl Into<String>, max_entries: usize) -> Self {
+        Self {
+            embedder,
+            model: model.into(),
+            task: None,
+            max_entries,
+        }
+    }
+
+    /// Use the model, Jina task and `cache_max_entries` of an embedder
+    /// config
+    pub fn from_config(embedder: E, config: &EmbedderConfigDef) -> Self {
+        let task = config.jina.as_ref().and_then(|jina| jina.task.clone());
+        let cache = Self::new(
+            embedder,
+            config.model.clone(),
+            config.cache_max_entries.unwrap_or(DEFAULT_CACHE_ENTRIES),
+        );
+        match task {
+            Some(task) => cache.with_task(task),
+            None => cache,
+        }
+    }
+
+    /// Key entries by the task the embedder is set up for (e.g. a Gemini
+    /// `task_type`), so embeddings made for another task aren't served
+    pub fn with_task(mut self, task: impl Into<String>) -> Self {
+        self.task = Some(task.into());
+        self
+    }
+
+    fn key(&self, text: &str) -> String {
+        cache_key(
+            self.embedder.name(),
+            &self.model,
+            self.embedder.dimensions(),
+            self.task.as_deref(),
+            text,
+        )
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: Embedder> Embedder for PersistentCachedEmbedder<E> {
+    fn name(&self) -> &str {
+        self.embedder.name()
+    }
+
+    /// Embeddings that depend on the rest of the call are neither looked up
+    /// nor cached, since the same text embeds differently in another document
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        if self.embedder.embeds_in_context() {
+            return self.embedder.embed(texts).await;
+        }
+
+        let keys: Vec<String> = texts.iter().map(|t| self.key(t)).collect();
+        let mut embeddings: Vec<Option<Vec<f32>>> = CACHE.with(|c| match c.borrow_mut().as_mut() {
+            Some(cache) => keys.iter().map(|key| cache.get(key)).collect(),
+            None => vec![None; keys.len()],
+        });
+
+        let missing: Vec<usize> = (0..texts.len()).filter(|&i| embeddings[i].is_none()).collect();
+        if !missing.is_empty() {
+            let fresh = self
+                .embedder
+                .embed(missing.iter().map(|&i| texts[i].clone()).collect())
+                .await?;
+            if fresh.len() != missing.len() {
+                return Err(ContragError::EmbedderError(format!(
+                    "{} returned {} embeddings for {} texts",
+                    self.name(),
+                    fresh.len(),
+                    missing.len()
+                ).into()));
+            }
+
+            CACHE.with(|c| {
+                let mut cache = c.borrow_mut();
+                for (&i, embedding) in missing.iter().zip(fresh) {
+                    if let Some(cache) = cache.as_mut() {
+                        cache.insert(keys[i].clone(), embedding.clone(), self.max_entries);
+                    }
+                    embeddings[i] = Some(embedding);
+                }
+            });
+        }
+
+        Ok(embeddings.into_iter().flatten().collect())
+    }
+
+    async fn embed_queries(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.embedder.embed_queries(texts).await
+    }
+
+    fn dimensions(&self) -> usize {
+        self.embedder.dimensions()
+    }
+
+    fn embeds_in_context(&self) -> bool {
+        self.embedder.embeds_in_context()
+    }
+
+    async fn test_connection(&self) -> Result<ConnectionTestResult> {
+        self.embedder.test_connection().await
+    }
+
+    async fn generate_with_prompt(&self, text: String, system_prompt: String) -> Result<String> {
+        self.embedder.generate_with_prompt(text, system_prompt).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::DefaultMemoryImpl;
+    use crate::embedders::mock::MockEmbedder;
+
+    #[tokio::test]
+    async fn test_cache_hits_evicts_and_survives_reopening() {
+        let memory = DefaultMemoryImpl::default();
+        init_cache(memory.clone());
+        let embedder = PersistentCachedEmbedder::new(MockEmbedder::new(1), "m", 2);
+        let texts = |ts: &[&str]| ts.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(embedder.embed(texts(&["a", "bb"])).await.unwrap(), vec![vec![1.0], vec![2.0]]);
+        assert_eq!(embedder.embed(texts(&["bb", "ccc"])).await.unwrap(), vec![vec![2.0], vec![3.0]]);
+        assert_eq!(embedder.embedder.texts(), 3);
+        assert_eq!(
+            cache_stats(),
+            EmbeddingCacheStats { entries: 2, hits: 1, misses: 3, evictions: 1 }
+        );
+
+        // As after an upgrade: the entries are still in stable memory
+        init_cache(memory);
+        embedder.embed(texts(&["bb", "ccc"])).await.unwrap();
+        assert_eq!(embedder.embedder.texts(), 3);
+
+        clear_cache();
+        assert_eq!(cache_stats().entries, 0);
+        embedder.embed(texts(&["bb"])).await.unwrap();
+        assert_eq!(embedder.embedder.texts(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_context_dependent_embeddings_are_not_cached() {
+        init_cache(DefaultMemoryImpl::default());
+        let embedder = PersistentCachedEmbedder::new(MockEmbedder::new(1).in_context(), "m", 10);
+        let texts = vec!["a".to_string(), "bb".to_string()];
+
+        embedder.embed(texts.clone()).await.unwrap();
+        embedder.embed(texts).await.unwrap();
+        assert_eq!(embedder.embedder.texts(), 4);
+        assert_eq!(cache_stats().entries, 0);
+    }
+
+    #[test]
+    fn test_cache_key_covers_provider_dimensions_and_task() {
+        let key = cache_key("openai", "m", 1536, None, "a");
+        assert_ne!(key, cache_key("openai", "other", 1536, None, "a"));
+        assert_ne!(key, cache_key("azure_openai", "m", 1536, None, "a"));
+        assert_ne!(key, cache_key("openai", "m", 256, None, "a"));
+        assert_ne!(key, cache_key("openai", "m", 1536, Some("retrieval.passage"), "a"));
+        assert_eq!(key, cache_key("openai", "m", 1536, None, "a"));
+    }
+}
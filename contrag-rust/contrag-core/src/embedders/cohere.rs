@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use crate::config::{EmbedderConfigDef, OutcallConfig};
+use crate::embedders::{Embedder, http_client::{HttpClient, RequestClass}};
+use crate::embedders::provider_error::parse_cohere_error;
+use crate::embedders::validation::validate_embeddings;
+use crate::error::{ContragError, Result};
+use crate::types::ConnectionTestResult;
+
+/// Most texts Cohere accepts in one embed request
+const MAX_TEXTS_PER_REQUEST: usize = 96;
+
+/// Cohere embedder (embed v3 models) using HTTP outcalls
+///
+/// Cohere embeds documents and queries differently: [`Embedder::embed`]
+/// sends `input_type: search_document` and [`Embedder::embed_queries`]
+/// sends `search_query`, so retrieval needs the pipeline to embed queries
+/// through the latter, which it does.
+pub struct CohereEmbedder {
+    api_key: String,
+    model: String,
+    dimensions: usize,
+    api_endpoint: String,
+    http_client: HttpClient,
+}
+
+impl CohereEmbedder {
+    /// Create a new Cohere embedder
+    pub fn new(api_key: String, model: String) -> Self {
+        let dimensions = match model.as_str() {
+            "embed-english-v3.0" => 1024,
+            "embed-multilingual-v3.0" => 1024,
+            "embed-english-light-v3.0" => 384,
+            "embed-multilingual-light-v3.0" => 384,
+            _ => 1024, // default
+        };
+
+        Self {
+            api_key,
+            model,
+            dimensions,
+            api_endpoint: "https://api.cohere.com/v2/embed".to_string(),
+            http_client: HttpClient::new(),
+        }
+    }
+
+    /// Build from an embedder config with `provider = "cohere"`
+    pub fn from_config(config: &EmbedderConfigDef, outcalls: &OutcallConfig, api_key: String) -> Self {
+        let mut embedder = Self::new(api_key, config.model.clone()).with_http_client(HttpClient::from_config(config, outcalls));
+        if let Some(endpoint) = &config.api_endpoint {
+            embedder = embedder.with_endpoint(endpoint.clone());
+        }
+        embedder
+    }
+
+    /// Create with custom API endpoint
+    pub fn with_endpoint(mut self, endpoint: String) -> Self {
+        self.api_endpoint = endpoint;
+        self
+    }
+
+    /// Use a custom HTTP client (e.g. with compression enabled)
+    pub fn with_http_client(mut self, http_client: HttpClient) -> Self {
+        self.http_client = http_client;
+        self
+    }
+
+    async fn embed_as(&self, texts: Vec<String>, input_type: InputType) -> Result<Vec<Vec<f32>>> {
+        let count = texts.len();
+        let mut embeddings = Vec::with_capacity(count);
+
+        let batches = texts
+            .chunks(MAX_TEXTS_PER_REQUEST)
+            .flat_map(|chunk| self.http_client.split_batch(chunk, self.dimensions));
+        for batch in batches {
+            let request = CohereEmbedRequest {
+                model: &self.model,
+                texts: batch,
+                input_type,
+                embedding_types: ["float"],
+            };
+
+            let body = serde_json::to_vec(&request)
+                .map_err(|e| ContragError::SerializationError(e.to_string()))?;
+
+            let headers = vec![
+                ("Content-Type".to_string(), "application/json".to_string()),
+                ("Authorization".to_string(), format!("Bearer {}", self.api_key)),
+            ];
+
+            let response = self
+                .http_client
+                .post_as(RequestClass::Embedding, self.api_endpoint.clone(), headers, body)
+                .await?;
+
+            if response.status != 200 {
+                return Err(parse_cohere_error(&response));
+            }
+
+            let embed_response: CohereEmbedResponse = response.json()?;
+            embeddings.extend(embed_response.embeddings.float);
+        }
+
+        validate_embeddings("cohere", &embeddings, count, self.dimensions)?;
+        Ok(embeddings)
+    }
+}
+
+#[async_trait::async_trait]
+impl Embedder for CohereEmbedder {
+    fn name(&self) -> &str {
+        "cohere"
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.embed_as(texts, InputType::SearchDocument).await
+    }
+
+    async fn embed_queries(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.embed_as(texts, InputType::SearchQuery).await
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    async fn test_connection(&self) -> Result<ConnectionTestResult> {
+        let start = ic_cdk::api::time();
+
+        match self.embed(vec!["test connection".to_string()]).await {
+            Ok(_) => {
+                let latency = (ic_cdk::api::time() - start) / 1_000_000; // Convert to ms
+                Ok(ConnectionTestResult {
+                    plugin: self.name().to_string(),
+                    connected: true,
+                    latency: Some(latency),
+                    error: None,
+                    details: Some(format!(
+                        "model: {}, dimensions: {}",
+                        self.model, self.dimensions
+                    )),
+                })
+            }
+            Err(e) => Ok(ConnectionTestResult {
+                plugin: self.name().to_string(),
+                connected: false,
+                latency: None,
+                error: Some(e.to_string()),
+                details: None,
+            }),
+        }
+    }
+}
+
+// Request/Response types for the Cohere v2 embed API
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum InputType {
+    SearchDocument,
+    SearchQuery,
+}
+
+#[derive(Serialize)]
+struct CohereEmbedRequest<'a> {
+    model: &'a str,
+    texts: &'a [String],
+    input_type: InputType,
+    embedding_types: [&'static str; 1],
+}
+
+#[derive(Deserialize)]
+struct CohereEmbedResponse {
+    embeddings: CohereEmbeddings,
+}
+
+#[derive(Deserialize)]
+struct CohereEmbeddings {
+    float: Vec<Vec<f32>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_and_response_shape() {
+        let texts = vec!["refund policy".to_string()];
+        let request = CohereEmbedRequest {
+            model: "embed-english-v3.0",
+            texts: &texts,
+            input_type: InputType::SearchQuery,
+            embedding_types: ["float"],
+        };
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "model": "embed-english-v3.0",
+                "texts": ["refund policy"],
+                "input_type": "search_query",
+                "embedding_types": ["float"],
+            })
+        );
+
+        let response: CohereEmbedResponse =
+            serde_json::from_str(r#"{"id":"x","embeddings":{"float":[[0.1,0.2]]},"texts":["refund policy"]}"#).unwrap();
+        assert_eq!(response.embeddings.float, vec![vec![0.1, 0.2]]);
+        assert_eq!(CohereEmbedder::new("key".to_string(), "embed-english-light-v3.0".to_string()).dimensions(), 384);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::embedders::Embedder;
+use crate::error::{ContragError, ProviderError, ProviderErrorKind, Result};
+use crate::types::ConnectionTestResult;
+
+/// Embedder that falls back to a secondary provider when the primary fails
+///
+/// A call goes to the secondary when the primary's fails with an outage:
+/// a retryable provider error (rate limit, server error), a failed or
+/// timed-out outcall, or a malformed response. Errors that the secondary
+/// would hit as well, such as invalid config or a local concurrency limit,
+/// are returned as they are.
+///
+/// Both embedders must produce the same number of dimensions, which
+/// [`FallbackEmbedder::new`] checks. Embeddings of different models don't
+/// share a vector space even then, so the secondary should serve the same
+/// model, e.g. OpenAI with an Azure OpenAI deployment of it.
+pub struct FallbackEmbedder<P: Embedder, S: Embedder> {
+    primary: P,
+    secondary: S,
+    fails_over: fn(&ContragError) -> bool,
+}
+
+impl<P: Embedder, S: Embedder> FallbackEmbedder<P, S> {
+    pub fn new(primary: P, secondary: S) -> Result<Self> {
+        if primary.dimensions() != secondary.dimensions() {
+            return Err(ContragError::InvalidConfig(format!(
+                "Fallback embedder {} has {} dimensions, but {} has {}",
+                secondary.name(),
+                secondary.dimensions(),
+                primary.name(),
+                primary.dimensions()
+            )));
+        }
+        Ok(Self {
+            primary,
+            secondary,
+            fails_over: is_outage,
+        })
+    }
+
+    /// Fall back only when the primary's key is rejected, for the same
+    /// provider with the key a rotation replaced (see
+    /// [`KeyStore::keys`](crate::keys::KeyStore::keys))
+    pub fn on_auth_failure(primary: P, secondary: S) -> Result<Self> {
+        Ok(Self {
+            fails_over: is_auth_failure,
+            ..Self::new(primary, secondary)?
+        })
+    }
+}
+
+/// Whether an error means the provider is unavailable rather than the
+/// request being wrong
+fn is_outage(error: &ContragError) -> bool {
+    match error {
+        ContragError::EmbedderError(failure) => failure.provider.as_ref().is_some_and(ProviderError::is_retryable),
+        ContragError::HttpOutcallError(_)
+        | ContragError::SerializationError(_)
+        | ContragError::InvalidEmbedding { .. } => true,
+        _ => false,
+    }
+}
+
+/// Whether the provider rejected the request's API key
+pub(crate) fn is_auth_failure(error: &ContragError) -> bool {
+    error
+        .provider_error()
+        .is_some_and(|e| e.kind == ProviderErrorKind::Authentication)
+}
+
+#[async_trait::async_trait]
+impl<P: Embedder, S: Embedder> Embedder for FallbackEmbedder<P, S> {
+    fn name(&self) -> &str {
+        self.primary.name()
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        match self.primary.embed(texts.clone()).await {
+            Err(e) if (self.fails_over)(&e) => self.secondary.embed(texts).await,
+            result => result,
+        }
+    }
+
+    async fn embed_queries(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        match self.primary.embed_queries(texts.clone()).await {
+            Err(e) if (self.fails_over)(&e) => self.secondary.embed_queries(texts).await,
+            result => result,
+        }
+    }
+
+    fn dimensions(&self) -> usize {
+        self.primary.dimensions()
+    }
+
+    fn embeds_in_context(&self) -> bool {
+        self.primary.embeds_in_context() || self.secondary.embeds_in_context()
+    }
+
+    /// Reports the primary, or the secondary if the primary is unreachable
+    async fn test_connection(&self) -> Result<ConnectionTestResult> {
+        let primary = self.primary.test_connection().await?;
+        if primary.connected {
+            return Ok(primary);
+        }
+
+        let mut secondary = self.secondary.test_connection().await?;
+        secondary.details = Some(format!(
+            "fallback for {} ({}){}",
+            primary.plugin,
+            primary.error.unwrap_or_default(),
+            secondary.details.map(|d| format!(", {}", d)).unwrap_or_default()
+        ));
+        Ok(secondary)
+    }
+
+    async fn generate_with_prompt(&self, text: String, system_prompt: String) -> Result<String> {
+        match self.primary.generate_with_prompt(text.clone(), system_prompt.clone()).await {
+            Err(e) if (self.fails_over)(&e) => self.secondary.generate_with_prompt(text, system_prompt).await,
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedders::mock::MockEmbedder;
+
+    fn stub(name: &'static str, dimensions: usize, error: Option<ProviderErrorKind>) -> MockEmbedder {
+        let embedder = MockEmbedder::new(dimensions).named(name);
+        match error {
+            Some(kind) => embedder.failing_with(kind),
+            None => embedder,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_on_outage_only() {
+        assert!(FallbackEmbedder::new(stub("a", 2, None), stub("b", 3, None)).is_err());
+
+        let down = FallbackEmbedder::new(stub("a", 2, Some(ProviderErrorKind::ServerError)), stub("b", 2, None)).unwrap();
+        assert_eq!(down.embed(vec!["x".to_string()]).await.unwrap(), vec![vec![1.0, 1.0]]);
+
+        let rejected =
+            FallbackEmbedder::new(stub("a", 2, Some(ProviderErrorKind::InvalidRequest)), stub("b", 2, None)).unwrap();
+        assert!(rejected.embed(vec!["x".to_string()]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_on_auth_failure_only() {
+        let revoked =
+            FallbackEmbedder::on_auth_failure(stub("a", 2, Some(ProviderErrorKind::Authentication)), stub("b", 2, None))
+                .unwrap();
+        assert_eq!(revoked.embed(vec!["x".to_string()]).await.unwrap(), vec![vec![1.0, 1.0]]);
+
+        let down =
+            FallbackEmbedder::on_auth_failure(stub("a", 2, Some(ProviderErrorKind::ServerError)), stub("b", 2, None))
+                .unwrap();
+        assert!(down.embed(vec!["x".to_string()]).await.is_err());
+    }
+}
@@ -1,13 +1,28 @@
 use serde::{Deserialize, Serialize};
-use crate::embedders::{Embedder, http_client::HttpClient};
+use crate::config::{EmbedderConfigDef, OutcallConfig};
+use crate::embedders::{Embedder, http_client::{HttpClient, RequestClass}};
+use crate::embedders::provider_error::{error_in_success_body, parse_gemini_error};
+use crate::embedders::validation::validate_embeddings;
 use crate::error::{ContragError, Result};
 use crate::types::ConnectionTestResult;
 
+/// Task type of documents embedded by [`Embedder::embed`]
+const DOCUMENT_TASK_TYPE: &str = "RETRIEVAL_DOCUMENT";
+
+/// Task type of queries embedded by [`Embedder::embed_queries`]
+const QUERY_TASK_TYPE: &str = "RETRIEVAL_QUERY";
+
 /// Google Gemini embedder using HTTP outcalls
+///
+/// Documents are embedded with `taskType: RETRIEVAL_DOCUMENT` and queries,
+/// through [`Embedder::embed_queries`], with `RETRIEVAL_QUERY`, unless a
+/// task type is set for both.
 pub struct GeminiEmbedder {
     api_key: String,
     model: String,
     dimensions: usize,
+    target_dimensions: Option<usize>,
+    task_type: Option<String>,
     api_endpoint: String,
     http_client: HttpClient,
 }
@@ -25,17 +40,52 @@ impl GeminiEmbedder {
             api_key,
             model,
             dimensions,
+            target_dimensions: None,
+            task_type: None,
             api_endpoint: "https://generativelanguage.googleapis.com/v1beta/models".to_string(),
             http_client: HttpClient::new(),
         }
     }
 
+    /// Build from an embedder config with `provider = "gemini"`
+    pub fn from_config(config: &EmbedderConfigDef, outcalls: &OutcallConfig, api_key: String) -> Self {
+        let mut embedder = Self::new(api_key, config.model.clone()).with_http_client(HttpClient::from_config(config, outcalls));
+        if let Some(endpoint) = &config.api_endpoint {
+            embedder = embedder.with_endpoint(endpoint.clone());
+        }
+        if let Some(dimensions) = config.target_dimensions {
+            embedder = embedder.with_target_dimensions(dimensions);
+        }
+        embedder
+    }
+
     /// Create with custom API endpoint
     pub fn with_endpoint(mut self, endpoint: String) -> Self {
         self.api_endpoint = endpoint;
         self
     }
 
+    /// Request embeddings shortened to `dimensions` (`outputDimensionality`,
+    /// supported from `text-embedding-004`)
+    pub fn with_target_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = dimensions;
+        self.target_dimensions = Some(dimensions);
+        self
+    }
+
+    /// Use `task_type` (e.g. "SEMANTIC_SIMILARITY", "CLASSIFICATION") for
+    /// documents and queries alike
+    pub fn with_task_type(mut self, task_type: String) -> Self {
+        self.task_type = Some(task_type);
+        self
+    }
+
+    /// Use a custom HTTP client (e.g. with compression enabled)
+    pub fn with_http_client(mut self, http_client: HttpClient) -> Self {
+        self.http_client = http_client;
+        self
+    }
+
     fn get_embed_url(&self) -> String {
         format!(
             "{}/{}:embedContent?key={}",
@@ -58,45 +108,11 @@ impl Embedder for GeminiEmbedder {
     }
 
     async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
-        if texts.is_empty() {
-            return Ok(vec![]);
-        }
-
-        // Use batch embed for multiple texts
-        if texts.len() > 1 {
-            return self.batch_embed(texts).await;
-        }
-
-        // Single text embedding
-        let request = GeminiEmbedRequest {
-            content: GeminiContent {
-                parts: vec![GeminiPart {
-                    text: texts[0].clone(),
-                }],
-            },
-        };
-
-        let body = serde_json::to_vec(&request)
-            .map_err(|e| ContragError::SerializationError(e.to_string()))?;
-
-        let headers = vec![("Content-Type".to_string(), "application/json".to_string())];
-
-        let response = self
-            .http_client
-            .post(self.get_embed_url(), headers, body)
-            .await?;
-
-        if response.status != 200 {
-            let error_text = response.text().unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(ContragError::EmbedderError(format!(
-                "Gemini API returned status {}: {}",
-                response.status, error_text
-            )));
-        }
-
-        let embed_response: GeminiEmbedResponse = response.json()?;
+        self.embed_as(texts, DOCUMENT_TASK_TYPE).await
+    }
 
-        Ok(vec![embed_response.embedding.values])
+    async fn embed_queries(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.embed_as(texts, QUERY_TASK_TYPE).await
     }
 
     fn dimensions(&self) -> usize {
@@ -157,14 +173,10 @@ impl Embedder for GeminiEmbedder {
             self.api_endpoint, self.api_key
         );
 
-        let response = self.http_client.post(url, headers, body).await?;
+        let response = self.http_client.post_as(RequestClass::Generation, url, headers, body).await?;
 
-        if response.status != 200 {
-            let error_text = response.text().unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(ContragError::EmbedderError(format!(
-                "Gemini API returned status {}: {}",
-                response.status, error_text
-            )));
+        if response.status != 200 || error_in_success_body(&response) {
+            return Err(parse_gemini_error(&response));
         }
 
         let generate_response: GeminiGenerateResponse = response.json()?;
@@ -179,14 +191,67 @@ impl Embedder for GeminiEmbedder {
 }
 
 impl GeminiEmbedder {
-    async fn batch_embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+    /// Embed texts with `default_task_type`, unless a task type is set
+    async fn embed_as(&self, texts: Vec<String>, default_task_type: &str) -> Result<Vec<Vec<f32>>> {
+        let task_type = self.resolve_task_type(default_task_type);
+        if texts.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // Use batch embed for multiple texts
+        if texts.len() > 1 {
+            let count = texts.len();
+            let mut embeddings = Vec::with_capacity(count);
+            for batch in self.http_client.split_batch(&texts, self.dimensions) {
+                embeddings.extend(self.batch_embed(batch.to_vec(), task_type).await?);
+            }
+            validate_embeddings("gemini", &embeddings, count, self.dimensions)?;
+            return Ok(embeddings);
+        }
+
+        // Single text embedding
+        let request = self.embed_request(texts[0].clone(), task_type);
+
+        let body = serde_json::to_vec(&request)
+            .map_err(|e| ContragError::SerializationError(e.to_string()))?;
+
+        let headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+
+        let response = self
+            .http_client
+            .post_as(RequestClass::Embedding, self.get_embed_url(), headers, body)
+            .await?;
+
+        if response.status != 200 || error_in_success_body(&response) {
+            return Err(parse_gemini_error(&response));
+        }
+
+        let embed_response: GeminiEmbedResponse = response.json()?;
+
+        let embeddings = vec![embed_response.embedding.values];
+        validate_embeddings("gemini", &embeddings, 1, self.dimensions)?;
+        Ok(embeddings)
+    }
+
+    /// The task type set with [`Self::with_task_type`], or `default_task_type`
+    fn resolve_task_type<'a>(&'a self, default_task_type: &'a str) -> &'a str {
+        self.task_type.as_deref().unwrap_or(default_task_type)
+    }
+
+    fn embed_request(&self, text: String, task_type: &str) -> GeminiEmbedRequest {
+        GeminiEmbedRequest {
+            content: GeminiContent {
+                parts: vec![GeminiPart { text }],
+            },
+            task_type: task_type.to_string(),
+            output_dimensionality: self.target_dimensions,
+        }
+    }
+
+    async fn batch_embed(&self, texts: Vec<String>, task_type: &str) -> Result<Vec<Vec<f32>>> {
         let requests: Vec<GeminiEmbedRequest> = texts
             .into_iter()
-            .map(|text| GeminiEmbedRequest {
-                content: GeminiContent {
-                    parts: vec![GeminiPart { text }],
-                },
-            })
+            .map(|text| self.embed_request(text, task_type))
             .collect();
 
         let batch_request = GeminiBatchEmbedRequest { requests };
@@ -198,15 +263,11 @@ impl GeminiEmbedder {
 
         let response = self
             .http_client
-            .post(self.get_batch_embed_url(), headers, body)
+            .post_as(RequestClass::Embedding, self.get_batch_embed_url(), headers, body)
             .await?;
 
-        if response.status != 200 {
-            let error_text = response.text().unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(ContragError::EmbedderError(format!(
-                "Gemini API returned status {}: {}",
-                response.status, error_text
-            )));
+        if response.status != 200 || error_in_success_body(&response) {
+            return Err(parse_gemini_error(&response));
         }
 
         let batch_response: GeminiBatchEmbedResponse = response.json()?;
@@ -224,6 +285,10 @@ impl GeminiEmbedder {
 #[derive(Serialize)]
 struct GeminiEmbedRequest {
     content: GeminiContent,
+    #[serde(rename = "taskType")]
+    task_type: String,
+    #[serde(rename = "outputDimensionality", skip_serializing_if = "Option::is_none")]
+    output_dimensionality: Option<usize>,
 }
 
 #[derive(Serialize)]
@@ -278,3 +343,35 @@ struct GeminiGenerateResponse {
 struct GeminiCandidate {
     content: GeminiContent,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embed_request_task_type() {
+        let embedder = GeminiEmbedder::new("key".to_string(), "text-embedding-004".to_string());
+        let task_type = embedder.resolve_task_type(QUERY_TASK_TYPE);
+        assert_eq!(
+            serde_json::to_value(embedder.embed_request("refund policy".to_string(), task_type)).unwrap(),
+            serde_json::json!({
+                "content": {"parts": [{"text": "refund policy"}]},
+                "taskType": "RETRIEVAL_QUERY",
+            })
+        );
+
+        // An explicit task type overrides both retrieval defaults
+        let embedder = embedder.with_target_dimensions(256).with_task_type("SEMANTIC_SIMILARITY".to_string());
+        for default_task_type in [DOCUMENT_TASK_TYPE, QUERY_TASK_TYPE] {
+            let task_type = embedder.resolve_task_type(default_task_type);
+            assert_eq!(
+                serde_json::to_value(embedder.embed_request("refund policy".to_string(), task_type)).unwrap(),
+                serde_json::json!({
+                    "content": {"parts": [{"text": "refund policy"}]},
+                    "taskType": "SEMANTIC_SIMILARITY",
+                    "outputDimensionality": 256,
+                })
+            );
+        }
+    }
+}
@@ -1,18 +1,370 @@
+use std::cell::Cell;
+use std::io::{Read, Write};
+use candid::CandidType;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use crate::concurrency;
+use crate::config::{EmbedderConfigDef, OutcallConfig};
 use crate::error::{ContragError, Result};
+use crate::utils::estimate_tokens;
+
+/// How many replicas make an outcall
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplicationMode {
+    /// Every replica of the subnet makes the request and the responses go
+    /// through consensus
+    #[default]
+    Replicated,
+    /// A single replica makes the request
+    ///
+    /// Costs a fraction of a replicated outcall, but the response is trusted
+    /// as returned by that replica. Only available where the subnet supports
+    /// non-replicated outcalls.
+    NonReplicated,
+}
+
+/// Kind of outcall, used to pick its replication mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestClass {
+    Embedding,
+    Generation,
+    /// Webhooks and anything else
+    Other,
+}
+
+thread_local! {
+    static CYCLES_SPENT: Cell<u128> = const { Cell::new(0) };
+}
+
+/// Cycles spent on HTTP outcalls since the canister started (attached minus
+/// refunded)
+pub fn outcall_cycles_spent() -> u128 {
+    CYCLES_SPENT.with(|c| c.get())
+}
+
+#[cfg_attr(not(target_family = "wasm"), allow(dead_code))]
+fn record_cycles(cycles: u128) {
+    CYCLES_SPENT.with(|c| c.set(c.get().saturating_add(cycles)));
+}
+
+/// Name of the query method the host canister exports for
+/// [`transform`]
+pub const TRANSFORM_METHOD: &str = "contrag_transform";
+
+/// Response headers kept by [`HttpOutcallResponse::normalized`]; the rest
+/// (dates, request IDs, rate-limit counters) differ between replicas
+const KEPT_HEADERS: [&str; 1] = ["retry-after"];
+
+/// Top-level JSON fields of provider responses that differ between replicas
+const VOLATILE_FIELDS: [&str; 5] = ["id", "created", "request_id", "meta", "system_fingerprint"];
+
+/// Largest outcall request and response the IC allows
+pub const MAX_OUTCALL_BYTES: u64 = 2_000_000;
+
+/// Bytes of a request or response reserved for headers and the JSON
+/// envelope when splitting a batch
+const ENVELOPE_BYTES: u64 = 4_096;
+
+/// Request bytes per text besides the text itself (quotes, escapes and the
+/// provider's per-input wrapping)
+const REQUEST_BYTES_PER_TEXT: u64 = 64;
+
+/// Response bytes per embedding value
+///
+/// Providers pretty-print their JSON, so a value takes its digits plus
+/// indentation, comma and newline: `        -0.012345678,\n` is 22 bytes.
+/// Budgeted with headroom for deeper indentation and longer exponents.
+const RESPONSE_BYTES_PER_DIMENSION: u64 = 32;
+
+/// Response bytes per embedding besides its values (index, object type)
+const RESPONSE_BYTES_PER_EMBEDDING: u64 = 64;
+
+/// Limits one embedding request must stay within, see [`batch_end`]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BatchLimits {
+    pub max_texts: usize,
+    /// Estimated tokens; `None` means unlimited
+    pub max_tokens: Option<usize>,
+    pub max_request_bytes: u64,
+    pub max_response_bytes: u64,
+    /// Values per embedding in the response
+    pub dimensions: usize,
+}
+
+impl Default for BatchLimits {
+    fn default() -> Self {
+        Self {
+            max_texts: usize::MAX,
+            max_tokens: None,
+            max_request_bytes: u64::MAX,
+            max_response_bytes: u64::MAX,
+            dimensions: 0,
+        }
+    }
+}
+
+/// End of the batch of `texts` starting at `start` that stays within
+/// `limits`, but always at least one text
+///
+/// Request sizes are estimated from the text lengths, response sizes from
+/// the JSON encoding of the embeddings. Both the outcall client and
+/// [`AdaptiveBatcher`](super::batching::AdaptiveBatcher) cut batches here.
+pub(crate) fn batch_end(texts: &[String], start: usize, limits: &BatchLimits) -> usize {
+    let per_embedding = limits.dimensions as u64 * RESPONSE_BYTES_PER_DIMENSION + RESPONSE_BYTES_PER_EMBEDDING;
+    let max_tokens = limits.max_tokens.unwrap_or(usize::MAX);
+    let (mut tokens, mut request, mut response) = (0usize, 0u64, 0u64);
+
+    for (i, text) in texts[start..].iter().enumerate().take(limits.max_texts.max(1)) {
+        tokens = tokens.saturating_add(estimate_tokens(text));
+        request = request.saturating_add(text.len() as u64 + REQUEST_BYTES_PER_TEXT);
+        response = response.saturating_add(per_embedding);
+        if i > 0 && (tokens > max_tokens || request > limits.max_request_bytes || response > limits.max_response_bytes) {
+            return start + i;
+        }
+    }
+    start.saturating_add(limits.max_texts.max(1)).min(texts.len())
+}
 
 /// HTTP client for making outcalls from ICP canisters
 /// 
 /// This wraps the ICP HTTP outcall functionality for easier use.
+///
+/// Outcalls are sent without a transform by default. Replicas then see
+/// different dates and request IDs in provider responses, so replicated
+/// outcalls may fail consensus; canisters that export a transform query
+/// opt in with [`with_transform`](Self::with_transform) or
+/// `OutcallConfig::transform_method`, forwarding it to [`transform`]:
+///
+/// ```ignore
+/// #[query]
+/// fn contrag_transform(args: TransformArgs) -> HttpResponse {
+///     contrag_core::embedders::http_client::transform(args)
+/// }
+/// ```
 pub struct HttpClient {
     max_response_bytes: u64,
+    max_request_bytes: u64,
+    compression: bool,
+    transform: Option<String>,
+    idempotency_header: Option<String>,
+    subnet_nodes: u64,
+    max_cycles: Option<u128>,
+    embedding_replication: ReplicationMode,
+    generation_replication: ReplicationMode,
 }
 
 impl HttpClient {
     pub fn new() -> Self {
         Self {
-            max_response_bytes: 2_000_000, // 2MB default
+            max_response_bytes: MAX_OUTCALL_BYTES,
+            max_request_bytes: MAX_OUTCALL_BYTES,
+            compression: false,
+            transform: None,
+            idempotency_header: None,
+            subnet_nodes: 13,
+            max_cycles: None,
+            embedding_replication: ReplicationMode::Replicated,
+            generation_replication: ReplicationMode::Replicated,
+        }
+    }
+
+    /// Client for an embedder config: its embedding replication mode and
+    /// idempotency header, with the limits of `outcalls`
+    pub fn from_config(config: &EmbedderConfigDef, outcalls: &OutcallConfig) -> Self {
+        let client = Self::new()
+            .with_outcall_config(outcalls)
+            .with_replication(RequestClass::Embedding, config.embedding_replication);
+        match &config.idempotency_header {
+            Some(header) => client.with_idempotency_header(header.clone()),
+            None => client,
+        }
+    }
+
+    /// Send an [`idempotency_key`] of each POST in header `name` (e.g.
+    /// "Idempotency-Key")
+    ///
+    /// The key depends only on the URL and body, so every replica and every
+    /// retry of the same request sends the same key, and providers that
+    /// honor it process and bill the request once.
+    pub fn with_idempotency_header(mut self, name: impl Into<String>) -> Self {
+        self.idempotency_header = Some(name.into());
+        self
+    }
+
+    /// Set the replication mode for a class of requests
+    ///
+    /// Embeddings tolerate a single replica's answer well, since a bad
+    /// vector only degrades ranking and is caught by validation. Other
+    /// requests (webhooks, GETs) are always replicated.
+    pub fn with_replication(mut self, class: RequestClass, mode: ReplicationMode) -> Self {
+        match class {
+            RequestClass::Embedding => self.embedding_replication = mode,
+            RequestClass::Generation => self.generation_replication = mode,
+            RequestClass::Other => {}
+        }
+        self
+    }
+
+    /// Replication mode used for a class of requests
+    pub fn replication(&self, class: RequestClass) -> ReplicationMode {
+        match class {
+            RequestClass::Embedding => self.embedding_replication,
+            RequestClass::Generation => self.generation_replication,
+            RequestClass::Other => ReplicationMode::Replicated,
+        }
+    }
+
+    /// Transform responses with the host's query method `method`, usually
+    /// [`TRANSFORM_METHOD`]
+    pub fn with_transform(mut self, method: impl Into<String>) -> Self {
+        self.transform = Some(method.into());
+        self
+    }
+
+    /// Send outcalls without a transform (the default); replicated
+    /// outcalls may then fail consensus
+    pub fn without_transform(mut self) -> Self {
+        self.transform = None;
+        self
+    }
+
+    #[cfg_attr(not(target_family = "wasm"), allow(dead_code))]
+    fn transform_context(&self) -> Option<ic_cdk::api::management_canister::http_request::TransformContext> {
+        self.transform.as_ref().map(|method| {
+            ic_cdk::api::management_canister::http_request::TransformContext::from_name(method.clone(), vec![])
+        })
+    }
+
+    /// Apply the subnet size, size limits, cycles ceiling and transform of
+    /// an outcall config
+    pub fn with_outcall_config(mut self, config: &OutcallConfig) -> Self {
+        self.transform = config.transform_method.clone();
+        self.subnet_nodes = config.subnet_nodes;
+        self.max_response_bytes = config.max_response_bytes;
+        self.max_request_bytes = config.max_request_bytes;
+        self.max_cycles = config.max_cycles_per_call.map(u128::from);
+        self
+    }
+
+    /// Accept responses of up to `bytes` (capped at [`MAX_OUTCALL_BYTES`])
+    ///
+    /// Outcalls pay for the whole limit, so a lower one makes small
+    /// requests cheaper and makes embedders split batches sooner.
+    pub fn with_max_response_bytes(mut self, bytes: u64) -> Self {
+        self.max_response_bytes = bytes.min(MAX_OUTCALL_BYTES);
+        self
+    }
+
+    /// Send requests (URL, headers and body) of up to `bytes` (capped at
+    /// [`MAX_OUTCALL_BYTES`]); larger ones fail without being sent
+    pub fn with_max_request_bytes(mut self, bytes: u64) -> Self {
+        self.max_request_bytes = bytes.min(MAX_OUTCALL_BYTES);
+        self
+    }
+
+    pub fn max_response_bytes(&self) -> u64 {
+        self.max_response_bytes
+    }
+
+    pub fn max_request_bytes(&self) -> u64 {
+        self.max_request_bytes
+    }
+
+    /// Split `texts` into consecutive batches whose embedding requests and
+    /// responses fit the size limits, given `dimensions` values per
+    /// embedding; see [`batch_end`]
+    ///
+    /// A text too large on its own gets a batch of its own, whose request
+    /// then fails the limit check.
+    pub fn split_batch<'a>(&self, texts: &'a [String], dimensions: usize) -> Vec<&'a [String]> {
+        let limits = BatchLimits {
+            max_request_bytes: self.max_request_bytes.saturating_sub(ENVELOPE_BYTES),
+            max_response_bytes: self.max_response_bytes.saturating_sub(ENVELOPE_BYTES),
+            dimensions,
+            ..BatchLimits::default()
+        };
+
+        let mut batches = vec![];
+        let mut start = 0;
+        while start < texts.len() {
+            let end = batch_end(texts, start, &limits);
+            batches.push(&texts[start..end]);
+            start = end;
+        }
+        batches
+    }
+
+    /// Bytes of an outcall request counted for pricing: URL, headers, body
+    /// and the transform's method name and context
+    fn request_bytes(&self, url: &str, headers: &[(String, String)], body: &[u8]) -> u64 {
+        let header_bytes: usize = headers.iter().map(|(name, value)| name.len() + value.len()).sum();
+        // The transform context is always empty
+        let transform_bytes = self.transform.as_ref().map_or(0, String::len);
+        (url.len() + header_bytes + body.len() + transform_bytes) as u64
+    }
+
+    /// Fail if a request is over the request size limit
+    fn check_request_size(&self, request_bytes: u64) -> Result<()> {
+        if request_bytes > self.max_request_bytes {
+            return Err(ContragError::HttpOutcallError(format!(
+                "Request of {} bytes is over the size limit of {} bytes",
+                request_bytes, self.max_request_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    /// Cycles to attach to an outcall sending `request_bytes`, failing when
+    /// they exceed the configured ceiling
+    fn cycles_for(&self, request_bytes: u64) -> Result<u128> {
+        let cycles = estimate_outcall_cycles(request_bytes, self.max_response_bytes, self.subnet_nodes);
+        match self.max_cycles {
+            Some(max) if cycles > max => Err(ContragError::HttpOutcallError(format!(
+                "Outcall needs about {} cycles, above the ceiling of {}",
+                cycles, max
+            ))),
+            _ => Ok(cycles),
+        }
+    }
+
+    /// Gzip request bodies and accept gzip-encoded responses
+    ///
+    /// Only enable this for providers that accept `Content-Encoding: gzip`
+    /// requests. Compressed responses are decoded transparently.
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        self.compression = enabled;
+        self
+    }
+
+    /// Add the idempotency header, if enabled
+    fn with_idempotency_key(
+        &self,
+        url: &str,
+        mut headers: Vec<(String, String)>,
+        body: &[u8],
+    ) -> Vec<(String, String)> {
+        if let Some(name) = &self.idempotency_header {
+            headers.push((name.clone(), idempotency_key(url, body)));
+        }
+        headers
+    }
+
+    /// Add compression headers and encode the body if compression is enabled
+    fn prepare_body(
+        &self,
+        mut headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    ) -> Result<(Vec<(String, String)>, Vec<u8>)> {
+        if !self.compression {
+            return Ok((headers, body));
         }
+
+        headers.push(("Content-Encoding".to_string(), "gzip".to_string()));
+        headers.push(("Accept-Encoding".to_string(), "gzip".to_string()));
+        Ok((headers, gzip(&body)?))
     }
 
     /// Make an HTTP POST request
@@ -25,11 +377,32 @@ impl HttpClient {
         headers: Vec<(String, String)>,
         body: Vec<u8>,
     ) -> Result<HttpOutcallResponse> {
+        self.post_as(RequestClass::Other, url, headers, body).await
+    }
+
+    /// Make an HTTP POST request with the replication mode of `class`
+    pub async fn post_as(
+        &self,
+        class: RequestClass,
+        url: String,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    ) -> Result<HttpOutcallResponse> {
+        let _permit = concurrency::acquire()?;
+
+        #[cfg(feature = "chaos")]
+        crate::chaos::outcall(&url)?;
+
+        let headers = self.with_idempotency_key(&url, headers, &body);
+        let (headers, body) = self.prepare_body(headers, body)?;
+        let bytes = self.request_bytes(&url, &headers, &body);
+        self.check_request_size(bytes)?;
+        let cycles = self.cycles_for(bytes)?;
+
         #[cfg(target_family = "wasm")]
         {
             use ic_cdk::api::management_canister::http_request::{
-                http_request, CanisterHttpRequestArgument, HttpMethod, HttpHeader, HttpResponse,
-                TransformContext,
+                CanisterHttpRequestArgument, HttpMethod, HttpHeader,
             };
 
             let request_headers: Vec<HttpHeader> = headers
@@ -42,14 +415,15 @@ impl HttpClient {
                 method: HttpMethod::POST,
                 body: Some(body),
                 max_response_bytes: Some(self.max_response_bytes),
-                transform: None,
+                transform: self.transform_context(),
                 headers: request_headers,
             };
 
-            let cycles = 1_000_000_000u128; // 1B cycles
+            let result = send(request, cycles, self.replication(class)).await;
+            record_cycles(cycles.saturating_sub(ic_cdk::api::call::msg_cycles_refunded128()));
 
-            match http_request(request, cycles).await {
-                Ok((response,)) => Ok(HttpOutcallResponse {
+            match result {
+                Ok((response,)) => HttpOutcallResponse {
                     status: response.status.0.into(),
                     headers: response
                         .headers
@@ -57,7 +431,8 @@ impl HttpClient {
                         .map(|h| (h.name, h.value))
                         .collect(),
                     body: response.body,
-                }),
+                }
+                .decoded(),
                 Err((code, msg)) => Err(ContragError::HttpOutcallError(format!(
                     "HTTP outcall failed: {:?} - {}",
                     code, msg
@@ -67,6 +442,7 @@ impl HttpClient {
 
         #[cfg(not(target_family = "wasm"))]
         {
+            let _ = (class, cycles);
             Err(ContragError::HttpOutcallError(
                 "HTTP outcalls only work in WASM environment".to_string(),
             ))
@@ -79,11 +455,23 @@ impl HttpClient {
         url: String,
         headers: Vec<(String, String)>,
     ) -> Result<HttpOutcallResponse> {
+        let _permit = concurrency::acquire()?;
+
+        #[cfg(feature = "chaos")]
+        crate::chaos::outcall(&url)?;
+
+        let mut headers = headers;
+        if self.compression {
+            headers.push(("Accept-Encoding".to_string(), "gzip".to_string()));
+        }
+        let bytes = self.request_bytes(&url, &headers, &[]);
+        self.check_request_size(bytes)?;
+        let cycles = self.cycles_for(bytes)?;
+
         #[cfg(target_family = "wasm")]
         {
             use ic_cdk::api::management_canister::http_request::{
                 http_request, CanisterHttpRequestArgument, HttpMethod, HttpHeader,
-                TransformContext,
             };
 
             let request_headers: Vec<HttpHeader> = headers
@@ -96,14 +484,15 @@ impl HttpClient {
                 method: HttpMethod::GET,
                 body: None,
                 max_response_bytes: Some(self.max_response_bytes),
-                transform: None,
+                transform: self.transform_context(),
                 headers: request_headers,
             };
 
-            let cycles = 500_000_000u128; // 500M cycles
+            let result = http_request(request, cycles).await;
+            record_cycles(cycles.saturating_sub(ic_cdk::api::call::msg_cycles_refunded128()));
 
-            match http_request(request, cycles).await {
-                Ok((response,)) => Ok(HttpOutcallResponse {
+            match result {
+                Ok((response,)) => HttpOutcallResponse {
                     status: response.status.0.into(),
                     headers: response
                         .headers
@@ -111,7 +500,8 @@ impl HttpClient {
                         .map(|h| (h.name, h.value))
                         .collect(),
                     body: response.body,
-                }),
+                }
+                .decoded(),
                 Err((code, msg)) => Err(ContragError::HttpOutcallError(format!(
                     "HTTP outcall failed: {:?} - {}",
                     code, msg
@@ -121,6 +511,7 @@ impl HttpClient {
 
         #[cfg(not(target_family = "wasm"))]
         {
+            let _ = cycles;
             Err(ContragError::HttpOutcallError(
                 "HTTP outcalls only work in WASM environment".to_string(),
             ))
@@ -128,6 +519,54 @@ impl HttpClient {
     }
 }
 
+/// Send an outcall through the management canister
+///
+/// ic-cdk's `http_request` has no replication flag yet, so non-replicated
+/// requests are sent with their own argument type carrying `is_replicated`.
+#[cfg(target_family = "wasm")]
+async fn send(
+    request: ic_cdk::api::management_canister::http_request::CanisterHttpRequestArgument,
+    cycles: u128,
+    mode: ReplicationMode,
+) -> ic_cdk::api::call::CallResult<(ic_cdk::api::management_canister::http_request::HttpResponse,)> {
+    use ic_cdk::api::management_canister::http_request::{
+        http_request, HttpHeader, HttpMethod, HttpResponse, TransformContext,
+    };
+
+    #[derive(CandidType)]
+    struct NonReplicatedArgument {
+        url: String,
+        max_response_bytes: Option<u64>,
+        method: HttpMethod,
+        headers: Vec<HttpHeader>,
+        body: Option<Vec<u8>>,
+        transform: Option<TransformContext>,
+        is_replicated: Option<bool>,
+    }
+
+    match mode {
+        ReplicationMode::Replicated => http_request(request, cycles).await,
+        ReplicationMode::NonReplicated => {
+            let argument = NonReplicatedArgument {
+                url: request.url,
+                max_response_bytes: request.max_response_bytes,
+                method: request.method,
+                headers: request.headers,
+                body: request.body,
+                transform: request.transform,
+                is_replicated: Some(false),
+            };
+            ic_cdk::api::call::call_with_payment128::<_, (HttpResponse,)>(
+                candid::Principal::management_canister(),
+                "http_request",
+                (argument,),
+                cycles,
+            )
+            .await
+        }
+    }
+}
+
 impl Default for HttpClient {
     fn default() -> Self {
         Self::new()
@@ -143,12 +582,62 @@ pub struct HttpOutcallResponse {
 
 impl HttpOutcallResponse {
     /// Parse body as JSON
+    ///
+    /// On failure the error includes the start of the body, so unexpected
+    /// provider schemas can be diagnosed from logs.
     pub fn json<T: for<'de> Deserialize<'de>>(&self) -> Result<T> {
         serde_json::from_slice(&self.body).map_err(|e| {
-            ContragError::SerializationError(format!("Failed to parse JSON response: {}", e))
+            let preview: String = String::from_utf8_lossy(&self.body).chars().take(200).collect();
+            ContragError::SerializationError(format!(
+                "Failed to parse JSON response: {} (body: {})",
+                e, preview
+            ))
         })
     }
 
+    /// Decode a gzip-encoded body and drop the `Content-Encoding` header
+    pub fn decoded(mut self) -> Result<Self> {
+        let gzipped = self.headers.iter().any(|(name, value)| {
+            name.eq_ignore_ascii_case("content-encoding") && value.trim().eq_ignore_ascii_case("gzip")
+        });
+
+        if gzipped {
+            self.body = gunzip(&self.body)?;
+            self.headers
+                .retain(|(name, _)| !name.eq_ignore_ascii_case("content-encoding"));
+        }
+
+        Ok(self)
+    }
+
+    /// Drop what differs between replicas: headers other than
+    /// `Retry-After` and volatile top-level JSON fields such as response IDs
+    ///
+    /// Gzip-encoded bodies are decoded first, since the encoding header is
+    /// dropped too. Bodies that aren't JSON objects are kept as they are.
+    pub fn normalized(self) -> Self {
+        let mut response = match self.clone().decoded() {
+            Ok(decoded) => decoded,
+            Err(_) => self,
+        };
+
+        response
+            .headers
+            .retain(|(name, _)| KEPT_HEADERS.iter().any(|kept| name.eq_ignore_ascii_case(kept)));
+
+        if let Ok(Value::Object(mut body)) = serde_json::from_slice::<Value>(&response.body) {
+            let before = body.len();
+            body.retain(|field, _| !VOLATILE_FIELDS.contains(&field.as_str()));
+            if body.len() != before {
+                if let Ok(bytes) = serde_json::to_vec(&body) {
+                    response.body = bytes;
+                }
+            }
+        }
+
+        response
+    }
+
     /// Get body as string
     pub fn text(&self) -> Result<String> {
         String::from_utf8(self.body.clone()).map_err(|e| {
@@ -156,3 +645,188 @@ impl HttpOutcallResponse {
         })
     }
 }
+
+/// Cycles an outcall costs on a subnet of `subnet_nodes` nodes
+///
+/// Follows the IC's pricing: a base fee growing with the subnet size, plus
+/// per-byte fees on the request and on `max_response_bytes`, which is paid
+/// for whether or not the response uses it.
+pub fn estimate_outcall_cycles(request_bytes: u64, max_response_bytes: u64, subnet_nodes: u64) -> u128 {
+    let n = subnet_nodes as u128;
+    (3_000_000 + 60_000 * n) * n + 400 * n * request_bytes as u128 + 800 * n * max_response_bytes as u128
+}
+
+
+/// Deterministic idempotency key of a POST: hex SHA-256 of URL and body
+pub fn idempotency_key(url: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(url.as_bytes());
+    hasher.update([0]);
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+/// Transform function for outcall responses, to be exported by the host
+/// canister as the [`TRANSFORM_METHOD`] query
+///
+/// Applies [`HttpOutcallResponse::normalized`] and keeps the status.
+pub fn transform(
+    args: ic_cdk::api::management_canister::http_request::TransformArgs,
+) -> ic_cdk::api::management_canister::http_request::HttpResponse {
+    use ic_cdk::api::management_canister::http_request::{HttpHeader, HttpResponse};
+
+    let response = args.response;
+    let normalized = HttpOutcallResponse {
+        status: 0,
+        headers: response.headers.into_iter().map(|h| (h.name, h.value)).collect(),
+        body: response.body,
+    }
+    .normalized();
+
+    HttpResponse {
+        status: response.status,
+        headers: normalized
+            .headers
+            .into_iter()
+            .map(|(name, value)| HttpHeader { name, value })
+            .collect(),
+        body: normalized.body,
+    }
+}
+
+/// Gzip-compress a request body
+pub fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(data)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| ContragError::SerializationError(format!("Failed to gzip body: {}", e)))
+}
+
+/// Decompress a gzip-encoded response body
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    GzDecoder::new(data)
+        .read_to_end(&mut decoded)
+        .map_err(|e| ContragError::SerializationError(format!("Failed to gunzip body: {}", e)))?;
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gzip_response_is_decoded() {
+        let body = br#"{"data": []}"#.repeat(10);
+        let response = HttpOutcallResponse {
+            status: 200,
+            headers: vec![("Content-Encoding".to_string(), "gzip".to_string())],
+            body: gzip(&body).unwrap(),
+        }
+        .decoded()
+        .unwrap();
+
+        assert_eq!(response.body, body);
+        assert!(response.headers.is_empty());
+    }
+
+    #[test]
+    fn test_normalized_response_is_replica_independent() {
+        let response = |id: &str, date: &str| HttpOutcallResponse {
+            status: 200,
+            headers: vec![
+                ("Date".to_string(), date.to_string()),
+                ("x-request-id".to_string(), id.to_string()),
+                ("Retry-After".to_string(), "1".to_string()),
+            ],
+            body: format!(r#"{{"id":"{}","embeddings":{{"float":[[0.5]]}}}}"#, id).into_bytes(),
+        }
+        .normalized();
+
+        let a = response("a1", "Mon, 01 Jan 2024 00:00:00 GMT");
+        let b = response("b2", "Mon, 01 Jan 2024 00:00:01 GMT");
+        assert_eq!((a.headers.clone(), a.body.clone()), (b.headers, b.body));
+        assert_eq!(a.headers, vec![("Retry-After".to_string(), "1".to_string())]);
+        assert_eq!(a.body, br#"{"embeddings":{"float":[[0.5]]}}"#);
+    }
+
+    #[test]
+    fn test_cycles_estimate_and_ceiling() {
+        // Base fee, 1 KB request and 10 KB response on a 13-node subnet
+        assert_eq!(
+            estimate_outcall_cycles(1_000, 10_000, 13),
+            49_140_000 + 5_200_000 + 104_000_000
+        );
+        assert!(estimate_outcall_cycles(0, 0, 34) > estimate_outcall_cycles(0, 0, 13));
+
+        let config = OutcallConfig {
+            max_response_bytes: 10_000,
+            max_cycles_per_call: Some(200_000_000),
+            ..OutcallConfig::default()
+        };
+        let embedder = crate::config::create_default_config().embedder;
+        let client = HttpClient::from_config(&embedder, &config);
+        assert_eq!(client.max_response_bytes(), 10_000);
+        assert!(client.cycles_for(1_000).is_ok());
+        assert!(matches!(client.cycles_for(1_000_000), Err(ContragError::HttpOutcallError(_))));
+
+        // The transform is opt-in and priced as part of the request
+        let headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+        let bytes = |client: HttpClient| client.request_bytes("https://api.openai.com/v1/embeddings", &headers, b"{}");
+        assert_eq!(bytes(HttpClient::new()), 36 + 28 + 2);
+        assert_eq!(
+            bytes(HttpClient::new().with_transform(TRANSFORM_METHOD)),
+            36 + 28 + 2 + TRANSFORM_METHOD.len() as u64
+        );
+        let config = OutcallConfig {
+            transform_method: Some(TRANSFORM_METHOD.to_string()),
+            ..OutcallConfig::default()
+        };
+        assert_eq!(
+            bytes(HttpClient::from_config(&embedder, &config)),
+            36 + 28 + 2 + TRANSFORM_METHOD.len() as u64
+        );
+    }
+
+    #[test]
+    fn test_split_batch_by_size_limits() {
+        let texts: Vec<String> = (0..7).map(|i| format!("text {}", i)).collect();
+        let lens = |batches: Vec<&[String]>| batches.iter().map(|b| b.len()).collect::<Vec<_>>();
+
+        // Room for three 10-dimensional embeddings per response
+        let client = HttpClient::new().with_max_response_bytes(ENVELOPE_BYTES + 3 * 384);
+        assert_eq!(lens(client.split_batch(&texts, 10)), [3, 3, 1]);
+        assert_eq!(lens(HttpClient::new().split_batch(&texts, 1536)), [7]);
+
+        // A text over the request limit still gets a batch, which then fails
+        let long = vec!["x".repeat(150), "y".to_string(), "z".to_string()];
+        let client = HttpClient::new().with_max_request_bytes(ENVELOPE_BYTES + 200);
+        assert_eq!(lens(client.split_batch(&long, 10)), [1, 2]);
+        assert!(client.check_request_size(ENVELOPE_BYTES + 200).is_ok());
+        assert!(matches!(client.check_request_size(ENVELOPE_BYTES + 201), Err(ContragError::HttpOutcallError(_))));
+        assert_eq!(HttpClient::new().with_max_response_bytes(u64::MAX).max_response_bytes(), MAX_OUTCALL_BYTES);
+    }
+
+    #[test]
+    fn test_idempotency_key_header() {
+        let client = HttpClient::new().with_idempotency_header("Idempotency-Key");
+        let headers = |body: &[u8]| client.with_idempotency_key("https://api.openai.com/v1/embeddings", vec![], body);
+
+        assert_eq!(headers(b"{}"), headers(b"{}"));
+        assert_ne!(headers(b"{}"), headers(b"[]"));
+        assert_eq!(headers(b"{}")[0].0, "Idempotency-Key");
+        assert!(HttpClient::new().with_idempotency_key("u", vec![], b"{}").is_empty());
+    }
+
+    #[test]
+    fn test_replication_per_request_class() {
+        let client = HttpClient::new()
+            .with_replication(RequestClass::Embedding, ReplicationMode::NonReplicated)
+            .with_replication(RequestClass::Other, ReplicationMode::NonReplicated);
+
+        assert_eq!(client.replication(RequestClass::Embedding), ReplicationMode::NonReplicated);
+        assert_eq!(client.replication(RequestClass::Generation), ReplicationMode::Replicated);
+        assert_eq!(client.replication(RequestClass::Other), ReplicationMode::Replicated);
+    }
+}
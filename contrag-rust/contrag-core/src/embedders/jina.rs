@@ -0,0 +1,309 @@
+use serde::{Deserialize, Serialize};
+use crate::config::{EmbedderConfigDef, JinaConfig, OutcallConfig};
+use crate::embedders::{Embedder, http_client::{HttpClient, RequestClass}};
+use crate::embedders::provider_error::parse_jina_error;
+use crate::embedders::validation::validate_embeddings;
+use crate::error::{ContragError, Result};
+use crate::types::ConnectionTestResult;
+
+/// Jina embedder (jina-embeddings-v3) using HTTP outcalls
+///
+/// With jina-embeddings-v3, documents are embedded with the
+/// `retrieval.passage` task and queries, through [`Embedder::embed_queries`],
+/// with `retrieval.query`, unless a task is set for both; the v2 models take
+/// no task. With late chunking, the texts of one `embed` call are embedded
+/// as consecutive chunks of one document, which suits the chunks of a
+/// single entity and its relationships; they must fit the model's
+/// 8192-token context and one outcall together, since splitting them would
+/// lose the shared context.
+pub struct JinaEmbedder {
+    api_key: String,
+    model: String,
+    dimensions: usize,
+    target_dimensions: Option<usize>,
+    task: Option<String>,
+    late_chunking: bool,
+    api_endpoint: String,
+    http_client: HttpClient,
+}
+
+impl JinaEmbedder {
+    /// Create a new Jina embedder
+    pub fn new(api_key: String, model: String) -> Self {
+        let dimensions = match model.as_str() {
+            "jina-embeddings-v3" => 1024,
+            "jina-embeddings-v2-base-en" => 768,
+            "jina-embeddings-v2-small-en" => 512,
+            _ => 1024, // default
+        };
+
+        Self {
+            api_key,
+            model,
+            dimensions,
+            target_dimensions: None,
+            task: None,
+            late_chunking: false,
+            api_endpoint: "https://api.jina.ai/v1/embeddings".to_string(),
+            http_client: HttpClient::new(),
+        }
+    }
+
+    /// Build from an embedder config with `provider = "jina"`
+    pub fn from_config(config: &EmbedderConfigDef, outcalls: &OutcallConfig, api_key: String) -> Self {
+        let mut embedder = Self::new(api_key, config.model.clone()).with_http_client(HttpClient::from_config(config, outcalls));
+        if let Some(endpoint) = &config.api_endpoint {
+            embedder = embedder.with_endpoint(endpoint.clone());
+        }
+        if let Some(dimensions) = config.target_dimensions {
+            embedder = embedder.with_target_dimensions(dimensions);
+        }
+        if let Some(JinaConfig { task, late_chunking }) = &config.jina {
+            if let Some(task) = task {
+                embedder = embedder.with_task(task.clone());
+            }
+            embedder = embedder.with_late_chunking(*late_chunking);
+        }
+        embedder
+    }
+
+    /// Create with custom API endpoint
+    pub fn with_endpoint(mut self, endpoint: String) -> Self {
+        self.api_endpoint = endpoint;
+        self
+    }
+
+    /// Ask for embeddings shortened to `dimensions` (jina-embeddings-v3)
+    pub fn with_target_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = dimensions;
+        self.target_dimensions = Some(dimensions);
+        self
+    }
+
+    /// Use `task` (e.g. "text-matching", "classification") for documents
+    /// and queries alike
+    pub fn with_task(mut self, task: String) -> Self {
+        self.task = Some(task);
+        self
+    }
+
+    /// Embed the texts of each `embed` call as chunks of one document
+    pub fn with_late_chunking(mut self, late_chunking: bool) -> Self {
+        self.late_chunking = late_chunking;
+        self
+    }
+
+    /// Use a custom HTTP client (e.g. with compression enabled)
+    pub fn with_http_client(mut self, http_client: HttpClient) -> Self {
+        self.http_client = http_client;
+        self
+    }
+
+    /// Only jina-embeddings-v3 accepts a task; v2 models reject the field
+    fn takes_task(&self) -> bool {
+        self.model.starts_with("jina-embeddings-v3")
+    }
+
+    fn request<'a>(&'a self, texts: &'a [String], default_task: &'a str, late_chunking: bool) -> JinaEmbedRequest<'a> {
+        JinaEmbedRequest {
+            model: &self.model,
+            input: texts,
+            task: self
+                .takes_task()
+                .then(|| self.task.as_deref().unwrap_or(default_task)),
+            dimensions: self.target_dimensions,
+            late_chunking: late_chunking.then_some(true),
+        }
+    }
+
+    /// Embed texts in as many requests as the size limits need; late
+    /// chunking only shares context within one request, so a late-chunked
+    /// document too large for one fails instead of being split
+    async fn embed_all(&self, texts: &[String], default_task: &str, late_chunking: bool) -> Result<Vec<Vec<f32>>> {
+        let batches = self.http_client.split_batch(texts, self.dimensions);
+        if late_chunking && batches.len() > 1 {
+            return Err(ContragError::EmbedderError(format!(
+                "Late chunking sends a document in one request, but its {} chunks exceed the outcall size limits; \
+                 use smaller entities or disable late_chunking",
+                texts.len()
+            ).into()));
+        }
+
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in batches {
+            embeddings.extend(self.embed_with(self.request(batch, default_task, late_chunking)).await?);
+        }
+        Ok(embeddings)
+    }
+
+    async fn embed_with(&self, request: JinaEmbedRequest<'_>) -> Result<Vec<Vec<f32>>> {
+        if request.input.is_empty() {
+            return Ok(vec![]);
+        }
+        let count = request.input.len();
+
+        let body = serde_json::to_vec(&request)
+            .map_err(|e| ContragError::SerializationError(e.to_string()))?;
+
+        let headers = vec![
+            ("Content-Type".to_string(), "application/json".to_string()),
+            ("Authorization".to_string(), format!("Bearer {}", self.api_key)),
+        ];
+
+        let response = self
+            .http_client
+            .post_as(RequestClass::Embedding, self.api_endpoint.clone(), headers, body)
+            .await?;
+
+        if response.status != 200 {
+            return Err(parse_jina_error(&response));
+        }
+
+        let mut embed_response: JinaEmbedResponse = response.json()?;
+        embed_response.data.sort_by_key(|item| item.index);
+
+        let embeddings: Vec<Vec<f32>> = embed_response
+            .data
+            .into_iter()
+            .map(|item| item.embedding)
+            .collect();
+
+        validate_embeddings("jina", &embeddings, count, self.dimensions)?;
+        Ok(embeddings)
+    }
+}
+
+#[async_trait::async_trait]
+impl Embedder for JinaEmbedder {
+    fn name(&self) -> &str {
+        "jina"
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.embed_all(&texts, "retrieval.passage", self.late_chunking).await
+    }
+
+    async fn embed_queries(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        // Queries are independent, so they never share a late-chunked context
+        self.embed_all(&texts, "retrieval.query", false).await
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embeds_in_context(&self) -> bool {
+        self.late_chunking
+    }
+
+    async fn test_connection(&self) -> Result<ConnectionTestResult> {
+        let start = ic_cdk::api::time();
+
+        match self.embed(vec!["test connection".to_string()]).await {
+            Ok(_) => {
+                let latency = (ic_cdk::api::time() - start) / 1_000_000; // Convert to ms
+                Ok(ConnectionTestResult {
+                    plugin: self.name().to_string(),
+                    connected: true,
+                    latency: Some(latency),
+                    error: None,
+                    details: Some(format!(
+                        "model: {}, dimensions: {}",
+                        self.model, self.dimensions
+                    )),
+                })
+            }
+            Err(e) => Ok(ConnectionTestResult {
+                plugin: self.name().to_string(),
+                connected: false,
+                latency: None,
+                error: Some(e.to_string()),
+                details: None,
+            }),
+        }
+    }
+}
+
+// Request/Response types for the Jina embeddings API
+
+#[derive(Serialize)]
+struct JinaEmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    task: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    late_chunking: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct JinaEmbedResponse {
+    data: Vec<JinaEmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct JinaEmbeddingData {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_and_response_shape() {
+        let texts = vec!["refund policy".to_string()];
+        let v3 = JinaEmbedder::new("key".to_string(), "jina-embeddings-v3".to_string())
+            .with_target_dimensions(256)
+            .with_late_chunking(true);
+        assert_eq!(
+            serde_json::to_value(v3.request(&texts, "retrieval.passage", true)).unwrap(),
+            serde_json::json!({
+                "model": "jina-embeddings-v3",
+                "input": ["refund policy"],
+                "task": "retrieval.passage",
+                "dimensions": 256,
+                "late_chunking": true,
+            })
+        );
+        let v3 = v3.with_task("text-matching".to_string());
+        assert_eq!(serde_json::to_value(v3.request(&texts, "retrieval.query", false)).unwrap()["task"], "text-matching");
+
+        let v2 = JinaEmbedder::new("key".to_string(), "jina-embeddings-v2-base-en".to_string())
+            .with_task("text-matching".to_string());
+        assert_eq!(v2.dimensions(), 768);
+        assert_eq!(
+            serde_json::to_value(v2.request(&texts, "retrieval.query", false)).unwrap(),
+            serde_json::json!({ "model": "jina-embeddings-v2-base-en", "input": ["refund policy"] })
+        );
+
+        let response: JinaEmbedResponse = serde_json::from_str(
+            r#"{"model":"jina-embeddings-v3","data":[{"index":1,"embedding":[0.2]},{"index":0,"embedding":[0.1]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(response.data.iter().map(|d| d.index).collect::<Vec<_>>(), [1, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_late_chunked_documents_stay_whole() {
+        let mut config = crate::config::create_default_config().embedder;
+        config.provider = "jina".to_string();
+        config.jina = Some(JinaConfig {
+            task: None,
+            late_chunking: true,
+        });
+        let embedder = JinaEmbedder::from_config(&config, &OutcallConfig::default(), "key".to_string());
+        assert!(embedder.embeds_in_context());
+
+        // Too large for one outcall: fails rather than losing the context
+        let embedder = embedder.with_http_client(HttpClient::new().with_max_request_bytes(10_000));
+        assert!(matches!(
+            embedder.embed(vec!["x".repeat(6_000); 2]).await,
+            Err(ContragError::EmbedderError(message)) if message.contains("late_chunking")
+        ));
+    }
+}
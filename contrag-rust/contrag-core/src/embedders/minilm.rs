@@ -0,0 +1,281 @@
+//! MiniLM sentence embeddings computed with candle
+//!
+//! With the `minilm` feature, [`MiniLm`] is a ready-made [`EmbeddingModel`]
+//! for BERT-family sentence-transformers such as all-MiniLM-L6-v2:
+//! WordPiece tokenization, a candle forward pass on the CPU and mean
+//! pooling over the tokens. The model's `config.json` and `vocab.txt` are
+//! small enough to compile into the canister; its `model.safetensors`
+//! weights are uploaded to stable memory as described in
+//! [`onchain`](super::onchain).
+//!
+//! ```ignore
+//! const CONFIG: &str = include_str!("../model/config.json");
+//! const VOCAB: &str = include_str!("../model/vocab.txt");
+//!
+//! let model = MiniLm::new(CONFIG, VOCAB, weights)?;
+//! ```
+//!
+//! candle pulls in `getrandom`, so wasm32 canister builds need its custom
+//! backend: `RUSTFLAGS='--cfg getrandom_backend="custom"'`.
+//!
+//! The tokenizer lowercases and splits on whitespace and punctuation like
+//! BERT's uncased tokenizer, but doesn't strip accents.
+
+use std::collections::HashMap;
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+use crate::embedders::onchain::EmbeddingModel;
+use crate::error::{ContragError, Result};
+
+/// Word pieces all-MiniLM-L6-v2 was trained on; longer texts are truncated
+const DEFAULT_MAX_TOKENS: usize = 256;
+
+/// Words longer than this many characters become `[UNK]`, as in BERT
+const MAX_WORD_CHARS: usize = 100;
+
+/// A BERT-family sentence-transformer run with candle
+pub struct MiniLm {
+    model: BertModel,
+    tokenizer: WordPiece,
+    dimensions: usize,
+    max_positions: usize,
+    max_tokens: usize,
+}
+
+impl MiniLm {
+    /// Load a model from its `config.json`, `vocab.txt` and safetensors weights
+    pub fn new(config: &str, vocab: &str, weights: Vec<u8>) -> Result<Self> {
+        let config: Config = serde_json::from_str(config)
+            .map_err(|e| ContragError::InvalidConfig(format!("Invalid MiniLM config: {}", e)))?;
+        let tokenizer = WordPiece::new(vocab)?;
+        let vb = VarBuilder::from_buffered_safetensors(weights, DTYPE, &Device::Cpu)
+            .map_err(candle_error)?;
+        let model = BertModel::load(vb, &config).map_err(candle_error)?;
+
+        Ok(Self {
+            model,
+            tokenizer,
+            dimensions: config.hidden_size,
+            max_positions: config.max_position_embeddings,
+            max_tokens: DEFAULT_MAX_TOKENS.min(config.max_position_embeddings),
+        })
+    }
+
+    /// Truncate texts to `max_tokens` word pieces, counting `[CLS]` and
+    /// `[SEP]`; at most the model's position embeddings
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens.clamp(2, self.max_positions);
+        self
+    }
+
+    fn forward(&self, texts: &[String]) -> candle_core::Result<Vec<Vec<f32>>> {
+        let ids: Vec<Vec<u32>> = texts
+            .iter()
+            .map(|text| self.tokenizer.encode(text, self.max_tokens))
+            .collect();
+        let len = ids.iter().map(Vec::len).max().unwrap_or(0);
+
+        let mut input_ids = Vec::with_capacity(ids.len() * len);
+        let mut mask = Vec::with_capacity(ids.len() * len);
+        for text in &ids {
+            input_ids.extend(text.iter().copied().chain(std::iter::repeat(self.tokenizer.pad)).take(len));
+            mask.extend((0..len).map(|i| u32::from(i < text.len())));
+        }
+
+        let input_ids = Tensor::from_vec(input_ids, (ids.len(), len), &Device::Cpu)?;
+        let mask = Tensor::from_vec(mask, (ids.len(), len), &Device::Cpu)?;
+        let token_type_ids = input_ids.zeros_like()?;
+        let hidden = self.model.forward(&input_ids, &token_type_ids, Some(&mask))?;
+
+        // Mean over the real tokens; padding doesn't count
+        let mask = mask.to_dtype(DType::F32)?.unsqueeze(2)?;
+        let sums = hidden.broadcast_mul(&mask)?.sum(1)?;
+        let counts = mask.sum(1)?;
+        sums.broadcast_div(&counts)?.to_vec2()
+    }
+}
+
+impl EmbeddingModel for MiniLm {
+    fn name(&self) -> &str {
+        "minilm"
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        self.forward(texts).map_err(candle_error)
+    }
+}
+
+fn candle_error(e: candle_core::Error) -> ContragError {
+    ContragError::EmbedderError(format!("MiniLM: {}", e).into())
+}
+
+/// BERT's uncased WordPiece tokenizer
+struct WordPiece {
+    vocab: HashMap<String, u32>,
+    unk: u32,
+    cls: u32,
+    sep: u32,
+    pad: u32,
+}
+
+impl WordPiece {
+    /// Build from `vocab.txt`, one token per line in id order
+    fn new(vocab: &str) -> Result<Self> {
+        let vocab: HashMap<String, u32> = vocab
+            .lines()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id as u32))
+            .collect();
+        let special = |token: &str| {
+            vocab.get(token).copied().ok_or_else(|| {
+                ContragError::InvalidConfig(format!("MiniLM vocabulary has no {} token", token))
+            })
+        };
+
+        Ok(Self {
+            unk: special("[UNK]")?,
+            cls: special("[CLS]")?,
+            sep: special("[SEP]")?,
+            pad: special("[PAD]")?,
+            vocab,
+        })
+    }
+
+    /// Token ids of `text` between `[CLS]` and `[SEP]`, at most `max_tokens`
+    fn encode(&self, text: &str, max_tokens: usize) -> Vec<u32> {
+        let mut ids = vec![self.cls];
+        for word in split_words(&text.to_lowercase()) {
+            self.push_pieces(&word, &mut ids);
+            if ids.len() >= max_tokens - 1 {
+                break;
+            }
+        }
+        ids.truncate(max_tokens - 1);
+        ids.push(self.sep);
+        ids
+    }
+
+    /// Greedy longest-match-first split of `word` into vocabulary pieces
+    fn push_pieces(&self, word: &str, ids: &mut Vec<u32>) {
+        let chars: Vec<(usize, char)> = word.char_indices().collect();
+        if chars.len() > MAX_WORD_CHARS {
+            ids.push(self.unk);
+            return;
+        }
+
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let from = chars[start].0;
+            let piece = (start + 1..=chars.len()).rev().find_map(|end| {
+                let to = chars.get(end).map_or(word.len(), |&(i, _)| i);
+                let piece = if start == 0 {
+                    word[from..to].to_string()
+                } else {
+                    format!("##{}", &word[from..to])
+                };
+                self.vocab.get(&piece).map(|&id| (id, end))
+            });
+            match piece {
+                Some((id, end)) => {
+                    pieces.push(id);
+                    start = end;
+                }
+                None => {
+                    ids.push(self.unk);
+                    return;
+                }
+            }
+        }
+        ids.extend(pieces);
+    }
+}
+
+/// Split on whitespace, and around punctuation and CJK characters
+fn split_words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for c in text.chars() {
+        if c.is_whitespace() || c.is_control() {
+            words.extend((!word.is_empty()).then(|| std::mem::take(&mut word)));
+        } else if !c.is_alphanumeric() || is_cjk(c) {
+            words.extend((!word.is_empty()).then(|| std::mem::take(&mut word)));
+            words.push(c.to_string());
+        } else {
+            word.push(c);
+        }
+    }
+    words.extend((!word.is_empty()).then_some(word));
+    words
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x4E00..=0x9FFF | 0x3400..=0x4DBF | 0x20000..=0x2A6DF | 0x2A700..=0x2B73F
+        | 0x2B740..=0x2B81F | 0x2B820..=0x2CEAF | 0xF900..=0xFAFF | 0x2F800..=0x2FA1F)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candle_nn::VarMap;
+
+    const VOCAB: &str = "[PAD]\n[UNK]\n[CLS]\n[SEP]\nhello\nworld\n##s\n,\nun\n##aff\n##able";
+
+    const CONFIG: &str = r#"{
+        "vocab_size": 11, "hidden_size": 8, "num_hidden_layers": 1,
+        "num_attention_heads": 2, "intermediate_size": 16, "hidden_act": "gelu",
+        "hidden_dropout_prob": 0.1, "max_position_embeddings": 32, "type_vocab_size": 2,
+        "initializer_range": 0.02, "layer_norm_eps": 1e-12, "pad_token_id": 0
+    }"#;
+
+    /// Randomly initialized weights for [`CONFIG`], as safetensors
+    fn random_weights() -> Vec<u8> {
+        let varmap = VarMap::new();
+        let vb = VarBuilder::from_varmap(&varmap, DTYPE, &Device::Cpu);
+        BertModel::load(vb, &serde_json::from_str(CONFIG).unwrap()).unwrap();
+
+        let path = std::env::temp_dir().join(format!("contrag-minilm-{}.safetensors", std::process::id()));
+        varmap.save(&path).unwrap();
+        let weights = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        weights
+    }
+
+    #[test]
+    fn test_wordpiece() {
+        let tokenizer = WordPiece::new(VOCAB).unwrap();
+        assert_eq!(
+            tokenizer.encode("Hello, worlds unaffable!", 32),
+            vec![2, 4, 7, 5, 6, 8, 9, 10, 1, 3]
+        );
+        assert_eq!(tokenizer.encode("hello world hello", 4), vec![2, 4, 5, 3]);
+        assert!(WordPiece::new("hello\nworld").is_err());
+    }
+
+    #[test]
+    fn test_embeddings_ignore_padding() {
+        let model = MiniLm::new(CONFIG, VOCAB, random_weights()).unwrap();
+        assert_eq!(model.dimensions(), 8);
+
+        let alone = model.embed(&["hello world".to_string()]).unwrap();
+        let batched = model
+            .embed(&["hello world".to_string(), "hello worlds, unaffable worlds".to_string()])
+            .unwrap();
+        assert_eq!(batched.len(), 2);
+        assert_eq!(batched[0].len(), 8);
+        for (a, b) in alone[0].iter().zip(&batched[0]) {
+            assert!((a - b).abs() < 1e-5);
+        }
+
+        let truncated = model.with_max_tokens(4);
+        let short = truncated.embed(&["hello world".to_string()]).unwrap();
+        let long = truncated.embed(&["hello world hello world".to_string()]).unwrap();
+        assert_eq!(short, long);
+    }
+}
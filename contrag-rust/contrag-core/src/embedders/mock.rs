@@ -0,0 +1,157 @@
+//! Configurable embedder shared by the tests of embedder wrappers and the
+//! pipeline
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::embedders::validation::validate_embeddings;
+use crate::embedders::Embedder;
+use crate::error::{ContragError, ProviderError, ProviderErrorKind, Result};
+use crate::types::ConnectionTestResult;
+
+/// Embeds each text as `dimensions` copies of its length, unless given a
+/// fixed embedding, and counts calls and texts
+///
+/// One set up with [`returning_dimensions`](Self::returning_dimensions)
+/// fails in [`validate_embeddings`] the way a provider adapter does when
+/// the model returns other dimensions than configured.
+pub(crate) struct MockEmbedder {
+    name: &'static str,
+    dimensions: usize,
+    returned_dimensions: usize,
+    embedding: Option<Vec<f32>>,
+    max_batch: Option<usize>,
+    error: Option<ProviderErrorKind>,
+    in_context: bool,
+    calls: AtomicUsize,
+    texts: AtomicUsize,
+}
+
+impl MockEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        Self {
+            name: "mock",
+            dimensions,
+            returned_dimensions: dimensions,
+            embedding: None,
+            max_batch: None,
+            error: None,
+            in_context: false,
+            calls: AtomicUsize::new(0),
+            texts: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn named(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Embed every text as `embedding`
+    pub fn with_embedding(mut self, embedding: Vec<f32>) -> Self {
+        self.dimensions = embedding.len();
+        self.returned_dimensions = embedding.len();
+        self.embedding = Some(embedding);
+        self
+    }
+
+    /// Return embeddings of `dimensions` while reporting the configured ones
+    pub fn returning_dimensions(mut self, dimensions: usize) -> Self {
+        self.returned_dimensions = dimensions;
+        self
+    }
+
+    /// Reject batches of more than `max_batch` texts with a 413
+    pub fn with_max_batch(mut self, max_batch: usize) -> Self {
+        self.max_batch = Some(max_batch);
+        self
+    }
+
+    /// Fail every call with a provider error of `kind`
+    pub fn failing_with(mut self, kind: ProviderErrorKind) -> Self {
+        self.error = Some(kind);
+        self
+    }
+
+    /// Report embeddings as depending on the whole call, like late chunking
+    pub fn in_context(mut self) -> Self {
+        self.in_context = true;
+        self
+    }
+
+    /// Calls to `embed` and `embed_queries` so far
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// Texts sent to `embed` and `embed_queries` so far
+    pub fn texts(&self) -> usize {
+        self.texts.load(Ordering::SeqCst)
+    }
+
+    fn provider_error(&self, kind: ProviderErrorKind) -> ContragError {
+        let status = match kind {
+            ProviderErrorKind::RateLimited => 429,
+            ProviderErrorKind::Authentication => 401,
+            ProviderErrorKind::PayloadTooLarge => 413,
+            ProviderErrorKind::InvalidRequest => 400,
+            _ => 500,
+        };
+        ContragError::from(ProviderError {
+            provider: self.name.to_string(),
+            kind,
+            status,
+            retry_after: None,
+            code: None,
+            message: format!("{:?}", kind),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Embedder for MockEmbedder {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.texts.fetch_add(texts.len(), Ordering::SeqCst);
+
+        if let Some(kind) = self.error {
+            return Err(self.provider_error(kind));
+        }
+        if self.max_batch.is_some_and(|max| texts.len() > max) {
+            return Err(self.provider_error(ProviderErrorKind::PayloadTooLarge));
+        }
+
+        let embeddings: Vec<Vec<f32>> = texts
+            .iter()
+            .map(|text| match &self.embedding {
+                Some(embedding) => embedding.clone(),
+                None => vec![text.len() as f32; self.returned_dimensions],
+            })
+            .collect();
+        if self.returned_dimensions != self.dimensions {
+            validate_embeddings(self.name, &embeddings, texts.len(), self.dimensions)?;
+        }
+        Ok(embeddings)
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embeds_in_context(&self) -> bool {
+        self.in_context
+    }
+
+    /// Connected unless the mock fails every call, without counting a call
+    async fn test_connection(&self) -> Result<ConnectionTestResult> {
+        Ok(ConnectionTestResult {
+            plugin: self.name.to_string(),
+            connected: self.error.is_none(),
+            latency: Some(0),
+            error: self.error.map(|kind| self.provider_error(kind).to_string()),
+            details: Some(format!("dimensions: {}", self.dimensions)),
+        })
+    }
+}
@@ -1,8 +1,27 @@
+pub mod batching;
+pub mod cache;
+pub mod cohere;
+pub mod fallback;
 pub mod openai;
 pub mod gemini;
+pub mod jina;
+#[cfg(feature = "minilm")]
+pub mod minilm;
+#[cfg(test)]
+pub(crate) mod mock;
+pub mod normalize;
+pub mod ollama;
+pub mod onchain;
+pub mod openai_compat;
 pub mod http_client;
+pub mod provider_error;
+pub mod usage_ledger;
+pub mod usage_meter;
+pub mod validation;
 
-use crate::error::Result;
+use crate::config::{EmbedderConfigDef, OutcallConfig};
+use crate::error::{ContragError, Result};
+use crate::keys::KeyStore;
 use crate::types::ConnectionTestResult;
 
 /// Trait for embedding providers
@@ -16,13 +35,40 @@ pub trait Embedder: Send + Sync {
     /// Generate embeddings for a batch of texts
     async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>>;
 
+    /// Generate embeddings for search queries
+    ///
+    /// `embed` is used for the documents being indexed. Providers that embed
+    /// queries differently, like Cohere's `input_type`, override this.
+    async fn embed_queries(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.embed(texts).await
+    }
+
     /// Get the dimensions of the embeddings
     fn dimensions(&self) -> usize;
 
+    /// Whether an embedding depends on the other texts of its `embed` call,
+    /// as with Jina's late chunking
+    ///
+    /// Wrappers that split a call or answer part of it from a cache pass such
+    /// calls on whole, so the provider still sees the complete document.
+    fn embeds_in_context(&self) -> bool {
+        false
+    }
+
     /// Test the connection to the embedding service
     async fn test_connection(&self) -> Result<ConnectionTestResult>;
 
+    /// Check that embeddings have the dimensions `config` expects; see
+    /// [`validation::verify_dimensions`]
+    async fn verify(&self, config: &EmbedderConfigDef) -> Result<()> {
+        validation::verify_dimensions(self, config).await
+    }
+
     /// Optional: Generate text with prompt (for LLM features)
+    ///
+    /// Only used by pipelines without a
+    /// [`TextGenerator`](crate::generators::TextGenerator); prefer setting
+    /// one with `RagPipeline::with_generator`.
     async fn generate_with_prompt(
         &self,
         _text: String,
@@ -32,37 +78,169 @@ pub trait Embedder: Send + Sync {
     }
 }
 
-/// Cache for embeddings to reduce API calls
+#[async_trait::async_trait]
+impl<E: Embedder + ?Sized> Embedder for Box<E> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        (**self).embed(texts).await
+    }
+
+    async fn embed_queries(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        (**self).embed_queries(texts).await
+    }
+
+    fn dimensions(&self) -> usize {
+        (**self).dimensions()
+    }
+
+    fn embeds_in_context(&self) -> bool {
+        (**self).embeds_in_context()
+    }
+
+    async fn test_connection(&self) -> Result<ConnectionTestResult> {
+        (**self).test_connection().await
+    }
+
+    async fn generate_with_prompt(&self, text: String, system_prompt: String) -> Result<String> {
+        (**self).generate_with_prompt(text, system_prompt).await
+    }
+}
+
+/// Build the embedder named by `config.provider`
+///
+/// Known providers are "openai", "azure_openai", "gemini", "cohere", "jina"
+/// and "ollama". Any other provider with an `api_endpoint` is treated as
+/// OpenAI-compatible. An empty `api_key` means no key, for self-hosted
+/// servers. Outcalls follow the subnet size, size limits and cycles ceiling
+/// of `outcalls` (`ContragConfig::outcalls`). The provider comes wrapped as
+/// configured: requests are split by an
+/// [`AdaptiveBatcher`](batching::AdaptiveBatcher) following `batching`, each
+/// one is held to `rate_limit` by a
+/// [`RateLimitedEmbedder`](usage_ledger::RateLimitedEmbedder), and with
+/// `normalize` set a [`NormalizedEmbedder`](normalize::NormalizedEmbedder)
+/// scales the results to unit length.
+pub fn from_config(config: &EmbedderConfigDef, outcalls: &OutcallConfig, api_key: String) -> Result<Box<dyn Embedder>> {
+    let optional_key = Some(api_key.clone()).filter(|key| !key.is_empty());
+
+    let embedder: Box<dyn Embedder> = match config.provider.as_str() {
+        "openai" | "azure_openai" => {
+            if config.provider == "azure_openai" && config.azure.is_none() {
+                return Err(ContragError::InvalidConfig(
+                    "Embedder provider 'azure_openai' requires azure settings".to_string(),
+                ));
+            }
+            Box::new(openai::OpenAIEmbedder::from_config(config, outcalls, api_key))
+        }
+        "gemini" => Box::new(gemini::GeminiEmbedder::from_config(config, outcalls, api_key)),
+        "cohere" => Box::new(cohere::CohereEmbedder::from_config(config, outcalls, api_key)),
+        "jina" => Box::new(jina::JinaEmbedder::from_config(config, outcalls, api_key)),
+        "ollama" => Box::new(ollama::OllamaEmbedder::from_config(config, outcalls, optional_key)?),
+        _ if config.api_endpoint.is_some() => {
+            Box::new(openai_compat::GenericOpenAICompatEmbedder::from_config(config, outcalls, optional_key)?)
+        }
+        other => {
+            return Err(ContragError::InvalidConfig(format!(
+                "Unknown embedder provider '{}'; set api_endpoint to use an OpenAI-compatible server",
+                other
+            )))
+        }
+    };
+
+    Ok(with_configured_wrappers(embedder, config))
+}
+
+/// Wrap a provider in the batching, rate limiting and normalization of
+/// `config`, see [`from_config`]
+fn with_configured_wrappers(embedder: Box<dyn Embedder>, config: &EmbedderConfigDef) -> Box<dyn Embedder> {
+    let embedder: Box<dyn Embedder> = match &config.rate_limit {
+        Some(limit) => Box::new(usage_ledger::RateLimitedEmbedder::new(embedder, limit.clone())),
+        None => embedder,
+    };
+    let embedder = Box::new(batching::AdaptiveBatcher::from_config(embedder, config));
+    if config.normalize {
+        Box::new(normalize::NormalizedEmbedder::new(embedder))
+    } else {
+        embedder
+    }
+}
+
+/// Build the embedder for `config` with the current key of its provider in
+/// `keys`
+///
+/// During a rotation's grace period (at `now`, nanoseconds), requests whose
+/// key the provider rejects are retried with the replaced key. Ollama and
+/// other self-hosted providers (those with an `api_endpoint`) may have no
+/// key; every other provider must have one.
+pub fn from_key_store(
+    config: &EmbedderConfigDef,
+    outcalls: &OutcallConfig,
+    keys: &KeyStore,
+    now: u64,
+) -> Result<Box<dyn Embedder>> {
+    let mut api_keys = keys.keys(&config.provider, now).into_iter();
+    let Some(current) = api_keys.next() else {
+        if config.provider == "ollama" || config.api_endpoint.is_some() {
+            return from_config(config, outcalls, String::new());
+        }
+        return Err(ContragError::InvalidConfig(format!(
+            "No API key for embedder provider '{}'",
+            config.provider
+        )));
+    };
+
+    let embedder = from_config(config, outcalls, current.to_string())?;
+    match api_keys.next() {
+        Some(previous) => {
+            let previous = from_config(config, outcalls, previous.to_string())?;
+            Ok(Box::new(fallback::FallbackEmbedder::on_auth_failure(embedder, previous)?))
+        }
+        None => Ok(embedder),
+    }
+}
+
+/// In-memory cache for embeddings to reduce API calls
+///
+/// Keyed by the SHA-256 of the text, so cached texts aren't kept; when full,
+/// the least recently used entry is evicted. Entries are lost on upgrade;
+/// see [`cache::PersistentCachedEmbedder`] for a cache that survives them.
 pub struct EmbeddingCache {
-    cache: std::collections::HashMap<String, Vec<f32>>,
+    cache: cache::Lru<[u8; 32]>,
     max_size: usize,
 }
 
 impl EmbeddingCache {
     pub fn new(max_size: usize) -> Self {
         Self {
-            cache: std::collections::HashMap::new(),
+            cache: cache::Lru::default(),
             max_size,
         }
     }
 
-    pub fn get(&self, text: &str) -> Option<Vec<f32>> {
-        self.cache.get(text).cloned()
+    /// Look up `text`, marking it as most recently used
+    pub fn get(&mut self, text: &str) -> Option<Vec<f32>> {
+        self.cache.get(&text_key(text))
     }
 
     pub fn insert(&mut self, text: String, embedding: Vec<f32>) {
-        if self.cache.len() >= self.max_size {
-            // Simple LRU: remove first entry
-            if let Some(first_key) = self.cache.keys().next().cloned() {
-                self.cache.remove(&first_key);
-            }
-        }
-        self.cache.insert(text, embedding);
+        self.cache.insert(text_key(&text), embedding, self.max_size);
     }
 
     pub fn clear(&mut self) {
-        self.cache.clear();
+        self.cache = cache::Lru::default();
     }
+
+    /// Size, hits, misses and evictions since creation or the last clear
+    pub fn stats(&self) -> cache::EmbeddingCacheStats {
+        self.cache.stats()
+    }
+}
+
+fn text_key(text: &str) -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(text.as_bytes()).into()
 }
 
 /// Embedder wrapper with caching support
@@ -80,6 +258,10 @@ impl<E: Embedder> CachedEmbedder<E> {
     }
 
     pub async fn embed_with_cache(&mut self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        if self.embedder.embeds_in_context() {
+            return self.embedder.embed(texts).await;
+        }
+
         let mut results = vec![];
         let mut to_embed = vec![];
         let mut indices = vec![];
@@ -113,4 +295,81 @@ impl<E: Embedder> CachedEmbedder<E> {
         results.sort_by_key(|(idx, _)| *idx);
         Ok(results.into_iter().map(|(_, emb)| emb).collect())
     }
+
+    /// Hit and eviction counters of the cache
+    pub fn cache_stats(&self) -> cache::EmbeddingCacheStats {
+        self.cache.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::EmbedderFailure;
+    use crate::config::create_default_config;
+
+    #[test]
+    fn test_from_config_picks_provider() {
+        let outcalls = OutcallConfig::default();
+        let mut config = create_default_config().embedder;
+        assert_eq!(from_config(&config, &outcalls, "key".to_string()).unwrap().name(), "openai");
+
+        config.provider = "cohere".to_string();
+        config.model = "embed-english-light-v3.0".to_string();
+        let embedder = from_config(&config, &outcalls, "key".to_string()).unwrap();
+        assert_eq!((embedder.name(), embedder.dimensions()), ("cohere", 384));
+
+        config.provider = "together".to_string();
+        assert!(matches!(from_config(&config, &outcalls, String::new()), Err(ContragError::InvalidConfig(_))));
+        config.api_endpoint = Some("https://api.together.xyz/v1".to_string());
+        assert_eq!(from_config(&config, &outcalls, String::new()).unwrap().name(), "together");
+
+        // Self-hosted providers may have no key in the store
+        let mut keys = KeyStore::new();
+        assert!(from_key_store(&config, &outcalls, &keys, 0).is_ok());
+        let openai = create_default_config().embedder;
+        assert!(matches!(from_key_store(&openai, &outcalls, &keys, 0), Err(ContragError::InvalidConfig(_))));
+        keys.set("openai", "sk-key");
+        assert_eq!(from_key_store(&openai, &outcalls, &keys, 0).unwrap().name(), "openai");
+        keys.rotate("openai", "sk-new-key", 60, 0);
+        assert_eq!(from_key_store(&openai, &outcalls, &keys, 0).unwrap().name(), "openai");
+    }
+
+    #[tokio::test]
+    async fn test_configured_wrappers() {
+        use crate::embedders::mock::MockEmbedder;
+        use crate::embedders::usage_ledger::RateLimit;
+        use crate::error::ProviderErrorKind;
+
+        let mut config = create_default_config().embedder;
+        config.normalize = true;
+        config.rate_limit = Some(RateLimit { requests_per_minute: Some(1), tokens_per_minute: None });
+        let embedder = with_configured_wrappers(Box::new(MockEmbedder::new(2).named("wrapped")), &config);
+
+        let texts: Vec<String> = (1..=3).map(|i| "x".repeat(i)).collect();
+        let embeddings = embedder.embed(texts).await.unwrap();
+        let unit = std::f32::consts::FRAC_1_SQRT_2;
+        assert!(embeddings.iter().all(|e| (e[0] - unit).abs() < 1e-6));
+
+        // The second request of the minute is over the limit
+        match embedder.embed(vec!["y".to_string()]).await {
+            Err(ContragError::EmbedderError(EmbedderFailure { provider: Some(e), .. })) => assert_eq!(e.kind, ProviderErrorKind::RateLimited),
+            other => panic!("expected a rate limit error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_embedding_cache_evicts_least_recently_used() {
+        let mut cache = EmbeddingCache::new(2);
+        cache.insert("a".to_string(), vec![1.0]);
+        cache.insert("b".to_string(), vec![2.0]);
+        assert_eq!(cache.get("a"), Some(vec![1.0]));
+        cache.insert("c".to_string(), vec![3.0]);
+
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(vec![1.0]));
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.evictions), (2, 1));
+        assert!((stats.hit_rate() - 2.0 / 3.0).abs() < 1e-9);
+    }
 }
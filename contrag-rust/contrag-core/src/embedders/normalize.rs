@@ -0,0 +1,87 @@
+use crate::config::EmbedderConfigDef;
+use crate::embedders::Embedder;
+use crate::error::Result;
+use crate::types::ConnectionTestResult;
+use crate::vector_store::l2_normalize;
+
+/// Embedder wrapper that scales every embedding to unit length
+///
+/// With normalized documents and queries, dot product equals cosine
+/// similarity, so the vector store can use
+/// [`DistanceMetric::DotProduct`](crate::config::DistanceMetric::DotProduct)
+/// and skip computing magnitudes on every search. Normalizing embeddings
+/// that already have unit length changes nothing.
+pub struct NormalizedEmbedder<E: Embedder> {
+    embedder: E,
+    enabled: bool,
+}
+
+impl<E: Embedder> NormalizedEmbedder<E> {
+    pub fn new(embedder: E) -> Self {
+        Self { embedder, enabled: true }
+    }
+
+    /// Normalize only if the embedder config sets `normalize`
+    pub fn from_config(embedder: E, config: &EmbedderConfigDef) -> Self {
+        Self {
+            embedder,
+            enabled: config.normalize,
+        }
+    }
+
+    fn normalized(&self, mut embeddings: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
+        if self.enabled {
+            embeddings.iter_mut().for_each(|e| l2_normalize(e));
+        }
+        embeddings
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: Embedder> Embedder for NormalizedEmbedder<E> {
+    fn name(&self) -> &str {
+        self.embedder.name()
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        Ok(self.normalized(self.embedder.embed(texts).await?))
+    }
+
+    async fn embed_queries(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        Ok(self.normalized(self.embedder.embed_queries(texts).await?))
+    }
+
+    fn dimensions(&self) -> usize {
+        self.embedder.dimensions()
+    }
+
+    fn embeds_in_context(&self) -> bool {
+        self.embedder.embeds_in_context()
+    }
+
+    async fn test_connection(&self) -> Result<ConnectionTestResult> {
+        self.embedder.test_connection().await
+    }
+
+    async fn generate_with_prompt(&self, text: String, system_prompt: String) -> Result<String> {
+        self.embedder.generate_with_prompt(text, system_prompt).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedders::mock::MockEmbedder;
+
+    #[tokio::test]
+    async fn test_normalizes_documents_and_queries_when_enabled() {
+        let embedder = NormalizedEmbedder::new(MockEmbedder::new(2).with_embedding(vec![3.0, 4.0]));
+        assert_eq!(embedder.embed(vec!["a".to_string()]).await.unwrap(), vec![vec![0.6, 0.8]]);
+        assert_eq!(embedder.embed_queries(vec!["a".to_string()]).await.unwrap(), vec![vec![0.6, 0.8]]);
+
+        let mut config = crate::config::create_default_config().embedder;
+        config.normalize = false;
+        let embedder = NormalizedEmbedder::from_config(MockEmbedder::new(2).with_embedding(vec![3.0, 4.0]), &config);
+        assert_eq!(embedder.embed(vec!["a".to_string()]).await.unwrap(), vec![vec![3.0, 4.0]]);
+    }
+}
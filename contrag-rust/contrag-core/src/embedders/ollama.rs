@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use crate::config::{EmbedderConfigDef, OutcallConfig};
+use crate::embedders::{Embedder, http_client::{HttpClient, RequestClass}};
+use crate::embedders::provider_error::{error_in_success_body, parse_openai_error};
+use crate::embedders::validation::validate_embeddings;
+use crate::error::{ContragError, Result};
+use crate::types::ConnectionTestResult;
+
+/// Embedder for a self-hosted Ollama server, using its native `/api/embed`
+/// endpoint
+///
+/// The server must be reachable from the subnet's nodes, which make HTTP
+/// outcalls over IPv6. For Ollama's OpenAI-compatible `/v1/embeddings`
+/// endpoint use [`GenericOpenAICompatEmbedder`](super::openai_compat::GenericOpenAICompatEmbedder)
+/// instead.
+pub struct OllamaEmbedder {
+    api_key: Option<String>,
+    model: String,
+    dimensions: usize,
+    base_url: String,
+    http_client: HttpClient,
+}
+
+impl OllamaEmbedder {
+    /// Create an embedder for the server at `base_url`
+    /// (e.g. "https://ollama.example.com")
+    ///
+    /// Ollama serves many models, so `dimensions` can't be derived from the
+    /// model name.
+    pub fn new(base_url: String, model: String, dimensions: usize) -> Self {
+        Self {
+            api_key: None,
+            model,
+            dimensions,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http_client: HttpClient::new(),
+        }
+    }
+
+    /// Build from an embedder config with `provider = "ollama"`;
+    /// `api_endpoint` is required
+    pub fn from_config(config: &EmbedderConfigDef, outcalls: &OutcallConfig, api_key: Option<String>) -> Result<Self> {
+        let base_url = config.api_endpoint.clone().ok_or_else(|| {
+            ContragError::InvalidConfig("Embedder provider 'ollama' requires api_endpoint".to_string())
+        })?;
+
+        let mut embedder = Self::new(base_url, config.model.clone(), config.dimensions).with_http_client(HttpClient::from_config(config, outcalls));
+        if let Some(key) = api_key {
+            embedder = embedder.with_api_key(key);
+        }
+        Ok(embedder)
+    }
+
+    /// Send a Bearer token, for servers behind an authenticating proxy
+    pub fn with_api_key(mut self, api_key: String) -> Self {
+        self.api_key = Some(api_key);
+        self
+    }
+
+    /// Use a custom HTTP client (e.g. with compression enabled)
+    pub fn with_http_client(mut self, http_client: HttpClient) -> Self {
+        self.http_client = http_client;
+        self
+    }
+
+    /// Embed texts in one request
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let count = texts.len();
+        let request = OllamaEmbedRequest {
+            model: &self.model,
+            input: texts,
+        };
+
+        let body = serde_json::to_vec(&request)
+            .map_err(|e| ContragError::SerializationError(e.to_string()))?;
+
+        let response = self
+            .http_client
+            .post_as(RequestClass::Embedding, format!("{}/api/embed", self.base_url), self.headers(), body)
+            .await?;
+
+        // Ollama reports errors as {"error": "..."}, which the OpenAI parser
+        // falls back to
+        if response.status != 200 || error_in_success_body(&response) {
+            return Err(parse_openai_error("ollama", &response));
+        }
+
+        let embed_response: OllamaEmbedResponse = response.json()?;
+        validate_embeddings("ollama", &embed_response.embeddings, count, self.dimensions)?;
+        Ok(embed_response.embeddings)
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        let mut headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+        if let Some(key) = &self.api_key {
+            headers.push(("Authorization".to_string(), format!("Bearer {}", key)));
+        }
+        headers
+    }
+}
+
+#[async_trait::async_trait]
+impl Embedder for OllamaEmbedder {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in self.http_client.split_batch(&texts, self.dimensions) {
+            embeddings.extend(self.embed_batch(batch).await?);
+        }
+        Ok(embeddings)
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    async fn test_connection(&self) -> Result<ConnectionTestResult> {
+        let start = ic_cdk::api::time();
+
+        match self.embed(vec!["test connection".to_string()]).await {
+            Ok(_) => {
+                let latency = (ic_cdk::api::time() - start) / 1_000_000; // Convert to ms
+                Ok(ConnectionTestResult {
+                    plugin: self.name().to_string(),
+                    connected: true,
+                    latency: Some(latency),
+                    error: None,
+                    details: Some(format!(
+                        "endpoint: {}, model: {}, dimensions: {}",
+                        self.base_url, self.model, self.dimensions
+                    )),
+                })
+            }
+            Err(e) => Ok(ConnectionTestResult {
+                plugin: self.name().to_string(),
+                connected: false,
+                latency: None,
+                error: Some(e.to_string()),
+                details: None,
+            }),
+        }
+    }
+}
+
+// Request/Response types for the Ollama embed API
+
+#[derive(Serialize)]
+struct OllamaEmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct OllamaEmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_and_response_shape() {
+        let texts = vec!["refund policy".to_string()];
+        let request = OllamaEmbedRequest {
+            model: "nomic-embed-text",
+            input: &texts,
+        };
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({ "model": "nomic-embed-text", "input": ["refund policy"] })
+        );
+
+        let response: OllamaEmbedResponse =
+            serde_json::from_str(r#"{"model":"nomic-embed-text","embeddings":[[0.1,0.2]],"total_duration":1}"#).unwrap();
+        assert_eq!(response.embeddings, vec![vec![0.1, 0.2]]);
+    }
+
+    #[test]
+    fn test_from_config_needs_endpoint_and_sends_key() {
+        let mut config = crate::config::create_default_config().embedder;
+        config.provider = "ollama".to_string();
+        assert!(OllamaEmbedder::from_config(&config, &OutcallConfig::default(), None).is_err());
+
+        config.api_endpoint = Some("https://ollama.example.com/".to_string());
+        let embedder = OllamaEmbedder::from_config(&config, &OutcallConfig::default(), None).unwrap();
+        assert_eq!(embedder.base_url, "https://ollama.example.com");
+        assert_eq!(embedder.headers().len(), 1);
+
+        let embedder = embedder.with_api_key("secret".to_string());
+        assert!(embedder.headers().contains(&("Authorization".to_string(), "Bearer secret".to_string())));
+    }
+}
@@ -0,0 +1,265 @@
+//! Embeddings computed inside the canister
+//!
+//! For deployments that can't make HTTP outcalls, [`OnChainEmbedder`] runs
+//! a small sentence-embedding model in the canister itself. The model is
+//! anything implementing [`EmbeddingModel`]; with the `minilm` feature,
+//! `MiniLm` runs BERT-family sentence-transformers such as a MiniLM with
+//! candle.
+//!
+//! Model weights are too large for the wasm module and for a single
+//! ingress message, so they live in the [`ContragMemory::ModelWeights`]
+//! region: upload them with [`upload_weights_chunk`], check them with
+//! [`finish_weights_upload`], then build the model from [`load_weights`]
+//! in `init`/`post_upgrade`.
+//!
+//! ```ignore
+//! #[update]
+//! fn upload_weights(offset: u64, chunk: Vec<u8>) -> Result<(), String> {
+//!     upload_weights_chunk(&weights_memory(), offset, &chunk).map_err(|e| e.to_string())
+//! }
+//!
+//! #[update]
+//! fn finish_weights(len: u64, sha256: [u8; 32]) -> Result<(), String> {
+//!     finish_weights_upload(&weights_memory(), len, sha256).map_err(|e| e.to_string())
+//! }
+//!
+//! #[post_upgrade]
+//! fn post_upgrade() {
+//!     if let Some(weights) = load_weights(&weights_memory()).unwrap() {
+//!         let model = MiniLm::new(CONFIG, VOCAB, weights).expect("Invalid model weights");
+//!         EMBEDDER.with(|e| *e.borrow_mut() = Some(OnChainEmbedder::new(Box::new(model))));
+//!     }
+//! }
+//! ```
+//!
+//! Inference counts against the instruction limit of the calling message,
+//! so keep batches small, e.g. with [`AdaptiveBatcher`](super::batching::AdaptiveBatcher).
+//!
+//! [`ContragMemory::ModelWeights`]: crate::storage::memory::ContragMemory::ModelWeights
+
+use ic_stable_structures::Memory;
+use sha2::{Digest, Sha256};
+use crate::embedders::Embedder;
+use crate::embedders::validation::validate_embeddings;
+use crate::error::{ContragError, Result};
+use crate::storage::memory::{blob_len, read_blob_at, write_blob_at, write_blob_chunk};
+use crate::types::ConnectionTestResult;
+use crate::vector_store::l2_normalize;
+
+/// A sentence-embedding model that runs in the canister
+pub trait EmbeddingModel: Send + Sync {
+    /// Model name, reported in connection tests
+    fn name(&self) -> &str;
+
+    /// Length of the embeddings the model produces
+    fn dimensions(&self) -> usize;
+
+    /// Embed `texts`, one embedding per text in order
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// [`Embedder`] backed by an in-canister [`EmbeddingModel`]
+pub struct OnChainEmbedder {
+    model: Box<dyn EmbeddingModel>,
+    normalize: bool,
+}
+
+impl OnChainEmbedder {
+    /// Create an embedder for `model`; embeddings are L2-normalized
+    pub fn new(model: Box<dyn EmbeddingModel>) -> Self {
+        Self { model, normalize: true }
+    }
+
+    /// Keep the model's embeddings as they are, for models that already
+    /// normalize their output
+    pub fn without_normalization(mut self) -> Self {
+        self.normalize = false;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl Embedder for OnChainEmbedder {
+    fn name(&self) -> &str {
+        "onchain"
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut embeddings = self.model.embed(&texts)?;
+        if self.normalize {
+            embeddings.iter_mut().for_each(|e| l2_normalize(e));
+        }
+
+        validate_embeddings("onchain", &embeddings, texts.len(), self.model.dimensions())?;
+        Ok(embeddings)
+    }
+
+    fn dimensions(&self) -> usize {
+        self.model.dimensions()
+    }
+
+    async fn test_connection(&self) -> Result<ConnectionTestResult> {
+        let start = ic_cdk::api::time();
+
+        match self.embed(vec!["test connection".to_string()]).await {
+            Ok(_) => {
+                let latency = (ic_cdk::api::time() - start) / 1_000_000; // Convert to ms
+                Ok(ConnectionTestResult {
+                    plugin: self.name().to_string(),
+                    connected: true,
+                    latency: Some(latency),
+                    error: None,
+                    details: Some(format!(
+                        "model: {}, dimensions: {}",
+                        self.model.name(),
+                        self.model.dimensions()
+                    )),
+                })
+            }
+            Err(e) => Ok(ConnectionTestResult {
+                plugin: self.name().to_string(),
+                connected: false,
+                latency: None,
+                error: Some(e.to_string()),
+                details: None,
+            }),
+        }
+    }
+}
+
+/// Bytes of the weights checksum stored ahead of the weights, zeroed
+/// until [`finish_weights_upload`] verifies the upload
+const CHECKSUM_BYTES: u64 = 32;
+
+/// Bytes hashed per read when verifying the weights
+const HASH_WINDOW: usize = 64 * 1024;
+
+/// Write one chunk of model weights at byte `offset`
+///
+/// Chunks must arrive in order: `offset` is either 0, which starts a new
+/// upload, or the number of bytes uploaded so far. The weights can't be
+/// loaded until [`finish_weights_upload`] has checked them.
+pub fn upload_weights_chunk(memory: &impl Memory, offset: u64, chunk: &[u8]) -> Result<()> {
+    if offset == 0 {
+        write_blob_chunk(memory, 0, &[0; CHECKSUM_BYTES as usize])?;
+    } else {
+        let uploaded = uploaded_len(memory)?;
+        if offset != uploaded {
+            return Err(ContragError::StorageError(format!(
+                "Weights chunk at offset {} does not follow the {} bytes uploaded so far",
+                offset, uploaded
+            )));
+        }
+        // A new chunk invalidates an earlier finish
+        write_blob_at(memory, 0, &[0; CHECKSUM_BYTES as usize])?;
+    }
+    write_blob_chunk(memory, CHECKSUM_BYTES + offset, chunk)
+}
+
+/// Complete an upload by checking the weights against the length and
+/// SHA-256 digest of the file they came from
+pub fn finish_weights_upload(memory: &impl Memory, len: u64, sha256: [u8; 32]) -> Result<()> {
+    let uploaded = uploaded_len(memory)?;
+    if uploaded != len {
+        return Err(ContragError::StorageError(format!(
+            "Uploaded {} bytes of weights, expected {}",
+            uploaded, len
+        )));
+    }
+
+    let mut hasher = Sha256::new();
+    let mut window = vec![0; HASH_WINDOW];
+    let mut offset = 0;
+    while offset < len {
+        let n = HASH_WINDOW.min((len - offset) as usize);
+        read_blob_at(memory, CHECKSUM_BYTES + offset, &mut window[..n])?;
+        hasher.update(&window[..n]);
+        offset += n as u64;
+    }
+
+    let digest: [u8; 32] = hasher.finalize().into();
+    if digest != sha256 {
+        return Err(ContragError::StorageError(format!(
+            "Weights checksum {} does not match the expected {}",
+            hex::encode(digest),
+            hex::encode(sha256)
+        )));
+    }
+    write_blob_at(memory, 0, &digest)
+}
+
+/// Read the uploaded model weights; `None` unless an upload was finished
+pub fn load_weights(memory: &impl Memory) -> Result<Option<Vec<u8>>> {
+    if blob_len(memory)? < CHECKSUM_BYTES {
+        return Ok(None);
+    }
+    let mut checksum = [0; CHECKSUM_BYTES as usize];
+    read_blob_at(memory, 0, &mut checksum)?;
+    if checksum == [0; CHECKSUM_BYTES as usize] {
+        return Ok(None);
+    }
+
+    let mut weights = vec![0; uploaded_len(memory)? as usize];
+    read_blob_at(memory, CHECKSUM_BYTES, &mut weights)?;
+    Ok(Some(weights))
+}
+
+/// Number of weight bytes uploaded so far
+fn uploaded_len(memory: &impl Memory) -> Result<u64> {
+    Ok(blob_len(memory)?.saturating_sub(CHECKSUM_BYTES))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::DefaultMemoryImpl;
+
+    /// Counts letters a and b
+    struct LetterCounts;
+
+    impl EmbeddingModel for LetterCounts {
+        fn name(&self) -> &str {
+            "letters"
+        }
+
+        fn dimensions(&self) -> usize {
+            2
+        }
+
+        fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|t| vec![t.matches('a').count() as f32, t.matches('b').count() as f32])
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embed_and_weights_upload() {
+        let embedder = OnChainEmbedder::new(Box::new(LetterCounts));
+        let embeddings = embedder.embed(vec!["aaa".to_string(), "ab".to_string()]).await.unwrap();
+        assert_eq!(embeddings[0], vec![1.0, 0.0]);
+        assert!((embeddings[1][0] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+
+        let memory = DefaultMemoryImpl::default();
+        assert_eq!(load_weights(&memory).unwrap(), None);
+        upload_weights_chunk(&memory, 0, b"wei").unwrap();
+        assert!(upload_weights_chunk(&memory, 5, b"xx").is_err());
+        upload_weights_chunk(&memory, 3, b"ghts").unwrap();
+        assert_eq!(load_weights(&memory).unwrap(), None);
+
+        let sha256: [u8; 32] = Sha256::digest(b"weights").into();
+        assert!(finish_weights_upload(&memory, 8, sha256).is_err());
+        assert!(finish_weights_upload(&memory, 7, [1; 32]).is_err());
+        finish_weights_upload(&memory, 7, sha256).unwrap();
+        assert_eq!(load_weights(&memory).unwrap().unwrap(), b"weights");
+
+        // Restarting the upload unloads the old weights
+        upload_weights_chunk(&memory, 0, b"new").unwrap();
+        assert_eq!(load_weights(&memory).unwrap(), None);
+    }
+}
@@ -1,14 +1,22 @@
 use serde::{Deserialize, Serialize};
-use crate::embedders::{Embedder, http_client::HttpClient};
+use crate::config::{AzureOpenAIConfig, EmbedderConfigDef, OutcallConfig};
+use crate::embedders::{Embedder, http_client::{HttpClient, RequestClass}};
+use crate::embedders::provider_error::{error_in_success_body, parse_openai_error};
+use crate::embedders::validation::validate_embeddings;
 use crate::error::{ContragError, Result};
 use crate::types::ConnectionTestResult;
 
 /// OpenAI embedder using HTTP outcalls
+///
+/// Talks to api.openai.com by default, or to an Azure OpenAI deployment
+/// when built [`with_azure`](Self::with_azure).
 pub struct OpenAIEmbedder {
     api_key: String,
     model: String,
     dimensions: usize,
+    target_dimensions: Option<usize>,
     api_endpoint: String,
+    azure: Option<AzureOpenAIConfig>,
     http_client: HttpClient,
 }
 
@@ -26,62 +34,152 @@ impl OpenAIEmbedder {
             api_key,
             model,
             dimensions,
+            target_dimensions: None,
             api_endpoint: "https://api.openai.com/v1/embeddings".to_string(),
+            azure: None,
             http_client: HttpClient::new(),
         }
     }
 
+    /// Build from an embedder config with `provider = "openai"` or
+    /// `"azure_openai"`
+    ///
+    /// The config's `dimensions` replaces the size derived from the model
+    /// name, and `target_dimensions`, when set, is both requested from the
+    /// API and reported by `dimensions()`, so the embedder always agrees
+    /// with what the vector store was configured for.
+    pub fn from_config(config: &EmbedderConfigDef, outcalls: &OutcallConfig, api_key: String) -> Self {
+        let mut embedder = Self::new(api_key, config.model.clone()).with_http_client(HttpClient::from_config(config, outcalls));
+        embedder.dimensions = config.dimensions;
+
+        if let Some(azure) = &config.azure {
+            embedder = embedder.with_azure(azure.clone());
+        }
+        if let Some(endpoint) = &config.api_endpoint {
+            embedder = embedder.with_endpoint(endpoint.clone());
+        }
+        if let Some(dimensions) = config.target_dimensions {
+            embedder = embedder.with_target_dimensions(dimensions);
+        }
+        embedder
+    }
+
+    /// Use an Azure OpenAI deployment instead of api.openai.com
+    ///
+    /// Requests go to the deployment's URL with the `api-version` query
+    /// parameter and send the key in an `api-key` header. `model` still
+    /// determines the default dimensions. Answer generation needs
+    /// `chat_deployment`.
+    pub fn with_azure(mut self, azure: AzureOpenAIConfig) -> Self {
+        self.api_endpoint = azure_url(&azure, &azure.deployment, "embeddings");
+        self.azure = Some(azure);
+        self
+    }
+
     /// Create with custom API endpoint
     pub fn with_endpoint(mut self, endpoint: String) -> Self {
         self.api_endpoint = endpoint;
         self
     }
-}
 
-#[async_trait::async_trait]
-impl Embedder for OpenAIEmbedder {
-    fn name(&self) -> &str {
-        "openai"
+    /// Request embeddings shortened to `dimensions`
+    ///
+    /// Only the `text-embedding-3` models accept this; OpenAI truncates and
+    /// renormalizes them server-side.
+    pub fn with_target_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = dimensions;
+        self.target_dimensions = Some(dimensions);
+        self
     }
 
-    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
-        if texts.is_empty() {
-            return Ok(vec![]);
-        }
+    /// Use a custom HTTP client (e.g. with compression enabled)
+    pub fn with_http_client(mut self, http_client: HttpClient) -> Self {
+        self.http_client = http_client;
+        self
+    }
 
+    /// Embed texts in one request
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let count = texts.len();
         let request = OpenAIEmbeddingRequest {
             model: self.model.clone(),
-            input: texts,
+            input: texts.to_vec(),
+            dimensions: self.target_dimensions,
         };
 
         let body = serde_json::to_vec(&request)
             .map_err(|e| ContragError::SerializationError(e.to_string()))?;
 
-        let headers = vec![
-            ("Content-Type".to_string(), "application/json".to_string()),
-            ("Authorization".to_string(), format!("Bearer {}", self.api_key)),
-        ];
-
         let response = self
             .http_client
-            .post(self.api_endpoint.clone(), headers, body)
+            .post_as(RequestClass::Embedding, self.api_endpoint.clone(), self.headers(), body)
             .await?;
 
-        if response.status != 200 {
-            let error_text = response.text().unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(ContragError::EmbedderError(format!(
-                "OpenAI API returned status {}: {}",
-                response.status, error_text
-            )));
+        if response.status != 200 || error_in_success_body(&response) {
+            return Err(parse_openai_error(self.name(), &response));
         }
 
-        let embedding_response: OpenAIEmbeddingResponse = response.json()?;
+        let mut embedding_response: OpenAIEmbeddingResponse = response.json()?;
+        embedding_response.data.sort_by_key(|item| item.index);
 
-        Ok(embedding_response
+        let embeddings: Vec<Vec<f32>> = embedding_response
             .data
             .into_iter()
             .map(|item| item.embedding)
-            .collect())
+            .collect();
+
+        validate_embeddings(self.name(), &embeddings, count, self.dimensions)?;
+        Ok(embeddings)
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        let auth = match self.azure {
+            Some(_) => ("api-key".to_string(), self.api_key.clone()),
+            None => ("Authorization".to_string(), format!("Bearer {}", self.api_key)),
+        };
+        vec![("Content-Type".to_string(), "application/json".to_string()), auth]
+    }
+
+    fn chat_url(&self) -> Result<String> {
+        match &self.azure {
+            None => Ok("https://api.openai.com/v1/chat/completions".to_string()),
+            Some(azure) => azure
+                .chat_deployment
+                .as_ref()
+                .map(|deployment| azure_url(azure, deployment, "chat/completions"))
+                .ok_or_else(|| {
+                    ContragError::InvalidConfig("Azure OpenAI generation needs a chat_deployment".to_string())
+                }),
+        }
+    }
+}
+
+/// URL of an Azure OpenAI deployment operation
+fn azure_url(azure: &AzureOpenAIConfig, deployment: &str, operation: &str) -> String {
+    format!(
+        "{}/openai/deployments/{}/{}?api-version={}",
+        azure.endpoint.trim_end_matches('/'),
+        deployment,
+        operation,
+        azure.api_version
+    )
+}
+
+#[async_trait::async_trait]
+impl Embedder for OpenAIEmbedder {
+    fn name(&self) -> &str {
+        match self.azure {
+            Some(_) => "azure_openai",
+            None => "openai",
+        }
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in self.http_client.split_batch(&texts, self.dimensions) {
+            embeddings.extend(self.embed_batch(batch).await?);
+        }
+        Ok(embeddings)
     }
 
     fn dimensions(&self) -> usize {
@@ -139,26 +237,13 @@ impl Embedder for OpenAIEmbedder {
         let body = serde_json::to_vec(&request)
             .map_err(|e| ContragError::SerializationError(e.to_string()))?;
 
-        let headers = vec![
-            ("Content-Type".to_string(), "application/json".to_string()),
-            ("Authorization".to_string(), format!("Bearer {}", self.api_key)),
-        ];
-
         let response = self
             .http_client
-            .post(
-                "https://api.openai.com/v1/chat/completions".to_string(),
-                headers,
-                body,
-            )
+            .post_as(RequestClass::Generation, self.chat_url()?, self.headers(), body)
             .await?;
 
-        if response.status != 200 {
-            let error_text = response.text().unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(ContragError::EmbedderError(format!(
-                "OpenAI API returned status {}: {}",
-                response.status, error_text
-            )));
+        if response.status != 200 || error_in_success_body(&response) {
+            return Err(parse_openai_error(self.name(), &response));
         }
 
         let chat_response: OpenAIChatResponse = response.json()?;
@@ -177,6 +262,8 @@ impl Embedder for OpenAIEmbedder {
 struct OpenAIEmbeddingRequest {
     model: String,
     input: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
 }
 
 #[derive(Deserialize)]
@@ -186,6 +273,8 @@ struct OpenAIEmbeddingResponse {
 
 #[derive(Deserialize)]
 struct EmbeddingData {
+    #[serde(default)]
+    index: usize,
     embedding: Vec<f32>,
 }
 
@@ -212,3 +301,44 @@ struct OpenAIChatResponse {
 struct ChatChoice {
     message: ChatMessage,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_azure_urls_and_auth() {
+        let azure = AzureOpenAIConfig {
+            endpoint: "https://acme.openai.azure.com/".to_string(),
+            deployment: "embeddings".to_string(),
+            ..AzureOpenAIConfig::default()
+        };
+        let embedder = OpenAIEmbedder::new("key".to_string(), "text-embedding-3-small".to_string()).with_azure(azure);
+
+        assert_eq!(
+            embedder.api_endpoint,
+            "https://acme.openai.azure.com/openai/deployments/embeddings/embeddings?api-version=2024-02-01"
+        );
+        assert_eq!(embedder.name(), "azure_openai");
+        assert!(embedder.headers().contains(&("api-key".to_string(), "key".to_string())));
+        assert!(matches!(embedder.chat_url(), Err(ContragError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_from_config_target_dimensions() {
+        let mut config = crate::config::create_default_config().embedder;
+        config.model = "text-embedding-3-large".to_string();
+        config.dimensions = 3072;
+        config.target_dimensions = Some(256);
+
+        let embedder = OpenAIEmbedder::from_config(&config, &OutcallConfig::default(), "key".to_string());
+        assert_eq!(embedder.dimensions(), 256);
+
+        let request = OpenAIEmbeddingRequest {
+            model: embedder.model.clone(),
+            input: vec!["text".to_string()],
+            dimensions: embedder.target_dimensions,
+        };
+        assert_eq!(serde_json::to_value(&request).unwrap()["dimensions"], 256);
+    }
+}
@@ -0,0 +1,266 @@
+use serde::{Deserialize, Serialize};
+use crate::config::{EmbedderConfigDef, OutcallConfig};
+use crate::embedders::{Embedder, http_client::{HttpClient, RequestClass}};
+use crate::embedders::provider_error::{error_in_success_body, parse_openai_error};
+use crate::embedders::validation::validate_embeddings;
+use crate::error::{ContragError, Result};
+use crate::types::ConnectionTestResult;
+
+/// Embedder for any provider exposing an OpenAI-compatible `/embeddings` API
+///
+/// Works with Together, Fireworks, LM Studio, vLLM and similar servers. The
+/// base URL, auth header, model and dimensions all come from configuration,
+/// so new providers don't need a dedicated module.
+pub struct GenericOpenAICompatEmbedder {
+    name: String,
+    api_key: Option<String>,
+    auth_header: String,
+    model: String,
+    dimensions: usize,
+    target_dimensions: Option<usize>,
+    base_url: String,
+    http_client: HttpClient,
+}
+
+impl GenericOpenAICompatEmbedder {
+    /// Create an embedder for `base_url` (e.g. "https://api.together.xyz/v1")
+    pub fn new(base_url: String, model: String, dimensions: usize) -> Self {
+        Self {
+            name: "openai_compatible".to_string(),
+            api_key: None,
+            auth_header: "Authorization".to_string(),
+            model,
+            dimensions,
+            target_dimensions: None,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http_client: HttpClient::new(),
+        }
+    }
+
+    /// Build from an embedder config; `api_endpoint` is required
+    pub fn from_config(config: &EmbedderConfigDef, outcalls: &OutcallConfig, api_key: Option<String>) -> Result<Self> {
+        let base_url = config.api_endpoint.clone().ok_or_else(|| {
+            ContragError::InvalidConfig(format!(
+                "Embedder provider '{}' requires api_endpoint",
+                config.provider
+            ))
+        })?;
+
+        let mut embedder = Self::new(base_url, config.model.clone(), config.dimensions)
+            .with_name(config.provider.clone())
+            .with_http_client(HttpClient::from_config(config, outcalls));
+
+        if let Some(header) = &config.auth_header {
+            embedder = embedder.with_auth_header(header.clone());
+        }
+        if let Some(key) = api_key {
+            embedder = embedder.with_api_key(key);
+        }
+        if let Some(dimensions) = config.target_dimensions {
+            embedder = embedder.with_target_dimensions(dimensions);
+        }
+
+        Ok(embedder)
+    }
+
+    /// Set the API key (omit for local servers without auth)
+    pub fn with_api_key(mut self, api_key: String) -> Self {
+        self.api_key = Some(api_key);
+        self
+    }
+
+    /// Send the key in a custom header instead of `Authorization: Bearer`
+    ///
+    /// For the `Authorization` header the key is sent as a Bearer token; any
+    /// other header receives the raw key.
+    pub fn with_auth_header(mut self, header: String) -> Self {
+        self.auth_header = header;
+        self
+    }
+
+    /// Send `dimensions` with each request, for servers that can shorten
+    /// embeddings
+    pub fn with_target_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = dimensions;
+        self.target_dimensions = Some(dimensions);
+        self
+    }
+
+    /// Name reported by `name()` and in connection tests
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Use a custom HTTP client (e.g. with compression enabled)
+    pub fn with_http_client(mut self, http_client: HttpClient) -> Self {
+        self.http_client = http_client;
+        self
+    }
+
+    /// Embed texts in one request
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let count = texts.len();
+        let request = CompatEmbeddingRequest {
+            model: self.model.clone(),
+            input: texts.to_vec(),
+            dimensions: self.target_dimensions,
+        };
+
+        let body = serde_json::to_vec(&request)
+            .map_err(|e| ContragError::SerializationError(e.to_string()))?;
+
+        let response = self
+            .http_client
+            .post_as(RequestClass::Embedding, format!("{}/embeddings", self.base_url), self.headers(), body)
+            .await?;
+
+        if response.status != 200 || error_in_success_body(&response) {
+            return Err(parse_openai_error(&self.name, &response));
+        }
+
+        let mut embedding_response: CompatEmbeddingResponse = response.json()?;
+
+        // Not every server keeps input order, but all of them report the index
+        embedding_response.data.sort_by_key(|item| item.index);
+
+        let embeddings: Vec<Vec<f32>> = embedding_response
+            .data
+            .into_iter()
+            .map(|item| item.embedding)
+            .collect();
+
+        validate_embeddings(&self.name, &embeddings, count, self.dimensions)?;
+        Ok(embeddings)
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        let mut headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+
+        if let Some(key) = &self.api_key {
+            let value = if self.auth_header.eq_ignore_ascii_case("authorization") {
+                format!("Bearer {}", key)
+            } else {
+                key.clone()
+            };
+            headers.push((self.auth_header.clone(), value));
+        }
+
+        headers
+    }
+}
+
+#[async_trait::async_trait]
+impl Embedder for GenericOpenAICompatEmbedder {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in self.http_client.split_batch(&texts, self.dimensions) {
+            embeddings.extend(self.embed_batch(batch).await?);
+        }
+        Ok(embeddings)
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    async fn test_connection(&self) -> Result<ConnectionTestResult> {
+        let start = ic_cdk::api::time();
+
+        match self.embed(vec!["test connection".to_string()]).await {
+            Ok(_) => {
+                let latency = (ic_cdk::api::time() - start) / 1_000_000; // Convert to ms
+                Ok(ConnectionTestResult {
+                    plugin: self.name().to_string(),
+                    connected: true,
+                    latency: Some(latency),
+                    error: None,
+                    details: Some(format!(
+                        "endpoint: {}, model: {}, dimensions: {}",
+                        self.base_url, self.model, self.dimensions
+                    )),
+                })
+            }
+            Err(e) => Ok(ConnectionTestResult {
+                plugin: self.name().to_string(),
+                connected: false,
+                latency: None,
+                error: Some(e.to_string()),
+                details: None,
+            }),
+        }
+    }
+}
+
+// Request/Response types for OpenAI-compatible APIs
+
+#[derive(Serialize)]
+struct CompatEmbeddingRequest {
+    model: String,
+    input: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct CompatEmbeddingResponse {
+    data: Vec<CompatEmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct CompatEmbeddingData {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_and_response_shape() {
+        let mut request = CompatEmbeddingRequest {
+            model: "BAAI/bge-base-en-v1.5".to_string(),
+            input: vec!["refund policy".to_string()],
+            dimensions: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({ "model": "BAAI/bge-base-en-v1.5", "input": ["refund policy"] })
+        );
+        request.dimensions = Some(256);
+        assert_eq!(serde_json::to_value(&request).unwrap()["dimensions"], 256);
+
+        // Servers that omit the index default to input order
+        let response: CompatEmbeddingResponse =
+            serde_json::from_str(r#"{"object":"list","data":[{"embedding":[0.1]},{"index":3,"embedding":[0.2]}]}"#).unwrap();
+        assert_eq!(response.data[0].index, 0);
+        assert_eq!(response.data[1].index, 3);
+    }
+
+    #[test]
+    fn test_from_config_name_and_auth_header() {
+        let mut config = crate::config::create_default_config().embedder;
+        config.provider = "together".to_string();
+        assert!(GenericOpenAICompatEmbedder::from_config(&config, &OutcallConfig::default(), None).is_err());
+
+        config.api_endpoint = Some("https://api.together.xyz/v1/".to_string());
+        config.target_dimensions = Some(256);
+        let embedder =
+            GenericOpenAICompatEmbedder::from_config(&config, &OutcallConfig::default(), Some("key".to_string())).unwrap();
+        assert_eq!(embedder.name(), "together");
+        assert_eq!(embedder.base_url, "https://api.together.xyz/v1");
+        assert_eq!(embedder.dimensions(), 256);
+        assert!(embedder.headers().contains(&("Authorization".to_string(), "Bearer key".to_string())));
+
+        config.auth_header = Some("x-api-key".to_string());
+        let embedder =
+            GenericOpenAICompatEmbedder::from_config(&config, &OutcallConfig::default(), Some("key".to_string())).unwrap();
+        assert!(embedder.headers().contains(&("x-api-key".to_string(), "key".to_string())));
+    }
+}
@@ -0,0 +1,320 @@
+use serde_json::Value;
+use crate::embedders::http_client::HttpOutcallResponse;
+use crate::error::{ContragError, ProviderError, ProviderErrorKind};
+
+/// Maximum length of a raw body kept in an error message
+const MAX_RAW_MESSAGE: usize = 500;
+
+/// Parse an OpenAI-style error response
+///
+/// Expects `{"error": {"message", "type", "code"}}` but falls back to the raw
+/// body for servers that return something else. Used by all OpenAI-compatible
+/// embedders.
+pub fn parse_openai_error(provider: &str, response: &HttpOutcallResponse) -> ContragError {
+    let body: Option<Value> = serde_json::from_slice(&response.body).ok();
+    let error = body.as_ref().and_then(|b| b.get("error"));
+
+    let message = error
+        .and_then(|e| e.get("message").or(Some(e)))
+        .and_then(|m| m.as_str())
+        .map(|m| m.to_string())
+        .unwrap_or_else(|| raw_message(response));
+
+    let code = error
+        .and_then(|e| e.get("code").filter(|c| !c.is_null()).or_else(|| e.get("type")))
+        .and_then(|c| c.as_str().map(|s| s.to_string()).or_else(|| Some(c.to_string())));
+
+    let kind = match code.as_deref() {
+        Some("insufficient_quota") => ProviderErrorKind::QuotaExceeded,
+        Some("model_not_found") => ProviderErrorKind::InvalidModel,
+        Some("context_length_exceeded") => ProviderErrorKind::ContextLengthExceeded,
+        Some("invalid_api_key") => ProviderErrorKind::Authentication,
+        _ => kind_from_status(response.status),
+    };
+
+    ContragError::from(ProviderError {
+        provider: provider.to_string(),
+        kind,
+        status: response.status,
+        retry_after: retry_after_header(response),
+        code,
+        message,
+    })
+}
+
+/// Parse a Google API error response
+///
+/// Expects `{"error": {"code", "message", "status", "details"}}`, including
+/// the `RetryInfo` detail Gemini attaches to rate-limit errors.
+pub fn parse_gemini_error(response: &HttpOutcallResponse) -> ContragError {
+    let body: Option<Value> = serde_json::from_slice(&response.body).ok();
+    let error = body.as_ref().and_then(|b| b.get("error"));
+
+    let message = error
+        .and_then(|e| e.get("message"))
+        .and_then(|m| m.as_str())
+        .map(|m| m.to_string())
+        .unwrap_or_else(|| raw_message(response));
+
+    let code = error
+        .and_then(|e| e.get("status"))
+        .and_then(|s| s.as_str())
+        .map(|s| s.to_string());
+
+    let kind = match code.as_deref() {
+        Some("RESOURCE_EXHAUSTED") => ProviderErrorKind::RateLimited,
+        Some("UNAUTHENTICATED") | Some("PERMISSION_DENIED") => ProviderErrorKind::Authentication,
+        Some("NOT_FOUND") => ProviderErrorKind::InvalidModel,
+        Some("INVALID_ARGUMENT") | Some("FAILED_PRECONDITION") => ProviderErrorKind::InvalidRequest,
+        Some("INTERNAL") | Some("UNAVAILABLE") | Some("DEADLINE_EXCEEDED") => {
+            ProviderErrorKind::ServerError
+        }
+        _ => kind_from_status(response.status),
+    };
+
+    let retry_after = error
+        .and_then(|e| e.get("details"))
+        .and_then(|d| d.as_array())
+        .and_then(|details| {
+            details
+                .iter()
+                .filter_map(|d| d.get("retryDelay").and_then(|r| r.as_str()))
+                .find_map(parse_duration_secs)
+        })
+        .or_else(|| retry_after_header(response));
+
+    ContragError::from(ProviderError {
+        provider: "gemini".to_string(),
+        kind,
+        status: response.status,
+        retry_after,
+        code,
+        message,
+    })
+}
+
+/// Parse a Cohere error response
+///
+/// Cohere answers errors with `{"message": ...}` and no error code, so the
+/// kind follows from the status.
+pub fn parse_cohere_error(response: &HttpOutcallResponse) -> ContragError {
+    let body: Option<Value> = serde_json::from_slice(&response.body).ok();
+    let message = body
+        .as_ref()
+        .and_then(|b| b.get("message"))
+        .and_then(|m| m.as_str())
+        .map(|m| m.to_string())
+        .unwrap_or_else(|| raw_message(response));
+
+    ContragError::from(ProviderError {
+        provider: "cohere".to_string(),
+        kind: kind_from_status(response.status),
+        status: response.status,
+        retry_after: retry_after_header(response),
+        code: None,
+        message,
+    })
+}
+
+/// Parse a Jina error response
+///
+/// Jina answers errors with `{"detail": ...}`, where `detail` is a message
+/// or a list of validation errors.
+pub fn parse_jina_error(response: &HttpOutcallResponse) -> ContragError {
+    let body: Option<Value> = serde_json::from_slice(&response.body).ok();
+    let message = body
+        .as_ref()
+        .and_then(|b| b.get("detail"))
+        .map(|d| d.as_str().map(|m| m.to_string()).unwrap_or_else(|| d.to_string()))
+        .unwrap_or_else(|| raw_message(response));
+
+    ContragError::from(ProviderError {
+        provider: "jina".to_string(),
+        kind: kind_from_status(response.status),
+        status: response.status,
+        retry_after: retry_after_header(response),
+        code: None,
+        message,
+    })
+}
+
+/// Parse an Anthropic error response
+///
+/// Expects `{"type": "error", "error": {"type", "message"}}`; `overloaded_error`
+/// (status 529) is treated as a retryable server error.
+pub fn parse_anthropic_error(response: &HttpOutcallResponse) -> ContragError {
+    let body: Option<Value> = serde_json::from_slice(&response.body).ok();
+    let error = body.as_ref().and_then(|b| b.get("error"));
+
+    let message = error
+        .and_then(|e| e.get("message"))
+        .and_then(|m| m.as_str())
+        .map(|m| m.to_string())
+        .unwrap_or_else(|| raw_message(response));
+
+    let code = error
+        .and_then(|e| e.get("type"))
+        .and_then(|t| t.as_str())
+        .map(|t| t.to_string());
+
+    let kind = match code.as_deref() {
+        Some("rate_limit_error") => ProviderErrorKind::RateLimited,
+        Some("authentication_error") | Some("permission_error") => ProviderErrorKind::Authentication,
+        Some("not_found_error") => ProviderErrorKind::InvalidModel,
+        Some("request_too_large") => ProviderErrorKind::PayloadTooLarge,
+        Some("invalid_request_error") => ProviderErrorKind::InvalidRequest,
+        Some("api_error") | Some("overloaded_error") => ProviderErrorKind::ServerError,
+        _ => kind_from_status(response.status),
+    };
+
+    ContragError::from(ProviderError {
+        provider: "anthropic".to_string(),
+        kind,
+        status: response.status,
+        retry_after: retry_after_header(response),
+        code,
+        message,
+    })
+}
+
+/// Detect an error object in a response that was reported as successful
+///
+/// Some OpenAI-compatible servers answer with status 200 and an `error` body.
+pub fn error_in_success_body(response: &HttpOutcallResponse) -> bool {
+    serde_json::from_slice::<Value>(&response.body)
+        .map(|b| b.get("error").map_or(false, |e| !e.is_null()))
+        .unwrap_or(false)
+}
+
+fn kind_from_status(status: u16) -> ProviderErrorKind {
+    match status {
+        400 | 422 => ProviderErrorKind::InvalidRequest,
+        401 | 403 => ProviderErrorKind::Authentication,
+        404 => ProviderErrorKind::InvalidModel,
+        413 => ProviderErrorKind::PayloadTooLarge,
+        429 => ProviderErrorKind::RateLimited,
+        500..=599 => ProviderErrorKind::ServerError,
+        _ => ProviderErrorKind::Unknown,
+    }
+}
+
+fn retry_after_header(response: &HttpOutcallResponse) -> Option<u64> {
+    response
+        .headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("retry-after"))
+        .and_then(|(_, value)| value.trim().parse::<u64>().ok())
+}
+
+/// Parse durations like "30s" or "1.5s" into whole seconds (rounded up)
+fn parse_duration_secs(value: &str) -> Option<u64> {
+    let secs: f64 = value.trim().strip_suffix('s')?.parse().ok()?;
+    Some(secs.ceil() as u64)
+}
+
+fn raw_message(response: &HttpOutcallResponse) -> String {
+    let text = String::from_utf8_lossy(&response.body);
+    if text.trim().is_empty() {
+        "Unknown error".to_string()
+    } else {
+        text.chars().take(MAX_RAW_MESSAGE).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::EmbedderFailure;
+
+    fn response(status: u16, headers: Vec<(&str, &str)>, body: &str) -> HttpOutcallResponse {
+        HttpOutcallResponse {
+            status,
+            headers: headers
+                .into_iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_parse_openai_rate_limit() {
+        let resp = response(
+            429,
+            vec![("Retry-After", "20")],
+            r#"{"error": {"message": "Rate limit reached", "type": "requests", "code": "rate_limit_exceeded"}}"#,
+        );
+
+        match parse_openai_error("openai", &resp) {
+            ContragError::EmbedderError(EmbedderFailure { provider: Some(e), .. }) => {
+                assert_eq!(e.kind, ProviderErrorKind::RateLimited);
+                assert_eq!(e.retry_after, Some(20));
+                assert_eq!(e.code.as_deref(), Some("rate_limit_exceeded"));
+                assert!(e.is_retryable());
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_gemini_retry_info() {
+        let resp = response(
+            429,
+            vec![],
+            r#"{"error": {"code": 429, "message": "Quota", "status": "RESOURCE_EXHAUSTED",
+                "details": [{"@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "1.5s"}]}}"#,
+        );
+
+        match parse_gemini_error(&resp) {
+            ContragError::EmbedderError(EmbedderFailure { provider: Some(e), .. }) => {
+                assert_eq!(e.kind, ProviderErrorKind::RateLimited);
+                assert_eq!(e.retry_after, Some(2));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_non_json_body_falls_back_to_text() {
+        let resp = response(502, vec![], "Bad Gateway");
+
+        match parse_openai_error("openai", &resp) {
+            ContragError::EmbedderError(EmbedderFailure { provider: Some(e), .. }) => {
+                assert_eq!(e.kind, ProviderErrorKind::ServerError);
+                assert_eq!(e.message, "Bad Gateway");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_jina_detail() {
+        let resp = response(401, vec![], r#"{"detail": "Invalid API key"}"#);
+
+        match parse_jina_error(&resp) {
+            ContragError::EmbedderError(EmbedderFailure { provider: Some(e), .. }) => {
+                assert_eq!(e.kind, ProviderErrorKind::Authentication);
+                assert_eq!(e.message, "Invalid API key");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_anthropic_overloaded() {
+        let resp = response(
+            529,
+            vec![],
+            r#"{"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}"#,
+        );
+
+        match parse_anthropic_error(&resp) {
+            ContragError::EmbedderError(EmbedderFailure { provider: Some(e), .. }) => {
+                assert_eq!(e.kind, ProviderErrorKind::ServerError);
+                assert_eq!(e.code.as_deref(), Some("overloaded_error"));
+                assert!(e.is_retryable());
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
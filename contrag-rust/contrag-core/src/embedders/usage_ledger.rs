@@ -0,0 +1,275 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use candid::CandidType;
+use ic_stable_structures::Memory;
+use serde::{Deserialize, Serialize};
+use crate::config::EmbedderConfigDef;
+use crate::embedders::Embedder;
+use crate::error::{ContragError, ProviderError, ProviderErrorKind, Result};
+use crate::storage::memory::{read_blob, write_blob};
+use crate::storage::migrations::{encode_versioned, Migrator, StorageComponent};
+use crate::types::ConnectionTestResult;
+use crate::utils::{estimate_tokens, get_timestamp};
+
+const NANOS_PER_MINUTE: u64 = 60_000_000_000;
+
+/// Minutes of usage kept per key
+const HISTORY_MINUTES: usize = 60;
+
+/// Provider rate limits for one ledger key
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+#[serde(default)]
+pub struct RateLimit {
+    /// Requests allowed per minute; `None` means unlimited
+    pub requests_per_minute: Option<u32>,
+
+    /// Estimated input tokens allowed per minute; `None` means unlimited
+    pub tokens_per_minute: Option<u64>,
+}
+
+/// Requests and tokens sent under one key during one minute
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CandidType)]
+pub struct MinuteUsage {
+    /// Minutes since the epoch
+    pub minute: u64,
+    pub requests: u32,
+    pub tokens: u64,
+}
+
+#[derive(Serialize, Deserialize, CandidType)]
+struct LedgerSnapshot {
+    keys: Vec<(String, Vec<MinuteUsage>)>,
+}
+
+thread_local! {
+    static LEDGER: RefCell<BTreeMap<String, Vec<MinuteUsage>>> = RefCell::new(BTreeMap::new());
+}
+
+/// Write the ledger to `memory` (the [`ContragMemory::UsageLedger`] region)
+/// in pre_upgrade, so an upgrade mid-minute doesn't reset the budgets
+///
+/// [`ContragMemory::UsageLedger`]: crate::storage::memory::ContragMemory::UsageLedger
+pub fn persist_usage(memory: &impl Memory) -> Result<()> {
+    let snapshot = LEDGER.with(|l| LedgerSnapshot {
+        keys: l.borrow().iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+    });
+    let payload = candid::encode_one(&snapshot)
+        .map_err(|e| ContragError::StorageError(format!("Failed to encode usage ledger: {}", e)))?;
+    write_blob(memory, &encode_versioned(StorageComponent::UsageLedger, &payload))
+}
+
+/// Load the ledger [`persist_usage`] wrote to `memory` in post_upgrade;
+/// empty memory loads nothing
+pub fn restore_usage(memory: &impl Memory) -> Result<()> {
+    let Some(raw) = read_blob(memory)? else {
+        return Ok(());
+    };
+    let payload = Migrator::new().load(StorageComponent::UsageLedger, &raw)?;
+    let snapshot: LedgerSnapshot = candid::decode_one(&payload)
+        .map_err(|e| ContragError::StorageError(format!("Failed to decode usage ledger: {}", e)))?;
+
+    LEDGER.with(|l| l.borrow_mut().extend(snapshot.keys));
+    Ok(())
+}
+
+/// Recorded usage for a key, oldest minute first
+pub fn usage(key: &str) -> Vec<MinuteUsage> {
+    LEDGER.with(|l| l.borrow().get(key).cloned().unwrap_or_default())
+}
+
+/// Record usage that bypassed [`try_reserve`], e.g. generation calls
+pub fn record(key: &str, now: u64, requests: u32, tokens: u64) {
+    LEDGER.with(|l| {
+        let mut ledger = l.borrow_mut();
+        let minute = current_minute(ledger.entry(key.to_string()).or_default(), now);
+        minute.requests = minute.requests.saturating_add(requests);
+        minute.tokens = minute.tokens.saturating_add(tokens);
+    });
+}
+
+/// Reserve capacity for one request of `tokens` tokens at `now`
+///
+/// Records the request and returns `Ok` when it fits in the current minute,
+/// otherwise returns the nanoseconds until the next minute starts. A request
+/// larger than the whole token budget is let through on an otherwise idle
+/// minute, so it can never wait forever.
+pub fn try_reserve(key: &str, limit: &RateLimit, now: u64, tokens: u64) -> std::result::Result<(), u64> {
+    LEDGER.with(|l| {
+        let mut ledger = l.borrow_mut();
+        let minute = current_minute(ledger.entry(key.to_string()).or_default(), now);
+
+        let requests_full = limit
+            .requests_per_minute
+            .is_some_and(|rpm| minute.requests >= rpm);
+        let tokens_full = limit
+            .tokens_per_minute
+            .is_some_and(|tpm| minute.requests > 0 && minute.tokens.saturating_add(tokens) > tpm);
+
+        if requests_full || tokens_full {
+            return Err(NANOS_PER_MINUTE - now % NANOS_PER_MINUTE);
+        }
+
+        minute.requests += 1;
+        minute.tokens = minute.tokens.saturating_add(tokens);
+        Ok(())
+    })
+}
+
+/// Bucket for the minute containing `now`, pruning old history
+fn current_minute(history: &mut Vec<MinuteUsage>, now: u64) -> &mut MinuteUsage {
+    let minute = now / NANOS_PER_MINUTE;
+
+    if history.last().map(|m| m.minute) != Some(minute) {
+        history.push(MinuteUsage {
+            minute,
+            requests: 0,
+            tokens: 0,
+        });
+        if history.len() > HISTORY_MINUTES {
+            history.drain(..history.len() - HISTORY_MINUTES);
+        }
+    }
+
+    history.last_mut().expect("bucket was just ensured")
+}
+
+/// Embedder wrapper that keeps requests within the configured rate limits
+///
+/// Consults the shared usage ledger under a key (the provider name unless set
+/// with [`RateLimitedEmbedder::with_key`]). A request that would exceed the
+/// RPM or TPM of the current minute is not sent; it fails at once with a
+/// rate-limited provider error whose `retry_after` is the time until the
+/// next minute, which the queue treats as retryable. Canister messages
+/// can't block, so nothing waits inside the call.
+pub struct RateLimitedEmbedder<E: Embedder> {
+    embedder: E,
+    limit: RateLimit,
+    key: String,
+}
+
+impl<E: Embedder> RateLimitedEmbedder<E> {
+    pub fn new(embedder: E, limit: RateLimit) -> Self {
+        let key = embedder.name().to_string();
+        Self { embedder, limit, key }
+    }
+
+    /// Apply the `rate_limit` of an embedder config (unlimited when unset)
+    pub fn from_config(embedder: E, config: &EmbedderConfigDef) -> Self {
+        Self::new(embedder, config.rate_limit.clone().unwrap_or_default())
+    }
+
+    /// Share a budget across embedders, e.g. ones using the same API key
+    pub fn with_key(mut self, key: impl Into<String>) -> Self {
+        self.key = key.into();
+        self
+    }
+
+    fn acquire(&self, tokens: u64) -> Result<()> {
+        try_reserve(&self.key, &self.limit, get_timestamp(), tokens).map_err(|wait| {
+            ContragError::from(ProviderError {
+                provider: self.embedder.name().to_string(),
+                kind: ProviderErrorKind::RateLimited,
+                status: 429,
+                retry_after: Some(wait.div_ceil(1_000_000_000)),
+                code: None,
+                message: format!("Local rate limit for '{}' reached", self.key),
+            })
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: Embedder> Embedder for RateLimitedEmbedder<E> {
+    fn name(&self) -> &str {
+        self.embedder.name()
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let tokens = texts.iter().map(|t| estimate_tokens(t) as u64).sum();
+        self.acquire(tokens)?;
+        self.embedder.embed(texts).await
+    }
+
+    async fn embed_queries(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let tokens = texts.iter().map(|t| estimate_tokens(t) as u64).sum();
+        self.acquire(tokens)?;
+        self.embedder.embed_queries(texts).await
+    }
+
+    fn dimensions(&self) -> usize {
+        self.embedder.dimensions()
+    }
+
+    fn embeds_in_context(&self) -> bool {
+        self.embedder.embeds_in_context()
+    }
+
+    async fn test_connection(&self) -> Result<ConnectionTestResult> {
+        self.embedder.test_connection().await
+    }
+
+    async fn generate_with_prompt(&self, text: String, system_prompt: String) -> Result<String> {
+        let tokens = (estimate_tokens(&text) + estimate_tokens(&system_prompt)) as u64;
+        self.acquire(tokens)?;
+        self.embedder.generate_with_prompt(text, system_prompt).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::EmbedderFailure;
+    use crate::embedders::mock::MockEmbedder;
+
+    #[test]
+    fn test_reserve_respects_limits_per_minute() {
+        let limit = RateLimit {
+            requests_per_minute: Some(2),
+            tokens_per_minute: Some(100),
+        };
+        let start = 1_000 * NANOS_PER_MINUTE;
+
+        assert!(try_reserve("rpm", &limit, start, 10).is_ok());
+        assert!(try_reserve("rpm", &limit, start + 1, 10).is_ok());
+        assert_eq!(try_reserve("rpm", &limit, start + 2, 10), Err(NANOS_PER_MINUTE - 2));
+        assert!(try_reserve("rpm", &limit, start + NANOS_PER_MINUTE, 10).is_ok());
+
+        assert!(try_reserve("tpm", &limit, start, 80).is_ok());
+        assert!(try_reserve("tpm", &limit, start, 30).is_err());
+        // Oversized requests still go through on an idle minute
+        assert!(try_reserve("tpm", &limit, start + NANOS_PER_MINUTE, 500).is_ok());
+
+        let history = usage("tpm");
+        assert_eq!(history.len(), 2);
+        assert_eq!((history[0].requests, history[0].tokens), (1, 80));
+        assert_eq!((history[1].requests, history[1].tokens), (1, 500));
+    }
+
+    #[tokio::test]
+    async fn test_over_limit_fails_with_retry_after() {
+        let limit = RateLimit {
+            requests_per_minute: Some(1),
+            tokens_per_minute: None,
+        };
+        let embedder = RateLimitedEmbedder::new(MockEmbedder::new(1), limit)
+            .with_key("retry-after");
+        assert!(embedder.embed(vec!["a".to_string()]).await.is_ok());
+
+        let Err(ContragError::EmbedderError(EmbedderFailure { provider: Some(e), .. })) = embedder.embed(vec!["b".to_string()]).await else {
+            panic!("expected a rate-limited error");
+        };
+        assert_eq!(e.kind, ProviderErrorKind::RateLimited);
+        assert!(e.retry_after.is_some_and(|secs| secs <= 60));
+    }
+
+    #[test]
+    fn test_persist_and_restore_usage() {
+        let memory = ic_stable_structures::DefaultMemoryImpl::default();
+        record("persisted", 5 * NANOS_PER_MINUTE, 3, 30);
+        persist_usage(&memory).unwrap();
+
+        LEDGER.with(|l| l.borrow_mut().clear());
+        restore_usage(&memory).unwrap();
+        assert_eq!(usage("persisted"), vec![MinuteUsage { minute: 5, requests: 3, tokens: 30 }]);
+    }
+}
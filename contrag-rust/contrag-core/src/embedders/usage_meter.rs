@@ -0,0 +1,194 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::config::EmbedderConfigDef;
+use crate::embedders::Embedder;
+use crate::embedders::http_client::outcall_cycles_spent;
+use crate::error::Result;
+use crate::types::ConnectionTestResult;
+use crate::utils::estimate_tokens;
+
+/// Usage of one provider and model since the last reset
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, CandidType)]
+pub struct EmbeddingUsage {
+    pub provider: String,
+    pub model: String,
+    /// Embedding and generation calls made
+    pub requests: u64,
+    /// Estimated input tokens sent
+    pub tokens: u64,
+    /// Cycles spent on HTTP outcalls during those calls
+    pub outcall_cycles: u128,
+    /// Estimated API spend, from the model's price per million tokens
+    pub estimated_usd: f64,
+}
+
+thread_local! {
+    static METER: RefCell<BTreeMap<(String, String), EmbeddingUsage>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Usage of every metered provider and model
+pub fn all_usage() -> Vec<EmbeddingUsage> {
+    METER.with(|m| m.borrow().values().cloned().collect())
+}
+
+/// Clear the usage of every provider and model
+pub fn reset_all_usage() {
+    METER.with(|m| m.borrow_mut().clear());
+}
+
+/// List price in USD per million input tokens of well-known embedding
+/// models; unknown models are metered at no cost
+pub fn default_price(model: &str) -> f64 {
+    match model {
+        "text-embedding-3-small" => 0.02,
+        "text-embedding-3-large" => 0.13,
+        "text-embedding-ada-002" => 0.10,
+        "embed-english-v3.0" | "embed-multilingual-v3.0" => 0.10,
+        "embed-english-light-v3.0" | "embed-multilingual-light-v3.0" => 0.10,
+        "jina-embeddings-v3" => 0.02,
+        _ => 0.0,
+    }
+}
+
+/// Embedder wrapper that meters requests, tokens, outcall cycles and
+/// estimated cost per provider and model
+///
+/// Usage is shared by all wrappers of the same provider and model and kept
+/// until reset. Cycles are the outcall cycles the canister spent while a
+/// call was in flight, so calls overlapping across messages are each
+/// charged for the other's outcalls.
+pub struct MeteredEmbedder<E: Embedder> {
+    embedder: E,
+    model: String,
+    usd_per_million_tokens: f64,
+}
+
+impl<E: Embedder> MeteredEmbedder<E> {
+    /// Meter `embedder`, which uses `model`, at the model's [`default_price`]
+    pub fn new(embedder: E, model: impl Into<String>) -> Self {
+        let model = model.into();
+        let usd_per_million_tokens = default_price(&model);
+        Self {
+            embedder,
+            model,
+            usd_per_million_tokens,
+        }
+    }
+
+    /// Meter the model of an embedder config
+    pub fn from_config(embedder: E, config: &EmbedderConfigDef) -> Self {
+        Self::new(embedder, config.model.clone())
+    }
+
+    /// Override the price in USD per million input tokens
+    pub fn with_price(mut self, usd_per_million_tokens: f64) -> Self {
+        self.usd_per_million_tokens = usd_per_million_tokens;
+        self
+    }
+
+    /// Usage of this embedder's provider and model since the last reset
+    pub fn usage(&self) -> EmbeddingUsage {
+        METER.with(|m| m.borrow().get(&self.key()).cloned()).unwrap_or_else(|| EmbeddingUsage {
+            provider: self.embedder.name().to_string(),
+            model: self.model.clone(),
+            ..EmbeddingUsage::default()
+        })
+    }
+
+    /// Clear the usage of this embedder's provider and model
+    pub fn reset_usage(&self) {
+        METER.with(|m| m.borrow_mut().remove(&self.key()));
+    }
+
+    fn key(&self) -> (String, String) {
+        (self.embedder.name().to_string(), self.model.clone())
+    }
+
+    fn record(&self, tokens: u64, cycles_before: u128, usd_per_million_tokens: f64) {
+        let cycles = outcall_cycles_spent().saturating_sub(cycles_before);
+        let cost = tokens as f64 * usd_per_million_tokens / 1_000_000.0;
+
+        METER.with(|m| {
+            let mut meter = m.borrow_mut();
+            let usage = meter.entry(self.key()).or_insert_with(|| EmbeddingUsage {
+                provider: self.embedder.name().to_string(),
+                model: self.model.clone(),
+                ..EmbeddingUsage::default()
+            });
+            usage.requests += 1;
+            usage.tokens = usage.tokens.saturating_add(tokens);
+            usage.outcall_cycles = usage.outcall_cycles.saturating_add(cycles);
+            usage.estimated_usd += cost;
+        });
+    }
+}
+
+fn texts_tokens(texts: &[String]) -> u64 {
+    texts.iter().map(|t| estimate_tokens(t) as u64).sum()
+}
+
+#[async_trait::async_trait]
+impl<E: Embedder> Embedder for MeteredEmbedder<E> {
+    fn name(&self) -> &str {
+        self.embedder.name()
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let (tokens, cycles) = (texts_tokens(&texts), outcall_cycles_spent());
+        let result = self.embedder.embed(texts).await;
+        self.record(tokens, cycles, self.usd_per_million_tokens);
+        result
+    }
+
+    async fn embed_queries(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let (tokens, cycles) = (texts_tokens(&texts), outcall_cycles_spent());
+        let result = self.embedder.embed_queries(texts).await;
+        self.record(tokens, cycles, self.usd_per_million_tokens);
+        result
+    }
+
+    fn dimensions(&self) -> usize {
+        self.embedder.dimensions()
+    }
+
+    fn embeds_in_context(&self) -> bool {
+        self.embedder.embeds_in_context()
+    }
+
+    async fn test_connection(&self) -> Result<ConnectionTestResult> {
+        self.embedder.test_connection().await
+    }
+
+    async fn generate_with_prompt(&self, text: String, system_prompt: String) -> Result<String> {
+        let tokens = (estimate_tokens(&text) + estimate_tokens(&system_prompt)) as u64;
+        let cycles = outcall_cycles_spent();
+        let result = self.embedder.generate_with_prompt(text, system_prompt).await;
+        // Generation models are priced differently, so only usage is counted
+        self.record(tokens, cycles, 0.0);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedders::mock::MockEmbedder;
+
+    #[tokio::test]
+    async fn test_meters_tokens_and_cost() {
+        let embedder = MeteredEmbedder::new(MockEmbedder::new(1), "text-embedding-3-small").with_price(2.0);
+        embedder.embed(vec!["a".repeat(400), "b".repeat(400)]).await.unwrap();
+        embedder.embed_queries(vec!["c".repeat(200)]).await.unwrap();
+
+        let usage = embedder.usage();
+        assert_eq!((usage.requests, usage.tokens), (2, 250));
+        assert!((usage.estimated_usd - 0.0005).abs() < 1e-12);
+        assert_eq!(all_usage(), vec![usage]);
+
+        embedder.reset_usage();
+        assert_eq!(embedder.usage().requests, 0);
+        assert!(all_usage().is_empty());
+    }
+}
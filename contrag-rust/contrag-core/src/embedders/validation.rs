@@ -0,0 +1,200 @@
+use crate::config::EmbedderConfigDef;
+use crate::embedders::Embedder;
+use crate::error::{ContragError, Result};
+use crate::types::ConnectionTestResult;
+
+/// Text embedded by [`verify_dimensions`]
+const PROBE_TEXT: &str = "contrag dimension check";
+
+/// Check a provider response before it can reach the store
+///
+/// Rejects responses with the wrong number of embeddings, NaN or infinite
+/// values and all-zero vectors, any of which would silently corrupt
+/// similarity ranking, as [`ContragError::InvalidEmbedding`]. Embeddings of
+/// the wrong dimension are a [`ContragError::DimensionMismatch`] carrying
+/// the dimensions returned.
+pub fn validate_embeddings(
+    provider: &str,
+    embeddings: &[Vec<f32>],
+    expected_count: usize,
+    dimensions: usize,
+) -> Result<()> {
+    let invalid = |index: usize, reason: String| ContragError::InvalidEmbedding {
+        provider: provider.to_string(),
+        index,
+        reason,
+    };
+
+    if embeddings.len() != expected_count {
+        return Err(invalid(
+            embeddings.len().min(expected_count),
+            format!("expected {} embeddings, got {}", expected_count, embeddings.len()),
+        ));
+    }
+
+    for (index, embedding) in embeddings.iter().enumerate() {
+        if dimensions > 0 && embedding.len() != dimensions {
+            return Err(ContragError::DimensionMismatch {
+                expected: dimensions,
+                actual: embedding.len(),
+            });
+        }
+        if let Some(position) = embedding.iter().position(|v| !v.is_finite()) {
+            return Err(invalid(index, format!("non-finite value at position {}", position)));
+        }
+        if embedding.iter().all(|v| *v == 0.0) {
+            return Err(invalid(index, "zero vector".to_string()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that `embedder` produces embeddings of the configured size:
+/// `config.target_dimensions` when set, otherwise `config.dimensions`
+///
+/// Compares the dimensions the embedder reports, then embeds a probe text
+/// and compares its length, so a model returning vectors of another size is
+/// caught at startup rather than after the store fills with them. Costs one
+/// embedding outcall.
+pub async fn verify_dimensions<E: Embedder + ?Sized>(embedder: &E, config: &EmbedderConfigDef) -> Result<()> {
+    let expected = config.target_dimensions.unwrap_or(config.dimensions);
+    let mismatch = |actual| ContragError::DimensionMismatch { expected, actual };
+
+    if embedder.dimensions() != expected {
+        return Err(mismatch(embedder.dimensions()));
+    }
+
+    // Built-in providers already reject vectors of the wrong size with a
+    // DimensionMismatch
+    let embeddings = embedder.embed(vec![PROBE_TEXT.to_string()]).await?;
+
+    match embeddings.first() {
+        Some(embedding) if embedding.len() == expected => Ok(()),
+        Some(embedding) => Err(mismatch(embedding.len())),
+        None => Err(ContragError::EmbedderError("No embedding generated".into())),
+    }
+}
+
+/// Embedder wrapper that validates every response and retries bad ones
+///
+/// For custom [`Embedder`] implementations; the built-in providers already
+/// validate their responses. A response failing [`validate_embeddings`] is
+/// requested again up to `retries` times before the
+/// [`ContragError::InvalidEmbedding`] is returned.
+pub struct ValidatedEmbedder<E: Embedder> {
+    embedder: E,
+    retries: u32,
+}
+
+impl<E: Embedder> ValidatedEmbedder<E> {
+    pub fn new(embedder: E, retries: u32) -> Self {
+        Self { embedder, retries }
+    }
+
+    async fn embed_validated(&self, texts: Vec<String>, queries: bool) -> Result<Vec<Vec<f32>>> {
+        let mut attempt = 0;
+
+        loop {
+            let embeddings = if queries {
+                self.embedder.embed_queries(texts.clone()).await?
+            } else {
+                self.embedder.embed(texts.clone()).await?
+            };
+            match validate_embeddings(self.name(), &embeddings, texts.len(), self.dimensions()) {
+                Ok(()) => return Ok(embeddings),
+                Err(e) if attempt >= self.retries => return Err(e),
+                Err(_) => attempt += 1,
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<E: Embedder> Embedder for ValidatedEmbedder<E> {
+    fn name(&self) -> &str {
+        self.embedder.name()
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.embed_validated(texts, false).await
+    }
+
+    async fn embed_queries(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.embed_validated(texts, true).await
+    }
+
+    fn dimensions(&self) -> usize {
+        self.embedder.dimensions()
+    }
+
+    fn embeds_in_context(&self) -> bool {
+        self.embedder.embeds_in_context()
+    }
+
+    async fn test_connection(&self) -> Result<ConnectionTestResult> {
+        self.embedder.test_connection().await
+    }
+
+    async fn generate_with_prompt(&self, text: String, system_prompt: String) -> Result<String> {
+        self.embedder.generate_with_prompt(text, system_prompt).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedders::mock::MockEmbedder;
+
+    #[test]
+    fn test_rejects_bad_embeddings() {
+        let ok = vec![vec![0.1, 0.2], vec![0.0, 1.0]];
+        assert!(validate_embeddings("p", &ok, 2, 2).is_ok());
+
+        let cases = [
+            (vec![vec![0.1, 0.2]], "expected 2 embeddings"),
+            (vec![vec![0.1, 0.2], vec![f32::NAN, 1.0]], "non-finite"),
+            (vec![vec![0.0, 0.0], vec![0.1, 0.2]], "zero vector"),
+        ];
+        for (embeddings, expected) in cases {
+            match validate_embeddings("p", &embeddings, 2, 2) {
+                Err(ContragError::InvalidEmbedding { reason, .. }) => assert!(reason.contains(expected), "{}", reason),
+                other => panic!("expected InvalidEmbedding, got {:?}", other),
+            }
+        }
+
+        let short = vec![vec![0.1, 0.2], vec![0.1]];
+        assert!(matches!(
+            validate_embeddings("p", &short, 2, 2),
+            Err(ContragError::DimensionMismatch { expected: 2, actual: 1 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_verify_dimensions() {
+        let mut config = crate::config::create_default_config().embedder;
+        config.dimensions = 4;
+
+        let ok = MockEmbedder::new(4);
+        assert!(ok.verify(&config).await.is_ok());
+
+        let cases = [
+            (MockEmbedder::new(8), 8),
+            (MockEmbedder::new(4).returning_dimensions(3), 3),
+        ];
+        for (embedder, actual) in cases {
+            match embedder.verify(&config).await {
+                Err(ContragError::DimensionMismatch { expected: 4, actual: a }) => assert_eq!(a, actual),
+                other => panic!("expected DimensionMismatch, got {:?}", other),
+            }
+        }
+
+        // Shortened embeddings are checked against the target dimensions
+        config.target_dimensions = Some(2);
+        assert!(MockEmbedder::new(2).verify(&config).await.is_ok());
+        assert!(matches!(
+            MockEmbedder::new(4).verify(&config).await,
+            Err(ContragError::DimensionMismatch { expected: 2, actual: 4 })
+        ));
+    }
+}
@@ -1,3 +1,5 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -8,8 +10,10 @@ pub enum ContragError {
     #[error("Data source error: {0}")]
     DataSourceError(String),
 
+    /// Includes failures a provider reported, with their structured
+    /// [`ProviderError`] in [`EmbedderFailure::provider`]
     #[error("Embedder error: {0}")]
-    EmbedderError(String),
+    EmbedderError(EmbedderFailure),
 
     #[error("Vector store error: {0}")]
     VectorStoreError(String),
@@ -37,10 +41,138 @@ pub enum ContragError {
 
     #[error("Context building error: {0}")]
     ContextBuildError(String),
+
+    #[error("Concurrency limit reached: {limit} calls already in flight")]
+    ConcurrencyLimitReached { limit: usize },
+
+    #[error("Idempotency key {key}: {reason}")]
+    IdempotencyConflict { key: String, reason: String },
+
+    #[error("Invalid embedding {index} from {provider}: {reason}")]
+    InvalidEmbedding { provider: String, index: usize, reason: String },
+
+    #[error("Access denied: {principal} may not access namespace {namespace}")]
+    AccessDenied { principal: String, namespace: String },
+}
+
+impl ContragError {
+    /// Structured error of the provider behind an embedder failure, if it
+    /// answered with one
+    pub fn provider_error(&self) -> Option<&ProviderError> {
+        match self {
+            ContragError::EmbedderError(failure) => failure.provider.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Detail of an [`ContragError::EmbedderError`]
+///
+/// Dereferences to the message, so it reads like the plain message the
+/// variant used to carry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmbedderFailure {
+    pub message: String,
+    /// Set when the provider answered with an error response
+    pub provider: Option<ProviderError>,
+}
+
+impl std::ops::Deref for EmbedderFailure {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for EmbedderFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<String> for EmbedderFailure {
+    fn from(message: String) -> Self {
+        Self { message, provider: None }
+    }
+}
+
+impl From<&str> for EmbedderFailure {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+impl From<ProviderError> for EmbedderFailure {
+    fn from(error: ProviderError) -> Self {
+        Self {
+            message: error.to_string(),
+            provider: Some(error),
+        }
+    }
+}
+
+/// Category of an error reported by an embedding or LLM provider
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+pub enum ProviderErrorKind {
+    RateLimited,
+    QuotaExceeded,
+    Authentication,
+    InvalidModel,
+    InvalidRequest,
+    ContextLengthExceeded,
+    PayloadTooLarge,
+    ServerError,
+    Unknown,
+}
+
+/// Structured error parsed from a provider's error response
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CandidType)]
+pub struct ProviderError {
+    /// Provider name, e.g. "openai"
+    pub provider: String,
+    pub kind: ProviderErrorKind,
+    /// HTTP status code of the response
+    pub status: u16,
+    /// Seconds to wait before retrying, when the provider says so
+    pub retry_after: Option<u64>,
+    /// Provider-specific error code, e.g. "model_not_found"
+    pub code: Option<String>,
+    pub message: String,
+}
+
+impl ProviderError {
+    /// Whether retrying the same request later can succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.kind,
+            ProviderErrorKind::RateLimited | ProviderErrorKind::ServerError
+        )
+    }
+}
+
+impl std::fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} API returned status {} ({:?}): {}",
+            self.provider, self.status, self.kind, self.message
+        )?;
+        if let Some(retry_after) = self.retry_after {
+            write!(f, " (retry after {}s)", retry_after)?;
+        }
+        Ok(())
+    }
 }
 
 pub type Result<T> = std::result::Result<T, ContragError>;
 
+impl From<ProviderError> for ContragError {
+    fn from(error: ProviderError) -> Self {
+        ContragError::EmbedderError(error.into())
+    }
+}
+
 impl From<serde_json::Error> for ContragError {
     fn from(err: serde_json::Error) -> Self {
         ContragError::SerializationError(err.to_string())
@@ -0,0 +1,289 @@
+//! Retrieval quality evaluation with golden queries
+//!
+//! A golden query is a question together with the entities that should be
+//! retrieved for it. Running the registered set against the live index gives
+//! recall@k per query; comparing that to a stored baseline detects
+//! regressions after data or configuration changes.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::Duration;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::config::EvalConfig;
+use crate::embedders::Embedder;
+use crate::embedders::http_client::HttpClient;
+use crate::error::{ContragError, Result};
+use crate::logs::{self, LogEvent, LogLevel};
+use crate::types::SearchResult;
+use crate::utils::get_timestamp;
+use crate::vector_store::VectorStore;
+
+/// A query with the entities it is expected to retrieve
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct GoldenQuery {
+    pub id: String,
+    pub namespace: String,
+    pub query: String,
+    /// Expected entity IDs (or vector IDs)
+    pub expected_ids: Vec<String>,
+    pub k: usize,
+}
+
+/// Recall of a single golden query
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct QueryEval {
+    pub query_id: String,
+    pub recall: f64,
+    /// Expected IDs that were not retrieved
+    pub missing: Vec<String>,
+}
+
+/// Result of running a set of golden queries
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct EvalReport {
+    pub timestamp: u64,
+    pub queries: Vec<QueryEval>,
+    pub mean_recall: f64,
+}
+
+/// Drop in recall of one query compared to the baseline
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct Regression {
+    pub query_id: String,
+    pub baseline_recall: f64,
+    pub current_recall: f64,
+}
+
+#[derive(Default)]
+struct EvalState {
+    queries: BTreeMap<String, GoldenQuery>,
+    baseline: Option<EvalReport>,
+    last_report: Option<EvalReport>,
+}
+
+thread_local! {
+    static STATE: RefCell<EvalState> = RefCell::new(EvalState::default());
+}
+
+/// Register (or replace) a golden query
+pub fn register_golden_query(query: GoldenQuery) {
+    STATE.with(|s| s.borrow_mut().queries.insert(query.id.clone(), query));
+}
+
+/// Remove a golden query; returns whether it existed
+pub fn remove_golden_query(id: &str) -> bool {
+    STATE.with(|s| s.borrow_mut().queries.remove(id).is_some())
+}
+
+/// All registered golden queries
+pub fn golden_queries() -> Vec<GoldenQuery> {
+    STATE.with(|s| s.borrow().queries.values().cloned().collect())
+}
+
+/// Use `report` as the baseline for regression checks
+pub fn set_baseline(report: EvalReport) {
+    STATE.with(|s| s.borrow_mut().baseline = Some(report));
+}
+
+/// Current baseline, if any
+pub fn baseline() -> Option<EvalReport> {
+    STATE.with(|s| s.borrow().baseline.clone())
+}
+
+/// Report of the most recent scheduled run
+pub fn last_report() -> Option<EvalReport> {
+    STATE.with(|s| s.borrow().last_report.clone())
+}
+
+/// Fraction of `expected` IDs found among `results`
+///
+/// A result matches an expected ID by entity ID or vector ID.
+pub fn recall_at_k(results: &[SearchResult], expected: &[String]) -> (f64, Vec<String>) {
+    if expected.is_empty() {
+        return (1.0, vec![]);
+    }
+
+    let missing: Vec<String> = expected
+        .iter()
+        .filter(|id| {
+            !results
+                .iter()
+                .any(|r| &r.metadata.entity_id == *id || &r.vector_id == *id)
+        })
+        .cloned()
+        .collect();
+
+    let recall = (expected.len() - missing.len()) as f64 / expected.len() as f64;
+    (recall, missing)
+}
+
+/// Run golden queries against an index
+pub async fn run_golden_queries<E, S>(
+    embedder: &E,
+    store: &S,
+    queries: &[GoldenQuery],
+) -> Result<EvalReport>
+where
+    E: Embedder + ?Sized,
+    S: VectorStore + ?Sized,
+{
+    let mut evals = Vec::with_capacity(queries.len());
+
+    if !queries.is_empty() {
+        let texts = queries.iter().map(|q| q.query.clone()).collect();
+        let embeddings = embedder.embed_queries(texts).await?;
+        if embeddings.len() != queries.len() {
+            return Err(ContragError::EmbedderError(format!(
+                "Expected {} embeddings, got {}",
+                queries.len(),
+                embeddings.len()
+            ).into()));
+        }
+
+        for (query, embedding) in queries.iter().zip(embeddings) {
+            let results = store.search(&query.namespace, embedding, query.k).await?;
+            let (recall, missing) = recall_at_k(&results, &query.expected_ids);
+            evals.push(QueryEval {
+                query_id: query.id.clone(),
+                recall,
+                missing,
+            });
+        }
+    }
+
+    let mean_recall = if evals.is_empty() {
+        1.0
+    } else {
+        evals.iter().map(|e| e.recall).sum::<f64>() / evals.len() as f64
+    };
+
+    Ok(EvalReport {
+        timestamp: get_timestamp(),
+        queries: evals,
+        mean_recall,
+    })
+}
+
+/// Queries whose recall dropped by more than `tolerance` against the baseline
+///
+/// Queries missing from the baseline are not compared.
+pub fn compare_to_baseline(baseline: &EvalReport, current: &EvalReport, tolerance: f64) -> Vec<Regression> {
+    current
+        .queries
+        .iter()
+        .filter_map(|eval| {
+            let base = baseline.queries.iter().find(|b| b.query_id == eval.query_id)?;
+            (base.recall - eval.recall > tolerance).then(|| Regression {
+                query_id: eval.query_id.clone(),
+                baseline_recall: base.recall,
+                current_recall: eval.recall,
+            })
+        })
+        .collect()
+}
+
+/// Record a report from a scheduled run and alert on regressions
+///
+/// The first report becomes the baseline when none is set. Regressions are
+/// recorded as error log events and, if configured, posted to the webhook.
+pub async fn check_report(config: &EvalConfig, report: EvalReport) -> Vec<Regression> {
+    let baseline = STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        state.last_report = Some(report.clone());
+        state.baseline.get_or_insert_with(|| report.clone()).clone()
+    });
+
+    let regressions = compare_to_baseline(&baseline, &report, config.regression_tolerance);
+
+    for regression in &regressions {
+        logs::record(
+            LogEvent::new(
+                LogLevel::Error,
+                "eval",
+                format!("Retrieval regression on golden query '{}'", regression.query_id),
+            )
+            .with_field("baseline_recall", regression.baseline_recall)
+            .with_field("current_recall", regression.current_recall),
+        );
+    }
+
+    if let (Some(url), false) = (&config.webhook_url, regressions.is_empty()) {
+        if let Err(e) = post_webhook(url, &report, &regressions).await {
+            ic_cdk::println!("contrag: failed to send regression webhook: {}", e);
+        }
+    }
+
+    regressions
+}
+
+async fn post_webhook(url: &str, report: &EvalReport, regressions: &[Regression]) -> Result<()> {
+    let body = serde_json::to_vec(&serde_json::json!({
+        "event": "retrieval_regression",
+        "timestamp": report.timestamp,
+        "mean_recall": report.mean_recall,
+        "regressions": regressions,
+    }))
+    .map_err(|e| ContragError::SerializationError(e.to_string()))?;
+
+    let headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+    HttpClient::new().post(url.to_string(), headers, body).await?;
+    Ok(())
+}
+
+/// Start a periodic timer that runs the golden queries
+///
+/// `run` is spawned on every tick with the registered queries and should
+/// evaluate them against the canister's index, typically with
+/// [`run_golden_queries`]. Failed runs are logged and skipped.
+pub fn start_golden_query_monitor<F, Fut>(config: EvalConfig, run: F) -> ic_cdk_timers::TimerId
+where
+    F: Fn(Vec<GoldenQuery>) -> Fut + 'static,
+    Fut: Future<Output = Result<EvalReport>> + 'static,
+{
+    let interval = Duration::from_secs(config.check_interval_secs);
+    ic_cdk_timers::set_timer_interval(interval, move || {
+        let config = config.clone();
+        let run = run(golden_queries());
+        ic_cdk::spawn(async move {
+            match run.await {
+                Ok(report) => {
+                    check_report(&config, report).await;
+                }
+                Err(e) => ic_cdk::println!("contrag: golden query run failed: {}", e),
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(recalls: &[(&str, f64)]) -> EvalReport {
+        EvalReport {
+            timestamp: 0,
+            queries: recalls
+                .iter()
+                .map(|(id, recall)| QueryEval {
+                    query_id: id.to_string(),
+                    recall: *recall,
+                    missing: vec![],
+                })
+                .collect(),
+            mean_recall: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_compare_to_baseline() {
+        let baseline = report(&[("a", 1.0), ("b", 0.5)]);
+        let current = report(&[("a", 0.5), ("b", 0.48), ("c", 0.0)]);
+
+        let regressions = compare_to_baseline(&baseline, &current, 0.05);
+
+        assert_eq!(regressions.len(), 1);
+        assert_eq!(regressions[0].query_id, "a");
+    }
+}
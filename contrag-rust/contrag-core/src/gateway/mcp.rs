@@ -0,0 +1,346 @@
+//! Model Context Protocol endpoint at `POST /mcp`
+//!
+//! Implements the stateless subset of the streamable HTTP transport: every
+//! JSON-RPC request is answered with a single JSON response, no sessions or
+//! server-sent events. Exposed tools:
+//!
+//! - `search_context` returns a [`ToolResult`](crate::pipeline::tool::ToolResult)
+//!   for a query against a namespace
+//! - `ingest_entity` chunks, embeds and stores a text, only when
+//!   [`GatewayConfig::allow_ingest`] is set
+//!
+//! Tools only run for requests authorized like the `/v1` routes, see
+//! [`openai::is_authorized`].
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::config::GatewayConfig;
+use crate::embedders::Embedder;
+use crate::gateway::{openai, GatewayRequest, GatewayResponse};
+use crate::pipeline::RagPipeline;
+use crate::vector_store::VectorStore;
+
+pub const MCP_PATH: &str = "/mcp";
+
+/// Protocol revision answered when the client doesn't ask for one
+const PROTOCOL_VERSION: &str = "2025-03-26";
+
+/// Characters kept per chunk in `search_context` results
+const MAX_TOOL_TEXT_CHARS: usize = 1000;
+
+/// Largest `k` accepted by `search_context`
+const MAX_SEARCH_RESULTS: usize = 50;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+#[derive(Deserialize)]
+struct RpcRequest {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// Whether the request must be served by the update call
+///
+/// Only `tools/call` needs outcalls or writes; the handshake and tool
+/// listing are answered from the query.
+pub fn needs_update(request: &GatewayRequest) -> bool {
+    match serde_json::from_slice::<RpcRequest>(&request.body) {
+        Ok(rpc) => rpc.method == "tools/call",
+        Err(_) => false,
+    }
+}
+
+/// Handle a JSON-RPC message posted to `/mcp`
+pub async fn handle<E: Embedder, S: VectorStore>(
+    pipeline: &mut RagPipeline<E, S>,
+    config: &GatewayConfig,
+    request: &GatewayRequest,
+) -> GatewayResponse {
+    let rpc = match parse(request) {
+        Ok(rpc) => rpc,
+        Err(response) => return response,
+    };
+
+    if rpc.method == "tools/call" {
+        if !openai::is_authorized(config, request) {
+            return openai::unauthorized();
+        }
+        let result = call_tool(pipeline, config, &rpc.params).await;
+        return respond(rpc.id, result);
+    }
+
+    handle_sync(config, &rpc).unwrap_or_else(|| {
+        let message = format!("Method not found: {}", rpc.method);
+        GatewayResponse::json(200, &error(rpc.id.clone(), METHOD_NOT_FOUND, &message))
+    })
+}
+
+/// Answer the methods that need neither outcalls nor writes
+pub fn handle_query(config: &GatewayConfig, request: &GatewayRequest) -> Option<GatewayResponse> {
+    if needs_update(request) {
+        return (!openai::is_authorized(config, request)).then(openai::unauthorized);
+    }
+    let rpc = match parse(request) {
+        Ok(rpc) => rpc,
+        Err(response) => return Some(response),
+    };
+    handle_sync(config, &rpc)
+}
+
+fn parse(request: &GatewayRequest) -> std::result::Result<RpcRequest, GatewayResponse> {
+    if !request.method.eq_ignore_ascii_case("POST") {
+        return Err(GatewayResponse::error(405, "invalid_request_error", "MCP messages must be POSTed"));
+    }
+
+    let value: Value = serde_json::from_slice(&request.body)
+        .map_err(|e| GatewayResponse::json(200, &error(None, PARSE_ERROR, &e.to_string())))?;
+    serde_json::from_value(value)
+        .map_err(|e| GatewayResponse::json(200, &error(None, INVALID_REQUEST, &e.to_string())))
+}
+
+fn handle_sync(config: &GatewayConfig, rpc: &RpcRequest) -> Option<GatewayResponse> {
+    // Notifications carry no id and get no JSON-RPC response
+    if rpc.id.is_none() && rpc.method.starts_with("notifications/") {
+        return Some(GatewayResponse {
+            status_code: 202,
+            headers: vec![],
+            body: vec![],
+            upgrade: None,
+        });
+    }
+
+    let result = match rpc.method.as_str() {
+        "initialize" => {
+            let version = rpc.params["protocolVersion"].as_str().unwrap_or(PROTOCOL_VERSION);
+            Ok(json!({
+                "protocolVersion": version,
+                "capabilities": { "tools": { "listChanged": false } },
+                "serverInfo": { "name": "contrag", "version": env!("CARGO_PKG_VERSION") },
+            }))
+        }
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tools(config) })),
+        _ => return None,
+    };
+
+    Some(respond(rpc.id.clone(), result))
+}
+
+fn tools(config: &GatewayConfig) -> Vec<Value> {
+    let mut tools = vec![json!({
+        "name": "search_context",
+        "description": "Search a contrag namespace for the chunks most relevant to a query",
+        "inputSchema": {
+            "type": "object",
+            "required": ["namespace", "query"],
+            "properties": {
+                "namespace": { "type": "string", "description": "Namespace to search, e.g. \"User:42\"" },
+                "query": { "type": "string" },
+                "k": { "type": "integer", "minimum": 1, "maximum": MAX_SEARCH_RESULTS },
+            },
+        },
+    })];
+
+    if config.allow_ingest {
+        tools.push(json!({
+            "name": "ingest_entity",
+            "description": "Chunk, embed and store a text as an entity in a contrag namespace",
+            "inputSchema": {
+                "type": "object",
+                "required": ["namespace", "entity_type", "entity_id", "text"],
+                "properties": {
+                    "namespace": { "type": "string" },
+                    "entity_type": { "type": "string" },
+                    "entity_id": { "type": "string" },
+                    "text": { "type": "string" },
+                    "idempotency_key": {
+                        "type": "string",
+                        "description": "Retries with the same key return the original result",
+                    },
+                },
+            },
+        }));
+    }
+
+    tools
+}
+
+#[derive(Deserialize)]
+struct ToolCall {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(Deserialize)]
+struct SearchArgs {
+    namespace: String,
+    query: String,
+    #[serde(default)]
+    k: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct IngestArgs {
+    namespace: String,
+    entity_type: String,
+    entity_id: String,
+    text: String,
+    #[serde(default)]
+    idempotency_key: Option<String>,
+}
+
+/// Run a tool; `Err` is a JSON-RPC error, tool failures are `isError` results
+async fn call_tool<E: Embedder, S: VectorStore>(
+    pipeline: &mut RagPipeline<E, S>,
+    config: &GatewayConfig,
+    params: &Value,
+) -> std::result::Result<Value, (i64, String)> {
+    let call: ToolCall = serde_json::from_value(params.clone())
+        .map_err(|e| (INVALID_PARAMS, e.to_string()))?;
+
+    let outcome = match call.name.as_str() {
+        "search_context" => {
+            let args: SearchArgs = arguments(call.arguments)?;
+            let k = args.k.map(|k| k.clamp(1, MAX_SEARCH_RESULTS));
+            pipeline
+                .search_tool(&args.namespace, &args.query, k, MAX_TOOL_TEXT_CHARS)
+                .await
+        }
+        "ingest_entity" if config.allow_ingest => {
+            let args: IngestArgs = arguments(call.arguments)?;
+            let stored = match &args.idempotency_key {
+                Some(key) => {
+                    pipeline
+                        .ingest_text_idempotent(key, &args.namespace, &args.entity_type, &args.entity_id, &args.text)
+                        .await
+                }
+                None => {
+                    pipeline
+                        .ingest_text(&args.namespace, &args.entity_type, &args.entity_id, &args.text)
+                        .await
+                }
+            };
+            stored.map(|chunks| format!("Stored {} chunks for {}:{} in {}", chunks, args.entity_type, args.entity_id, args.namespace))
+        }
+        name => return Err((INVALID_PARAMS, format!("Unknown tool: {}", name))),
+    };
+
+    Ok(match outcome {
+        Ok(text) => tool_content(&text, false),
+        Err(e) => tool_content(&e.to_string(), true),
+    })
+}
+
+fn arguments<T: for<'de> Deserialize<'de>>(value: Value) -> std::result::Result<T, (i64, String)> {
+    serde_json::from_value(value).map_err(|e| (INVALID_PARAMS, format!("Invalid arguments: {}", e)))
+}
+
+fn tool_content(text: &str, is_error: bool) -> Value {
+    json!({
+        "content": [{ "type": "text", "text": text }],
+        "isError": is_error,
+    })
+}
+
+fn respond(id: Option<Value>, result: std::result::Result<Value, (i64, String)>) -> GatewayResponse {
+    let body = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => error(id, code, &message),
+    };
+    GatewayResponse::json(200, &body)
+}
+
+fn error(id: Option<Value>, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::Digest;
+    use crate::config::PipelineConfig;
+    use crate::embedders::mock::MockEmbedder;
+    use crate::vector_store::stable_memory_store::StableMemoryVectorStore;
+
+    const TOKEN: &str = "mcp-token";
+
+    fn post(body: &str) -> GatewayRequest {
+        GatewayRequest {
+            method: "POST".to_string(),
+            url: MCP_PATH.to_string(),
+            headers: vec![],
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn authorized(body: &str) -> GatewayRequest {
+        GatewayRequest {
+            headers: vec![("Authorization".to_string(), format!("Bearer {}", TOKEN))],
+            ..post(body)
+        }
+    }
+
+    fn config() -> GatewayConfig {
+        GatewayConfig {
+            mcp: true,
+            api_key_hashes: vec![format!("{:x}", sha2::Sha256::digest(TOKEN.as_bytes()))],
+            ..GatewayConfig::default()
+        }
+    }
+
+    fn body(response: &GatewayResponse) -> Value {
+        serde_json::from_slice(&response.body).unwrap()
+    }
+
+    #[test]
+    fn test_query_methods() {
+        let config = config();
+
+        let list = handle_query(&config, &post(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#)).unwrap();
+        let tools = body(&list)["result"]["tools"].clone();
+        assert_eq!(tools.as_array().unwrap().len(), 1);
+        assert_eq!(tools[0]["name"], "search_context");
+
+        let ingest = GatewayConfig { allow_ingest: true, ..config.clone() };
+        let list = handle_query(&ingest, &post(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#)).unwrap();
+        assert_eq!(body(&list)["result"]["tools"].as_array().unwrap().len(), 2);
+
+        let call = r#"{"jsonrpc":"2.0","id":2,"method":"tools/call","params":{"name":"search_context"}}"#;
+        assert!(handle_query(&config, &authorized(call)).is_none());
+
+        let invalid = handle_query(&config, &post("{")).unwrap();
+        assert_eq!(body(&invalid)["error"]["code"], PARSE_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_require_a_token() {
+        let config = config();
+        let mut pipeline = RagPipeline::new(
+            MockEmbedder::new(4),
+            StableMemoryVectorStore::new(),
+            PipelineConfig::default(),
+        );
+        let call = r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"search_context","arguments":{"namespace":"User:42","query":"orders"}}}"#;
+
+        let rejected = handle(&mut pipeline, &config, &post(call)).await;
+        assert_eq!(rejected.status_code, 401);
+        assert_eq!(handle_query(&config, &post(call)).unwrap().status_code, 401);
+
+        let answered = handle(&mut pipeline, &config, &authorized(call)).await;
+        assert_eq!(answered.status_code, 200);
+        assert!(body(&answered)["result"]["content"].is_array());
+
+        assert!(!GatewayConfig::default().mcp);
+    }
+}
@@ -0,0 +1,427 @@
+//! HTTP gateway for canisters serving contrag over `http_request`
+//!
+//! The IC HTTP gateway calls a canister's `http_request` query first. Routes
+//! that need outcalls or writes answer it with `upgrade = true`, and the
+//! gateway repeats the request as the `http_request_update` update call. A
+//! canister wires both endpoints to [`handle_query`] and [`handle_update`].
+//!
+//! Route groups can be switched off through [`GatewayConfig`].
+
+pub mod mcp;
+pub mod openai;
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::config::GatewayConfig;
+use crate::embedders::Embedder;
+use crate::pipeline::query::EntityQuery;
+use crate::pipeline::RagPipeline;
+use crate::types::Vector;
+use crate::vector_store::export::{to_jsonl, ColumnarExport};
+use crate::vector_store::VectorStore;
+
+/// Path of the combined entity and retrieval query route
+pub const QUERY_PATH: &str = "/query";
+
+/// Path of the vector export route
+pub const EXPORT_PATH: &str = "/export";
+
+/// Vectors per export page when the request sets no `limit`
+pub const DEFAULT_EXPORT_LIMIT: usize = 100;
+
+/// Most vectors per export page
+pub const MAX_EXPORT_LIMIT: usize = 1_000;
+
+/// Response header holding the cursor of the next export page
+pub const NEXT_CURSOR_HEADER: &str = "X-Next-Cursor";
+
+/// Body budget of one export page, below the 2 MiB response limit
+const EXPORT_PAGE_BYTES: usize = 1_800_000;
+
+/// Request received by `http_request` / `http_request_update`
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct GatewayRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl GatewayRequest {
+    /// URL path without the query string
+    pub fn path(&self) -> &str {
+        self.url.split('?').next().unwrap_or("")
+    }
+
+    /// First header with the given name (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// First query string parameter with the given name, URL-decoded
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        let (_, query) = self.url.split_once('?')?;
+        query.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (key == name).then(|| percent_decode(value)).flatten()
+        })
+    }
+
+    /// Parse the body as JSON
+    pub fn json<T: for<'de> Deserialize<'de>>(&self) -> Result<T, GatewayResponse> {
+        serde_json::from_slice(&self.body)
+            .map_err(|e| GatewayResponse::error(400, "invalid_request_error", &format!("Invalid JSON body: {}", e)))
+    }
+}
+
+/// Response returned from `http_request` / `http_request_update`
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct GatewayResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub upgrade: Option<bool>,
+}
+
+impl GatewayResponse {
+    /// JSON response with the given status
+    pub fn json(status_code: u16, value: &serde_json::Value) -> Self {
+        Self {
+            status_code,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: serde_json::to_vec(value).unwrap_or_default(),
+            upgrade: None,
+        }
+    }
+
+    /// Error in the OpenAI error envelope, which most clients understand
+    pub fn error(status_code: u16, error_type: &str, message: &str) -> Self {
+        Self::json(status_code, &serde_json::json!({
+            "error": {
+                "message": message,
+                "type": error_type,
+                "code": null,
+            }
+        }))
+    }
+
+    pub fn not_found(path: &str) -> Self {
+        Self::error(404, "not_found_error", &format!("No route for {}", path))
+    }
+
+    /// Ask the HTTP gateway to retry the request as an update call
+    pub fn upgrade() -> Self {
+        Self {
+            status_code: 200,
+            headers: vec![],
+            body: vec![],
+            upgrade: Some(true),
+        }
+    }
+}
+
+/// Handle a request in the `http_request` query
+///
+/// Answers the routes that need neither outcalls nor writes and upgrades
+/// everything else.
+pub fn handle_query<E: Embedder, S: VectorStore>(
+    pipeline: &RagPipeline<E, S>,
+    config: &GatewayConfig,
+    request: &GatewayRequest,
+) -> GatewayResponse {
+    let path = request.path();
+
+    if config.mcp && path == mcp::MCP_PATH {
+        return mcp::handle_query(config, request).unwrap_or_else(GatewayResponse::upgrade);
+    }
+
+    // Loaders may call other canisters, so queries always run as updates
+    if config.entity_query && path == QUERY_PATH {
+        if !openai::is_authorized(config, request) {
+            return openai::unauthorized();
+        }
+        return GatewayResponse::upgrade();
+    }
+
+    // Reading the store is async, so exports run as updates too
+    if config.export && path == EXPORT_PATH {
+        return GatewayResponse::upgrade();
+    }
+
+    if config.openai_routes && openai::is_route(path) {
+        return openai::handle_query(pipeline, config, request).unwrap_or_else(GatewayResponse::upgrade);
+    }
+
+    GatewayResponse::not_found(path)
+}
+
+/// Handle a request in the `http_request_update` update call
+pub async fn handle_update<E: Embedder, S: VectorStore>(
+    pipeline: &mut RagPipeline<E, S>,
+    config: &GatewayConfig,
+    request: &GatewayRequest,
+) -> GatewayResponse {
+    let path = request.path().to_string();
+
+    if config.mcp && path == mcp::MCP_PATH {
+        return mcp::handle(pipeline, config, request).await;
+    }
+
+    if config.entity_query && path == QUERY_PATH {
+        if !openai::is_authorized(config, request) {
+            return openai::unauthorized();
+        }
+        return entity_query(pipeline, request).await;
+    }
+
+    if config.export && path == EXPORT_PATH {
+        return export(pipeline, request).await;
+    }
+
+    if config.openai_routes && openai::is_route(&path) {
+        return openai::handle_update(pipeline, config, request).await;
+    }
+
+    GatewayResponse::not_found(&path)
+}
+
+/// `POST /query` with an [`EntityQuery`] body
+async fn entity_query<E: Embedder, S: VectorStore>(
+    pipeline: &RagPipeline<E, S>,
+    request: &GatewayRequest,
+) -> GatewayResponse {
+    if !request.method.eq_ignore_ascii_case("POST") {
+        return GatewayResponse::error(405, "invalid_request_error", "Method not allowed");
+    }
+
+    let query: EntityQuery = match request.json() {
+        Ok(query) => query,
+        Err(response) => return response,
+    };
+
+    match pipeline.query(&query).await {
+        Ok(document) => match serde_json::to_value(&document) {
+            Ok(value) => GatewayResponse::json(200, &value),
+            Err(e) => GatewayResponse::error(500, "api_error", &e.to_string()),
+        },
+        Err(e @ crate::error::ContragError::InvalidConfig(_)) => {
+            GatewayResponse::error(400, "invalid_request_error", &e.to_string())
+        }
+        Err(e) => GatewayResponse::error(502, "api_error", &e.to_string()),
+    }
+}
+
+/// `GET /export?namespace=...&format=jsonl|columns[&cursor=C&limit=N]`
+///
+/// `jsonl` (the default) returns one record per line; `columns` returns a
+/// [`ColumnarExport`] for loading into Arrow or Parquet. Each response is
+/// one page of at most `limit` vectors ([`DEFAULT_EXPORT_LIMIT`], capped at
+/// [`MAX_EXPORT_LIMIT`]), cut short to stay under the response size limit.
+/// Unless it is the last page, its [`NEXT_CURSOR_HEADER`] header holds the
+/// `cursor` of the next one.
+async fn export<E: Embedder, S: VectorStore>(
+    pipeline: &RagPipeline<E, S>,
+    request: &GatewayRequest,
+) -> GatewayResponse {
+    if !request.method.eq_ignore_ascii_case("GET") {
+        return GatewayResponse::error(405, "invalid_request_error", "Method not allowed");
+    }
+
+    let namespace = match request.query_param("namespace") {
+        Some(namespace) if !namespace.is_empty() => namespace,
+        _ => return GatewayResponse::error(400, "invalid_request_error", "Missing 'namespace' parameter"),
+    };
+    let cursor: usize = match request.query_param("cursor").map(|v| v.parse()) {
+        None => 0,
+        Some(Ok(cursor)) => cursor,
+        Some(Err(_)) => return GatewayResponse::error(400, "invalid_request_error", "Invalid 'cursor' parameter"),
+    };
+    let limit = request
+        .query_param("limit")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_EXPORT_LIMIT)
+        .clamp(1, MAX_EXPORT_LIMIT);
+
+    // One more than the page, to tell whether another page follows
+    let mut vectors = match pipeline.store().list_vectors(&namespace, cursor, limit + 1).await {
+        Ok(vectors) => vectors,
+        Err(e) => return GatewayResponse::error(500, "api_error", &e.to_string()),
+    };
+    let page_len = export_page_len(&vectors, limit);
+    let next_cursor = (vectors.len() > page_len).then(|| cursor + page_len);
+    vectors.truncate(page_len);
+
+    let mut response = match request.query_param("format").as_deref().unwrap_or("jsonl") {
+        "jsonl" => GatewayResponse {
+            status_code: 200,
+            headers: vec![("Content-Type".to_string(), "application/x-ndjson".to_string())],
+            body: to_jsonl(&namespace, vectors).into_bytes(),
+            upgrade: None,
+        },
+        "columns" => match serde_json::to_value(ColumnarExport::new(&namespace, vectors)) {
+            Ok(value) => GatewayResponse::json(200, &value),
+            Err(e) => return GatewayResponse::error(500, "api_error", &e.to_string()),
+        },
+        other => {
+            return GatewayResponse::error(
+                400,
+                "invalid_request_error",
+                &format!("Unknown format '{}', expected 'jsonl' or 'columns'", other),
+            )
+        }
+    };
+    if let Some(next_cursor) = next_cursor {
+        response.headers.push((NEXT_CURSOR_HEADER.to_string(), next_cursor.to_string()));
+    }
+    response
+}
+
+/// Vectors of `vectors` that fit one export page of at most `limit`
+///
+/// Sizes are upper estimates of a JSON record: up to 16 bytes per float of
+/// the embedding plus the strings and field names. A page holds at least
+/// one vector so paging always advances.
+fn export_page_len(vectors: &[Vector], limit: usize) -> usize {
+    let mut bytes = 0;
+    let mut len = 0;
+    for vector in vectors.iter().take(limit) {
+        bytes += vector.embedding.len() * 16
+            + vector.id.len()
+            + vector.text.len()
+            + vector.metadata.entity_type.len()
+            + vector.metadata.entity_id.len()
+            + vector.metadata.custom.as_ref().map_or(0, String::len)
+            + 256;
+        if len > 0 && bytes > EXPORT_PAGE_BYTES {
+            break;
+        }
+        len += 1;
+    }
+    len
+}
+
+/// Decode `%XX` escapes; `None` for malformed input
+pub(crate) fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PipelineConfig;
+    use crate::embedders::mock::MockEmbedder;
+    use crate::types::test_vector;
+    use crate::vector_store::stable_memory_store::StableMemoryVectorStore;
+
+    fn get(url: &str) -> GatewayRequest {
+        GatewayRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: vec![],
+        }
+    }
+
+    fn next_cursor(response: &GatewayResponse) -> Option<&str> {
+        response
+            .headers
+            .iter()
+            .find(|(name, _)| name == NEXT_CURSOR_HEADER)
+            .map(|(_, value)| value.as_str())
+    }
+
+    async fn pipeline(vectors: usize, dimensions: usize) -> RagPipeline<MockEmbedder, StableMemoryVectorStore> {
+        let mut store = StableMemoryVectorStore::new();
+        for i in 0..vectors {
+            let mut vector = Vector {
+                text: format!("doc {}", i),
+                ..test_vector(&format!("Doc::{}::chunk_0", i), vec![1.0; dimensions])
+            };
+            vector.metadata.total_chunks = 3;
+            store.store("docs", vector).await.unwrap();
+        }
+        RagPipeline::new(MockEmbedder::new(dimensions), store, PipelineConfig::default())
+    }
+
+    #[tokio::test]
+    async fn test_export_pages_from_a_cursor() {
+        let mut pipeline = pipeline(5, 2).await;
+        let config = GatewayConfig { export: true, ..GatewayConfig::default() };
+
+        let mut ids = vec![];
+        let mut url = format!("{}?namespace=docs&limit=2", EXPORT_PATH);
+        loop {
+            let response = handle_update(&mut pipeline, &config, &get(&url)).await;
+            assert_eq!(response.status_code, 200);
+            let body = String::from_utf8(response.body.clone()).unwrap();
+            assert!(body.lines().count() <= 2);
+            for line in body.lines() {
+                let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                ids.push(record["id"].as_str().unwrap().to_string());
+            }
+            match next_cursor(&response) {
+                Some(cursor) => url = format!("{}?namespace=docs&limit=2&cursor={}", EXPORT_PATH, cursor),
+                None => break,
+            }
+        }
+        assert_eq!(ids.len(), 5);
+        assert_eq!(ids[4], "Doc::4::chunk_0");
+
+        let columns = handle_update(&mut pipeline, &config, &get("/export?namespace=docs&format=columns")).await;
+        let columns: ColumnarExport = serde_json::from_slice(&columns.body).unwrap();
+        assert_eq!((columns.len(), columns.total_chunks[0]), (5, 3));
+
+        let invalid = handle_update(&mut pipeline, &config, &get("/export?namespace=docs&cursor=x")).await;
+        assert_eq!(invalid.status_code, 400);
+        let disabled = handle_update(&mut pipeline, &GatewayConfig::default(), &get("/export?namespace=docs")).await;
+        assert_eq!(disabled.status_code, 404);
+    }
+
+    #[tokio::test]
+    async fn test_export_pages_stay_under_the_response_limit() {
+        let mut pipeline = pipeline(200, 1536).await;
+        let config = GatewayConfig { export: true, ..GatewayConfig::default() };
+
+        let url = format!("{}?namespace=docs&limit={}", EXPORT_PATH, MAX_EXPORT_LIMIT);
+        let response = handle_update(&mut pipeline, &config, &get(&url)).await;
+        assert!(response.body.len() <= EXPORT_PAGE_BYTES);
+        let exported = String::from_utf8(response.body.clone()).unwrap().lines().count();
+        assert!(exported < 200);
+        assert_eq!(next_cursor(&response), Some(exported.to_string().as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_entity_query_requires_a_token() {
+        let mut pipeline = pipeline(0, 2).await;
+        let config = GatewayConfig { entity_query: true, ..GatewayConfig::default() };
+        let request = GatewayRequest {
+            method: "POST".to_string(),
+            url: QUERY_PATH.to_string(),
+            headers: vec![("Authorization".to_string(), "Bearer unknown".to_string())],
+            body: br#"{"namespace": "docs", "query": "orders"}"#.to_vec(),
+        };
+
+        assert_eq!(handle_query(&pipeline, &config, &request).status_code, 401);
+        assert_eq!(handle_update(&mut pipeline, &config, &request).await.status_code, 401);
+        let disabled = handle_update(&mut pipeline, &GatewayConfig::default(), &request).await;
+        assert_eq!(disabled.status_code, 404);
+    }
+}
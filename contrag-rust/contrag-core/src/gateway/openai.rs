@@ -0,0 +1,308 @@
+//! OpenAI-compatible `/v1` routes
+//!
+//! - `GET /v1/models` lists the configured embedding model
+//! - `POST /v1/embeddings` proxies to the canister's embedder
+//! - `POST /v1/vector_stores/{namespace}/search` searches a namespace and
+//!   answers in the vector store search results format used by file_search
+//!
+//! Existing OpenAI client SDKs work against these routes by pointing their
+//! base URL at `https://<canister-id>.icp0.io/v1` and passing one of the
+//! tokens of [`GatewayConfig::api_key_hashes`] as their API key.
+
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use crate::config::GatewayConfig;
+use crate::embedders::Embedder;
+use crate::gateway::{percent_decode, GatewayRequest, GatewayResponse};
+use crate::pipeline::RagPipeline;
+use crate::types::SearchResult;
+use crate::utils::estimate_tokens;
+use crate::vector_store::VectorStore;
+
+const VECTOR_STORES_PREFIX: &str = "/v1/vector_stores/";
+const SEARCH_SUFFIX: &str = "/search";
+
+/// Largest `max_num_results` accepted by the search route
+const MAX_SEARCH_RESULTS: usize = 50;
+
+/// Whether a path belongs to the OpenAI-compatible routes
+pub fn is_route(path: &str) -> bool {
+    path == "/v1/models" || path == "/v1/embeddings" || search_namespace(path).is_some()
+}
+
+/// Whether the request's Bearer token hashes to one of the configured
+/// `api_key_hashes`
+pub fn is_authorized(config: &GatewayConfig, request: &GatewayRequest) -> bool {
+    let Some(token) = request
+        .header("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    let hash = format!("{:x}", Sha256::digest(token.trim().as_bytes()));
+    config
+        .api_key_hashes
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(&hash))
+}
+
+pub(crate) fn unauthorized() -> GatewayResponse {
+    GatewayResponse::error(401, "authentication_error", "Missing or invalid API key")
+}
+
+/// Serve the routes that can be answered from a query call
+pub fn handle_query<E: Embedder, S: VectorStore>(
+    pipeline: &RagPipeline<E, S>,
+    config: &GatewayConfig,
+    request: &GatewayRequest,
+) -> Option<GatewayResponse> {
+    if !is_authorized(config, request) {
+        return Some(unauthorized());
+    }
+    if request.method.eq_ignore_ascii_case("GET") && request.path() == "/v1/models" {
+        return Some(models(pipeline));
+    }
+    None
+}
+
+/// Serve the routes that need an update call
+pub async fn handle_update<E: Embedder, S: VectorStore>(
+    pipeline: &RagPipeline<E, S>,
+    config: &GatewayConfig,
+    request: &GatewayRequest,
+) -> GatewayResponse {
+    if !is_authorized(config, request) {
+        return unauthorized();
+    }
+    let path = request.path();
+
+    if !request.method.eq_ignore_ascii_case("POST") {
+        if path == "/v1/models" {
+            return models(pipeline);
+        }
+        return GatewayResponse::error(405, "invalid_request_error", "Method not allowed");
+    }
+
+    if path == "/v1/embeddings" {
+        return embeddings(pipeline, request).await;
+    }
+    if let Some(namespace) = search_namespace(path) {
+        return search(pipeline, &namespace, request).await;
+    }
+
+    GatewayResponse::not_found(path)
+}
+
+fn models<E: Embedder, S: VectorStore>(pipeline: &RagPipeline<E, S>) -> GatewayResponse {
+    GatewayResponse::json(200, &json!({
+        "object": "list",
+        "data": [{
+            "id": pipeline.embedder().name(),
+            "object": "model",
+            "created": 0,
+            "owned_by": "contrag",
+        }],
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EmbeddingInput {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsRequest {
+    input: EmbeddingInput,
+    #[serde(default)]
+    encoding_format: Option<String>,
+}
+
+async fn embeddings<E: Embedder, S: VectorStore>(
+    pipeline: &RagPipeline<E, S>,
+    request: &GatewayRequest,
+) -> GatewayResponse {
+    let body: EmbeddingsRequest = match request.json() {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    let texts = match body.input {
+        EmbeddingInput::One(text) => vec![text],
+        EmbeddingInput::Many(texts) => texts,
+    };
+    let tokens: usize = texts.iter().map(|t| estimate_tokens(t)).sum();
+    let base64 = body.encoding_format.as_deref() == Some("base64");
+
+    let embeddings = match pipeline.embedder().embed(texts).await {
+        Ok(embeddings) => embeddings,
+        Err(e) => return GatewayResponse::error(502, "api_error", &e.to_string()),
+    };
+
+    let data: Vec<serde_json::Value> = embeddings
+        .into_iter()
+        .enumerate()
+        .map(|(index, embedding)| {
+            let embedding = if base64 {
+                // Little-endian f32 bytes, as the OpenAI SDKs expect
+                let bytes: Vec<u8> = embedding.iter().flat_map(|x| x.to_le_bytes()).collect();
+                json!(base64::engine::general_purpose::STANDARD.encode(bytes))
+            } else {
+                json!(embedding)
+            };
+            json!({ "object": "embedding", "index": index, "embedding": embedding })
+        })
+        .collect();
+
+    GatewayResponse::json(200, &json!({
+        "object": "list",
+        "data": data,
+        "model": pipeline.embedder().name(),
+        "usage": { "prompt_tokens": tokens, "total_tokens": tokens },
+    }))
+}
+
+#[derive(Deserialize)]
+struct SearchRequest {
+    query: String,
+    #[serde(default)]
+    max_num_results: Option<usize>,
+}
+
+async fn search<E: Embedder, S: VectorStore>(
+    pipeline: &RagPipeline<E, S>,
+    namespace: &str,
+    request: &GatewayRequest,
+) -> GatewayResponse {
+    let body: SearchRequest = match request.json() {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    let k = body.max_num_results.map(|k| k.clamp(1, MAX_SEARCH_RESULTS));
+    let results = match pipeline.search(namespace, &body.query, k).await {
+        Ok(results) => results,
+        Err(e) => return GatewayResponse::error(502, "api_error", &e.to_string()),
+    };
+
+    GatewayResponse::json(200, &json!({
+        "object": "vector_store.search_results.page",
+        "search_query": body.query,
+        "data": results.iter().map(search_result).collect::<Vec<_>>(),
+        "has_more": false,
+        "next_page": null,
+    }))
+}
+
+fn search_result(result: &SearchResult) -> serde_json::Value {
+    let meta = &result.metadata;
+    json!({
+        "file_id": result.vector_id,
+        "filename": format!("{}:{}", meta.entity_type, meta.entity_id),
+        "score": result.score,
+        "attributes": {
+            "entity_type": meta.entity_type,
+            "entity_id": meta.entity_id,
+            "chunk_index": meta.chunk_index,
+            "total_chunks": meta.total_chunks,
+            "timestamp": meta.timestamp,
+        },
+        "content": [{ "type": "text", "text": result.text }],
+    })
+}
+
+/// Namespace of a `/v1/vector_stores/{namespace}/search` path, URL-decoded
+fn search_namespace(path: &str) -> Option<String> {
+    let encoded = path
+        .strip_prefix(VECTOR_STORES_PREFIX)?
+        .strip_suffix(SEARCH_SUFFIX)?;
+    if encoded.is_empty() || encoded.contains('/') {
+        return None;
+    }
+    percent_decode(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PipelineConfig;
+    use crate::embedders::mock::MockEmbedder;
+    use crate::gateway;
+    use crate::vector_store::stable_memory_store::StableMemoryVectorStore;
+
+    const TOKEN: &str = "sk-gateway";
+
+    fn config() -> GatewayConfig {
+        GatewayConfig {
+            openai_routes: true,
+            api_key_hashes: vec![format!("{:x}", Sha256::digest(TOKEN.as_bytes()))],
+            ..GatewayConfig::default()
+        }
+    }
+
+    fn request(method: &str, path: &str, token: Option<&str>, body: &str) -> GatewayRequest {
+        GatewayRequest {
+            method: method.to_string(),
+            url: path.to_string(),
+            headers: token
+                .map(|token| vec![("authorization".to_string(), format!("Bearer {}", token))])
+                .unwrap_or_default(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn body(response: &GatewayResponse) -> serde_json::Value {
+        serde_json::from_slice(&response.body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_routes_require_an_api_key() {
+        let mut pipeline = RagPipeline::new(MockEmbedder::new(2), StableMemoryVectorStore::new(), PipelineConfig::default());
+        let embeddings = |token| request("POST", "/v1/embeddings", token, r#"{"input":["hi","hello"]}"#);
+
+        // Off by default
+        let response = gateway::handle_update(&mut pipeline, &GatewayConfig::default(), &embeddings(Some(TOKEN))).await;
+        assert_eq!(response.status_code, 404);
+
+        for token in [None, Some("sk-other")] {
+            let response = gateway::handle_update(&mut pipeline, &config(), &embeddings(token)).await;
+            assert_eq!(response.status_code, 401);
+            let models = gateway::handle_query(&pipeline, &config(), &request("GET", "/v1/models", token, ""));
+            assert_eq!(models.status_code, 401);
+        }
+        assert_eq!(pipeline.embedder().calls(), 0);
+
+        let response = gateway::handle_update(&mut pipeline, &config(), &embeddings(Some(TOKEN))).await;
+        assert_eq!(response.status_code, 200);
+        let data = body(&response)["data"].clone();
+        assert_eq!(data[1]["embedding"], json!([5.0, 5.0]));
+
+        let models = gateway::handle_query(&pipeline, &config(), &request("GET", "/v1/models", Some(TOKEN), ""));
+        assert_eq!(body(&models)["data"][0]["id"], "mock");
+    }
+
+    #[tokio::test]
+    async fn test_search_route() {
+        let mut pipeline = RagPipeline::new(MockEmbedder::new(2), StableMemoryVectorStore::new(), PipelineConfig::default());
+        let search = request("POST", "/v1/vector_stores/User%3A1/search", Some(TOKEN), r#"{"query":"orders"}"#);
+
+        let response = gateway::handle_update(&mut pipeline, &config(), &search).await;
+        assert_eq!(response.status_code, 200);
+        assert_eq!(body(&response)["object"], "vector_store.search_results.page");
+
+        let invalid = request("POST", "/v1/vector_stores/User%3A1/search", Some(TOKEN), "{");
+        assert_eq!(gateway::handle_update(&mut pipeline, &config(), &invalid).await.status_code, 400);
+    }
+
+    #[test]
+    fn test_search_route_parsing() {
+        assert_eq!(search_namespace("/v1/vector_stores/User%3A42/search").as_deref(), Some("User:42"));
+        assert_eq!(search_namespace("/v1/vector_stores/User:42/search").as_deref(), Some("User:42"));
+        assert_eq!(search_namespace("/v1/vector_stores//search"), None);
+        assert_eq!(search_namespace("/v1/vector_stores/a/b/search"), None);
+        assert!(is_route("/v1/embeddings"));
+    }
+}
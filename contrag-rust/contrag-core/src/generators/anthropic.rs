@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+use crate::config::{GeneratorConfigDef, OutcallConfig};
+use crate::embedders::http_client::{HttpClient, RequestClass};
+use crate::embedders::provider_error::parse_anthropic_error;
+use crate::error::{ContragError, Result};
+use crate::generators::{Defaults, GenerationRequest, Role, TextGenerator};
+
+/// Version of the Messages API requests are sent for
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Anthropic Messages API generator using HTTP outcalls
+pub struct AnthropicGenerator {
+    api_key: String,
+    defaults: Defaults,
+    api_endpoint: String,
+    http_client: HttpClient,
+}
+
+impl AnthropicGenerator {
+    /// Create a generator for model `model`, e.g. "claude-3-5-haiku-latest"
+    pub fn new(api_key: String, model: String) -> Self {
+        Self {
+            api_key,
+            defaults: Defaults::new(model),
+            api_endpoint: "https://api.anthropic.com/v1/messages".to_string(),
+            http_client: HttpClient::new(),
+        }
+    }
+
+    /// Build from a generator config with `provider = "anthropic"`
+    pub fn from_config(config: &GeneratorConfigDef, outcalls: &OutcallConfig, api_key: String) -> Self {
+        let mut generator = Self::new(api_key, config.model.clone())
+            .with_http_client(HttpClient::new().with_outcall_config(outcalls));
+        generator.defaults.apply(config);
+        if let Some(endpoint) = &config.api_endpoint {
+            generator = generator.with_endpoint(endpoint.clone());
+        }
+        generator
+    }
+
+    /// Create with custom API endpoint
+    pub fn with_endpoint(mut self, endpoint: String) -> Self {
+        self.api_endpoint = endpoint;
+        self
+    }
+
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.defaults.temperature = temperature;
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.defaults.max_tokens = max_tokens;
+        self
+    }
+
+    /// Use a custom HTTP client (e.g. with compression enabled)
+    pub fn with_http_client(mut self, http_client: HttpClient) -> Self {
+        self.http_client = http_client;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl TextGenerator for AnthropicGenerator {
+    fn name(&self) -> &str {
+        "anthropic"
+    }
+
+    async fn generate(&self, request: GenerationRequest) -> Result<String> {
+        let settings = self.defaults.resolve(&request.options);
+        let messages = MessagesRequest::new(&request, &settings);
+
+        let body = serde_json::to_vec(&messages)
+            .map_err(|e| ContragError::SerializationError(e.to_string()))?;
+
+        let headers = vec![
+            ("Content-Type".to_string(), "application/json".to_string()),
+            ("x-api-key".to_string(), self.api_key.clone()),
+            ("anthropic-version".to_string(), ANTHROPIC_VERSION.to_string()),
+        ];
+
+        let response = self
+            .http_client
+            .post_as(RequestClass::Generation, self.api_endpoint.clone(), headers, body)
+            .await?;
+
+        if response.status != 200 {
+            return Err(parse_anthropic_error(&response));
+        }
+
+        let messages_response: MessagesResponse = response.json()?;
+        Ok(messages_response.text())
+    }
+}
+
+// Request/Response types for the Anthropic Messages API
+
+#[derive(Serialize)]
+struct MessagesRequest<'a> {
+    model: &'a str,
+    max_tokens: u32,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<&'a str>,
+    messages: Vec<MessageParam<'a>>,
+}
+
+impl<'a> MessagesRequest<'a> {
+    fn new(request: &'a GenerationRequest, settings: &'a Defaults) -> Self {
+        Self {
+            model: &settings.model,
+            max_tokens: settings.max_tokens,
+            temperature: settings.temperature,
+            system: request.system.as_deref(),
+            messages: request
+                .messages()
+                .map(|(role, content)| MessageParam { role, content })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct MessageParam<'a> {
+    role: Role,
+    content: &'a str,
+}
+
+#[derive(Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+}
+
+impl MessagesResponse {
+    /// Text of all text blocks of the reply
+    fn text(self) -> String {
+        self.content
+            .into_iter()
+            .filter(|block| block.kind == "text")
+            .filter_map(|block| block.text)
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    text: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators::{GenerationOptions, Message};
+
+    #[test]
+    fn test_request_and_response_shape() {
+        let request = GenerationRequest::new("And in EUR?")
+            .with_system("Be brief.")
+            .with_history(vec![Message::user("Price of plan A?"), Message::assistant("$10")])
+            .with_options(GenerationOptions {
+                temperature: Some(0.5),
+                ..GenerationOptions::default()
+            });
+        let settings = Defaults::new("claude-3-5-haiku-latest".to_string()).resolve(&request.options);
+
+        assert_eq!(
+            serde_json::to_value(MessagesRequest::new(&request, &settings)).unwrap(),
+            serde_json::json!({
+                "model": "claude-3-5-haiku-latest",
+                "max_tokens": 1000,
+                "temperature": 0.5,
+                "system": "Be brief.",
+                "messages": [
+                    {"role": "user", "content": "Price of plan A?"},
+                    {"role": "assistant", "content": "$10"},
+                    {"role": "user", "content": "And in EUR?"},
+                ],
+            })
+        );
+
+        let response: MessagesResponse = serde_json::from_str(
+            r#"{"id":"msg_1","type":"message","role":"assistant","content":[{"type":"text","text":"About 9 EUR."}],"stop_reason":"end_turn"}"#,
+        )
+        .unwrap();
+        assert_eq!(response.text(), "About 9 EUR.");
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = GeneratorConfigDef {
+            provider: "anthropic".to_string(),
+            model: "claude-3-5-sonnet-latest".to_string(),
+            temperature: None,
+            max_tokens: Some(300),
+            api_endpoint: None,
+        };
+        let outcalls = OutcallConfig::default();
+        let generator = AnthropicGenerator::from_config(&config, &outcalls, "key".to_string());
+        assert_eq!(generator.defaults.model, "claude-3-5-sonnet-latest");
+        assert_eq!(generator.defaults.max_tokens, 300);
+        assert_eq!(generator.defaults.temperature, crate::generators::DEFAULT_TEMPERATURE);
+
+        assert_eq!(crate::generators::from_config(&config, &outcalls, "key".to_string()).unwrap().name(), "anthropic");
+        let unknown = GeneratorConfigDef { provider: "cohere".to_string(), ..config };
+        assert!(crate::generators::from_config(&unknown, &outcalls, "key".to_string()).is_err());
+    }
+}
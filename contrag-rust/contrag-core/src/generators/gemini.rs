@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use crate::config::{GeneratorConfigDef, OutcallConfig};
+use crate::embedders::http_client::{HttpClient, RequestClass};
+use crate::embedders::provider_error::{error_in_success_body, parse_gemini_error};
+use crate::error::{ContragError, Result};
+use crate::generators::{Defaults, GenerationRequest, Role, TextGenerator};
+
+/// Google Gemini generator using HTTP outcalls
+///
+/// The system prompt is sent as `systemInstruction` and assistant turns
+/// with Gemini's `model` role.
+pub struct GeminiGenerator {
+    api_key: String,
+    defaults: Defaults,
+    api_endpoint: String,
+    http_client: HttpClient,
+}
+
+impl GeminiGenerator {
+    /// Create a generator for model `model`, e.g. "gemini-1.5-flash"
+    pub fn new(api_key: String, model: String) -> Self {
+        Self {
+            api_key,
+            defaults: Defaults::new(model),
+            api_endpoint: "https://generativelanguage.googleapis.com/v1beta/models".to_string(),
+            http_client: HttpClient::new(),
+        }
+    }
+
+    /// Build from a generator config with `provider = "gemini"`
+    pub fn from_config(config: &GeneratorConfigDef, outcalls: &OutcallConfig, api_key: String) -> Self {
+        let mut generator = Self::new(api_key, config.model.clone())
+            .with_http_client(HttpClient::new().with_outcall_config(outcalls));
+        generator.defaults.apply(config);
+        if let Some(endpoint) = &config.api_endpoint {
+            generator = generator.with_endpoint(endpoint.clone());
+        }
+        generator
+    }
+
+    /// Create with custom API endpoint (the `models` collection URL)
+    pub fn with_endpoint(mut self, endpoint: String) -> Self {
+        self.api_endpoint = endpoint;
+        self
+    }
+
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.defaults.temperature = temperature;
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.defaults.max_tokens = max_tokens;
+        self
+    }
+
+    /// Use a custom HTTP client (e.g. with compression enabled)
+    pub fn with_http_client(mut self, http_client: HttpClient) -> Self {
+        self.http_client = http_client;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl TextGenerator for GeminiGenerator {
+    fn name(&self) -> &str {
+        "gemini"
+    }
+
+    async fn generate(&self, request: GenerationRequest) -> Result<String> {
+        let settings = self.defaults.resolve(&request.options);
+        let generate = GenerateRequest::new(&request, &settings);
+
+        let body = serde_json::to_vec(&generate)
+            .map_err(|e| ContragError::SerializationError(e.to_string()))?;
+
+        let headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+
+        let url = format!(
+            "{}/{}:generateContent?key={}",
+            self.api_endpoint, settings.model, self.api_key
+        );
+
+        let response = self.http_client.post_as(RequestClass::Generation, url, headers, body).await?;
+
+        if response.status != 200 || error_in_success_body(&response) {
+            return Err(parse_gemini_error(&response));
+        }
+
+        let generate_response: GenerateResponse = response.json()?;
+        Ok(generate_response
+            .candidates
+            .into_iter()
+            .next()
+            .map(|c| c.content.parts.into_iter().map(|p| p.text).collect())
+            .unwrap_or_default())
+    }
+}
+
+// Request/Response types for the Gemini generateContent API
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content<'a>>,
+    contents: Vec<Content<'a>>,
+    generation_config: GenerationConfig,
+}
+
+impl<'a> GenerateRequest<'a> {
+    fn new(request: &'a GenerationRequest, settings: &Defaults) -> Self {
+        Self {
+            system_instruction: request.system.as_deref().map(|text| Content {
+                role: None,
+                parts: vec![Part { text }],
+            }),
+            contents: request
+                .messages()
+                .map(|(role, text)| Content {
+                    role: Some(match role {
+                        Role::User => "user",
+                        Role::Assistant => "model",
+                    }),
+                    parts: vec![Part { text }],
+                })
+                .collect(),
+            generation_config: GenerationConfig {
+                temperature: settings.temperature,
+                max_output_tokens: settings.max_tokens,
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Content<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'static str>,
+    parts: Vec<Part<'a>>,
+}
+
+#[derive(Serialize)]
+struct Part<'a> {
+    text: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    temperature: f32,
+    max_output_tokens: u32,
+}
+
+#[derive(Deserialize)]
+struct GenerateResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+}
+
+#[derive(Deserialize)]
+struct Candidate {
+    content: ReplyContent,
+}
+
+#[derive(Deserialize)]
+struct ReplyContent {
+    #[serde(default)]
+    parts: Vec<ReplyPart>,
+}
+
+#[derive(Deserialize)]
+struct ReplyPart {
+    #[serde(default)]
+    text: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators::{GenerationOptions, Message};
+
+    #[test]
+    fn test_request_roles_and_options() {
+        let request = GenerationRequest::new("And in EUR?")
+            .with_system("Be brief.")
+            .with_history(vec![Message::user("Price of plan A?"), Message::assistant("$10")])
+            .with_options(GenerationOptions {
+                temperature: Some(0.5),
+                max_tokens: Some(50),
+                ..GenerationOptions::default()
+            });
+        let settings = Defaults::new("gemini-1.5-flash".to_string()).resolve(&request.options);
+
+        assert_eq!(
+            serde_json::to_value(GenerateRequest::new(&request, &settings)).unwrap(),
+            serde_json::json!({
+                "systemInstruction": {"parts": [{"text": "Be brief."}]},
+                "contents": [
+                    {"role": "user", "parts": [{"text": "Price of plan A?"}]},
+                    {"role": "model", "parts": [{"text": "$10"}]},
+                    {"role": "user", "parts": [{"text": "And in EUR?"}]},
+                ],
+                "generationConfig": {"temperature": 0.5, "maxOutputTokens": 50},
+            })
+        );
+    }
+}
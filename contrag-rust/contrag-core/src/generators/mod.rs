@@ -0,0 +1,238 @@
+//! LLM text generation
+//!
+//! Answering, summarization and query routing need a chat model, which is
+//! usually not the embedding model. A [`TextGenerator`] is set on the
+//! pipeline with [`RagPipeline::with_generator`](crate::pipeline::RagPipeline::with_generator);
+//! without one the pipeline falls back to
+//! [`Embedder::generate_with_prompt`](crate::embedders::Embedder::generate_with_prompt).
+//!
+//! ```ignore
+//! let generator = AnthropicGenerator::new(api_key, "claude-3-5-haiku-latest".to_string())
+//!     .with_max_tokens(500);
+//! let pipeline = RagPipeline::new(embedder, store, config.pipeline).with_generator(generator);
+//! ```
+
+pub mod anthropic;
+pub mod gemini;
+pub mod openai;
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::config::{GeneratorConfigDef, OutcallConfig};
+use crate::error::{ContragError, Result};
+use crate::keys::KeyStore;
+
+/// Temperature used when neither the generator nor the request sets one
+pub const DEFAULT_TEMPERATURE: f32 = 0.7;
+
+/// Output token limit used when neither the generator nor the request sets
+/// one
+pub const DEFAULT_MAX_TOKENS: u32 = 1000;
+
+/// Author of a message in a conversation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+/// One earlier turn of a conversation
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CandidType)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+}
+
+impl Message {
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            content: content.into(),
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::Assistant,
+            content: content.into(),
+        }
+    }
+}
+
+/// Per-request overrides of a generator's model and sampling settings
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, CandidType)]
+#[serde(default)]
+pub struct GenerationOptions {
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+/// A prompt for a [`TextGenerator`]: an optional system prompt, earlier
+/// turns of the conversation and the new user message
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, CandidType)]
+pub struct GenerationRequest {
+    pub system: Option<String>,
+    pub history: Vec<Message>,
+    pub user: String,
+    pub options: GenerationOptions,
+}
+
+impl GenerationRequest {
+    pub fn new(user: impl Into<String>) -> Self {
+        Self {
+            user: user.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_system(mut self, system: impl Into<String>) -> Self {
+        self.system = Some(system.into());
+        self
+    }
+
+    /// Earlier turns, oldest first
+    pub fn with_history(mut self, history: Vec<Message>) -> Self {
+        self.history = history;
+        self
+    }
+
+    pub fn with_options(mut self, options: GenerationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// History followed by the user message
+    pub fn messages(&self) -> impl Iterator<Item = (Role, &str)> {
+        self.history
+            .iter()
+            .map(|m| (m.role, m.content.as_str()))
+            .chain(std::iter::once((Role::User, self.user.as_str())))
+    }
+}
+
+/// Settings a generator applies unless a request overrides them
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Defaults {
+    pub model: String,
+    pub temperature: f32,
+    pub max_tokens: u32,
+}
+
+impl Defaults {
+    pub fn new(model: String) -> Self {
+        Self {
+            model,
+            temperature: DEFAULT_TEMPERATURE,
+            max_tokens: DEFAULT_MAX_TOKENS,
+        }
+    }
+
+    /// Take the temperature and token limit a config sets
+    pub fn apply(&mut self, config: &GeneratorConfigDef) {
+        if let Some(temperature) = config.temperature {
+            self.temperature = temperature;
+        }
+        if let Some(max_tokens) = config.max_tokens {
+            self.max_tokens = max_tokens;
+        }
+    }
+
+    /// Settings for `options`, falling back to these defaults
+    pub fn resolve(&self, options: &GenerationOptions) -> Defaults {
+        Defaults {
+            model: options.model.clone().unwrap_or_else(|| self.model.clone()),
+            temperature: options.temperature.unwrap_or(self.temperature),
+            max_tokens: options.max_tokens.unwrap_or(self.max_tokens),
+        }
+    }
+}
+
+/// Trait for LLM providers generating text from a prompt
+///
+/// Implement this trait to add support for additional chat APIs.
+#[async_trait::async_trait]
+pub trait TextGenerator: Send + Sync {
+    /// Get the name of this generator
+    fn name(&self) -> &str;
+
+    /// Generate the reply to `request`
+    async fn generate(&self, request: GenerationRequest) -> Result<String>;
+}
+
+#[async_trait::async_trait]
+impl<G: TextGenerator + ?Sized> TextGenerator for Box<G> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    async fn generate(&self, request: GenerationRequest) -> Result<String> {
+        (**self).generate(request).await
+    }
+}
+
+/// Build the generator named by `config.provider`: "openai", "gemini" or
+/// "anthropic", making outcalls with the limits of `outcalls`
+pub fn from_config(config: &GeneratorConfigDef, outcalls: &OutcallConfig, api_key: String) -> Result<Box<dyn TextGenerator>> {
+    let generator: Box<dyn TextGenerator> = match config.provider.as_str() {
+        "openai" => Box::new(openai::OpenAIGenerator::from_config(config, outcalls, api_key)),
+        "gemini" => Box::new(gemini::GeminiGenerator::from_config(config, outcalls, api_key)),
+        "anthropic" => Box::new(anthropic::AnthropicGenerator::from_config(config, outcalls, api_key)),
+        other => {
+            return Err(ContragError::InvalidConfig(format!(
+                "Unknown generator provider '{}'",
+                other
+            )))
+        }
+    };
+
+    Ok(generator)
+}
+
+/// Build the generator for `config` with the current key of its provider in
+/// `keys`
+///
+/// During a rotation's grace period (at `now`, nanoseconds), requests whose
+/// key the provider rejects are retried with the replaced key.
+pub fn from_key_store(
+    config: &GeneratorConfigDef,
+    outcalls: &OutcallConfig,
+    keys: &KeyStore,
+    now: u64,
+) -> Result<Box<dyn TextGenerator>> {
+    let mut api_keys = keys.keys(&config.provider, now).into_iter();
+    let current = api_keys.next().ok_or_else(|| {
+        ContragError::InvalidConfig(format!("No API key for generator provider '{}'", config.provider))
+    })?;
+
+    let generator = from_config(config, outcalls, current.to_string())?;
+    match api_keys.next() {
+        Some(previous) => Ok(Box::new(RotatedKeyGenerator {
+            current: generator,
+            previous: from_config(config, outcalls, previous.to_string())?,
+        })),
+        None => Ok(generator),
+    }
+}
+
+/// Generator that retries with the key a rotation replaced when the
+/// current key is rejected
+struct RotatedKeyGenerator {
+    current: Box<dyn TextGenerator>,
+    previous: Box<dyn TextGenerator>,
+}
+
+#[async_trait::async_trait]
+impl TextGenerator for RotatedKeyGenerator {
+    fn name(&self) -> &str {
+        self.current.name()
+    }
+
+    async fn generate(&self, request: GenerationRequest) -> Result<String> {
+        match self.current.generate(request.clone()).await {
+            Err(e) if crate::embedders::fallback::is_auth_failure(&e) => self.previous.generate(request).await,
+            result => result,
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use crate::config::{GeneratorConfigDef, OutcallConfig};
+use crate::embedders::http_client::{HttpClient, RequestClass};
+use crate::embedders::provider_error::{error_in_success_body, parse_openai_error};
+use crate::error::{ContragError, Result};
+use crate::generators::{Defaults, GenerationRequest, Role, TextGenerator};
+
+/// OpenAI chat completions generator using HTTP outcalls
+///
+/// Works with any OpenAI-compatible chat completions endpoint set
+/// [`with_endpoint`](Self::with_endpoint).
+pub struct OpenAIGenerator {
+    api_key: String,
+    defaults: Defaults,
+    api_endpoint: String,
+    http_client: HttpClient,
+}
+
+impl OpenAIGenerator {
+    /// Create a generator for chat model `model`, e.g. "gpt-4o-mini"
+    pub fn new(api_key: String, model: String) -> Self {
+        Self {
+            api_key,
+            defaults: Defaults::new(model),
+            api_endpoint: "https://api.openai.com/v1/chat/completions".to_string(),
+            http_client: HttpClient::new(),
+        }
+    }
+
+    /// Build from a generator config with `provider = "openai"`
+    pub fn from_config(config: &GeneratorConfigDef, outcalls: &OutcallConfig, api_key: String) -> Self {
+        let mut generator = Self::new(api_key, config.model.clone())
+            .with_http_client(HttpClient::new().with_outcall_config(outcalls));
+        generator.defaults.apply(config);
+        if let Some(endpoint) = &config.api_endpoint {
+            generator = generator.with_endpoint(endpoint.clone());
+        }
+        generator
+    }
+
+    /// Create with custom API endpoint
+    pub fn with_endpoint(mut self, endpoint: String) -> Self {
+        self.api_endpoint = endpoint;
+        self
+    }
+
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.defaults.temperature = temperature;
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.defaults.max_tokens = max_tokens;
+        self
+    }
+
+    /// Use a custom HTTP client (e.g. with compression enabled)
+    pub fn with_http_client(mut self, http_client: HttpClient) -> Self {
+        self.http_client = http_client;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl TextGenerator for OpenAIGenerator {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    async fn generate(&self, request: GenerationRequest) -> Result<String> {
+        let settings = self.defaults.resolve(&request.options);
+        let chat = ChatRequest::new(&request, &settings);
+
+        let body = serde_json::to_vec(&chat)
+            .map_err(|e| ContragError::SerializationError(e.to_string()))?;
+
+        let headers = vec![
+            ("Content-Type".to_string(), "application/json".to_string()),
+            ("Authorization".to_string(), format!("Bearer {}", self.api_key)),
+        ];
+
+        let response = self
+            .http_client
+            .post_as(RequestClass::Generation, self.api_endpoint.clone(), headers, body)
+            .await?;
+
+        if response.status != 200 || error_in_success_body(&response) {
+            return Err(parse_openai_error(self.name(), &response));
+        }
+
+        let chat_response: ChatResponse = response.json()?;
+        Ok(chat_response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|c| c.message.content)
+            .unwrap_or_default())
+    }
+}
+
+// Request/Response types for the OpenAI chat completions API
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage<'a>>,
+    max_tokens: u32,
+    temperature: f32,
+}
+
+impl<'a> ChatRequest<'a> {
+    fn new(request: &'a GenerationRequest, settings: &'a Defaults) -> Self {
+        let system = request
+            .system
+            .as_deref()
+            .map(|content| ChatMessage { role: "system", content });
+        let turns = request.messages().map(|(role, content)| ChatMessage {
+            role: match role {
+                Role::User => "user",
+                Role::Assistant => "assistant",
+            },
+            content,
+        });
+
+        Self {
+            model: &settings.model,
+            messages: system.into_iter().chain(turns).collect(),
+            max_tokens: settings.max_tokens,
+            temperature: settings.temperature,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ChatMessage<'a> {
+    role: &'static str,
+    content: &'a str,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatReply,
+}
+
+#[derive(Deserialize)]
+struct ChatReply {
+    content: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators::{GenerationOptions, Message};
+
+    #[test]
+    fn test_request_and_response_shape() {
+        let request = GenerationRequest::new("And in EUR?")
+            .with_system("Be brief.")
+            .with_history(vec![Message::user("Price of plan A?"), Message::assistant("$10")])
+            .with_options(GenerationOptions {
+                model: Some("gpt-4o".to_string()),
+                max_tokens: Some(50),
+                ..GenerationOptions::default()
+            });
+        let settings = Defaults::new("gpt-4o-mini".to_string()).resolve(&request.options);
+
+        assert_eq!(
+            serde_json::to_value(ChatRequest::new(&request, &settings)).unwrap(),
+            serde_json::json!({
+                "model": "gpt-4o",
+                "max_tokens": 50,
+                "temperature": crate::generators::DEFAULT_TEMPERATURE,
+                "messages": [
+                    {"role": "system", "content": "Be brief."},
+                    {"role": "user", "content": "Price of plan A?"},
+                    {"role": "assistant", "content": "$10"},
+                    {"role": "user", "content": "And in EUR?"},
+                ],
+            })
+        );
+
+        let response: ChatResponse = serde_json::from_str(
+            r#"{"id":"chatcmpl-1","object":"chat.completion","choices":[{"index":0,"message":{"role":"assistant","content":"About 9 EUR."},"finish_reason":"stop"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(response.choices[0].message.content.as_deref(), Some("About 9 EUR."));
+
+        // Refusals and tool calls come back without content
+        let response: ChatResponse = serde_json::from_str(
+            r#"{"choices":[{"message":{"role":"assistant","content":null,"refusal":"No."}}]}"#,
+        )
+        .unwrap();
+        assert_eq!(response.choices[0].message.content, None);
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = GeneratorConfigDef {
+            provider: "openai".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            temperature: Some(0.2),
+            max_tokens: None,
+            api_endpoint: Some("https://llm.example.com/v1/chat/completions".to_string()),
+        };
+        let outcalls = OutcallConfig::default();
+        let generator = OpenAIGenerator::from_config(&config, &outcalls, "key".to_string());
+        assert_eq!(generator.defaults.model, "gpt-4o-mini");
+        assert_eq!(generator.defaults.temperature, 0.2);
+        assert_eq!(generator.defaults.max_tokens, crate::generators::DEFAULT_MAX_TOKENS);
+        assert_eq!(generator.api_endpoint, "https://llm.example.com/v1/chat/completions");
+    }
+}
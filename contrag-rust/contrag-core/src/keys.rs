@@ -0,0 +1,298 @@
+//! Per-provider API keys with rotation
+//!
+//! A [`KeyStore`] holds one key per provider name (the `provider` of an
+//! embedder or generator config) and is what [`embedders::from_key_store`]
+//! and [`generators::from_key_store`] take their keys from. Rotating a key
+//! keeps the old one available for a grace period, so callers can fall
+//! back to it while the new key propagates at the provider.
+//!
+//! The store is written to the [`ContragMemory::KeyStore`] region
+//! encrypted under a secret the canister supplies, e.g. a key derived with
+//! vetKD, so stable memory snapshots don't expose the keys. The store is
+//! sealed with AES-256-GCM-SIV under a key derived from the secret with
+//! HMAC-SHA256. Canisters have no synchronous randomness, so the nonce is
+//! an HMAC of the plaintext; GCM-SIV stays safe under repeated nonces, and
+//! persisting the same store twice writes the same bytes.
+//!
+//! ```ignore
+//! KEYS.with(|k| k.borrow_mut().rotate("openai", new_key, 3600, get_timestamp()));
+//!
+//! #[pre_upgrade]
+//! fn pre_upgrade() {
+//!     KEYS.with(|k| k.borrow().persist(&key_memory, &secret)).expect("Failed to persist keys");
+//! }
+//! ```
+//!
+//! [`embedders::from_key_store`]: crate::embedders::from_key_store
+//! [`generators::from_key_store`]: crate::generators::from_key_store
+//! [`ContragMemory::KeyStore`]: crate::storage::memory::ContragMemory::KeyStore
+
+use std::collections::BTreeMap;
+use candid::CandidType;
+use ic_stable_structures::Memory;
+use serde::{Deserialize, Serialize};
+use aes_gcm_siv::aead::{Aead, KeyInit};
+use aes_gcm_siv::{Aes256GcmSiv, Nonce};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use crate::error::{ContragError, Result};
+use crate::storage::memory::{read_blob, write_blob};
+use crate::storage::migrations::{encode_versioned, Migrator, StorageComponent};
+
+/// Shortest secret accepted for encrypting a key store
+pub const MIN_SECRET_BYTES: usize = 32;
+
+/// Length of the AES-GCM-SIV nonce in front of the ciphertext
+const NONCE_BYTES: usize = 12;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, CandidType)]
+struct ProviderKeys {
+    current: String,
+    /// Key replaced by the last rotation and the end of its grace period
+    previous: Option<(String, u64)>,
+}
+
+/// A provider's keys without the keys themselves, for listings
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CandidType)]
+pub struct KeyInfo {
+    pub provider: String,
+    /// Last 4 characters of the current key
+    pub current_suffix: String,
+    /// End of the previous key's grace period (nanoseconds), while it lasts
+    pub previous_valid_until: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, CandidType)]
+struct KeyStoreSnapshot {
+    providers: Vec<(String, ProviderKeys)>,
+}
+
+/// API keys by provider
+///
+/// Deliberately not `Debug`, so keys don't end up in logs.
+#[derive(Clone, Default)]
+pub struct KeyStore {
+    providers: BTreeMap<String, ProviderKeys>,
+}
+
+impl KeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the key of `provider`, dropping any previous key at once
+    pub fn set(&mut self, provider: impl Into<String>, key: impl Into<String>) {
+        self.providers.insert(
+            provider.into(),
+            ProviderKeys {
+                current: key.into(),
+                previous: None,
+            },
+        );
+    }
+
+    /// Make `key` the current key of `provider`, keeping the replaced key
+    /// among [`keys`](Self::keys) for `grace_secs` seconds after `now`
+    /// (nanoseconds)
+    pub fn rotate(&mut self, provider: impl Into<String>, key: impl Into<String>, grace_secs: u64, now: u64) {
+        let provider = provider.into();
+        let key = key.into();
+        let valid_until = now.saturating_add(grace_secs.saturating_mul(NANOS_PER_SEC));
+
+        match self.providers.get_mut(&provider) {
+            Some(keys) if keys.current != key => {
+                let replaced = std::mem::replace(&mut keys.current, key);
+                keys.previous = Some((replaced, valid_until));
+            }
+            Some(_) => {}
+            None => self.set(provider, key),
+        }
+    }
+
+    /// Current key of `provider`
+    pub fn current(&self, provider: &str) -> Option<&str> {
+        self.providers.get(provider).map(|keys| keys.current.as_str())
+    }
+
+    /// Keys of `provider` to try, newest first: the current key and, during
+    /// a rotation's grace period, the key it replaced
+    pub fn keys(&self, provider: &str, now: u64) -> Vec<&str> {
+        let Some(keys) = self.providers.get(provider) else {
+            return vec![];
+        };
+        let previous = keys
+            .previous
+            .as_ref()
+            .filter(|(_, valid_until)| now < *valid_until)
+            .map(|(key, _)| key.as_str());
+        std::iter::once(keys.current.as_str()).chain(previous).collect()
+    }
+
+    /// Remove all keys of `provider`; `false` if it had none
+    pub fn remove(&mut self, provider: &str) -> bool {
+        self.providers.remove(provider).is_some()
+    }
+
+    /// Forget replaced keys whose grace period has ended
+    pub fn prune(&mut self, now: u64) {
+        for keys in self.providers.values_mut() {
+            if keys.previous.as_ref().is_some_and(|(_, valid_until)| now >= *valid_until) {
+                keys.previous = None;
+            }
+        }
+    }
+
+    /// Providers with a key, without exposing the keys
+    pub fn list(&self, now: u64) -> Vec<KeyInfo> {
+        self.providers
+            .iter()
+            .map(|(provider, keys)| KeyInfo {
+                provider: provider.clone(),
+                current_suffix: suffix(&keys.current),
+                previous_valid_until: keys
+                    .previous
+                    .as_ref()
+                    .map(|(_, valid_until)| *valid_until)
+                    .filter(|valid_until| now < *valid_until),
+            })
+            .collect()
+    }
+
+    /// Write the store to `memory` encrypted under `secret`, e.g. in
+    /// pre_upgrade
+    pub fn persist(&self, memory: &impl Memory, secret: &[u8]) -> Result<()> {
+        check_secret(secret)?;
+        let snapshot = KeyStoreSnapshot {
+            providers: self.providers.iter().map(|(p, k)| (p.clone(), k.clone())).collect(),
+        };
+        let payload = candid::encode_one(&snapshot)
+            .map_err(|e| ContragError::StorageError(format!("Failed to encode key store: {}", e)))?;
+        write_blob(memory, &encode_versioned(StorageComponent::KeyStore, &seal(secret, &payload)?))
+    }
+
+    /// Load the store [`persist`](Self::persist) wrote to `memory`, e.g. in
+    /// post_upgrade; empty memory gives an empty store
+    ///
+    /// Fails if `secret` is not the one the store was written with.
+    pub fn restore(memory: &impl Memory, secret: &[u8]) -> Result<Self> {
+        check_secret(secret)?;
+        let Some(raw) = read_blob(memory)? else {
+            return Ok(Self::new());
+        };
+        let sealed = Migrator::new().load(StorageComponent::KeyStore, &raw)?;
+        let payload = open(secret, &sealed)?;
+        let snapshot: KeyStoreSnapshot = candid::decode_one(&payload)
+            .map_err(|e| ContragError::StorageError(format!("Failed to decode key store: {}", e)))?;
+
+        Ok(Self {
+            providers: snapshot.providers.into_iter().collect(),
+        })
+    }
+}
+
+fn suffix(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    chars[chars.len().saturating_sub(4)..].iter().collect()
+}
+
+fn check_secret(secret: &[u8]) -> Result<()> {
+    if secret.len() < MIN_SECRET_BYTES {
+        return Err(ContragError::InvalidConfig(format!(
+            "Key store secret must be at least {} bytes",
+            MIN_SECRET_BYTES
+        )));
+    }
+    Ok(())
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+fn cipher(secret: &[u8]) -> Aes256GcmSiv {
+    let key = hmac(secret, b"contrag key store encryption");
+    Aes256GcmSiv::new(&key.into())
+}
+
+/// Encrypt `plaintext` as nonce followed by ciphertext and tag
+fn seal(secret: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let nonce_key = hmac(secret, b"contrag key store nonce");
+    let digest = hmac(&nonce_key, plaintext);
+    let nonce = Nonce::from_slice(&digest[..NONCE_BYTES]);
+
+    let ciphertext = cipher(secret)
+        .encrypt(nonce, plaintext)
+        .map_err(|_| ContragError::StorageError("Failed to encrypt key store".to_string()))?;
+    let mut sealed = Vec::with_capacity(NONCE_BYTES + ciphertext.len());
+    sealed.extend_from_slice(nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypt what [`seal`] produced, checking the tag
+fn open(secret: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+    let invalid = || ContragError::StorageError("Key store secret is wrong or the data is corrupted".to_string());
+    if sealed.len() < NONCE_BYTES {
+        return Err(invalid());
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_BYTES);
+    cipher(secret)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::DefaultMemoryImpl;
+
+    const SECRET: &[u8; 32] = b"0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn test_rotation_grace_period() {
+        let mut keys = KeyStore::new();
+        keys.set("openai", "sk-old1");
+        keys.rotate("openai", "sk-new2", 60, 0);
+
+        assert_eq!(keys.current("openai"), Some("sk-new2"));
+        assert_eq!(keys.keys("openai", 59 * NANOS_PER_SEC), ["sk-new2", "sk-old1"]);
+        assert_eq!(keys.keys("openai", 60 * NANOS_PER_SEC), ["sk-new2"]);
+        assert_eq!(keys.list(0)[0].current_suffix, "new2");
+
+        keys.prune(60 * NANOS_PER_SEC);
+        assert!(keys.list(0)[0].previous_valid_until.is_none());
+        assert!(keys.keys("cohere", 0).is_empty());
+    }
+
+    #[test]
+    fn test_persist_encrypted() {
+        let memory = DefaultMemoryImpl::default();
+        assert!(KeyStore::restore(&memory, SECRET).unwrap().current("openai").is_none());
+
+        let mut keys = KeyStore::new();
+        keys.set("openai", "sk-secret-key");
+        keys.rotate("anthropic", "sk-ant-key", 0, 0);
+        keys.persist(&memory, SECRET).unwrap();
+
+        let mut raw = vec![0u8; 4096];
+        memory.read(0, &mut raw);
+        assert!(!raw.windows(13).any(|w| w == b"sk-secret-key"));
+
+        let restored = KeyStore::restore(&memory, SECRET).unwrap();
+        assert_eq!(restored.current("openai"), Some("sk-secret-key"));
+        assert_eq!(restored.current("anthropic"), Some("sk-ant-key"));
+
+        let wrong = [7u8; 32];
+        assert!(matches!(KeyStore::restore(&memory, &wrong), Err(ContragError::StorageError(_))));
+        let mut sealed = seal(SECRET, b"payload").unwrap();
+        assert_eq!(open(SECRET, &sealed).unwrap(), b"payload");
+        *sealed.last_mut().unwrap() ^= 1;
+        assert!(open(SECRET, &sealed).is_err());
+        assert!(matches!(keys.persist(&memory, b"short"), Err(ContragError::InvalidConfig(_))));
+    }
+}
@@ -1,9 +1,27 @@
+pub mod api;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod concurrency;
 pub mod config;
 pub mod context_builder;
 pub mod data_sources;
+pub mod documents;
 pub mod embedders;
 pub mod entity;
 pub mod error;
+pub mod eval;
+pub mod gateway;
+pub mod generators;
+pub mod keys;
+pub mod logs;
+pub mod monitoring;
+pub mod namespace;
+pub mod pipeline;
+pub mod provenance;
+pub mod queue;
+pub mod retention;
+pub mod slo;
+pub mod storage;
 pub mod types;
 pub mod utils;
 pub mod vector_store;
@@ -11,16 +29,22 @@ pub mod vector_store;
 // Re-exports for convenience
 pub use config::{ContragConfig, EntityConfig, load_config};
 pub use context_builder::ContextBuilder;
+pub use documents::{Chunk, Collection, Document};
 pub use entity::{RagEntity, EntityRelationship, RelationshipType};
 pub use error::{ContragError, Result};
+pub use namespace::Namespace;
+pub use pipeline::RagPipeline;
+pub use queue::{IngestionQueue, IngestionPriority};
 pub use types::*;
 
 // Prelude module for common imports
 pub mod prelude {
     pub use crate::config::{ContragConfig, EntityConfig};
     pub use crate::context_builder::ContextBuilder;
+    pub use crate::documents::{Chunk, Collection, Document};
     pub use crate::entity::{RagEntity, EntityRelationship, RelationshipType};
     pub use crate::error::{ContragError, Result};
+    pub use crate::namespace::Namespace;
     pub use crate::types::*;
     pub use crate::data_sources::DataSource;
     pub use crate::embedders::Embedder;
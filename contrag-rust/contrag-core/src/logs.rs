@@ -0,0 +1,266 @@
+//! Canister logs and metrics as searchable entities
+//!
+//! Events recorded here are buffered in memory and drained by the canister
+//! into the normal ingestion path, so questions like "what failed yesterday
+//! for user X?" can be answered with the same retrieval as any other entity.
+//! Events are stored in one namespace per UTC day, which makes retention a
+//! matter of dropping whole namespaces.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::config::LogConfig;
+use crate::entity::{EntityRelationship, RagEntity, RelationshipType};
+use crate::utils::{format_date, format_timestamp, get_timestamp};
+
+/// Entity type (and namespace prefix) used for log events
+pub const LOG_ENTITY_TYPE: &str = "LogEvent";
+
+const NANOS_PER_DAY: u64 = 86_400 * 1_000_000_000;
+
+/// Severity of a log event
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, CandidType)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// A structured log line or metric sample
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct LogEvent {
+    pub id: String,
+    pub timestamp: u64,
+    pub level: LogLevel,
+    /// Subsystem that emitted the event (e.g. "payments", "monitoring")
+    pub component: String,
+    pub message: String,
+    /// Entity the event is about, if any
+    pub entity_type: Option<String>,
+    pub entity_id: Option<String>,
+    /// Extra structured fields, e.g. metric values
+    pub fields: Vec<(String, String)>,
+}
+
+impl LogEvent {
+    pub fn new(level: LogLevel, component: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            id: String::new(),
+            timestamp: get_timestamp(),
+            level,
+            component: component.into(),
+            message: message.into(),
+            entity_type: None,
+            entity_id: None,
+            fields: vec![],
+        }
+    }
+
+    /// Link the event to the entity it is about
+    pub fn with_subject(mut self, entity_type: impl Into<String>, entity_id: impl Into<String>) -> Self {
+        self.entity_type = Some(entity_type.into());
+        self.entity_id = Some(entity_id.into());
+        self
+    }
+
+    /// Attach a structured field
+    pub fn with_field(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.fields.push((key.into(), value.to_string()));
+        self
+    }
+
+    /// Namespace this event is stored in
+    pub fn namespace(&self) -> String {
+        log_namespace(self.timestamp)
+    }
+}
+
+impl RagEntity for LogEvent {
+    fn entity_type() -> &'static str {
+        LOG_ENTITY_TYPE
+    }
+
+    fn entity_id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn to_context_map(&self) -> Vec<(String, String)> {
+        // A readable date lets queries like "yesterday" or "on Monday" match
+        let mut map = vec![
+            ("time".to_string(), format_timestamp(self.timestamp)),
+            ("level".to_string(), format!("{:?}", self.level)),
+            ("component".to_string(), self.component.clone()),
+            ("message".to_string(), self.message.clone()),
+        ];
+
+        if let (Some(entity_type), Some(entity_id)) = (&self.entity_type, &self.entity_id) {
+            map.push(("subject".to_string(), format!("{} {}", entity_type, entity_id)));
+        }
+
+        map.extend(self.fields.iter().cloned());
+        map
+    }
+
+    fn relationships(&self) -> Vec<EntityRelationship> {
+        match (&self.entity_type, &self.entity_id) {
+            (Some(entity_type), Some(entity_id)) => vec![EntityRelationship {
+                field_name: "subject".to_string(),
+                target_entity_type: entity_type.clone(),
+                target_id: entity_id.clone(),
+                relationship_type: RelationshipType::ManyToOne,
+            }],
+            _ => vec![],
+        }
+    }
+}
+
+/// Bounded buffer of events waiting to be indexed
+///
+/// When full, the oldest events are dropped.
+pub struct LogBuffer {
+    config: LogConfig,
+    events: VecDeque<LogEvent>,
+    next_seq: u64,
+    dropped: u64,
+}
+
+impl LogBuffer {
+    pub fn new(config: LogConfig) -> Self {
+        Self {
+            config,
+            events: VecDeque::new(),
+            next_seq: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Buffer an event, assigning its id; ignored when logging is disabled
+    pub fn record(&mut self, mut event: LogEvent) {
+        if !self.config.enabled || self.config.max_buffered_events == 0 {
+            return;
+        }
+
+        event.id = format!("{}-{}", event.timestamp, self.next_seq);
+        self.next_seq += 1;
+
+        while self.events.len() >= self.config.max_buffered_events {
+            self.events.pop_front();
+            self.dropped += 1;
+        }
+        self.events.push_back(event);
+    }
+
+    /// Take up to `max` of the oldest buffered events for indexing
+    pub fn drain(&mut self, max: usize) -> Vec<LogEvent> {
+        let count = max.min(self.events.len());
+        self.events.drain(..count).collect()
+    }
+
+    /// Number of events waiting to be indexed
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Events dropped because the buffer was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+thread_local! {
+    static BUFFER: RefCell<LogBuffer> = RefCell::new(LogBuffer::new(LogConfig::default()));
+}
+
+/// Apply a log configuration to the global buffer, keeping buffered events
+pub fn configure(config: &LogConfig) {
+    BUFFER.with(|b| {
+        let mut buffer = b.borrow_mut();
+        buffer.config = config.clone();
+        while buffer.events.len() > config.max_buffered_events {
+            buffer.events.pop_front();
+            buffer.dropped += 1;
+        }
+    });
+}
+
+/// Record an event in the global buffer
+pub fn record(event: LogEvent) {
+    BUFFER.with(|b| b.borrow_mut().record(event));
+}
+
+/// Take up to `max` events from the global buffer for indexing
+pub fn drain(max: usize) -> Vec<LogEvent> {
+    BUFFER.with(|b| b.borrow_mut().drain(max))
+}
+
+/// Number of events waiting in the global buffer
+pub fn pending() -> usize {
+    BUFFER.with(|b| b.borrow().len())
+}
+
+/// Namespace holding the log events of the UTC day of `timestamp`
+pub fn log_namespace(timestamp: u64) -> String {
+    format!("{}:{}", LOG_ENTITY_TYPE, format_date(timestamp))
+}
+
+/// Log namespaces whose day is older than `retention_days` before `now`
+///
+/// Day namespaces use ISO dates, so they compare correctly as strings.
+pub fn expired_log_namespaces(namespaces: &[String], now: u64, retention_days: u64) -> Vec<String> {
+    let cutoff = log_namespace(now.saturating_sub(retention_days * NANOS_PER_DAY));
+    let prefix = format!("{}:", LOG_ENTITY_TYPE);
+
+    namespaces
+        .iter()
+        .filter(|ns| ns.starts_with(&prefix) && ns.as_str() < cutoff.as_str())
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_and_retention() {
+        let mut buffer = LogBuffer::new(LogConfig {
+            enabled: true,
+            max_buffered_events: 2,
+            retention_days: 7,
+        });
+
+        for i in 0..3 {
+            buffer.record(
+                LogEvent::new(LogLevel::Error, "payments", format!("charge failed #{}", i))
+                    .with_subject("User", "42"),
+            );
+        }
+
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.dropped(), 1);
+
+        let events = buffer.drain(10);
+        assert_eq!(events[0].message, "charge failed #1");
+        assert_eq!(events[0].relationships()[0].target_id, "42");
+        assert!(buffer.is_empty());
+
+        let day = NANOS_PER_DAY;
+        let now = 20_000 * day;
+        let namespaces = vec![
+            log_namespace(now),
+            log_namespace(now - 7 * day),
+            log_namespace(now - 8 * day),
+            "User".to_string(),
+        ];
+        assert_eq!(
+            expired_log_namespaces(&namespaces, now, 7),
+            vec![log_namespace(now - 8 * day)]
+        );
+    }
+}
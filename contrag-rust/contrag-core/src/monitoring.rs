@@ -0,0 +1,276 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::time::Duration;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::config::MemoryMonitorConfig;
+use crate::error::{ContragError, Result};
+use crate::logs::{self, LogEvent, LogLevel};
+use crate::utils::{format_bytes, get_timestamp};
+
+/// Maximum number of alerts kept for inspection
+const MAX_ALERTS: usize = 100;
+
+/// WASM page size in bytes
+#[cfg(target_family = "wasm")]
+const WASM_PAGE_SIZE: u64 = 65536;
+
+/// Memory kind being monitored
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+pub enum MemoryKind {
+    Heap,
+    Stable,
+}
+
+/// Usage level relative to the configured watermarks
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, CandidType)]
+pub enum MemoryLevel {
+    Normal,
+    Warning,
+    Critical,
+}
+
+/// Alert raised when a memory level changes
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct MemoryAlert {
+    pub timestamp: u64,
+    pub kind: MemoryKind,
+    pub level: MemoryLevel,
+    pub used_bytes: u64,
+    pub threshold_bytes: u64,
+}
+
+/// Snapshot of memory usage after a check
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct MemoryReport {
+    pub timestamp: u64,
+    pub heap_bytes: u64,
+    pub stable_bytes: u64,
+    pub heap_level: MemoryLevel,
+    pub stable_level: MemoryLevel,
+    pub protective_mode: bool,
+}
+
+struct MonitorState {
+    protective_mode: bool,
+    // Whether protective mode was engaged by the monitor rather than manually
+    auto_engaged: bool,
+    heap_level: MemoryLevel,
+    stable_level: MemoryLevel,
+    alerts: VecDeque<MemoryAlert>,
+}
+
+thread_local! {
+    static STATE: RefCell<MonitorState> = RefCell::new(MonitorState {
+        protective_mode: false,
+        auto_engaged: false,
+        heap_level: MemoryLevel::Normal,
+        stable_level: MemoryLevel::Normal,
+        alerts: VecDeque::new(),
+    });
+}
+
+/// Current WASM heap size in bytes
+pub fn heap_bytes() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        core::arch::wasm32::memory_size(0) as u64 * WASM_PAGE_SIZE
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        0
+    }
+}
+
+/// Current stable memory size in bytes
+pub fn stable_bytes() -> u64 {
+    #[cfg(target_family = "wasm")]
+    {
+        ic_cdk::api::stable::stable64_size() * WASM_PAGE_SIZE
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    {
+        0
+    }
+}
+
+/// Measure memory usage and update alerts and protective mode
+pub fn check(config: &MemoryMonitorConfig) -> MemoryReport {
+    evaluate(config, heap_bytes(), stable_bytes())
+}
+
+/// Evaluate the given usage against the configured watermarks
+///
+/// Alerts are raised only when a level changes. With `auto_protect` enabled,
+/// reaching a critical level switches into protective mode, which is lifted
+/// again once both heap and stable memory are back below their warning
+/// thresholds.
+pub fn evaluate(config: &MemoryMonitorConfig, heap: u64, stable: u64) -> MemoryReport {
+    let heap_level = level_for(heap, config.heap_warning_bytes, config.heap_critical_bytes);
+    let stable_level = level_for(stable, config.stable_warning_bytes, config.stable_critical_bytes);
+    let timestamp = get_timestamp();
+
+    STATE.with(|s| {
+        let mut state = s.borrow_mut();
+
+        if heap_level != state.heap_level {
+            let threshold = threshold_for(heap_level, config.heap_warning_bytes, config.heap_critical_bytes);
+            push_alert(&mut state, MemoryAlert {
+                timestamp,
+                kind: MemoryKind::Heap,
+                level: heap_level,
+                used_bytes: heap,
+                threshold_bytes: threshold,
+            });
+            state.heap_level = heap_level;
+        }
+
+        if stable_level != state.stable_level {
+            let threshold = threshold_for(stable_level, config.stable_warning_bytes, config.stable_critical_bytes);
+            push_alert(&mut state, MemoryAlert {
+                timestamp,
+                kind: MemoryKind::Stable,
+                level: stable_level,
+                used_bytes: stable,
+                threshold_bytes: threshold,
+            });
+            state.stable_level = stable_level;
+        }
+
+        if config.auto_protect {
+            let critical = heap_level == MemoryLevel::Critical || stable_level == MemoryLevel::Critical;
+            let recovered = heap_level == MemoryLevel::Normal && stable_level == MemoryLevel::Normal;
+
+            if critical && !state.protective_mode {
+                ic_cdk::println!("contrag: entering read-only protective mode");
+                state.protective_mode = true;
+                state.auto_engaged = true;
+            } else if recovered && state.protective_mode && state.auto_engaged {
+                ic_cdk::println!("contrag: leaving read-only protective mode");
+                state.protective_mode = false;
+                state.auto_engaged = false;
+            }
+        }
+
+        MemoryReport {
+            timestamp,
+            heap_bytes: heap,
+            stable_bytes: stable,
+            heap_level,
+            stable_level,
+            protective_mode: state.protective_mode,
+        }
+    })
+}
+
+/// Whether writes are currently blocked by protective mode
+pub fn is_protective_mode() -> bool {
+    STATE.with(|s| s.borrow().protective_mode)
+}
+
+/// Manually enable or disable protective mode
+///
+/// A manually enabled protective mode is never lifted automatically.
+pub fn set_protective_mode(enabled: bool) {
+    STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        state.protective_mode = enabled;
+        state.auto_engaged = false;
+    });
+}
+
+/// Return an error if writes are blocked by protective mode
+pub fn ensure_writable() -> Result<()> {
+    if is_protective_mode() {
+        return Err(ContragError::StorageError(
+            "Canister is in read-only protective mode due to memory pressure".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Alerts raised so far, oldest first
+pub fn recent_alerts() -> Vec<MemoryAlert> {
+    STATE.with(|s| s.borrow().alerts.iter().cloned().collect())
+}
+
+/// Start a periodic timer that checks memory watermarks
+pub fn start_memory_monitor(config: MemoryMonitorConfig) -> ic_cdk_timers::TimerId {
+    let interval = Duration::from_secs(config.check_interval_secs);
+    ic_cdk_timers::set_timer_interval(interval, move || {
+        check(&config);
+    })
+}
+
+fn level_for(used: u64, warning: u64, critical: u64) -> MemoryLevel {
+    if used >= critical {
+        MemoryLevel::Critical
+    } else if used >= warning {
+        MemoryLevel::Warning
+    } else {
+        MemoryLevel::Normal
+    }
+}
+
+fn threshold_for(level: MemoryLevel, warning: u64, critical: u64) -> u64 {
+    match level {
+        MemoryLevel::Critical => critical,
+        MemoryLevel::Warning | MemoryLevel::Normal => warning,
+    }
+}
+
+fn push_alert(state: &mut MonitorState, alert: MemoryAlert) {
+    ic_cdk::println!(
+        "contrag: {:?} memory {:?} at {} (threshold {})",
+        alert.kind,
+        alert.level,
+        format_bytes(alert.used_bytes),
+        format_bytes(alert.threshold_bytes)
+    );
+
+    let level = match alert.level {
+        MemoryLevel::Normal => LogLevel::Info,
+        MemoryLevel::Warning => LogLevel::Warn,
+        MemoryLevel::Critical => LogLevel::Error,
+    };
+    logs::record(
+        LogEvent::new(level, "monitoring", format!("{:?} memory {:?}", alert.kind, alert.level))
+            .with_field("used_bytes", alert.used_bytes)
+            .with_field("threshold_bytes", alert.threshold_bytes),
+    );
+
+    if state.alerts.len() >= MAX_ALERTS {
+        state.alerts.pop_front();
+    }
+    state.alerts.push_back(alert);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_protective_mode_with_hysteresis() {
+        let config = MemoryMonitorConfig {
+            heap_warning_bytes: 100,
+            heap_critical_bytes: 200,
+            stable_warning_bytes: 1000,
+            stable_critical_bytes: 2000,
+            check_interval_secs: 60,
+            auto_protect: true,
+        };
+
+        assert!(!evaluate(&config, 50, 0).protective_mode);
+        assert!(evaluate(&config, 250, 0).protective_mode);
+        assert!(ensure_writable().is_err());
+
+        // Still protective while in the warning band
+        assert!(evaluate(&config, 150, 0).protective_mode);
+        assert!(!evaluate(&config, 50, 0).protective_mode);
+        assert!(ensure_writable().is_ok());
+
+        assert_eq!(recent_alerts().len(), 3);
+    }
+}
@@ -0,0 +1,249 @@
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use crate::entity::RagEntity;
+use crate::error::{ContragError, Result};
+
+/// Maximum length of a namespace in bytes
+pub const MAX_NAMESPACE_LEN: usize = 256;
+
+/// Separator between namespace segments
+pub const SEPARATOR: char = ':';
+
+const TENANT_PREFIX: &str = "tenant";
+
+/// A validated vector store namespace
+///
+/// Namespaces are `:`-separated segments such as `User:42` or
+/// `tenant:<principal>:User:42`. Building them through the constructors
+/// instead of `format!` keeps writers and readers agreeing on the layout.
+/// Dereferences to `&str`, so it can be passed to any `VectorStore` method.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, CandidType)]
+#[serde(try_from = "String", into = "String")]
+pub struct Namespace(String);
+
+impl Namespace {
+    /// Parse and validate a raw namespace
+    pub fn parse(raw: &str) -> Result<Self> {
+        validate(raw)?;
+        Ok(Self(raw.to_string()))
+    }
+
+    /// Namespace of one entity, e.g. `User:42`
+    ///
+    /// Fails if the entity type or ID is empty or contains the separator: an
+    /// ID like `42:orders` would land in a child namespace of `User:42`.
+    pub fn entity<T: RagEntity>(entity_id: &str) -> Result<Self> {
+        validate_segment(T::entity_type())?;
+        validate_segment(entity_id)?;
+        Self::parse(&format!("{}{}{}", T::entity_type(), SEPARATOR, entity_id))
+    }
+
+    /// Namespace shared by all entities of a type, e.g. `User`
+    pub fn entity_type<T: RagEntity>() -> Result<Self> {
+        validate_segment(T::entity_type())?;
+        Self::parse(T::entity_type())
+    }
+
+    /// Scope for the namespaces of one tenant
+    pub fn tenant(principal: &Principal) -> TenantScope {
+        TenantScope {
+            prefix: format!("{}{}{}", TENANT_PREFIX, SEPARATOR, principal.to_text()),
+        }
+    }
+
+    /// Append a segment, e.g. `User:42` + `orders` = `User:42:orders`
+    pub fn child(&self, segment: &str) -> Result<Self> {
+        validate_segment(segment)?;
+        Self::parse(&format!("{}{}{}", self.0, SEPARATOR, segment))
+    }
+
+    /// The `:`-separated segments
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.0.split(SEPARATOR)
+    }
+
+    /// Tenant principal text if this is a tenant namespace
+    pub fn tenant_id(&self) -> Option<&str> {
+        let mut segments = self.segments();
+        match (segments.next(), segments.next()) {
+            (Some(TENANT_PREFIX), Some(tenant)) => Some(tenant),
+            _ => None,
+        }
+    }
+
+    /// Whether this namespace equals `prefix` or lies below it
+    pub fn starts_with(&self, prefix: &Namespace) -> bool {
+        self.0 == prefix.0
+            || (self.0.starts_with(&prefix.0) && self.0[prefix.0.len()..].starts_with(SEPARATOR))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+/// Namespaces of a single tenant, prefixed with `tenant:<principal>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TenantScope {
+    prefix: String,
+}
+
+impl TenantScope {
+    /// Tenant namespace of one entity, e.g. `tenant:<principal>:User:42`
+    ///
+    /// Validates the entity type and ID like [`Namespace::entity`].
+    pub fn entity<T: RagEntity>(&self, entity_id: &str) -> Result<Namespace> {
+        Namespace::entity::<T>(entity_id)
+            .and_then(|entity| Namespace::parse(&format!("{}{}{}", self.prefix, SEPARATOR, entity)))
+    }
+
+    /// Tenant namespace with a custom suffix, e.g. `tenant:<principal>:docs`
+    pub fn child(&self, segment: &str) -> Result<Namespace> {
+        Namespace::parse(&format!("{}{}{}", self.prefix, SEPARATOR, segment))
+    }
+
+    /// Namespace covering everything of this tenant, for prefix operations
+    pub fn root(&self) -> Namespace {
+        Namespace(self.prefix.clone())
+    }
+}
+
+/// Check that `segment` is exactly one segment: non-empty, without the
+/// separator; its characters are checked with the whole namespace
+fn validate_segment(segment: &str) -> Result<()> {
+    if segment.is_empty() || segment.contains(SEPARATOR) {
+        return Err(ContragError::InvalidConfig(format!(
+            "Invalid namespace segment '{}': must be non-empty and must not contain '{}'",
+            segment, SEPARATOR
+        )));
+    }
+    Ok(())
+}
+
+fn validate(raw: &str) -> Result<()> {
+    let invalid = |reason: &str| {
+        Err(ContragError::InvalidConfig(format!(
+            "Invalid namespace '{}': {}",
+            raw, reason
+        )))
+    };
+
+    if raw.is_empty() {
+        return invalid("must not be empty");
+    }
+    if raw.len() > MAX_NAMESPACE_LEN {
+        return invalid("too long");
+    }
+    if raw.split(SEPARATOR).any(|segment| segment.is_empty()) {
+        return invalid("segments must not be empty");
+    }
+    if let Some(c) = raw
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, ':' | '-' | '_' | '.' | '@')))
+    {
+        return invalid(&format!("unsupported character {:?}", c));
+    }
+
+    Ok(())
+}
+
+impl Deref for Namespace {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Namespace {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for Namespace {
+    type Err = ContragError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<String> for Namespace {
+    type Error = ContragError;
+
+    fn try_from(value: String) -> Result<Self> {
+        validate(&value)?;
+        Ok(Self(value))
+    }
+}
+
+impl From<Namespace> for String {
+    fn from(namespace: Namespace) -> Self {
+        namespace.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::EntityRelationship;
+
+    #[derive(CandidType, Serialize, Clone)]
+    struct User;
+
+    impl RagEntity for User {
+        fn entity_type() -> &'static str {
+            "User"
+        }
+
+        fn entity_id(&self) -> String {
+            String::new()
+        }
+
+        fn to_context_map(&self) -> Vec<(String, String)> {
+            vec![]
+        }
+
+        fn relationships(&self) -> Vec<EntityRelationship> {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn test_namespace_construction_and_validation() {
+        assert_eq!(Namespace::entity::<User>("42").unwrap().as_str(), "User:42");
+        assert_eq!(Namespace::entity_type::<User>().unwrap().as_str(), "User");
+        assert!(Namespace::entity::<User>("42:orders").is_err());
+        assert!(Namespace::entity::<User>("").is_err());
+        assert!(Namespace::entity::<User>("4 2").is_err());
+
+        let tenant = Namespace::tenant(&Principal::anonymous());
+        assert!(tenant.entity::<User>("a:b").is_err());
+        assert!(tenant.child("docs:2024").is_ok());
+        assert!(Namespace::entity::<User>("42").unwrap().child("a:b").is_err());
+        let ns = tenant.entity::<User>("42").unwrap();
+        assert_eq!(ns.as_str(), "tenant:2vxsx-fae:User:42");
+        assert_eq!(ns.tenant_id(), Some("2vxsx-fae"));
+        assert!(ns.starts_with(&tenant.root()));
+        assert!(!Namespace::parse("tenant:2vxsx-faeX").unwrap().starts_with(&tenant.root()));
+
+        assert!(Namespace::parse("User:42").is_ok());
+        assert!(Namespace::parse("User::42").is_err());
+        assert!(Namespace::parse("User 42").is_err());
+        assert!(Namespace::parse("").is_err());
+    }
+}
@@ -0,0 +1,83 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// Progress of ingesting a whole entity collection
+///
+/// Serializable so a canister can keep it in state and resume the ingestion
+/// page by page from a timer.
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct IngestAllProgress {
+    pub entity_type: String,
+    /// Cursor of the next page to read; `None` before the first page
+    pub cursor: Option<String>,
+    pub pages: u64,
+    pub entities: u64,
+    pub chunks: u64,
+    /// Entities that could not be ingested
+    pub failed: u64,
+    pub last_error: Option<String>,
+    pub finished: bool,
+    pub started_at: u64,
+    pub updated_at: u64,
+}
+
+impl IngestAllProgress {
+    pub fn new(entity_type: impl Into<String>, now: u64) -> Self {
+        Self {
+            entity_type: entity_type.into(),
+            cursor: None,
+            pages: 0,
+            entities: 0,
+            chunks: 0,
+            failed: 0,
+            last_error: None,
+            finished: false,
+            started_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// Outcome of a differential sync against a remote collection
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct SyncReport {
+    pub entity_type: String,
+    /// Entities not indexed before
+    pub added: u64,
+    /// Entities changed since they were indexed
+    pub updated: u64,
+    pub unchanged: u64,
+    /// Indexed entities no longer in the remote collection
+    pub deleted: u64,
+    pub failed: u64,
+    pub last_error: Option<String>,
+}
+
+/// Parse an `updated_at` field value into nanoseconds since the epoch
+///
+/// Integers are read as seconds, milliseconds or nanoseconds depending on
+/// their magnitude, so the usual timestamp conventions all work.
+pub fn parse_updated_at(value: &str) -> Option<u64> {
+    let value: u64 = value.trim().parse().ok()?;
+
+    Some(if value < 100_000_000_000 {
+        value.saturating_mul(1_000_000_000)
+    } else if value < 100_000_000_000_000 {
+        value.saturating_mul(1_000_000)
+    } else {
+        value
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_updated_at() {
+        assert_eq!(parse_updated_at("1700000000"), Some(1_700_000_000_000_000_000));
+        assert_eq!(parse_updated_at("1700000000000"), Some(1_700_000_000_000_000_000));
+        assert_eq!(parse_updated_at("1700000000000000000"), Some(1_700_000_000_000_000_000));
+        assert_eq!(parse_updated_at("yesterday"), None);
+    }
+}
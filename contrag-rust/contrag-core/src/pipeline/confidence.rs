@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::types::SearchResult;
+
+/// Words ignored when measuring how much of a question the context covers
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "are", "was", "were", "what", "which", "who", "whom", "when", "where",
+    "why", "how", "did", "does", "has", "have", "had", "with", "from", "that", "this", "these",
+    "those", "about", "into", "any", "all", "can", "could", "should", "would", "will", "his",
+    "her", "its", "their", "them", "they", "you", "your", "our", "not", "but", "there",
+];
+
+/// Weight of the retrieval component in the combined score
+const RETRIEVAL_WEIGHT: f32 = 0.6;
+/// Weight of the term coverage component in the combined score
+const COVERAGE_WEIGHT: f32 = 0.4;
+/// Share of the combined score given to the LLM self-assessment, when present
+const SELF_ASSESSMENT_WEIGHT: f32 = 0.3;
+
+/// Prompt asking the model to grade its own answer
+pub const SELF_ASSESSMENT_PROMPT: &str = "You grade answers. Given a context, a question and an \
+answer, reply with a single number between 0 and 1 saying how well the answer is supported by \
+the context. Reply with the number only.";
+
+/// How much an answer can be trusted
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct Confidence {
+    /// Combined score in `0.0..=1.0`
+    pub score: f32,
+    /// Strength of the retrieved matches
+    pub retrieval: f32,
+    /// Fraction of the question's terms found in the retrieved context
+    pub coverage: f32,
+    /// The model's own grade of the answer, if requested
+    pub self_assessment: Option<f32>,
+    /// Whether the score is below the configured minimum
+    pub low: bool,
+}
+
+/// Strength of the retrieved matches in `0.0..=1.0`
+///
+/// Blends the best score with the mean of the top three, so a single lucky
+/// match counts for less than several good ones.
+pub fn retrieval_score(results: &[SearchResult]) -> f32 {
+    if results.is_empty() {
+        return 0.0;
+    }
+
+    let top = results.iter().map(|r| r.score).fold(f32::MIN, f32::max);
+    let top3: Vec<f32> = results.iter().take(3).map(|r| r.score).collect();
+    let mean = top3.iter().sum::<f32>() / top3.len() as f32;
+
+    (0.7 * top + 0.3 * mean).clamp(0.0, 1.0)
+}
+
+/// Fraction of the question's content words present in the results
+pub fn term_coverage(question: &str, results: &[SearchResult]) -> f32 {
+    let terms = content_terms(question);
+    if terms.is_empty() {
+        return 1.0;
+    }
+
+    let context: HashSet<String> = results
+        .iter()
+        .flat_map(|r| content_terms(&r.text))
+        .collect();
+
+    terms.iter().filter(|t| context.contains(*t)).count() as f32 / terms.len() as f32
+}
+
+/// Parse a self-assessment reply like "0.8", "80%" or "Score: 8/10"
+pub fn parse_self_assessment(reply: &str) -> Option<f32> {
+    let start = reply.find(|c: char| c.is_ascii_digit())?;
+    let rest = &reply[start..];
+    let end = rest
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(rest.len());
+    let value: f32 = rest[..end].parse().ok()?;
+    let suffix = rest[end..].trim_start();
+
+    let normalized = if suffix.starts_with('%') {
+        value / 100.0
+    } else if let Some(denominator) = suffix.strip_prefix('/') {
+        let digits: String = denominator.trim_start().chars().take_while(|c| c.is_ascii_digit()).collect();
+        value / digits.parse::<f32>().ok().filter(|d| *d > 0.0)?
+    } else if value > 1.0 {
+        value / 100.0
+    } else {
+        value
+    };
+
+    Some(normalized.clamp(0.0, 1.0))
+}
+
+/// Combine the components into a [`Confidence`]
+pub fn score(
+    question: &str,
+    results: &[SearchResult],
+    self_assessment: Option<f32>,
+    min_confidence: f32,
+) -> Confidence {
+    let retrieval = retrieval_score(results);
+    let coverage = term_coverage(question, results);
+    let base = RETRIEVAL_WEIGHT * retrieval + COVERAGE_WEIGHT * coverage;
+
+    let score = match self_assessment {
+        Some(grade) => (1.0 - SELF_ASSESSMENT_WEIGHT) * base + SELF_ASSESSMENT_WEIGHT * grade,
+        None => base,
+    };
+
+    Confidence {
+        score,
+        retrieval,
+        coverage,
+        self_assessment,
+        low: score < min_confidence,
+    }
+}
+
+fn content_terms(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= 3)
+        .map(|w| w.to_lowercase())
+        .filter(|w| !STOPWORDS.contains(&w.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_self_assessment() {
+        assert_eq!(parse_self_assessment("0.8"), Some(0.8));
+        assert_eq!(parse_self_assessment("Confidence: 75%"), Some(0.75));
+        assert_eq!(parse_self_assessment("8/10"), Some(0.8));
+        assert_eq!(parse_self_assessment("no idea"), None);
+    }
+}
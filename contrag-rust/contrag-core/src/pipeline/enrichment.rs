@@ -0,0 +1,102 @@
+use serde_json::{Map, Value};
+
+/// A chunk about to be embedded and stored
+#[derive(Clone, Copy, Debug)]
+pub struct ChunkInfo<'a> {
+    pub entity_type: &'a str,
+    pub entity_id: &'a str,
+    pub chunk_index: usize,
+    pub total_chunks: usize,
+    pub text: &'a str,
+}
+
+/// Hook computing custom metadata for each chunk at ingestion
+///
+/// Returned fields (e.g. sentiment, category or numeric features) are merged
+/// into the chunk's `custom` metadata as a JSON object, where search filters
+/// and [`Scorer`](crate::vector_store::scoring::Scorer)s can read them back
+/// with [`VectorMetadata::custom_field`](crate::types::VectorMetadata::custom_field).
+/// Later enrichers overwrite fields set by earlier ones.
+///
+/// Any `Fn(&ChunkInfo) -> Map<String, Value>` is an enricher, e.g. one
+/// recording the chunk length:
+///
+/// ```rust
+/// use contrag_core::pipeline::enrichment::{ChunkEnricher, ChunkInfo};
+/// use serde_json::{json, Map, Value};
+///
+/// let length = |chunk: &ChunkInfo| {
+///     let mut fields = Map::new();
+///     fields.insert("chars".to_string(), json!(chunk.text.len()));
+///     fields
+/// };
+/// let _: &dyn ChunkEnricher = &length;
+/// ```
+pub trait ChunkEnricher: Send + Sync {
+    fn enrich(&self, chunk: &ChunkInfo) -> Map<String, Value>;
+}
+
+impl<F> ChunkEnricher for F
+where
+    F: Fn(&ChunkInfo) -> Map<String, Value> + Send + Sync,
+{
+    fn enrich(&self, chunk: &ChunkInfo) -> Map<String, Value> {
+        self(chunk)
+    }
+}
+
+/// Run all enrichers on a chunk and serialize the merged fields
+///
+/// Returns `None` when no enricher produced a field.
+pub fn enrich_chunk(enrichers: &[Box<dyn ChunkEnricher>], chunk: &ChunkInfo) -> Option<String> {
+    let mut fields = Map::new();
+    for enricher in enrichers {
+        fields.extend(enricher.enrich(chunk));
+    }
+
+    if fields.is_empty() {
+        None
+    } else {
+        Some(Value::Object(fields).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::types::{test_vector, VectorMetadata};
+
+    #[test]
+    fn test_enrichers_merge_into_custom_metadata() {
+        let category = |chunk: &ChunkInfo| {
+            let mut fields = Map::new();
+            fields.insert("category".to_string(), json!(chunk.entity_type.to_lowercase()));
+            fields.insert("score".to_string(), json!(0.1));
+            fields
+        };
+        let score = |chunk: &ChunkInfo| {
+            let mut fields = Map::new();
+            fields.insert("score".to_string(), json!(chunk.text.len()));
+            fields
+        };
+        let enrichers: Vec<Box<dyn ChunkEnricher>> = vec![Box::new(category), Box::new(score)];
+
+        let chunk = ChunkInfo {
+            entity_type: "Order",
+            entity_id: "1",
+            chunk_index: 0,
+            total_chunks: 1,
+            text: "shipped",
+        };
+        let metadata = VectorMetadata {
+            entity_type: "Order".to_string(),
+            custom: enrich_chunk(&enrichers, &chunk),
+            ..test_vector("1", vec![]).metadata
+        };
+
+        assert_eq!(metadata.custom_field("category"), Some(json!("order")));
+        assert_eq!(metadata.custom_field("score").and_then(|v| v.as_f64()), Some(7.0));
+        assert_eq!(enrich_chunk(&[], &chunk), None);
+    }
+}
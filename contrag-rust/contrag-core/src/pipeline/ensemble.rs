@@ -0,0 +1,147 @@
+use std::collections::{HashMap, HashSet};
+use crate::embedders::Embedder;
+use crate::types::SearchResult;
+
+/// Prefix of the companion namespaces holding secondary embeddings
+pub const ENSEMBLE_PREFIX: &str = "ensemble";
+
+/// Candidates fetched per result slot from each provider before combining
+pub const ENSEMBLE_OVERFETCH: usize = 2;
+
+/// A second embedding provider scored alongside the pipeline's embedder
+///
+/// Chunks ingested into an ensemble namespace are embedded by both
+/// providers. The secondary embeddings live in a companion namespace
+/// (`ensemble:<namespace>`) under the same vector IDs, and queries are
+/// scored against both as `(1 - weight) * primary + weight * secondary`,
+/// so a chunk one model ranks poorly can still surface through the other.
+pub struct Ensemble {
+    embedder: Box<dyn Embedder>,
+    weight: f32,
+    namespaces: HashSet<String>,
+}
+
+impl Ensemble {
+    /// Ensemble with `weight` (clamped to 0..=1) for the secondary scores
+    ///
+    /// Applies to every namespace unless restricted with
+    /// [`Ensemble::with_namespace`].
+    pub fn new(embedder: impl Embedder + 'static, weight: f32) -> Self {
+        Self {
+            embedder: Box::new(embedder),
+            weight: weight.clamp(0.0, 1.0),
+            namespaces: HashSet::new(),
+        }
+    }
+
+    /// Restrict the ensemble to a namespace; may be called repeatedly
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespaces.insert(namespace.into());
+        self
+    }
+
+    pub fn embedder(&self) -> &dyn Embedder {
+        self.embedder.as_ref()
+    }
+
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+
+    /// Whether chunks of `namespace` get secondary embeddings
+    pub fn applies_to(&self, namespace: &str) -> bool {
+        !is_ensemble_namespace(namespace)
+            && (self.namespaces.is_empty() || self.namespaces.contains(namespace))
+    }
+}
+
+/// Companion namespace holding the secondary embeddings of `namespace`
+pub fn ensemble_namespace(namespace: &str) -> String {
+    format!("{}:{}", ENSEMBLE_PREFIX, namespace)
+}
+
+pub fn is_ensemble_namespace(namespace: &str) -> bool {
+    namespace
+        .strip_prefix(ENSEMBLE_PREFIX)
+        .is_some_and(|rest| rest.starts_with(':'))
+}
+
+/// Combine the hits of both providers into the top `k` by weighted score
+///
+/// A chunk only one provider returned gets that provider's lowest returned
+/// score for the other, since it ranked below everything it did return.
+pub fn combine(primary: Vec<SearchResult>, secondary: Vec<SearchResult>, weight: f32, k: usize) -> Vec<SearchResult> {
+    let floor = |results: &[SearchResult]| results.iter().map(|r| r.score).fold(f32::INFINITY, f32::min);
+    let (primary_floor, secondary_floor) = (floor(&primary), floor(&secondary));
+
+    let secondary_scores: HashMap<String, f32> = secondary
+        .iter()
+        .map(|r| (r.vector_id.clone(), r.score))
+        .collect();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut combined = vec![];
+
+    for mut result in primary {
+        let secondary_score = secondary_scores
+            .get(&result.vector_id)
+            .copied()
+            .unwrap_or(secondary_floor);
+        result.score = weighted(result.score, secondary_score, weight);
+        seen.insert(result.vector_id.clone());
+        combined.push(result);
+    }
+
+    for mut result in secondary {
+        if seen.contains(&result.vector_id) {
+            continue;
+        }
+        result.score = weighted(primary_floor, result.score, weight);
+        combined.push(result);
+    }
+
+    combined.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    combined.truncate(k);
+    combined
+}
+
+fn weighted(primary: f32, secondary: f32, weight: f32) -> f32 {
+    // An infinite floor means the other provider returned nothing at all
+    match (primary.is_finite(), secondary.is_finite()) {
+        (true, true) => (1.0 - weight) * primary + weight * secondary,
+        (true, false) => primary,
+        _ => secondary,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::test_vector;
+
+    fn hit(id: &str, score: f32) -> SearchResult {
+        SearchResult {
+            vector_id: id.to_string(),
+            text: id.to_string(),
+            score,
+            metadata: test_vector(id, vec![]).metadata,
+        }
+    }
+
+    #[test]
+    fn test_combine_weights_both_providers() {
+        let primary = vec![hit("a", 0.9), hit("b", 0.8), hit("c", 0.5)];
+        let secondary = vec![hit("b", 0.9), hit("d", 0.85), hit("a", 0.4)];
+
+        let combined = combine(primary, secondary, 0.5, 3);
+        let ids: Vec<&str> = combined.iter().map(|r| r.vector_id.as_str()).collect();
+        // b: 0.85, d: (0.5 + 0.85) / 2, a: 0.65
+        assert_eq!(ids, ["b", "d", "a"]);
+        assert!((combined[0].score - 0.85).abs() < 1e-6);
+
+        let only_primary = combine(vec![hit("a", 0.9)], vec![], 0.5, 3);
+        assert_eq!(only_primary[0].score, 0.9);
+
+        assert!(is_ensemble_namespace(&ensemble_namespace("User:1")));
+        assert!(!is_ensemble_namespace("ensembles:1"));
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::utils::stable_hash;
+
+/// Outcome of claiming an idempotency key
+#[derive(Clone, Debug, PartialEq)]
+pub enum Claim<T> {
+    /// First time this key is seen; run the operation and call `complete`
+    New,
+    /// The operation already ran; return its result instead
+    Replay(T),
+    /// The first request with this key has not finished yet
+    InProgress,
+    /// The key was used before for a different request
+    Conflict,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Entry<T> {
+    fingerprint: u64,
+    result: Option<T>,
+    expires_at: u64,
+}
+
+/// Results of write operations by client-supplied idempotency key
+///
+/// A client retrying a timed-out call with the same key gets the original
+/// result instead of the operation running twice. Keys expire after the
+/// TTL; when full, the entry closest to expiry is evicted. The cache is
+/// serializable so canisters can keep it across upgrades.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IdempotencyCache<T> {
+    ttl_ns: u64,
+    max_entries: usize,
+    entries: HashMap<String, Entry<T>>,
+}
+
+impl<T: Clone> IdempotencyCache<T> {
+    pub fn new(ttl_secs: u64, max_entries: usize) -> Self {
+        Self {
+            ttl_ns: ttl_secs.saturating_mul(1_000_000_000),
+            max_entries,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Claim `key` for a request identified by `fingerprint`
+    pub fn claim(&mut self, key: &str, fingerprint: u64, now: u64) -> Claim<T> {
+        if let Some(entry) = self.entries.get(key) {
+            if entry.expires_at > now {
+                if entry.fingerprint != fingerprint {
+                    return Claim::Conflict;
+                }
+                return match &entry.result {
+                    Some(result) => Claim::Replay(result.clone()),
+                    None => Claim::InProgress,
+                };
+            }
+        }
+
+        self.purge_expired(now);
+        if self.entries.len() >= self.max_entries {
+            self.evict_one();
+        }
+
+        self.entries.insert(
+            key.to_string(),
+            Entry {
+                fingerprint,
+                result: None,
+                expires_at: now.saturating_add(self.ttl_ns),
+            },
+        );
+        Claim::New
+    }
+
+    /// Record the result of a claimed key
+    pub fn complete(&mut self, key: &str, result: T) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.result = Some(result);
+        }
+    }
+
+    /// Forget a claimed key after a failure so a retry runs again
+    pub fn release(&mut self, key: &str) {
+        self.entries.remove(key);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn purge_expired(&mut self, now: u64) {
+        self.entries.retain(|_, entry| entry.expires_at > now);
+    }
+
+    fn evict_one(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.expires_at)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = oldest {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+/// Fingerprint of a request's parameters
+///
+/// Parts are NUL-separated, so `["ab", "c"]` and `["a", "bc"]` differ.
+pub fn fingerprint(parts: &[&str]) -> u64 {
+    stable_hash(&parts.join("\0"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_lifecycle() {
+        let mut cache: IdempotencyCache<usize> = IdempotencyCache::new(60, 2);
+        let request = fingerprint(&["User:42", "User", "42"]);
+
+        assert_eq!(cache.claim("k1", request, 0), Claim::New);
+        assert_eq!(cache.claim("k1", request, 1), Claim::InProgress);
+
+        cache.complete("k1", 3);
+        assert_eq!(cache.claim("k1", request, 2), Claim::Replay(3));
+        assert_eq!(cache.claim("k1", fingerprint(&["other"]), 2), Claim::Conflict);
+
+        // Expired keys run again
+        assert_eq!(cache.claim("k1", request, 61_000_000_000), Claim::New);
+
+        cache.release("k1");
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,1729 @@
+pub mod collection;
+pub mod confidence;
+pub mod enrichment;
+pub mod ensemble;
+pub mod idempotency;
+pub mod pinned;
+pub mod prompt;
+pub mod query;
+pub mod query_cache;
+pub mod result_cache;
+pub mod router;
+pub mod summarize;
+pub mod tool;
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::time::Duration;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::config::{ChunkingConfig, FallbackStrategy, PipelineConfig, RetrievalProfile};
+use crate::context_builder::ContextBuilder;
+use crate::data_sources::DataSource;
+use crate::documents::{Chunk, Document};
+use crate::embedders::Embedder;
+use crate::entity::RagEntity;
+use crate::generators::{GenerationRequest, TextGenerator};
+use crate::error::{ContragError, Result};
+use crate::namespace::Namespace;
+use crate::provenance::{clear_provenance, get_provenance, record_provenance, Provenance, ProvenanceMismatch};
+use crate::slo;
+use crate::types::{
+    BatchMode, BulkCursor, NamespaceListRequest, SearchResult, Vector, VectorMetadata, MAX_NAMESPACE_PAGE_SIZE,
+};
+use crate::utils::{generate_vector_id, get_timestamp};
+use crate::vector_store::{cap_per_entity, prefix_page_request, VectorStore};
+use collection::{parse_updated_at, IngestAllProgress, SyncReport};
+use confidence::{Confidence, SELF_ASSESSMENT_PROMPT};
+use enrichment::{enrich_chunk, ChunkEnricher, ChunkInfo};
+use ensemble::{combine, ensemble_namespace, Ensemble, ENSEMBLE_OVERFETCH};
+use idempotency::{fingerprint, Claim, IdempotencyCache};
+use pinned::{PinnedQueries, PinnedQuery, PinnedResults};
+use prompt::PromptAssembler;
+use query::{EntityLoader, EntityLoaders, EntityQuery, QueryDocument};
+use query_cache::{query_key, QueryCacheStats, QueryEmbeddingCache};
+use result_cache::{CachedSearch, Freshness, SearchResultCache};
+use router::{classifier_prompt, match_keywords, parse_classification, RoutingDecision, RoutingMethod};
+use summarize::{group_chunks, summary_source, PlannedGroup, SummaryGroup, SummaryJob, SummaryProgress, SUMMARY_ENTITY_TYPE};
+use tool::ToolResult;
+
+/// Default system prompt for answer generation
+const DEFAULT_SYSTEM_PROMPT: &str = "Answer the question using only the provided context. \
+If the context does not contain the answer, say that you don't know.";
+
+/// Candidates fetched per result slot when some hits are dropped after
+/// the search, by a per-entity cap or a metadata filter
+const OVERFETCH: usize = 4;
+
+/// A chunk waiting to be embedded and stored
+struct PendingChunk {
+    entity_type: String,
+    entity_id: String,
+    chunk_index: usize,
+    total_chunks: usize,
+    text: String,
+}
+
+/// A generated answer with its sources
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct Answer {
+    pub text: String,
+    pub sources: Vec<SearchResult>,
+    pub confidence: Confidence,
+    /// Fallback applied because retrieval came back empty
+    pub fallback: Option<FallbackStrategy>,
+    pub diagnostics: Diagnostics,
+}
+
+/// How an answer was produced
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct Diagnostics {
+    /// Namespace the sources were retrieved from
+    pub namespace: String,
+    /// Retrieval profile used, if any
+    pub profile: Option<String>,
+    /// Router decision, for routed answers
+    pub routing: Option<RoutingDecision>,
+}
+
+/// Retrieval pipeline tying an embedder to a vector store
+///
+/// Embeds queries (with a TTL cache so repeated queries skip the embedding
+/// outcall) and searches the store. Pinned queries have their results
+/// precomputed by [`RagPipeline::refresh_pinned`] and are answered without
+/// any outcall or search.
+pub struct RagPipeline<E: Embedder, S: VectorStore> {
+    embedder: E,
+    store: S,
+    config: PipelineConfig,
+    query_cache: RefCell<QueryEmbeddingCache>,
+    result_cache: RefCell<SearchResultCache>,
+    pinned: RefCell<PinnedQueries>,
+    system_prompt: String,
+    context_builder: ContextBuilder,
+    loaders: EntityLoaders,
+    ingest_keys: IdempotencyCache<usize>,
+    enrichers: Vec<Box<dyn ChunkEnricher>>,
+    ensemble: Option<Ensemble>,
+    provenance: Provenance,
+    generator: Option<Box<dyn TextGenerator>>,
+}
+
+impl<E: Embedder, S: VectorStore> RagPipeline<E, S> {
+    pub fn new(embedder: E, store: S, config: PipelineConfig) -> Self {
+        let query_cache = QueryEmbeddingCache::new(
+            config.query_cache_ttl_secs,
+            config.query_cache_max_entries,
+        );
+        let result_cache = SearchResultCache::new(
+            config.result_cache_fresh_secs,
+            config.result_cache_max_stale_secs,
+            config.result_cache_max_entries,
+        );
+        let ingest_keys = IdempotencyCache::new(
+            config.idempotency_ttl_secs,
+            config.idempotency_max_entries,
+        );
+        let provenance = Provenance::new(embedder.name(), embedder.dimensions(), &ChunkingConfig::default());
+
+        Self {
+            embedder,
+            store,
+            config,
+            query_cache: RefCell::new(query_cache),
+            result_cache: RefCell::new(result_cache),
+            pinned: RefCell::new(PinnedQueries::default()),
+            system_prompt: DEFAULT_SYSTEM_PROMPT.to_string(),
+            context_builder: ContextBuilder::new(ChunkingConfig::default()),
+            loaders: EntityLoaders::default(),
+            ingest_keys,
+            enrichers: Vec::new(),
+            ensemble: None,
+            provenance,
+            generator: None,
+        }
+    }
+
+    /// Use a custom chunking configuration for ingestion
+    pub fn with_chunking(mut self, chunking: ChunkingConfig) -> Self {
+        self.provenance.set_chunking(&chunking);
+        self.context_builder = ContextBuilder::new(chunking);
+        self
+    }
+
+    /// Attach computed metadata to every ingested chunk
+    ///
+    /// Enrichers run in the order they were added; see [`ChunkEnricher`].
+    pub fn with_enricher(mut self, enricher: impl ChunkEnricher + 'static) -> Self {
+        self.enrichers.push(Box::new(enricher));
+        self
+    }
+
+    /// Embed and score ensemble namespaces with a second provider
+    ///
+    /// Only chunks ingested after this is set get secondary embeddings;
+    /// re-ingest existing namespaces to score them with both providers.
+    pub fn with_ensemble(mut self, ensemble: Ensemble) -> Self {
+        self.ensemble = Some(ensemble);
+        self
+    }
+
+    /// Provenance recorded for namespaces this pipeline ingests into
+    ///
+    /// Defaults to the embedder name and dimensions plus the chunking
+    /// config; set one from [`Provenance::from_config`] to include the
+    /// model, template and code versions.
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    /// Generate answers, summaries and routing decisions with `generator`
+    /// instead of the embedder's `generate_with_prompt`
+    pub fn with_generator(mut self, generator: impl TextGenerator + 'static) -> Self {
+        self.generator = Some(Box::new(generator));
+        self
+    }
+
+    /// Use a custom system prompt for answer generation
+    pub fn with_system_prompt(mut self, system_prompt: String) -> Self {
+        self.system_prompt = system_prompt;
+        self
+    }
+
+    pub fn embedder(&self) -> &E {
+        &self.embedder
+    }
+
+    /// Generate text for `text` under `system_prompt`, with the generator if
+    /// one is set and the embedder otherwise
+    async fn generate(&self, text: String, system_prompt: String) -> Result<String> {
+        match &self.generator {
+            Some(generator) => {
+                generator
+                    .generate(GenerationRequest::new(text).with_system(system_prompt))
+                    .await
+            }
+            None => self.embedder.generate_with_prompt(text, system_prompt).await,
+        }
+    }
+
+    /// Name of the provider [`Self::generate`] uses
+    fn generator_name(&self) -> &str {
+        match &self.generator {
+            Some(generator) => generator.name(),
+            None => self.embedder.name(),
+        }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn store_mut(&mut self) -> &mut S {
+        &mut self.store
+    }
+
+    /// Give up the pipeline and keep its store, e.g. to hand a canister's
+    /// store back to its state after a call
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    pub fn config(&self) -> &PipelineConfig {
+        &self.config
+    }
+
+    pub fn provenance(&self) -> &Provenance {
+        &self.provenance
+    }
+
+    /// How a namespace's recorded provenance differs from this pipeline's
+    ///
+    /// `None` if nothing was recorded for the namespace. An empty list means
+    /// the index matches, unless the manifest marks it as mixed.
+    pub fn check_provenance(&self, namespace: &str) -> Option<Vec<ProvenanceMismatch>> {
+        get_provenance(namespace).map(|entry| entry.provenance.mismatches(&self.provenance))
+    }
+
+    /// Embed a query, reusing a cached embedding when available
+    pub async fn embed_query(&self, query: &str) -> Result<Vec<f32>> {
+        self.embed_query_with(&self.embedder, query).await
+    }
+
+    /// Embed a query with the given embedder through the query cache
+    async fn embed_query_with(&self, embedder: &dyn Embedder, query: &str) -> Result<Vec<f32>> {
+        let key = query_key(&model_key(embedder), query);
+
+        if let Some(embedding) = self.query_cache.borrow_mut().get(key, get_timestamp()) {
+            return Ok(embedding);
+        }
+
+        let embedding = embedder
+            .embed_queries(vec![query.to_string()])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ContragError::EmbedderError("No embedding generated".into()))?;
+
+        self.query_cache
+            .borrow_mut()
+            .insert(key, embedding.clone(), get_timestamp());
+
+        Ok(embedding)
+    }
+
+    /// Cache key of a query for the current embedding model
+    fn cache_key(&self, query: &str) -> u64 {
+        query_key(&model_key(&self.embedder), query)
+    }
+
+    /// Register how entities of a type are loaded for [`RagPipeline::query`]
+    pub fn register_loader(&mut self, entity_type: &str, loader: Box<dyn EntityLoader>) {
+        self.loaders.register(entity_type, loader);
+    }
+
+    /// Run a combined entity and retrieval query
+    ///
+    /// The search clause, if any, runs first; without explicit ids the
+    /// matched entities of the root type become the roots, best match first.
+    pub async fn query(&self, query: &EntityQuery) -> Result<QueryDocument> {
+        if query.ids.is_empty() && query.search.is_none() {
+            return Err(ContragError::InvalidConfig(
+                "Query needs either ids or a search clause".to_string(),
+            ));
+        }
+
+        let matches = match &query.search {
+            Some(search) => {
+                self.search(&search.namespace, &search.query, search.k.map(|k| k as usize))
+                    .await?
+            }
+            None => vec![],
+        };
+
+        self.loaders.execute(query, matches).await
+    }
+
+    /// Chunk, embed and store an entity; returns the number of chunks stored
+    pub async fn ingest_entity<T: RagEntity>(&mut self, namespace: &str, entity: &T) -> Result<usize> {
+        let text = self.context_builder.build_entity_context(entity);
+        self.ingest_text(namespace, T::entity_type(), &entity.entity_id(), &text)
+            .await
+    }
+
+    /// Ingest every entity of type `T` from a paginated data source
+    ///
+    /// Each entity goes to its own `{entity_type}:{entity_id}` namespace.
+    /// `on_progress` is called after every page. All pages run in this call,
+    /// so large collections should instead call [`RagPipeline::ingest_page`]
+    /// from a timer (see [`crate::vector_store::bulk::run_resumable`]) with
+    /// the progress kept in canister state.
+    pub async fn ingest_all<T, D, F>(&mut self, source: &D, mut on_progress: F) -> Result<IngestAllProgress>
+    where
+        T: RagEntity + Send,
+        D: DataSource,
+        F: FnMut(&IngestAllProgress),
+    {
+        let mut progress = IngestAllProgress::new(T::entity_type(), get_timestamp());
+
+        while !progress.finished {
+            self.ingest_page::<T, D>(source, &mut progress).await?;
+            on_progress(&progress);
+        }
+
+        Ok(progress)
+    }
+
+    /// Ingest the next page of a collection and advance `progress`
+    ///
+    /// Entities that fail are counted in `progress.failed` and skipped. If
+    /// the page itself can't be read, the error is returned and `progress`
+    /// is left unchanged so the page can be retried.
+    pub async fn ingest_page<T, D>(&mut self, source: &D, progress: &mut IngestAllProgress) -> Result<()>
+    where
+        T: RagEntity + Send,
+        D: DataSource,
+    {
+        if progress.finished {
+            return Ok(());
+        }
+
+        let page = source
+            .read_page::<T>(&progress.entity_type, progress.cursor.clone())
+            .await?;
+
+        for entity in &page.items {
+            let ingested = match Namespace::entity::<T>(&entity.entity_id()) {
+                Ok(namespace) => self.ingest_entity(&namespace, entity).await,
+                Err(e) => Err(e),
+            };
+            match ingested {
+                Ok(chunks) => {
+                    progress.entities += 1;
+                    progress.chunks += chunks as u64;
+                }
+                Err(e) => {
+                    progress.failed += 1;
+                    progress.last_error = Some(format!("{}: {}", entity.entity_id(), e));
+                }
+            }
+        }
+
+        // A source returning the same cursor again would loop forever
+        if page.next_cursor.is_some() && page.next_cursor == progress.cursor {
+            progress.last_error = Some("Data source returned the same cursor twice".to_string());
+            progress.finished = true;
+        } else {
+            progress.finished = page.next_cursor.is_none();
+        }
+
+        progress.cursor = page.next_cursor;
+        progress.pages += 1;
+        progress.updated_at = get_timestamp();
+        Ok(())
+    }
+
+    /// Bring the index of entity type `T` in line with the remote collection
+    ///
+    /// Pages through the source and ingests entities that are not indexed
+    /// yet, or whose `updated_at_field` (from
+    /// [`EntityConfig::updated_at_field`](crate::config::EntityConfig::updated_at_field))
+    /// is newer than their indexed vectors. Without the field, indexed
+    /// entities are left as they are. Entity namespaces
+    /// (`{entity_type}:{entity_id}`) whose entity is gone from the
+    /// collection are deleted, but only after the listing completed; other
+    /// namespaces sharing the prefix, such as child or summary namespaces,
+    /// are kept.
+    pub async fn sync_collection<T, D>(&mut self, source: &D, updated_at_field: Option<&str>) -> Result<SyncReport>
+    where
+        T: RagEntity + Send,
+        D: DataSource,
+    {
+        let entity_type = T::entity_type();
+        let mut report = SyncReport {
+            entity_type: entity_type.to_string(),
+            ..SyncReport::default()
+        };
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut cursor = None;
+
+        loop {
+            let page = source.read_page::<T>(entity_type, cursor.clone()).await?;
+
+            for entity in &page.items {
+                let namespace = match Namespace::entity::<T>(&entity.entity_id()) {
+                    Ok(namespace) => namespace,
+                    Err(e) => {
+                        report.failed += 1;
+                        report.last_error = Some(format!("{}: {}", entity.entity_id(), e));
+                        continue;
+                    }
+                };
+                seen.insert(namespace.to_string());
+
+                let indexed_at = self
+                    .store
+                    .sample(&namespace, 1)
+                    .await?
+                    .first()
+                    .map(|v| v.metadata.timestamp);
+                let updated_at = updated_at_field.and_then(|field| {
+                    entity
+                        .to_context_map()
+                        .into_iter()
+                        .find(|(key, _)| key == field)
+                        .and_then(|(_, value)| parse_updated_at(&value))
+                });
+
+                let changed = match (indexed_at, updated_at) {
+                    (None, _) => false,
+                    (Some(indexed_at), Some(updated_at)) if updated_at > indexed_at => true,
+                    _ => {
+                        report.unchanged += 1;
+                        continue;
+                    }
+                };
+
+                // Drop old chunks first, the new text may have fewer of them
+                if changed {
+                    self.delete_namespace(&namespace).await?;
+                }
+
+                match self.ingest_entity(&namespace, entity).await {
+                    Ok(_) if changed => report.updated += 1,
+                    Ok(_) => report.added += 1,
+                    Err(e) => {
+                        report.failed += 1;
+                        report.last_error = Some(format!("{}: {}", entity.entity_id(), e));
+                    }
+                }
+            }
+
+            if page.next_cursor.is_none() {
+                break;
+            }
+            // A source returning the same cursor again would loop forever
+            if page.next_cursor == cursor {
+                report.last_error = Some("Data source returned the same cursor twice".to_string());
+                return Ok(report);
+            }
+            cursor = page.next_cursor;
+        }
+
+        let mut stale = vec![];
+        let mut start_after = None;
+        loop {
+            let page = self
+                .store
+                .list_namespaces_page(NamespaceListRequest {
+                    prefix: Some(format!("{}:", entity_type)),
+                    start_after: start_after.clone(),
+                    limit: MAX_NAMESPACE_PAGE_SIZE,
+                })
+                .await?;
+
+            for info in page.namespaces {
+                if !seen.contains(&info.name) && self.is_entity_namespace::<T>(&info.name).await? {
+                    stale.push(info.name);
+                }
+            }
+
+            match page.next_cursor {
+                Some(next) => start_after = Some(next),
+                None => break,
+            }
+        }
+
+        for namespace in stale {
+            self.delete_namespace(&namespace).await?;
+            report.deleted += 1;
+        }
+
+        Ok(report)
+    }
+
+    /// Whether `namespace` is the namespace of a single `T` entity, going by
+    /// the vectors it holds rather than its name alone
+    async fn is_entity_namespace<T: RagEntity>(&self, namespace: &str) -> Result<bool> {
+        Ok(self.store.sample(namespace, 1).await?.first().is_some_and(|v| {
+            v.metadata.entity_type == T::entity_type()
+                && Namespace::entity::<T>(&v.metadata.entity_id).is_ok_and(|ns| ns.as_str() == namespace)
+        }))
+    }
+
+    /// Delete a namespace with its provenance and ensemble embeddings
+    ///
+    /// Prefer this and the other deletes of the pipeline over those of the
+    /// store, which leave the `ensemble:<namespace>` companion behind. The
+    /// companion is deleted even if the current ensemble doesn't apply to
+    /// the namespace, since an earlier one may have.
+    pub async fn delete_namespace(&mut self, namespace: &str) -> Result<()> {
+        self.store.delete_namespace(namespace).await?;
+        self.store.delete_namespace(&ensemble_namespace(namespace)).await?;
+        clear_provenance(namespace);
+        Ok(())
+    }
+
+    /// Delete an entity's chunks from a namespace and its ensemble
+    /// namespace; returns the deleted vector IDs of the namespace
+    pub async fn delete_entity(&mut self, namespace: &str, entity_type: &str, entity_id: &str) -> Result<Vec<String>> {
+        let deleted = self.store.delete_by_entity(namespace, entity_type, entity_id).await?;
+        self.store
+            .delete_by_entity(&ensemble_namespace(namespace), entity_type, entity_id)
+            .await?;
+        Ok(deleted)
+    }
+
+    /// Delete up to `batch` namespaces under the cursor's prefix with their
+    /// provenance and ensemble namespaces
+    ///
+    /// Call again with the returned cursor until it is `finished`.
+    pub async fn delete_by_prefix(&mut self, cursor: BulkCursor, batch: usize) -> Result<BulkCursor> {
+        let page = self.store.list_namespaces_page(prefix_page_request(&cursor, batch)).await?;
+        for info in &page.namespaces {
+            self.store.delete_namespace(&ensemble_namespace(&info.name)).await?;
+            clear_provenance(&info.name);
+        }
+        self.store.delete_by_prefix(cursor, batch).await
+    }
+
+    /// [`RagPipeline::ingest_text`] deduplicated by a client-supplied key
+    ///
+    /// Retrying with the same key and parameters returns the original chunk
+    /// count without embedding or storing again. Reusing a key for a
+    /// different request, or while the first one is still running, fails.
+    /// Failed attempts release the key so a retry runs again.
+    pub async fn ingest_text_idempotent(
+        &mut self,
+        key: &str,
+        namespace: &str,
+        entity_type: &str,
+        entity_id: &str,
+        text: &str,
+    ) -> Result<usize> {
+        let request = fingerprint(&[namespace, entity_type, entity_id, text]);
+
+        match self.ingest_keys.claim(key, request, get_timestamp()) {
+            Claim::New => {}
+            Claim::Replay(chunks) => return Ok(chunks),
+            Claim::InProgress => {
+                return Err(ContragError::IdempotencyConflict {
+                    key: key.to_string(),
+                    reason: "a request with this key is still in progress".to_string(),
+                })
+            }
+            Claim::Conflict => {
+                return Err(ContragError::IdempotencyConflict {
+                    key: key.to_string(),
+                    reason: "key was already used for a different request".to_string(),
+                })
+            }
+        }
+
+        match self.ingest_text(namespace, entity_type, entity_id, text).await {
+            Ok(chunks) => {
+                self.ingest_keys.complete(key, chunks);
+                Ok(chunks)
+            }
+            Err(e) => {
+                self.ingest_keys.release(key);
+                Err(e)
+            }
+        }
+    }
+
+    /// Chunk, embed and store a raw text for an entity
+    ///
+    /// Returns the number of chunks stored and marks pinned queries of the
+    /// namespace stale. Latency is recorded for SLO tracking as "ingest".
+    pub async fn ingest_text(
+        &mut self,
+        namespace: &str,
+        entity_type: &str,
+        entity_id: &str,
+        text: &str,
+    ) -> Result<usize> {
+        slo::timed("ingest", self.run_ingest(namespace, entity_type, entity_id, text)).await
+    }
+
+    async fn run_ingest(
+        &mut self,
+        namespace: &str,
+        entity_type: &str,
+        entity_id: &str,
+        text: &str,
+    ) -> Result<usize> {
+        let chunks = self.context_builder.chunk_text(text);
+        let total_chunks = chunks.len();
+        let pending = chunks
+            .into_iter()
+            .map(|chunk| PendingChunk {
+                entity_type: entity_type.to_string(),
+                entity_id: entity_id.to_string(),
+                chunk_index: chunk.chunk_index,
+                total_chunks,
+                text: chunk.text,
+            })
+            .collect();
+
+        self.index_chunks(namespace, pending).await
+    }
+
+    /// Embed and store chunks, with enrichment, ensemble embeddings and
+    /// provenance; returns the number of chunks stored
+    async fn index_chunks(&mut self, namespace: &str, chunks: Vec<PendingChunk>) -> Result<usize> {
+        let texts: Vec<String> = chunks.iter().map(|c| c.text.clone()).collect();
+        let ensemble = self.ensemble.as_ref().filter(|e| e.applies_to(namespace));
+        let secondary = match ensemble {
+            Some(ensemble) => Some(ensemble.embedder().embed(texts.clone()).await?),
+            None => None,
+        };
+        let embeddings = self.embedder.embed(texts).await?;
+
+        for count in std::iter::once(embeddings.len()).chain(secondary.as_ref().map(Vec::len)) {
+            if count != chunks.len() {
+                return Err(ContragError::EmbedderError(format!(
+                    "Expected {} embeddings, got {}",
+                    chunks.len(),
+                    count
+                ).into()));
+            }
+        }
+
+        let timestamp = get_timestamp();
+        let stored = chunks.len();
+        let vectors = chunks
+            .into_iter()
+            .zip(embeddings)
+            .map(|(chunk, embedding)| {
+                let custom = enrich_chunk(
+                    &self.enrichers,
+                    &ChunkInfo {
+                        entity_type: &chunk.entity_type,
+                        entity_id: &chunk.entity_id,
+                        chunk_index: chunk.chunk_index,
+                        total_chunks: chunk.total_chunks,
+                        text: &chunk.text,
+                    },
+                );
+
+                Vector {
+                    id: generate_vector_id(&chunk.entity_type, &chunk.entity_id, chunk.chunk_index),
+                    embedding,
+                    text: chunk.text,
+                    metadata: VectorMetadata {
+                        entity_type: chunk.entity_type,
+                        entity_id: chunk.entity_id,
+                        chunk_index: chunk.chunk_index,
+                        total_chunks: chunk.total_chunks,
+                        timestamp,
+                        custom,
+                        ttl_seconds: None,
+                    },
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let secondary_vectors = secondary.map(|embeddings| {
+            vectors
+                .iter()
+                .cloned()
+                .zip(embeddings)
+                .map(|(vector, embedding)| Vector { embedding, ..vector })
+                .collect::<Vec<_>>()
+        });
+
+        let stored = match self.config.dedup {
+            Some(dedup) => {
+                let report = self
+                    .store
+                    .store_batch_dedup(namespace, vectors, BatchMode::AllOrNothing, dedup)
+                    .await?;
+                report.stored
+            }
+            None => {
+                self.store.store_batch(namespace, vectors).await?;
+                stored
+            }
+        };
+        if let Some(secondary_vectors) = secondary_vectors {
+            let namespace = ensemble_namespace(namespace);
+            match self.config.dedup {
+                Some(dedup) => {
+                    self.store
+                        .store_batch_dedup(&namespace, secondary_vectors, BatchMode::AllOrNothing, dedup)
+                        .await?;
+                }
+                None => self.store.store_batch(&namespace, secondary_vectors).await?,
+            }
+        }
+        record_provenance(namespace, &self.provenance, timestamp);
+        self.notify_ingested(namespace);
+
+        Ok(stored)
+    }
+
+    /// Chunk, embed and store a [`Document`] in [`Document::namespace`]
+    ///
+    /// The content is indexed under a short title and metadata header
+    /// rather than as entity fields.
+    /// A document that was ingested before is replaced, so chunks beyond the
+    /// new content's length don't linger.
+    pub async fn ingest_document(&mut self, document: &Document) -> Result<usize> {
+        let namespace = document.namespace()?;
+        self.delete_entity(&namespace, Document::entity_type(), &document.id)
+            .await?;
+        self.ingest_text(&namespace, Document::entity_type(), &document.id, &document.to_text())
+            .await
+    }
+
+    /// Embed and store pre-split chunks as they are, without re-chunking
+    ///
+    /// Each chunk is stored as chunk `index` of its document, so results
+    /// point at the document like those of [`RagPipeline::ingest_document`].
+    /// A document's total chunk count is taken from the highest index given.
+    pub async fn ingest_chunks(&mut self, namespace: &str, chunks: &[Chunk]) -> Result<usize> {
+        let mut totals: HashMap<&str, usize> = HashMap::new();
+        for chunk in chunks {
+            let total = totals.entry(chunk.document_id.as_str()).or_default();
+            *total = (*total).max(chunk.index as usize + 1);
+        }
+
+        let pending = chunks
+            .iter()
+            .map(|chunk| PendingChunk {
+                entity_type: Document::entity_type().to_string(),
+                entity_id: chunk.document_id.clone(),
+                chunk_index: chunk.index as usize,
+                total_chunks: totals[chunk.document_id.as_str()],
+                text: chunk.text.clone(),
+            })
+            .collect();
+
+        slo::timed("ingest", self.index_chunks(namespace, pending)).await
+    }
+
+    /// Summarize up to `max_groups` more groups of a [`SummaryJob`]
+    ///
+    /// The first step groups the namespace, stores the groups in
+    /// `progress.plan` and deletes earlier summaries of the namespace that
+    /// match no group. Later steps resume at `progress.next_group` of that
+    /// plan and set `progress.finished` once every group is done, so long
+    /// jobs can run one step per timer tick (see
+    /// [`crate::vector_store::bulk::run_resumable`]). A failed group is
+    /// counted and skipped.
+    pub async fn summarize_step(
+        &mut self,
+        job: &SummaryJob,
+        progress: &mut SummaryProgress,
+        max_groups: usize,
+    ) -> Result<()> {
+        let plan = match &progress.plan {
+            Some(plan) => plan.clone(),
+            None => {
+                let plan = self.plan_summaries(job).await?;
+                progress.groups = plan.len() as u64;
+                progress.plan = Some(plan.clone());
+                plan
+            }
+        };
+
+        for planned in plan.iter().skip(progress.next_group as usize).take(max_groups) {
+            match self.summarize_group(job, planned).await {
+                Ok(()) => progress.summaries += 1,
+                Err(e) => {
+                    progress.failed += 1;
+                    progress.last_error = Some(format!("{}: {}", planned.key, e));
+                }
+            }
+            progress.next_group += 1;
+        }
+
+        progress.finished = progress.next_group >= progress.groups;
+        Ok(())
+    }
+
+    /// Run a [`SummaryJob`] to completion in this call
+    pub async fn summarize(&mut self, job: &SummaryJob) -> Result<SummaryProgress> {
+        let mut progress = SummaryProgress::default();
+        self.summarize_step(job, &mut progress, usize::MAX).await?;
+        Ok(progress)
+    }
+
+    /// Group the job's chunks and delete summaries of groups that are gone
+    async fn plan_summaries(&mut self, job: &SummaryJob) -> Result<Vec<PlannedGroup>> {
+        let chunks = self
+            .store
+            .export_namespace(&job.namespace)
+            .await?
+            .into_iter()
+            .filter(|v| job.matches(&v.metadata))
+            .collect();
+        let plan: Vec<PlannedGroup> = group_chunks(chunks, &job.grouping, job.max_chunks_per_group as usize)
+            .iter()
+            .map(PlannedGroup::from)
+            .collect();
+
+        let keys: HashSet<&str> = plan.iter().map(|group| group.key.as_str()).collect();
+        let stale: Vec<String> = self
+            .store
+            .export_namespace(&job.target_namespace)
+            .await?
+            .into_iter()
+            .filter(|v| {
+                v.metadata.entity_type == SUMMARY_ENTITY_TYPE
+                    && !keys.contains(v.metadata.entity_id.as_str())
+                    && summary_source(&v.metadata).as_deref() == Some(job.namespace.as_str())
+            })
+            .map(|v| v.id)
+            .collect();
+        for id in &stale {
+            self.store.delete(&job.target_namespace, id).await?;
+        }
+        Ok(plan)
+    }
+
+    async fn summarize_group(&mut self, job: &SummaryJob, planned: &PlannedGroup) -> Result<()> {
+        // Chunks deleted since the plan was made are left out
+        let mut chunks = self.store.get_many(&job.namespace, &planned.chunk_ids).await?;
+        if chunks.is_empty() {
+            return Err(ContragError::VectorStoreError("All chunks of the group were deleted".to_string()));
+        }
+        chunks.sort_by_key(|c| planned.chunk_ids.iter().position(|id| *id == c.id));
+        let group = SummaryGroup {
+            key: planned.key.clone(),
+            chunks,
+        };
+
+        let summary = self
+            .generate(group.to_prompt_text(), job.prompt().to_string())
+            .await?;
+        if summary.trim().is_empty() {
+            return Err(ContragError::EmbedderError(format!(
+                "'{}' returned no summary; it may not support generation",
+                self.generator_name()
+            ).into()));
+        }
+
+        let embedding = self
+            .embedder
+            .embed(vec![summary.clone()])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ContragError::EmbedderError("No embedding generated".into()))?;
+
+        let sources: Vec<&str> = group.chunks.iter().map(|c| c.id.as_str()).collect();
+        let id = generate_vector_id(SUMMARY_ENTITY_TYPE, &group.key, 0);
+        let vector = Vector {
+            id: id.clone(),
+            embedding,
+            text: summary,
+            metadata: VectorMetadata {
+                entity_type: SUMMARY_ENTITY_TYPE.to_string(),
+                entity_id: group.key.clone(),
+                chunk_index: 0,
+                total_chunks: 1,
+                timestamp: get_timestamp(),
+                custom: Some(
+                    serde_json::json!({
+                        "source_namespace": job.namespace,
+                        "sources": sources,
+                    })
+                    .to_string(),
+                ),
+                ttl_seconds: None,
+            },
+        };
+
+        // Replace the previous summary of this group
+        self.store.delete(&job.target_namespace, &id).await?;
+        self.store.store(&job.target_namespace, vector).await?;
+        self.notify_ingested(&job.target_namespace);
+        Ok(())
+    }
+
+    /// Search a namespace for the `k` chunks most similar to `query`
+    ///
+    /// Uses the configured default when `k` is `None`. Queries matching a
+    /// fresh pinned query are answered from its precomputed results.
+    pub async fn search(&self, namespace: &str, query: &str, k: Option<usize>) -> Result<Vec<SearchResult>> {
+        self.search_capped(namespace, query, k, self.config.max_chunks_per_entity)
+            .await
+    }
+
+    /// Search with at most `max_chunks_per_entity` hits per entity
+    ///
+    /// Overrides the configured cap. Capped searches fetch extra candidates
+    /// from the store so the freed slots are backfilled with the next best
+    /// entities; they may still return fewer than `k` results when few
+    /// entities match. Latency is recorded for SLO tracking as "search".
+    pub async fn search_capped(
+        &self,
+        namespace: &str,
+        query: &str,
+        k: Option<usize>,
+        max_chunks_per_entity: Option<usize>,
+    ) -> Result<Vec<SearchResult>> {
+        slo::timed("search", self.run_search(namespace, query, k, max_chunks_per_entity)).await
+    }
+
+    async fn run_search(
+        &self,
+        namespace: &str,
+        query: &str,
+        k: Option<usize>,
+        max_chunks_per_entity: Option<usize>,
+    ) -> Result<Vec<SearchResult>> {
+        let k = k.unwrap_or(self.config.default_k);
+
+        if let Some(results) = self.pinned.borrow().lookup(namespace, query, k) {
+            return Ok(match max_chunks_per_entity {
+                Some(max) => cap_per_entity(results, max, k),
+                None => results,
+            });
+        }
+
+        let embedding = self.embed_query(query).await?;
+        let fetch = match max_chunks_per_entity {
+            Some(_) => k.saturating_mul(OVERFETCH),
+            None => k,
+        };
+        let candidates = match &self.ensemble {
+            Some(ensemble) if ensemble.applies_to(namespace) => {
+                self.ensemble_search(ensemble, namespace, query, embedding, fetch).await?
+            }
+            _ => self.store.search(namespace, embedding, fetch).await?,
+        };
+
+        Ok(match max_chunks_per_entity {
+            Some(max) => cap_per_entity(candidates, max, k),
+            None => candidates,
+        })
+    }
+
+    /// Search both providers' embeddings and combine the scores
+    async fn ensemble_search(
+        &self,
+        ensemble: &Ensemble,
+        namespace: &str,
+        query: &str,
+        embedding: Vec<f32>,
+        k: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let secondary_embedding = self.embed_query_with(ensemble.embedder(), query).await?;
+        let fetch = k.saturating_mul(ENSEMBLE_OVERFETCH);
+
+        let primary = self.store.search(namespace, embedding, fetch).await?;
+        let secondary = self
+            .store
+            .search(&ensemble_namespace(namespace), secondary_embedding, fetch)
+            .await?;
+
+        Ok(combine(primary, secondary, ensemble.weight(), k))
+    }
+
+    /// Stale-while-revalidate search for latency-sensitive callers
+    ///
+    /// Serves cached results immediately, even when they are past their
+    /// freshness window or the namespace was ingested into since, and queues
+    /// a refresh instead of searching. When the response has
+    /// `refresh_scheduled` set, run [`RagPipeline::revalidate`] in the
+    /// background (see [`schedule_revalidation`]) so the next call gets
+    /// current results. Cache misses search as usual.
+    pub async fn search_swr(&self, namespace: &str, query: &str, k: Option<usize>) -> Result<CachedSearch> {
+        let k = k.unwrap_or(self.config.default_k);
+        let key = self.cache_key(query);
+
+        let cached = self
+            .result_cache
+            .borrow_mut()
+            .get(namespace, key, k, get_timestamp());
+        if let Some((results, age, freshness)) = cached {
+            let stale = freshness == Freshness::Stale;
+            let refresh_scheduled = stale && self.result_cache.borrow_mut().request_refresh(namespace, key, k);
+            return Ok(CachedSearch {
+                results,
+                stale,
+                age_secs: age / 1_000_000_000,
+                refresh_scheduled,
+            });
+        }
+
+        let results = self.search(namespace, query, Some(k)).await?;
+        self.result_cache
+            .borrow_mut()
+            .insert(namespace, key, k, query, results.clone(), get_timestamp());
+
+        Ok(CachedSearch {
+            results,
+            stale: false,
+            age_secs: 0,
+            refresh_scheduled: false,
+        })
+    }
+
+    /// Rerun searches queued by [`RagPipeline::search_swr`]; returns how many
+    /// were refreshed
+    ///
+    /// Stops at the first failure; the failed and remaining searches are
+    /// queued again on the next stale hit.
+    pub async fn revalidate(&self) -> Result<usize> {
+        let pending = self.result_cache.borrow_mut().take_pending();
+
+        for (key, refresh) in &pending {
+            let results = self
+                .search(&refresh.namespace, &refresh.query, Some(refresh.k))
+                .await?;
+            self.result_cache.borrow_mut().insert(
+                &refresh.namespace,
+                *key,
+                refresh.k,
+                &refresh.query,
+                results,
+                get_timestamp(),
+            );
+        }
+
+        Ok(pending.len())
+    }
+
+    /// Search for the `k` best chunks whose metadata passes `filter`
+    ///
+    /// Useful with enriched custom metadata, e.g. only chunks whose
+    /// `category` field is "billing". Fetches extra candidates so filtered
+    /// out chunks are backfilled, but may return fewer than `k` results
+    /// when the filter is selective.
+    pub async fn search_filtered<F>(
+        &self,
+        namespace: &str,
+        query: &str,
+        k: Option<usize>,
+        filter: F,
+    ) -> Result<Vec<SearchResult>>
+    where
+        F: Fn(&VectorMetadata) -> bool,
+    {
+        let k = k.unwrap_or(self.config.default_k);
+        let embedding = self.embed_query(query).await?;
+        let candidates = self
+            .store
+            .search(namespace, embedding, k.saturating_mul(OVERFETCH))
+            .await?;
+
+        Ok(candidates
+            .into_iter()
+            .filter(|result| filter(&result.metadata))
+            .take(k)
+            .collect())
+    }
+
+    /// Search and return the results as a JSON tool response
+    ///
+    /// The payload follows [`tool::TOOL_RESULT_SCHEMA`] and can be returned
+    /// as-is from an agent's tool or function call.
+    pub async fn search_tool(
+        &self,
+        namespace: &str,
+        query: &str,
+        k: Option<usize>,
+        max_text_chars: usize,
+    ) -> Result<String> {
+        let results = self.search(namespace, query, k).await?;
+        ToolResult::new(namespace, query, &results, max_text_chars).to_json()
+    }
+
+    /// Answer a question from the context retrieved in `namespace`
+    ///
+    /// Generation uses the [generator](Self::with_generator), or the
+    /// embedder's `generate_with_prompt` when none is set. The answer
+    /// carries a confidence score so callers can show "I'm not sure" instead
+    /// of a weakly supported answer. When retrieval finds nothing, the
+    /// configured [`FallbackStrategy`] decides what happens.
+    pub async fn answer(&self, namespace: &str, question: &str, k: Option<usize>) -> Result<Answer> {
+        let assembler = PromptAssembler::new(self.config.context_format);
+        self.answer_with(namespace, question, k, &assembler, &self.system_prompt)
+            .await
+    }
+
+    /// Answer a question using a named retrieval profile from the config
+    ///
+    /// Searches the profile's namespace instead of `namespace` if it has one.
+    pub async fn answer_with_profile(&self, namespace: &str, question: &str, profile: &str) -> Result<Answer> {
+        let profile = self.profile(profile)?;
+
+        let mut assembler = PromptAssembler::new(profile.context_format);
+        if let Some(max_chars) = profile.max_context_chars {
+            assembler = assembler.with_max_context_chars(max_chars);
+        }
+        let system_prompt = profile.system_prompt.as_ref().unwrap_or(&self.system_prompt);
+        let namespace = profile.namespace.as_deref().unwrap_or(namespace);
+
+        let mut answer = self
+            .answer_with(namespace, question, profile.k, &assembler, system_prompt)
+            .await?;
+        answer.diagnostics.profile = Some(profile.name.clone());
+        Ok(answer)
+    }
+
+    /// Answer with the retrieval profile picked by the configured router
+    ///
+    /// The routing decision is recorded in the answer's diagnostics.
+    pub async fn answer_routed(&self, namespace: &str, question: &str) -> Result<Answer> {
+        let decision = self.route(question).await;
+
+        let mut answer = match &decision.profile {
+            Some(profile) => self.answer_with_profile(namespace, question, profile).await?,
+            None => self.answer(namespace, question, None).await?,
+        };
+        answer.diagnostics.routing = Some(decision);
+        Ok(answer)
+    }
+
+    /// Pick the retrieval profile for a query
+    ///
+    /// Without a router config every query uses the pipeline defaults. A
+    /// failing classifier call falls back to the default profile, so routing
+    /// never fails the query itself.
+    pub async fn route(&self, query: &str) -> RoutingDecision {
+        let Some(router) = &self.config.router else {
+            return RoutingDecision::default_profile(None);
+        };
+
+        if let Some((profile, keyword)) = match_keywords(&router.rules, query) {
+            return RoutingDecision {
+                profile: Some(profile.to_string()),
+                method: RoutingMethod::Keyword,
+                matched_keyword: Some(keyword.to_string()),
+                classifier_reply: None,
+            };
+        }
+
+        let mut decision = RoutingDecision::default_profile(router.default_profile.clone());
+        if router.llm_fallback && !self.config.profiles.is_empty() {
+            let reply = self
+                .generate(query.to_string(), classifier_prompt(&self.config.profiles))
+                .await;
+
+            match reply {
+                Ok(reply) => {
+                    if let Some(profile) = parse_classification(&reply, &self.config.profiles) {
+                        decision.profile = Some(profile.to_string());
+                        decision.method = RoutingMethod::Classifier;
+                    }
+                    decision.classifier_reply = Some(reply);
+                }
+                Err(e) => decision.classifier_reply = Some(format!("Classifier failed: {}", e)),
+            }
+        }
+
+        decision
+    }
+
+    /// Look up a retrieval profile by name
+    pub fn profile(&self, name: &str) -> Result<&RetrievalProfile> {
+        self.config
+            .profiles
+            .iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| ContragError::InvalidConfig(format!("Unknown retrieval profile: {}", name)))
+    }
+
+    /// Answer generation shared by the public entry points, recorded for
+    /// SLO tracking as "answer"
+    async fn answer_with(
+        &self,
+        namespace: &str,
+        question: &str,
+        k: Option<usize>,
+        assembler: &PromptAssembler,
+        system_prompt: &str,
+    ) -> Result<Answer> {
+        slo::timed("answer", self.generate_answer(namespace, question, k, assembler, system_prompt)).await
+    }
+
+    async fn generate_answer(
+        &self,
+        namespace: &str,
+        question: &str,
+        k: Option<usize>,
+        assembler: &PromptAssembler,
+        system_prompt: &str,
+    ) -> Result<Answer> {
+        let mut sources = self.search(namespace, question, k).await?;
+        let mut fallback = None;
+        let mut searched = namespace;
+
+        if sources.is_empty() && self.config.fallback == FallbackStrategy::Broaden {
+            fallback = Some(FallbackStrategy::Broaden);
+            for other in &self.config.fallback_namespaces {
+                sources = self.search(other, question, k).await?;
+                if !sources.is_empty() {
+                    searched = other.as_str();
+                    break;
+                }
+            }
+        }
+        let diagnostics = Diagnostics {
+            namespace: searched.to_string(),
+            ..Diagnostics::default()
+        };
+
+        if sources.is_empty() {
+            return match self.config.fallback {
+                FallbackStrategy::AnswerWithoutContext => {
+                    let text = self
+                        .generate(question.to_string(), system_prompt.to_string())
+                        .await?;
+                    Ok(self.fallback_answer(
+                        question,
+                        format!("{} {}", self.config.no_context_disclaimer, text),
+                        FallbackStrategy::AnswerWithoutContext,
+                        diagnostics,
+                    ))
+                }
+                strategy => Ok(self.fallback_answer(
+                    question,
+                    self.config.refusal_message.clone(),
+                    strategy,
+                    diagnostics,
+                )),
+            };
+        }
+
+        let prompt = assembler.assemble(question, &sources);
+        let text = self
+            .generate(prompt.clone(), system_prompt.to_string())
+            .await?;
+
+        let self_assessment = if self.config.self_assess {
+            let grading = format!("{}\n\nAnswer: {}", prompt, text);
+            let reply = self
+                .generate(grading, SELF_ASSESSMENT_PROMPT.to_string())
+                .await?;
+            confidence::parse_self_assessment(&reply)
+        } else {
+            None
+        };
+
+        let confidence = confidence::score(question, &sources, self_assessment, self.config.min_confidence);
+
+        Ok(Answer {
+            text,
+            sources,
+            confidence,
+            fallback,
+            diagnostics,
+        })
+    }
+
+    fn fallback_answer(
+        &self,
+        question: &str,
+        text: String,
+        strategy: FallbackStrategy,
+        diagnostics: Diagnostics,
+    ) -> Answer {
+        Answer {
+            text,
+            sources: vec![],
+            confidence: confidence::score(question, &[], None, self.config.min_confidence),
+            fallback: Some(strategy),
+            diagnostics,
+        }
+    }
+
+    /// Pin a query so its results are precomputed on every refresh
+    pub fn pin_query(&self, query: PinnedQuery) {
+        self.pinned.borrow_mut().pin(query);
+    }
+
+    pub fn unpin_query(&self, id: &str) -> bool {
+        self.pinned.borrow_mut().unpin(id)
+    }
+
+    pub fn pinned_queries(&self) -> Vec<PinnedQuery> {
+        self.pinned.borrow().queries()
+    }
+
+    /// Precomputed results of a pinned query, without any outcall
+    pub fn pinned_results(&self, id: &str) -> Option<PinnedResults> {
+        self.pinned.borrow().results(id)
+    }
+
+    /// Mark pinned queries of a namespace stale, e.g. after an ingestion batch
+    pub fn notify_ingested(&self, namespace: &str) {
+        self.pinned.borrow_mut().mark_stale(namespace);
+        self.result_cache.borrow_mut().mark_stale(namespace);
+    }
+
+    /// Recompute stale pinned queries; returns how many were refreshed
+    ///
+    /// Embeddings of pinned queries are kept, so only newly pinned queries
+    /// cost an embedding outcall (one batched call for all of them).
+    pub async fn refresh_pinned(&self) -> Result<usize> {
+        let stale = self.pinned.borrow().stale();
+        if stale.is_empty() {
+            return Ok(0);
+        }
+
+        let missing: Vec<String> = stale
+            .iter()
+            .filter(|(_, embedding)| embedding.is_none())
+            .map(|(query, _)| query.query.clone())
+            .collect();
+        let mut new_embeddings = if missing.is_empty() {
+            vec![]
+        } else {
+            self.embedder.embed_queries(missing).await?
+        }
+        .into_iter();
+
+        for (query, embedding) in &stale {
+            let embedding = match embedding {
+                Some(embedding) => embedding.clone(),
+                None => new_embeddings.next().ok_or_else(|| {
+                    ContragError::EmbedderError("Missing embedding for pinned query".into())
+                })?,
+            };
+
+            let results = self
+                .store
+                .search(&query.namespace, embedding.clone(), query.k)
+                .await?;
+            self.pinned
+                .borrow_mut()
+                .update(&query.id, embedding, results, get_timestamp());
+        }
+
+        Ok(stale.len())
+    }
+
+    pub fn query_cache_stats(&self) -> QueryCacheStats {
+        self.query_cache.borrow().stats()
+    }
+
+    /// Drop all cached query embeddings and search results, e.g. after
+    /// switching models
+    pub fn clear_query_cache(&self) {
+        self.query_cache.borrow_mut().clear();
+        self.result_cache.borrow_mut().clear();
+    }
+}
+
+/// Identifies an embedding model in cache keys
+fn model_key(embedder: &dyn Embedder) -> String {
+    format!("{}/{}", embedder.name(), embedder.dimensions())
+}
+
+/// Start a periodic timer that refreshes pinned queries
+///
+/// `refresh` is spawned on every tick and should call
+/// [`RagPipeline::refresh_pinned`] on the canister's pipeline. Calling
+/// `refresh_pinned` right after an ingestion batch keeps results current
+/// between ticks.
+pub fn start_pinned_refresh<F, Fut>(interval: Duration, refresh: F) -> ic_cdk_timers::TimerId
+where
+    F: Fn() -> Fut + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    ic_cdk_timers::set_timer_interval(interval, move || ic_cdk::spawn(refresh()))
+}
+
+/// Run a stale-while-revalidate refresh in its own message
+///
+/// Call with a closure invoking [`RagPipeline::revalidate`] after
+/// [`RagPipeline::search_swr`] returned `refresh_scheduled`; the refresh runs
+/// right after the current message, so the stale response is not delayed.
+pub fn schedule_revalidation<F, Fut>(refresh: F) -> ic_cdk_timers::TimerId
+where
+    F: FnOnce() -> Fut + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    ic_cdk_timers::set_timer(Duration::ZERO, move || ic_cdk::spawn(refresh()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedders::mock::MockEmbedder;
+    use crate::types::{test_vector, Vector};
+    use crate::vector_store::stable_memory_store::StableMemoryVectorStore;
+
+    #[tokio::test]
+    async fn test_cached_and_pinned_queries_skip_embedder() {
+        let mut store = StableMemoryVectorStore::new();
+        store
+            .store("User:1", Vector {
+                text: "Alice ordered a laptop".to_string(),
+                ..vector("v1", "User", "1")
+            })
+            .await
+            .unwrap();
+
+        let pipeline = RagPipeline::new(MockEmbedder::new(2).with_embedding(vec![1.0, 0.0]), store, PipelineConfig::default());
+
+        pipeline.search("User:1", "What did Alice order?", None).await.unwrap();
+        pipeline.search("User:1", "what did  alice order?", None).await.unwrap();
+        assert_eq!(pipeline.embedder().calls(), 1);
+
+        pipeline.pin_query(PinnedQuery {
+            id: "orders".to_string(),
+            namespace: "User:1".to_string(),
+            query: "recent orders".to_string(),
+            k: 5,
+        });
+        assert_eq!(pipeline.refresh_pinned().await.unwrap(), 1);
+        assert_eq!(pipeline.embedder().calls(), 2);
+
+        let results = pipeline.search("User:1", "Recent orders", Some(3)).await.unwrap();
+        assert_eq!(results[0].vector_id, "v1");
+        assert_eq!(pipeline.embedder().calls(), 2);
+    }
+
+    #[derive(CandidType, Serialize, Clone)]
+    struct Order;
+
+    impl RagEntity for Order {
+        fn entity_type() -> &'static str {
+            "Order"
+        }
+
+        fn entity_id(&self) -> String {
+            String::new()
+        }
+
+        fn to_context_map(&self) -> Vec<(String, String)> {
+            vec![]
+        }
+
+        fn relationships(&self) -> Vec<crate::entity::EntityRelationship> {
+            vec![]
+        }
+    }
+
+    /// Empty collection; `stuck` sources return the same cursor forever
+    struct EmptyCollection {
+        stuck: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl DataSource for EmptyCollection {
+        async fn read_entity<T: RagEntity + CandidType>(&self, _: &str, entity_id: &str) -> Result<T> {
+            Err(ContragError::EntityNotFound(entity_id.to_string()))
+        }
+
+        async fn read_entities<T: RagEntity + CandidType + Send>(&self, _: &str, _: Vec<String>) -> Result<Vec<T>> {
+            Ok(vec![])
+        }
+
+        async fn read_page<T: RagEntity + CandidType + Send>(
+            &self,
+            _: &str,
+            _: Option<String>,
+        ) -> Result<crate::data_sources::EntityPage<T>> {
+            Ok(crate::data_sources::EntityPage {
+                items: vec![],
+                next_cursor: self.stuck.then(|| "page-2".to_string()),
+            })
+        }
+    }
+
+    /// Empty pages numbered from 0 to `pages - 1`, each pointing at the
+    /// next; reading page `fail_at` fails
+    struct PagedCollection {
+        pages: u32,
+        fail_at: Option<u32>,
+    }
+
+    #[async_trait::async_trait]
+    impl DataSource for PagedCollection {
+        async fn read_entity<T: RagEntity + CandidType>(&self, _: &str, entity_id: &str) -> Result<T> {
+            Err(ContragError::EntityNotFound(entity_id.to_string()))
+        }
+
+        async fn read_entities<T: RagEntity + CandidType + Send>(&self, _: &str, _: Vec<String>) -> Result<Vec<T>> {
+            Ok(vec![])
+        }
+
+        async fn read_page<T: RagEntity + CandidType + Send>(
+            &self,
+            _: &str,
+            cursor: Option<String>,
+        ) -> Result<crate::data_sources::EntityPage<T>> {
+            let page: u32 = cursor.map_or(0, |cursor| cursor.parse().unwrap());
+            if self.fail_at == Some(page) {
+                return Err(ContragError::DataSourceError(format!("page {} unavailable", page)));
+            }
+            Ok(crate::data_sources::EntityPage {
+                items: vec![],
+                next_cursor: (page + 1 < self.pages).then(|| (page + 1).to_string()),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ingest_all_follows_cursors_and_resumes_failed_pages() {
+        let mut pipeline = RagPipeline::new(
+            MockEmbedder::new(2),
+            StableMemoryVectorStore::new(),
+            PipelineConfig::default(),
+        );
+
+        let mut cursors = vec![];
+        let progress = pipeline
+            .ingest_all::<Order, _, _>(&PagedCollection { pages: 3, fail_at: None }, |p| cursors.push(p.cursor.clone()))
+            .await
+            .unwrap();
+        assert_eq!(cursors, [Some("1".to_string()), Some("2".to_string()), None]);
+        assert_eq!((progress.entity_type.as_str(), progress.pages, progress.finished), ("Order", 3, true));
+
+        // A failed page leaves the progress as it was, to be retried
+        let mut progress = IngestAllProgress::new("Order", 0);
+        let flaky = PagedCollection { pages: 3, fail_at: Some(1) };
+        pipeline.ingest_page::<Order, _>(&flaky, &mut progress).await.unwrap();
+        assert!(pipeline.ingest_page::<Order, _>(&flaky, &mut progress).await.is_err());
+        assert_eq!((progress.cursor.as_deref(), progress.pages), (Some("1"), 1));
+
+        let recovered = PagedCollection { pages: 3, fail_at: None };
+        while !progress.finished {
+            pipeline.ingest_page::<Order, _>(&recovered, &mut progress).await.unwrap();
+        }
+        assert_eq!((progress.pages, progress.last_error.as_deref()), (3, None));
+
+        // Finished progress reads nothing more
+        pipeline.ingest_page::<Order, _>(&flaky, &mut progress).await.unwrap();
+        assert_eq!(progress.pages, 3);
+    }
+
+    fn vector(id: &str, entity_type: &str, entity_id: &str) -> Vector {
+        let mut vector = test_vector(id, vec![1.0, 0.0]);
+        vector.metadata.entity_type = entity_type.to_string();
+        vector.metadata.entity_id = entity_id.to_string();
+        vector
+    }
+
+    #[tokio::test]
+    async fn test_sync_deletes_only_missing_entity_namespaces() {
+        let mut store = StableMemoryVectorStore::new();
+        store.store("Order:2", vector("o2", "Order", "2")).await.unwrap();
+        store.store("Order:2:items", vector("item", "Order", "2")).await.unwrap();
+        store.store("Order:summaries", vector("summary", SUMMARY_ENTITY_TYPE, "topic-0")).await.unwrap();
+        let mut pipeline = RagPipeline::new(MockEmbedder::new(2).with_embedding(vec![1.0, 0.0]), store, PipelineConfig::default());
+
+        let report = pipeline.sync_collection::<Order, _>(&EmptyCollection { stuck: true }, None).await.unwrap();
+        assert_eq!(report.deleted, 0);
+        assert!(report.last_error.is_some());
+        assert_eq!(pipeline.store().count("Order:2").await.unwrap(), 1);
+
+        let report = pipeline.sync_collection::<Order, _>(&EmptyCollection { stuck: false }, None).await.unwrap();
+        assert_eq!(report.deleted, 1);
+        assert_eq!(pipeline.store().count("Order:2").await.unwrap(), 0);
+        assert_eq!(pipeline.store().count("Order:2:items").await.unwrap(), 1);
+        assert_eq!(pipeline.store().count("Order:summaries").await.unwrap(), 1);
+    }
+
+    /// Summarizes a prompt as the number of records in it
+    struct CountingGenerator;
+
+    #[async_trait::async_trait]
+    impl TextGenerator for CountingGenerator {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        async fn generate(&self, request: GenerationRequest) -> Result<String> {
+            Ok(format!("{} records", request.user.split("\n\n").count()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summary_job_keeps_its_plan_and_removes_stale_summaries() {
+        let mut store = StableMemoryVectorStore::new();
+        for id in ["1", "2", "3"] {
+            store.store("Order", vector(&format!("o{}", id), "Order", id)).await.unwrap();
+        }
+        for (source, key) in [("Order", "Order:9"), ("Invoice", "Invoice:1")] {
+            let mut summary = vector(key, SUMMARY_ENTITY_TYPE, key);
+            summary.metadata.custom = Some(serde_json::json!({ "source_namespace": source }).to_string());
+            store.store("summaries", summary).await.unwrap();
+        }
+        let mut pipeline = RagPipeline::new(MockEmbedder::new(2).with_embedding(vec![1.0, 0.0]), store, PipelineConfig::default())
+            .with_generator(CountingGenerator);
+
+        let job = SummaryJob::new("Order", "summaries", summarize::SummaryGrouping::Entity);
+        let mut progress = SummaryProgress::default();
+        pipeline.summarize_step(&job, &mut progress, 1).await.unwrap();
+        assert_eq!(progress.groups, 3);
+        assert!(!progress.finished);
+        let keys: Vec<String> = pipeline
+            .store()
+            .export_namespace("summaries")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|v| v.metadata.entity_id)
+            .collect();
+        assert!(!keys.contains(&"Order:9".to_string()));
+        assert!(keys.contains(&"Invoice:1".to_string()));
+
+        // Later steps follow the plan, not the changed namespace
+        pipeline.store_mut().delete("Order", "o2").await.unwrap();
+        pipeline.store_mut().store("Order", vector("o4", "Order", "4")).await.unwrap();
+        pipeline.summarize_step(&job, &mut progress, 10).await.unwrap();
+        assert!(progress.finished);
+        assert_eq!((progress.summaries, progress.failed), (2, 1));
+        assert!(progress.last_error.unwrap().starts_with("Order:2"));
+
+        let mut keys: Vec<String> = pipeline
+            .store()
+            .export_namespace("summaries")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|v| v.metadata.entity_id)
+            .collect();
+        keys.sort();
+        assert_eq!(keys, ["Invoice:1", "Order:1", "Order:3"]);
+    }
+
+    #[tokio::test]
+    async fn test_deletes_remove_ensemble_namespaces() {
+        let mut pipeline = RagPipeline::new(
+            MockEmbedder::new(2).with_embedding(vec![1.0, 0.0]),
+            StableMemoryVectorStore::new(),
+            PipelineConfig::default(),
+        )
+        .with_ensemble(Ensemble::new(MockEmbedder::new(3), 0.5));
+        for (namespace, id) in [("Order:1", "1"), ("Order:1", "9"), ("Order:2", "2"), ("Order:3", "3")] {
+            pipeline.ingest_text(namespace, "Order", id, "Shipped today.").await.unwrap();
+        }
+        assert_eq!(pipeline.store().count("ensemble:Order:1").await.unwrap(), 2);
+
+        pipeline.delete_entity("Order:1", "Order", "9").await.unwrap();
+        assert_eq!(pipeline.store().count("ensemble:Order:1").await.unwrap(), 1);
+
+        pipeline.delete_namespace("Order:1").await.unwrap();
+        assert_eq!(pipeline.store().count("ensemble:Order:1").await.unwrap(), 0);
+
+        let mut cursor = BulkCursor::new("Order:");
+        while !cursor.finished {
+            cursor = pipeline.delete_by_prefix(cursor, 1).await.unwrap();
+        }
+        assert_eq!(cursor.namespaces_done, 2);
+        assert!(pipeline.store().list_namespaces().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_documents_and_chunks_index_natively() {
+        let mut pipeline = RagPipeline::new(
+            MockEmbedder::new(2).with_embedding(vec![1.0, 0.0]),
+            StableMemoryVectorStore::new(),
+            PipelineConfig::default(),
+        );
+
+        let document = Document::new("faq", "Refunds", "Refunds take 5 days.")
+            .with_collection("help")
+            .with_source("help.example.com/faq");
+        assert_eq!(pipeline.ingest_document(&document).await.unwrap(), 1);
+
+        let chunks = [Chunk::new("guide", 0, "Install the app."), Chunk::new("guide", 1, "Sign in.")];
+        assert_eq!(pipeline.ingest_chunks("Collection:help", &chunks).await.unwrap(), 2);
+
+        let vectors = pipeline.store().export_namespace("Collection:help").await.unwrap();
+        assert_eq!(vectors.len(), 3);
+        assert!(vectors[0].text.starts_with("Title: Refunds\nSource: help.example.com/faq"));
+        assert!(vectors.iter().all(|v| v.metadata.entity_type == "Document"));
+        assert_eq!(vectors[2].id, "Document::guide::chunk_1");
+        assert_eq!((vectors[2].text.as_str(), vectors[2].metadata.total_chunks), ("Sign in.", 2));
+    }
+
+    #[tokio::test]
+    async fn test_reingested_document_replaces_its_chunks() {
+        let mut pipeline = RagPipeline::new(
+            MockEmbedder::new(2).with_embedding(vec![1.0, 0.0]),
+            StableMemoryVectorStore::new(),
+            PipelineConfig::default(),
+        )
+        .with_chunking(ChunkingConfig {
+            chunk_size: 40,
+            overlap: 0,
+            include_field_names: true,
+        });
+
+        let long = Document::new("faq", "Refunds", "Refunds take five business days. ".repeat(6)).with_collection("help");
+        assert!(pipeline.ingest_document(&long).await.unwrap() > 1);
+        let other = Document::new("guide", "Install", "Kept.").with_collection("help");
+        pipeline.ingest_document(&other).await.unwrap();
+
+        let short = Document::new("faq", "Refunds", "Instant.").with_collection("help");
+        assert_eq!(pipeline.ingest_document(&short).await.unwrap(), 1);
+        let vectors = pipeline.store().export_namespace(&short.namespace().unwrap()).await.unwrap();
+        let faq: Vec<&Vector> = vectors.iter().filter(|v| v.metadata.entity_id == "faq").collect();
+        assert_eq!(faq.len(), 1);
+        assert_eq!(faq[0].metadata.total_chunks, 1);
+        assert_eq!(vectors.len(), 2);
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::pipeline::query_cache::normalize_query;
+use crate::types::SearchResult;
+
+/// A query whose results are precomputed
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct PinnedQuery {
+    pub id: String,
+    pub namespace: String,
+    pub query: String,
+    pub k: usize,
+}
+
+/// Precomputed results of a pinned query
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct PinnedResults {
+    pub query: PinnedQuery,
+    pub results: Vec<SearchResult>,
+    /// When the results were computed (nanoseconds)
+    pub computed_at: u64,
+}
+
+struct PinnedEntry {
+    query: PinnedQuery,
+    embedding: Option<Vec<f32>>,
+    results: Option<(Vec<SearchResult>, u64)>,
+    stale: bool,
+}
+
+/// Registry of pinned queries and their precomputed results
+#[derive(Default)]
+pub struct PinnedQueries {
+    entries: BTreeMap<String, PinnedEntry>,
+}
+
+impl PinnedQueries {
+    /// Register or replace a pinned query; it is computed on the next refresh
+    pub fn pin(&mut self, query: PinnedQuery) {
+        self.entries.insert(query.id.clone(), PinnedEntry {
+            query,
+            embedding: None,
+            results: None,
+            stale: true,
+        });
+    }
+
+    pub fn unpin(&mut self, id: &str) -> bool {
+        self.entries.remove(id).is_some()
+    }
+
+    pub fn queries(&self) -> Vec<PinnedQuery> {
+        self.entries.values().map(|e| e.query.clone()).collect()
+    }
+
+    /// Mark the pinned queries of a namespace for recomputation
+    pub fn mark_stale(&mut self, namespace: &str) {
+        for entry in self.entries.values_mut() {
+            if entry.query.namespace == namespace {
+                entry.stale = true;
+            }
+        }
+    }
+
+    /// Pinned queries that need recomputing, with their embedding if known
+    pub fn stale(&self) -> Vec<(PinnedQuery, Option<Vec<f32>>)> {
+        self.entries
+            .values()
+            .filter(|e| e.stale)
+            .map(|e| (e.query.clone(), e.embedding.clone()))
+            .collect()
+    }
+
+    /// Store freshly computed results for a pinned query
+    pub fn update(&mut self, id: &str, embedding: Vec<f32>, results: Vec<SearchResult>, now: u64) {
+        if let Some(entry) = self.entries.get_mut(id) {
+            entry.embedding = Some(embedding);
+            entry.results = Some((results, now));
+            entry.stale = false;
+        }
+    }
+
+    /// Precomputed results of a pinned query by ID
+    pub fn results(&self, id: &str) -> Option<PinnedResults> {
+        let entry = self.entries.get(id)?;
+        let (results, computed_at) = entry.results.clone()?;
+        Some(PinnedResults {
+            query: entry.query.clone(),
+            results,
+            computed_at,
+        })
+    }
+
+    /// Fresh results for an ad-hoc query that matches a pinned one
+    pub fn lookup(&self, namespace: &str, query: &str, k: usize) -> Option<Vec<SearchResult>> {
+        let normalized = normalize_query(query);
+        self.entries
+            .values()
+            .filter(|e| !e.stale && e.query.namespace == namespace && e.query.k >= k)
+            .find(|e| normalize_query(&e.query.query) == normalized)
+            .and_then(|e| e.results.as_ref())
+            .map(|(results, _)| results.iter().take(k).cloned().collect())
+    }
+}
@@ -0,0 +1,152 @@
+use crate::config::ContextFormat;
+use crate::types::SearchResult;
+
+/// Builds the prompt sent to the model from retrieved chunks
+///
+/// The layout of the context depends on the [`ContextFormat`]; some models
+/// follow XML-style tags better, others do better with markdown.
+#[derive(Clone, Debug)]
+pub struct PromptAssembler {
+    format: ContextFormat,
+    max_context_chars: Option<usize>,
+}
+
+impl PromptAssembler {
+    pub fn new(format: ContextFormat) -> Self {
+        Self {
+            format,
+            max_context_chars: None,
+        }
+    }
+
+    /// Stop adding sources once the context reaches this many characters
+    pub fn with_max_context_chars(mut self, max_chars: usize) -> Self {
+        self.max_context_chars = Some(max_chars);
+        self
+    }
+
+    pub fn format(&self) -> ContextFormat {
+        self.format
+    }
+
+    /// Render the retrieved chunks as a context block
+    ///
+    /// Sources are numbered from 1 in all formats so answers can cite them.
+    pub fn assemble_context(&self, sources: &[SearchResult]) -> String {
+        let mut sections = vec![];
+        let mut length = 0;
+
+        for (i, source) in sources.iter().enumerate() {
+            let section = self.render_source(i + 1, source);
+            if let Some(max) = self.max_context_chars {
+                if length + section.len() > max && !sections.is_empty() {
+                    break;
+                }
+            }
+            length += section.len();
+            sections.push(section);
+        }
+
+        match self.format {
+            ContextFormat::Numbered => sections.join("\n\n"),
+            ContextFormat::Xml => format!("<sources>\n{}\n</sources>", sections.join("\n")),
+            ContextFormat::Markdown => sections.join("\n\n"),
+        }
+    }
+
+    /// Render the full user prompt for a question
+    pub fn assemble(&self, question: &str, sources: &[SearchResult]) -> String {
+        let context = self.assemble_context(sources);
+
+        match self.format {
+            ContextFormat::Numbered => format!("Context:\n{}\n\nQuestion: {}", context, question),
+            ContextFormat::Xml => format!(
+                "{}\n\n<question>{}</question>\n\nCite sources by their id.",
+                context,
+                escape_xml(question)
+            ),
+            ContextFormat::Markdown => format!(
+                "# Context\n\n{}\n\n# Question\n\n{}\n\nCite sources like [1].",
+                context, question
+            ),
+        }
+    }
+
+    fn render_source(&self, number: usize, source: &SearchResult) -> String {
+        let meta = &source.metadata;
+
+        match self.format {
+            ContextFormat::Numbered => format!("[{}] {}", number, source.text),
+            ContextFormat::Xml => format!(
+                "<source id=\"{}\" entity_type=\"{}\" entity_id=\"{}\" score=\"{:.3}\">\n{}\n</source>",
+                number,
+                escape_xml(&meta.entity_type),
+                escape_xml(&meta.entity_id),
+                source.score,
+                escape_xml(&source.text)
+            ),
+            ContextFormat::Markdown => format!(
+                "## [{}] {} {} (chunk {}/{})\n\n{}",
+                number,
+                meta.entity_type,
+                meta.entity_id,
+                meta.chunk_index + 1,
+                meta.total_chunks.max(1),
+                source.text
+            ),
+        }
+    }
+}
+
+impl Default for PromptAssembler {
+    fn default() -> Self {
+        Self::new(ContextFormat::Numbered)
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{test_vector, VectorMetadata};
+
+    fn source(text: &str) -> SearchResult {
+        SearchResult {
+            vector_id: "v1".to_string(),
+            text: text.to_string(),
+            score: 0.9,
+            metadata: VectorMetadata {
+                entity_type: "User".to_string(),
+                entity_id: "42".to_string(),
+                total_chunks: 2,
+                ..test_vector("v1", vec![]).metadata
+            },
+        }
+    }
+
+    #[test]
+    fn test_context_formats() {
+        let sources = vec![source("Alice <admin>"), source("Bob")];
+
+        let numbered = PromptAssembler::new(ContextFormat::Numbered).assemble_context(&sources);
+        assert_eq!(numbered, "[1] Alice <admin>\n\n[2] Bob");
+
+        let xml = PromptAssembler::new(ContextFormat::Xml).assemble_context(&sources);
+        assert!(xml.contains("<source id=\"1\" entity_type=\"User\" entity_id=\"42\" score=\"0.900\">"));
+        assert!(xml.contains("Alice &lt;admin&gt;"));
+
+        let markdown = PromptAssembler::new(ContextFormat::Markdown).assemble_context(&sources);
+        assert!(markdown.starts_with("## [1] User 42 (chunk 1/2)"));
+
+        let limited = PromptAssembler::new(ContextFormat::Numbered)
+            .with_max_context_chars(20)
+            .assemble_context(&sources);
+        assert_eq!(limited, "[1] Alice <admin>");
+    }
+}
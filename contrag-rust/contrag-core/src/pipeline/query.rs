@@ -0,0 +1,362 @@
+//! Combined entity and retrieval queries
+//!
+//! An [`EntityQuery`] names a root entity type, the ids to load (or a
+//! semantic search clause that picks them), the fields to return and the
+//! relationships to expand. The pipeline loads entities through registered
+//! [`EntityLoader`]s, usually backed by a [`DataSource`], and returns one
+//! [`QueryDocument`] so a UI needs a single round-trip.
+
+use std::collections::{BTreeMap, HashMap};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::data_sources::DataSource;
+use crate::entity::RagEntity;
+use crate::error::{ContragError, Result};
+use crate::types::{EntityRelationship, SearchResult};
+
+/// Largest number of root entities a query may return
+pub const MAX_QUERY_ENTITIES: usize = 100;
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct EntityQuery {
+    /// Root entity type, e.g. "User"
+    pub entity_type: String,
+
+    /// Root entity ids; when empty they come from the search matches
+    #[serde(default)]
+    pub ids: Vec<String>,
+
+    /// Fields to return; `None` returns all of them. A field also selects
+    /// its nested fields, so "profile" matches "profile.age".
+    #[serde(default)]
+    pub fields: Option<Vec<String>>,
+
+    /// Relationships to expand on every root entity
+    #[serde(default)]
+    pub include: Vec<RelationInclude>,
+
+    /// Semantic search run alongside the entity lookup
+    #[serde(default)]
+    pub search: Option<SearchClause>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct RelationInclude {
+    /// Relationship field name or target entity type
+    pub relationship: String,
+
+    /// Fields to return for the related entities
+    #[serde(default)]
+    pub fields: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct SearchClause {
+    pub namespace: String,
+    pub query: String,
+    #[serde(default)]
+    pub k: Option<u32>,
+}
+
+/// Result of an [`EntityQuery`]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct QueryDocument {
+    pub entities: Vec<DocumentEntity>,
+    /// Raw search matches, including ones for other entity types
+    pub matches: Vec<SearchResult>,
+    /// Entities or relationships that could not be loaded
+    pub errors: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct DocumentEntity {
+    pub entity_type: String,
+    pub entity_id: String,
+    pub fields: BTreeMap<String, String>,
+    /// Best search score of the entity's chunks, if it was matched
+    pub score: Option<f32>,
+    /// Expanded relationships, keyed by the requested relationship
+    pub related: BTreeMap<String, Vec<DocumentEntity>>,
+}
+
+/// An entity loaded without knowing its Rust type
+#[derive(Clone, Debug)]
+pub struct ResolvedEntity {
+    pub entity_type: String,
+    pub entity_id: String,
+    pub fields: Vec<(String, String)>,
+    pub relationships: Vec<EntityRelationship>,
+}
+
+impl ResolvedEntity {
+    pub fn from_entity<T: RagEntity>(entity: &T) -> Self {
+        Self {
+            entity_type: T::entity_type().to_string(),
+            entity_id: entity.entity_id(),
+            fields: entity.to_context_map(),
+            relationships: entity.relationships(),
+        }
+    }
+
+    fn to_document(&self, fields: Option<&[String]>, score: Option<f32>) -> DocumentEntity {
+        DocumentEntity {
+            entity_type: self.entity_type.clone(),
+            entity_id: self.entity_id.clone(),
+            fields: self
+                .fields
+                .iter()
+                .filter(|(key, _)| fields.map_or(true, |f| selects(f, key)))
+                .cloned()
+                .collect(),
+            score,
+            related: BTreeMap::new(),
+        }
+    }
+}
+
+/// Loads entities of one type by id
+///
+/// Missing ids are skipped rather than failing the whole load. Closures of
+/// the form `Fn(&[String]) -> Vec<ResolvedEntity>` implement this trait,
+/// which suits entities kept in the canister's own state.
+#[async_trait::async_trait]
+pub trait EntityLoader: Send + Sync {
+    async fn load(&self, ids: &[String]) -> Result<Vec<ResolvedEntity>>;
+}
+
+#[async_trait::async_trait]
+impl<F> EntityLoader for F
+where
+    F: Fn(&[String]) -> Vec<ResolvedEntity> + Send + Sync,
+{
+    async fn load(&self, ids: &[String]) -> Result<Vec<ResolvedEntity>> {
+        Ok(self(ids))
+    }
+}
+
+/// [`EntityLoader`] reading `T` entities from a [`DataSource`]
+pub struct SourceLoader<T, D> {
+    source: Arc<D>,
+    entity_type: String,
+    _entity: PhantomData<fn() -> T>,
+}
+
+impl<T, D> SourceLoader<T, D>
+where
+    T: RagEntity + Send + 'static,
+    D: DataSource + 'static,
+{
+    pub fn new(source: Arc<D>) -> Self {
+        Self {
+            source,
+            entity_type: T::entity_type().to_string(),
+            _entity: PhantomData,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T, D> EntityLoader for SourceLoader<T, D>
+where
+    T: RagEntity + Send + 'static,
+    D: DataSource + 'static,
+{
+    async fn load(&self, ids: &[String]) -> Result<Vec<ResolvedEntity>> {
+        let entities: Vec<T> = self
+            .source
+            .read_entities(&self.entity_type, ids.to_vec())
+            .await?;
+        Ok(entities.iter().map(ResolvedEntity::from_entity).collect())
+    }
+}
+
+/// Registered loaders by entity type
+#[derive(Default)]
+pub struct EntityLoaders {
+    loaders: HashMap<String, Box<dyn EntityLoader>>,
+}
+
+impl EntityLoaders {
+    pub fn register(&mut self, entity_type: &str, loader: Box<dyn EntityLoader>) {
+        self.loaders.insert(entity_type.to_string(), loader);
+    }
+
+    async fn load(&self, entity_type: &str, ids: &[String]) -> Result<Vec<ResolvedEntity>> {
+        let loader = self.loaders.get(entity_type).ok_or_else(|| {
+            ContragError::ConfigError(format!("No entity loader registered for {}", entity_type))
+        })?;
+        loader.load(ids).await
+    }
+
+    /// Load the roots, attach scores and expand the requested relationships
+    pub(crate) async fn execute(&self, query: &EntityQuery, matches: Vec<SearchResult>) -> Result<QueryDocument> {
+        let mut scores: HashMap<&str, f32> = HashMap::new();
+        let mut matched_ids: Vec<String> = vec![];
+        for m in matches.iter().filter(|m| m.metadata.entity_type == query.entity_type) {
+            let id = m.metadata.entity_id.as_str();
+            match scores.get_mut(id) {
+                Some(best) => *best = best.max(m.score),
+                None => {
+                    scores.insert(id, m.score);
+                    matched_ids.push(id.to_string());
+                }
+            }
+        }
+
+        let mut ids = if query.ids.is_empty() { matched_ids } else { query.ids.clone() };
+        ids.truncate(MAX_QUERY_ENTITIES);
+
+        let mut document = QueryDocument::default();
+        let roots = order_by_ids(self.load(&query.entity_type, &ids).await?, &ids);
+        for id in ids.iter().filter(|id| !roots.iter().any(|r| &r.entity_id == *id)) {
+            document.errors.push(format!("{} {} not found", query.entity_type, id));
+        }
+
+        let mut entities: Vec<DocumentEntity> = roots
+            .iter()
+            .map(|root| root.to_document(query.fields.as_deref(), scores.get(root.entity_id.as_str()).copied()))
+            .collect();
+
+        for include in &query.include {
+            self.expand(include, &roots, &mut entities, &mut document.errors).await;
+        }
+
+        document.entities = entities;
+        document.matches = matches;
+        Ok(document)
+    }
+
+    /// Load one relationship for all roots, one batch per target type
+    async fn expand(
+        &self,
+        include: &RelationInclude,
+        roots: &[ResolvedEntity],
+        entities: &mut [DocumentEntity],
+        errors: &mut Vec<String>,
+    ) {
+        let selected = |rel: &EntityRelationship| {
+            rel.field_name == include.relationship || rel.target_entity_type == include.relationship
+        };
+
+        let mut targets: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for rel in roots.iter().flat_map(|r| r.relationships.iter()).filter(|r| selected(r)) {
+            let ids = targets.entry(rel.target_entity_type.as_str()).or_default();
+            if !ids.contains(&rel.target_id) {
+                ids.push(rel.target_id.clone());
+            }
+        }
+
+        let mut loaded: HashMap<(String, String), ResolvedEntity> = HashMap::new();
+        for (entity_type, ids) in targets {
+            match self.load(entity_type, &ids).await {
+                Ok(found) => {
+                    for entity in found {
+                        loaded.insert((entity.entity_type.clone(), entity.entity_id.clone()), entity);
+                    }
+                }
+                Err(e) => errors.push(format!("{}: {}", include.relationship, e)),
+            }
+        }
+
+        for (root, entity) in roots.iter().zip(entities.iter_mut()) {
+            let related = root
+                .relationships
+                .iter()
+                .filter(|r| selected(r))
+                .filter_map(|r| loaded.get(&(r.target_entity_type.clone(), r.target_id.clone())))
+                .map(|target| target.to_document(include.fields.as_deref(), None))
+                .collect();
+            entity.related.insert(include.relationship.clone(), related);
+        }
+    }
+}
+
+/// Sort loaded entities in the order their ids were requested
+fn order_by_ids(mut entities: Vec<ResolvedEntity>, ids: &[String]) -> Vec<ResolvedEntity> {
+    entities.sort_by_key(|e| ids.iter().position(|id| *id == e.entity_id).unwrap_or(usize::MAX));
+    entities
+}
+
+/// Whether a field selection covers a context map key
+fn selects(fields: &[String], key: &str) -> bool {
+    fields.iter().any(|f| {
+        key == f || (key.starts_with(f.as_str()) && key[f.len()..].starts_with('.'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{test_vector, RelationshipType, VectorMetadata};
+
+    fn user(id: &str, order_id: &str) -> ResolvedEntity {
+        ResolvedEntity {
+            entity_type: "User".to_string(),
+            entity_id: id.to_string(),
+            fields: vec![
+                ("name".to_string(), format!("User {}", id)),
+                ("profile.age".to_string(), "30".to_string()),
+                ("email".to_string(), "a@b.c".to_string()),
+            ],
+            relationships: vec![EntityRelationship {
+                field_name: "orders".to_string(),
+                target_entity_type: "Order".to_string(),
+                target_id: order_id.to_string(),
+                relationship_type: RelationshipType::OneToMany,
+            }],
+        }
+    }
+
+    fn matched(entity_id: &str, score: f32) -> SearchResult {
+        SearchResult {
+            vector_id: format!("User:{}:0", entity_id),
+            text: String::new(),
+            score,
+            metadata: VectorMetadata {
+                entity_type: "User".to_string(),
+                ..test_vector(entity_id, vec![]).metadata
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_with_search_and_include() {
+        let mut loaders = EntityLoaders::default();
+        let users = |ids: &[String]| -> Vec<ResolvedEntity> {
+            ids.iter().filter(|id| *id != "404").map(|id| user(id, &format!("o{}", id))).collect()
+        };
+        let orders = |ids: &[String]| -> Vec<ResolvedEntity> {
+            ids.iter()
+                .map(|id| ResolvedEntity {
+                    entity_type: "Order".to_string(),
+                    entity_id: id.clone(),
+                    fields: vec![("total".to_string(), "10".to_string())],
+                    relationships: vec![],
+                })
+                .collect()
+        };
+        loaders.register("User", Box::new(users));
+        loaders.register("Order", Box::new(orders));
+
+        let query = EntityQuery {
+            entity_type: "User".to_string(),
+            ids: vec![],
+            fields: Some(vec!["name".to_string(), "profile".to_string()]),
+            include: vec![RelationInclude { relationship: "orders".to_string(), fields: None }],
+            search: None,
+        };
+        let matches = vec![matched("2", 0.9), matched("1", 0.8), matched("2", 0.7), matched("404", 0.5)];
+
+        let document = loaders.execute(&query, matches).await.unwrap();
+
+        assert_eq!(document.entities.len(), 2);
+        assert_eq!(document.entities[0].entity_id, "2");
+        assert_eq!(document.entities[0].score, Some(0.9));
+        assert!(document.entities[0].fields.contains_key("profile.age"));
+        assert!(!document.entities[0].fields.contains_key("email"));
+        assert_eq!(document.entities[0].related["orders"][0].entity_id, "o2");
+        assert_eq!(document.errors, vec!["User 404 not found".to_string()]);
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::utils::stable_hash;
+
+/// Hit and size counters of a query embedding cache
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct QueryCacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+struct CachedQuery {
+    embedding: Vec<f32>,
+    expires_at: u64,
+    last_used: u64,
+}
+
+/// Cache of query embeddings keyed by a hash of the normalized query text
+///
+/// Entries expire after a TTL; when full, the least recently used entry is
+/// evicted.
+pub struct QueryEmbeddingCache {
+    entries: HashMap<u64, CachedQuery>,
+    ttl_ns: u64,
+    max_entries: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl QueryEmbeddingCache {
+    pub fn new(ttl_secs: u64, max_entries: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            ttl_ns: ttl_secs.saturating_mul(1_000_000_000),
+            max_entries,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Whether the cache stores anything at all
+    pub fn is_enabled(&self) -> bool {
+        self.ttl_ns > 0 && self.max_entries > 0
+    }
+
+    /// Look up a live entry, counting the hit or miss
+    pub fn get(&mut self, key: u64, now: u64) -> Option<Vec<f32>> {
+        match self.entries.get_mut(&key) {
+            Some(entry) if entry.expires_at > now => {
+                entry.last_used = now;
+                self.hits += 1;
+                Some(entry.embedding.clone())
+            }
+            Some(_) => {
+                self.entries.remove(&key);
+                self.misses += 1;
+                None
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, key: u64, embedding: Vec<f32>, now: u64) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        if self.entries.len() >= self.max_entries && !self.entries.contains_key(&key) {
+            self.entries.retain(|_, e| e.expires_at > now);
+        }
+        if self.entries.len() >= self.max_entries && !self.entries.contains_key(&key) {
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| *k)
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.entries.insert(key, CachedQuery {
+            embedding,
+            expires_at: now.saturating_add(self.ttl_ns),
+            last_used: now,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn stats(&self) -> QueryCacheStats {
+        QueryCacheStats {
+            entries: self.entries.len(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+}
+
+/// Normalize a query so trivially different spellings share an embedding
+///
+/// Lowercases and collapses whitespace.
+pub fn normalize_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Cache key of a query for a given embedding model
+pub fn query_key(model: &str, query: &str) -> u64 {
+    stable_hash(&format!("{}\0{}", model, normalize_query(query)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalized_hits_and_expiry() {
+        let mut cache = QueryEmbeddingCache::new(10, 2);
+        let key = query_key("model", "What did  Alice order?");
+        assert_eq!(key, query_key("model", "what did alice order?"));
+        assert_ne!(key, query_key("other-model", "what did alice order?"));
+
+        cache.insert(key, vec![1.0], 0);
+        assert_eq!(cache.get(key, 1), Some(vec![1.0]));
+        assert_eq!(cache.get(key, 10_000_000_000), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 0));
+    }
+}
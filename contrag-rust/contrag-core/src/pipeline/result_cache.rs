@@ -0,0 +1,174 @@
+use std::collections::{HashMap, VecDeque};
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::types::SearchResult;
+
+/// Search results served by [`RagPipeline::search_swr`](super::RagPipeline::search_swr)
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct CachedSearch {
+    pub results: Vec<SearchResult>,
+    /// Served from an entry past its freshness window or invalidated by an
+    /// ingestion; a refresh is pending
+    pub stale: bool,
+    /// Age of the served results in seconds (0 for a live search)
+    pub age_secs: u64,
+    /// This call queued the refresh; schedule
+    /// [`RagPipeline::revalidate`](super::RagPipeline::revalidate) when set
+    pub refresh_scheduled: bool,
+}
+
+/// A search to rerun in the background
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingRefresh {
+    pub namespace: String,
+    pub query: String,
+    pub k: usize,
+}
+
+/// Whether a cached entry can be served as-is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Freshness {
+    Fresh,
+    Stale,
+}
+
+type CacheKey = (String, u64, usize);
+
+struct CachedResults {
+    query: String,
+    results: Vec<SearchResult>,
+    cached_at: u64,
+    invalidated: bool,
+}
+
+/// Search results kept for stale-while-revalidate serving
+///
+/// Entries are fresh for `fresh_secs`, may be served stale (while a refresh
+/// is pending) until `max_stale_secs`, and are dropped after that. Ingesting
+/// into a namespace marks its entries stale rather than dropping them.
+pub struct SearchResultCache {
+    entries: HashMap<CacheKey, CachedResults>,
+    pending: VecDeque<CacheKey>,
+    fresh_ns: u64,
+    max_stale_ns: u64,
+    max_entries: usize,
+}
+
+impl SearchResultCache {
+    pub fn new(fresh_secs: u64, max_stale_secs: u64, max_entries: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            pending: VecDeque::new(),
+            fresh_ns: fresh_secs.saturating_mul(1_000_000_000),
+            max_stale_ns: max_stale_secs.saturating_mul(1_000_000_000),
+            max_entries,
+        }
+    }
+
+    /// Cached results with their age in nanoseconds and freshness
+    pub fn get(&mut self, namespace: &str, key: u64, k: usize, now: u64) -> Option<(Vec<SearchResult>, u64, Freshness)> {
+        let cache_key = (namespace.to_string(), key, k);
+        let entry = self.entries.get(&cache_key)?;
+        let age = now.saturating_sub(entry.cached_at);
+
+        if age > self.max_stale_ns.max(self.fresh_ns) {
+            self.entries.remove(&cache_key);
+            return None;
+        }
+
+        let freshness = if entry.invalidated || age > self.fresh_ns {
+            Freshness::Stale
+        } else {
+            Freshness::Fresh
+        };
+        Some((entry.results.clone(), age, freshness))
+    }
+
+    pub fn insert(&mut self, namespace: &str, key: u64, k: usize, query: &str, results: Vec<SearchResult>, now: u64) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let cache_key = (namespace.to_string(), key, k);
+        if self.entries.len() >= self.max_entries && !self.entries.contains_key(&cache_key) {
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.cached_at)
+                .map(|(k, _)| k.clone())
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.entries.insert(cache_key, CachedResults {
+            query: query.to_string(),
+            results,
+            cached_at: now,
+            invalidated: false,
+        });
+    }
+
+    /// Queue a refresh; returns false if one is already pending
+    pub fn request_refresh(&mut self, namespace: &str, key: u64, k: usize) -> bool {
+        let cache_key = (namespace.to_string(), key, k);
+        if self.pending.contains(&cache_key) || !self.entries.contains_key(&cache_key) {
+            return false;
+        }
+        self.pending.push_back(cache_key);
+        true
+    }
+
+    /// Take all pending refreshes, oldest request first
+    pub fn take_pending(&mut self) -> Vec<(u64, PendingRefresh)> {
+        let pending: Vec<CacheKey> = self.pending.drain(..).collect();
+        pending
+            .into_iter()
+            .filter_map(|(namespace, key, k)| {
+                let query = self.entries.get(&(namespace.clone(), key, k))?.query.clone();
+                Some((key, PendingRefresh { namespace, query, k }))
+            })
+            .collect()
+    }
+
+    /// Mark all entries of a namespace stale
+    pub fn mark_stale(&mut self, namespace: &str) {
+        for ((ns, _, _), entry) in self.entries.iter_mut() {
+            if ns == namespace {
+                entry.invalidated = true;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEC: u64 = 1_000_000_000;
+
+    #[test]
+    fn test_fresh_stale_and_expired() {
+        let mut cache = SearchResultCache::new(10, 60, 10);
+        cache.insert("User:1", 7, 5, "orders", vec![], 0);
+
+        assert_eq!(cache.get("User:1", 7, 5, 5 * SEC).unwrap().2, Freshness::Fresh);
+        assert_eq!(cache.get("User:1", 7, 5, 20 * SEC).unwrap().2, Freshness::Stale);
+
+        assert!(cache.request_refresh("User:1", 7, 5));
+        assert!(!cache.request_refresh("User:1", 7, 5));
+        let pending = cache.take_pending();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].1.query, "orders");
+
+        cache.insert("User:1", 7, 5, "orders", vec![], 20 * SEC);
+        cache.mark_stale("User:1");
+        assert_eq!(cache.get("User:1", 7, 5, 21 * SEC).unwrap().2, Freshness::Stale);
+        assert!(cache.get("User:1", 7, 5, 100 * SEC).is_none());
+    }
+}
@@ -0,0 +1,137 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::config::{RetrievalProfile, RoutingRule};
+
+/// How the retrieval profile of a query was chosen
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+pub enum RoutingMethod {
+    /// A keyword rule matched
+    Keyword,
+    /// The model classified the query
+    Classifier,
+    /// Nothing matched; the router's default profile (if any) was used
+    Default,
+}
+
+/// Routing decision recorded in the answer diagnostics
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct RoutingDecision {
+    /// Selected profile; `None` means the pipeline defaults
+    pub profile: Option<String>,
+    pub method: RoutingMethod,
+    /// Keyword that matched, for keyword routing
+    pub matched_keyword: Option<String>,
+    /// Raw classifier reply, for classifier routing
+    pub classifier_reply: Option<String>,
+}
+
+impl RoutingDecision {
+    pub fn default_profile(profile: Option<String>) -> Self {
+        Self {
+            profile,
+            method: RoutingMethod::Default,
+            matched_keyword: None,
+            classifier_reply: None,
+        }
+    }
+}
+
+/// First rule with a keyword in the query, as `(profile, keyword)`
+pub fn match_keywords<'a>(rules: &'a [RoutingRule], query: &str) -> Option<(&'a str, &'a str)> {
+    let query = format!(" {} ", normalize(query));
+
+    rules.iter().find_map(|rule| {
+        rule.keywords
+            .iter()
+            .find(|keyword| {
+                let keyword = normalize(keyword);
+                !keyword.is_empty() && query.contains(&format!(" {} ", keyword))
+            })
+            .map(|keyword| (rule.profile.as_str(), keyword.as_str()))
+    })
+}
+
+/// System prompt asking the model to pick one of the profiles
+pub fn classifier_prompt(profiles: &[RetrievalProfile]) -> String {
+    let options: Vec<String> = profiles
+        .iter()
+        .map(|p| match &p.description {
+            Some(description) => format!("- {}: {}", p.name, description),
+            None => format!("- {}", p.name),
+        })
+        .collect();
+
+    format!(
+        "You route questions to a knowledge base. Pick the category that best \
+matches the question. Reply with the category name only, or \"none\" if no \
+category fits.\n\nCategories:\n{}",
+        options.join("\n")
+    )
+}
+
+/// Profile named in a classifier reply
+///
+/// An exact (case-insensitive) answer wins; otherwise the first profile
+/// mentioned as a word in the reply.
+pub fn parse_classification<'a>(reply: &str, profiles: &'a [RetrievalProfile]) -> Option<&'a str> {
+    let trimmed = reply.trim().trim_matches(|c: char| c == '"' || c == '\'' || c == '.');
+    if let Some(profile) = profiles.iter().find(|p| p.name.eq_ignore_ascii_case(trimmed)) {
+        return Some(profile.name.as_str());
+    }
+
+    let reply = format!(" {} ", normalize(reply));
+    profiles
+        .iter()
+        .find(|p| reply.contains(&format!(" {} ", normalize(&p.name))))
+        .map(|p| p.name.as_str())
+}
+
+/// Lowercase and reduce to words separated by single spaces
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ContextFormat;
+
+    fn profile(name: &str) -> RetrievalProfile {
+        RetrievalProfile {
+            name: name.to_string(),
+            k: None,
+            context_format: ContextFormat::Numbered,
+            max_context_chars: None,
+            system_prompt: None,
+            namespace: None,
+            description: None,
+        }
+    }
+
+    #[test]
+    fn test_keyword_and_classifier_routing() {
+        let rules = vec![
+            RoutingRule {
+                profile: "billing".to_string(),
+                keywords: vec!["invoice".to_string(), "refund policy".to_string()],
+            },
+            RoutingRule {
+                profile: "product".to_string(),
+                keywords: vec!["feature".to_string()],
+            },
+        ];
+
+        assert_eq!(match_keywords(&rules, "What's the Refund-Policy?"), Some(("billing", "refund policy")));
+        assert_eq!(match_keywords(&rules, "Which features ship next?"), None);
+        assert_eq!(match_keywords(&rules, "Is this feature free?"), Some(("product", "feature")));
+
+        let profiles = vec![profile("billing"), profile("product")];
+        assert_eq!(parse_classification("Product", &profiles), Some("product"));
+        assert_eq!(parse_classification("The category is billing.", &profiles), Some("billing"));
+        assert_eq!(parse_classification("none", &profiles), None);
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::types::{Vector, VectorMetadata};
+use crate::vector_store::cosine_similarity;
+
+/// Entity type of stored summary vectors
+pub const SUMMARY_ENTITY_TYPE: &str = "Summary";
+
+/// Default instructions for summarizing a group of chunks
+pub const DEFAULT_SUMMARY_PROMPT: &str = "Summarize the following records in a short paragraph. \
+Mention the most important facts, figures and changes; do not add information that is not in the records.";
+
+/// k-means iterations when grouping by topic
+const CLUSTER_ITERATIONS: usize = 10;
+
+/// How chunks are grouped into summaries
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CandidType)]
+pub enum SummaryGrouping {
+    /// One summary per entity
+    Entity,
+    /// One summary per cluster of similar chunks, up to `clusters` of them
+    Topic { clusters: u32 },
+}
+
+/// A batch summarization job over one namespace
+///
+/// Selects the namespace's chunks (optionally only one entity type and only
+/// chunks indexed since a timestamp, e.g. "orders of the last week"),
+/// groups them, has the embedder's LLM summarize each group and stores the
+/// summaries as `Summary` vectors in `target_namespace`, replacing the
+/// summaries an earlier run made of the same namespace. Plain data, so jobs can be kept in
+/// canister state and run in steps from a timer.
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct SummaryJob {
+    pub namespace: String,
+    pub target_namespace: String,
+    pub grouping: SummaryGrouping,
+    /// Only summarize chunks of this entity type
+    pub entity_type: Option<String>,
+    /// Only summarize chunks indexed at or after this timestamp (ns)
+    pub since: Option<u64>,
+    /// Chunks sent to the LLM per group; topics keep the most central ones
+    pub max_chunks_per_group: u32,
+    /// System prompt for summarization; defaults to [`DEFAULT_SUMMARY_PROMPT`]
+    pub prompt: Option<String>,
+}
+
+impl SummaryJob {
+    pub fn new(namespace: impl Into<String>, target_namespace: impl Into<String>, grouping: SummaryGrouping) -> Self {
+        Self {
+            namespace: namespace.into(),
+            target_namespace: target_namespace.into(),
+            grouping,
+            entity_type: None,
+            since: None,
+            max_chunks_per_group: 20,
+            prompt: None,
+        }
+    }
+
+    pub fn with_entity_type(mut self, entity_type: impl Into<String>) -> Self {
+        self.entity_type = Some(entity_type.into());
+        self
+    }
+
+    pub fn with_since(mut self, since: u64) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn with_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = Some(prompt.into());
+        self
+    }
+
+    /// Whether a chunk is selected by the job's filters
+    pub fn matches(&self, metadata: &VectorMetadata) -> bool {
+        metadata.entity_type != SUMMARY_ENTITY_TYPE
+            && self.entity_type.as_ref().is_none_or(|t| *t == metadata.entity_type)
+            && self.since.is_none_or(|since| metadata.timestamp >= since)
+    }
+
+    pub fn prompt(&self) -> &str {
+        self.prompt.as_deref().unwrap_or(DEFAULT_SUMMARY_PROMPT)
+    }
+}
+
+/// Progress of a summary job, kept between steps
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct SummaryProgress {
+    /// Groups chosen by the job's first step; later steps summarize these
+    /// instead of regrouping a namespace that may have changed
+    #[serde(default)]
+    pub plan: Option<Vec<PlannedGroup>>,
+    /// Index of the next group to summarize
+    pub next_group: u64,
+    pub groups: u64,
+    pub summaries: u64,
+    pub failed: u64,
+    pub last_error: Option<String>,
+    pub finished: bool,
+}
+
+/// A group of a job's plan: its key and the IDs of its chunks in order
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CandidType)]
+pub struct PlannedGroup {
+    pub key: String,
+    pub chunk_ids: Vec<String>,
+}
+
+impl From<&SummaryGroup> for PlannedGroup {
+    fn from(group: &SummaryGroup) -> Self {
+        Self {
+            key: group.key.clone(),
+            chunk_ids: group.chunks.iter().map(|c| c.id.clone()).collect(),
+        }
+    }
+}
+
+/// Chunks summarized together
+#[derive(Clone, Debug)]
+pub struct SummaryGroup {
+    /// `{entity_type}:{entity_id}` or `topic-N`
+    pub key: String,
+    pub chunks: Vec<Vector>,
+}
+
+impl SummaryGroup {
+    /// Chunks as the LLM input, each tagged with its entity
+    pub fn to_prompt_text(&self) -> String {
+        self.chunks
+            .iter()
+            .map(|c| format!("[{}:{}] {}", c.metadata.entity_type, c.metadata.entity_id, c.text))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// Namespace a stored summary was made from, read from its metadata
+pub fn summary_source(metadata: &VectorMetadata) -> Option<String> {
+    let custom: serde_json::Value = serde_json::from_str(metadata.custom.as_deref()?).ok()?;
+    custom.get("source_namespace")?.as_str().map(str::to_string)
+}
+
+/// Group chunks for summarization, deterministically for the same input
+pub fn group_chunks(chunks: Vec<Vector>, grouping: &SummaryGrouping, max_chunks: usize) -> Vec<SummaryGroup> {
+    match grouping {
+        SummaryGrouping::Entity => {
+            let mut by_entity: BTreeMap<String, Vec<Vector>> = BTreeMap::new();
+            for chunk in chunks {
+                let key = format!("{}:{}", chunk.metadata.entity_type, chunk.metadata.entity_id);
+                by_entity.entry(key).or_default().push(chunk);
+            }
+
+            by_entity
+                .into_iter()
+                .map(|(key, mut chunks)| {
+                    chunks.sort_by_key(|c| c.metadata.chunk_index);
+                    chunks.truncate(max_chunks);
+                    SummaryGroup { key, chunks }
+                })
+                .collect()
+        }
+        SummaryGrouping::Topic { clusters } => cluster(chunks, *clusters as usize, max_chunks)
+            .into_iter()
+            .enumerate()
+            .map(|(i, chunks)| SummaryGroup {
+                key: format!("topic-{}", i),
+                chunks,
+            })
+            .collect(),
+    }
+}
+
+/// k-means over cosine similarity, largest cluster first
+///
+/// Each cluster keeps its `max_chunks` chunks closest to the centroid.
+fn cluster(chunks: Vec<Vector>, clusters: usize, max_chunks: usize) -> Vec<Vec<Vector>> {
+    let k = clusters.min(chunks.len());
+    if k == 0 {
+        return vec![];
+    }
+
+    // Spread the initial centroids over the input
+    let mut centroids: Vec<Vec<f32>> = (0..k)
+        .map(|i| chunks[i * chunks.len() / k].embedding.clone())
+        .collect();
+    let mut assignment = vec![0; chunks.len()];
+
+    for _ in 0..CLUSTER_ITERATIONS {
+        for (chunk, assigned) in chunks.iter().zip(assignment.iter_mut()) {
+            *assigned = nearest(&centroids, &chunk.embedding);
+        }
+
+        for (c, centroid) in centroids.iter_mut().enumerate() {
+            let members: Vec<&Vec<f32>> = chunks
+                .iter()
+                .zip(&assignment)
+                .filter(|(_, assigned)| **assigned == c)
+                .map(|(chunk, _)| &chunk.embedding)
+                .collect();
+            if let Some(mean) = mean(&members) {
+                *centroid = mean;
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<(f32, Vector)>> = vec![vec![]; k];
+    for (chunk, c) in chunks.into_iter().zip(assignment) {
+        let similarity = cosine_similarity(&centroids[c], &chunk.embedding);
+        groups[c].push((similarity, chunk));
+    }
+
+    let mut groups: Vec<Vec<Vector>> = groups
+        .into_iter()
+        .filter(|group| !group.is_empty())
+        .map(|mut group| {
+            group.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+            group.into_iter().take(max_chunks).map(|(_, chunk)| chunk).collect()
+        })
+        .collect();
+    groups.sort_by(|a, b| b.len().cmp(&a.len()));
+    groups
+}
+
+fn nearest(centroids: &[Vec<f32>], embedding: &[f32]) -> usize {
+    centroids
+        .iter()
+        .map(|centroid| cosine_similarity(centroid, embedding))
+        .enumerate()
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+fn mean(embeddings: &[&Vec<f32>]) -> Option<Vec<f32>> {
+    let first = embeddings.first()?;
+    let mut sum = vec![0.0; first.len()];
+    for embedding in embeddings {
+        for (s, v) in sum.iter_mut().zip(embedding.iter()) {
+            *s += v;
+        }
+    }
+    Some(sum.into_iter().map(|s| s / embeddings.len() as f32).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::test_vector;
+
+    fn chunk(entity_id: &str, index: usize, embedding: Vec<f32>, timestamp: u64) -> Vector {
+        let mut vector = Vector {
+            text: format!("{} part {}", entity_id, index),
+            ..test_vector(&format!("{}-{}", entity_id, index), embedding)
+        };
+        vector.metadata = VectorMetadata {
+            entity_type: "Order".to_string(),
+            entity_id: entity_id.to_string(),
+            chunk_index: index,
+            total_chunks: 2,
+            timestamp,
+            ..vector.metadata
+        };
+        vector
+    }
+
+    #[test]
+    fn test_groups_by_entity_and_topic() {
+        let chunks = vec![
+            chunk("2", 1, vec![0.0, 1.0], 20),
+            chunk("1", 0, vec![1.0, 0.0], 5),
+            chunk("2", 0, vec![0.1, 1.0], 20),
+            chunk("3", 0, vec![1.0, 0.1], 20),
+        ];
+
+        let job = SummaryJob::new("Orders", "Orders:summaries", SummaryGrouping::Entity).with_since(10);
+        let selected: Vec<Vector> = chunks.iter().filter(|c| job.matches(&c.metadata)).cloned().collect();
+        let groups = group_chunks(selected, &job.grouping, 20);
+        assert_eq!(groups.iter().map(|g| g.key.as_str()).collect::<Vec<_>>(), ["Order:2", "Order:3"]);
+        assert_eq!(groups[0].chunks[0].metadata.chunk_index, 0);
+
+        let topics = group_chunks(chunks, &SummaryGrouping::Topic { clusters: 2 }, 20);
+        assert_eq!(topics.len(), 2);
+        let mut ids: Vec<&str> = topics[0].chunks.iter().map(|c| c.metadata.entity_id.as_str()).collect();
+        ids.sort();
+        assert!(ids == ["1", "3"] || ids == ["2", "2"], "{:?}", ids);
+    }
+}
@@ -0,0 +1,224 @@
+//! Compact JSON results for agent tool / function calls
+//!
+//! [`ToolResult`] serializes to the schema in [`TOOL_RESULT_SCHEMA`], which
+//! can be handed to an agent framework as the tool's output description.
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::error::{ContragError, Result};
+use crate::types::SearchResult;
+use crate::utils::truncate_text;
+
+/// Identifier of the tool result format, bumped on breaking changes
+pub const TOOL_RESULT_VERSION: &str = "contrag.tool_result.v1";
+
+/// JSON Schema of a serialized [`ToolResult`]
+pub const TOOL_RESULT_SCHEMA: &str = r#"{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "title": "contrag.tool_result.v1",
+  "type": "object",
+  "required": ["version", "query", "namespace", "results", "entities", "suggested_filters"],
+  "properties": {
+    "version": {"const": "contrag.tool_result.v1"},
+    "query": {"type": "string"},
+    "namespace": {"type": "string"},
+    "results": {
+      "type": "array",
+      "items": {
+        "type": "object",
+        "required": ["rank", "score", "text", "entity_type", "entity_id", "chunk"],
+        "properties": {
+          "rank": {"type": "integer", "minimum": 1},
+          "score": {"type": "number"},
+          "text": {"type": "string"},
+          "entity_type": {"type": "string"},
+          "entity_id": {"type": "string"},
+          "chunk": {"type": "string", "description": "1-based chunk position, e.g. \"1/3\""}
+        }
+      }
+    },
+    "entities": {
+      "type": "array",
+      "description": "Distinct entities in the results, best match first",
+      "items": {
+        "type": "object",
+        "required": ["entity_type", "entity_id", "best_score", "chunks"],
+        "properties": {
+          "entity_type": {"type": "string"},
+          "entity_id": {"type": "string"},
+          "best_score": {"type": "number"},
+          "chunks": {"type": "integer"}
+        }
+      }
+    },
+    "suggested_filters": {
+      "type": "array",
+      "description": "Filters that would narrow a follow-up query",
+      "items": {
+        "type": "object",
+        "required": ["field", "value", "matches"],
+        "properties": {
+          "field": {"enum": ["entity_type", "entity_id"]},
+          "value": {"type": "string"},
+          "matches": {"type": "integer"}
+        }
+      }
+    }
+  }
+}"#;
+
+/// Search results shaped for an agent tool response
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ToolResult {
+    pub version: String,
+    pub query: String,
+    pub namespace: String,
+    pub results: Vec<ToolChunk>,
+    pub entities: Vec<ToolEntity>,
+    pub suggested_filters: Vec<ToolFilter>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ToolChunk {
+    pub rank: usize,
+    pub score: f32,
+    pub text: String,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub chunk: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ToolEntity {
+    pub entity_type: String,
+    pub entity_id: String,
+    pub best_score: f32,
+    pub chunks: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ToolFilter {
+    pub field: String,
+    pub value: String,
+    pub matches: usize,
+}
+
+impl ToolResult {
+    /// Build a tool result, truncating chunk texts to `max_text_chars`
+    pub fn new(namespace: &str, query: &str, results: &[SearchResult], max_text_chars: usize) -> Self {
+        let chunks = results
+            .iter()
+            .enumerate()
+            .map(|(i, r)| ToolChunk {
+                rank: i + 1,
+                score: r.score,
+                text: truncate_text(&r.text, max_text_chars),
+                entity_type: r.metadata.entity_type.clone(),
+                entity_id: r.metadata.entity_id.clone(),
+                chunk: format!("{}/{}", r.metadata.chunk_index + 1, r.metadata.total_chunks.max(1)),
+            })
+            .collect();
+
+        let mut entities: Vec<ToolEntity> = vec![];
+        for r in results {
+            let meta = &r.metadata;
+            match entities
+                .iter_mut()
+                .find(|e| e.entity_type == meta.entity_type && e.entity_id == meta.entity_id)
+            {
+                Some(entity) => {
+                    entity.best_score = entity.best_score.max(r.score);
+                    entity.chunks += 1;
+                }
+                None => entities.push(ToolEntity {
+                    entity_type: meta.entity_type.clone(),
+                    entity_id: meta.entity_id.clone(),
+                    best_score: r.score,
+                    chunks: 1,
+                }),
+            }
+        }
+        entities.sort_by(|a, b| {
+            b.best_score
+                .partial_cmp(&a.best_score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        Self {
+            version: TOOL_RESULT_VERSION.to_string(),
+            query: query.to_string(),
+            namespace: namespace.to_string(),
+            results: chunks,
+            suggested_filters: suggest_filters(&entities),
+            entities,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(|e| ContragError::SerializationError(e.to_string()))
+    }
+}
+
+/// Suggest narrowing filters when results span several types or entities
+fn suggest_filters(entities: &[ToolEntity]) -> Vec<ToolFilter> {
+    let mut by_type: BTreeMap<&str, usize> = BTreeMap::new();
+    for entity in entities {
+        *by_type.entry(entity.entity_type.as_str()).or_default() += entity.chunks;
+    }
+
+    let mut filters = vec![];
+    if by_type.len() > 1 {
+        filters.extend(by_type.iter().map(|(entity_type, matches)| ToolFilter {
+            field: "entity_type".to_string(),
+            value: entity_type.to_string(),
+            matches: *matches,
+        }));
+    }
+    if entities.len() > 1 {
+        filters.extend(entities.iter().take(3).map(|e| ToolFilter {
+            field: "entity_id".to_string(),
+            value: e.entity_id.clone(),
+            matches: e.chunks,
+        }));
+    }
+
+    filters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{test_vector, VectorMetadata};
+
+    fn result(entity_type: &str, entity_id: &str, score: f32) -> SearchResult {
+        SearchResult {
+            vector_id: format!("{}:{}", entity_type, entity_id),
+            text: "some text".to_string(),
+            score,
+            metadata: VectorMetadata {
+                entity_type: entity_type.to_string(),
+                ..test_vector(entity_id, vec![]).metadata
+            },
+        }
+    }
+
+    #[test]
+    fn test_tool_result_shape() {
+        let results = vec![
+            result("Order", "7", 0.9),
+            result("User", "42", 0.8),
+            result("Order", "7", 0.7),
+        ];
+
+        let tool = ToolResult::new("User:42", "recent orders", &results, 100);
+
+        assert_eq!(tool.entities.len(), 2);
+        assert_eq!(tool.entities[0].chunks, 2);
+        assert!(tool.suggested_filters.iter().any(|f| f.field == "entity_type" && f.value == "Order"));
+
+        let json: serde_json::Value = serde_json::from_str(&tool.to_json().unwrap()).unwrap();
+        assert_eq!(json["version"], TOOL_RESULT_VERSION);
+        assert_eq!(json["results"][0]["chunk"], "1/1");
+        assert!(serde_json::from_str::<serde_json::Value>(TOOL_RESULT_SCHEMA).is_ok());
+    }
+}
@@ -0,0 +1,262 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use candid::CandidType;
+use ic_stable_structures::Memory;
+use serde::{Deserialize, Serialize};
+use crate::config::{ChunkingConfig, ContragConfig};
+use crate::error::{ContragError, Result};
+use crate::storage::memory::{read_blob, write_blob};
+use crate::storage::migrations::{encode_versioned, Migrator, StorageComponent};
+
+/// Configuration that produced the vectors of a namespace
+///
+/// Recorded on every ingestion so operators can tell whether an index still
+/// matches the running configuration or needs to be rebuilt.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CandidType)]
+pub struct Provenance {
+    /// Embedder provider, e.g. "openai"
+    pub embedder: String,
+    /// Embedding model, when known
+    pub model: Option<String>,
+    pub dimensions: u64,
+    pub chunk_size: u64,
+    pub chunk_overlap: u64,
+    pub include_field_names: bool,
+    /// Version of the application's entity text templates
+    pub template_version: Option<String>,
+    /// contrag version that wrote the vectors
+    pub contrag_version: String,
+    /// Application build or release that wrote the vectors
+    pub code_version: Option<String>,
+}
+
+impl Provenance {
+    pub fn new(embedder: impl Into<String>, dimensions: usize, chunking: &ChunkingConfig) -> Self {
+        Self {
+            embedder: embedder.into(),
+            model: None,
+            dimensions: dimensions as u64,
+            chunk_size: chunking.chunk_size as u64,
+            chunk_overlap: chunking.overlap as u64,
+            include_field_names: chunking.include_field_names,
+            template_version: None,
+            contrag_version: env!("CARGO_PKG_VERSION").to_string(),
+            code_version: None,
+        }
+    }
+
+    /// Provenance of vectors written with a loaded config
+    pub fn from_config(config: &ContragConfig) -> Self {
+        Self::new(
+            config.embedder.provider.clone(),
+            config.embedder.dimensions,
+            &config.chunking,
+        )
+        .with_model(config.embedder.model.clone())
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    pub fn with_template_version(mut self, version: impl Into<String>) -> Self {
+        self.template_version = Some(version.into());
+        self
+    }
+
+    pub fn with_code_version(mut self, version: impl Into<String>) -> Self {
+        self.code_version = Some(version.into());
+        self
+    }
+
+    pub(crate) fn set_chunking(&mut self, chunking: &ChunkingConfig) {
+        self.chunk_size = chunking.chunk_size as u64;
+        self.chunk_overlap = chunking.overlap as u64;
+        self.include_field_names = chunking.include_field_names;
+    }
+
+    /// Fields that differ from `current`, as `(field, recorded, current)`
+    ///
+    /// Only fields that change the vectors are compared; a different contrag
+    /// or application version alone does not require a migration.
+    pub fn mismatches(&self, current: &Provenance) -> Vec<ProvenanceMismatch> {
+        let mut mismatches = Vec::new();
+        let mut check = |field: &str, recorded: String, now: String| {
+            if recorded != now {
+                mismatches.push(ProvenanceMismatch {
+                    field: field.to_string(),
+                    recorded,
+                    current: now,
+                });
+            }
+        };
+
+        check("embedder", self.embedder.clone(), current.embedder.clone());
+        check("model", format!("{:?}", self.model), format!("{:?}", current.model));
+        check("dimensions", self.dimensions.to_string(), current.dimensions.to_string());
+        check("chunk_size", self.chunk_size.to_string(), current.chunk_size.to_string());
+        check("chunk_overlap", self.chunk_overlap.to_string(), current.chunk_overlap.to_string());
+        check(
+            "include_field_names",
+            self.include_field_names.to_string(),
+            current.include_field_names.to_string(),
+        );
+        check(
+            "template_version",
+            format!("{:?}", self.template_version),
+            format!("{:?}", current.template_version),
+        );
+
+        mismatches
+    }
+}
+
+/// A provenance field that differs from the running configuration
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CandidType)]
+pub struct ProvenanceMismatch {
+    pub field: String,
+    pub recorded: String,
+    pub current: String,
+}
+
+/// Provenance manifest entry of one namespace
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct NamespaceProvenance {
+    /// Configuration of the most recent ingestion
+    pub provenance: Provenance,
+    /// Vectors from an earlier, different configuration may remain
+    pub mixed: bool,
+    pub first_recorded_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Serialize, Deserialize, CandidType)]
+struct ManifestSnapshot {
+    namespaces: Vec<(String, NamespaceProvenance)>,
+}
+
+thread_local! {
+    static MANIFEST: RefCell<BTreeMap<String, NamespaceProvenance>> = RefCell::new(BTreeMap::new());
+}
+
+/// Record that `namespace` was (partly) written with `provenance`
+pub fn record_provenance(namespace: &str, provenance: &Provenance, now: u64) {
+    MANIFEST.with(|m| {
+        let mut manifest = m.borrow_mut();
+        match manifest.get_mut(namespace) {
+            Some(entry) => {
+                if !entry.provenance.mismatches(provenance).is_empty() {
+                    entry.mixed = true;
+                }
+                entry.provenance = provenance.clone();
+                entry.updated_at = now;
+            }
+            None => {
+                manifest.insert(namespace.to_string(), NamespaceProvenance {
+                    provenance: provenance.clone(),
+                    mixed: false,
+                    first_recorded_at: now,
+                    updated_at: now,
+                });
+            }
+        }
+    });
+}
+
+/// Provenance of a namespace, if anything was ingested into it
+pub fn get_provenance(namespace: &str) -> Option<NamespaceProvenance> {
+    MANIFEST.with(|m| m.borrow().get(namespace).cloned())
+}
+
+/// Forget a namespace's provenance, e.g. after deleting or rebuilding it
+pub fn clear_provenance(namespace: &str) {
+    MANIFEST.with(|m| m.borrow_mut().remove(namespace));
+}
+
+/// Namespaces whose recorded provenance differs from `current`
+pub fn outdated_namespaces(current: &Provenance) -> Vec<(String, Vec<ProvenanceMismatch>)> {
+    MANIFEST.with(|m| {
+        m.borrow()
+            .iter()
+            .filter_map(|(namespace, entry)| {
+                let mismatches = entry.provenance.mismatches(current);
+                (entry.mixed || !mismatches.is_empty()).then(|| (namespace.clone(), mismatches))
+            })
+            .collect()
+    })
+}
+
+/// Write the manifest to `memory` (the [`ContragMemory::Provenance`]
+/// region) in pre_upgrade; without it every namespace looks unrecorded
+/// after an upgrade and [`outdated_namespaces`] misses stale indexes
+///
+/// [`ContragMemory::Provenance`]: crate::storage::memory::ContragMemory::Provenance
+pub fn persist_provenance(memory: &impl Memory) -> Result<()> {
+    let snapshot = MANIFEST.with(|m| ManifestSnapshot {
+        namespaces: m.borrow().iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+    });
+    let payload = candid::encode_one(&snapshot)
+        .map_err(|e| ContragError::StorageError(format!("Failed to encode provenance manifest: {}", e)))?;
+    write_blob(memory, &encode_versioned(StorageComponent::Provenance, &payload))
+}
+
+/// Reload the manifest [`persist_provenance`] wrote to `memory`, in
+/// post_upgrade before anything is ingested; a canister that never persisted
+/// one starts with an empty manifest
+pub fn restore_provenance(memory: &impl Memory) -> Result<()> {
+    let Some(raw) = read_blob(memory)? else {
+        return Ok(());
+    };
+    let payload = Migrator::new().load(StorageComponent::Provenance, &raw)?;
+    let snapshot: ManifestSnapshot = candid::decode_one(&payload)
+        .map_err(|e| ContragError::StorageError(format!("Failed to decode provenance manifest: {}", e)))?;
+
+    MANIFEST.with(|m| m.borrow_mut().extend(snapshot.namespaces));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_detects_config_changes() {
+        let v1 = Provenance::new("openai", 1536, &ChunkingConfig::default())
+            .with_model("text-embedding-3-small")
+            .with_template_version("1");
+
+        record_provenance("User:1", &v1, 10);
+        assert!(outdated_namespaces(&v1).is_empty());
+
+        let v2 = v1.clone().with_template_version("2");
+        let outdated = outdated_namespaces(&v2);
+        assert_eq!(outdated.len(), 1);
+        assert_eq!(outdated[0].1[0].field, "template_version");
+
+        // A newer code version alone does not outdate the index
+        assert!(v1.mismatches(&v1.clone().with_code_version("abc123")).is_empty());
+
+        record_provenance("User:1", &v2, 20);
+        let entry = get_provenance("User:1").unwrap();
+        assert!(entry.mixed);
+        assert_eq!((entry.first_recorded_at, entry.updated_at), (10, 20));
+        assert_eq!(outdated_namespaces(&v2).len(), 1);
+
+        clear_provenance("User:1");
+        assert!(get_provenance("User:1").is_none());
+    }
+
+    #[test]
+    fn test_persist_and_restore_provenance() {
+        let memory = ic_stable_structures::DefaultMemoryImpl::default();
+        let provenance = Provenance::new("gemini", 768, &ChunkingConfig::default());
+        record_provenance("Order:7", &provenance, 30);
+        persist_provenance(&memory).unwrap();
+
+        MANIFEST.with(|m| m.borrow_mut().clear());
+        restore_provenance(&memory).unwrap();
+        let entry = get_provenance("Order:7").unwrap();
+        assert_eq!((entry.provenance, entry.first_recorded_at), (provenance, 30));
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::time::Duration;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::config::IngestionQueueConfig;
+use crate::utils::get_timestamp;
+
+/// Priority lane for an ingestion job
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, CandidType)]
+pub enum IngestionPriority {
+    /// "Index this entity now" requests coming from users
+    Interactive,
+    /// Regular incremental updates
+    Normal,
+    /// Full re-indexes and backfills
+    Bulk,
+}
+
+impl IngestionPriority {
+    fn lane(self) -> usize {
+        match self {
+            IngestionPriority::Interactive => 0,
+            IngestionPriority::Normal => 1,
+            IngestionPriority::Bulk => 2,
+        }
+    }
+}
+
+/// An entity waiting to be (re-)indexed
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct IngestionJob {
+    pub id: u64,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub namespace: String,
+    pub priority: IngestionPriority,
+    pub enqueued_at: u64,
+    pub attempts: u32,
+}
+
+/// Number of queued jobs per lane
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct QueueStats {
+    pub interactive: usize,
+    pub normal: usize,
+    pub bulk: usize,
+}
+
+/// Ingestion queue with weighted priority lanes
+///
+/// Jobs are dispatched with smooth weighted round-robin across the non-empty
+/// lanes, so interactive requests overtake a running bulk re-index without
+/// starving it. The queue is serializable so canisters can persist it across
+/// upgrades.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IngestionQueue {
+    lanes: [VecDeque<IngestionJob>; 3],
+    weights: [u32; 3],
+    current: [i64; 3],
+    next_id: u64,
+}
+
+impl IngestionQueue {
+    /// Create an empty queue using the lane weights from configuration
+    pub fn new(config: &IngestionQueueConfig) -> Self {
+        Self {
+            lanes: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
+            weights: [
+                config.interactive_weight.max(1),
+                config.normal_weight.max(1),
+                config.bulk_weight.max(1),
+            ],
+            current: [0; 3],
+            next_id: 0,
+        }
+    }
+
+    /// Enqueue an entity for ingestion and return the job ID
+    ///
+    /// If the entity is already queued for the same namespace, the existing
+    /// job is kept and moved to the higher of the two priorities.
+    pub fn enqueue(
+        &mut self,
+        entity_type: &str,
+        entity_id: &str,
+        namespace: &str,
+        priority: IngestionPriority,
+    ) -> u64 {
+        if let Some((lane, pos)) = self.find(entity_type, entity_id, namespace) {
+            if priority.lane() < lane {
+                let mut job = self.lanes[lane].remove(pos).expect("position was just found");
+                job.priority = priority;
+                let id = job.id;
+                self.lanes[priority.lane()].push_back(job);
+                return id;
+            }
+            return self.lanes[lane][pos].id;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.lanes[priority.lane()].push_back(IngestionJob {
+            id,
+            entity_type: entity_type.to_string(),
+            entity_id: entity_id.to_string(),
+            namespace: namespace.to_string(),
+            priority,
+            enqueued_at: get_timestamp(),
+            attempts: 0,
+        });
+
+        id
+    }
+
+    /// Pop the next job according to the lane weights
+    pub fn next(&mut self) -> Option<IngestionJob> {
+        let mut total = 0i64;
+        let mut best: Option<usize> = None;
+
+        for lane in 0..self.lanes.len() {
+            if self.lanes[lane].is_empty() {
+                self.current[lane] = 0;
+                continue;
+            }
+
+            let weight = self.weights[lane] as i64;
+            self.current[lane] += weight;
+            total += weight;
+
+            if best.map_or(true, |b| self.current[lane] > self.current[b]) {
+                best = Some(lane);
+            }
+        }
+
+        let lane = best?;
+        self.current[lane] -= total;
+        self.lanes[lane].pop_front()
+    }
+
+    /// Pop up to `max` jobs for one processing tick
+    pub fn next_batch(&mut self, max: usize) -> Vec<IngestionJob> {
+        let mut batch = Vec::with_capacity(max.min(self.len()));
+        while batch.len() < max {
+            match self.next() {
+                Some(job) => batch.push(job),
+                None => break,
+            }
+        }
+        batch
+    }
+
+    /// Put a failed job back at the end of its lane
+    pub fn requeue(&mut self, mut job: IngestionJob) {
+        job.attempts += 1;
+        self.lanes[job.priority.lane()].push_back(job);
+    }
+
+    /// Remove a queued job by ID
+    pub fn remove(&mut self, job_id: u64) -> Option<IngestionJob> {
+        for lane in self.lanes.iter_mut() {
+            if let Some(pos) = lane.iter().position(|j| j.id == job_id) {
+                return lane.remove(pos);
+            }
+        }
+        None
+    }
+
+    /// Total number of queued jobs
+    pub fn len(&self) -> usize {
+        self.lanes.iter().map(|l| l.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Queue depth per lane
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            interactive: self.lanes[0].len(),
+            normal: self.lanes[1].len(),
+            bulk: self.lanes[2].len(),
+        }
+    }
+
+    fn find(&self, entity_type: &str, entity_id: &str, namespace: &str) -> Option<(usize, usize)> {
+        self.lanes.iter().enumerate().find_map(|(lane, jobs)| {
+            jobs.iter()
+                .position(|j| {
+                    j.entity_type == entity_type
+                        && j.entity_id == entity_id
+                        && j.namespace == namespace
+                })
+                .map(|pos| (lane, pos))
+        })
+    }
+}
+
+impl Default for IngestionQueue {
+    fn default() -> Self {
+        Self::new(&IngestionQueueConfig::default())
+    }
+}
+
+/// Start a periodic timer that drives queue processing
+///
+/// `process` is spawned on every tick with the configured batch size and is
+/// expected to drain that many jobs from the canister's queue via
+/// [`IngestionQueue::next_batch`].
+pub fn start_queue_processor<F, Fut>(
+    config: &IngestionQueueConfig,
+    process: F,
+) -> ic_cdk_timers::TimerId
+where
+    F: Fn(usize) -> Fut + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    let batch_size = config.batch_size;
+    ic_cdk_timers::set_timer_interval(
+        Duration::from_secs(config.process_interval_secs),
+        move || ic_cdk::spawn(process(batch_size)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted_scheduling() {
+        let mut queue = IngestionQueue::default();
+        for i in 0..20 {
+            queue.enqueue("User", &format!("bulk_{}", i), "users", IngestionPriority::Bulk);
+        }
+        for i in 0..20 {
+            queue.enqueue("User", &format!("hot_{}", i), "users", IngestionPriority::Interactive);
+        }
+
+        let batch = queue.next_batch(9);
+        let bulk = batch
+            .iter()
+            .filter(|j| j.priority == IngestionPriority::Bulk)
+            .count();
+
+        // 8:1 weighting lets exactly one bulk job through per nine
+        assert_eq!(bulk, 1);
+        assert_eq!(queue.len(), 31);
+    }
+
+    #[test]
+    fn test_enqueue_promotes_existing_job() {
+        let mut queue = IngestionQueue::default();
+        let id = queue.enqueue("User", "1", "users", IngestionPriority::Bulk);
+        let again = queue.enqueue("User", "1", "users", IngestionPriority::Interactive);
+
+        assert_eq!(id, again);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.stats().interactive, 1);
+        assert_eq!(queue.next().unwrap().priority, IngestionPriority::Interactive);
+    }
+}
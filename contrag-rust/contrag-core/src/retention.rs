@@ -0,0 +1,275 @@
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::time::Duration;
+use candid::CandidType;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use crate::config::RetentionConfig;
+use crate::data_sources::DataSource;
+use crate::entity::RagEntity;
+use crate::error::Result;
+use crate::logs::{self, LogEvent, LogLevel};
+use crate::pipeline::ensemble::ensemble_namespace;
+use crate::vector_store::VectorStore;
+
+const NANOS_PER_DAY: u64 = 86_400 * 1_000_000_000;
+
+/// Entity type reported for vectors deleted because their TTL passed
+pub const TTL_EXPIRED: &str = "*";
+
+/// Vectors deleted for one entity type in one namespace
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct PurgedVectors {
+    pub namespace: String,
+    /// Entity type of the retention policy, or [`TTL_EXPIRED`]
+    pub entity_type: String,
+    pub vector_ids: Vec<String>,
+}
+
+/// Outcome of a retention purge run
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct PurgeReport {
+    pub timestamp: u64,
+    pub purged: Vec<PurgedVectors>,
+    pub total_deleted: usize,
+}
+
+/// Delete vectors past their TTL or their entity type's retention period
+///
+/// Every namespace is checked for vectors whose TTL (their own
+/// `ttl_seconds` or the store's default) has passed, then against every
+/// policy. Empty results are left out of the report; a non-empty purge is
+/// also recorded as a log event.
+pub async fn purge_expired<S: VectorStore + ?Sized>(
+    store: &mut S,
+    config: &RetentionConfig,
+    now: u64,
+) -> Result<PurgeReport> {
+    let mut purged = vec![];
+
+    for namespace in store.list_namespaces().await? {
+        let vector_ids = store.delete_ttl_expired(&namespace, now).await?;
+        if !vector_ids.is_empty() {
+            purged.push(PurgedVectors {
+                namespace: namespace.clone(),
+                entity_type: TTL_EXPIRED.to_string(),
+                vector_ids,
+            });
+        }
+
+        for policy in &config.policies {
+            let cutoff = now.saturating_sub(policy.max_age_days * NANOS_PER_DAY);
+            let vector_ids = store
+                .delete_expired(&namespace, &policy.entity_type, cutoff)
+                .await?;
+
+            if !vector_ids.is_empty() {
+                purged.push(PurgedVectors {
+                    namespace: namespace.clone(),
+                    entity_type: policy.entity_type.clone(),
+                    vector_ids,
+                });
+            }
+        }
+    }
+
+    let total_deleted = purged.iter().map(|p| p.vector_ids.len()).sum();
+
+    if total_deleted > 0 {
+        logs::record(
+            LogEvent::new(
+                LogLevel::Info,
+                "retention",
+                format!("Purged {} expired vectors", total_deleted),
+            )
+            .with_field("namespaces", purged.len()),
+        );
+    }
+
+    Ok(PurgeReport {
+        timestamp: now,
+        purged,
+        total_deleted,
+    })
+}
+
+/// Delete the chunks of `T` entities that no longer exist at `source`
+///
+/// Every distinct entity ID of type `T` in `namespace` is looked up once
+/// with [`DataSource::entity_exists`]; chunks of missing entities are
+/// deleted, along with their copies in the namespace's ensemble companion
+/// (see [`Ensemble`](crate::pipeline::ensemble::Ensemble)). A lookup error aborts the run before anything more is deleted,
+/// so an unreachable source cannot wipe a namespace.
+pub async fn gc_orphans<T, S, D>(store: &mut S, namespace: &str, source: &D) -> Result<PurgedVectors>
+where
+    T: RagEntity + DeserializeOwned + Send,
+    S: VectorStore + ?Sized,
+    D: DataSource,
+{
+    let entity_type = T::entity_type();
+    let entity_ids: BTreeSet<String> = store
+        .export_namespace(namespace)
+        .await?
+        .into_iter()
+        .filter(|v| v.metadata.entity_type == entity_type)
+        .map(|v| v.metadata.entity_id)
+        .collect();
+
+    let mut vector_ids = vec![];
+    for entity_id in entity_ids {
+        if !source.entity_exists::<T>(entity_type, &entity_id).await? {
+            vector_ids.extend(store.delete_by_entity(namespace, entity_type, &entity_id).await?);
+            store
+                .delete_by_entity(&ensemble_namespace(namespace), entity_type, &entity_id)
+                .await?;
+        }
+    }
+
+    if !vector_ids.is_empty() {
+        logs::record(
+            LogEvent::new(
+                LogLevel::Info,
+                "retention",
+                format!("Deleted {} orphaned {} vectors", vector_ids.len(), entity_type),
+            )
+            .with_field("namespace", namespace),
+        );
+    }
+
+    Ok(PurgedVectors {
+        namespace: namespace.to_string(),
+        entity_type: entity_type.to_string(),
+        vector_ids,
+    })
+}
+
+/// Start a periodic timer that enforces TTLs and retention policies
+///
+/// `purge` is spawned on every tick and should run [`purge_expired`] against
+/// the canister's vector store.
+pub fn start_retention_purge<F, Fut>(config: &RetentionConfig, purge: F) -> ic_cdk_timers::TimerId
+where
+    F: Fn() -> Fut + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    ic_cdk_timers::set_timer_interval(
+        Duration::from_secs(config.purge_interval_secs),
+        move || ic_cdk::spawn(purge()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RetentionPolicy;
+    use crate::entity::EntityRelationship;
+    use crate::error::ContragError;
+    use crate::types::{test_vector, Vector};
+    use crate::vector_store::stable_memory_store::StableMemoryVectorStore;
+
+    fn vector(id: &str, entity_type: &str, timestamp: u64) -> Vector {
+        let mut vector = test_vector(id, vec![1.0, 0.0]);
+        vector.metadata.entity_type = entity_type.to_string();
+        vector.metadata.timestamp = timestamp;
+        vector
+    }
+
+    #[tokio::test]
+    async fn test_purge_by_entity_type() {
+        let now = 100 * NANOS_PER_DAY;
+        let mut store = StableMemoryVectorStore::new();
+        store.store("ns", vector("old_session", "Session", now - 10 * NANOS_PER_DAY)).await.unwrap();
+        store.store("ns", vector("new_session", "Session", now - NANOS_PER_DAY)).await.unwrap();
+        store.store("ns", vector("old_user", "User", 0)).await.unwrap();
+
+        let config = RetentionConfig {
+            policies: vec![RetentionPolicy {
+                entity_type: "Session".to_string(),
+                max_age_days: 7,
+            }],
+            purge_interval_secs: 3600,
+        };
+
+        let report = purge_expired(&mut store, &config, now).await.unwrap();
+
+        assert_eq!(report.total_deleted, 1);
+        assert_eq!(report.purged[0].vector_ids, vec!["old_session"]);
+        assert_eq!(store.count("ns").await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_purge_by_ttl() {
+        let now = 100 * NANOS_PER_DAY;
+        let mut store = StableMemoryVectorStore::new().with_ttl(Some(86_400 * 7));
+        store.store("ns", vector("old", "Price", now - 10 * NANOS_PER_DAY)).await.unwrap();
+        store.store("ns", vector("recent", "Price", now - NANOS_PER_DAY)).await.unwrap();
+
+        let mut short_lived = vector("short_lived", "Price", now - NANOS_PER_DAY);
+        short_lived.metadata.ttl_seconds = Some(3600);
+        store.store("ns", short_lived).await.unwrap();
+
+        let report = purge_expired(&mut store, &RetentionConfig::default(), now).await.unwrap();
+
+        assert_eq!(report.total_deleted, 2);
+        assert_eq!(report.purged[0].entity_type, TTL_EXPIRED);
+        assert_eq!(report.purged[0].vector_ids, vec!["old", "short_lived"]);
+        assert_eq!(store.count("ns").await.unwrap(), 1);
+    }
+
+    #[derive(CandidType, Serialize, Deserialize, Clone)]
+    struct User;
+
+    impl RagEntity for User {
+        fn entity_type() -> &'static str {
+            "User"
+        }
+
+        fn entity_id(&self) -> String {
+            String::new()
+        }
+
+        fn to_context_map(&self) -> Vec<(String, String)> {
+            vec![]
+        }
+
+        fn relationships(&self) -> Vec<EntityRelationship> {
+            vec![]
+        }
+    }
+
+    /// Source knowing which entity IDs exist; "down" fails every lookup
+    struct KnownIds(Vec<&'static str>);
+
+    #[async_trait::async_trait]
+    impl DataSource for KnownIds {
+        async fn read_entity<T: RagEntity + CandidType>(&self, _: &str, entity_id: &str) -> Result<T> {
+            Err(ContragError::EntityNotFound(entity_id.to_string()))
+        }
+
+        async fn read_entities<T: RagEntity + CandidType + Send>(&self, _: &str, _: Vec<String>) -> Result<Vec<T>> {
+            Ok(vec![])
+        }
+
+        async fn entity_exists<T: RagEntity + CandidType + DeserializeOwned + Send>(&self, _: &str, entity_id: &str) -> Result<bool> {
+            if self.0.contains(&"down") {
+                return Err(ContragError::DataSourceError("unreachable".to_string()));
+            }
+            Ok(self.0.contains(&entity_id))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gc_orphans() {
+        let mut store = StableMemoryVectorStore::new();
+        store.store("ns", vector("alice", "User", 0)).await.unwrap();
+        store.store("ns", vector("bob", "User", 0)).await.unwrap();
+        store.store("ns", vector("order", "Order", 0)).await.unwrap();
+
+        assert!(gc_orphans::<User, _, _>(&mut store, "ns", &KnownIds(vec!["down"])).await.is_err());
+        assert_eq!(store.count("ns").await.unwrap(), 3);
+
+        let report = gc_orphans::<User, _, _>(&mut store, "ns", &KnownIds(vec!["alice"])).await.unwrap();
+        assert_eq!(report.vector_ids, vec!["bob"]);
+        assert_eq!(store.count("ns").await.unwrap(), 2);
+    }
+}
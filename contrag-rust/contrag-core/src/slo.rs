@@ -0,0 +1,270 @@
+//! Latency SLO tracking per operation
+//!
+//! Operations record their latency (including outcalls) with
+//! [`record_latency`] or [`timed`]. Checking the configured targets computes
+//! the percentile over a rolling window and raises a breach event when a
+//! target starts failing, so alerting fires once per breach rather than on
+//! every check.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::future::Future;
+use std::time::Duration;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::config::{SloConfig, SloTarget};
+use crate::embedders::http_client::HttpClient;
+use crate::error::{ContragError, Result};
+use crate::logs::{self, LogEvent, LogLevel};
+use crate::utils::get_timestamp;
+
+/// Maximum number of samples kept per operation
+const MAX_SAMPLES: usize = 10_000;
+
+/// Maximum number of breach events kept for inspection
+const MAX_BREACHES: usize = 100;
+
+/// Attainment of one target over the rolling window
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct SloStatus {
+    pub operation: String,
+    pub percentile: f64,
+    pub threshold_ms: u64,
+    /// Observed latency at the target percentile; `None` without samples
+    pub observed_ms: Option<u64>,
+    /// Fraction of requests within the threshold
+    pub attainment: f64,
+    pub samples: u64,
+    pub breached: bool,
+}
+
+/// A target that started failing
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct SloBreach {
+    pub timestamp: u64,
+    pub status: SloStatus,
+}
+
+#[derive(Default)]
+struct SloState {
+    // (timestamp, latency in ms) per operation, oldest first
+    samples: BTreeMap<String, VecDeque<(u64, u64)>>,
+    // Operations whose target is currently breached
+    breached: BTreeSet<String>,
+    breaches: VecDeque<SloBreach>,
+}
+
+thread_local! {
+    static STATE: RefCell<SloState> = RefCell::new(SloState::default());
+}
+
+/// Record one latency sample for an operation
+pub fn record_latency(operation: &str, latency_ms: u64) {
+    record_latency_at(operation, latency_ms, get_timestamp());
+}
+
+fn record_latency_at(operation: &str, latency_ms: u64, now: u64) {
+    STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        let samples = state.samples.entry(operation.to_string()).or_default();
+        if samples.len() >= MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back((now, latency_ms));
+    });
+}
+
+/// Await `future` and record its latency under `operation`
+///
+/// Failed operations are recorded too, since a slow failure still hurts.
+pub async fn timed<T>(operation: &str, future: impl Future<Output = T>) -> T {
+    let start = get_timestamp();
+    let output = future.await;
+    record_latency(operation, get_timestamp().saturating_sub(start) / 1_000_000);
+    output
+}
+
+/// Current attainment of every configured target
+pub fn status(config: &SloConfig) -> Vec<SloStatus> {
+    evaluate(config, get_timestamp())
+}
+
+fn evaluate(config: &SloConfig, now: u64) -> Vec<SloStatus> {
+    let cutoff = now.saturating_sub(config.window_secs.saturating_mul(1_000_000_000));
+
+    STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        for samples in state.samples.values_mut() {
+            while samples.front().is_some_and(|(at, _)| *at < cutoff) {
+                samples.pop_front();
+            }
+        }
+
+        config
+            .targets
+            .iter()
+            .map(|target| {
+                let latencies: Vec<u64> = state
+                    .samples
+                    .get(&target.operation)
+                    .map(|samples| samples.iter().map(|(_, ms)| *ms).collect())
+                    .unwrap_or_default();
+                target_status(target, latencies, config.min_samples)
+            })
+            .collect()
+    })
+}
+
+fn target_status(target: &SloTarget, mut latencies: Vec<u64>, min_samples: usize) -> SloStatus {
+    latencies.sort_unstable();
+    let observed_ms = percentile(&latencies, target.percentile);
+    let within = latencies.iter().filter(|ms| **ms <= target.threshold_ms).count();
+    let attainment = if latencies.is_empty() {
+        1.0
+    } else {
+        within as f64 / latencies.len() as f64
+    };
+
+    SloStatus {
+        operation: target.operation.clone(),
+        percentile: target.percentile,
+        threshold_ms: target.threshold_ms,
+        observed_ms,
+        attainment,
+        samples: latencies.len() as u64,
+        breached: latencies.len() >= min_samples.max(1)
+            && observed_ms.is_some_and(|ms| ms > target.threshold_ms),
+    }
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[u64], percentile: f64) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((percentile / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Evaluate the targets and alert on new breaches
+///
+/// A breach is recorded as an error log event and, if configured, posted to
+/// the webhook when a target starts failing; it is raised again only after
+/// the target recovered in between.
+pub async fn check(config: &SloConfig) -> Vec<SloBreach> {
+    let now = get_timestamp();
+    let statuses = evaluate(config, now);
+
+    let breaches: Vec<SloBreach> = STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        let mut breaches = vec![];
+
+        for status in statuses {
+            if !status.breached {
+                state.breached.remove(&status.operation);
+            } else if state.breached.insert(status.operation.clone()) {
+                breaches.push(SloBreach { timestamp: now, status });
+            }
+        }
+
+        for breach in &breaches {
+            if state.breaches.len() >= MAX_BREACHES {
+                state.breaches.pop_front();
+            }
+            state.breaches.push_back(breach.clone());
+        }
+        breaches
+    });
+
+    for breach in &breaches {
+        let status = &breach.status;
+        logs::record(
+            LogEvent::new(
+                LogLevel::Error,
+                "slo",
+                format!("Latency SLO breached for '{}'", status.operation),
+            )
+            .with_field("percentile", status.percentile)
+            .with_field("threshold_ms", status.threshold_ms)
+            .with_field("observed_ms", status.observed_ms.unwrap_or_default())
+            .with_field("attainment", status.attainment),
+        );
+    }
+
+    if let (Some(url), false) = (&config.webhook_url, breaches.is_empty()) {
+        if let Err(e) = post_webhook(url, &breaches).await {
+            ic_cdk::println!("contrag: failed to send SLO webhook: {}", e);
+        }
+    }
+
+    breaches
+}
+
+/// Breach events raised so far, oldest first
+pub fn recent_breaches() -> Vec<SloBreach> {
+    STATE.with(|s| s.borrow().breaches.iter().cloned().collect())
+}
+
+async fn post_webhook(url: &str, breaches: &[SloBreach]) -> Result<()> {
+    let body = serde_json::to_vec(&serde_json::json!({
+        "event": "slo_breach",
+        "breaches": breaches,
+    }))
+    .map_err(|e| ContragError::SerializationError(e.to_string()))?;
+
+    let headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+    HttpClient::new().post(url.to_string(), headers, body).await?;
+    Ok(())
+}
+
+/// Start a periodic timer that checks the SLO targets
+pub fn start_slo_monitor(config: SloConfig) -> ic_cdk_timers::TimerId {
+    let interval = Duration::from_secs(config.check_interval_secs);
+    ic_cdk_timers::set_timer_interval(interval, move || {
+        let config = config.clone();
+        ic_cdk::spawn(async move {
+            check(&config).await;
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEC: u64 = 1_000_000_000;
+
+    #[test]
+    fn test_percentile_over_rolling_window() {
+        let config = SloConfig {
+            targets: vec![SloTarget {
+                operation: "search".to_string(),
+                percentile: 95.0,
+                threshold_ms: 2000,
+            }],
+            window_secs: 60,
+            min_samples: 10,
+            ..SloConfig::default()
+        };
+
+        // Old slow samples fall out of the window
+        for _ in 0..20 {
+            record_latency_at("search", 5000, 0);
+        }
+        for ms in 1..=19 {
+            record_latency_at("search", ms * 100, 100 * SEC);
+        }
+        record_latency_at("search", 3000, 100 * SEC);
+
+        let status = &evaluate(&config, 120 * SEC)[0];
+        assert_eq!(status.samples, 20);
+        assert_eq!(status.observed_ms, Some(1900));
+        assert!(!status.breached);
+        assert_eq!(status.attainment, 0.95);
+
+        for _ in 0..5 {
+            record_latency_at("search", 4000, 110 * SEC);
+        }
+        assert!(evaluate(&config, 120 * SEC)[0].breached);
+    }
+}
@@ -0,0 +1,166 @@
+//! Sharing stable memory with the host canister
+//!
+//! Canisters split stable memory with a `MemoryManager`, giving each stable
+//! structure its own `MemoryId`. contrag never takes stable memory on its
+//! own: the host passes it one [`VirtualMemory`] per [`ContragMemory`]
+//! region, taken from the [`MEMORY_ID_COUNT`] IDs starting at
+//! [`StableMemoryConfig::first_memory_id`], and keeps its own structures on
+//! the other IDs.
+//!
+//! ```ignore
+//! thread_local! {
+//!     static MEMORY_MANAGER: MemoryManager<DefaultMemoryImpl> =
+//!         MemoryManager::init(DefaultMemoryImpl::default());
+//! }
+//!
+//! #[pre_upgrade]
+//! fn pre_upgrade() {
+//!     let vectors = MEMORY_MANAGER.with(|m| memory(m, &config.stable_memory, ContragMemory::Vectors));
+//!     VECTOR_STORE.with(|store| store.borrow().persist(&vectors)).expect("Failed to persist vectors");
+//! }
+//! ```
+
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::{Memory, WASM_PAGE_SIZE};
+use crate::config::StableMemoryConfig;
+use crate::error::{ContragError, Result};
+
+/// Number of consecutive `MemoryId`s contrag may use
+pub const MEMORY_ID_COUNT: u8 = 7;
+
+/// Length prefix of a blob written by [`write_blob`], a little-endian u64
+const LEN_BYTES: u64 = 8;
+
+/// A stable memory region owned by contrag
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContragMemory {
+    /// Snapshot of the [`StableMemoryVectorStore`](crate::vector_store::stable_memory_store::StableMemoryVectorStore)
+    Vectors,
+    /// Cold tier of the [`HybridVectorStore`](crate::vector_store::hybrid::HybridVectorStore)
+    ColdVectors,
+    /// Weights of the [`OnChainEmbedder`](crate::embedders::onchain::OnChainEmbedder) model
+    ModelWeights,
+    /// Entries of the [persistent embedding cache](crate::embedders::cache)
+    EmbeddingCache,
+    /// Encrypted [`KeyStore`](crate::keys::KeyStore)
+    KeyStore,
+    /// Per-minute [usage ledger](crate::embedders::usage_ledger) of rate limits
+    UsageLedger,
+    /// Per-namespace [provenance manifest](crate::provenance)
+    Provenance,
+}
+
+impl ContragMemory {
+    fn offset(self) -> u8 {
+        match self {
+            ContragMemory::Vectors => 0,
+            ContragMemory::ColdVectors => 1,
+            ContragMemory::ModelWeights => 2,
+            ContragMemory::EmbeddingCache => 3,
+            ContragMemory::KeyStore => 4,
+            ContragMemory::UsageLedger => 5,
+            ContragMemory::Provenance => 6,
+        }
+    }
+}
+
+/// `MemoryId` of `region` under `config`
+pub fn memory_id(config: &StableMemoryConfig, region: ContragMemory) -> MemoryId {
+    MemoryId::new(config.first_memory_id + region.offset())
+}
+
+/// Virtual memory of `region` from the host's memory manager
+pub fn memory<M: Memory>(
+    manager: &MemoryManager<M>,
+    config: &StableMemoryConfig,
+    region: ContragMemory,
+) -> VirtualMemory<M> {
+    manager.get(memory_id(config, region))
+}
+
+/// Write `bytes` to the start of `memory`, growing it as needed
+pub(crate) fn write_blob(memory: &(impl Memory + ?Sized), bytes: &[u8]) -> Result<()> {
+    write_blob_chunk(memory, 0, bytes)
+}
+
+/// Write `chunk` at `offset` of the blob in `memory`, growing it as needed
+///
+/// The blob then ends with this chunk, so a blob too large for one message
+/// is written chunk by chunk in order; starting over at offset 0 replaces it.
+pub(crate) fn write_blob_chunk(memory: &(impl Memory + ?Sized), offset: u64, chunk: &[u8]) -> Result<()> {
+    let len = offset + chunk.len() as u64;
+    let pages = (LEN_BYTES + len).div_ceil(WASM_PAGE_SIZE);
+    let size = memory.size();
+    if size < pages && memory.grow(pages - size) < 0 {
+        return Err(ContragError::StorageError(format!(
+            "Could not grow stable memory to {} pages",
+            pages
+        )));
+    }
+
+    memory.write(LEN_BYTES + offset, chunk);
+    memory.write(0, &len.to_le_bytes());
+    Ok(())
+}
+
+/// Overwrite bytes at `offset` of the blob in `memory`, keeping its length
+pub(crate) fn write_blob_at(memory: &(impl Memory + ?Sized), offset: u64, bytes: &[u8]) -> Result<()> {
+    let len = blob_len(memory)?;
+    if offset + bytes.len() as u64 > len {
+        return Err(ContragError::StorageError(format!(
+            "Write of {} bytes at offset {} overruns the {} byte blob",
+            bytes.len(),
+            offset,
+            len
+        )));
+    }
+
+    memory.write(LEN_BYTES + offset, bytes);
+    Ok(())
+}
+
+/// Length of the blob in `memory`, read from its prefix alone; 0 if empty
+pub(crate) fn blob_len(memory: &(impl Memory + ?Sized)) -> Result<u64> {
+    if memory.size() == 0 {
+        return Ok(0);
+    }
+
+    let mut len = [0u8; LEN_BYTES as usize];
+    memory.read(0, &mut len);
+    let len = u64::from_le_bytes(len);
+    if LEN_BYTES + len > memory.size() * WASM_PAGE_SIZE {
+        return Err(ContragError::StorageError(format!(
+            "Stable memory blob of {} bytes overruns its memory",
+            len
+        )));
+    }
+    Ok(len)
+}
+
+/// Fill `buffer` from `offset` of the blob in `memory`
+pub(crate) fn read_blob_at(memory: &(impl Memory + ?Sized), offset: u64, buffer: &mut [u8]) -> Result<()> {
+    let len = blob_len(memory)?;
+    if offset + buffer.len() as u64 > len {
+        return Err(ContragError::StorageError(format!(
+            "Read of {} bytes at offset {} overruns the {} byte blob",
+            buffer.len(),
+            offset,
+            len
+        )));
+    }
+
+    memory.read(LEN_BYTES + offset, buffer);
+    Ok(())
+}
+
+/// Read the blob [`write_blob`] left in `memory`; `None` if it is empty
+pub(crate) fn read_blob(memory: &(impl Memory + ?Sized)) -> Result<Option<Vec<u8>>> {
+    let len = blob_len(memory)?;
+    if len == 0 {
+        return Ok(None);
+    }
+
+    let mut bytes = vec![0; len as usize];
+    memory.read(LEN_BYTES, &mut bytes);
+    Ok(Some(bytes))
+}
@@ -0,0 +1,273 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::error::{ContragError, Result};
+
+/// Magic bytes prefixed to every versioned stable-memory blob
+const MAGIC: &[u8; 4] = b"CRAG";
+
+/// Header length: magic + component tag + little-endian u32 version
+const HEADER_LEN: usize = 9;
+
+/// A stable-memory layout owned by contrag
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, CandidType)]
+pub enum StorageComponent {
+    Vectors,
+    Config,
+    Queues,
+    Logs,
+    EmbeddingCache,
+    KeyStore,
+    UsageLedger,
+    HnswIndex,
+    IvfIndex,
+    Provenance,
+}
+
+impl StorageComponent {
+    /// Layout version written by this build of contrag
+    ///
+    /// Bump this whenever the serialized layout of a component changes and
+    /// register a migration from the previous version.
+    pub fn current_version(self) -> u32 {
+        match self {
+            StorageComponent::Vectors => 3,
+            StorageComponent::Config => 1,
+            StorageComponent::Queues => 1,
+            StorageComponent::Logs => 1,
+            StorageComponent::EmbeddingCache => 1,
+            StorageComponent::KeyStore => 1,
+            StorageComponent::UsageLedger => 1,
+            StorageComponent::HnswIndex => 1,
+            StorageComponent::IvfIndex => 1,
+            StorageComponent::Provenance => 1,
+        }
+    }
+
+    fn tag(self) -> u8 {
+        match self {
+            StorageComponent::Vectors => 1,
+            StorageComponent::Config => 2,
+            StorageComponent::Queues => 3,
+            StorageComponent::Logs => 4,
+            StorageComponent::EmbeddingCache => 5,
+            StorageComponent::KeyStore => 6,
+            StorageComponent::UsageLedger => 7,
+            StorageComponent::HnswIndex => 8,
+            StorageComponent::IvfIndex => 9,
+            StorageComponent::Provenance => 10,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            1 => Some(StorageComponent::Vectors),
+            2 => Some(StorageComponent::Config),
+            3 => Some(StorageComponent::Queues),
+            4 => Some(StorageComponent::Logs),
+            5 => Some(StorageComponent::EmbeddingCache),
+            6 => Some(StorageComponent::KeyStore),
+            7 => Some(StorageComponent::UsageLedger),
+            8 => Some(StorageComponent::HnswIndex),
+            9 => Some(StorageComponent::IvfIndex),
+            10 => Some(StorageComponent::Provenance),
+            _ => None,
+        }
+    }
+}
+
+/// A single step that upgrades one component's layout by one version
+pub trait Migration {
+    /// Component this migration applies to
+    fn component(&self) -> StorageComponent;
+
+    /// Version this migration upgrades from (it produces `from_version() + 1`)
+    fn from_version(&self) -> u32;
+
+    /// Human-readable description for logs
+    fn description(&self) -> &str;
+
+    /// Transform the serialized payload to the next layout version
+    fn migrate(&self, payload: Vec<u8>) -> Result<Vec<u8>>;
+}
+
+/// Record of an applied migration step
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct AppliedMigration {
+    pub component: StorageComponent,
+    pub from_version: u32,
+    pub to_version: u32,
+    pub description: String,
+}
+
+/// Runs ordered migrations over versioned stable-memory blobs
+///
+/// Call [`Migrator::load`] for every component in `post_upgrade`. If it
+/// returns an error the canister should trap, which rolls the upgrade back
+/// instead of starting on data it does not understand.
+#[derive(Default)]
+pub struct Migrator {
+    migrations: Vec<Box<dyn Migration>>,
+    applied: Vec<AppliedMigration>,
+}
+
+impl Migrator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a migration step
+    pub fn register(mut self, migration: impl Migration + 'static) -> Self {
+        self.migrations.push(Box::new(migration));
+        self
+    }
+
+    /// Decode a stored blob and migrate its payload to the current version
+    ///
+    /// Blobs without a header are treated as version 0, so a migration from
+    /// version 0 can adopt data written before versioning existed.
+    pub fn load(&mut self, component: StorageComponent, raw: &[u8]) -> Result<Vec<u8>> {
+        let (version, payload) = match decode_header(raw)? {
+            Some((stored_component, version)) => {
+                if stored_component != component {
+                    return Err(ContragError::StorageError(format!(
+                        "Expected {:?} data in stable memory, found {:?}",
+                        component, stored_component
+                    )));
+                }
+                (version, raw[HEADER_LEN..].to_vec())
+            }
+            None => (0, raw.to_vec()),
+        };
+
+        self.migrate(component, version, payload)
+    }
+
+    /// Migrate a payload from `stored_version` to the current version
+    pub fn migrate(
+        &mut self,
+        component: StorageComponent,
+        stored_version: u32,
+        mut payload: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let target = component.current_version();
+
+        if stored_version > target {
+            return Err(ContragError::StorageError(format!(
+                "{:?} layout version {} is newer than supported version {}; refusing to start",
+                component, stored_version, target
+            )));
+        }
+
+        for version in stored_version..target {
+            let migration = self
+                .migrations
+                .iter()
+                .find(|m| m.component() == component && m.from_version() == version)
+                .ok_or_else(|| {
+                    ContragError::StorageError(format!(
+                        "No migration registered for {:?} from version {} to {}",
+                        component,
+                        version,
+                        version + 1
+                    ))
+                })?;
+
+            payload = migration.migrate(payload)?;
+
+            self.applied.push(AppliedMigration {
+                component,
+                from_version: version,
+                to_version: version + 1,
+                description: migration.description().to_string(),
+            });
+        }
+
+        Ok(payload)
+    }
+
+    /// Migrations applied so far by this migrator
+    pub fn applied(&self) -> &[AppliedMigration] {
+        &self.applied
+    }
+}
+
+/// Prefix a payload with the header for the component's current version
+///
+/// Use this in `pre_upgrade` before writing a component to stable memory.
+pub fn encode_versioned(component: StorageComponent, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+    out.extend_from_slice(MAGIC);
+    out.push(component.tag());
+    out.extend_from_slice(&component.current_version().to_le_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Read the component and version from a blob header, if present
+fn decode_header(raw: &[u8]) -> Result<Option<(StorageComponent, u32)>> {
+    if raw.len() < HEADER_LEN || &raw[..4] != MAGIC {
+        return Ok(None);
+    }
+
+    let component = StorageComponent::from_tag(raw[4]).ok_or_else(|| {
+        ContragError::StorageError(format!("Unknown storage component tag: {}", raw[4]))
+    })?;
+
+    let mut version = [0u8; 4];
+    version.copy_from_slice(&raw[5..HEADER_LEN]);
+
+    Ok(Some((component, u32::from_le_bytes(version))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct AdoptLegacyLogs;
+
+    impl Migration for AdoptLegacyLogs {
+        fn component(&self) -> StorageComponent {
+            StorageComponent::Logs
+        }
+
+        fn from_version(&self) -> u32 {
+            0
+        }
+
+        fn description(&self) -> &str {
+            "adopt unversioned log data"
+        }
+
+        fn migrate(&self, payload: Vec<u8>) -> Result<Vec<u8>> {
+            Ok(payload)
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_current_version() {
+        let raw = encode_versioned(StorageComponent::Queues, b"queue");
+        let mut migrator = Migrator::new();
+
+        assert_eq!(migrator.load(StorageComponent::Queues, &raw).unwrap(), b"queue");
+        assert!(migrator.applied().is_empty());
+    }
+
+    #[test]
+    fn test_legacy_data_runs_migrations() {
+        let mut migrator = Migrator::new().register(AdoptLegacyLogs);
+
+        assert_eq!(migrator.load(StorageComponent::Logs, b"legacy").unwrap(), b"legacy");
+        assert_eq!(migrator.applied().len(), 1);
+
+        // Without a registered migration the load is refused
+        assert!(Migrator::new().load(StorageComponent::Config, b"legacy").is_err());
+    }
+
+    #[test]
+    fn test_refuses_newer_versions() {
+        let mut raw = encode_versioned(StorageComponent::Logs, b"");
+        raw[5..9].copy_from_slice(&99u32.to_le_bytes());
+
+        assert!(Migrator::new().load(StorageComponent::Logs, &raw).is_err());
+    }
+}
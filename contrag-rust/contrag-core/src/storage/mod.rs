@@ -0,0 +1,4 @@
+pub mod memory;
+pub mod migrations;
+
+pub use migrations::{Migration, Migrator, StorageComponent};
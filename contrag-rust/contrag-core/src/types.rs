@@ -19,6 +19,134 @@ pub struct VectorMetadata {
     pub total_chunks: usize,
     pub timestamp: u64,
     pub custom: Option<String>, // JSON string for custom metadata
+    /// Seconds after `timestamp` until the vector expires; `None` falls
+    /// back to the store's default TTL
+    #[serde(default)]
+    pub ttl_seconds: Option<u64>,
+}
+
+impl VectorMetadata {
+    /// Custom metadata as a JSON object; empty if unset or not an object
+    pub fn custom_fields(&self) -> serde_json::Map<String, serde_json::Value> {
+        self.custom
+            .as_deref()
+            .and_then(|custom| serde_json::from_str(custom).ok())
+            .unwrap_or_default()
+    }
+
+    /// Expiry timestamp (ns), from the vector's own TTL or `default_ttl`
+    pub fn expires_at(&self, default_ttl: Option<u64>) -> Option<u64> {
+        self.ttl_seconds
+            .or(default_ttl)
+            .map(|ttl| expiry_timestamp(self.timestamp, ttl))
+    }
+
+    pub fn is_expired(&self, now: u64, default_ttl: Option<u64>) -> bool {
+        self.expires_at(default_ttl).is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// A single field of the custom metadata
+    pub fn custom_field(&self, key: &str) -> Option<serde_json::Value> {
+        self.custom_fields().remove(key)
+    }
+}
+
+/// Single-chunk `Doc` vector for tests, with `id` as its text and entity ID
+///
+/// Tests override what they need with struct update syntax or by setting
+/// metadata fields, so new metadata fields only need a default here.
+#[cfg(test)]
+pub(crate) fn test_vector(id: &str, embedding: Vec<f32>) -> Vector {
+    Vector {
+        id: id.to_string(),
+        embedding,
+        text: id.to_string(),
+        metadata: VectorMetadata {
+            entity_type: "Doc".to_string(),
+            entity_id: id.to_string(),
+            chunk_index: 0,
+            total_chunks: 1,
+            timestamp: 0,
+            custom: None,
+            ttl_seconds: None,
+        },
+    }
+}
+
+/// Timestamp (ns) at which a vector stored at `timestamp` with a TTL of
+/// `ttl_seconds` expires
+pub fn expiry_timestamp(timestamp: u64, ttl_seconds: u64) -> u64 {
+    timestamp.saturating_add(ttl_seconds.saturating_mul(1_000_000_000))
+}
+
+/// Conditions on vector metadata; a vector matches when all set ones hold
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct SearchFilter {
+    pub entity_type: Option<String>,
+    pub entity_id: Option<String>,
+    /// Only vectors indexed before this timestamp (ns)
+    pub before: Option<u64>,
+    /// Only vectors indexed at or after this timestamp (ns)
+    pub since: Option<u64>,
+    /// Custom metadata fields that must equal the given values; strings
+    /// compare as is, other JSON values by their JSON text
+    pub custom: Vec<(String, String)>,
+}
+
+impl SearchFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Filter matching every chunk of one entity
+    pub fn entity(entity_type: impl Into<String>, entity_id: impl Into<String>) -> Self {
+        Self::new().with_entity_type(entity_type).with_entity_id(entity_id)
+    }
+
+    pub fn with_entity_type(mut self, entity_type: impl Into<String>) -> Self {
+        self.entity_type = Some(entity_type.into());
+        self
+    }
+
+    pub fn with_entity_id(mut self, entity_id: impl Into<String>) -> Self {
+        self.entity_id = Some(entity_id.into());
+        self
+    }
+
+    pub fn with_before(mut self, before: u64) -> Self {
+        self.before = Some(before);
+        self
+    }
+
+    pub fn with_since(mut self, since: u64) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn with_custom(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.custom.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn matches(&self, metadata: &VectorMetadata) -> bool {
+        if self.entity_type.as_ref().is_some_and(|t| *t != metadata.entity_type)
+            || self.entity_id.as_ref().is_some_and(|id| *id != metadata.entity_id)
+            || self.before.is_some_and(|before| metadata.timestamp >= before)
+            || self.since.is_some_and(|since| metadata.timestamp < since)
+        {
+            return false;
+        }
+        if self.custom.is_empty() {
+            return true;
+        }
+
+        let fields = metadata.custom_fields();
+        self.custom.iter().all(|(key, expected)| match fields.get(key) {
+            Some(serde_json::Value::String(value)) => value == expected,
+            Some(value) => value.to_string() == *expected,
+            None => false,
+        })
+    }
 }
 
 /// Search result from vector store
@@ -26,10 +154,261 @@ pub struct VectorMetadata {
 pub struct SearchResult {
     pub vector_id: String,
     pub text: String,
+    /// Similarity to the query; higher is always more similar
+    ///
+    /// The range depends on the store's
+    /// [`DistanceMetric`](crate::config::DistanceMetric): -1..=1 for cosine,
+    /// unbounded for dot product (equal to cosine on unit-length
+    /// embeddings) and `1 / (1 + distance)` in 0..=1 for euclidean. Stores
+    /// with normalized scores report
+    /// [`normalized_similarity`](crate::vector_store::normalized_similarity)
+    /// instead, in 0..=1 and comparable across metrics. Custom
+    /// [`Scorer`](crate::vector_store::scoring::Scorer)s may rescale it, so
+    /// compare scores only within one store and metric.
     pub score: f32,
     pub metadata: VectorMetadata,
 }
 
+/// Largest page size accepted when listing namespaces
+pub const MAX_NAMESPACE_PAGE_SIZE: usize = 1000;
+
+/// Paginated namespace listing request
+///
+/// Namespaces are listed in lexicographic order; pass the `next_cursor` of
+/// the previous page as `start_after` to continue.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct NamespaceListRequest {
+    /// Only list namespaces starting with this prefix
+    pub prefix: Option<String>,
+    /// Only list namespaces sorting after this one
+    pub start_after: Option<String>,
+    /// Page size, clamped to `1..=MAX_NAMESPACE_PAGE_SIZE`
+    pub limit: usize,
+}
+
+/// Namespace with its size
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct NamespaceInfo {
+    pub name: String,
+    pub vector_count: usize,
+    /// Approximate bytes used by embeddings and texts (0 if unknown)
+    pub size_bytes: u64,
+}
+
+/// One page of a namespace listing
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct NamespacePage {
+    pub namespaces: Vec<NamespaceInfo>,
+    /// Cursor for the next page; `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// How `store_batch_with` handles vectors that cannot be stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+pub enum BatchMode {
+    /// Store nothing if any vector fails; the call returns an error
+    AllOrNothing,
+    /// Store every vector that can be stored and report the others
+    BestEffort,
+}
+
+/// How `store_batch_dedup` handles vectors whose text is already stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupMode {
+    /// Keep the stored vector and drop the new one
+    Skip,
+    /// Overwrite the stored vector with the new one, keeping its ID
+    Update,
+}
+
+/// How `import_vectors` handles vectors whose ID is already stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, CandidType)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateIdMode {
+    /// Keep the stored vector and drop the imported one
+    Skip,
+    /// Replace the stored vector with the imported one
+    Replace,
+}
+
+/// Outcome of one vector in a batch write
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct VectorWriteResult {
+    pub vector_id: String,
+    /// Why the vector was not stored; `None` if it was stored or skipped
+    pub error: Option<String>,
+    /// Stored vector with the same text, skipped or overwritten in its place
+    #[serde(default)]
+    pub duplicate_of: Option<String>,
+}
+
+/// Per-vector outcome of a batch write
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct BatchWriteReport {
+    pub stored: usize,
+    pub failed: usize,
+    /// Vectors left out as duplicates of stored ones
+    #[serde(default)]
+    pub skipped: usize,
+    /// One entry per input vector, in input order
+    pub results: Vec<VectorWriteResult>,
+}
+
+impl BatchWriteReport {
+    pub fn push(&mut self, vector_id: String, error: Option<String>) {
+        if error.is_some() {
+            self.failed += 1;
+        } else {
+            self.stored += 1;
+        }
+        self.results.push(VectorWriteResult {
+            vector_id,
+            error,
+            duplicate_of: None,
+        });
+    }
+
+    /// Record a vector left out as a duplicate of `existing_id`
+    pub fn skip(&mut self, vector_id: String, existing_id: String) {
+        self.skipped += 1;
+        self.results.push(VectorWriteResult {
+            vector_id,
+            error: None,
+            duplicate_of: Some(existing_id),
+        });
+    }
+
+    /// Append the outcomes of a later batch
+    pub fn merge(&mut self, other: BatchWriteReport) {
+        self.stored += other.stored;
+        self.failed += other.failed;
+        self.skipped += other.skipped;
+        self.results.extend(other.results);
+    }
+
+    /// Mark a vector reported as stored as failed after all
+    pub fn reject(&mut self, vector_id: &str, error: String) {
+        if let Some(result) = self
+            .results
+            .iter_mut()
+            .find(|r| r.vector_id == vector_id && r.error.is_none())
+        {
+            result.error = Some(error);
+            self.stored -= 1;
+            self.failed += 1;
+        }
+    }
+}
+
+/// Progress of a prefix-scoped bulk operation
+///
+/// Each step handles a bounded number of namespaces, so long operations can
+/// be spread over several timer ticks by passing the returned cursor back in.
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct BulkCursor {
+    pub prefix: String,
+    /// Last namespace handled so far
+    pub start_after: Option<String>,
+    pub namespaces_done: u64,
+    pub vectors_done: u64,
+    pub finished: bool,
+}
+
+impl BulkCursor {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            start_after: None,
+            namespaces_done: 0,
+            vectors_done: 0,
+            finished: false,
+        }
+    }
+}
+
+/// Aggregated size of all namespaces under a prefix
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct PrefixStats {
+    pub prefix: String,
+    pub namespaces: usize,
+    pub vectors: usize,
+    pub size_bytes: u64,
+}
+
+/// One version of a vector in a versioned store
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct VectorVersion {
+    /// Store-wide write number; later writes have higher versions
+    pub version: u64,
+    /// Version of the write or delete that replaced this one; `None` while
+    /// it is the live version
+    pub retired_at: Option<u64>,
+    /// Whether this version was retired by a delete
+    pub deleted: bool,
+    pub vector: Vector,
+}
+
+/// Size and age of one namespace's vectors
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, CandidType)]
+pub struct NamespaceStats {
+    pub namespace: String,
+    pub vector_count: usize,
+    /// Bytes of the embeddings as stored
+    pub embedding_bytes: u64,
+    /// Bytes of the chunk text as stored
+    pub text_bytes: u64,
+    /// Embedding dimensions; `None` while the namespace is empty
+    pub dimensions: Option<usize>,
+    /// Earliest and latest `timestamp` of the namespace's vectors
+    pub oldest_timestamp: Option<u64>,
+    pub newest_timestamp: Option<u64>,
+}
+
+impl NamespaceStats {
+    pub fn new(namespace: &str) -> Self {
+        Self {
+            namespace: namespace.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Count one vector with `dimensions`, its sizes and `timestamp`
+    pub fn add(&mut self, dimensions: usize, embedding_bytes: usize, text_bytes: usize, timestamp: u64) {
+        self.vector_count += 1;
+        self.embedding_bytes += embedding_bytes as u64;
+        self.text_bytes += text_bytes as u64;
+        self.dimensions.get_or_insert(dimensions);
+        self.oldest_timestamp = Some(self.oldest_timestamp.map_or(timestamp, |t| t.min(timestamp)));
+        self.newest_timestamp = Some(self.newest_timestamp.map_or(timestamp, |t| t.max(timestamp)));
+    }
+}
+
+/// Totals of [`NamespaceStats`] over every namespace of a store
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, CandidType)]
+pub struct StoreStats {
+    pub namespaces: usize,
+    pub vector_count: usize,
+    pub embedding_bytes: u64,
+    pub text_bytes: u64,
+    pub oldest_timestamp: Option<u64>,
+    pub newest_timestamp: Option<u64>,
+}
+
+impl StoreStats {
+    pub fn add(&mut self, stats: &NamespaceStats) {
+        self.namespaces += 1;
+        self.vector_count += stats.vector_count;
+        self.embedding_bytes += stats.embedding_bytes;
+        self.text_bytes += stats.text_bytes;
+        self.oldest_timestamp = match (self.oldest_timestamp, stats.oldest_timestamp) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.newest_timestamp = self.newest_timestamp.max(stats.newest_timestamp);
+    }
+}
+
 /// Text chunk with overlap
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TextChunk {
@@ -5,6 +5,25 @@ pub fn generate_vector_id(entity_type: &str, entity_id: &str, chunk_index: usize
     format!("{}::{}::chunk_{}", entity_type, entity_id, chunk_index)
 }
 
+/// FNV-1a, stable across Rust versions so persisted hashes and shard
+/// routing never change under existing data
+pub fn stable_hash(value: &str) -> u64 {
+    let hash = value.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+
+    // FNV barely moves the high bits for keys differing in their last byte;
+    // finish with the murmur3 mix so neighbouring names spread evenly
+    let hash = (hash ^ (hash >> 33)).wrapping_mul(0xff51_afd7_ed55_8ccd);
+    let hash = (hash ^ (hash >> 33)).wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+/// Hex hash of chunk text, which deduplicating batch stores compare
+pub fn content_hash(text: &str) -> String {
+    format!("{:016x}", stable_hash(text))
+}
+
 /// Get current timestamp in nanoseconds (ICP time)
 pub fn get_timestamp() -> u64 {
     #[cfg(target_family = "wasm")]
@@ -22,6 +41,53 @@ pub fn get_timestamp() -> u64 {
     }
 }
 
+/// Format an ICP timestamp (nanoseconds) as a UTC date, e.g. "2025-10-16"
+pub fn format_date(timestamp_ns: u64) -> String {
+    let (year, month, day) = civil_from_days((timestamp_ns / NANOS_PER_DAY) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Format an ICP timestamp (nanoseconds) as a readable UTC date and time,
+/// e.g. "Thursday 2025-10-16 14:02:11 UTC"
+pub fn format_timestamp(timestamp_ns: u64) -> String {
+    const WEEKDAYS: [&str; 7] = [
+        "Thursday", "Friday", "Saturday", "Sunday", "Monday", "Tuesday", "Wednesday",
+    ];
+
+    let days = timestamp_ns / NANOS_PER_DAY;
+    let secs_of_day = (timestamp_ns % NANOS_PER_DAY) / 1_000_000_000;
+
+    format!(
+        "{} {} {:02}:{:02}:{:02} UTC",
+        WEEKDAYS[(days % 7) as usize],
+        format_date(timestamp_ns),
+        secs_of_day / 3600,
+        (secs_of_day % 3600) / 60,
+        secs_of_day % 60
+    )
+}
+
+const NANOS_PER_DAY: u64 = 86_400 * 1_000_000_000;
+
+/// Convert days since the Unix epoch to a (year, month, day) civil date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Rough token count of a text (about four characters per token)
+pub fn estimate_tokens(text: &str) -> usize {
+    (text.chars().count() + 3) / 4
+}
+
 /// Sanitize text for embedding (remove excessive whitespace, etc.)
 pub fn sanitize_text(text: &str) -> String {
     text.split_whitespace()
@@ -34,7 +100,12 @@ pub fn truncate_text(text: &str, max_len: usize) -> String {
     if text.len() <= max_len {
         text.to_string()
     } else {
-        format!("{}...", &text[..max_len])
+        // Back off to a char boundary so multi-byte text never panics
+        let mut end = max_len;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}...", &text[..end])
     }
 }
 
@@ -74,6 +145,19 @@ mod tests {
         let text = "Hello world";
         assert_eq!(truncate_text(text, 5), "Hello...");
         assert_eq!(truncate_text(text, 100), "Hello world");
+
+        // Cutting inside a multi-byte char backs off to its start
+        assert_eq!(truncate_text("héllo", 2), "h...");
+        assert_eq!(truncate_text("日本語", 4), "日...");
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        // 2025-10-16 14:02:11 UTC
+        let ts = 1_760_623_331u64 * 1_000_000_000;
+        assert_eq!(format_date(ts), "2025-10-16");
+        assert_eq!(format_timestamp(ts), "Thursday 2025-10-16 14:02:11 UTC");
+        assert_eq!(format_date(0), "1970-01-01");
     }
 
     #[test]
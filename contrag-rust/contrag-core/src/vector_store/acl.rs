@@ -0,0 +1,113 @@
+//! Per-namespace access control by principal
+//!
+//! An owner set on a namespace also covers the namespaces below it, so
+//! owning `tenant:<principal>` covers `tenant:<principal>:User:42`; the
+//! closest owner wins. Tenant namespaces without an explicit owner belong
+//! to their tenant principal, and any other namespace without one is open.
+//! Admins, typically the canister's controllers, pass every check.
+//!
+//! The ACL only answers questions; endpoints call [`NamespaceAcl::check_access`]
+//! with `ic_cdk::api::caller()` before touching a namespace.
+
+use std::collections::{BTreeMap, BTreeSet};
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use crate::error::{ContragError, Result};
+use crate::namespace::{Namespace, SEPARATOR};
+
+/// Owners of namespaces, kept next to the store's namespace list
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, CandidType)]
+pub struct NamespaceAcl {
+    owners: BTreeMap<String, Principal>,
+    admins: BTreeSet<Principal>,
+}
+
+impl NamespaceAcl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let `admin` access every namespace
+    pub fn with_admin(mut self, admin: Principal) -> Self {
+        self.admins.insert(admin);
+        self
+    }
+
+    /// Restrict `namespace` and the namespaces below it to `owner`
+    ///
+    /// Ownership outlives the namespace's vectors: deleting them does not
+    /// open the namespace to everyone.
+    pub fn set_namespace_owner(&mut self, namespace: &str, owner: Principal) {
+        self.owners.insert(namespace.to_string(), owner);
+    }
+
+    /// Drop the explicit owner of `namespace`, returning it
+    pub fn clear_namespace_owner(&mut self, namespace: &str) -> Option<Principal> {
+        self.owners.remove(namespace)
+    }
+
+    /// Principal allowed to access `namespace`; `None` when it is open
+    pub fn owner(&self, namespace: &str) -> Option<Principal> {
+        let explicit = std::iter::once(namespace)
+            .chain(namespace.rmatch_indices(SEPARATOR).map(|(at, _)| &namespace[..at]))
+            .find_map(|scope| self.owners.get(scope));
+        if let Some(owner) = explicit {
+            return Some(*owner);
+        }
+
+        Namespace::parse(namespace)
+            .ok()
+            .and_then(|ns| ns.tenant_id().and_then(|tenant| Principal::from_text(tenant).ok()))
+    }
+
+    /// Whether `caller` may access `namespace`
+    pub fn can_access(&self, namespace: &str, caller: &Principal) -> bool {
+        self.admins.contains(caller) || self.owner(namespace).is_none_or(|owner| owner == *caller)
+    }
+
+    /// Fail with [`ContragError::AccessDenied`] unless `caller` may access
+    /// `namespace`
+    pub fn check_access(&self, namespace: &str, caller: &Principal) -> Result<()> {
+        if self.can_access(namespace, caller) {
+            Ok(())
+        } else {
+            Err(ContragError::AccessDenied {
+                principal: caller.to_text(),
+                namespace: namespace.to_string(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_owners_tenants_and_admins() {
+        let alice = Principal::from_slice(&[1]);
+        let bob = Principal::from_slice(&[2]);
+        let admin = Principal::from_slice(&[3]);
+        let mut acl = NamespaceAcl::new().with_admin(admin);
+
+        acl.set_namespace_owner("User:alice", alice);
+        assert!(acl.check_access("User:alice", &alice).is_ok());
+        assert!(acl.check_access("User:alice:orders", &alice).is_ok());
+        assert!(matches!(
+            acl.check_access("User:alice", &bob),
+            Err(ContragError::AccessDenied { .. })
+        ));
+        assert!(acl.can_access("User:alice", &admin));
+        assert!(acl.can_access("User:alice2", &bob));
+
+        // Tenant namespaces belong to their principal unless overridden
+        let tenant = Namespace::tenant(&bob).child("docs").unwrap();
+        assert!(acl.can_access(&tenant, &bob));
+        assert!(!acl.can_access(&tenant, &alice));
+        acl.set_namespace_owner(&tenant, alice);
+        assert!(acl.can_access(&tenant, &alice));
+
+        assert_eq!(acl.clear_namespace_owner("User:alice"), Some(alice));
+        assert!(acl.can_access("User:alice", &bob));
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, HashSet};
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::error::Result;
+use crate::utils::{estimate_tokens, sanitize_text};
+use crate::vector_store::VectorStore;
+
+const NANOS_PER_DAY: u64 = 86_400 * 1_000_000_000;
+
+/// Upper bounds (in days) of the staleness histogram buckets
+const STALENESS_BUCKETS: [(u64, &str); 4] = [(1, "<1d"), (7, "<7d"), (30, "<30d"), (90, "<90d")];
+
+/// Distribution of embedding L2 norms
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct NormStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub p10: f32,
+    pub p50: f32,
+    pub p90: f32,
+    /// Embeddings with a norm of zero (never match anything)
+    pub zero_count: usize,
+}
+
+/// Sampled quality report for one namespace
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct NamespaceReport {
+    pub namespace: String,
+    pub total_vectors: usize,
+    pub sampled: usize,
+    pub avg_chunk_chars: f64,
+    pub avg_chunk_tokens: f64,
+    pub norms: NormStats,
+    /// Fraction of sampled chunks whose text repeats another sampled chunk
+    pub duplicate_rate: f64,
+    /// Sampled chunk counts per entity type
+    pub entity_types: Vec<(String, usize)>,
+    /// Sampled chunk counts per age bucket ("<1d", "<7d", ..., ">=90d")
+    pub staleness: Vec<(String, usize)>,
+}
+
+/// Sample stored chunks of a namespace and summarize their quality
+///
+/// Useful to diagnose poor retrieval: very short or very long chunks,
+/// degenerate embeddings, heavy duplication, a skewed entity mix or stale
+/// data all show up here. Only `sample_size` vectors are read.
+pub async fn analyze_namespace<S: VectorStore + ?Sized>(
+    store: &S,
+    namespace: &str,
+    sample_size: usize,
+    now: u64,
+) -> Result<NamespaceReport> {
+    let total_vectors = store.count(namespace).await?;
+    let sample = store.sample(namespace, sample_size).await?;
+    let sampled = sample.len();
+    let per_chunk = |total: usize| if sampled == 0 { 0.0 } else { total as f64 / sampled as f64 };
+
+    let avg_chunk_chars = per_chunk(sample.iter().map(|v| v.text.chars().count()).sum());
+    let avg_chunk_tokens = per_chunk(sample.iter().map(|v| estimate_tokens(&v.text)).sum());
+
+    let norms: Vec<f32> = sample
+        .iter()
+        .map(|v| v.embedding.iter().map(|x| x * x).sum::<f32>().sqrt())
+        .collect();
+
+    let mut seen = HashSet::new();
+    let duplicates = sample
+        .iter()
+        .filter(|v| !seen.insert(sanitize_text(&v.text).to_lowercase()))
+        .count();
+
+    let mut entity_types: BTreeMap<String, usize> = BTreeMap::new();
+    for v in &sample {
+        *entity_types.entry(v.metadata.entity_type.clone()).or_default() += 1;
+    }
+
+    let mut staleness = vec![0usize; STALENESS_BUCKETS.len() + 1];
+    for v in &sample {
+        let age_days = now.saturating_sub(v.metadata.timestamp) / NANOS_PER_DAY;
+        let bucket = STALENESS_BUCKETS
+            .iter()
+            .position(|(max_days, _)| age_days < *max_days)
+            .unwrap_or(STALENESS_BUCKETS.len());
+        staleness[bucket] += 1;
+    }
+
+    let labels = STALENESS_BUCKETS
+        .iter()
+        .map(|(_, label)| label.to_string())
+        .chain(std::iter::once(">=90d".to_string()));
+
+    Ok(NamespaceReport {
+        namespace: namespace.to_string(),
+        total_vectors,
+        sampled,
+        avg_chunk_chars,
+        avg_chunk_tokens,
+        norms: norm_stats(norms),
+        duplicate_rate: per_chunk(duplicates),
+        entity_types: entity_types.into_iter().collect(),
+        staleness: labels.zip(staleness).collect(),
+    })
+}
+
+fn norm_stats(mut norms: Vec<f32>) -> NormStats {
+    if norms.is_empty() {
+        return NormStats::default();
+    }
+
+    norms.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let percentile = |p: usize| norms[(norms.len() - 1) * p / 100];
+
+    NormStats {
+        min: norms[0],
+        max: norms[norms.len() - 1],
+        mean: norms.iter().sum::<f32>() / norms.len() as f32,
+        p10: percentile(10),
+        p50: percentile(50),
+        p90: percentile(90),
+        zero_count: norms.iter().filter(|n| **n == 0.0).count(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{test_vector, Vector};
+    use crate::vector_store::stable_memory_store::StableMemoryVectorStore;
+
+    fn vector(id: &str, entity_type: &str, text: &str, embedding: Vec<f32>, age_days: u64) -> Vector {
+        let mut vector = Vector {
+            text: text.to_string(),
+            ..test_vector(id, embedding)
+        };
+        vector.metadata.entity_type = entity_type.to_string();
+        vector.metadata.timestamp = (100 - age_days) * NANOS_PER_DAY;
+        vector
+    }
+
+    #[tokio::test]
+    async fn test_reports_lengths_norms_duplicates_mix_and_staleness() {
+        let mut store = StableMemoryVectorStore::new();
+        store
+            .store_batch(
+                "docs",
+                vec![
+                    vector("a", "User", "Hello  World", vec![3.0, 4.0], 0),
+                    vector("b", "User", "hello world", vec![0.0, 1.0], 3),
+                    vector("c", "Order", "order 7 shipped", vec![1.0, 0.0], 45),
+                    vector("d", "Order", "order 8 paid", vec![0.0, 2.0], 95),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let report = analyze_namespace(&store, "docs", 10, 100 * NANOS_PER_DAY).await.unwrap();
+        assert_eq!((report.total_vectors, report.sampled), (4, 4));
+        assert_eq!(report.avg_chunk_chars, (12 + 11 + 15 + 12) as f64 / 4.0);
+        assert_eq!(report.duplicate_rate, 0.25);
+        assert_eq!(report.entity_types, [("Order".to_string(), 2), ("User".to_string(), 2)]);
+        let staleness: Vec<usize> = report.staleness.iter().map(|(_, count)| *count).collect();
+        assert_eq!(staleness, [1, 1, 0, 1, 1]);
+        assert_eq!(report.staleness[4].0, ">=90d");
+
+        assert!((report.norms.min - 1.0).abs() < 1e-3);
+        assert!((report.norms.max - 5.0).abs() < 1e-3);
+        assert!((report.norms.mean - 2.25).abs() < 1e-3);
+        assert_eq!(report.norms.zero_count, 0);
+
+        // Only the sample is read, spread over the namespace
+        let report = analyze_namespace(&store, "docs", 2, 100 * NANOS_PER_DAY).await.unwrap();
+        assert_eq!((report.total_vectors, report.sampled), (4, 2));
+
+        let empty = analyze_namespace(&store, "none", 10, 0).await.unwrap();
+        assert_eq!((empty.sampled, empty.avg_chunk_chars, empty.duplicate_rate), (0, 0.0, 0.0));
+    }
+}
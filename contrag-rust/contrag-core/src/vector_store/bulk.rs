@@ -0,0 +1,33 @@
+use std::future::Future;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Run a resumable bulk operation across timer ticks
+///
+/// `step` performs one bounded unit of work, typically one call of
+/// [`VectorStore::delete_by_prefix`](crate::vector_store::VectorStore::delete_by_prefix)
+/// with a cursor kept in canister state, and returns `true` once the
+/// operation has finished. Each step runs in its own message, so a large
+/// offboarding never hits the instruction limit. A failed step should
+/// return `true` to stop, or keep its cursor and return `false` to retry.
+pub fn run_resumable<F, Fut>(step: F, delay: Duration)
+where
+    F: Fn() -> Fut + 'static,
+    Fut: Future<Output = bool> + 'static,
+{
+    schedule(Rc::new(step), delay);
+}
+
+fn schedule<F, Fut>(step: Rc<F>, delay: Duration)
+where
+    F: Fn() -> Fut + 'static,
+    Fut: Future<Output = bool> + 'static,
+{
+    ic_cdk_timers::set_timer(delay, move || {
+        ic_cdk::spawn(async move {
+            if !step().await {
+                schedule(step, delay);
+            }
+        })
+    });
+}
@@ -0,0 +1,78 @@
+//! Transparent compression of stored chunk text
+//!
+//! Text is deflated with the pure-Rust miniz backend of `flate2`, which
+//! builds for `wasm32-unknown-unknown`. Texts below the configured size, or
+//! that deflate would not shrink, are kept as they are.
+
+use std::borrow::Cow;
+use std::io::{Read, Write};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use crate::config::TextCompressionConfig;
+
+/// Chunk text as held by a vector store
+#[derive(Clone, Debug, PartialEq)]
+pub enum StoredText {
+    Plain(String),
+    Deflated(Vec<u8>),
+}
+
+impl StoredText {
+    pub fn new(text: String, config: &TextCompressionConfig) -> Self {
+        if !config.enabled || text.len() < config.min_bytes {
+            return Self::Plain(text);
+        }
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        match encoder.write_all(text.as_bytes()).and_then(|_| encoder.finish()) {
+            Ok(bytes) if bytes.len() < text.len() => Self::Deflated(bytes),
+            _ => Self::Plain(text),
+        }
+    }
+
+    /// Bytes taken in storage
+    pub fn stored_len(&self) -> usize {
+        match self {
+            Self::Plain(text) => text.len(),
+            Self::Deflated(bytes) => bytes.len(),
+        }
+    }
+
+    /// The original text, inflated if needed
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            Self::Plain(text) => Cow::Borrowed(text),
+            Self::Deflated(bytes) => {
+                let mut text = String::new();
+                DeflateDecoder::new(bytes.as_slice())
+                    .read_to_string(&mut text)
+                    .expect("Deflated text is only written by StoredText::new");
+                Cow::Owned(text)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compresses_long_text_only() {
+        let config = TextCompressionConfig {
+            enabled: true,
+            min_bytes: 64,
+        };
+        let long = "Order 1042 for jane@example.com shipped. ".repeat(20);
+
+        let stored = StoredText::new(long.clone(), &config);
+        assert!(matches!(stored, StoredText::Deflated(_)));
+        assert!(stored.stored_len() < long.len() / 4);
+        assert_eq!(stored.text(), long);
+
+        assert_eq!(StoredText::new("short".to_string(), &config), StoredText::Plain("short".to_string()));
+        let disabled = TextCompressionConfig::default();
+        assert!(matches!(StoredText::new(long, &disabled), StoredText::Plain(_)));
+    }
+}
@@ -0,0 +1,211 @@
+use candid::{decode_one, encode_one, CandidType};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::error::{ContragError, Result};
+use crate::types::Vector;
+
+/// Format version written into namespace snapshots
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// A namespace's vectors as a self-contained backup
+///
+/// Candid-encoded by [`NamespaceSnapshot::to_bytes`], so a snapshot can be
+/// returned from a canister method, stored off-chain and passed back to
+/// another canister's import endpoint unchanged.
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct NamespaceSnapshot {
+    pub version: u32,
+    /// Namespace the vectors were exported from
+    pub namespace: String,
+    pub vectors: Vec<Vector>,
+}
+
+impl NamespaceSnapshot {
+    pub fn new(namespace: &str, vectors: Vec<Vector>) -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            namespace: namespace.to_string(),
+            vectors,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        encode_one(self)
+            .map_err(|e| ContragError::SerializationError(format!("Failed to encode snapshot: {}", e)))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let snapshot: Self = decode_one(bytes)
+            .map_err(|e| ContragError::SerializationError(format!("Failed to decode snapshot: {}", e)))?;
+        if snapshot.version > SNAPSHOT_VERSION {
+            return Err(ContragError::SerializationError(format!(
+                "Snapshot version {} is newer than the supported version {}",
+                snapshot.version, SNAPSHOT_VERSION
+            )));
+        }
+        Ok(snapshot)
+    }
+}
+
+/// One exported vector, as a line of JSONL
+///
+/// Field names follow the common `{id, text, embedding, metadata}` layout,
+/// so exports load directly into pandas or LangChain and can be re-imported
+/// with [`ImportSession`](super::import::ImportSession).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportRecord {
+    pub id: String,
+    pub namespace: String,
+    pub text: String,
+    pub embedding: Vec<f32>,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub chunk_index: usize,
+    pub total_chunks: usize,
+    pub timestamp: u64,
+    pub ttl_seconds: Option<u64>,
+    /// Custom metadata, parsed when it is JSON
+    pub metadata: Value,
+}
+
+impl ExportRecord {
+    pub fn new(namespace: &str, vector: Vector) -> Self {
+        Self {
+            metadata: custom_value(vector.metadata.custom.as_deref()),
+            id: vector.id,
+            namespace: namespace.to_string(),
+            text: vector.text,
+            embedding: vector.embedding,
+            entity_type: vector.metadata.entity_type,
+            entity_id: vector.metadata.entity_id,
+            chunk_index: vector.metadata.chunk_index,
+            total_chunks: vector.metadata.total_chunks,
+            timestamp: vector.metadata.timestamp,
+            ttl_seconds: vector.metadata.ttl_seconds,
+        }
+    }
+}
+
+/// Custom metadata as JSON: parsed if possible, a string otherwise
+fn custom_value(custom: Option<&str>) -> Value {
+    match custom {
+        Some(custom) => serde_json::from_str(custom).unwrap_or_else(|_| Value::String(custom.to_string())),
+        None => Value::Null,
+    }
+}
+
+/// Line-delimited JSON of a namespace's vectors, one [`ExportRecord`] per line
+pub fn to_jsonl(namespace: &str, vectors: Vec<Vector>) -> String {
+    let mut out = String::new();
+    for vector in vectors {
+        if let Ok(line) = serde_json::to_string(&ExportRecord::new(namespace, vector)) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Column-oriented export, one array per field
+///
+/// Maps one-to-one onto an Arrow record batch or Parquet file: every column
+/// has one entry per vector, `embeddings` is a fixed-size list column of
+/// `dimensions` floats, and `metadata` holds the raw custom metadata JSON.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct ColumnarExport {
+    pub namespace: String,
+    pub dimensions: u64,
+    pub ids: Vec<String>,
+    pub texts: Vec<String>,
+    pub entity_types: Vec<String>,
+    pub entity_ids: Vec<String>,
+    pub chunk_indexes: Vec<u64>,
+    pub total_chunks: Vec<u64>,
+    pub timestamps: Vec<u64>,
+    pub metadata: Vec<Option<String>>,
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+impl ColumnarExport {
+    pub fn new(namespace: &str, vectors: Vec<Vector>) -> Self {
+        let mut export = Self {
+            namespace: namespace.to_string(),
+            dimensions: vectors.first().map(|v| v.embedding.len() as u64).unwrap_or(0),
+            ..Self::default()
+        };
+
+        for vector in vectors {
+            export.ids.push(vector.id);
+            export.texts.push(vector.text);
+            export.entity_types.push(vector.metadata.entity_type);
+            export.entity_ids.push(vector.metadata.entity_id);
+            export.chunk_indexes.push(vector.metadata.chunk_index as u64);
+            export.total_chunks.push(vector.metadata.total_chunks as u64);
+            export.timestamps.push(vector.metadata.timestamp);
+            export.metadata.push(vector.metadata.custom);
+            export.embeddings.push(vector.embedding);
+        }
+
+        export
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{test_vector, VectorMetadata};
+    use crate::vector_store::import::{parse_record, ImportMapping};
+    use crate::vector_store::stable_memory_store::StableMemoryVectorStore;
+    use crate::vector_store::VectorStore;
+
+    #[tokio::test]
+    async fn test_jsonl_and_snapshot_round_trips() {
+        let mut vector = Vector {
+            text: "Order 7 shipped".to_string(),
+            ..test_vector("Order::7::chunk_0", vec![0.5, 0.25])
+        };
+        vector.metadata = VectorMetadata {
+            entity_type: "Order".to_string(),
+            entity_id: "7".to_string(),
+            timestamp: 42,
+            custom: Some(r#"{"status":"shipped"}"#.to_string()),
+            ..vector.metadata
+        };
+
+        let jsonl = to_jsonl("Order:7", vec![vector.clone()]);
+        assert_eq!(jsonl.lines().count(), 1);
+
+        let mapping = ImportMapping {
+            namespace_template: "copy:{entity_type}:{entity_id}".to_string(),
+            ..ImportMapping::default()
+        };
+        let (namespace, imported) = parse_record(jsonl.trim(), &mapping, 0, &mut Default::default()).unwrap();
+        assert_eq!(namespace, "copy:Order:7");
+        assert_eq!(imported.id, vector.id);
+        assert_eq!(imported.embedding, vector.embedding);
+        assert_eq!((imported.metadata.chunk_index, imported.metadata.total_chunks), (0, 1));
+        assert_eq!(imported.metadata.custom.as_deref(), Some(r#"{"status":"shipped"}"#));
+
+        let columns = ColumnarExport::new("Order:7", vec![vector.clone()]);
+        assert_eq!((columns.len(), columns.dimensions), (1, 2));
+        assert_eq!(columns.metadata[0].as_deref(), Some(r#"{"status":"shipped"}"#));
+
+        let mut source = StableMemoryVectorStore::new();
+        source.store("Order:7", vector).await.unwrap();
+        let bytes = source.export_snapshot("Order:7").await.unwrap();
+
+        let mut target = StableMemoryVectorStore::new();
+        assert_eq!(target.import_snapshot("seed:Order:7", &bytes).await.unwrap(), 1);
+        let restored = target.get("seed:Order:7", "Order::7::chunk_0").await.unwrap().unwrap();
+        assert_eq!(restored.embedding, vec![0.5, 0.25]);
+        assert!(NamespaceSnapshot::from_bytes(b"not candid").is_err());
+    }
+}
@@ -0,0 +1,751 @@
+//! HNSW approximate nearest neighbor index
+//!
+//! A linear scan over a namespace costs instructions proportional to its
+//! size and runs out of the per-message instruction limit after a few
+//! thousand vectors. [`HnswVectorStore`] keeps a hierarchical navigable
+//! small world graph per namespace instead, so a search visits a few
+//! hundred nodes regardless of the namespace size. Small namespaces are
+//! still scanned exactly (see [`HnswConfig::exact_search_below`]).
+//!
+//! Node levels are derived from a hash of the vector ID rather than a
+//! random number generator, so the graph is deterministic across replicas
+//! and toolchain versions.
+//! Deletes and replacements leave tombstones; once they outnumber the live
+//! vectors, the graph is rebuilt a few vectors per write (see
+//! [`HnswConfig::rebuild_batch`] and [`VectorStore::maintain`]), so no
+//! single message re-inserts a whole namespace. The graphs are
+//! written to stable memory in `pre_upgrade` with [`HnswVectorStore::persist`]
+//! and loaded back without re-inserting any vector by
+//! [`HnswVectorStore::init`].
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use candid::CandidType;
+use ic_stable_structures::Memory;
+use serde::{Deserialize, Serialize};
+use crate::config::{DistanceMetric, HnswConfig};
+use crate::error::{ContragError, Result};
+use crate::monitoring;
+use crate::storage::memory::{read_blob, write_blob};
+use crate::storage::migrations::encode_versioned;
+use crate::storage::{Migrator, StorageComponent};
+use crate::types::{SearchResult, Vector};
+use crate::utils::stable_hash;
+use crate::vector_store::{retain_min_score, similarity, VectorStore};
+
+/// Highest layer a node can be placed on
+const MAX_LEVEL: usize = 16;
+
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+struct HnswNode {
+    vector: Vector,
+    /// Neighbor node indexes per layer, from layer 0 up to the node's level
+    neighbors: Vec<Vec<u32>>,
+    deleted: bool,
+}
+
+/// A node and its similarity to the query, ordered by similarity
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Scored {
+    similarity: f32,
+    node: u32,
+}
+
+impl Eq for Scored {}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.similarity
+            .total_cmp(&other.similarity)
+            .then(self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// HNSW graph of one namespace
+///
+/// Deleted and replaced vectors are tombstoned and skipped in results.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct HnswIndex {
+    nodes: Vec<HnswNode>,
+    ids: HashMap<String, u32>,
+    entry: Option<u32>,
+    max_level: usize,
+    deleted: usize,
+}
+
+impl HnswIndex {
+    /// Number of live vectors
+    pub fn len(&self) -> usize {
+        self.nodes.len() - self.deleted
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Embedding dimensions of the indexed vectors
+    pub fn dimensions(&self) -> Option<usize> {
+        self.live().next().map(|v| v.embedding.len())
+    }
+
+    /// Live vectors in insertion order
+    pub fn live(&self) -> impl Iterator<Item = &Vector> {
+        self.nodes.iter().filter(|n| !n.deleted).map(|n| &n.vector)
+    }
+
+    /// A live vector by ID
+    pub fn get(&self, vector_id: &str) -> Option<&Vector> {
+        self.ids.get(vector_id).map(|&node| &self.nodes[node as usize].vector)
+    }
+
+    /// Insert a vector, replacing any vector with the same ID
+    pub fn insert(&mut self, vector: Vector, config: &HnswConfig, metric: DistanceMetric) {
+        self.tombstone(&vector.id);
+
+        let id = self.nodes.len() as u32;
+        let level = node_level(&vector.id, config.m);
+        let query = vector.embedding.clone();
+        self.ids.insert(vector.id.clone(), id);
+        self.nodes.push(HnswNode {
+            vector,
+            neighbors: vec![vec![]; level + 1],
+            deleted: false,
+        });
+
+        let Some(mut entry) = self.entry else {
+            self.entry = Some(id);
+            self.max_level = level;
+            return;
+        };
+
+        for layer in (level + 1..=self.max_level).rev() {
+            entry = self.search_layer(&query, &[entry], 1, layer, metric)[0].node;
+        }
+
+        let mut entries = vec![entry];
+        for layer in (0..=level.min(self.max_level)).rev() {
+            let candidates = self.search_layer(&query, &entries, config.ef_construction.max(1), layer, metric);
+            let max = max_links(config.m, layer);
+            let selected: Vec<u32> = candidates.iter().take(max).map(|c| c.node).collect();
+
+            for &neighbor in &selected {
+                self.link(neighbor, id, layer, max, metric);
+            }
+            self.nodes[id as usize].neighbors[layer] = selected;
+            entries = candidates.iter().map(|c| c.node).collect();
+        }
+
+        if level > self.max_level {
+            self.max_level = level;
+            self.entry = Some(id);
+        }
+    }
+
+    /// Delete a vector by ID; returns whether it was present
+    pub fn remove(&mut self, vector_id: &str) -> bool {
+        self.tombstone(vector_id)
+    }
+
+    /// Whether tombstones outnumber the live vectors, so the graph should
+    /// be rebuilt without them
+    pub fn needs_rebuild(&self) -> bool {
+        self.deleted > self.len()
+    }
+
+    /// The `k` live vectors most similar to `query` under `metric`
+    pub fn search(&self, query: &[f32], k: usize, config: &HnswConfig, metric: DistanceMetric) -> Vec<(f32, &Vector)> {
+        if self.len() < config.exact_search_below {
+            let mut results: Vec<(f32, &Vector)> = self
+                .live()
+                .map(|v| (similarity(metric, query, &v.embedding), v))
+                .collect();
+            results.sort_by(|a, b| b.0.total_cmp(&a.0));
+            results.truncate(k);
+            return results;
+        }
+
+        let Some(mut entry) = self.entry else {
+            return vec![];
+        };
+        for layer in (1..=self.max_level).rev() {
+            entry = self.search_layer(query, &[entry], 1, layer, metric)[0].node;
+        }
+
+        self.search_layer(query, &[entry], config.ef_search.max(k), 0, metric)
+            .into_iter()
+            .map(|scored| (scored.similarity, &self.nodes[scored.node as usize]))
+            .filter(|(_, node)| !node.deleted)
+            .map(|(similarity, node)| (similarity, &node.vector))
+            .take(k)
+            .collect()
+    }
+
+    fn tombstone(&mut self, vector_id: &str) -> bool {
+        match self.ids.remove(vector_id) {
+            Some(node) => {
+                self.nodes[node as usize].deleted = true;
+                self.deleted += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn score(&self, query: &[f32], node: u32, metric: DistanceMetric) -> Scored {
+        Scored {
+            similarity: similarity(metric, query, &self.nodes[node as usize].vector.embedding),
+            node,
+        }
+    }
+
+    fn neighbors(&self, node: u32, layer: usize) -> &[u32] {
+        self.nodes[node as usize]
+            .neighbors
+            .get(layer)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Best-first search of one layer, most similar first
+    fn search_layer(&self, query: &[f32], entries: &[u32], ef: usize, layer: usize, metric: DistanceMetric) -> Vec<Scored> {
+        let mut visited: HashSet<u32> = entries.iter().copied().collect();
+        let mut candidates: BinaryHeap<Scored> = BinaryHeap::new();
+        let mut results: BinaryHeap<Reverse<Scored>> = BinaryHeap::new();
+
+        for &entry in entries {
+            let scored = self.score(query, entry, metric);
+            candidates.push(scored);
+            results.push(Reverse(scored));
+            if results.len() > ef {
+                results.pop();
+            }
+        }
+
+        while let Some(current) = candidates.pop() {
+            let worst = results.peek().map(|r| r.0.similarity).unwrap_or(f32::NEG_INFINITY);
+            if results.len() >= ef && current.similarity < worst {
+                break;
+            }
+
+            for &neighbor in self.neighbors(current.node, layer) {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let scored = self.score(query, neighbor, metric);
+                let worst = results.peek().map(|r| r.0.similarity).unwrap_or(f32::NEG_INFINITY);
+                if results.len() < ef || scored.similarity > worst {
+                    candidates.push(scored);
+                    results.push(Reverse(scored));
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        let mut results: Vec<Scored> = results.into_iter().map(|r| r.0).collect();
+        results.sort_by(|a, b| b.cmp(a));
+        results
+    }
+
+    /// Add a link, keeping only the `max` most similar neighbors
+    fn link(&mut self, from: u32, to: u32, layer: usize, max: usize, metric: DistanceMetric) {
+        let mut links = self.nodes[from as usize].neighbors[layer].clone();
+        links.push(to);
+
+        if links.len() > max {
+            let base = self.nodes[from as usize].vector.embedding.clone();
+            let mut scored: Vec<Scored> = links.iter().map(|&n| self.score(&base, n, metric)).collect();
+            scored.sort_by(|a, b| b.cmp(a));
+            links = scored.into_iter().take(max).map(|s| s.node).collect();
+        }
+
+        self.nodes[from as usize].neighbors[layer] = links;
+    }
+}
+
+fn max_links(m: usize, layer: usize) -> usize {
+    if layer == 0 {
+        m * 2
+    } else {
+        m
+    }
+}
+
+/// Level of a node, exponentially distributed with factor `1 / ln(m)`
+fn node_level(vector_id: &str, m: usize) -> usize {
+    // Uniform in (0, 1] from the top 53 bits
+    let uniform = ((stable_hash(vector_id) >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+    let level = -uniform.ln() / (m.max(2) as f64).ln();
+    (level as usize).min(MAX_LEVEL)
+}
+
+/// Graph being rebuilt from the live vectors of an index
+#[derive(Default)]
+struct Rebuild {
+    graph: HnswIndex,
+    /// Next node of the index to copy
+    next: usize,
+}
+
+/// Vector store searching through one HNSW index per namespace
+pub struct HnswVectorStore {
+    config: HnswConfig,
+    metric: DistanceMetric,
+    min_score: Option<f32>,
+    indexes: BTreeMap<String, HnswIndex>,
+    /// Rebuilds in progress; searches keep using the index until its
+    /// rebuild has copied every node
+    rebuilds: BTreeMap<String, Rebuild>,
+}
+
+impl HnswVectorStore {
+    pub fn new(config: HnswConfig) -> Self {
+        Self {
+            config,
+            metric: DistanceMetric::default(),
+            min_score: None,
+            indexes: BTreeMap::new(),
+            rebuilds: BTreeMap::new(),
+        }
+    }
+
+    /// Link and rank vectors by `metric`
+    ///
+    /// Set it before storing vectors; graphs linked under another metric
+    /// keep their neighbors until they are rebuilt.
+    pub fn with_metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Drop search results scoring below `min_score` by default
+    pub fn with_min_score(mut self, min_score: Option<f32>) -> Self {
+        self.min_score = min_score;
+        self
+    }
+
+    pub fn config(&self) -> &HnswConfig {
+        &self.config
+    }
+
+    fn search_scored(&self, namespace: &str, query_embedding: &[f32], k: usize, min_score: Option<f32>) -> Vec<SearchResult> {
+        let Some(index) = self.indexes.get(namespace) else {
+            return vec![];
+        };
+
+        let mut results: Vec<SearchResult> = index
+            .search(query_embedding, k, &self.config, self.metric)
+            .into_iter()
+            .map(|(score, v)| SearchResult {
+                vector_id: v.id.clone(),
+                text: v.text.clone(),
+                score,
+                metadata: v.metadata.clone(),
+            })
+            .collect();
+        retain_min_score(&mut results, min_score);
+        results
+    }
+
+    /// Open a store over `memory`, restoring the graphs [`Self::persist`]
+    /// last wrote there
+    pub fn with_memory(config: HnswConfig, memory: &(impl Memory + ?Sized)) -> Result<Self> {
+        let mut store = Self::new(config);
+        store.init(memory)?;
+        Ok(store)
+    }
+
+    /// Load the graphs [`Self::persist`] wrote to `memory` without
+    /// re-inserting any vector; empty memory loads nothing
+    ///
+    /// Call this during canister init or post_upgrade. A rebuild that was
+    /// in progress starts over with the next write.
+    pub fn init(&mut self, memory: &(impl Memory + ?Sized)) -> Result<()> {
+        let Some(raw) = read_blob(memory)? else {
+            return Ok(());
+        };
+        let payload = Migrator::new().load(StorageComponent::HnswIndex, &raw)?;
+        self.indexes = candid::decode_one(&payload)
+            .map_err(|e| ContragError::StorageError(format!("Failed to decode HNSW graphs: {}", e)))?;
+        self.rebuilds.clear();
+        Ok(())
+    }
+
+    /// Write the graphs to `memory`
+    ///
+    /// Call this during pre_upgrade. `memory` must belong to this store
+    /// alone, e.g. the [`ContragMemory::Vectors`] region of the host's
+    /// memory manager.
+    ///
+    /// [`ContragMemory::Vectors`]: crate::storage::memory::ContragMemory::Vectors
+    pub fn persist(&self, memory: &(impl Memory + ?Sized)) -> Result<()> {
+        let payload = candid::encode_one(&self.indexes)
+            .map_err(|e| ContragError::StorageError(format!("Failed to encode HNSW graphs: {}", e)))?;
+        write_blob(memory, &encode_versioned(StorageComponent::HnswIndex, &payload))
+    }
+
+    /// Re-insert up to `max_nodes` live vectors into the rebuilt graph of
+    /// every namespace that needs one; returns whether no rebuild is left
+    ///
+    /// Writes advance the rebuild of their namespace by
+    /// [`HnswConfig::rebuild_batch`] vectors; call this from a timer to
+    /// finish rebuilds of namespaces that are no longer written to.
+    pub fn rebuild_step(&mut self, max_nodes: usize) -> bool {
+        let namespaces: Vec<String> = self
+            .indexes
+            .iter()
+            .filter(|(namespace, index)| index.needs_rebuild() || self.rebuilds.contains_key(*namespace))
+            .map(|(namespace, _)| namespace.clone())
+            .collect();
+
+        let mut done = true;
+        for namespace in namespaces {
+            done &= self.advance_rebuild(&namespace, max_nodes);
+        }
+        done
+    }
+
+    /// Copy up to `max_nodes` live vectors of `namespace` into its rebuilt
+    /// graph, starting the rebuild if tombstones outnumber them and
+    /// swapping the graphs once every node is copied; returns whether no
+    /// rebuild is left in progress
+    fn advance_rebuild(&mut self, namespace: &str, max_nodes: usize) -> bool {
+        let Some(index) = self.indexes.get_mut(namespace) else {
+            self.rebuilds.remove(namespace);
+            return true;
+        };
+        if !self.rebuilds.contains_key(namespace) && !index.needs_rebuild() {
+            return true;
+        }
+        let rebuild = self.rebuilds.entry(namespace.to_string()).or_default();
+
+        let mut copied = 0;
+        while copied < max_nodes && rebuild.next < index.nodes.len() {
+            let node = &index.nodes[rebuild.next];
+            rebuild.next += 1;
+            if !node.deleted {
+                rebuild.graph.insert(node.vector.clone(), &self.config, self.metric);
+                copied += 1;
+            }
+        }
+
+        if rebuild.next < index.nodes.len() {
+            return false;
+        }
+        if let Some(rebuild) = self.rebuilds.remove(namespace) {
+            *index = rebuild.graph;
+        }
+        true
+    }
+
+    /// Drop `vector_id` from the graph being rebuilt for `namespace`, if it
+    /// was copied there already, ahead of its deletion or replacement
+    fn forget_rebuilt(&mut self, namespace: &str, vector_id: &str) {
+        if let Some(rebuild) = self.rebuilds.get_mut(namespace) {
+            rebuild.graph.tombstone(vector_id);
+        }
+    }
+}
+
+impl Default for HnswVectorStore {
+    fn default() -> Self {
+        Self::new(HnswConfig::default())
+    }
+}
+
+#[async_trait::async_trait]
+impl VectorStore for HnswVectorStore {
+    async fn store(&mut self, namespace: &str, vector: Vector) -> Result<()> {
+        monitoring::ensure_writable()?;
+
+        if vector.embedding.is_empty() {
+            return Err(ContragError::VectorStoreError("Empty embedding".to_string()));
+        }
+        let index = self.indexes.entry(namespace.to_string()).or_default();
+        if let Some(expected) = index.dimensions() {
+            if expected != vector.embedding.len() {
+                return Err(ContragError::DimensionMismatch {
+                    expected,
+                    actual: vector.embedding.len(),
+                });
+            }
+        }
+
+        let vector_id = vector.id.clone();
+        index.insert(vector, &self.config, self.metric);
+        self.forget_rebuilt(namespace, &vector_id);
+        self.advance_rebuild(namespace, self.config.rebuild_batch);
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        namespace: &str,
+        query_embedding: Vec<f32>,
+        k: usize,
+    ) -> Result<Vec<SearchResult>> {
+        Ok(self.search_scored(namespace, &query_embedding, k, self.min_score))
+    }
+
+    async fn search_with_min_score(
+        &self,
+        namespace: &str,
+        query_embedding: Vec<f32>,
+        k: usize,
+        min_score: Option<f32>,
+    ) -> Result<Vec<SearchResult>> {
+        Ok(self.search_scored(namespace, &query_embedding, k, min_score.or(self.min_score)))
+    }
+
+    async fn delete(&mut self, namespace: &str, vector_id: &str) -> Result<()> {
+        if let Some(index) = self.indexes.get_mut(namespace) {
+            index.remove(vector_id);
+            self.forget_rebuilt(namespace, vector_id);
+            self.advance_rebuild(namespace, self.config.rebuild_batch);
+        }
+        Ok(())
+    }
+
+    async fn delete_namespace(&mut self, namespace: &str) -> Result<()> {
+        self.indexes.remove(namespace);
+        self.rebuilds.remove(namespace);
+        Ok(())
+    }
+
+    async fn delete_expired(
+        &mut self,
+        namespace: &str,
+        entity_type: &str,
+        cutoff: u64,
+    ) -> Result<Vec<String>> {
+        let Some(index) = self.indexes.get_mut(namespace) else {
+            return Ok(vec![]);
+        };
+
+        let expired: Vec<String> = index
+            .live()
+            .filter(|v| v.metadata.entity_type == entity_type && v.metadata.timestamp < cutoff)
+            .map(|v| v.id.clone())
+            .collect();
+        for id in &expired {
+            index.remove(id);
+        }
+        for id in &expired {
+            self.forget_rebuilt(namespace, id);
+        }
+        self.advance_rebuild(namespace, self.config.rebuild_batch);
+        Ok(expired)
+    }
+
+    async fn sample(&self, namespace: &str, limit: usize) -> Result<Vec<Vector>> {
+        let index = match self.indexes.get(namespace) {
+            Some(index) if limit > 0 => index,
+            _ => return Ok(vec![]),
+        };
+
+        let step = (index.len() / limit).max(1);
+        Ok(index.live().step_by(step).take(limit).cloned().collect())
+    }
+
+    async fn count(&self, namespace: &str) -> Result<usize> {
+        Ok(self.indexes.get(namespace).map(HnswIndex::len).unwrap_or(0))
+    }
+
+    async fn list_namespaces(&self) -> Result<Vec<String>> {
+        Ok(self.indexes.keys().cloned().collect())
+    }
+
+    async fn get(&self, namespace: &str, vector_id: &str) -> Result<Option<Vector>> {
+        Ok(self
+            .indexes
+            .get(namespace)
+            .and_then(|index| index.get(vector_id))
+            .cloned())
+    }
+
+    async fn export_namespace(&self, namespace: &str) -> Result<Vec<Vector>> {
+        Ok(self
+            .indexes
+            .get(namespace)
+            .map(|index| index.live().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn list_vectors(&self, namespace: &str, offset: usize, limit: usize) -> Result<Vec<Vector>> {
+        Ok(self
+            .indexes
+            .get(namespace)
+            .map(|index| index.live().skip(offset).take(limit).cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn persist_state(&mut self, memory: &dyn Memory) -> Result<()> {
+        self.persist(memory)
+    }
+
+    fn restore_state(&mut self, memory: &dyn Memory) -> Result<()> {
+        self.init(memory)
+    }
+
+    fn maintain(&mut self, budget: usize) -> Result<bool> {
+        Ok(self.rebuild_step(budget))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::DefaultMemoryImpl;
+    use crate::types::test_vector;
+    use crate::vector_store::cosine_similarity;
+
+    fn vector(i: usize, embedding: Vec<f32>) -> Vector {
+        let mut vector = Vector {
+            text: format!("chunk {}", i),
+            ..test_vector(&format!("v{}", i), embedding)
+        };
+        vector.metadata.timestamp = i as u64;
+        vector
+    }
+
+    /// Deterministic pseudo-random embeddings
+    fn embeddings(count: usize, dimensions: usize) -> Vec<Vec<f32>> {
+        let mut state: u64 = 42;
+        (0..count)
+            .map(|_| {
+                (0..dimensions)
+                    .map(|_| {
+                        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                        ((state >> 33) as f32 / (1u64 << 31) as f32) - 0.5
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_recall_deletion_and_persistence() {
+        let config = HnswConfig {
+            exact_search_below: 0,
+            ..HnswConfig::default()
+        };
+        let mut store = HnswVectorStore::new(config);
+        let data = embeddings(600, 16);
+        for (i, embedding) in data.iter().enumerate() {
+            store.store("docs", vector(i, embedding.clone())).await.unwrap();
+        }
+
+        let queries = embeddings(620, 16).split_off(600);
+        let mut found = 0;
+        for query in &queries {
+            let mut exact: Vec<(f32, usize)> = data
+                .iter()
+                .enumerate()
+                .map(|(i, e)| (cosine_similarity(query, e), i))
+                .collect();
+            exact.sort_by(|a, b| b.0.total_cmp(&a.0));
+            let expected: HashSet<String> = exact.iter().take(10).map(|(_, i)| format!("v{}", i)).collect();
+
+            let results = store.search("docs", query.clone(), 10).await.unwrap();
+            found += results.iter().filter(|r| expected.contains(&r.vector_id)).count();
+        }
+        let recall = found as f32 / (queries.len() * 10) as f32;
+        assert!(recall >= 0.9, "recall {}", recall);
+
+        // Deleted vectors never come back, also after the rebuild
+        for i in 0..400 {
+            store.delete("docs", &format!("v{}", i)).await.unwrap();
+        }
+        assert_eq!(store.count("docs").await.unwrap(), 200);
+        let results = store.search("docs", data[0].clone(), 10).await.unwrap();
+        assert_eq!(results.len(), 10);
+        assert!(results.iter().all(|r| r.metadata.timestamp >= 400));
+
+        let memory = DefaultMemoryImpl::default();
+        store.persist(&memory).unwrap();
+        let restored = HnswVectorStore::with_memory(HnswConfig::default(), &memory).unwrap();
+        let again = restored.search("docs", data[0].clone(), 10).await.unwrap();
+        assert_eq!(
+            again.iter().map(|r| &r.vector_id).collect::<Vec<_>>(),
+            results.iter().map(|r| &r.vector_id).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_runs_in_bounded_steps() {
+        let config = HnswConfig {
+            exact_search_below: 0,
+            rebuild_batch: 1,
+            ..HnswConfig::default()
+        };
+        let mut store = HnswVectorStore::new(config);
+        let data = embeddings(100, 8);
+        for (i, embedding) in data.iter().enumerate() {
+            store.store("docs", vector(i, embedding.clone())).await.unwrap();
+        }
+        // Replacements leave tombstones too
+        for (i, embedding) in data.iter().enumerate().take(40) {
+            store.store("docs", vector(i, embedding.clone())).await.unwrap();
+        }
+        for i in 40..95 {
+            store.delete("docs", &format!("v{}", i)).await.unwrap();
+        }
+
+        // The rebuild started once tombstones outnumbered live vectors and
+        // has not copied all of them yet
+        assert!(store.rebuilds.contains_key("docs"));
+        assert_eq!(store.count("docs").await.unwrap(), 45);
+        let results = store.search("docs", data[50].clone(), 45).await.unwrap();
+        assert!(results.iter().all(|r| !(40..95).contains(&r.metadata.timestamp)));
+
+        assert!(!store.rebuild_step(10));
+        assert!(store.rebuild_step(100));
+        let index = &store.indexes["docs"];
+        assert_eq!(index.len(), 45);
+        assert!(index.nodes.len() < 100);
+        assert_eq!(store.search("docs", data[0].clone(), 1).await.unwrap()[0].vector_id, "v0");
+    }
+
+    #[tokio::test]
+    async fn test_rolled_back_batch_restores_replaced_vectors() {
+        let mut store = HnswVectorStore::new(HnswConfig::default());
+        store.store("docs", vector(0, vec![1.0, 0.0])).await.unwrap();
+
+        let replacement = Vector {
+            text: "replacement".to_string(),
+            ..vector(0, vec![0.0, 1.0])
+        };
+        let batch = vec![replacement, vector(1, vec![1.0, 1.0]), vector(2, vec![1.0])];
+        assert!(store.store_batch("docs", batch).await.is_err());
+
+        assert_eq!(store.count("docs").await.unwrap(), 1);
+        let kept = store.get("docs", "v0").await.unwrap().unwrap();
+        assert_eq!(kept.embedding, vec![1.0, 0.0]);
+    }
+
+    #[tokio::test]
+    async fn test_search_honors_metric() {
+        let config = HnswConfig {
+            exact_search_below: 0,
+            ..HnswConfig::default()
+        };
+        let mut store = HnswVectorStore::new(config).with_metric(DistanceMetric::Euclidean);
+        for i in 0..20 {
+            let scale = if i % 2 == 0 { 1.0 } else { 10.0 };
+            store.store("docs", vector(i, vec![scale, scale])).await.unwrap();
+        }
+
+        // Cosine would score both clusters 1.0
+        let results = store.search("docs", vec![10.0, 10.0], 10).await.unwrap();
+        assert!(results.iter().all(|r| r.metadata.timestamp % 2 == 1 && r.score == 1.0));
+        let results = store.search("docs", vec![1.0, 1.0], 20).await.unwrap();
+        assert!(results[9].score == 1.0 && results[10].score < 0.1);
+    }
+}
@@ -0,0 +1,533 @@
+//! Hot/cold vector storage
+//!
+//! Heap memory is scarce and lost on upgrade, while stable memory is large
+//! but slow to scan. [`HybridVectorStore`] keeps the `max_hot_vectors` most
+//! recently used vectors on the heap and the rest Candid-encoded in a
+//! [`StableBTreeMap`]. Searches scan both tiers. Cold vectors returned by a
+//! search or `get` are promoted on the next write, which demotes the least
+//! recently used hot vectors to make room.
+//!
+//! The cold tier survives upgrades by itself: call
+//! [`HybridVectorStore::persist`] in `pre_upgrade` to demote the hot tier and
+//! reopen the store over the same memory in `post_upgrade`.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{DefaultMemoryImpl, Memory, StableBTreeMap, Storable};
+use crate::config::{DistanceMetric, VectorStoreConfig};
+use crate::error::{ContragError, Result};
+use crate::monitoring;
+use crate::types::{BatchMode, BatchWriteReport, SearchResult, Vector};
+use crate::vector_store::stable_memory_store::validate;
+use crate::vector_store::{similarity, VectorStore};
+
+/// Hot tier size when the config leaves `max_hot_vectors` unset
+pub const DEFAULT_MAX_HOT_VECTORS: usize = 10_000;
+
+/// Vector as stored in the cold tier
+struct ColdVector(Vector);
+
+impl Storable for ColdVector {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(&self.0).expect("Vectors are always Candid-encodable"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Self(candid::decode_one(&bytes).expect("Cold vectors are only written by this store"))
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+struct HotVector {
+    vector: Vector,
+    // Clock tick of the last write or search hit
+    last_access: AtomicU64,
+}
+
+/// Cold tier key; the NUL separator keeps one namespace's keys contiguous
+fn cold_key(namespace: &str, vector_id: &str) -> String {
+    format!("{}\u{0}{}", namespace, vector_id)
+}
+
+/// Vector store keeping recently used vectors on the heap and the rest in
+/// stable memory
+pub struct HybridVectorStore<M: Memory = DefaultMemoryImpl> {
+    hot: HashMap<String, HashMap<String, HotVector>>,
+    hot_len: usize,
+    cold: StableBTreeMap<String, ColdVector, M>,
+    // Vectors per namespace over both tiers
+    counts: BTreeMap<String, usize>,
+    max_hot: usize,
+    metric: DistanceMetric,
+    min_score: Option<f32>,
+    clock: AtomicU64,
+    // Cold vectors read since the last write, promoted by the next one
+    promotions: Mutex<HashSet<(String, String)>>,
+}
+
+impl<M: Memory> HybridVectorStore<M> {
+    /// Open a store over `memory`, keeping vectors already stored there
+    ///
+    /// Namespace counts are rebuilt by scanning the cold tier once.
+    pub fn new(memory: M) -> Self {
+        let cold: StableBTreeMap<String, ColdVector, M> = StableBTreeMap::init(memory);
+        let mut counts = BTreeMap::new();
+        for (key, _) in cold.iter() {
+            let namespace = key.split('\u{0}').next().unwrap_or_default();
+            *counts.entry(namespace.to_string()).or_insert(0) += 1;
+        }
+
+        Self {
+            hot: HashMap::new(),
+            hot_len: 0,
+            cold,
+            counts,
+            max_hot: DEFAULT_MAX_HOT_VECTORS,
+            metric: DistanceMetric::default(),
+            min_score: None,
+            clock: AtomicU64::new(0),
+            promotions: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Open a store using the configured hot tier size, distance metric and
+    /// minimum score
+    pub fn from_config(config: &VectorStoreConfig, memory: M) -> Self {
+        Self::new(memory)
+            .with_max_hot_vectors(config.max_hot_vectors.unwrap_or(DEFAULT_MAX_HOT_VECTORS))
+            .with_metric(config.distance_metric)
+            .with_min_score(config.min_score)
+    }
+
+    /// Keep at most `max_hot` vectors on the heap
+    pub fn with_max_hot_vectors(mut self, max_hot: usize) -> Self {
+        self.max_hot = max_hot;
+        self.demote_overflow();
+        self
+    }
+
+    /// Score search results with `metric` instead of cosine similarity
+    pub fn with_metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Drop search results scoring below `min_score` by default
+    pub fn with_min_score(mut self, min_score: Option<f32>) -> Self {
+        self.min_score = min_score;
+        self
+    }
+
+    pub fn metric(&self) -> DistanceMetric {
+        self.metric
+    }
+
+    /// Number of vectors on the heap
+    pub fn hot_len(&self) -> usize {
+        self.hot_len
+    }
+
+    /// Number of vectors in stable memory
+    pub fn cold_len(&self) -> usize {
+        self.cold.len() as usize
+    }
+
+    pub fn is_hot(&self, namespace: &str, vector_id: &str) -> bool {
+        self.hot.get(namespace).is_some_and(|v| v.contains_key(vector_id))
+    }
+
+    /// Demote every hot vector to stable memory
+    ///
+    /// Call this during pre_upgrade; the heap tier does not survive it.
+    pub fn persist(&mut self) {
+        for (namespace, vectors) in self.hot.drain() {
+            for (vector_id, hot) in vectors {
+                self.cold.insert(cold_key(&namespace, &vector_id), ColdVector(hot.vector));
+            }
+        }
+        self.hot_len = 0;
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn cold_vectors<'a>(&'a self, namespace: &str) -> impl Iterator<Item = Vector> + 'a {
+        let prefix = cold_key(namespace, "");
+        self.cold
+            .range(prefix.clone()..)
+            .take_while(move |(key, _)| key.starts_with(&prefix))
+            .map(|(_, ColdVector(vector))| vector)
+    }
+
+    fn dimensions(&self, namespace: &str) -> Option<usize> {
+        self.hot
+            .get(namespace)
+            .and_then(|v| v.values().next())
+            .map(|hot| hot.vector.embedding.len())
+            .or_else(|| self.cold_vectors(namespace).next().map(|v| v.embedding.len()))
+    }
+
+    fn insert_hot(&mut self, namespace: &str, vector: Vector, tick: u64) {
+        let hot = HotVector {
+            vector,
+            last_access: AtomicU64::new(tick),
+        };
+        let previous = self
+            .hot
+            .entry(namespace.to_string())
+            .or_default()
+            .insert(hot.vector.id.clone(), hot);
+        if previous.is_none() {
+            self.hot_len += 1;
+        }
+    }
+
+    /// Move cold vectors read since the last write onto the heap
+    fn promote_pending(&mut self) {
+        let pending = std::mem::take(self.promotions.get_mut().unwrap_or_else(PoisonError::into_inner));
+        if pending.is_empty() {
+            return;
+        }
+
+        let tick = self.tick();
+        for (namespace, vector_id) in pending {
+            if let Some(ColdVector(vector)) = self.cold.remove(&cold_key(&namespace, &vector_id)) {
+                self.insert_hot(&namespace, vector, tick);
+            }
+        }
+    }
+
+    /// Demote the least recently used hot vectors until the heap tier fits
+    fn demote_overflow(&mut self) {
+        if self.hot_len <= self.max_hot {
+            return;
+        }
+
+        let mut by_access: Vec<(u64, &str, &str)> = self
+            .hot
+            .iter()
+            .flat_map(|(namespace, vectors)| {
+                vectors
+                    .iter()
+                    .map(move |(id, hot)| (hot.last_access.load(Ordering::Relaxed), namespace.as_str(), id.as_str()))
+            })
+            .collect();
+        by_access.sort_unstable();
+        let victims: Vec<(String, String)> = by_access
+            .into_iter()
+            .take(self.hot_len - self.max_hot)
+            .map(|(_, namespace, id)| (namespace.to_string(), id.to_string()))
+            .collect();
+
+        for (namespace, vector_id) in victims {
+            if let Some(hot) = self.hot.get_mut(&namespace).and_then(|v| v.remove(&vector_id)) {
+                self.cold.insert(cold_key(&namespace, &vector_id), ColdVector(hot.vector));
+                self.hot_len -= 1;
+            }
+        }
+        self.hot.retain(|_, vectors| !vectors.is_empty());
+    }
+
+    /// Remove a vector from whichever tier holds it
+    fn remove(&mut self, namespace: &str, vector_id: &str) -> bool {
+        let removed = match self.hot.get_mut(namespace).and_then(|v| v.remove(vector_id)) {
+            Some(_) => {
+                self.hot_len -= 1;
+                true
+            }
+            None => self.cold.remove(&cold_key(namespace, vector_id)).is_some(),
+        };
+
+        if removed {
+            if let Some(count) = self.counts.get_mut(namespace) {
+                *count -= 1;
+                if *count == 0 {
+                    self.counts.remove(namespace);
+                }
+            }
+        }
+        removed
+    }
+
+    fn request_promotion(&self, namespace: &str, vector_id: &str) {
+        self.promotions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert((namespace.to_string(), vector_id.to_string()));
+    }
+}
+
+impl Default for HybridVectorStore {
+    fn default() -> Self {
+        Self::new(DefaultMemoryImpl::default())
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Memory + Send + Sync> VectorStore for HybridVectorStore<M> {
+    /// Demote the hot tier to the store's own memory; `memory` is unused
+    fn persist_state(&mut self, _memory: &dyn Memory) -> Result<()> {
+        self.persist();
+        Ok(())
+    }
+
+    async fn store(&mut self, namespace: &str, vector: Vector) -> Result<()> {
+        self.store_batch_with(namespace, vec![vector], BatchMode::AllOrNothing)
+            .await
+            .map(|_| ())
+    }
+
+    /// New and rewritten vectors always land in the hot tier
+    async fn store_batch_with(
+        &mut self,
+        namespace: &str,
+        vectors: Vec<Vector>,
+        mode: BatchMode,
+    ) -> Result<BatchWriteReport> {
+        monitoring::ensure_writable()?;
+        self.promote_pending();
+
+        let mut dimensions = self.dimensions(namespace);
+        let mut report = BatchWriteReport::default();
+        let mut accepted = Vec::with_capacity(vectors.len());
+        for vector in vectors {
+            match validate(&vector, dimensions) {
+                Ok(()) => {
+                    dimensions = Some(vector.embedding.len());
+                    report.push(vector.id.clone(), None);
+                    accepted.push(vector);
+                }
+                Err(e) if mode == BatchMode::BestEffort => report.push(vector.id, Some(e.to_string())),
+                Err(e) => {
+                    return Err(ContragError::VectorStoreError(format!(
+                        "Batch rejected, vector {} is invalid: {}",
+                        vector.id, e
+                    )));
+                }
+            }
+        }
+
+        let written = self.tick();
+        for vector in accepted {
+            let replaced = self.cold.remove(&cold_key(namespace, &vector.id)).is_some()
+                || self.is_hot(namespace, &vector.id);
+            if !replaced {
+                *self.counts.entry(namespace.to_string()).or_insert(0) += 1;
+            }
+            self.insert_hot(namespace, vector, written);
+        }
+        self.demote_overflow();
+
+        Ok(report)
+    }
+
+    async fn search(
+        &self,
+        namespace: &str,
+        query_embedding: Vec<f32>,
+        k: usize,
+    ) -> Result<Vec<SearchResult>> {
+        self.search_with_min_score(namespace, query_embedding, k, None).await
+    }
+
+    /// Scans both tiers; cold vectors in the results are promoted by the
+    /// next write
+    async fn search_with_min_score(
+        &self,
+        namespace: &str,
+        query_embedding: Vec<f32>,
+        k: usize,
+        min_score: Option<f32>,
+    ) -> Result<Vec<SearchResult>> {
+        let min_score = min_score.or(self.min_score);
+        let mut cold: Vec<(f32, Vector)> = self
+            .cold_vectors(namespace)
+            .map(|v| (similarity(self.metric, &query_embedding, &v.embedding), v))
+            .collect();
+        cold.sort_by(|a, b| b.0.total_cmp(&a.0));
+        cold.truncate(k);
+
+        let mut ranked: Vec<(f32, &Vector, Option<&AtomicU64>)> = self
+            .hot
+            .get(namespace)
+            .into_iter()
+            .flat_map(|vectors| vectors.values())
+            .map(|hot| {
+                let score = similarity(self.metric, &query_embedding, &hot.vector.embedding);
+                (score, &hot.vector, Some(&hot.last_access))
+            })
+            .chain(cold.iter().map(|(score, vector)| (*score, vector, None)))
+            .collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+        ranked.truncate(k);
+        if let Some(min_score) = min_score {
+            ranked.retain(|(score, _, _)| *score >= min_score);
+        }
+
+        let hit = self.tick();
+        Ok(ranked
+            .into_iter()
+            .map(|(score, vector, last_access)| {
+                match last_access {
+                    Some(last_access) => last_access.store(hit, Ordering::Relaxed),
+                    None => self.request_promotion(namespace, &vector.id),
+                }
+                SearchResult {
+                    vector_id: vector.id.clone(),
+                    text: vector.text.clone(),
+                    score,
+                    metadata: vector.metadata.clone(),
+                }
+            })
+            .collect())
+    }
+
+    async fn get(&self, namespace: &str, vector_id: &str) -> Result<Option<Vector>> {
+        if let Some(hot) = self.hot.get(namespace).and_then(|v| v.get(vector_id)) {
+            hot.last_access.store(self.tick(), Ordering::Relaxed);
+            return Ok(Some(hot.vector.clone()));
+        }
+
+        let cold = self.cold.get(&cold_key(namespace, vector_id)).map(|ColdVector(vector)| vector);
+        if cold.is_some() {
+            self.request_promotion(namespace, vector_id);
+        }
+        Ok(cold)
+    }
+
+    async fn delete(&mut self, namespace: &str, vector_id: &str) -> Result<()> {
+        self.remove(namespace, vector_id);
+        Ok(())
+    }
+
+    async fn delete_namespace(&mut self, namespace: &str) -> Result<()> {
+        if let Some(vectors) = self.hot.remove(namespace) {
+            self.hot_len -= vectors.len();
+        }
+        let keys: Vec<String> = self
+            .cold_vectors(namespace)
+            .map(|vector| cold_key(namespace, &vector.id))
+            .collect();
+        for key in &keys {
+            self.cold.remove(key);
+        }
+        self.counts.remove(namespace);
+
+        Ok(())
+    }
+
+    async fn count(&self, namespace: &str) -> Result<usize> {
+        Ok(self.counts.get(namespace).copied().unwrap_or(0))
+    }
+
+    async fn list_namespaces(&self) -> Result<Vec<String>> {
+        Ok(self.counts.keys().cloned().collect())
+    }
+
+    async fn export_namespace(&self, namespace: &str) -> Result<Vec<Vector>> {
+        let mut vectors: Vec<Vector> = self
+            .hot
+            .get(namespace)
+            .into_iter()
+            .flat_map(|vectors| vectors.values().map(|hot| hot.vector.clone()))
+            .chain(self.cold_vectors(namespace))
+            .collect();
+        vectors.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(vectors)
+    }
+
+    /// Pages in ID order, like `export_namespace`, merging the tiers without
+    /// copying the vectors before `offset`
+    async fn list_vectors(&self, namespace: &str, offset: usize, limit: usize) -> Result<Vec<Vector>> {
+        let mut hot: Vec<&Vector> = self
+            .hot
+            .get(namespace)
+            .into_iter()
+            .flat_map(|vectors| vectors.values().map(|hot| &hot.vector))
+            .collect();
+        hot.sort_by(|a, b| a.id.cmp(&b.id));
+        let mut hot = hot.into_iter().peekable();
+        let mut cold = self.cold_vectors(namespace).peekable();
+
+        let merged = std::iter::from_fn(|| match (hot.peek(), cold.peek()) {
+            (Some(h), Some(c)) if h.id <= c.id => hot.next().map(Cow::Borrowed),
+            (Some(_), None) => hot.next().map(Cow::Borrowed),
+            _ => cold.next().map(Cow::Owned),
+        });
+        Ok(merged.skip(offset).take(limit).map(Cow::into_owned).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use ic_stable_structures::WASM_PAGE_SIZE;
+    use crate::types::test_vector;
+
+    /// Heap memory that, unlike the native `DefaultMemoryImpl`, is `Sync`
+    #[derive(Clone, Default)]
+    struct SharedMemory(Arc<Mutex<Vec<u8>>>);
+
+    impl Memory for SharedMemory {
+        fn size(&self) -> u64 {
+            self.0.lock().unwrap().len() as u64 / WASM_PAGE_SIZE
+        }
+
+        fn grow(&self, pages: u64) -> i64 {
+            let mut bytes = self.0.lock().unwrap();
+            let previous = bytes.len() as u64 / WASM_PAGE_SIZE;
+            bytes.resize(((previous + pages) * WASM_PAGE_SIZE) as usize, 0);
+            previous as i64
+        }
+
+        fn read(&self, offset: u64, dst: &mut [u8]) {
+            let offset = offset as usize;
+            dst.copy_from_slice(&self.0.lock().unwrap()[offset..offset + dst.len()]);
+        }
+
+        fn write(&self, offset: u64, src: &[u8]) {
+            let offset = offset as usize;
+            self.0.lock().unwrap()[offset..offset + src.len()].copy_from_slice(src);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_demotes_and_promotes_between_tiers() {
+        let memory = SharedMemory::default();
+        let mut store = HybridVectorStore::new(memory.clone()).with_max_hot_vectors(2);
+        for (i, id) in ["a", "b", "c", "d"].iter().enumerate() {
+            store.store("docs", test_vector(id, vec![1.0, i as f32])).await.unwrap();
+        }
+
+        // The two oldest writes went cold but are still found
+        assert_eq!((store.hot_len(), store.cold_len()), (2, 2));
+        assert!(!store.is_hot("docs", "a") && store.is_hot("docs", "d"));
+        assert_eq!(store.count("docs").await.unwrap(), 4);
+        let results = store.search("docs", vec![1.0, 0.0], 1).await.unwrap();
+        assert_eq!(results[0].vector_id, "a");
+
+        // The cold hit is promoted by the next write, demoting the LRU vectors
+        store.store("docs", test_vector("e", vec![1.0, 4.0])).await.unwrap();
+        assert!(store.is_hot("docs", "a") && store.is_hot("docs", "e"));
+        assert_eq!((store.hot_len(), store.cold_len()), (2, 3));
+
+        store.delete("docs", "b").await.unwrap();
+        store.store("docs", test_vector("c", vec![1.0, 2.0])).await.unwrap();
+        assert_eq!(store.count("docs").await.unwrap(), 4);
+        assert_eq!(store.export_namespace("docs").await.unwrap().len(), 4);
+
+        // An upgrade keeps everything that was persisted to stable memory
+        store.persist();
+        drop(store);
+        let reopened = HybridVectorStore::new(memory);
+        assert_eq!(reopened.list_namespaces().await.unwrap(), ["docs"]);
+        assert_eq!(reopened.count("docs").await.unwrap(), 4);
+        assert!(reopened.get("docs", "e").await.unwrap().is_some());
+    }
+}
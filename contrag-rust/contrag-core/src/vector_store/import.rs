@@ -0,0 +1,473 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::error::{ContragError, Result};
+use crate::types::{BatchMode, BatchWriteReport, DuplicateIdMode, Vector, VectorMetadata};
+use crate::utils::{generate_vector_id, get_timestamp};
+use crate::vector_store::{write_planned, PlannedWrite, VectorStore};
+
+/// Errors kept per import session; later ones are only counted
+const MAX_IMPORT_ERRORS: usize = 50;
+
+/// Default number of vectors written per batch by [`import_vectors`]
+pub const DEFAULT_IMPORT_BATCH: usize = 500;
+
+/// Keys recognized for each field, across LangChain, LlamaIndex and contrag exports
+const ID_KEYS: [&str; 5] = ["id", "id_", "node_id", "doc_id", "vector_id"];
+const TEXT_KEYS: [&str; 4] = ["text", "page_content", "content", "document"];
+const EMBEDDING_KEYS: [&str; 3] = ["embedding", "vector", "values"];
+
+/// How exported records are mapped onto contrag vectors
+///
+/// Works for LangChain (`page_content`, `metadata`), LlamaIndex (`id_`,
+/// `text`, `metadata`) and plain `{text, vector, metadata}` JSONL. Metadata
+/// fields not consumed by the mapping are kept as custom metadata.
+#[derive(Clone, Debug, Serialize, Deserialize, CandidType)]
+pub struct ImportMapping {
+    /// Namespace of each record; `{field}` placeholders are filled from the
+    /// record's metadata plus `entity_type` and `entity_id`
+    pub namespace_template: String,
+    /// Metadata field holding the entity type
+    pub entity_type_field: Option<String>,
+    /// Entity type for records without one
+    pub default_entity_type: String,
+    /// Metadata field holding the entity ID; the record ID is used otherwise
+    pub entity_id_field: Option<String>,
+    /// Metadata field holding the chunk index; records without one are
+    /// numbered in the order they arrive per entity
+    pub chunk_index_field: Option<String>,
+    /// Embedding dimensions every record must have; `None` accepts any
+    pub dimensions: Option<usize>,
+}
+
+impl Default for ImportMapping {
+    fn default() -> Self {
+        Self {
+            namespace_template: "{entity_type}:{entity_id}".to_string(),
+            entity_type_field: None,
+            default_entity_type: "Document".to_string(),
+            entity_id_field: None,
+            chunk_index_field: None,
+            dimensions: None,
+        }
+    }
+}
+
+/// Running totals of an import session
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct ImportProgress {
+    pub records: u64,
+    pub imported: u64,
+    pub failed: u64,
+    /// First errors as `line N: reason`
+    pub errors: Vec<String>,
+    pub finished: bool,
+}
+
+/// Map one exported JSON record onto a namespace and vector
+///
+/// A record without a chunk index gets the next index of its entity in
+/// `next_chunk`, so chunks of an entity exported without IDs don't collide;
+/// pass the same map for every record of an import. `total_chunks` is taken
+/// from the record and is 0 (unknown) when it has none.
+pub fn parse_record(
+    line: &str,
+    mapping: &ImportMapping,
+    now: u64,
+    next_chunk: &mut HashMap<(String, String), usize>,
+) -> Result<(String, Vector)> {
+    let record: Map<String, Value> = serde_json::from_str(line)?;
+    let mut metadata = match record.get("metadata") {
+        Some(Value::Object(metadata)) => metadata.clone(),
+        _ => Map::new(),
+    };
+
+    let text = first_string(&record, &TEXT_KEYS)
+        .ok_or_else(|| ContragError::SerializationError("Record has no text".to_string()))?;
+    let embedding = first_embedding(&record)?;
+    if let Some(expected) = mapping.dimensions {
+        if embedding.len() != expected {
+            return Err(ContragError::DimensionMismatch { expected, actual: embedding.len() });
+        }
+    }
+
+    let record_id = first_string(&record, &ID_KEYS);
+    // contrag exports carry the entity fields at the top level
+    let entity_type = take_field(&mut metadata, mapping.entity_type_field.as_deref())
+        .or_else(|| first_string(&record, &["entity_type"]))
+        .unwrap_or_else(|| mapping.default_entity_type.clone());
+    let entity_id = take_field(&mut metadata, mapping.entity_id_field.as_deref())
+        .or_else(|| first_string(&record, &["entity_id"]))
+        .or_else(|| record_id.clone())
+        .ok_or_else(|| ContragError::SerializationError("Record has no entity ID".to_string()))?;
+    let chunk_index = take_field(&mut metadata, mapping.chunk_index_field.as_deref())
+        .or_else(|| first_string(&record, &["chunk_index"]))
+        .map(|index| {
+            index
+                .parse()
+                .map_err(|_| ContragError::SerializationError(format!("Invalid chunk index '{}'", index)))
+        })
+        .transpose()?;
+    let total_chunks = first_string(&record, &["total_chunks"])
+        .and_then(|total| total.parse().ok())
+        .unwrap_or(0);
+
+    let namespace = fill_template(&mapping.namespace_template, &entity_type, &entity_id, &metadata)?;
+    let next = next_chunk.entry((entity_type.clone(), entity_id.clone())).or_insert(0);
+    let chunk_index = chunk_index.unwrap_or(*next);
+    *next = (*next).max(chunk_index + 1);
+    let id = record_id.unwrap_or_else(|| generate_vector_id(&entity_type, &entity_id, chunk_index));
+    let custom = (!metadata.is_empty()).then(|| Value::Object(metadata).to_string());
+
+    Ok((
+        namespace,
+        Vector {
+            id,
+            embedding,
+            text,
+            metadata: VectorMetadata {
+                entity_type,
+                entity_id,
+                chunk_index,
+                total_chunks,
+                timestamp: now,
+                custom,
+                ttl_seconds: record.get("ttl_seconds").and_then(Value::as_u64),
+            },
+        },
+    ))
+}
+
+fn first_string(record: &Map<String, Value>, keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|key| match record.get(*key) {
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(Value::Number(n)) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+fn first_embedding(record: &Map<String, Value>) -> Result<Vec<f32>> {
+    let values = EMBEDDING_KEYS
+        .iter()
+        .find_map(|key| record.get(*key).and_then(Value::as_array))
+        .ok_or_else(|| ContragError::SerializationError("Record has no embedding".to_string()))?;
+
+    values
+        .iter()
+        .map(|v| {
+            v.as_f64()
+                .map(|f| f as f32)
+                .ok_or_else(|| ContragError::SerializationError("Embedding has a non-numeric value".to_string()))
+        })
+        .collect()
+}
+
+/// Remove a metadata field and return it as a string
+fn take_field(metadata: &mut Map<String, Value>, field: Option<&str>) -> Option<String> {
+    match metadata.remove(field?)? {
+        Value::String(s) => Some(s),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+/// Fill the `{field}` placeholders of `template`, failing on a field the
+/// record doesn't have rather than leaving it in the namespace
+fn fill_template(template: &str, entity_type: &str, entity_id: &str, metadata: &Map<String, Value>) -> Result<String> {
+    let mut namespace = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        namespace.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(|| {
+            ContragError::InvalidConfig(format!("Unclosed placeholder in namespace template '{}'", template))
+        })?;
+        let field = &rest[start + 1..start + end];
+        let value = match field {
+            "entity_type" => entity_type.to_string(),
+            "entity_id" => entity_id.to_string(),
+            _ => match metadata.get(field) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Null) | None => {
+                    return Err(ContragError::SerializationError(format!(
+                        "Record has no '{}' for the namespace template",
+                        field
+                    )))
+                }
+                Some(other) => other.to_string(),
+            },
+        };
+        namespace.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    namespace.push_str(rest);
+
+    Ok(namespace)
+}
+
+/// Chunked import of an exported JSONL dataset
+///
+/// Feed the file in pieces of any size, e.g. one per call of a canister
+/// update endpoint so each call stays within the message size limit; a
+/// record split across two pieces is buffered until its line is complete.
+/// Records are stored as they arrive without re-embedding, best effort, so
+/// one bad record does not abort the import.
+pub struct ImportSession {
+    mapping: ImportMapping,
+    /// Next chunk index of each entity, for records without one
+    next_chunk: HashMap<(String, String), usize>,
+    buffer: String,
+    line: u64,
+    progress: ImportProgress,
+}
+
+impl ImportSession {
+    pub fn new(mapping: ImportMapping) -> Self {
+        Self {
+            mapping,
+            next_chunk: HashMap::new(),
+            buffer: String::new(),
+            line: 0,
+            progress: ImportProgress::default(),
+        }
+    }
+
+    pub fn progress(&self) -> &ImportProgress {
+        &self.progress
+    }
+
+    /// Import the complete lines of `chunk`, keeping a trailing partial line
+    pub async fn push_chunk<S: VectorStore + ?Sized>(&mut self, store: &mut S, chunk: &str) -> Result<ImportProgress> {
+        self.buffer.push_str(chunk);
+        let complete = match self.buffer.rfind('\n') {
+            Some(end) => {
+                let rest = self.buffer.split_off(end + 1);
+                std::mem::replace(&mut self.buffer, rest)
+            }
+            None => return Ok(self.progress.clone()),
+        };
+
+        self.import_lines(store, &complete).await?;
+        Ok(self.progress.clone())
+    }
+
+    /// Import whatever is left in the buffer and mark the session finished
+    pub async fn finish<S: VectorStore + ?Sized>(&mut self, store: &mut S) -> Result<ImportProgress> {
+        let rest = std::mem::take(&mut self.buffer);
+        self.import_lines(store, &rest).await?;
+        self.progress.finished = true;
+        Ok(self.progress.clone())
+    }
+
+    async fn import_lines<S: VectorStore + ?Sized>(&mut self, store: &mut S, lines: &str) -> Result<()> {
+        let now = get_timestamp();
+        let mut by_namespace: BTreeMap<String, Vec<(u64, Vector)>> = BTreeMap::new();
+
+        for line in lines.lines() {
+            self.line += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            self.progress.records += 1;
+
+            match parse_record(line, &self.mapping, now, &mut self.next_chunk) {
+                Ok((namespace, vector)) => by_namespace.entry(namespace).or_default().push((self.line, vector)),
+                Err(e) => self.fail(self.line, &e.to_string()),
+            }
+        }
+
+        for (namespace, records) in by_namespace {
+            let (lines, vectors): (Vec<u64>, Vec<Vector>) = records.into_iter().unzip();
+            let report = store
+                .store_batch_with(&namespace, vectors, BatchMode::BestEffort)
+                .await?;
+
+            self.progress.imported += report.stored as u64;
+            for (line, result) in lines.into_iter().zip(report.results) {
+                if let Some(error) = result.error {
+                    self.fail(line, &error);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fail(&mut self, line: u64, error: &str) {
+        self.progress.failed += 1;
+        if self.progress.errors.len() < MAX_IMPORT_ERRORS {
+            self.progress.errors.push(format!("line {}: {}", line, error));
+        }
+    }
+}
+
+/// Store precomputed vectors in `namespace` without re-embedding them
+///
+/// Vectors are written `batch_size` at a time (see [`DEFAULT_IMPORT_BATCH`]),
+/// best effort, and reported one result per input vector in input order. A
+/// vector whose ID is already stored is skipped under
+/// [`DuplicateIdMode::Skip`] and replaces the stored one under
+/// [`DuplicateIdMode::Replace`], which is put back if the replacement fails.
+/// Repeats of an ID within `vectors` are always skipped. Endpoints call this
+/// once per update call with
+/// [`ImportVectorsRequest`](crate::api::ImportVectorsRequest)s small enough
+/// for one message.
+pub async fn import_vectors<S: VectorStore + ?Sized>(
+    store: &mut S,
+    namespace: &str,
+    vectors: Vec<Vector>,
+    on_duplicate: DuplicateIdMode,
+    batch_size: usize,
+) -> Result<BatchWriteReport> {
+    if batch_size == 0 {
+        return Err(ContragError::InvalidConfig(
+            "Import batch size must be greater than 0".to_string(),
+        ));
+    }
+
+    let mut report = BatchWriteReport::default();
+    let mut seen = HashSet::new();
+    let mut vectors = vectors.into_iter().peekable();
+    while vectors.peek().is_some() {
+        let batch: Vec<Vector> = vectors.by_ref().take(batch_size).collect();
+        let ids: Vec<String> = batch.iter().map(|v| v.id.clone()).collect();
+        let mut stored: HashMap<String, Vector> = store
+            .get_many(namespace, &ids)
+            .await?
+            .into_iter()
+            .map(|v| (v.id.clone(), v))
+            .collect();
+
+        let mut plan = Vec::with_capacity(batch.len());
+        let mut writes = vec![];
+        let mut replaced = vec![];
+        for vector in batch {
+            if !seen.insert(vector.id.clone()) {
+                plan.push(PlannedWrite::skip(vector.id.clone(), vector.id));
+                continue;
+            }
+            match stored.remove(&vector.id) {
+                Some(_) if on_duplicate == DuplicateIdMode::Skip => {
+                    plan.push(PlannedWrite::skip(vector.id.clone(), vector.id));
+                    continue;
+                }
+                Some(existing) => {
+                    plan.push(PlannedWrite::replace(vector.id.clone(), existing.id.clone()));
+                    replaced.push(existing);
+                }
+                None => plan.push(PlannedWrite::write(vector.id.clone())),
+            }
+            writes.push(vector);
+        }
+
+        report.merge(write_planned(store, namespace, plan, writes, replaced, BatchMode::BestEffort).await?);
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::test_vector;
+    use crate::vector_store::stable_memory_store::StableMemoryVectorStore;
+
+    #[tokio::test]
+    async fn test_chunked_import_of_common_schemas() {
+        let langchain = r#"{"page_content":"Refunds take 5 days","embedding":[0.1,0.2],"metadata":{"source":"faq","doc":"refunds"}}"#;
+        let llamaindex = r#"{"id_":"node-1","text":"Shipping is free","embedding":[0.3,0.4],"metadata":{"source":"faq","doc":"shipping"}}"#;
+        let broken = r#"{"text":"no vector","metadata":{"doc":"x"}}"#;
+        let data = format!("{}\n{}\n{}\n", langchain, llamaindex, broken);
+
+        let mapping = ImportMapping {
+            namespace_template: "docs:{source}".to_string(),
+            entity_id_field: Some("doc".to_string()),
+            dimensions: Some(2),
+            ..ImportMapping::default()
+        };
+        let mut session = ImportSession::new(mapping);
+        let mut store = StableMemoryVectorStore::new();
+
+        // Split inside the second record
+        let (first, second) = data.split_at(langchain.len() + 20);
+        session.push_chunk(&mut store, first).await.unwrap();
+        assert_eq!(session.progress().records, 1);
+        session.push_chunk(&mut store, second).await.unwrap();
+        let progress = session.finish(&mut store).await.unwrap();
+
+        assert_eq!((progress.records, progress.imported, progress.failed), (3, 2, 1));
+        assert!(progress.errors[0].starts_with("line 3"));
+
+        let vectors = store.export_namespace("docs:faq").await.unwrap();
+        assert_eq!(vectors.len(), 2);
+        assert_eq!(vectors[0].metadata.entity_id, "refunds");
+        assert_eq!(vectors[1].id, "node-1");
+        assert_eq!(vectors[1].metadata.custom.as_deref(), Some(r#"{"source":"faq"}"#));
+    }
+
+    #[test]
+    fn test_records_without_ids_are_numbered_per_entity() {
+        let mapping = ImportMapping {
+            entity_id_field: Some("doc".to_string()),
+            ..ImportMapping::default()
+        };
+        let mut next_chunk = HashMap::new();
+        let mut parse = |line: &str| parse_record(line, &mapping, 0, &mut next_chunk).map(|(_, v)| v);
+
+        let first = parse(r#"{"text":"a","embedding":[1.0],"metadata":{"doc":"x"}}"#).unwrap();
+        let second = parse(r#"{"text":"b","embedding":[1.0],"metadata":{"doc":"x"}}"#).unwrap();
+        let other = parse(r#"{"text":"c","embedding":[1.0],"metadata":{"doc":"y"}}"#).unwrap();
+        assert_eq!((first.metadata.chunk_index, second.metadata.chunk_index), (0, 1));
+        assert_ne!(first.id, second.id);
+        assert_eq!(other.metadata.chunk_index, 0);
+        assert_eq!(first.metadata.total_chunks, 0);
+    }
+
+    #[test]
+    fn test_unresolved_placeholders_are_rejected() {
+        let mapping = ImportMapping {
+            namespace_template: "docs:{source}".to_string(),
+            ..ImportMapping::default()
+        };
+        let line = r#"{"id":"1","text":"a","embedding":[1.0],"metadata":{"lang":"en"}}"#;
+        assert!(parse_record(line, &mapping, 0, &mut HashMap::new()).is_err());
+
+        let line = r#"{"id":"1","text":"a","embedding":[1.0],"metadata":{"source":"faq"}}"#;
+        let (namespace, _) = parse_record(line, &mapping, 0, &mut HashMap::new()).unwrap();
+        assert_eq!(namespace, "docs:faq");
+    }
+
+    fn precomputed(id: &str, embedding: Vec<f32>) -> Vector {
+        Vector {
+            text: format!("text of {}", id),
+            ..test_vector(id, embedding)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_import_vectors_in_batches() {
+        let mut store = StableMemoryVectorStore::new();
+        store.store("docs", precomputed("a", vec![1.0, 0.0])).await.unwrap();
+
+        let vectors = vec![
+            precomputed("a", vec![0.0, 1.0]),
+            precomputed("b", vec![1.0, 1.0]),
+            precomputed("b", vec![0.5, 0.5]),
+            precomputed("c", vec![1.0]),
+            precomputed("d", vec![0.0, 1.0]),
+        ];
+        let report = import_vectors(&mut store, "docs", vectors.clone(), DuplicateIdMode::Skip, 2).await.unwrap();
+        assert_eq!((report.stored, report.skipped, report.failed), (2, 2, 1));
+        let ids: Vec<&str> = report.results.iter().map(|r| r.vector_id.as_str()).collect();
+        assert_eq!(ids, ["a", "b", "b", "c", "d"]);
+        assert!(report.results[3].error.is_some());
+        assert_eq!(store.get("docs", "a").await.unwrap().unwrap().embedding, vec![1.0, 0.0]);
+
+        let report = import_vectors(&mut store, "docs", vectors, DuplicateIdMode::Replace, 10).await.unwrap();
+        assert_eq!((report.stored, report.skipped, report.failed), (3, 1, 1));
+        assert_eq!(report.results[0].duplicate_of.as_deref(), Some("a"));
+        assert_eq!(store.get("docs", "a").await.unwrap().unwrap().embedding, vec![0.0, 1.0]);
+        assert_eq!(store.count("docs").await.unwrap(), 3);
+    }
+}
@@ -0,0 +1,713 @@
+//! IVF (inverted file) approximate nearest neighbor index
+//!
+//! A lighter alternative to [`hnsw`](crate::vector_store::hnsw): each
+//! namespace is clustered by k-means into [`IvfConfig::lists`] lists, and a
+//! search only scores the vectors of the [`IvfConfig::nprobe`] lists whose
+//! centroids are closest to the query under the store's distance metric.
+//! Inserting costs one pass over the centroids instead of a graph update.
+//!
+//! Centroids are trained by [`IvfVectorStore::rebuild_step`], a bounded
+//! amount of work per call meant for a timer or the `maintain_index`
+//! endpoint (see [`VectorStore::maintain`]); until a namespace has been
+//! trained, and while it is smaller than [`IvfConfig::exact_search_below`],
+//! it is scanned exactly. New vectors join the list of their closest
+//! centroid, and [`IvfVectorStore::stale_namespaces`] reports namespaces
+//! that changed enough since their last training to be worth retraining.
+//!
+//! Like the HNSW store, the lists and centroids are written to stable
+//! memory with [`IvfVectorStore::persist`] and loaded back without
+//! retraining by [`IvfVectorStore::init`].
+
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Bound;
+use candid::CandidType;
+use ic_stable_structures::Memory;
+use serde::{Deserialize, Serialize};
+use crate::config::{DistanceMetric, IvfConfig};
+use crate::error::{ContragError, Result};
+use crate::monitoring;
+use crate::storage::memory::{read_blob, write_blob};
+use crate::storage::migrations::encode_versioned;
+use crate::storage::{Migrator, StorageComponent};
+use crate::types::{SearchResult, Vector};
+use crate::vector_store::{l2_normalize, retain_min_score, similarity, VectorStore};
+
+/// Clustered vectors of one namespace
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct IvfIndex {
+    vectors: BTreeMap<String, Vector>,
+    /// Cluster centers, unit-length unless the metric is euclidean; empty
+    /// until trained
+    centroids: Vec<Vec<f32>>,
+    /// Vector IDs per centroid
+    lists: Vec<Vec<String>>,
+    /// Inserts and deletes since the centroids were trained
+    changes: usize,
+}
+
+impl IvfIndex {
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty()
+    }
+
+    /// Embedding dimensions of the indexed vectors
+    pub fn dimensions(&self) -> Option<usize> {
+        self.vectors.values().next().map(|v| v.embedding.len())
+    }
+
+    /// Vectors in ID order
+    pub fn live(&self) -> impl Iterator<Item = &Vector> {
+        self.vectors.values()
+    }
+
+    pub fn get(&self, vector_id: &str) -> Option<&Vector> {
+        self.vectors.get(vector_id)
+    }
+
+    pub fn is_trained(&self) -> bool {
+        !self.centroids.is_empty()
+    }
+
+    /// Whether a rebuild would pay off: the namespace outgrew exact search
+    /// without being trained, or half of it changed since training
+    pub fn needs_rebuild(&self, config: &IvfConfig) -> bool {
+        if !self.is_trained() {
+            return self.len() >= config.exact_search_below.max(1);
+        }
+        self.changes > self.len() / 2
+    }
+
+    /// Insert a vector, replacing any vector with the same ID
+    pub fn insert(&mut self, vector: Vector, metric: DistanceMetric) {
+        self.remove(&vector.id, metric);
+
+        if let Some(list) = nearest(&self.centroids, &vector.embedding, metric) {
+            self.lists[list].push(vector.id.clone());
+        }
+        self.vectors.insert(vector.id.clone(), vector);
+        self.changes += 1;
+    }
+
+    /// Delete a vector by ID; returns whether it was present
+    pub fn remove(&mut self, vector_id: &str, metric: DistanceMetric) -> bool {
+        let Some(vector) = self.vectors.remove(vector_id) else {
+            return false;
+        };
+
+        if let Some(list) = nearest(&self.centroids, &vector.embedding, metric) {
+            self.lists[list].retain(|id| id != vector_id);
+        }
+        self.changes += 1;
+        true
+    }
+
+    /// The `k` vectors most similar to `query` among the probed lists
+    pub fn search(
+        &self,
+        query: &[f32],
+        k: usize,
+        config: &IvfConfig,
+        metric: DistanceMetric,
+    ) -> Vec<(f32, &Vector)> {
+        let candidates: Box<dyn Iterator<Item = &Vector>> =
+            if !self.is_trained() || self.len() < config.exact_search_below {
+                Box::new(self.vectors.values())
+            } else {
+                let mut probes: Vec<(f32, usize)> = self
+                    .centroids
+                    .iter()
+                    .enumerate()
+                    .map(|(list, centroid)| (similarity(metric, query, centroid), list))
+                    .collect();
+                probes.sort_by(|a, b| b.0.total_cmp(&a.0));
+                Box::new(
+                    probes
+                        .into_iter()
+                        .take(config.nprobe.max(1))
+                        .flat_map(|(_, list)| self.lists[list].iter())
+                        .filter_map(|id| self.vectors.get(id)),
+                )
+            };
+
+        let mut results: Vec<(f32, &Vector)> = candidates
+            .map(|v| (similarity(metric, query, &v.embedding), v))
+            .collect();
+        results.sort_by(|a, b| b.0.total_cmp(&a.0));
+        results.truncate(k);
+        results
+    }
+}
+
+/// Index of the centroid most similar to `embedding` under `metric`
+fn nearest(centroids: &[Vec<f32>], embedding: &[f32], metric: DistanceMetric) -> Option<usize> {
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(list, centroid)| (similarity(metric, embedding, centroid), list))
+        .max_by(|a, b| a.0.total_cmp(&b.0).then(b.1.cmp(&a.1)))
+        .map(|(_, list)| list)
+}
+
+/// Turn a vector into a centroid: cosine and dot product clustering is
+/// spherical, so their centroids are scaled to unit length
+fn centroid(mut vector: Vec<f32>, metric: DistanceMetric) -> Vec<f32> {
+    if metric != DistanceMetric::Euclidean {
+        l2_normalize(&mut vector);
+    }
+    vector
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Phase {
+    /// Picking spread-out starting centroids
+    Seed,
+    /// Running k-means iterations over the sample
+    Iterate,
+    /// Assigning every vector of the namespace to its closest centroid
+    Assign,
+}
+
+/// Centroids being trained for one namespace, a bounded number of vectors
+/// per step
+///
+/// The centroids are trained on a copy of a sample of the namespace, so
+/// writes during training don't disturb it. Centroids are seeded by
+/// farthest-point traversal of the sample in ID order rather than at
+/// random, so training is deterministic across replicas. Searches keep
+/// using the previous lists until every vector has been assigned.
+struct Training {
+    phase: Phase,
+    sample: Vec<Vec<f32>>,
+    centroids: Vec<Vec<f32>>,
+    /// Similarity of each sample to its closest centroid so far (seeding)
+    closest: Vec<f32>,
+    /// Sum and count of the samples closest to each centroid (k-means)
+    sums: Vec<(Vec<f32>, usize)>,
+    iteration: usize,
+    /// Next sample to process while seeding and iterating
+    cursor: usize,
+    /// Last vector assigned, in ID order
+    assigned_up_to: Option<String>,
+    lists: Vec<Vec<String>>,
+    /// Vectors written after their assignment, to assign again at the end
+    rewritten: HashSet<String>,
+    /// Changes of the index when training started
+    changes: usize,
+}
+
+impl Training {
+    fn start(index: &IvfIndex, config: &IvfConfig) -> Self {
+        let step = (index.len() / config.training_sample.max(1)).max(1);
+        let sample: Vec<Vec<f32>> = index
+            .vectors
+            .values()
+            .step_by(step)
+            .map(|v| v.embedding.clone())
+            .collect();
+
+        Self {
+            phase: Phase::Seed,
+            closest: vec![f32::NEG_INFINITY; sample.len()],
+            sample,
+            centroids: vec![],
+            sums: vec![],
+            iteration: 0,
+            cursor: 0,
+            assigned_up_to: None,
+            lists: vec![],
+            rewritten: HashSet::new(),
+            changes: index.changes,
+        }
+    }
+
+    /// Note a write of `vector_id`, which has to be assigned again if it
+    /// already was
+    fn rewrite(&mut self, vector_id: &str) {
+        let assigned = self.phase == Phase::Assign
+            && self.assigned_up_to.as_deref().is_some_and(|last| vector_id <= last);
+        if assigned {
+            self.rewritten.insert(vector_id.to_string());
+        }
+    }
+
+    /// Process up to `budget` vectors; returns the unused budget, or `None`
+    /// once every vector has been assigned
+    fn step(&mut self, index: &IvfIndex, config: &IvfConfig, metric: DistanceMetric, mut budget: usize) -> Option<usize> {
+        let k = config.lists.min(self.sample.len());
+        while budget > 0 {
+            match self.phase {
+                Phase::Seed if self.cursor == 0 && self.centroids.len() == k => {
+                    self.phase = Phase::Iterate;
+                }
+                Phase::Seed => {
+                    if self.cursor == 0 {
+                        // The first sample, then the one least similar to
+                        // every centroid chosen so far
+                        let next = self
+                            .closest
+                            .iter()
+                            .enumerate()
+                            .min_by(|a, b| a.1.total_cmp(b.1))
+                            .map(|(i, _)| i)
+                            .unwrap_or(0);
+                        self.centroids.push(centroid(self.sample[next].clone(), metric));
+                    }
+                    let newest = &self.centroids[self.centroids.len() - 1];
+                    let end = (self.cursor + budget).min(self.sample.len());
+                    for i in self.cursor..end {
+                        let score = similarity(metric, &self.sample[i], newest);
+                        self.closest[i] = self.closest[i].max(score);
+                    }
+                    budget -= end - self.cursor;
+                    self.cursor = if end == self.sample.len() { 0 } else { end };
+                }
+                Phase::Iterate if self.iteration == config.iterations => {
+                    self.lists = vec![vec![]; self.centroids.len()];
+                    self.phase = Phase::Assign;
+                }
+                Phase::Iterate => {
+                    if self.cursor == 0 {
+                        let dimensions = self.sample.first().map(Vec::len).unwrap_or(0);
+                        self.sums = vec![(vec![0.0; dimensions], 0); self.centroids.len()];
+                    }
+                    let end = (self.cursor + budget).min(self.sample.len());
+                    for embedding in &self.sample[self.cursor..end] {
+                        let Some(list) = nearest(&self.centroids, embedding, metric) else {
+                            continue;
+                        };
+                        let (sum, count) = &mut self.sums[list];
+                        for (sum, value) in sum.iter_mut().zip(embedding) {
+                            *sum += value;
+                        }
+                        *count += 1;
+                    }
+                    budget -= end - self.cursor;
+                    self.cursor = end;
+
+                    if self.cursor == self.sample.len() {
+                        // A cluster left empty keeps its previous centroid
+                        for (center, (sum, count)) in self.centroids.iter_mut().zip(std::mem::take(&mut self.sums)) {
+                            if count > 0 {
+                                *center = centroid(sum.iter().map(|x| x / count as f32).collect(), metric);
+                            }
+                        }
+                        self.iteration += 1;
+                        self.cursor = 0;
+                    }
+                }
+                Phase::Assign => {
+                    let start = match &self.assigned_up_to {
+                        Some(last) => Bound::Excluded(last.clone()),
+                        None => Bound::Unbounded,
+                    };
+                    let mut assigned = 0;
+                    for (id, vector) in index.vectors.range((start, Bound::Unbounded)).take(budget) {
+                        if let Some(list) = nearest(&self.centroids, &vector.embedding, metric) {
+                            self.lists[list].push(id.clone());
+                        }
+                        self.assigned_up_to = Some(id.clone());
+                        assigned += 1;
+                    }
+                    if assigned < budget {
+                        return None;
+                    }
+                    budget = 0;
+                }
+            }
+        }
+        Some(budget)
+    }
+
+    /// Replace the centroids and lists of `index` with the trained ones
+    fn finish(mut self, index: &mut IvfIndex, metric: DistanceMetric) {
+        if !self.rewritten.is_empty() {
+            for list in &mut self.lists {
+                list.retain(|id| !self.rewritten.contains(id));
+            }
+            for id in &self.rewritten {
+                let list = index
+                    .vectors
+                    .get(id)
+                    .and_then(|vector| nearest(&self.centroids, &vector.embedding, metric));
+                if let Some(list) = list {
+                    self.lists[list].push(id.clone());
+                }
+            }
+        }
+
+        index.centroids = self.centroids;
+        index.lists = self.lists;
+        index.changes = index.changes.saturating_sub(self.changes);
+    }
+}
+
+/// Vector store searching through one IVF index per namespace
+pub struct IvfVectorStore {
+    config: IvfConfig,
+    metric: DistanceMetric,
+    min_score: Option<f32>,
+    indexes: BTreeMap<String, IvfIndex>,
+    /// Trainings in progress
+    trainings: BTreeMap<String, Training>,
+}
+
+impl IvfVectorStore {
+    pub fn new(config: IvfConfig) -> Self {
+        Self {
+            config,
+            metric: DistanceMetric::default(),
+            min_score: None,
+            indexes: BTreeMap::new(),
+            trainings: BTreeMap::new(),
+        }
+    }
+
+    /// Cluster and rank vectors by `metric`
+    ///
+    /// Set it before storing vectors; lists trained under another metric
+    /// are only valid again after retraining.
+    pub fn with_metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Drop search results scoring below `min_score` by default
+    pub fn with_min_score(mut self, min_score: Option<f32>) -> Self {
+        self.min_score = min_score;
+        self
+    }
+
+    pub fn config(&self) -> &IvfConfig {
+        &self.config
+    }
+
+    fn search_scored(&self, namespace: &str, query_embedding: &[f32], k: usize, min_score: Option<f32>) -> Vec<SearchResult> {
+        let Some(index) = self.indexes.get(namespace) else {
+            return vec![];
+        };
+
+        let mut results: Vec<SearchResult> = index
+            .search(query_embedding, k, &self.config, self.metric)
+            .into_iter()
+            .map(|(score, v)| SearchResult {
+                vector_id: v.id.clone(),
+                text: v.text.clone(),
+                score,
+                metadata: v.metadata.clone(),
+            })
+            .collect();
+        retain_min_score(&mut results, min_score);
+        results
+    }
+
+    /// Namespaces for which [`IvfIndex::needs_rebuild`] holds
+    pub fn stale_namespaces(&self) -> Vec<String> {
+        self.indexes
+            .iter()
+            .filter(|(_, index)| index.needs_rebuild(&self.config))
+            .map(|(namespace, _)| namespace.clone())
+            .collect()
+    }
+
+    /// Train the centroids of stale namespaces, processing up to
+    /// `max_vectors` sampled or assigned vectors in all; returns whether no
+    /// training is left
+    ///
+    /// A namespace keeps its previous lists until its training has assigned
+    /// every vector, so calls can be spread over as many messages as
+    /// needed, e.g. from a periodic timer.
+    pub fn rebuild_step(&mut self, max_vectors: usize) -> bool {
+        let mut namespaces: Vec<String> = self.trainings.keys().cloned().collect();
+        namespaces.extend(
+            self.stale_namespaces()
+                .into_iter()
+                .filter(|namespace| !self.trainings.contains_key(namespace)),
+        );
+
+        let mut budget = max_vectors;
+        for namespace in namespaces {
+            let Some(index) = self.indexes.get_mut(&namespace) else {
+                self.trainings.remove(&namespace);
+                continue;
+            };
+            if budget == 0 {
+                return false;
+            }
+            let training = self
+                .trainings
+                .entry(namespace.clone())
+                .or_insert_with(|| Training::start(index, &self.config));
+
+            match training.step(index, &self.config, self.metric, budget) {
+                Some(left) => budget = left,
+                None => {
+                    if let Some(training) = self.trainings.remove(&namespace) {
+                        training.finish(index, self.metric);
+                    }
+                }
+            }
+        }
+        self.trainings.is_empty()
+    }
+
+    /// Open a store over `memory`, restoring the indexes [`Self::persist`]
+    /// last wrote there
+    pub fn with_memory(config: IvfConfig, memory: &(impl Memory + ?Sized)) -> Result<Self> {
+        let mut store = Self::new(config);
+        store.init(memory)?;
+        Ok(store)
+    }
+
+    /// Load the vectors, lists and centroids [`Self::persist`] wrote to
+    /// `memory` without retraining; empty memory loads nothing
+    ///
+    /// Call this during canister init or post_upgrade. A training that was
+    /// in progress starts over.
+    pub fn init(&mut self, memory: &(impl Memory + ?Sized)) -> Result<()> {
+        let Some(raw) = read_blob(memory)? else {
+            return Ok(());
+        };
+        let payload = Migrator::new().load(StorageComponent::IvfIndex, &raw)?;
+        self.indexes = candid::decode_one(&payload)
+            .map_err(|e| ContragError::StorageError(format!("Failed to decode IVF indexes: {}", e)))?;
+        self.trainings.clear();
+        Ok(())
+    }
+
+    /// Write the vectors, lists and centroids to `memory`
+    ///
+    /// Call this during pre_upgrade. `memory` must belong to this store
+    /// alone, e.g. the [`ContragMemory::Vectors`] region of the host's
+    /// memory manager.
+    ///
+    /// [`ContragMemory::Vectors`]: crate::storage::memory::ContragMemory::Vectors
+    pub fn persist(&self, memory: &(impl Memory + ?Sized)) -> Result<()> {
+        let payload = candid::encode_one(&self.indexes)
+            .map_err(|e| ContragError::StorageError(format!("Failed to encode IVF indexes: {}", e)))?;
+        write_blob(memory, &encode_versioned(StorageComponent::IvfIndex, &payload))
+    }
+
+    /// Tell a training of `namespace` in progress that `vector_id` changed
+    fn rewrite(&mut self, namespace: &str, vector_id: &str) {
+        if let Some(training) = self.trainings.get_mut(namespace) {
+            training.rewrite(vector_id);
+        }
+    }
+}
+
+impl Default for IvfVectorStore {
+    fn default() -> Self {
+        Self::new(IvfConfig::default())
+    }
+}
+
+#[async_trait::async_trait]
+impl VectorStore for IvfVectorStore {
+    async fn store(&mut self, namespace: &str, vector: Vector) -> Result<()> {
+        monitoring::ensure_writable()?;
+
+        if vector.embedding.is_empty() {
+            return Err(ContragError::VectorStoreError("Empty embedding".to_string()));
+        }
+        let index = self.indexes.entry(namespace.to_string()).or_default();
+        if let Some(expected) = index.dimensions() {
+            if expected != vector.embedding.len() {
+                return Err(ContragError::DimensionMismatch {
+                    expected,
+                    actual: vector.embedding.len(),
+                });
+            }
+        }
+
+        let vector_id = vector.id.clone();
+        index.insert(vector, self.metric);
+        self.rewrite(namespace, &vector_id);
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        namespace: &str,
+        query_embedding: Vec<f32>,
+        k: usize,
+    ) -> Result<Vec<SearchResult>> {
+        Ok(self.search_scored(namespace, &query_embedding, k, self.min_score))
+    }
+
+    async fn search_with_min_score(
+        &self,
+        namespace: &str,
+        query_embedding: Vec<f32>,
+        k: usize,
+        min_score: Option<f32>,
+    ) -> Result<Vec<SearchResult>> {
+        Ok(self.search_scored(namespace, &query_embedding, k, min_score.or(self.min_score)))
+    }
+
+    async fn delete(&mut self, namespace: &str, vector_id: &str) -> Result<()> {
+        if let Some(index) = self.indexes.get_mut(namespace) {
+            index.remove(vector_id, self.metric);
+            self.rewrite(namespace, vector_id);
+        }
+        Ok(())
+    }
+
+    async fn delete_namespace(&mut self, namespace: &str) -> Result<()> {
+        self.indexes.remove(namespace);
+        self.trainings.remove(namespace);
+        Ok(())
+    }
+
+    async fn sample(&self, namespace: &str, limit: usize) -> Result<Vec<Vector>> {
+        let index = match self.indexes.get(namespace) {
+            Some(index) if limit > 0 => index,
+            _ => return Ok(vec![]),
+        };
+
+        let step = (index.len() / limit).max(1);
+        Ok(index.live().step_by(step).take(limit).cloned().collect())
+    }
+
+    async fn count(&self, namespace: &str) -> Result<usize> {
+        Ok(self.indexes.get(namespace).map(IvfIndex::len).unwrap_or(0))
+    }
+
+    async fn list_namespaces(&self) -> Result<Vec<String>> {
+        Ok(self.indexes.keys().cloned().collect())
+    }
+
+    async fn get(&self, namespace: &str, vector_id: &str) -> Result<Option<Vector>> {
+        Ok(self
+            .indexes
+            .get(namespace)
+            .and_then(|index| index.get(vector_id))
+            .cloned())
+    }
+
+    async fn export_namespace(&self, namespace: &str) -> Result<Vec<Vector>> {
+        Ok(self
+            .indexes
+            .get(namespace)
+            .map(|index| index.live().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn list_vectors(&self, namespace: &str, offset: usize, limit: usize) -> Result<Vec<Vector>> {
+        Ok(self
+            .indexes
+            .get(namespace)
+            .map(|index| index.live().skip(offset).take(limit).cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn persist_state(&mut self, memory: &dyn Memory) -> Result<()> {
+        self.persist(memory)
+    }
+
+    fn restore_state(&mut self, memory: &dyn Memory) -> Result<()> {
+        self.init(memory)
+    }
+
+    fn maintain(&mut self, budget: usize) -> Result<bool> {
+        Ok(self.rebuild_step(budget))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_stable_structures::DefaultMemoryImpl;
+    use crate::types::test_vector;
+
+    fn vector(i: usize, embedding: Vec<f32>) -> Vector {
+        let mut vector = Vector {
+            text: format!("chunk {}", i),
+            ..test_vector(&format!("v{}", i), embedding)
+        };
+        vector.metadata.timestamp = i as u64;
+        vector
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_probes_closest_lists() {
+        let config = IvfConfig {
+            lists: 4,
+            nprobe: 1,
+            exact_search_below: 0,
+            ..IvfConfig::default()
+        };
+        let mut store = IvfVectorStore::new(config);
+
+        // Four tight clusters around the axes of the plane
+        let axes = [[1.0, 0.0], [0.0, 1.0], [-1.0, 0.0], [0.0, -1.0]];
+        for i in 0..40 {
+            let [x, y] = axes[i % 4];
+            let jitter = (i / 4) as f32 * 0.01;
+            store.store("docs", vector(i, vec![x + jitter * y, y - jitter * x])).await.unwrap();
+        }
+        assert_eq!(store.stale_namespaces(), ["docs"]);
+
+        // Training runs in bounded steps and swaps the lists in at the end
+        let mut steps = 1;
+        while !store.rebuild_step(25) {
+            assert!(!store.indexes["docs"].is_trained());
+            steps += 1;
+        }
+        assert!(steps > 2);
+        assert_eq!(store.indexes["docs"].centroids.len(), 4);
+        assert!(store.stale_namespaces().is_empty());
+
+        // Only the probed cluster is scored
+        let results = store.search("docs", vec![0.0, 1.0], 20).await.unwrap();
+        assert_eq!(results.len(), 10);
+        assert!(results.iter().all(|r| r.metadata.timestamp % 4 == 1));
+
+        // New and deleted vectors keep the lists current
+        store.store("docs", vector(100, vec![0.1, 1.0])).await.unwrap();
+        store.delete("docs", "v1").await.unwrap();
+        let results = store.search("docs", vec![0.1, 1.0], 1).await.unwrap();
+        assert_eq!(results[0].vector_id, "v100");
+        assert_eq!(store.count("docs").await.unwrap(), 40);
+
+        let memory = DefaultMemoryImpl::default();
+        store.persist(&memory).unwrap();
+        let restored = IvfVectorStore::with_memory(store.config().clone(), &memory).unwrap();
+        assert!(restored.indexes["docs"].is_trained());
+        let again = restored.search("docs", vec![0.0, 1.0], 20).await.unwrap();
+        assert_eq!(again.len(), 10);
+        assert!(again.iter().all(|r| r.vector_id != "v1"));
+    }
+
+    #[tokio::test]
+    async fn test_training_honors_metric_and_writes_during_training() {
+        let config = IvfConfig {
+            lists: 2,
+            nprobe: 1,
+            exact_search_below: 0,
+            ..IvfConfig::default()
+        };
+        let mut store = IvfVectorStore::new(config).with_metric(DistanceMetric::Euclidean);
+
+        // Two clusters along the same direction, only euclidean tells apart
+        for i in 0..20 {
+            let scale = if i % 2 == 0 { 1.0 } else { 10.0 };
+            store.store("docs", vector(i, vec![scale, scale + i as f32 * 0.01])).await.unwrap();
+        }
+        while !store.rebuild_step(5) {
+            // Rewrites after their assignment move to their new list
+            if store.trainings["docs"].phase == Phase::Assign {
+                store.store("docs", vector(0, vec![10.0, 10.0])).await.unwrap();
+            }
+        }
+
+        let results = store.search("docs", vec![1.0, 1.0], 20).await.unwrap();
+        assert_eq!(results.len(), 9);
+        assert!(results.iter().all(|r| r.metadata.timestamp % 2 == 0 && r.vector_id != "v0"));
+        let results = store.search("docs", vec![10.0, 10.0], 20).await.unwrap();
+        assert_eq!(results[0].vector_id, "v0");
+    }
+}
@@ -0,0 +1,185 @@
+//! BM25 keyword index over chunk text
+//!
+//! Embeddings capture meaning but blur exact strings: an order ID, an email
+//! address or a SKU rarely ranks first in a semantic search for it.
+//! [`KeywordIndex`] is an inverted index scored with Okapi BM25, kept next
+//! to the vectors of a namespace so such lookups can match literally.
+//!
+//! Tokens keep `@`, `.`, `_`, `-` and `+` between alphanumeric characters,
+//! so `jane.doe@example.com` and `SKU-1042` are indexed whole; their parts
+//! are indexed too, so `jane` or `1042` alone also match.
+
+use std::collections::HashMap;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// Term frequency saturation
+const K1: f32 = 1.2;
+
+/// Document length normalization
+const B: f32 = 0.75;
+
+/// Characters kept inside a token when surrounded by alphanumerics
+const CONNECTORS: [char; 5] = ['@', '.', '_', '-', '+'];
+
+/// Lowercased tokens of `text`, compound tokens followed by their parts
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = vec![];
+
+    for word in text.split(|c: char| !c.is_alphanumeric() && !CONNECTORS.contains(&c)) {
+        let word = word.trim_matches(|c: char| CONNECTORS.contains(&c));
+        if word.is_empty() {
+            continue;
+        }
+
+        let word = word.to_lowercase();
+        if word.contains(CONNECTORS) {
+            let parts: Vec<String> = word
+                .split(CONNECTORS)
+                .filter(|part| !part.is_empty())
+                .map(str::to_string)
+                .collect();
+            tokens.push(word);
+            tokens.extend(parts);
+        } else {
+            tokens.push(word);
+        }
+    }
+
+    tokens
+}
+
+/// Token count and distinct terms of an indexed vector
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+struct IndexedDoc {
+    length: u32,
+    terms: Vec<String>,
+}
+
+/// Inverted index of one namespace
+#[derive(Clone, Debug, Default, Serialize, Deserialize, CandidType)]
+pub struct KeywordIndex {
+    /// Term -> vector ID -> term frequency
+    postings: HashMap<String, HashMap<String, u32>>,
+    docs: HashMap<String, IndexedDoc>,
+    total_length: u64,
+}
+
+impl KeywordIndex {
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Index `text` under `vector_id`, replacing earlier text of the same ID
+    pub fn insert(&mut self, vector_id: &str, text: &str) {
+        self.remove(vector_id);
+
+        let tokens = tokenize(text);
+        let mut terms: Vec<String> = vec![];
+        for token in &tokens {
+            let count = self
+                .postings
+                .entry(token.clone())
+                .or_default()
+                .entry(vector_id.to_string())
+                .or_insert(0);
+            if *count == 0 {
+                terms.push(token.clone());
+            }
+            *count += 1;
+        }
+
+        let length = tokens.len() as u32;
+        self.docs.insert(vector_id.to_string(), IndexedDoc { length, terms });
+        self.total_length += length as u64;
+    }
+
+    /// Drop a vector from the index; returns whether it was indexed
+    pub fn remove(&mut self, vector_id: &str) -> bool {
+        let Some(doc) = self.docs.remove(vector_id) else {
+            return false;
+        };
+        self.total_length -= doc.length as u64;
+        for term in doc.terms {
+            if let Some(postings) = self.postings.get_mut(&term) {
+                postings.remove(vector_id);
+                if postings.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+        true
+    }
+
+    /// The `k` best matching vector IDs by BM25 score, best first
+    pub fn search(&self, query: &str, k: usize) -> Vec<(f32, &str)> {
+        if self.is_empty() || k == 0 {
+            return vec![];
+        }
+
+        let docs = self.docs.len() as f32;
+        let average_length = (self.total_length as f32 / docs).max(1.0);
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+
+        let mut scores: HashMap<&str, f32> = HashMap::new();
+        for term in &terms {
+            let Some(postings) = self.postings.get(term) else {
+                continue;
+            };
+            let df = postings.len() as f32;
+            let idf = ((docs - df + 0.5) / (df + 0.5) + 1.0).ln();
+
+            for (vector_id, &tf) in postings {
+                let tf = tf as f32;
+                let length = self.docs.get(vector_id).map(|doc| doc.length).unwrap_or(0) as f32;
+                let norm = K1 * (1.0 - B + B * length / average_length);
+                *scores.entry(vector_id.as_str()).or_insert(0.0) += idf * tf * (K1 + 1.0) / (tf + norm);
+            }
+        }
+
+        let mut results: Vec<(f32, &str)> = scores.into_iter().map(|(id, score)| (score, id)).collect();
+        results.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(b.1)));
+        results.truncate(k);
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_keeps_identifiers() {
+        assert_eq!(
+            tokenize("Mail Jane.Doe@example.com about SKU-1042."),
+            ["mail", "jane.doe@example.com", "jane", "doe", "example", "com", "about", "sku-1042", "sku", "1042"]
+        );
+    }
+
+    #[test]
+    fn test_bm25_ranks_exact_matches() {
+        let mut index = KeywordIndex::default();
+        index.insert("a", "Order 1042 for jane@example.com shipped");
+        index.insert("b", "Order 2001 is pending, order placed by bob@example.com");
+        index.insert("c", "Refund policy for damaged items");
+
+        let results = index.search("jane@example.com", 3);
+        assert_eq!(results[0].1, "a");
+
+        let results = index.search("order", 3);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].1, "b");
+
+        assert!(index.remove("a"));
+        assert!(index.search("1042", 3).is_empty());
+        index.insert("b", "replaced text");
+        assert!(index.search("pending", 3).is_empty());
+        assert_eq!(index.len(), 2);
+    }
+}
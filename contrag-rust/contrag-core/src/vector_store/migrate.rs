@@ -0,0 +1,199 @@
+//! Moving vectors between vector store backends
+//!
+//! [`migrate`] pages through each namespace of the source with
+//! `list_vectors` and writes every page to the destination as one
+//! all-or-nothing batch, so a failure leaves at most whole batches behind.
+//! The source is never modified. Inside a canister a large store may not
+//! fit in one message: keep the last reported [`MigrationProgress`] in
+//! canister state and pass it back to [`migrate`] to resume after the last
+//! copied batch, e.g. from a timer.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::error::{ContragError, Result};
+use crate::types::BatchMode;
+use crate::vector_store::VectorStore;
+
+/// Default number of vectors read and written per batch
+pub const DEFAULT_MIGRATION_BATCH: usize = 500;
+
+/// Where a running migration is, passed to the progress callback after
+/// every batch
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, CandidType)]
+pub struct MigrationProgress {
+    /// Namespace being copied
+    pub namespace: String,
+    /// Vectors of that namespace copied so far, out of `namespace_vectors`
+    pub copied: usize,
+    pub namespace_vectors: usize,
+    /// Namespaces fully copied before this one, out of `namespaces_total`
+    pub namespaces_done: usize,
+    pub namespaces_total: usize,
+    /// Vectors copied across all namespaces
+    pub vectors_done: u64,
+}
+
+/// Outcome of a finished migration
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, CandidType)]
+pub struct MigrationReport {
+    pub namespaces: usize,
+    pub vectors: u64,
+}
+
+/// Copy `namespaces` (every namespace when `None`) from `from` to `to`,
+/// `batch_size` vectors at a time (see [`DEFAULT_MIGRATION_BATCH`])
+///
+/// Vectors already in the destination are kept; IDs that exist on both
+/// sides follow the destination's `store` semantics. Passing the progress
+/// last reported by an interrupted run over the same namespaces as `resume`
+/// continues after its last batch.
+pub async fn migrate(
+    from: &dyn VectorStore,
+    to: &mut dyn VectorStore,
+    namespaces: Option<&[String]>,
+    batch_size: usize,
+    resume: Option<&MigrationProgress>,
+    mut on_progress: impl FnMut(&MigrationProgress),
+) -> Result<MigrationReport> {
+    if batch_size == 0 {
+        return Err(ContragError::InvalidConfig(
+            "Migration batch size must be greater than 0".to_string(),
+        ));
+    }
+
+    let namespaces = match namespaces {
+        Some(namespaces) => namespaces.to_vec(),
+        None => from.list_namespaces().await?,
+    };
+
+    let mut progress = MigrationProgress {
+        namespaces_total: namespaces.len(),
+        ..MigrationProgress::default()
+    };
+    let mut start = 0;
+    let mut offset = 0;
+    if let Some(resume) = resume {
+        start = namespaces.iter().position(|ns| *ns == resume.namespace).ok_or_else(|| {
+            ContragError::InvalidConfig(format!(
+                "Cannot resume the migration: namespace {} is not being migrated",
+                resume.namespace
+            ))
+        })?;
+        offset = resume.copied;
+        progress.namespaces_done = resume.namespaces_done;
+        progress.vectors_done = resume.vectors_done;
+    }
+
+    for namespace in namespaces.into_iter().skip(start) {
+        progress.namespace_vectors = from.count(&namespace).await?;
+        progress.namespace = namespace;
+        progress.copied = std::mem::take(&mut offset);
+
+        loop {
+            let batch = from.list_vectors(&progress.namespace, progress.copied, batch_size).await?;
+            if batch.is_empty() {
+                break;
+            }
+            let copied = batch.len();
+            to.store_batch_with(&progress.namespace, batch, BatchMode::AllOrNothing)
+                .await
+                .map_err(|e| {
+                    ContragError::VectorStoreError(format!(
+                        "Migration of {} failed after {} vectors: {}",
+                        progress.namespace, progress.copied, e
+                    ))
+                })?;
+
+            progress.copied += copied;
+            progress.vectors_done += copied as u64;
+            on_progress(&progress);
+            if copied < batch_size {
+                break;
+            }
+        }
+        progress.namespaces_done += 1;
+    }
+
+    Ok(MigrationReport {
+        namespaces: progress.namespaces_done,
+        vectors: progress.vectors_done,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HnswConfig;
+    use crate::types::{test_vector, Vector};
+    use crate::vector_store::hnsw::HnswVectorStore;
+    use crate::vector_store::stable_memory_store::StableMemoryVectorStore;
+
+    fn vector(id: usize) -> Vector {
+        Vector {
+            text: format!("chunk {}", id),
+            ..test_vector(&format!("v{}", id), vec![1.0, id as f32])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrate_in_batches() {
+        let mut from = StableMemoryVectorStore::new();
+        from.store_batch("a", (0..5).map(vector).collect()).await.unwrap();
+        from.store_batch("b", (5..7).map(vector).collect()).await.unwrap();
+        let mut to = HnswVectorStore::new(HnswConfig::default());
+
+        let mut seen = vec![];
+        let report = migrate(&from, &mut to, None, 2, None, |p| seen.push((p.namespace.clone(), p.copied)))
+            .await
+            .unwrap();
+
+        assert_eq!(report, MigrationReport { namespaces: 2, vectors: 7 });
+        assert_eq!(
+            seen,
+            [("a", 2), ("a", 4), ("a", 5), ("b", 2)].map(|(ns, n)| (ns.to_string(), n))
+        );
+        assert_eq!(to.count("a").await.unwrap(), 5);
+        assert_eq!(to.get("b", "v6").await.unwrap().unwrap().text, "chunk 6");
+
+        let only_b = ["b".to_string()];
+        let mut partial = StableMemoryVectorStore::new();
+        migrate(&from, &mut partial, Some(&only_b), 10, None, |_| {}).await.unwrap();
+        assert_eq!(partial.list_namespaces().await.unwrap(), ["b"]);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_resumes_after_last_batch() {
+        let mut from = HnswVectorStore::new(HnswConfig::default());
+        from.store_batch("a", (0..5).map(vector).collect()).await.unwrap();
+        from.store_batch("b", (5..7).map(vector).collect()).await.unwrap();
+        let namespaces = ["a".to_string(), "b".to_string()];
+
+        // A run interrupted after the second batch of "a"
+        let mut to = StableMemoryVectorStore::new();
+        let mut reported = vec![];
+        migrate(&from, &mut to, Some(&namespaces), 2, None, |p| reported.push(p.clone()))
+            .await
+            .unwrap();
+        let interrupted = &reported[1];
+        let mut resumed = StableMemoryVectorStore::new();
+        resumed
+            .store_batch("a", from.list_vectors("a", 0, interrupted.copied).await.unwrap())
+            .await
+            .unwrap();
+
+        let report = migrate(&from, &mut resumed, Some(&namespaces), 2, Some(interrupted), |_| {})
+            .await
+            .unwrap();
+        assert_eq!(report, MigrationReport { namespaces: 2, vectors: 7 });
+        assert_eq!(resumed.count("a").await.unwrap(), 5);
+        assert_eq!(resumed.count("b").await.unwrap(), 2);
+
+        let unknown = MigrationProgress {
+            namespace: "c".to_string(),
+            ..MigrationProgress::default()
+        };
+        assert!(migrate(&from, &mut resumed, Some(&namespaces), 2, Some(&unknown), |_| {})
+            .await
+            .is_err());
+    }
+}
@@ -1,7 +1,35 @@
+pub mod acl;
+pub mod analysis;
+pub mod bulk;
+pub mod compression;
+pub mod export;
+pub mod hnsw;
+pub mod hybrid;
+pub mod import;
+pub mod ivf;
+pub mod keyword;
+pub mod migrate;
+#[cfg(all(feature = "pgvector", not(target_arch = "wasm32")))]
+pub mod pgvector;
+pub mod precision;
+pub mod quantization;
+pub mod scoring;
+pub mod sharded;
+mod simd;
 pub mod stable_memory_store;
 
-use crate::error::Result;
-use crate::types::{Vector, SearchResult};
+use std::collections::{HashMap, HashSet};
+use ic_stable_structures::Memory;
+use crate::config::{DistanceMetric, VectorStoreConfig};
+use crate::error::{ContragError, Result};
+use crate::types::{
+    BatchMode, BatchWriteReport, BulkCursor, DedupMode, NamespaceInfo, NamespaceListRequest, NamespacePage,
+    NamespaceStats, PrefixStats, SearchFilter, SearchResult, StoreStats, Vector, MAX_NAMESPACE_PAGE_SIZE,
+};
+use crate::utils::content_hash;
+use crate::vector_store::hnsw::HnswVectorStore;
+use crate::vector_store::ivf::IvfVectorStore;
+use crate::vector_store::stable_memory_store::StableMemoryVectorStore;
 
 /// Trait for vector storage backends
 #[async_trait::async_trait]
@@ -9,12 +37,126 @@ pub trait VectorStore: Send + Sync {
     /// Store a single vector
     async fn store(&mut self, namespace: &str, vector: Vector) -> Result<()>;
 
-    /// Store multiple vectors
+    /// Store multiple vectors, all or nothing
     async fn store_batch(&mut self, namespace: &str, vectors: Vec<Vector>) -> Result<()> {
+        self.store_batch_with(namespace, vectors, BatchMode::AllOrNothing)
+            .await
+            .map(|_| ())
+    }
+
+    /// Store multiple vectors with explicit partial-failure semantics
+    ///
+    /// In [`BatchMode::AllOrNothing`] a failure leaves the namespace as it
+    /// was and is returned as an error; [`BatchMode::BestEffort`] stores what
+    /// it can and reports every vector's outcome. The default implementation
+    /// rolls back an all-or-nothing batch by deleting the vectors it already
+    /// stored and storing again the ones they replaced; backends that can
+    /// buffer a batch should override it.
+    async fn store_batch_with(
+        &mut self,
+        namespace: &str,
+        vectors: Vec<Vector>,
+        mode: BatchMode,
+    ) -> Result<BatchWriteReport> {
+        let mut report = BatchWriteReport::default();
+        // Vectors overwritten so far, to put back on rollback
+        let mut replaced = vec![];
+
         for vector in vectors {
-            self.store(namespace, vector).await?;
+            let vector_id = vector.id.clone();
+            if mode == BatchMode::AllOrNothing {
+                replaced.extend(self.get(namespace, &vector_id).await?);
+            }
+            match self.store(namespace, vector).await {
+                Ok(()) => report.push(vector_id, None),
+                Err(e) if mode == BatchMode::BestEffort => report.push(vector_id, Some(e.to_string())),
+                Err(e) => {
+                    for stored in &report.results {
+                        self.delete(namespace, &stored.vector_id).await?;
+                    }
+                    for original in replaced {
+                        self.store(namespace, original).await?;
+                    }
+                    return Err(ContragError::VectorStoreError(format!(
+                        "Batch rolled back, vector {} failed: {}",
+                        vector_id, e
+                    )));
+                }
+            }
         }
-        Ok(())
+
+        Ok(report)
+    }
+
+    /// Store a batch, leaving out chunks whose entity already has their text
+    ///
+    /// A vector whose text matches a stored chunk of the same entity (type
+    /// and ID) is skipped under [`DedupMode::Skip`] and overwrites that
+    /// chunk, keeping its ID, under [`DedupMode::Update`]; repeats within
+    /// the batch are always skipped. Only the chunks of the batch's
+    /// entities are read. `mode` applies to the vectors actually written,
+    /// and overwritten vectors are restored if their replacement fails.
+    async fn store_batch_dedup(
+        &mut self,
+        namespace: &str,
+        vectors: Vec<Vector>,
+        mode: BatchMode,
+        dedup: DedupMode,
+    ) -> Result<BatchWriteReport> {
+        // Entity type, entity ID and content hash -> ID and text of the
+        // chunk holding that text
+        let mut known: HashMap<(String, String, String), (String, String)> = HashMap::new();
+        let mut originals: HashMap<String, Vector> = HashMap::new();
+        let mut entities = HashSet::new();
+        for vector in &vectors {
+            let entity = (vector.metadata.entity_type.clone(), vector.metadata.entity_id.clone());
+            if !entities.insert(entity.clone()) {
+                continue;
+            }
+            for stored in self.get_by_entity(namespace, &entity.0, &entity.1).await? {
+                let key = (entity.0.clone(), entity.1.clone(), content_hash(&stored.text));
+                known.insert(key, (stored.id.clone(), stored.text.clone()));
+                originals.insert(stored.id.clone(), stored);
+            }
+        }
+
+        let mut plan = Vec::with_capacity(vectors.len());
+        let mut writes = vec![];
+        let mut replaced = vec![];
+        let mut in_batch = HashSet::new();
+        for mut vector in vectors {
+            let key = (
+                vector.metadata.entity_type.clone(),
+                vector.metadata.entity_id.clone(),
+                content_hash(&vector.text),
+            );
+            let duplicate = known
+                .get(&key)
+                .filter(|(_, text)| *text == vector.text)
+                .map(|(id, _)| id.clone());
+
+            match duplicate {
+                Some(existing) if dedup == DedupMode::Skip || in_batch.contains(&key) => {
+                    plan.push(PlannedWrite::skip(vector.id, existing));
+                }
+                Some(existing) => {
+                    if let Some(original) = originals.remove(&existing) {
+                        replaced.push(original);
+                    }
+                    let vector_id = std::mem::replace(&mut vector.id, existing.clone());
+                    plan.push(PlannedWrite::replace(vector_id, existing));
+                    writes.push(vector);
+                }
+                None => {
+                    known.insert(key.clone(), (vector.id.clone(), vector.text.clone()));
+                    plan.push(PlannedWrite::write(vector.id.clone()));
+                    writes.push(vector);
+                }
+            }
+            in_batch.insert(key);
+        }
+
+        write_planned(self, namespace, plan, writes, replaced, mode).await
     }
 
     /// Search for similar vectors
@@ -25,17 +167,548 @@ pub trait VectorStore: Send + Sync {
         k: usize,
     ) -> Result<Vec<SearchResult>>;
 
+    /// Search for similar vectors scoring at least `min_score`
+    ///
+    /// Fewer than `k` results come back when not enough vectors clear the
+    /// threshold. `None` applies the store's configured default, if any.
+    /// Thresholds are only meaningful for one metric; see
+    /// [`normalized_similarity`] for scores comparable across metrics.
+    async fn search_with_min_score(
+        &self,
+        namespace: &str,
+        query_embedding: Vec<f32>,
+        k: usize,
+        min_score: Option<f32>,
+    ) -> Result<Vec<SearchResult>> {
+        let mut results = self.search(namespace, query_embedding, k).await?;
+        retain_min_score(&mut results, min_score);
+        Ok(results)
+    }
+
+    /// Search for similar vectors, skipping the first `offset` results
+    ///
+    /// Pages through the same ranking as [`VectorStore::search`], so
+    /// `search_paged(ns, q, 10, 10)` returns results 11 to 20. The default
+    /// implementation searches `offset + k` and drops the first `offset`.
+    async fn search_paged(
+        &self,
+        namespace: &str,
+        query_embedding: Vec<f32>,
+        k: usize,
+        offset: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let results = self
+            .search(namespace, query_embedding, offset.saturating_add(k))
+            .await?;
+        Ok(results.into_iter().skip(offset).take(k).collect())
+    }
+
+    /// Return up to `limit` stored vectors starting at `offset`, in storage
+    /// order, for browsing a namespace's chunks
+    async fn list_vectors(&self, namespace: &str, offset: usize, limit: usize) -> Result<Vec<Vector>> {
+        Ok(self
+            .export_namespace(namespace)
+            .await?
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .collect())
+    }
+
+    /// Fetch a stored vector by ID
+    async fn get(&self, namespace: &str, vector_id: &str) -> Result<Option<Vector>> {
+        Ok(self
+            .export_namespace(namespace)
+            .await?
+            .into_iter()
+            .find(|v| v.id == vector_id))
+    }
+
+    /// Get the stored vectors among `vector_ids`, in no particular order
+    ///
+    /// The default calls [`get`](Self::get) per ID; backends whose `get`
+    /// scans the namespace should look them all up in one pass.
+    async fn get_many(&self, namespace: &str, vector_ids: &[String]) -> Result<Vec<Vector>> {
+        let mut vectors = Vec::with_capacity(vector_ids.len());
+        for vector_id in vector_ids {
+            vectors.extend(self.get(namespace, vector_id).await?);
+        }
+        Ok(vectors)
+    }
+
+    /// Fetch all chunks of an entity, ordered by chunk index
+    ///
+    /// Lets callers rebuild an entity's full context deterministically
+    /// instead of relying on which chunks a similarity search returns.
+    async fn get_by_entity(&self, namespace: &str, entity_type: &str, entity_id: &str) -> Result<Vec<Vector>> {
+        let mut chunks: Vec<Vector> = self
+            .export_namespace(namespace)
+            .await?
+            .into_iter()
+            .filter(|v| v.metadata.entity_type == entity_type && v.metadata.entity_id == entity_id)
+            .collect();
+        chunks.sort_by_key(|v| v.metadata.chunk_index);
+        Ok(chunks)
+    }
+
+    /// Search chunk text for the words of `query`, ranked by BM25
+    ///
+    /// Finds exact strings such as IDs, emails and SKUs that a semantic
+    /// search ranks poorly. Scores are BM25 scores, not similarities.
+    async fn keyword_search(&self, namespace: &str, query: &str, k: usize) -> Result<Vec<SearchResult>> {
+        let _ = (namespace, query, k);
+        Err(ContragError::VectorStoreError(
+            "This vector store does not support keyword search".to_string(),
+        ))
+    }
+
     /// Delete a vector by ID
     async fn delete(&mut self, namespace: &str, vector_id: &str) -> Result<()>;
 
     /// Delete all vectors in a namespace
     async fn delete_namespace(&mut self, namespace: &str) -> Result<()>;
 
+    /// Delete every chunk of an entity
+    ///
+    /// Returns the IDs of the deleted vectors.
+    async fn delete_by_entity(&mut self, namespace: &str, entity_type: &str, entity_id: &str) -> Result<Vec<String>> {
+        self.delete_where(namespace, &SearchFilter::entity(entity_type, entity_id)).await
+    }
+
+    /// Delete the vectors whose metadata matches `filter`
+    ///
+    /// Returns the IDs of the deleted vectors. The default implementation
+    /// exports the namespace and deletes matches one by one; backends should
+    /// override it with a single pass.
+    async fn delete_where(&mut self, namespace: &str, filter: &SearchFilter) -> Result<Vec<String>> {
+        let matching: Vec<String> = self
+            .export_namespace(namespace)
+            .await?
+            .into_iter()
+            .filter(|v| filter.matches(&v.metadata))
+            .map(|v| v.id)
+            .collect();
+
+        for vector_id in &matching {
+            self.delete(namespace, vector_id).await?;
+        }
+        Ok(matching)
+    }
+
+    /// Delete vectors of `entity_type` stored before `cutoff` (nanoseconds)
+    ///
+    /// Returns the IDs of the deleted vectors.
+    async fn delete_expired(
+        &mut self,
+        namespace: &str,
+        entity_type: &str,
+        cutoff: u64,
+    ) -> Result<Vec<String>> {
+        let _ = (namespace, entity_type, cutoff);
+        Err(ContragError::VectorStoreError(
+            "This vector store does not support expiring vectors".to_string(),
+        ))
+    }
+
+    /// Delete vectors whose TTL has passed at `now` (nanoseconds)
+    ///
+    /// Returns the IDs of the deleted vectors. The default implementation
+    /// only honors each vector's own `ttl_seconds`; stores with a default
+    /// TTL should override it.
+    async fn delete_ttl_expired(&mut self, namespace: &str, now: u64) -> Result<Vec<String>> {
+        let expired: Vec<String> = self
+            .export_namespace(namespace)
+            .await?
+            .into_iter()
+            .filter(|v| v.metadata.is_expired(now, None))
+            .map(|v| v.id)
+            .collect();
+
+        for vector_id in &expired {
+            self.delete(namespace, vector_id).await?;
+        }
+        Ok(expired)
+    }
+
+    /// Return up to `limit` stored vectors spread evenly across the namespace
+    async fn sample(&self, namespace: &str, limit: usize) -> Result<Vec<Vector>> {
+        let _ = (namespace, limit);
+        Err(ContragError::VectorStoreError(
+            "This vector store does not support sampling".to_string(),
+        ))
+    }
+
     /// Get vector count in namespace
     async fn count(&self, namespace: &str) -> Result<usize>;
 
     /// List all namespaces
     async fn list_namespaces(&self) -> Result<Vec<String>>;
+
+    /// List namespaces page by page, with counts
+    async fn list_namespaces_page(&self, request: NamespaceListRequest) -> Result<NamespacePage> {
+        let (names, next_cursor) = paginate_namespaces(self.list_namespaces().await?, &request);
+
+        let mut namespaces = Vec::with_capacity(names.len());
+        for name in names {
+            namespaces.push(NamespaceInfo {
+                vector_count: self.count(&name).await?,
+                size_bytes: 0,
+                name,
+            });
+        }
+
+        Ok(NamespacePage { namespaces, next_cursor })
+    }
+
+    /// Return all vectors stored in a namespace
+    async fn export_namespace(&self, namespace: &str) -> Result<Vec<Vector>> {
+        let _ = namespace;
+        Err(ContragError::VectorStoreError(
+            "This vector store does not support exporting namespaces".to_string(),
+        ))
+    }
+
+    /// Export a namespace as a Candid-encoded [`NamespaceSnapshot`]
+    ///
+    /// [`NamespaceSnapshot`]: export::NamespaceSnapshot
+    async fn export_snapshot(&self, namespace: &str) -> Result<Vec<u8>> {
+        let vectors = self.export_namespace(namespace).await?;
+        export::NamespaceSnapshot::new(namespace, vectors).to_bytes()
+    }
+
+    /// Import a snapshot made by [`VectorStore::export_snapshot`]
+    ///
+    /// The vectors are stored in `namespace`, which may differ from the one
+    /// they were exported from, all or nothing. Existing vectors are kept;
+    /// delete the namespace first to restore it exactly. Returns the number
+    /// of vectors imported.
+    async fn import_snapshot(&mut self, namespace: &str, bytes: &[u8]) -> Result<usize> {
+        let snapshot = export::NamespaceSnapshot::from_bytes(bytes)?;
+        let count = snapshot.vectors.len();
+        self.store_batch(namespace, snapshot.vectors).await?;
+        Ok(count)
+    }
+
+    /// Delete up to `batch` namespaces under the cursor's prefix
+    ///
+    /// Call again with the returned cursor until it is `finished`.
+    async fn delete_by_prefix(&mut self, cursor: BulkCursor, batch: usize) -> Result<BulkCursor> {
+        let page = self.list_namespaces_page(prefix_page_request(&cursor, batch)).await?;
+        let mut cursor = cursor;
+
+        for info in &page.namespaces {
+            self.delete_namespace(&info.name).await?;
+            cursor.namespaces_done += 1;
+            cursor.vectors_done += info.vector_count as u64;
+        }
+
+        advance_cursor(&mut cursor, &page);
+        Ok(cursor)
+    }
+
+    /// Export up to `batch` namespaces under the cursor's prefix
+    async fn export_by_prefix(
+        &self,
+        cursor: BulkCursor,
+        batch: usize,
+    ) -> Result<(Vec<(String, Vec<Vector>)>, BulkCursor)> {
+        let page = self.list_namespaces_page(prefix_page_request(&cursor, batch)).await?;
+        let mut cursor = cursor;
+        let mut exported = Vec::with_capacity(page.namespaces.len());
+
+        for info in &page.namespaces {
+            let vectors = self.export_namespace(&info.name).await?;
+            cursor.namespaces_done += 1;
+            cursor.vectors_done += vectors.len() as u64;
+            exported.push((info.name.clone(), vectors));
+        }
+
+        advance_cursor(&mut cursor, &page);
+        Ok((exported, cursor))
+    }
+
+    /// Vector count, sizes, dimensions and age range of a namespace
+    ///
+    /// The default implementation goes through `export_namespace`.
+    async fn stats(&self, namespace: &str) -> Result<NamespaceStats> {
+        let mut stats = NamespaceStats::new(namespace);
+        for vector in self.export_namespace(namespace).await? {
+            let embedding_bytes = vector.embedding.len() * std::mem::size_of::<f32>();
+            stats.add(vector.embedding.len(), embedding_bytes, vector.text.len(), vector.metadata.timestamp);
+        }
+        Ok(stats)
+    }
+
+    /// [`VectorStore::stats`] summed over every namespace
+    async fn global_stats(&self) -> Result<StoreStats> {
+        let mut totals = StoreStats::default();
+        for namespace in self.list_namespaces().await? {
+            totals.add(&self.stats(&namespace).await?);
+        }
+        Ok(totals)
+    }
+
+    /// Count namespaces, vectors and bytes under a prefix
+    async fn stats_by_prefix(&self, prefix: &str) -> Result<PrefixStats> {
+        let mut stats = PrefixStats {
+            prefix: prefix.to_string(),
+            namespaces: 0,
+            vectors: 0,
+            size_bytes: 0,
+        };
+        let mut cursor = BulkCursor::new(prefix);
+
+        while !cursor.finished {
+            let page = self
+                .list_namespaces_page(prefix_page_request(&cursor, MAX_NAMESPACE_PAGE_SIZE))
+                .await?;
+            for info in &page.namespaces {
+                stats.namespaces += 1;
+                stats.vectors += info.vector_count;
+                stats.size_bytes += info.size_bytes;
+            }
+            advance_cursor(&mut cursor, &page);
+        }
+
+        Ok(stats)
+    }
+
+    /// Write what the store keeps on the heap to `memory`, its region of
+    /// stable memory; call it in pre_upgrade
+    ///
+    /// Does nothing by default, for stores that keep nothing on the heap.
+    fn persist_state(&mut self, memory: &dyn Memory) -> Result<()> {
+        let _ = memory;
+        Ok(())
+    }
+
+    /// Load what [`VectorStore::persist_state`] last wrote to `memory`;
+    /// call it in init or post_upgrade
+    fn restore_state(&mut self, memory: &dyn Memory) -> Result<()> {
+        let _ = memory;
+        Ok(())
+    }
+
+    /// Do up to `budget` vectors' worth of index upkeep, such as training
+    /// IVF centroids or rebuilding HNSW graphs; returns whether none is left
+    ///
+    /// Call it from a timer or the `maintain_index` endpoint until it
+    /// returns true. Stores without an index have nothing to do.
+    fn maintain(&mut self, budget: usize) -> Result<bool> {
+        let _ = budget;
+        Ok(true)
+    }
+}
+
+/// Create the store `config.storage_type` names, configured from `config`
+///
+/// Restore what it persisted before an upgrade with
+/// [`VectorStore::restore_state`]. Hybrid stores keep their cold tier in
+/// a memory of their own, so they are opened with
+/// [`HybridVectorStore::from_config`](hybrid::HybridVectorStore::from_config)
+/// instead.
+pub fn from_config(config: &VectorStoreConfig) -> Result<Box<dyn VectorStore>> {
+    match config.storage_type.as_str() {
+        "stable_memory" => Ok(Box::new(StableMemoryVectorStore::from_config(config))),
+        "hnsw" => Ok(Box::new(
+            HnswVectorStore::new(config.hnsw.clone())
+                .with_metric(config.distance_metric)
+                .with_min_score(config.min_score),
+        )),
+        "ivf" => Ok(Box::new(
+            IvfVectorStore::new(config.ivf.clone())
+                .with_metric(config.distance_metric)
+                .with_min_score(config.min_score),
+        )),
+        "hybrid" => Err(ContragError::InvalidConfig(
+            "Hybrid stores need their own stable memory, open them with HybridVectorStore::from_config".to_string(),
+        )),
+        other => Err(ContragError::InvalidConfig(format!("Unknown storage type '{}'", other))),
+    }
+}
+
+/// What a deduplicating write does with one input vector
+pub(crate) struct PlannedWrite {
+    /// ID of the input vector
+    vector_id: String,
+    /// Stored vector it duplicates
+    duplicate_of: Option<String>,
+    /// Whether the vector is written, in place of `duplicate_of` if set
+    write: bool,
+}
+
+impl PlannedWrite {
+    pub fn write(vector_id: String) -> Self {
+        Self { vector_id, duplicate_of: None, write: true }
+    }
+
+    pub fn replace(vector_id: String, existing_id: String) -> Self {
+        Self { vector_id, duplicate_of: Some(existing_id), write: true }
+    }
+
+    pub fn skip(vector_id: String, existing_id: String) -> Self {
+        Self { vector_id, duplicate_of: Some(existing_id), write: false }
+    }
+}
+
+/// Store `writes` in place of the stored vectors `replaced` and report one
+/// result per entry of `plan`
+///
+/// `writes` holds the vectors of the written entries of `plan`, in order.
+/// A replaced vector is stored again if its replacement fails, or if the
+/// whole write does.
+pub(crate) async fn write_planned<S: VectorStore + ?Sized>(
+    store: &mut S,
+    namespace: &str,
+    plan: Vec<PlannedWrite>,
+    writes: Vec<Vector>,
+    replaced: Vec<Vector>,
+    mode: BatchMode,
+) -> Result<BatchWriteReport> {
+    for original in &replaced {
+        store.delete(namespace, &original.id).await?;
+    }
+
+    let written = match store.store_batch_with(namespace, writes, mode).await {
+        Ok(written) => written,
+        Err(e) => {
+            if !replaced.is_empty() {
+                store.store_batch(namespace, replaced).await?;
+            }
+            return Err(e);
+        }
+    };
+    let failed: HashSet<&str> = written
+        .results
+        .iter()
+        .filter(|r| r.error.is_some())
+        .map(|r| r.vector_id.as_str())
+        .collect();
+    let restore: Vec<Vector> = replaced
+        .into_iter()
+        .filter(|v| failed.contains(v.id.as_str()))
+        .collect();
+    if !restore.is_empty() {
+        store.store_batch(namespace, restore).await?;
+    }
+
+    let mut report = BatchWriteReport::default();
+    let mut results = written.results.into_iter();
+    for planned in plan {
+        match planned.duplicate_of {
+            Some(existing) if !planned.write => report.skip(planned.vector_id, existing),
+            duplicate_of => {
+                report.push(planned.vector_id, results.next().and_then(|r| r.error));
+                if let Some(result) = report.results.last_mut() {
+                    result.duplicate_of = duplicate_of;
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Drop the results scoring below `min_score`, if set
+pub(crate) fn retain_min_score(results: &mut Vec<SearchResult>, min_score: Option<f32>) {
+    if let Some(min_score) = min_score {
+        results.retain(|r| r.score >= min_score);
+    }
+}
+
+pub(crate) fn prefix_page_request(cursor: &BulkCursor, batch: usize) -> NamespaceListRequest {
+    NamespaceListRequest {
+        prefix: Some(cursor.prefix.clone()),
+        start_after: cursor.start_after.clone(),
+        limit: batch,
+    }
+}
+
+pub(crate) fn advance_cursor(cursor: &mut BulkCursor, page: &NamespacePage) {
+    if let Some(last) = page.namespaces.last() {
+        cursor.start_after = Some(last.name.clone());
+    }
+    cursor.finished = page.next_cursor.is_none();
+}
+
+/// Select one page of namespace names for a listing request
+///
+/// Returns the sorted names of the page and the cursor of the next page.
+pub fn paginate_namespaces(
+    mut names: Vec<String>,
+    request: &NamespaceListRequest,
+) -> (Vec<String>, Option<String>) {
+    let limit = request.limit.clamp(1, MAX_NAMESPACE_PAGE_SIZE);
+    names.sort();
+
+    let mut page: Vec<String> = names
+        .into_iter()
+        .filter(|name| request.prefix.as_ref().map_or(true, |p| name.starts_with(p.as_str())))
+        .filter(|name| request.start_after.as_ref().map_or(true, |after| name > after))
+        .take(limit + 1)
+        .collect();
+
+    let next_cursor = if page.len() > limit {
+        page.truncate(limit);
+        page.last().cloned()
+    } else {
+        None
+    };
+
+    (page, next_cursor)
+}
+
+/// Keep at most `max_per_entity` results per (entity_type, entity_id)
+///
+/// Results must be sorted best first; the first `k` survivors are returned
+/// in order, so capped entities make room for the next best ones.
+pub fn cap_per_entity(results: Vec<SearchResult>, max_per_entity: usize, k: usize) -> Vec<SearchResult> {
+    let mut per_entity: HashMap<(String, String), usize> = HashMap::new();
+
+    results
+        .into_iter()
+        .filter(|r| {
+            let key = (r.metadata.entity_type.clone(), r.metadata.entity_id.clone());
+            let count = per_entity.entry(key).or_insert(0);
+            *count += 1;
+            *count <= max_per_entity
+        })
+        .take(k)
+        .collect()
+}
+
+/// Score of `b` against the query `a` under `metric`; higher is more similar
+pub fn similarity(metric: DistanceMetric, a: &[f32], b: &[f32]) -> f32 {
+    match metric {
+        DistanceMetric::Cosine => cosine_similarity(a, b),
+        DistanceMetric::DotProduct => dot_product(a, b),
+        DistanceMetric::Euclidean => 1.0 / (1.0 + euclidean_distance(a, b)),
+    }
+}
+
+/// Score of `b` against the query `a` under `metric`, mapped into 0..=1
+///
+/// The mapping makes the metrics agree on unit-length embeddings, which is
+/// what most embedding models return: cosine `c` becomes `(1 + c) / 2`,
+/// the dot product is clamped to -1..=1 and mapped the same way, and a
+/// euclidean distance `d` becomes `1 - d² / 4` (since `d² = 2 - 2c` between
+/// unit vectors). A threshold of 0.75 therefore means a cosine of 0.5 under
+/// every metric. Off the unit sphere dot product and euclidean scores are
+/// clamped rather than exact.
+pub fn normalized_similarity(metric: DistanceMetric, a: &[f32], b: &[f32]) -> f32 {
+    let score = match metric {
+        DistanceMetric::Cosine => (1.0 + cosine_similarity(a, b)) / 2.0,
+        DistanceMetric::DotProduct => (1.0 + dot_product(a, b).clamp(-1.0, 1.0)) / 2.0,
+        DistanceMetric::Euclidean => 1.0 - euclidean_distance(a, b).powi(2) / 4.0,
+    };
+    score.clamp(0.0, 1.0)
+}
+
+pub fn dot_product(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+
+    simd::dot(a, b)
 }
 
 /// Cosine similarity calculation
@@ -44,9 +717,9 @@ pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
         return 0.0;
     }
 
-    let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
-    let magnitude_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-    let magnitude_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let (dot_product, squared_a, squared_b) = simd::dot_and_norms(a, b);
+    let magnitude_a = squared_a.sqrt();
+    let magnitude_b = squared_b.sqrt();
 
     if magnitude_a == 0.0 || magnitude_b == 0.0 {
         return 0.0;
@@ -55,22 +728,82 @@ pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     dot_product / (magnitude_a * magnitude_b)
 }
 
+/// Shorten a Matryoshka embedding to `dimensions` and scale it back to
+/// unit length; shorter embeddings are left as they are
+pub fn truncate_embedding(embedding: &mut Vec<f32>, dimensions: usize) {
+    if embedding.len() <= dimensions {
+        return;
+    }
+    embedding.truncate(dimensions);
+    l2_normalize(embedding);
+}
+
+/// Scale `embedding` to unit length; zero vectors are left as they are
+///
+/// For unit-length embeddings, dot product equals cosine similarity.
+pub fn l2_normalize(embedding: &mut [f32]) {
+    let magnitude = simd::dot(embedding, embedding).sqrt();
+    if magnitude > 0.0 {
+        embedding.iter_mut().for_each(|x| *x /= magnitude);
+    }
+}
+
 /// Euclidean distance calculation
 pub fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
     if a.len() != b.len() {
         return f32::MAX;
     }
 
-    a.iter()
-        .zip(b.iter())
-        .map(|(x, y)| (x - y).powi(2))
-        .sum::<f32>()
-        .sqrt()
+    simd::squared_distance(a, b).sqrt()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::test_vector;
+
+    #[tokio::test]
+    async fn test_from_config_honors_storage_type() {
+        let mut config = VectorStoreConfig {
+            storage_type: "hnsw".to_string(),
+            ..VectorStoreConfig::default()
+        };
+        let mut store = from_config(&config).unwrap();
+        store.store("docs", test_vector("v1", vec![1.0, 0.0])).await.unwrap();
+
+        let memory = ic_stable_structures::DefaultMemoryImpl::default();
+        store.persist_state(&memory).unwrap();
+        let restored = HnswVectorStore::with_memory(config.hnsw.clone(), &memory).unwrap();
+        assert_eq!(restored.count("docs").await.unwrap(), 1);
+
+        let mut flat = from_config(&VectorStoreConfig::default()).unwrap();
+        assert!(flat.restore_state(&memory).is_err());
+
+        config.storage_type = "hybrid".to_string();
+        assert!(from_config(&config).is_err());
+        config.storage_type = "flat".to_string();
+        assert!(from_config(&config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_from_config_applies_min_score() {
+        for storage_type in ["stable_memory", "hnsw", "ivf"] {
+            let config = VectorStoreConfig {
+                storage_type: storage_type.to_string(),
+                min_score: Some(0.5),
+                ..VectorStoreConfig::default()
+            };
+            let mut store = from_config(&config).unwrap();
+            store.store("docs", test_vector("near", vec![1.0, 0.1])).await.unwrap();
+            store.store("docs", test_vector("far", vec![0.0, 1.0])).await.unwrap();
+
+            let ids = |results: Vec<SearchResult>| results.into_iter().map(|r| r.vector_id).collect::<Vec<_>>();
+            let results = store.search("docs", vec![1.0, 0.0], 5).await.unwrap();
+            assert_eq!(ids(results), ["near"], "{}", storage_type);
+            let results = store.search_with_min_score("docs", vec![1.0, 0.0], 5, Some(-1.0)).await.unwrap();
+            assert_eq!(ids(results), ["near", "far"], "{}", storage_type);
+        }
+    }
 
     #[test]
     fn test_cosine_similarity() {
@@ -81,6 +814,62 @@ mod tests {
         let c = vec![1.0, 0.0, 0.0];
         let d = vec![0.0, 1.0, 0.0];
         assert!((cosine_similarity(&c, &d) - 0.0).abs() < 0.001);
+
+        let mut e = vec![3.0, 4.0];
+        l2_normalize(&mut e);
+        assert_eq!(e, vec![0.6, 0.8]);
+    }
+
+    #[test]
+    fn test_truncate_embedding() {
+        let mut embedding = vec![3.0, 4.0, 12.0];
+        truncate_embedding(&mut embedding, 2);
+        assert_eq!(embedding, [0.6, 0.8]);
+
+        truncate_embedding(&mut embedding, 4);
+        assert_eq!(embedding, [0.6, 0.8]);
+    }
+
+    #[test]
+    fn test_cap_per_entity() {
+        let hit = |entity_id: &str| SearchResult {
+            vector_id: entity_id.to_string(),
+            text: String::new(),
+            score: 0.0,
+            metadata: crate::types::VectorMetadata {
+                entity_type: "User".to_string(),
+                ..test_vector(entity_id, vec![]).metadata
+            },
+        };
+        let results = vec![hit("1"), hit("1"), hit("1"), hit("2"), hit("1"), hit("3"), hit("4")];
+
+        let capped: Vec<String> = cap_per_entity(results, 2, 4)
+            .into_iter()
+            .map(|r| r.metadata.entity_id)
+            .collect();
+        assert_eq!(capped, vec!["1", "1", "2", "3"]);
+    }
+
+    #[test]
+    fn test_paginate_namespaces() {
+        let names: Vec<String> = ["tenant:b:User", "tenant:a:User", "tenant:a:Order", "other"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let mut request = NamespaceListRequest {
+            prefix: Some("tenant:".to_string()),
+            start_after: None,
+            limit: 2,
+        };
+        let (page, cursor) = paginate_namespaces(names.clone(), &request);
+        assert_eq!(page, vec!["tenant:a:Order", "tenant:a:User"]);
+        assert_eq!(cursor.as_deref(), Some("tenant:a:User"));
+
+        request.start_after = cursor;
+        let (page, cursor) = paginate_namespaces(names, &request);
+        assert_eq!(page, vec!["tenant:b:User"]);
+        assert_eq!(cursor, None);
     }
 
     #[test]
@@ -93,4 +882,34 @@ mod tests {
         let d = vec![1.0, 1.0, 0.0];
         assert!((euclidean_distance(&c, &d) - 1.414).abs() < 0.01);
     }
+
+    #[test]
+    fn test_similarity_metrics() {
+        let query = vec![1.0, 0.0];
+        let near = vec![2.0, 0.0];
+        let far = vec![0.0, 3.0];
+
+        assert!((similarity(DistanceMetric::Cosine, &query, &near) - 1.0).abs() < 0.001);
+        assert!((similarity(DistanceMetric::DotProduct, &query, &near) - 2.0).abs() < 0.001);
+        assert!((similarity(DistanceMetric::Euclidean, &query, &near) - 0.5).abs() < 0.001);
+
+        for metric in [DistanceMetric::Cosine, DistanceMetric::DotProduct, DistanceMetric::Euclidean] {
+            assert!(similarity(metric, &query, &near) > similarity(metric, &query, &far));
+        }
+    }
+
+    #[test]
+    fn test_normalized_similarity_agrees_across_metrics() {
+        let query = vec![1.0, 0.0];
+        let unit = [vec![1.0, 0.0], vec![0.6, 0.8], vec![0.0, 1.0], vec![-1.0, 0.0]];
+        let expected = [1.0, 0.8, 0.5, 0.0];
+
+        for (embedding, expected) in unit.iter().zip(expected) {
+            for metric in [DistanceMetric::Cosine, DistanceMetric::DotProduct, DistanceMetric::Euclidean] {
+                let score = normalized_similarity(metric, &query, embedding);
+                assert!((score - expected).abs() < 0.001, "{:?} scored {}", metric, score);
+            }
+        }
+        assert_eq!(normalized_similarity(DistanceMetric::DotProduct, &query, &[5.0, 0.0]), 1.0);
+    }
 }
@@ -0,0 +1,502 @@
+//! Postgres/pgvector vector store for native builds
+//!
+//! Only compiled with the `pgvector` feature on non-wasm targets. Lets the
+//! same entities, context builder and pipeline run in a regular server
+//! against Postgres with the [pgvector](https://github.com/pgvector/pgvector)
+//! extension, e.g. for off-chain indexing or local development.
+//!
+//! All namespaces share one table keyed by `(namespace, id)`. Embeddings are
+//! exchanged as pgvector text literals, so no extra client-side type crate
+//! is needed. Search scores follow [`DistanceMetric`] like the canister
+//! stores.
+
+use sqlx::postgres::{PgPool, PgPoolOptions, PgRow};
+use sqlx::{Postgres, Row, Transaction};
+use crate::config::DistanceMetric;
+use crate::error::{ContragError, Result};
+use crate::types::{BatchMode, BatchWriteReport, SearchResult, Vector, VectorMetadata};
+use crate::vector_store::{retain_min_score, VectorStore};
+
+/// Default table holding all namespaces
+pub const DEFAULT_TABLE: &str = "contrag_vectors";
+
+const COLUMNS: &str = "id, embedding::text AS embedding, text, entity_type, entity_id, \
+chunk_index, total_chunks, indexed_at, custom, ttl_seconds";
+
+/// Vector store backed by a Postgres table with a pgvector column
+pub struct PgVectorStore {
+    pool: PgPool,
+    table: String,
+    metric: DistanceMetric,
+    min_score: Option<f32>,
+}
+
+impl PgVectorStore {
+    /// Connect to `database_url` using the default table
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(database_url)
+            .await
+            .map_err(storage_error)?;
+        Ok(Self::with_pool(pool))
+    }
+
+    /// Use an existing connection pool
+    pub fn with_pool(pool: PgPool) -> Self {
+        Self {
+            pool,
+            table: DEFAULT_TABLE.to_string(),
+            metric: DistanceMetric::default(),
+            min_score: None,
+        }
+    }
+
+    /// Store vectors in `table` instead of [`DEFAULT_TABLE`]
+    pub fn with_table(mut self, table: impl Into<String>) -> Result<Self> {
+        let table = table.into();
+        if !is_identifier(&table) {
+            return Err(ContragError::InvalidConfig(format!("Invalid table name: {}", table)));
+        }
+        self.table = table;
+        Ok(self)
+    }
+
+    pub fn with_metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Drop search results scoring below `min_score` by default
+    pub fn with_min_score(mut self, min_score: Option<f32>) -> Self {
+        self.min_score = min_score;
+        self
+    }
+
+    /// Create the pgvector extension, the table and its namespace index
+    ///
+    /// Idempotent; call once at startup.
+    pub async fn migrate(&self) -> Result<()> {
+        for statement in schema_sql(&self.table) {
+            sqlx::query(&statement)
+                .execute(&self.pool)
+                .await
+                .map_err(storage_error)?;
+        }
+        Ok(())
+    }
+
+    async fn upsert(&self, tx: &mut Transaction<'_, Postgres>, namespace: &str, vector: &Vector) -> Result<()> {
+        if vector.embedding.is_empty() {
+            return Err(ContragError::VectorStoreError("Empty embedding".to_string()));
+        }
+
+        let metadata = &vector.metadata;
+
+        sqlx::query(&upsert_sql(&self.table))
+            .bind(namespace)
+            .bind(&vector.id)
+            .bind(to_literal(&vector.embedding))
+            .bind(&vector.text)
+            .bind(&metadata.entity_type)
+            .bind(&metadata.entity_id)
+            .bind(metadata.chunk_index as i64)
+            .bind(metadata.total_chunks as i64)
+            .bind(metadata.timestamp as i64)
+            .bind(&metadata.custom)
+            .bind(metadata.ttl_seconds.map(|ttl| ttl as i64))
+            .execute(&mut **tx)
+            .await
+            .map_err(storage_error)?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl VectorStore for PgVectorStore {
+    async fn store(&mut self, namespace: &str, vector: Vector) -> Result<()> {
+        self.store_batch_with(namespace, vec![vector], BatchMode::AllOrNothing)
+            .await
+            .map(|_| ())
+    }
+
+    /// Writes the batch in one transaction; best-effort batches commit the
+    /// vectors that succeeded, each behind its own savepoint
+    async fn store_batch_with(
+        &mut self,
+        namespace: &str,
+        vectors: Vec<Vector>,
+        mode: BatchMode,
+    ) -> Result<BatchWriteReport> {
+        let mut tx = self.pool.begin().await.map_err(storage_error)?;
+        let mut report = BatchWriteReport::default();
+
+        for vector in &vectors {
+            if mode == BatchMode::BestEffort {
+                sqlx::query("SAVEPOINT contrag_vector")
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(storage_error)?;
+            }
+
+            match self.upsert(&mut tx, namespace, vector).await {
+                Ok(()) => report.push(vector.id.clone(), None),
+                Err(e) if mode == BatchMode::BestEffort => {
+                    sqlx::query("ROLLBACK TO SAVEPOINT contrag_vector")
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(storage_error)?;
+                    report.push(vector.id.clone(), Some(e.to_string()));
+                }
+                Err(e) => {
+                    return Err(ContragError::VectorStoreError(format!(
+                        "Batch rolled back, vector {} failed: {}",
+                        vector.id, e
+                    )));
+                }
+            }
+        }
+
+        tx.commit().await.map_err(storage_error)?;
+        Ok(report)
+    }
+
+    async fn search(
+        &self,
+        namespace: &str,
+        query_embedding: Vec<f32>,
+        k: usize,
+    ) -> Result<Vec<SearchResult>> {
+        self.search_with_min_score(namespace, query_embedding, k, None).await
+    }
+
+    async fn search_with_min_score(
+        &self,
+        namespace: &str,
+        query_embedding: Vec<f32>,
+        k: usize,
+        min_score: Option<f32>,
+    ) -> Result<Vec<SearchResult>> {
+        let rows = sqlx::query(&search_sql(&self.table, self.metric))
+            .bind(namespace)
+            .bind(to_literal(&query_embedding))
+            .bind(k as i64)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(storage_error)?;
+
+        let mut results = rows
+            .iter()
+            .map(|row| {
+                let vector = row_to_vector(row)?;
+                let score: f64 = row.try_get("score").map_err(storage_error)?;
+                Ok(SearchResult {
+                    vector_id: vector.id,
+                    text: vector.text,
+                    score: score as f32,
+                    metadata: vector.metadata,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        retain_min_score(&mut results, min_score.or(self.min_score));
+        Ok(results)
+    }
+
+    async fn delete(&mut self, namespace: &str, vector_id: &str) -> Result<()> {
+        sqlx::query(&format!("DELETE FROM {} WHERE namespace = $1 AND id = $2", self.table))
+            .bind(namespace)
+            .bind(vector_id)
+            .execute(&self.pool)
+            .await
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    async fn delete_namespace(&mut self, namespace: &str) -> Result<()> {
+        sqlx::query(&format!("DELETE FROM {} WHERE namespace = $1", self.table))
+            .bind(namespace)
+            .execute(&self.pool)
+            .await
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    async fn delete_expired(
+        &mut self,
+        namespace: &str,
+        entity_type: &str,
+        cutoff: u64,
+    ) -> Result<Vec<String>> {
+        let rows = sqlx::query(&format!(
+            "DELETE FROM {} WHERE namespace = $1 AND entity_type = $2 AND indexed_at < $3 RETURNING id",
+            self.table
+        ))
+        .bind(namespace)
+        .bind(entity_type)
+        .bind(cutoff as i64)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(storage_error)?;
+
+        rows.iter()
+            .map(|row| row.try_get("id").map_err(storage_error))
+            .collect()
+    }
+
+    async fn get(&self, namespace: &str, vector_id: &str) -> Result<Option<Vector>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM {} WHERE namespace = $1 AND id = $2",
+            COLUMNS, self.table
+        ))
+        .bind(namespace)
+        .bind(vector_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(storage_error)?;
+
+        row.as_ref().map(row_to_vector).transpose()
+    }
+
+    async fn get_many(&self, namespace: &str, vector_ids: &[String]) -> Result<Vec<Vector>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM {} WHERE namespace = $1 AND id = ANY($2)",
+            COLUMNS, self.table
+        ))
+        .bind(namespace)
+        .bind(vector_ids)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(storage_error)?;
+
+        rows.iter().map(row_to_vector).collect()
+    }
+
+    async fn get_by_entity(&self, namespace: &str, entity_type: &str, entity_id: &str) -> Result<Vec<Vector>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM {} WHERE namespace = $1 AND entity_type = $2 AND entity_id = $3 ORDER BY chunk_index",
+            COLUMNS, self.table
+        ))
+        .bind(namespace)
+        .bind(entity_type)
+        .bind(entity_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(storage_error)?;
+
+        rows.iter().map(row_to_vector).collect()
+    }
+
+    async fn list_vectors(&self, namespace: &str, offset: usize, limit: usize) -> Result<Vec<Vector>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM {} WHERE namespace = $1 ORDER BY id OFFSET $2 LIMIT $3",
+            COLUMNS, self.table
+        ))
+        .bind(namespace)
+        .bind(offset as i64)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(storage_error)?;
+
+        rows.iter().map(row_to_vector).collect()
+    }
+
+    async fn count(&self, namespace: &str) -> Result<usize> {
+        let count: i64 = sqlx::query(&format!("SELECT COUNT(*) FROM {} WHERE namespace = $1", self.table))
+            .bind(namespace)
+            .fetch_one(&self.pool)
+            .await
+            .and_then(|row| row.try_get(0))
+            .map_err(storage_error)?;
+        Ok(count as usize)
+    }
+
+    async fn list_namespaces(&self) -> Result<Vec<String>> {
+        let rows = sqlx::query(&format!("SELECT DISTINCT namespace FROM {} ORDER BY namespace", self.table))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(storage_error)?;
+
+        rows.iter()
+            .map(|row| row.try_get("namespace").map_err(storage_error))
+            .collect()
+    }
+
+    async fn export_namespace(&self, namespace: &str) -> Result<Vec<Vector>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM {} WHERE namespace = $1 ORDER BY id",
+            COLUMNS, self.table
+        ))
+        .bind(namespace)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(storage_error)?;
+
+        rows.iter().map(row_to_vector).collect()
+    }
+}
+
+/// Statements creating the extension, `table` and its entity index
+fn schema_sql(table: &str) -> [String; 3] {
+    [
+        "CREATE EXTENSION IF NOT EXISTS vector".to_string(),
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                namespace TEXT NOT NULL,
+                id TEXT NOT NULL,
+                embedding vector NOT NULL,
+                text TEXT NOT NULL,
+                entity_type TEXT NOT NULL,
+                entity_id TEXT NOT NULL,
+                chunk_index BIGINT NOT NULL,
+                total_chunks BIGINT NOT NULL,
+                indexed_at BIGINT NOT NULL,
+                custom TEXT,
+                ttl_seconds BIGINT,
+                PRIMARY KEY (namespace, id)
+            )",
+            table
+        ),
+        format!(
+            "CREATE INDEX IF NOT EXISTS {0}_entity_idx ON {0} (namespace, entity_type, entity_id)",
+            table
+        ),
+    ]
+}
+
+/// Insert binding `$1` namespace to `$11` ttl_seconds, replacing the row
+/// with the same `(namespace, id)`
+fn upsert_sql(table: &str) -> String {
+    format!(
+        "INSERT INTO {} (namespace, id, embedding, text, entity_type, entity_id, chunk_index,
+            total_chunks, indexed_at, custom, ttl_seconds)
+         VALUES ($1, $2, $3::vector, $4, $5, $6, $7, $8, $9, $10, $11)
+         ON CONFLICT (namespace, id) DO UPDATE SET
+            embedding = EXCLUDED.embedding, text = EXCLUDED.text,
+            entity_type = EXCLUDED.entity_type, entity_id = EXCLUDED.entity_id,
+            chunk_index = EXCLUDED.chunk_index, total_chunks = EXCLUDED.total_chunks,
+            indexed_at = EXCLUDED.indexed_at, custom = EXCLUDED.custom,
+            ttl_seconds = EXCLUDED.ttl_seconds",
+        table
+    )
+}
+
+/// Nearest neighbours of `$2` in namespace `$1`, `$3` at most, with their
+/// distance turned into a score under `metric`
+fn search_sql(table: &str, metric: DistanceMetric) -> String {
+    let (operator, score) = match metric {
+        DistanceMetric::Cosine => ("<=>", "1 - distance"),
+        // `<#>` is the negative inner product
+        DistanceMetric::DotProduct => ("<#>", "-distance"),
+        DistanceMetric::Euclidean => ("<->", "1 / (1 + distance)"),
+    };
+    format!(
+        "SELECT *, {score} AS score FROM (
+            SELECT {columns}, embedding {operator} $2::vector AS distance
+            FROM {table} WHERE namespace = $1
+            ORDER BY embedding {operator} $2::vector LIMIT $3
+         ) nearest ORDER BY distance",
+        score = score,
+        columns = COLUMNS,
+        operator = operator,
+        table = table
+    )
+}
+
+fn storage_error(e: sqlx::Error) -> ContragError {
+    ContragError::StorageError(e.to_string())
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// pgvector text literal, e.g. `[0.1,0.2]`
+fn to_literal(embedding: &[f32]) -> String {
+    let values: Vec<String> = embedding.iter().map(f32::to_string).collect();
+    format!("[{}]", values.join(","))
+}
+
+fn from_literal(literal: &str) -> Result<Vec<f32>> {
+    literal
+        .trim_matches(|c| c == '[' || c == ']')
+        .split(',')
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| ContragError::StorageError(format!("Invalid vector value: {}", value)))
+        })
+        .collect()
+}
+
+fn row_to_vector(row: &PgRow) -> Result<Vector> {
+    let get_i64 = |column: &str| row.try_get::<i64, _>(column).map_err(storage_error);
+    let embedding: String = row.try_get("embedding").map_err(storage_error)?;
+
+    Ok(Vector {
+        id: row.try_get("id").map_err(storage_error)?,
+        embedding: from_literal(&embedding)?,
+        text: row.try_get("text").map_err(storage_error)?,
+        metadata: VectorMetadata {
+            entity_type: row.try_get("entity_type").map_err(storage_error)?,
+            entity_id: row.try_get("entity_id").map_err(storage_error)?,
+            chunk_index: get_i64("chunk_index")? as usize,
+            total_chunks: get_i64("total_chunks")? as usize,
+            timestamp: get_i64("indexed_at")? as u64,
+            custom: row.try_get("custom").map_err(storage_error)?,
+            ttl_seconds: row
+                .try_get::<Option<i64>, _>("ttl_seconds")
+                .map_err(storage_error)?
+                .map(|ttl| ttl as u64),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_names_are_identifiers() {
+        assert!(is_identifier("contrag_vectors"));
+        assert!(is_identifier("_v2"));
+        assert!(!is_identifier("2vectors"));
+        assert!(!is_identifier("vectors; DROP TABLE users"));
+        assert!(!is_identifier("public.vectors"));
+        assert!(!is_identifier(""));
+    }
+
+    #[test]
+    fn test_builds_queries_for_table_and_metric() {
+        let search = search_sql("docs", DistanceMetric::Cosine);
+        assert!(search.contains("FROM docs WHERE namespace = $1"));
+        assert!(search.contains("ORDER BY embedding <=> $2::vector LIMIT $3"));
+        assert!(search.contains("1 - distance AS score"));
+        assert!(search_sql("docs", DistanceMetric::DotProduct).contains("embedding <#> $2::vector"));
+        assert!(search_sql("docs", DistanceMetric::DotProduct).contains("-distance AS score"));
+        assert!(search_sql("docs", DistanceMetric::Euclidean).contains("1 / (1 + distance) AS score"));
+
+        let upsert = upsert_sql("docs");
+        assert!(upsert.starts_with("INSERT INTO docs "));
+        assert!(upsert.contains("$3::vector"));
+        assert!(upsert.contains("ON CONFLICT (namespace, id) DO UPDATE"));
+
+        let [extension, table, index] = schema_sql("docs");
+        assert_eq!(extension, "CREATE EXTENSION IF NOT EXISTS vector");
+        assert!(table.starts_with("CREATE TABLE IF NOT EXISTS docs ("));
+        assert!(index.contains("docs_entity_idx ON docs (namespace, entity_type, entity_id)"));
+    }
+
+    #[test]
+    fn test_embedding_literals_round_trip() {
+        let embedding = vec![0.25, -1.0, 3.5e-7];
+        assert_eq!(to_literal(&[0.5, -1.0]), "[0.5,-1]");
+        assert_eq!(from_literal(&to_literal(&embedding)).unwrap(), embedding);
+        assert_eq!(from_literal("[]").unwrap(), Vec::<f32>::new());
+        assert!(from_literal("[0.1,abc]").is_err());
+    }
+}
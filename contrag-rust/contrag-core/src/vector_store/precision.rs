@@ -0,0 +1,166 @@
+//! Half-precision embedding storage
+//!
+//! Embeddings are narrowed to IEEE 754 binary16 on write, rounding to
+//! nearest even, and widened back to `f32` for scoring and reads. Values
+//! beyond ±65504 become infinite and those below about 6e-8 become zero,
+//! which is far outside what embedding models produce.
+
+use std::borrow::Cow;
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use crate::config::EmbeddingPrecision;
+
+/// An embedding as held by a vector store
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CandidType)]
+pub enum StoredEmbedding {
+    F32(Vec<f32>),
+    F16(Vec<u16>),
+}
+
+impl StoredEmbedding {
+    pub fn new(embedding: Vec<f32>, precision: EmbeddingPrecision) -> Self {
+        match precision {
+            EmbeddingPrecision::F32 => Self::F32(embedding),
+            EmbeddingPrecision::F16 => Self::F16(embedding.into_iter().map(f32_to_f16).collect()),
+        }
+    }
+
+    /// Number of dimensions
+    pub fn len(&self) -> usize {
+        match self {
+            Self::F32(values) => values.len(),
+            Self::F16(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bytes taken in storage
+    pub fn stored_len(&self) -> usize {
+        match self {
+            Self::F32(values) => values.len() * std::mem::size_of::<f32>(),
+            Self::F16(values) => values.len() * std::mem::size_of::<u16>(),
+        }
+    }
+
+    /// The embedding as `f32`s, widened if needed
+    pub fn values(&self) -> Cow<'_, [f32]> {
+        match self {
+            Self::F32(values) => Cow::Borrowed(values),
+            Self::F16(values) => Cow::Owned(values.iter().copied().map(f16_to_f32).collect()),
+        }
+    }
+
+    /// The embedding as `f32`s, widened into `buffer` if needed, so scoring
+    /// many embeddings reuses one allocation
+    pub fn values_in<'a>(&'a self, buffer: &'a mut Vec<f32>) -> &'a [f32] {
+        match self {
+            Self::F32(values) => values,
+            Self::F16(values) => {
+                buffer.clear();
+                buffer.extend(values.iter().copied().map(f16_to_f32));
+                buffer
+            }
+        }
+    }
+
+    /// The same embedding at `precision`, narrowed or widened if needed
+    pub fn into_precision(self, precision: EmbeddingPrecision) -> Self {
+        match (self, precision) {
+            (Self::F16(values), EmbeddingPrecision::F32) => Self::F32(values.into_iter().map(f16_to_f32).collect()),
+            (Self::F32(values), EmbeddingPrecision::F16) => Self::new(values, precision),
+            (stored, _) => stored,
+        }
+    }
+}
+
+/// Narrow an `f32` to binary16 bits, rounding to nearest even
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+
+    // Infinity and NaN, keeping NaNs quiet
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x0200 } else { 0 };
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    // Subnormal halves, or zero when too small for even those
+    if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - half_exponent) as u32;
+        let round_bit = 1 << (shift - 1);
+        let mut half = mantissa >> shift;
+        if mantissa & round_bit != 0 && mantissa & (3 * round_bit - 1) != 0 {
+            half += 1;
+        }
+        return sign | half as u16;
+    }
+
+    // A carry out of the mantissa correctly bumps the exponent
+    let round_bit = 0x1000;
+    let mut half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    if mantissa & round_bit != 0 && mantissa & (3 * round_bit - 1) != 0 {
+        half += 1;
+    }
+    sign | half as u16
+}
+
+/// Widen binary16 bits to an `f32`; exact for every half value
+pub fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x03ff) as u32;
+
+    match exponent {
+        0 => {
+            // Zero or subnormal: mantissa * 2^-24
+            let magnitude = mantissa as f32 * f32::from_bits(0x3380_0000);
+            if sign != 0 { -magnitude } else { magnitude }
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_f16_round_trip() {
+        for value in [0.0, -0.0, 1.0, -0.5, 65504.0, 2f32.powi(-14)] {
+            assert_eq!(f16_to_f32(f32_to_f16(value)), value);
+        }
+        assert!((f16_to_f32(f32_to_f16(0.1)) - 0.1).abs() < 1e-4);
+        assert!((f16_to_f32(f32_to_f16(1e-7)) - 1e-7).abs() < 6e-8);
+        assert_eq!(f16_to_f32(f32_to_f16(1e6)), f32::INFINITY);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+
+        // Halfway cases go to the even neighbour
+        assert_eq!(f16_to_f32(f32_to_f16(1.0 + 2f32.powi(-11))), 1.0);
+        assert_eq!(f16_to_f32(f32_to_f16(1.0 + 3.0 * 2f32.powi(-11))), 1.0 + 2f32.powi(-9));
+
+        let stored = StoredEmbedding::new(vec![0.25, -0.75, 0.5], EmbeddingPrecision::F16);
+        assert_eq!(stored.stored_len(), 6);
+        assert_eq!(stored.values().as_ref(), [0.25, -0.75, 0.5]);
+        let mut buffer = vec![];
+        assert_eq!(stored.values_in(&mut buffer), [0.25, -0.75, 0.5]);
+        assert_eq!(stored.clone().into_precision(EmbeddingPrecision::F16), stored);
+        assert_eq!(
+            stored.into_precision(EmbeddingPrecision::F32),
+            StoredEmbedding::F32(vec![0.25, -0.75, 0.5])
+        );
+    }
+}
@@ -0,0 +1,67 @@
+//! 1-bit embedding codes for a cheap search pre-filter
+//!
+//! Each dimension is reduced to its sign, packed 64 to a word. The Hamming
+//! distance between two codes approximates the angle between the original
+//! embeddings, so a popcount pass over all codes can pick the candidates
+//! worth scoring at full precision.
+
+/// Sign bits of `embedding`, 64 dimensions per word
+pub fn quantize(embedding: &[f32]) -> Vec<u64> {
+    embedding
+        .chunks(64)
+        .map(|dims| {
+            dims.iter()
+                .enumerate()
+                .filter(|(_, v)| **v > 0.0)
+                .fold(0u64, |code, (bit, _)| code | (1 << bit))
+        })
+        .collect()
+}
+
+/// Number of differing bits; codes of different lengths count the excess as
+/// differing
+pub fn hamming(a: &[u64], b: &[u64]) -> u32 {
+    let common: u32 = a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum();
+    let excess = a.len().abs_diff(b.len()) as u32 * 64;
+    common + excess
+}
+
+/// Indexes of the `n` codes closest to `query`, in no particular order
+pub fn nearest<'a>(query: &[u64], codes: impl Iterator<Item = &'a [u64]>, n: usize) -> Vec<usize> {
+    let mut distances: Vec<(u32, usize)> = codes
+        .enumerate()
+        .map(|(i, code)| (hamming(query, code), i))
+        .collect();
+
+    if n < distances.len() {
+        distances.select_nth_unstable(n);
+        distances.truncate(n);
+    }
+    distances.into_iter().map(|(_, i)| i).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hamming_prefilter() {
+        let query = quantize(&[0.5, -0.2, 0.1, -0.9]);
+        assert_eq!(query, vec![0b0101]);
+
+        let codes = [
+            quantize(&[-0.5, 0.2, -0.1, 0.9]),
+            quantize(&[0.4, -0.1, 0.3, -0.2]),
+            quantize(&[0.4, 0.1, 0.3, -0.2]),
+        ];
+        assert_eq!(hamming(&query, &codes[0]), 4);
+        assert_eq!(hamming(&query, &codes[1]), 0);
+
+        let mut closest = nearest(&query, codes.iter().map(|c| c.as_slice()), 2);
+        closest.sort();
+        assert_eq!(closest, vec![1, 2]);
+
+        // 70 dimensions span two words
+        assert_eq!(quantize(&[1.0; 70]).len(), 2);
+    }
+}
@@ -0,0 +1,35 @@
+use crate::types::VectorMetadata;
+
+/// Ranking hook applied to every candidate of a search
+///
+/// Gets the query embedding, the stored embedding and its metadata plus the
+/// built-in `similarity` under the store's
+/// [`DistanceMetric`](crate::config::DistanceMetric), and returns the score
+/// used for ranking.
+/// Return `similarity` adjusted to post-process the built-in score (e.g.
+/// boost completed orders), or compute something else to replace it.
+///
+/// A boost by entity type needs no struct, just a closure over the four
+/// arguments:
+///
+/// ```rust
+/// use contrag_core::vector_store::scoring::Scorer;
+/// use contrag_core::types::VectorMetadata;
+///
+/// let boost_orders = |_: &[f32], _: &[f32], meta: &VectorMetadata, similarity: f32| {
+///     if meta.entity_type == "Order" { similarity * 1.2 } else { similarity }
+/// };
+/// let _: &dyn Scorer = &boost_orders;
+/// ```
+pub trait Scorer: Send + Sync {
+    fn score(&self, query: &[f32], embedding: &[f32], metadata: &VectorMetadata, similarity: f32) -> f32;
+}
+
+impl<F> Scorer for F
+where
+    F: Fn(&[f32], &[f32], &VectorMetadata, f32) -> f32 + Send + Sync,
+{
+    fn score(&self, query: &[f32], embedding: &[f32], metadata: &VectorMetadata, similarity: f32) -> f32 {
+        self(query, embedding, metadata, similarity)
+    }
+}
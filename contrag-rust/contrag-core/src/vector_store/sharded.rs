@@ -0,0 +1,429 @@
+//! Vector storage spread over several canisters
+//!
+//! A single canister's stable memory caps how many vectors it can hold.
+//! [`ShardedVectorStore`] spreads namespaces over several shards by
+//! consistent hashing: each namespace lives entirely on one shard, so
+//! writes and single-namespace searches are one call, and adding a shard
+//! only moves the namespaces that hash onto it. Searches over several
+//! namespaces fan out to their shards concurrently and merge the top `k`.
+//!
+//! Shards are any [`VectorStore`]; [`CanisterShard`] forwards to a worker
+//! canister that exposes the [`shard_methods`] endpoints backed by its own
+//! local store, such as `examples/shard-worker`.
+
+use std::collections::{BTreeMap, HashMap};
+use candid::{encode_args, CandidType, Principal};
+use futures::future::join_all;
+use serde::de::DeserializeOwned;
+use crate::concurrency;
+use crate::error::{ContragError, Result};
+use crate::types::{BatchMode, BatchWriteReport, SearchFilter, SearchResult, Vector};
+use crate::utils::stable_hash;
+use crate::vector_store::VectorStore;
+
+/// Ring positions per shard; more spread namespaces more evenly
+const VIRTUAL_NODES: usize = 64;
+
+/// Methods a worker canister exposes for [`CanisterShard`]
+///
+/// Every method returns `variant { Ok : T; Err : text }`:
+///
+/// ```text
+/// shard_store_batch : (text, vec Vector, BatchMode) -> (Result<BatchWriteReport>)
+/// shard_search : (text, vec float32, nat64) -> (Result<vec SearchResult>)
+/// shard_delete : (text, text) -> (Result<null>)
+/// shard_delete_namespace : (text) -> (Result<null>)
+/// shard_delete_where : (text, SearchFilter) -> (Result<vec text>)
+/// shard_count : (text) -> (Result<nat64>) query
+/// shard_list_namespaces : () -> (Result<vec text>) query
+/// shard_export_namespace : (text) -> (Result<vec Vector>) query
+/// ```
+pub mod shard_methods {
+    pub const STORE_BATCH: &str = "shard_store_batch";
+    pub const SEARCH: &str = "shard_search";
+    pub const DELETE: &str = "shard_delete";
+    pub const DELETE_NAMESPACE: &str = "shard_delete_namespace";
+    pub const DELETE_WHERE: &str = "shard_delete_where";
+    pub const COUNT: &str = "shard_count";
+    pub const LIST_NAMESPACES: &str = "shard_list_namespaces";
+    pub const EXPORT_NAMESPACE: &str = "shard_export_namespace";
+}
+
+/// Consistent hash ring over shard names
+#[derive(Clone, Debug, Default)]
+pub struct HashRing {
+    points: BTreeMap<u64, usize>,
+}
+
+impl HashRing {
+    pub fn new<'a>(shards: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut points = BTreeMap::new();
+        for (index, name) in shards.into_iter().enumerate() {
+            for node in 0..VIRTUAL_NODES {
+                points.insert(stable_hash(&format!("{}#{}", name, node)), index);
+            }
+        }
+        Self { points }
+    }
+
+    /// Index of the shard owning `namespace`
+    pub fn route(&self, namespace: &str) -> Option<usize> {
+        let hash = stable_hash(namespace);
+        self.points
+            .range(hash..)
+            .next()
+            .or_else(|| self.points.iter().next())
+            .map(|(_, &shard)| shard)
+    }
+}
+
+/// Vector store routing each namespace to one of several shards
+pub struct ShardedVectorStore<S: VectorStore> {
+    names: Vec<String>,
+    shards: Vec<S>,
+    ring: HashRing,
+}
+
+impl<S: VectorStore> ShardedVectorStore<S> {
+    /// Shards with stable names, e.g. their canister IDs
+    ///
+    /// Routing depends only on the names, not their order, so keep them
+    /// stable when adding shards.
+    pub fn new(shards: Vec<(String, S)>) -> Result<Self> {
+        if shards.is_empty() {
+            return Err(ContragError::InvalidConfig(
+                "A sharded vector store needs at least one shard".to_string(),
+            ));
+        }
+
+        let (names, shards): (Vec<String>, Vec<S>) = shards.into_iter().unzip();
+        let ring = HashRing::new(names.iter().map(String::as_str));
+        Ok(Self { names, shards, ring })
+    }
+
+    /// Name of the shard owning `namespace`
+    pub fn shard_name(&self, namespace: &str) -> &str {
+        &self.names[self.index(namespace)]
+    }
+
+    /// Namespaces grouped by owning shard name, e.g. to plan moving data
+    /// after adding a shard
+    pub fn assignments(&self, namespaces: &[String]) -> HashMap<String, Vec<String>> {
+        let mut by_shard: HashMap<String, Vec<String>> = HashMap::new();
+        for namespace in namespaces {
+            by_shard
+                .entry(self.shard_name(namespace).to_string())
+                .or_default()
+                .push(namespace.clone());
+        }
+        by_shard
+    }
+
+    fn index(&self, namespace: &str) -> usize {
+        self.ring.route(namespace).unwrap_or(0)
+    }
+
+    fn shard(&self, namespace: &str) -> &S {
+        &self.shards[self.index(namespace)]
+    }
+
+    fn shard_mut(&mut self, namespace: &str) -> &mut S {
+        let index = self.index(namespace);
+        &mut self.shards[index]
+    }
+
+    /// Search several namespaces at once and merge the top `k`
+    ///
+    /// Namespaces are searched concurrently, so shards are called in
+    /// parallel. A failing namespace fails the whole search.
+    pub async fn search_namespaces(
+        &self,
+        namespaces: &[String],
+        query_embedding: Vec<f32>,
+        k: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let searches = namespaces
+            .iter()
+            .map(|namespace| self.shard(namespace).search(namespace, query_embedding.clone(), k));
+
+        let mut merged = vec![];
+        for results in join_all(searches).await {
+            merged.extend(results?);
+        }
+        merged.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        merged.truncate(k);
+        Ok(merged)
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: VectorStore> VectorStore for ShardedVectorStore<S> {
+    async fn store(&mut self, namespace: &str, vector: Vector) -> Result<()> {
+        self.shard_mut(namespace).store(namespace, vector).await
+    }
+
+    async fn store_batch_with(
+        &mut self,
+        namespace: &str,
+        vectors: Vec<Vector>,
+        mode: BatchMode,
+    ) -> Result<BatchWriteReport> {
+        self.shard_mut(namespace).store_batch_with(namespace, vectors, mode).await
+    }
+
+    async fn search(
+        &self,
+        namespace: &str,
+        query_embedding: Vec<f32>,
+        k: usize,
+    ) -> Result<Vec<SearchResult>> {
+        self.shard(namespace).search(namespace, query_embedding, k).await
+    }
+
+    async fn search_with_min_score(
+        &self,
+        namespace: &str,
+        query_embedding: Vec<f32>,
+        k: usize,
+        min_score: Option<f32>,
+    ) -> Result<Vec<SearchResult>> {
+        self.shard(namespace)
+            .search_with_min_score(namespace, query_embedding, k, min_score)
+            .await
+    }
+
+    async fn keyword_search(&self, namespace: &str, query: &str, k: usize) -> Result<Vec<SearchResult>> {
+        self.shard(namespace).keyword_search(namespace, query, k).await
+    }
+
+    async fn get(&self, namespace: &str, vector_id: &str) -> Result<Option<Vector>> {
+        self.shard(namespace).get(namespace, vector_id).await
+    }
+
+    async fn get_many(&self, namespace: &str, vector_ids: &[String]) -> Result<Vec<Vector>> {
+        self.shard(namespace).get_many(namespace, vector_ids).await
+    }
+
+    async fn get_by_entity(&self, namespace: &str, entity_type: &str, entity_id: &str) -> Result<Vec<Vector>> {
+        self.shard(namespace).get_by_entity(namespace, entity_type, entity_id).await
+    }
+
+    async fn list_vectors(&self, namespace: &str, offset: usize, limit: usize) -> Result<Vec<Vector>> {
+        self.shard(namespace).list_vectors(namespace, offset, limit).await
+    }
+
+    async fn delete(&mut self, namespace: &str, vector_id: &str) -> Result<()> {
+        self.shard_mut(namespace).delete(namespace, vector_id).await
+    }
+
+    async fn delete_namespace(&mut self, namespace: &str) -> Result<()> {
+        self.shard_mut(namespace).delete_namespace(namespace).await
+    }
+
+    async fn delete_where(&mut self, namespace: &str, filter: &SearchFilter) -> Result<Vec<String>> {
+        self.shard_mut(namespace).delete_where(namespace, filter).await
+    }
+
+    async fn delete_expired(
+        &mut self,
+        namespace: &str,
+        entity_type: &str,
+        cutoff: u64,
+    ) -> Result<Vec<String>> {
+        self.shard_mut(namespace).delete_expired(namespace, entity_type, cutoff).await
+    }
+
+    async fn delete_ttl_expired(&mut self, namespace: &str, now: u64) -> Result<Vec<String>> {
+        self.shard_mut(namespace).delete_ttl_expired(namespace, now).await
+    }
+
+    async fn sample(&self, namespace: &str, limit: usize) -> Result<Vec<Vector>> {
+        self.shard(namespace).sample(namespace, limit).await
+    }
+
+    async fn count(&self, namespace: &str) -> Result<usize> {
+        self.shard(namespace).count(namespace).await
+    }
+
+    /// Namespaces of all shards, sorted
+    async fn list_namespaces(&self) -> Result<Vec<String>> {
+        let mut namespaces = vec![];
+        for listed in join_all(self.shards.iter().map(|shard| shard.list_namespaces())).await {
+            namespaces.extend(listed?);
+        }
+        namespaces.sort();
+        Ok(namespaces)
+    }
+
+    async fn export_namespace(&self, namespace: &str) -> Result<Vec<Vector>> {
+        self.shard(namespace).export_namespace(namespace).await
+    }
+}
+
+/// A worker canister holding one shard
+///
+/// Forwards store operations to the worker's [`shard_methods`] endpoints.
+/// Operations without an endpoint fall back to the trait defaults, which
+/// build on `shard_export_namespace`.
+pub struct CanisterShard {
+    canister_id: Principal,
+}
+
+impl CanisterShard {
+    pub fn new(canister_id: Principal) -> Self {
+        Self { canister_id }
+    }
+
+    pub fn from_text(canister_id: &str) -> Result<Self> {
+        Principal::from_text(canister_id)
+            .map(Self::new)
+            .map_err(|e| ContragError::ConfigError(format!("Invalid canister ID: {}", e)))
+    }
+
+    pub fn canister_id(&self) -> Principal {
+        self.canister_id
+    }
+
+    /// Call a worker method returning `variant { Ok : T; Err : text }`
+    async fn call<T: CandidType + DeserializeOwned>(&self, method: &str, args: Vec<u8>) -> Result<T> {
+        let _permit = concurrency::acquire()?;
+
+        #[cfg(feature = "chaos")]
+        crate::chaos::slow_call().await;
+
+        #[cfg(target_family = "wasm")]
+        {
+            use candid::decode_one;
+            use ic_cdk::api::call::call_raw;
+
+            let bytes = call_raw(self.canister_id, method, args, 0)
+                .await
+                .map_err(|(code, msg)| {
+                    ContragError::CanisterCallError(format!("Shard call {} failed: {:?} - {}", method, code, msg))
+                })?;
+            let result: std::result::Result<T, String> = decode_one(&bytes).map_err(|e| {
+                ContragError::CanisterCallError(format!("Failed to decode shard response: {}", e))
+            })?;
+            result.map_err(ContragError::VectorStoreError)
+        }
+
+        #[cfg(not(target_family = "wasm"))]
+        {
+            let _ = (method, args);
+            Err(ContragError::CanisterCallError(
+                "Canister calls only work in WASM environment".to_string()
+            ))
+        }
+    }
+}
+
+fn encode_error(e: candid::Error) -> ContragError {
+    ContragError::SerializationError(format!("Failed to encode args: {}", e))
+}
+
+#[async_trait::async_trait]
+impl VectorStore for CanisterShard {
+    async fn store(&mut self, namespace: &str, vector: Vector) -> Result<()> {
+        self.store_batch_with(namespace, vec![vector], BatchMode::AllOrNothing)
+            .await
+            .map(|_| ())
+    }
+
+    async fn store_batch_with(
+        &mut self,
+        namespace: &str,
+        vectors: Vec<Vector>,
+        mode: BatchMode,
+    ) -> Result<BatchWriteReport> {
+        let args = encode_args((namespace, vectors, mode)).map_err(encode_error)?;
+        self.call(shard_methods::STORE_BATCH, args).await
+    }
+
+    async fn search(
+        &self,
+        namespace: &str,
+        query_embedding: Vec<f32>,
+        k: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let args = encode_args((namespace, query_embedding, k as u64)).map_err(encode_error)?;
+        self.call(shard_methods::SEARCH, args).await
+    }
+
+    async fn delete(&mut self, namespace: &str, vector_id: &str) -> Result<()> {
+        let args = encode_args((namespace, vector_id)).map_err(encode_error)?;
+        self.call(shard_methods::DELETE, args).await
+    }
+
+    async fn delete_namespace(&mut self, namespace: &str) -> Result<()> {
+        let args = encode_args((namespace,)).map_err(encode_error)?;
+        self.call(shard_methods::DELETE_NAMESPACE, args).await
+    }
+
+    async fn delete_where(&mut self, namespace: &str, filter: &SearchFilter) -> Result<Vec<String>> {
+        let args = encode_args((namespace, filter)).map_err(encode_error)?;
+        self.call(shard_methods::DELETE_WHERE, args).await
+    }
+
+    async fn count(&self, namespace: &str) -> Result<usize> {
+        let args = encode_args((namespace,)).map_err(encode_error)?;
+        self.call::<u64>(shard_methods::COUNT, args).await.map(|count| count as usize)
+    }
+
+    async fn list_namespaces(&self) -> Result<Vec<String>> {
+        let args = encode_args(()).map_err(encode_error)?;
+        self.call(shard_methods::LIST_NAMESPACES, args).await
+    }
+
+    async fn export_namespace(&self, namespace: &str) -> Result<Vec<Vector>> {
+        let args = encode_args((namespace,)).map_err(encode_error)?;
+        self.call(shard_methods::EXPORT_NAMESPACE, args).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::test_vector;
+    use crate::vector_store::stable_memory_store::StableMemoryVectorStore;
+
+    fn sharded(names: &[&str]) -> ShardedVectorStore<StableMemoryVectorStore> {
+        ShardedVectorStore::new(
+            names
+                .iter()
+                .map(|name| (name.to_string(), StableMemoryVectorStore::new()))
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_routes_and_merges_across_shards() {
+        let mut store = sharded(&["shard-a", "shard-b", "shard-c"]);
+        let namespaces: Vec<String> = (0..30).map(|i| format!("User:{}", i)).collect();
+        for (i, namespace) in namespaces.iter().enumerate() {
+            let embedding = vec![1.0, i as f32 / 30.0];
+            store.store(namespace, test_vector(&format!("v{}", i), embedding)).await.unwrap();
+        }
+
+        // Every shard got some namespaces, and each namespace is on exactly one
+        let by_shard = store.assignments(&namespaces);
+        assert_eq!(by_shard.len(), 3);
+        for shard in &store.shards {
+            let held = shard.list_namespaces().await.unwrap();
+            assert!(held.iter().all(|ns| by_shard[store.shard_name(ns)].contains(ns)));
+        }
+        assert_eq!(store.list_namespaces().await.unwrap().len(), 30);
+        assert_eq!(store.count("User:7").await.unwrap(), 1);
+
+        let results = store.search_namespaces(&namespaces, vec![1.0, 0.0], 3).await.unwrap();
+        let ids: Vec<&str> = results.iter().map(|r| r.vector_id.as_str()).collect();
+        assert_eq!(ids, ["v0", "v1", "v2"]);
+
+        // Adding a shard only moves namespaces onto the new shard
+        let grown = sharded(&["shard-a", "shard-b", "shard-c", "shard-d"]);
+        for namespace in &namespaces {
+            let after = grown.shard_name(namespace);
+            assert!(after == store.shard_name(namespace) || after == "shard-d");
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Kernels behind the similarity functions
+//!
+//! With the `wasm-simd` feature on a `wasm32` build that enables `simd128`
+//! (`RUSTFLAGS="-C target-feature=+simd128"`), four lanes are multiplied and
+//! accumulated per instruction. Every other build uses the scalar loops, so
+//! the feature is safe to leave on for native tests.
+
+#[cfg(all(feature = "wasm-simd", target_arch = "wasm32", target_feature = "simd128"))]
+mod lanes {
+    use core::arch::wasm32::{f32x4, f32x4_add, f32x4_extract_lane, f32x4_mul, f32x4_splat, f32x4_sub, v128};
+
+    fn load(chunk: &[f32]) -> v128 {
+        f32x4(chunk[0], chunk[1], chunk[2], chunk[3])
+    }
+
+    fn sum(v: v128) -> f32 {
+        f32x4_extract_lane::<0>(v)
+            + f32x4_extract_lane::<1>(v)
+            + f32x4_extract_lane::<2>(v)
+            + f32x4_extract_lane::<3>(v)
+    }
+
+    pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+        let (a, b) = (a.chunks_exact(4), b.chunks_exact(4));
+        let tail = super::scalar::dot(a.remainder(), b.remainder());
+
+        let mut acc = f32x4_splat(0.0);
+        for (x, y) in a.zip(b) {
+            acc = f32x4_add(acc, f32x4_mul(load(x), load(y)));
+        }
+        sum(acc) + tail
+    }
+
+    pub fn dot_and_norms(a: &[f32], b: &[f32]) -> (f32, f32, f32) {
+        let (a, b) = (a.chunks_exact(4), b.chunks_exact(4));
+        let (dot, aa, bb) = super::scalar::dot_and_norms(a.remainder(), b.remainder());
+
+        let (mut ab_acc, mut aa_acc, mut bb_acc) = (f32x4_splat(0.0), f32x4_splat(0.0), f32x4_splat(0.0));
+        for (x, y) in a.zip(b) {
+            let (x, y) = (load(x), load(y));
+            ab_acc = f32x4_add(ab_acc, f32x4_mul(x, y));
+            aa_acc = f32x4_add(aa_acc, f32x4_mul(x, x));
+            bb_acc = f32x4_add(bb_acc, f32x4_mul(y, y));
+        }
+        (sum(ab_acc) + dot, sum(aa_acc) + aa, sum(bb_acc) + bb)
+    }
+
+    pub fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+        let (a, b) = (a.chunks_exact(4), b.chunks_exact(4));
+        let tail = super::scalar::squared_distance(a.remainder(), b.remainder());
+
+        let mut acc = f32x4_splat(0.0);
+        for (x, y) in a.zip(b) {
+            let d = f32x4_sub(load(x), load(y));
+            acc = f32x4_add(acc, f32x4_mul(d, d));
+        }
+        sum(acc) + tail
+    }
+}
+
+mod scalar {
+    pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+
+    pub fn dot_and_norms(a: &[f32], b: &[f32]) -> (f32, f32, f32) {
+        a.iter()
+            .zip(b)
+            .fold((0.0, 0.0, 0.0), |(ab, aa, bb), (x, y)| (ab + x * y, aa + x * x, bb + y * y))
+    }
+
+    pub fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum()
+    }
+}
+
+#[cfg(all(feature = "wasm-simd", target_arch = "wasm32", target_feature = "simd128"))]
+pub(crate) use lanes::{dot, dot_and_norms, squared_distance};
+
+#[cfg(not(all(feature = "wasm-simd", target_arch = "wasm32", target_feature = "simd128")))]
+pub(crate) use scalar::{dot, dot_and_norms, squared_distance};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kernels_cover_the_tail() {
+        let a = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+        let b = [7.0, 6.0, 5.0, 4.0, 3.0, 2.0, 1.0];
+
+        assert_eq!(dot(&a, &b), 84.0);
+        assert_eq!(dot_and_norms(&a, &b), (84.0, 140.0, 140.0));
+        assert_eq!(squared_distance(&a, &b), 112.0);
+        assert_eq!(dot(&[], &[]), 0.0);
+    }
+}
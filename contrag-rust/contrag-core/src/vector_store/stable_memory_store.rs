@@ -1,56 +1,1153 @@
-use std::sync::{Arc, RwLock};
-use std::collections::HashMap;
-use crate::vector_store::{VectorStore, cosine_similarity};
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use candid::{CandidType, Principal};
+use ic_stable_structures::Memory;
+use serde::{Deserialize, Serialize};
+use crate::vector_store::{
+    VectorStore, advance_cursor, normalized_similarity, paginate_namespaces, prefix_page_request, similarity,
+    truncate_embedding,
+};
+use crate::vector_store::acl::NamespaceAcl;
+use crate::vector_store::compression::StoredText;
+use crate::vector_store::precision::StoredEmbedding;
+use crate::vector_store::keyword::KeywordIndex;
+use crate::vector_store::quantization;
+use crate::vector_store::scoring::Scorer;
+use crate::config::{
+    DistanceMetric, EmbeddingPrecision, EvictionPolicy, NamespaceQuota, QuantizationConfig, TextCompressionConfig,
+    VectorStoreConfig,
+};
 use crate::error::{ContragError, Result};
-use crate::types::{Vector, SearchResult};
+use crate::logs::{self, LogEvent, LogLevel};
+use crate::monitoring;
+use crate::storage::memory::{read_blob, write_blob};
+use crate::storage::migrations::encode_versioned;
+use crate::storage::{Migration, Migrator, StorageComponent};
+use crate::types::{
+    BatchMode, BatchWriteReport, BulkCursor, NamespaceInfo, NamespaceListRequest, NamespacePage, NamespaceStats,
+    PrefixStats, SearchFilter, SearchResult, StoreStats, Vector, VectorMetadata, VectorVersion, expiry_timestamp,
+};
 
 /// Vector store implementation using ICP stable memory
 /// 
-/// This stores vectors persistently across canister upgrades.
+/// Vectors are served from the heap and survive upgrades through
+/// [`Self::persist`] and [`Self::init`] (or [`Self::with_memory`]).
 /// 
-/// Note: In a production implementation, this would use ic-stable-structures
-/// for true persistent storage. This version uses Arc<RwLock> for thread-safety.
+/// Canisters run single-threaded, so the maps are owned directly instead of
+/// behind locks: writes go through `&mut self`, no method can fail on a
+/// poisoned lock, and nothing can be poisoned by an earlier panic.
 pub struct StableMemoryVectorStore {
     // In-memory index for fast lookup (rebuilt on init)
-    vectors: Arc<RwLock<HashMap<String, Vec<StoredVector>>>>,
+    vectors: HashMap<String, Vec<StoredVector>>,
     // Metadata about namespaces
-    namespaces: Arc<RwLock<Vec<String>>>,
+    namespaces: Vec<String>,
+    // Similarity measure for search scores
+    metric: DistanceMetric,
+    // Map scores into 0..=1 so they compare across metrics
+    normalize_scores: bool,
+    // Results scoring below this are dropped unless a search overrides it
+    min_score: Option<f32>,
+    // Hamming pre-filter over 1-bit codes; disabled by default
+    quantization: QuantizationConfig,
+    // Lifetime of vectors without their own TTL
+    ttl_seconds: Option<u64>,
+    // Size limits per namespace and how they are enforced
+    quota: NamespaceQuota,
+    // Logical clock ordering writes and search hits for LRU eviction
+    clock: AtomicU64,
+    // BM25 index per namespace; `None` unless keyword search is enabled
+    keywords: Option<HashMap<String, KeywordIndex>>,
+    // Deflating of chunk text on write; disabled by default
+    text_compression: TextCompressionConfig,
+    // Width of stored embeddings; full f32 by default
+    precision: EmbeddingPrecision,
+    // Longer embeddings and queries are cut to this many dimensions
+    target_dimensions: Option<usize>,
+    // Optional ranking hook replacing or adjusting the metric's score
+    scorer: Option<Arc<dyn Scorer>>,
+    // Namespace owners consulted by canister endpoints
+    acl: NamespaceAcl,
+    // Replaced and deleted versions per namespace; `None` unless versioning
+    // is enabled
+    history: Option<HashMap<String, Vec<RetiredVector>>>,
+    // Last version handed out to a write or delete
+    version: u64,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 struct StoredVector {
     id: String,
-    embedding: Vec<f32>,
-    text: String,
+    embedding: StoredEmbedding,
+    // Sign bits of the embedding for the quantized pre-filter
+    code: Vec<u64>,
+    text: StoredText,
     entity_type: String,
     entity_id: String,
     chunk_index: usize,
     total_chunks: usize,
     timestamp: u64,
+    custom: Option<String>,
+    ttl_seconds: Option<u64>,
+    // Store-wide number of the write that stored this vector
+    version: u64,
+    // Clock tick of the last write or search hit
+    last_access: AtomicU64,
+}
+
+// Copies get a fresh access clock, as the copy has not been read yet
+impl Clone for StoredVector {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id.clone(),
+            embedding: self.embedding.clone(),
+            code: self.code.clone(),
+            text: self.text.clone(),
+            entity_type: self.entity_type.clone(),
+            entity_id: self.entity_id.clone(),
+            chunk_index: self.chunk_index,
+            total_chunks: self.total_chunks,
+            timestamp: self.timestamp,
+            custom: self.custom.clone(),
+            ttl_seconds: self.ttl_seconds,
+            version: self.version,
+            last_access: AtomicU64::new(0),
+        }
+    }
+}
+
+/// What [`StableMemoryVectorStore::persist`] writes to stable memory
+#[derive(Serialize, Deserialize, CandidType)]
+struct Snapshot {
+    namespaces: Vec<(String, Vec<PersistedVector>)>,
+    acl: NamespaceAcl,
+    // Retired versions per namespace; empty unless versioning is enabled
+    history: Vec<(String, Vec<PersistedRetired>)>,
+    // Last version handed out, so versions keep counting after an upgrade
+    version: u64,
+}
+
+/// A vector as persisted, with its embedding at the precision it was
+/// stored at, so half-precision stores take half the stable memory too
+#[derive(Serialize, Deserialize, CandidType)]
+struct PersistedVector {
+    id: String,
+    embedding: StoredEmbedding,
+    text: String,
+    metadata: VectorMetadata,
+    version: u64,
+}
+
+/// A retired version as persisted
+#[derive(Serialize, Deserialize, CandidType)]
+struct PersistedRetired {
+    vector: PersistedVector,
+    retired_at: u64,
+    deleted: bool,
+}
+
+/// Layout of [`Snapshot`] before version 3, without version history
+#[derive(Serialize, Deserialize, CandidType)]
+struct SnapshotV2 {
+    namespaces: Vec<(String, Vec<PersistedVectorV2>)>,
+    acl: NamespaceAcl,
+}
+
+/// Layout of [`PersistedVector`] before version 3, without its version
+#[derive(Serialize, Deserialize, CandidType)]
+struct PersistedVectorV2 {
+    id: String,
+    embedding: StoredEmbedding,
+    text: String,
+    metadata: VectorMetadata,
+}
+
+/// Layout of [`Snapshot`] before version 2, with `f32` embeddings
+#[derive(Serialize, Deserialize, CandidType)]
+struct SnapshotV1 {
+    namespaces: Vec<(String, Vec<Vector>)>,
+    acl: NamespaceAcl,
+}
+
+/// Moves persisted vectors from version 1, which widened every embedding to
+/// `f32`, to version 2, which keeps the stored precision
+struct KeepStoredPrecision;
+
+impl Migration for KeepStoredPrecision {
+    fn component(&self) -> StorageComponent {
+        StorageComponent::Vectors
+    }
+
+    fn from_version(&self) -> u32 {
+        1
+    }
+
+    fn description(&self) -> &str {
+        "persist embeddings at their stored precision"
+    }
+
+    fn migrate(&self, payload: Vec<u8>) -> Result<Vec<u8>> {
+        let old: SnapshotV1 = candid::decode_one(&payload)
+            .map_err(|e| ContragError::StorageError(format!("Failed to decode stored vectors: {}", e)))?;
+        let snapshot = SnapshotV2 {
+            namespaces: old
+                .namespaces
+                .into_iter()
+                .map(|(namespace, vectors)| {
+                    let vectors = vectors
+                        .into_iter()
+                        .map(|v| PersistedVectorV2 {
+                            id: v.id,
+                            embedding: StoredEmbedding::F32(v.embedding),
+                            text: v.text,
+                            metadata: v.metadata,
+                        })
+                        .collect();
+                    (namespace, vectors)
+                })
+                .collect(),
+            acl: old.acl,
+        };
+        candid::encode_one(&snapshot)
+            .map_err(|e| ContragError::StorageError(format!("Failed to encode stored vectors: {}", e)))
+    }
+}
+
+/// Moves persisted vectors from version 2 to version 3, which keeps each
+/// vector's version and the version history
+///
+/// Version 2 kept no versions; vectors are numbered in stored order, as
+/// loading them used to do.
+struct KeepVersionHistory;
+
+impl Migration for KeepVersionHistory {
+    fn component(&self) -> StorageComponent {
+        StorageComponent::Vectors
+    }
+
+    fn from_version(&self) -> u32 {
+        2
+    }
+
+    fn description(&self) -> &str {
+        "persist vector versions and version history"
+    }
+
+    fn migrate(&self, payload: Vec<u8>) -> Result<Vec<u8>> {
+        let old: SnapshotV2 = candid::decode_one(&payload)
+            .map_err(|e| ContragError::StorageError(format!("Failed to decode stored vectors: {}", e)))?;
+        let mut version = 0;
+        let namespaces = old
+            .namespaces
+            .into_iter()
+            .map(|(namespace, vectors)| {
+                let vectors = vectors
+                    .into_iter()
+                    .map(|v| {
+                        version += 1;
+                        PersistedVector {
+                            id: v.id,
+                            embedding: v.embedding,
+                            text: v.text,
+                            metadata: v.metadata,
+                            version,
+                        }
+                    })
+                    .collect();
+                (namespace, vectors)
+            })
+            .collect();
+        let snapshot = Snapshot {
+            namespaces,
+            acl: old.acl,
+            history: vec![],
+            version,
+        };
+        candid::encode_one(&snapshot)
+            .map_err(|e| ContragError::StorageError(format!("Failed to encode stored vectors: {}", e)))
+    }
+}
+
+/// A version replaced by a later write or removed by a delete
+#[derive(Debug)]
+struct RetiredVector {
+    vector: StoredVector,
+    retired_at: u64,
+    deleted: bool,
+}
+
+impl RetiredVector {
+    fn visible_at(&self, version: u64) -> bool {
+        self.vector.version <= version && version < self.retired_at
+    }
+}
+
+/// A search candidate ordered by score, earlier candidates first on ties
+struct Ranked<'a> {
+    score: f32,
+    position: usize,
+    vector: &'a StoredVector,
+}
+
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Ranked<'_> {}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then(other.position.cmp(&self.position))
+    }
+}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl StoredVector {
+    fn size_bytes(&self) -> u64 {
+        (self.embedding.stored_len() + self.text.stored_len() + self.id.len()) as u64
+    }
+
+    fn metadata(&self) -> crate::types::VectorMetadata {
+        crate::types::VectorMetadata {
+            entity_type: self.entity_type.clone(),
+            entity_id: self.entity_id.clone(),
+            chunk_index: self.chunk_index,
+            total_chunks: self.total_chunks,
+            timestamp: self.timestamp,
+            custom: self.custom.clone(),
+            ttl_seconds: self.ttl_seconds,
+        }
+    }
+
+    fn to_vector(&self) -> Vector {
+        Vector {
+            id: self.id.clone(),
+            embedding: self.embedding.values().into_owned(),
+            text: self.text.text().into_owned(),
+            metadata: self.metadata(),
+        }
+    }
+
+    fn to_persisted(&self) -> PersistedVector {
+        PersistedVector {
+            id: self.id.clone(),
+            embedding: self.embedding.clone(),
+            text: self.text.text().into_owned(),
+            metadata: self.metadata(),
+            version: self.version,
+        }
+    }
+}
+
+impl StoredVector {
+    fn new(vector: Vector, text_compression: &TextCompressionConfig, precision: EmbeddingPrecision) -> Self {
+        Self {
+            id: vector.id,
+            code: quantization::quantize(&vector.embedding),
+            embedding: StoredEmbedding::new(vector.embedding, precision),
+            text: StoredText::new(vector.text, text_compression),
+            entity_type: vector.metadata.entity_type,
+            entity_id: vector.metadata.entity_id,
+            chunk_index: vector.metadata.chunk_index,
+            total_chunks: vector.metadata.total_chunks,
+            timestamp: vector.metadata.timestamp,
+            custom: vector.metadata.custom,
+            ttl_seconds: vector.metadata.ttl_seconds,
+            version: 0,
+            last_access: AtomicU64::new(0),
+        }
+    }
+
+    /// A persisted vector, converted to `precision` if it was stored at
+    /// another one
+    fn restore(vector: PersistedVector, text_compression: &TextCompressionConfig, precision: EmbeddingPrecision) -> Self {
+        let metadata = vector.metadata;
+        Self {
+            id: vector.id,
+            code: quantization::quantize(&vector.embedding.values()),
+            embedding: vector.embedding.into_precision(precision),
+            text: StoredText::new(vector.text, text_compression),
+            entity_type: metadata.entity_type,
+            entity_id: metadata.entity_id,
+            chunk_index: metadata.chunk_index,
+            total_chunks: metadata.total_chunks,
+            timestamp: metadata.timestamp,
+            custom: metadata.custom,
+            ttl_seconds: metadata.ttl_seconds,
+            version: vector.version,
+            last_access: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Check a vector against the namespace's embedding dimensions
+pub(crate) fn validate(vector: &Vector, dimensions: Option<usize>) -> Result<()> {
+    if vector.embedding.is_empty() {
+        return Err(ContragError::VectorStoreError("Empty embedding".to_string()));
+    }
+    match dimensions {
+        Some(expected) if expected != vector.embedding.len() => Err(ContragError::DimensionMismatch {
+            expected,
+            actual: vector.embedding.len(),
+        }),
+        _ => Ok(()),
+    }
 }
 
 impl StableMemoryVectorStore {
     /// Create a new stable memory vector store
     pub fn new() -> Self {
         Self {
-            vectors: Arc::new(RwLock::new(HashMap::new())),
-            namespaces: Arc::new(RwLock::new(Vec::new())),
+            vectors: HashMap::new(),
+            namespaces: Vec::new(),
+            metric: DistanceMetric::default(),
+            normalize_scores: false,
+            min_score: None,
+            quantization: QuantizationConfig::default(),
+            ttl_seconds: None,
+            quota: NamespaceQuota::default(),
+            clock: AtomicU64::new(0),
+            keywords: None,
+            text_compression: TextCompressionConfig::default(),
+            precision: EmbeddingPrecision::default(),
+            target_dimensions: None,
+            scorer: None,
+            acl: NamespaceAcl::default(),
+            history: None,
+            version: 0,
+        }
+    }
+
+    /// Create a store using the configured distance metric and scoring,
+    /// quantization, default TTL, quota, keyword index, text compression,
+    /// embedding precision, target dimensions and versioning
+    pub fn from_config(config: &VectorStoreConfig) -> Self {
+        Self::new()
+            .with_metric(config.distance_metric)
+            .with_normalized_scores(config.normalize_scores)
+            .with_min_score(config.min_score)
+            .with_quantization(config.quantization.clone())
+            .with_ttl(config.ttl_seconds)
+            .with_quota(config.quota.clone())
+            .with_keyword_index(config.keyword_index)
+            .with_text_compression(config.text_compression.clone())
+            .with_precision(config.precision)
+            .with_target_dimensions(config.target_dimensions)
+            .with_versioning(config.versioning)
+    }
+
+    /// Deflate chunk text of vectors stored from now on
+    ///
+    /// Reads inflate it transparently; quotas count the compressed size.
+    pub fn with_text_compression(mut self, text_compression: TextCompressionConfig) -> Self {
+        self.text_compression = text_compression;
+        self
+    }
+
+    /// Store embeddings of later writes at `precision`
+    ///
+    /// Half precision halves embedding memory; reads and scoring widen the
+    /// values back to `f32`, so vectors come back slightly rounded.
+    pub fn with_precision(mut self, precision: EmbeddingPrecision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Truncate longer embeddings to `dimensions` and renormalize them
+    ///
+    /// For Matryoshka-trained models such as OpenAI's `text-embedding-3`,
+    /// whose leading dimensions carry most of the meaning. Queries are cut
+    /// the same way, so the embedder may keep returning full embeddings.
+    pub fn with_target_dimensions(mut self, dimensions: Option<usize>) -> Self {
+        self.target_dimensions = dimensions;
+        self
+    }
+
+    /// Maintain a BM25 index of chunk text for [`VectorStore::keyword_search`]
+    ///
+    /// Enable before storing vectors; vectors stored earlier are not indexed.
+    pub fn with_keyword_index(mut self, enabled: bool) -> Self {
+        self.keywords = enabled.then(HashMap::new);
+        self
+    }
+
+    fn index_keywords(&mut self, namespace: &str, vectors: &[StoredVector]) {
+        if let Some(keywords) = &mut self.keywords {
+            let index = keywords.entry(namespace.to_string()).or_default();
+            for vector in vectors {
+                index.insert(&vector.id, &vector.text.text());
+            }
+        }
+    }
+
+    fn unindex_keywords<'a>(&mut self, namespace: &str, vector_ids: impl IntoIterator<Item = &'a String>) {
+        if let Some(index) = self.keywords.as_mut().and_then(|k| k.get_mut(namespace)) {
+            for vector_id in vector_ids {
+                index.remove(vector_id);
+            }
+        }
+    }
+
+    /// Limit the size of every namespace
+    pub fn with_quota(mut self, quota: NamespaceQuota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Fit incoming vectors into the namespace quota
+    ///
+    /// Under [`EvictionPolicy::Reject`] vectors that do not fit are refused:
+    /// the whole batch in all-or-nothing mode, only those vectors otherwise.
+    /// The evicting policies remove existing vectors until the batch fits
+    /// and fail if the batch alone exceeds the quota.
+    fn enforce_quota(
+        &mut self,
+        namespace: &str,
+        incoming: Vec<StoredVector>,
+        mode: BatchMode,
+        report: &mut BatchWriteReport,
+    ) -> Result<Vec<StoredVector>> {
+        if self.quota.is_unlimited() {
+            return Ok(incoming);
+        }
+        let max_vectors = self.quota.max_vectors.unwrap_or(usize::MAX);
+        let max_bytes = self.quota.max_bytes.unwrap_or(u64::MAX);
+        // Vectors the batch replaces don't count against it
+        let replaced: HashSet<String> = incoming.iter().map(|v| v.id.clone()).collect();
+        let stored: Vec<&StoredVector> = self
+            .vectors
+            .get(namespace)
+            .into_iter()
+            .flatten()
+            .filter(|v| !replaced.contains(&v.id))
+            .collect();
+        let mut count = stored.len();
+        let mut bytes: u64 = stored.iter().map(|v| v.size_bytes()).sum();
+
+        let key: fn(&StoredVector) -> u64 = match self.quota.eviction {
+            EvictionPolicy::Lru => |v| v.last_access.load(Ordering::Relaxed),
+            EvictionPolicy::OldestFirst => |v| v.timestamp,
+            EvictionPolicy::Reject => {
+                let mut kept = Vec::with_capacity(incoming.len());
+                for vector in incoming {
+                    let size = vector.size_bytes();
+                    if count < max_vectors && bytes + size <= max_bytes {
+                        count += 1;
+                        bytes += size;
+                        kept.push(vector);
+                    } else if mode == BatchMode::BestEffort {
+                        report.reject(&vector.id, format!("Namespace {} is over its quota", namespace));
+                    } else {
+                        return Err(ContragError::VectorStoreError(format!(
+                            "Batch rejected, namespace {} is over its quota",
+                            namespace
+                        )));
+                    }
+                }
+                return Ok(kept);
+            }
+        };
+
+        let incoming_bytes: u64 = incoming.iter().map(StoredVector::size_bytes).sum();
+        if incoming.len() > max_vectors || incoming_bytes > max_bytes {
+            return Err(ContragError::VectorStoreError(format!(
+                "Batch rejected, it alone exceeds the quota of namespace {}",
+                namespace
+            )));
+        }
+
+        let mut victims = stored;
+        victims.sort_by_key(|v| key(v));
+        let mut evicted = HashSet::new();
+        for victim in victims {
+            if count + incoming.len() <= max_vectors && bytes + incoming_bytes <= max_bytes {
+                break;
+            }
+            count -= 1;
+            bytes -= victim.size_bytes();
+            evicted.insert(victim.id.clone());
+        }
+
+        if !evicted.is_empty() {
+            if let Some(stored) = self.vectors.get_mut(namespace) {
+                stored.retain(|v| !evicted.contains(&v.id));
+            }
+            self.unindex_keywords(namespace, &evicted);
+            logs::record(
+                LogEvent::new(
+                    LogLevel::Info,
+                    "vector_store",
+                    format!("Evicted {} vectors over the namespace quota", evicted.len()),
+                )
+                .with_field("namespace", namespace)
+                .with_field("policy", format!("{:?}", self.quota.eviction)),
+            );
+        }
+
+        Ok(incoming)
+    }
+
+    /// Expire vectors without their own `ttl_seconds` after `ttl_seconds`
+    pub fn with_ttl(mut self, ttl_seconds: Option<u64>) -> Self {
+        self.ttl_seconds = ttl_seconds;
+        self
+    }
+
+    /// Score search results with `metric` instead of cosine similarity
+    pub fn with_metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn metric(&self) -> DistanceMetric {
+        self.metric
+    }
+
+    /// Report scores mapped into 0..=1 by [`normalized_similarity`]
+    pub fn with_normalized_scores(mut self, normalize_scores: bool) -> Self {
+        self.normalize_scores = normalize_scores;
+        self
+    }
+
+    /// Drop search results scoring below `min_score` by default
+    pub fn with_min_score(mut self, min_score: Option<f32>) -> Self {
+        self.min_score = min_score;
+        self
+    }
+
+    fn similarity(&self, query: &[f32], embedding: &[f32]) -> f32 {
+        if self.normalize_scores {
+            normalized_similarity(self.metric, query, embedding)
+        } else {
+            similarity(self.metric, query, embedding)
+        }
+    }
+
+    /// The query cut to the target dimensions, if any
+    fn prepare_query<'q>(&self, query_embedding: &'q [f32]) -> Cow<'q, [f32]> {
+        match self.target_dimensions {
+            Some(target) if query_embedding.len() > target => {
+                let mut query = query_embedding.to_vec();
+                truncate_embedding(&mut query, target);
+                Cow::Owned(query)
+            }
+            _ => Cow::Borrowed(query_embedding),
+        }
+    }
+
+    /// Top `k` results scoring at least `min_score`, marked as recently used
+    fn search_scored(
+        &self,
+        namespace: &str,
+        query_embedding: &[f32],
+        k: usize,
+        min_score: Option<f32>,
+    ) -> Vec<SearchResult> {
+        // Unknown namespaces are simply empty
+        let namespace_vectors = match self.vectors.get(namespace) {
+            Some(v) if !v.is_empty() && k > 0 => v,
+            _ => return vec![],
+        };
+
+        let query_embedding = self.prepare_query(query_embedding);
+        let candidates = self.candidates(namespace_vectors, &query_embedding, k);
+        self.rank(candidates, &query_embedding, k, min_score)
+    }
+
+    /// Score `candidates` by reference, keeping only the best `k` in a
+    /// min-heap, and return them best first
+    fn rank<'a>(
+        &self,
+        candidates: impl IntoIterator<Item = &'a StoredVector>,
+        query_embedding: &[f32],
+        k: usize,
+        min_score: Option<f32>,
+    ) -> Vec<SearchResult> {
+        if k == 0 {
+            return vec![];
+        }
+
+        let mut top: BinaryHeap<Reverse<Ranked>> = BinaryHeap::with_capacity(k.saturating_add(1));
+        // Half-precision embeddings are widened into one reused buffer
+        let mut widened = vec![];
+        for (position, v) in candidates.into_iter().enumerate() {
+            let embedding = v.embedding.values_in(&mut widened);
+            let similarity = self.similarity(query_embedding, embedding);
+            let score = match &self.scorer {
+                Some(scorer) => scorer.score(query_embedding, embedding, &v.metadata(), similarity),
+                None => similarity,
+            };
+            if min_score.is_some_and(|min_score| score < min_score) {
+                continue;
+            }
+            let ranked = Ranked { score, position, vector: v };
+            if top.len() < k {
+                top.push(Reverse(ranked));
+            } else if top.peek().is_some_and(|Reverse(worst)| ranked > *worst) {
+                top.pop();
+                top.push(Reverse(ranked));
+            }
+        }
+
+        // Mark the results as recently used
+        let hit = self.tick();
+        top.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(Ranked { score, vector: v, .. })| {
+                v.last_access.store(hit, Ordering::Relaxed);
+                SearchResult {
+                    vector_id: v.id.clone(),
+                    text: v.text.text().into_owned(),
+                    score,
+                    metadata: v.metadata(),
+                }
+            })
+            .collect()
+    }
+
+    /// Pre-filter large namespaces by Hamming distance of 1-bit codes
+    ///
+    /// Only the `k * rerank_factor` closest codes are scored at full
+    /// precision, trading a little recall for far fewer instructions.
+    pub fn with_quantization(mut self, quantization: QuantizationConfig) -> Self {
+        self.quantization = quantization;
+        self
+    }
+
+    /// Vectors of a namespace worth scoring for a top-`k` search
+    fn candidates<'a>(&self, stored: &'a [StoredVector], query: &[f32], k: usize) -> Vec<&'a StoredVector> {
+        let config = &self.quantization;
+        let limit = k.saturating_mul(config.rerank_factor.max(1));
+        if !config.enabled || stored.len() < config.min_vectors || stored.len() <= limit {
+            return stored.iter().collect();
+        }
+
+        let code = quantization::quantize(query);
+        quantization::nearest(&code, stored.iter().map(|v| v.code.as_slice()), limit)
+            .into_iter()
+            .map(|i| &stored[i])
+            .collect()
+    }
+
+    /// Rank search results with a custom [`Scorer`]
+    pub fn with_scorer(mut self, scorer: Arc<dyn Scorer>) -> Self {
+        self.scorer = Some(scorer);
+        self
+    }
+
+    /// Replace or remove the custom [`Scorer`]
+    pub fn set_scorer(&mut self, scorer: Option<Arc<dyn Scorer>>) {
+        self.scorer = scorer;
+    }
+
+    /// Owners of this store's namespaces
+    pub fn acl(&self) -> &NamespaceAcl {
+        &self.acl
+    }
+
+    /// Replace the ACL, e.g. with one restored after an upgrade
+    pub fn set_acl(&mut self, acl: NamespaceAcl) {
+        self.acl = acl;
+    }
+
+    /// Restrict `namespace` and the namespaces below it to `owner`
+    pub fn set_namespace_owner(&mut self, namespace: &str, owner: Principal) {
+        self.acl.set_namespace_owner(namespace, owner);
+    }
+
+    /// Fail unless `caller` may access `namespace` (see [`NamespaceAcl`])
+    pub fn check_access(&self, namespace: &str, caller: &Principal) -> Result<()> {
+        self.acl.check_access(namespace, caller)
+    }
+
+    /// Open a store over `memory`, restoring what [`Self::persist`] last
+    /// wrote there
+    pub fn with_memory(memory: impl Memory) -> Result<Self> {
+        let mut store = Self::new();
+        store.init(&memory)?;
+        Ok(store)
+    }
+
+    /// Load the vectors and namespace owners [`Self::persist`] wrote to
+    /// `memory`; empty memory loads nothing
+    ///
+    /// Call this during canister init or post_upgrade, after configuring the
+    /// store: restored vectors are stored with its current precision and text
+    /// compression. Vectors keep their versions; the version history is
+    /// restored if versioning is enabled and dropped otherwise.
+    pub fn init(&mut self, memory: &(impl Memory + ?Sized)) -> Result<()> {
+        let Some(raw) = read_blob(memory)? else {
+            return Ok(());
+        };
+        let payload = Migrator::new()
+            .register(KeepStoredPrecision)
+            .register(KeepVersionHistory)
+            .load(StorageComponent::Vectors, &raw)?;
+        let snapshot: Snapshot = candid::decode_one(&payload)
+            .map_err(|e| ContragError::StorageError(format!("Failed to decode stored vectors: {}", e)))?;
+
+        for (namespace, vectors) in snapshot.namespaces {
+            let restored: Vec<StoredVector> = vectors
+                .into_iter()
+                .map(|v| StoredVector::restore(v, &self.text_compression, self.precision))
+                .collect();
+            self.index_keywords(&namespace, &restored);
+            if !self.namespaces.contains(&namespace) {
+                self.namespaces.push(namespace.clone());
+            }
+            self.vectors.entry(namespace).or_default().extend(restored);
+        }
+        if let Some(history) = &mut self.history {
+            for (namespace, retired) in snapshot.history {
+                history.entry(namespace).or_default().extend(retired.into_iter().map(|r| RetiredVector {
+                    vector: StoredVector::restore(r.vector, &self.text_compression, self.precision),
+                    retired_at: r.retired_at,
+                    deleted: r.deleted,
+                }));
+            }
         }
+        self.version = self.version.max(snapshot.version);
+        self.acl = snapshot.acl;
+        Ok(())
+    }
+
+    /// Write the live vectors, version history and namespace owners to
+    /// `memory`
+    ///
+    /// Call this during pre_upgrade. `memory` must belong to this store
+    /// alone, e.g. the [`ContragMemory::Vectors`] region of the host's
+    /// memory manager.
+    ///
+    /// [`ContragMemory::Vectors`]: crate::storage::memory::ContragMemory::Vectors
+    pub fn persist(&self, memory: &(impl Memory + ?Sized)) -> Result<()> {
+        let snapshot = Snapshot {
+            namespaces: self
+                .namespaces
+                .iter()
+                .map(|namespace| {
+                    let vectors = self.vectors.get(namespace).map(|v| v.as_slice()).unwrap_or(&[]);
+                    (namespace.clone(), vectors.iter().map(StoredVector::to_persisted).collect())
+                })
+                .collect(),
+            acl: self.acl.clone(),
+            history: self
+                .history
+                .iter()
+                .flatten()
+                .map(|(namespace, retired)| {
+                    let retired = retired
+                        .iter()
+                        .map(|r| PersistedRetired {
+                            vector: r.vector.to_persisted(),
+                            retired_at: r.retired_at,
+                            deleted: r.deleted,
+                        })
+                        .collect();
+                    (namespace.clone(), retired)
+                })
+                .collect(),
+            version: self.version,
+        };
+        let payload = candid::encode_one(&snapshot)
+            .map_err(|e| ContragError::StorageError(format!("Failed to encode stored vectors: {}", e)))?;
+        write_blob(memory, &encode_versioned(StorageComponent::Vectors, &payload))
+    }
+
+    /// List namespaces page by page with counts and sizes
+    ///
+    /// Synchronous so it can be used directly from a query endpoint.
+    pub fn namespace_page(&self, request: &NamespaceListRequest) -> NamespacePage {
+        let (names, next_cursor) = paginate_namespaces(self.namespaces.clone(), request);
+
+        let namespaces = names
+            .into_iter()
+            .map(|name| {
+                let stored = self.vectors.get(&name).map(|v| v.as_slice()).unwrap_or(&[]);
+                NamespaceInfo {
+                    vector_count: stored.len(),
+                    size_bytes: stored.iter().map(StoredVector::size_bytes).sum(),
+                    name,
+                }
+            })
+            .collect();
+
+        NamespacePage { namespaces, next_cursor }
+    }
+
+    /// Statistics of one namespace, with text bytes counted as stored
+    ///
+    /// Synchronous so it can be used directly from a query endpoint.
+    pub fn namespace_stats(&self, namespace: &str) -> NamespaceStats {
+        let mut stats = NamespaceStats::new(namespace);
+        for v in self.vectors.get(namespace).map(|v| v.as_slice()).unwrap_or(&[]) {
+            stats.add(v.embedding.len(), v.embedding.stored_len(), v.text.stored_len(), v.timestamp);
+        }
+        stats
+    }
+
+    /// [`Self::namespace_stats`] summed over every namespace
+    pub fn store_stats(&self) -> StoreStats {
+        let mut totals = StoreStats::default();
+        for namespace in &self.namespaces {
+            totals.add(&self.namespace_stats(namespace));
+        }
+        totals
+    }
+
+    /// Namespaces, vectors and bytes under `prefix`
+    ///
+    /// Synchronous so it can be used directly from a query endpoint.
+    pub fn prefix_stats(&self, prefix: &str) -> PrefixStats {
+        let mut stats = PrefixStats {
+            prefix: prefix.to_string(),
+            namespaces: 0,
+            vectors: 0,
+            size_bytes: 0,
+        };
+        for namespace in self.namespaces.iter().filter(|ns| ns.starts_with(prefix)) {
+            let stored = self.vectors.get(namespace).map(|v| v.as_slice()).unwrap_or(&[]);
+            stats.namespaces += 1;
+            stats.vectors += stored.len();
+            stats.size_bytes += stored.iter().map(StoredVector::size_bytes).sum::<u64>();
+        }
+        stats
+    }
+
+    /// Keep replaced and deleted vectors as versions
+    ///
+    /// Every write and delete gets the next store-wide version. Storing an
+    /// existing ID keeps the vector it replaces as a version,
+    /// `delete` and `delete_where` become undoable with [`Self::restore`],
+    /// and [`Self::search_as_of`] shows what a search saw at an earlier
+    /// version. Retired versions use memory until [`Self::vacuum`]; TTL
+    /// expiry, quota eviction and namespace deletes always remove for good.
+    pub fn with_versioning(mut self, enabled: bool) -> Self {
+        self.history = enabled.then(HashMap::new);
+        self
     }
 
-    /// Initialize or load from stable storage
-    /// 
-    /// Call this during canister init or post_upgrade
-    pub fn init(&self) {
-        // In a real implementation, this would load from stable structures
-        // For now, we use in-memory storage
+    /// Version of the latest write or delete, for a later `search_as_of`
+    pub fn current_version(&self) -> u64 {
+        self.version
     }
 
-    /// Persist to stable storage
-    /// 
-    /// Call this during pre_upgrade
-    pub fn persist(&self) {
-        // In a real implementation, this would save to stable structures
+    fn next_version(&mut self) -> u64 {
+        self.version += 1;
+        self.version
+    }
+
+    /// Add vectors that passed validation and the quota, each as the next
+    /// version, replacing live vectors with the same ID
+    ///
+    /// Replaced vectors are kept as versions if versioning is enabled.
+    fn insert(&mut self, namespace: &str, vectors: Vec<StoredVector>) {
+        if vectors.is_empty() {
+            return;
+        }
+        let written = self.tick();
+        for vector in &vectors {
+            vector.last_access.store(written, Ordering::Relaxed);
+        }
+        self.index_keywords(namespace, &vectors);
+
+        let namespace_vectors = self.vectors.entry(namespace.to_string()).or_default();
+        let mut positions: HashMap<String, usize> = namespace_vectors
+            .iter()
+            .enumerate()
+            .map(|(at, v)| (v.id.clone(), at))
+            .collect();
+        // Position of each replaced vector and the version replacing it
+        let mut replaced: HashMap<usize, u64> = HashMap::new();
+        for mut vector in vectors {
+            self.version += 1;
+            vector.version = self.version;
+            if let Some(at) = positions.insert(vector.id.clone(), namespace_vectors.len()) {
+                replaced.insert(at, vector.version);
+            }
+            namespace_vectors.push(vector);
+        }
+
+        if !replaced.is_empty() {
+            let mut retired = vec![];
+            for (at, vector) in std::mem::take(namespace_vectors).into_iter().enumerate() {
+                match replaced.get(&at) {
+                    Some(&retired_at) => retired.push(RetiredVector { vector, retired_at, deleted: false }),
+                    None => namespace_vectors.push(vector),
+                }
+            }
+            if let Some(history) = &mut self.history {
+                retired.sort_by_key(|r| r.retired_at);
+                history.entry(namespace.to_string()).or_default().extend(retired);
+            }
+        }
+
+        if !self.namespaces.iter().any(|ns| ns == namespace) {
+            self.namespaces.push(namespace.to_string());
+        }
+    }
+
+    /// Remove the live vectors of `namespace` matching `matched`, keeping
+    /// them as deleted versions if `soft` and versioning is enabled
+    fn remove_where(
+        &mut self,
+        namespace: &str,
+        soft: bool,
+        mut matched: impl FnMut(&StoredVector) -> bool,
+    ) -> Vec<String> {
+        let Some(namespace_vectors) = self.vectors.get_mut(namespace) else {
+            return vec![];
+        };
+        let (removed, kept): (Vec<_>, Vec<_>) = std::mem::take(namespace_vectors)
+            .into_iter()
+            .partition(|v| matched(v));
+        *namespace_vectors = kept;
+
+        let deleted: Vec<String> = removed.iter().map(|v| v.id.clone()).collect();
+        self.unindex_keywords(namespace, &deleted);
+        if soft && self.history.is_some() && !removed.is_empty() {
+            let retired_at = self.next_version();
+            if let Some(history) = &mut self.history {
+                history.entry(namespace.to_string()).or_default().extend(
+                    removed
+                        .into_iter()
+                        .map(|vector| RetiredVector { vector, retired_at, deleted: true }),
+                );
+            }
+        }
+        deleted
+    }
+
+    /// Undo the delete of `vector_id`, storing its last version again
+    ///
+    /// Returns `false` if the vector is live or was never deleted. The
+    /// restore is a new write, so searches as of earlier versions still see
+    /// the vector as deleted in between, and it is checked against the
+    /// namespace quota like any other write.
+    pub fn restore(&mut self, namespace: &str, vector_id: &str) -> Result<bool> {
+        monitoring::ensure_writable()?;
+        let history = self.history.as_ref().ok_or_else(|| {
+            ContragError::VectorStoreError("Versioning is not enabled for this store".to_string())
+        })?;
+        let live = self.vectors.get(namespace).map(|v| v.as_slice()).unwrap_or(&[]);
+        if live.iter().any(|v| v.id == vector_id) {
+            return Ok(false);
+        }
+        let latest = history
+            .get(namespace)
+            .and_then(|retired| retired.iter().filter(|r| r.vector.id == vector_id).max_by_key(|r| r.retired_at));
+        let vector = match latest {
+            Some(retired) if retired.deleted => retired.vector.clone(),
+            _ => return Ok(false),
+        };
+        if let Some(expected) = live.first().map(|v| v.embedding.len()) {
+            if expected != vector.embedding.len() {
+                return Err(ContragError::DimensionMismatch {
+                    expected,
+                    actual: vector.embedding.len(),
+                });
+            }
+        }
+
+        let accepted = self.enforce_quota(
+            namespace,
+            vec![vector],
+            BatchMode::AllOrNothing,
+            &mut BatchWriteReport::default(),
+        )?;
+        self.insert(namespace, accepted);
+        Ok(true)
+    }
+
+    /// Every kept version of `vector_id`, oldest first
+    pub fn versions(&self, namespace: &str, vector_id: &str) -> Vec<VectorVersion> {
+        let retired = self
+            .history
+            .as_ref()
+            .and_then(|history| history.get(namespace))
+            .into_iter()
+            .flatten()
+            .filter(|r| r.vector.id == vector_id)
+            .map(|r| VectorVersion {
+                version: r.vector.version,
+                retired_at: Some(r.retired_at),
+                deleted: r.deleted,
+                vector: r.vector.to_vector(),
+            });
+        let live = self
+            .vectors
+            .get(namespace)
+            .into_iter()
+            .flatten()
+            .filter(|v| v.id == vector_id)
+            .map(|v| VectorVersion {
+                version: v.version,
+                retired_at: None,
+                deleted: false,
+                vector: v.to_vector(),
+            });
+
+        let mut versions: Vec<VectorVersion> = retired.chain(live).collect();
+        versions.sort_by_key(|v| v.version);
+        versions
+    }
+
+    /// Search the namespace as it was right after `version`
+    pub fn search_as_of(&self, namespace: &str, query_embedding: &[f32], k: usize, version: u64) -> Vec<SearchResult> {
+        let live = self
+            .vectors
+            .get(namespace)
+            .into_iter()
+            .flatten()
+            .filter(|v| v.version <= version);
+        let retired = self
+            .history
+            .as_ref()
+            .and_then(|history| history.get(namespace))
+            .into_iter()
+            .flatten()
+            .filter(|r| r.visible_at(version))
+            .map(|r| &r.vector);
+
+        let query_embedding = self.prepare_query(query_embedding);
+        self.rank(live.chain(retired), &query_embedding, k, self.min_score)
+    }
+
+    /// Drop every retired version, returning how many were dropped
+    ///
+    /// Run it from a timer to keep history bounded; restores and as-of
+    /// searches only reach versions that are still kept.
+    pub fn vacuum(&mut self) -> usize {
+        self.vacuum_before(u64::MAX)
+    }
+
+    /// Drop the versions retired at or before `version`
+    pub fn vacuum_before(&mut self, version: u64) -> usize {
+        let Some(history) = &mut self.history else {
+            return 0;
+        };
+        let mut dropped = 0;
+        history.retain(|_, retired| {
+            let before = retired.len();
+            retired.retain(|r| r.retired_at > version);
+            dropped += before - retired.len();
+            !retired.is_empty()
+        });
+        dropped
     }
 
     fn get_namespace_key(namespace: &str, vector_id: &str) -> String {
@@ -66,115 +1163,265 @@ impl Default for StableMemoryVectorStore {
 
 #[async_trait::async_trait]
 impl VectorStore for StableMemoryVectorStore {
+    fn persist_state(&mut self, memory: &dyn Memory) -> Result<()> {
+        self.persist(memory)
+    }
+
+    fn restore_state(&mut self, memory: &dyn Memory) -> Result<()> {
+        self.init(memory)
+    }
+
     async fn store(&mut self, namespace: &str, vector: Vector) -> Result<()> {
-        let stored = StoredVector {
-            id: vector.id.clone(),
-            embedding: vector.embedding,
-            text: vector.text,
-            entity_type: vector.metadata.entity_type,
-            entity_id: vector.metadata.entity_id,
-            chunk_index: vector.metadata.chunk_index,
-            total_chunks: vector.metadata.total_chunks,
-            timestamp: vector.metadata.timestamp,
-        };
+        self.store_batch_with(namespace, vec![vector], BatchMode::AllOrNothing)
+            .await
+            .map(|_| ())
+    }
+
+    /// Validates the whole batch before writing anything, so an
+    /// all-or-nothing batch is never half-written
+    async fn store_batch_with(
+        &mut self,
+        namespace: &str,
+        vectors: Vec<Vector>,
+        mode: BatchMode,
+    ) -> Result<BatchWriteReport> {
+        monitoring::ensure_writable()?;
 
-        let mut vectors = self.vectors.write().unwrap();
-        vectors
-            .entry(namespace.to_string())
-            .or_insert_with(Vec::new)
-            .push(stored);
+        let mut dimensions = self
+            .vectors
+            .get(namespace)
+            .and_then(|stored| stored.first())
+            .map(|v| v.embedding.len());
 
-        // Update namespaces list
-        let mut namespaces = self.namespaces.write().unwrap();
-        if !namespaces.contains(&namespace.to_string()) {
-            namespaces.push(namespace.to_string());
+        let mut report = BatchWriteReport::default();
+        let mut accepted = Vec::with_capacity(vectors.len());
+        for mut vector in vectors {
+            if let Some(target) = self.target_dimensions {
+                truncate_embedding(&mut vector.embedding, target);
+            }
+            match validate(&vector, dimensions) {
+                Ok(()) => {
+                    dimensions = Some(vector.embedding.len());
+                    report.push(vector.id.clone(), None);
+                    accepted.push(StoredVector::new(vector, &self.text_compression, self.precision));
+                }
+                Err(e) if mode == BatchMode::BestEffort => report.push(vector.id, Some(e.to_string())),
+                Err(e) => {
+                    return Err(ContragError::VectorStoreError(format!(
+                        "Batch rejected, vector {} is invalid: {}",
+                        vector.id, e
+                    )));
+                }
+            }
         }
 
-        Ok(())
+        let accepted = self.enforce_quota(namespace, accepted, mode, &mut report)?;
+        self.insert(namespace, accepted);
+        Ok(report)
     }
 
-    async fn store_batch(&mut self, namespace: &str, vectors: Vec<Vector>) -> Result<()> {
-        for vector in vectors {
-            self.store(namespace, vector).await?;
-        }
-        Ok(())
+    async fn search(
+        &self,
+        namespace: &str,
+        query_embedding: Vec<f32>,
+        k: usize,
+    ) -> Result<Vec<SearchResult>> {
+        Ok(self.search_scored(namespace, &query_embedding, k, self.min_score))
     }
 
-    async fn search(
+    async fn search_with_min_score(
         &self,
         namespace: &str,
         query_embedding: Vec<f32>,
         k: usize,
+        min_score: Option<f32>,
     ) -> Result<Vec<SearchResult>> {
-        let vectors = self.vectors.read().unwrap();
-        
-        let namespace_vectors = vectors
-            .get(namespace)
-            .ok_or_else(|| ContragError::VectorStoreError(format!("Namespace not found: {}", namespace)))?;
+        Ok(self.search_scored(namespace, &query_embedding, k, min_score.or(self.min_score)))
+    }
 
-        if namespace_vectors.is_empty() {
+    async fn keyword_search(&self, namespace: &str, query: &str, k: usize) -> Result<Vec<SearchResult>> {
+        let keywords = self.keywords.as_ref().ok_or_else(|| {
+            ContragError::VectorStoreError("Keyword search is not enabled for this store".to_string())
+        })?;
+        let (Some(index), Some(stored)) = (keywords.get(namespace), self.vectors.get(namespace)) else {
             return Ok(vec![]);
-        }
-
-        // Calculate similarities
-        let mut results: Vec<(f32, StoredVector)> = namespace_vectors
-            .iter()
-            .map(|v| {
-                let similarity = cosine_similarity(&query_embedding, &v.embedding);
-                (similarity, v.clone())
-            })
-            .collect();
-
-        // Sort by similarity (descending)
-        results.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        };
 
-        // Take top k results
-        Ok(results
+        Ok(index
+            .search(query, k)
             .into_iter()
-            .take(k)
-            .map(|(score, v)| SearchResult {
-                vector_id: v.id.clone(),
-                text: v.text.clone(),
-                score,
-                metadata: crate::types::VectorMetadata {
-                    entity_type: v.entity_type.clone(),
-                    entity_id: v.entity_id.clone(),
-                    chunk_index: v.chunk_index,
-                    total_chunks: v.total_chunks,
-                    timestamp: v.timestamp,
-                    custom: None,
-                },
+            .filter_map(|(score, vector_id)| {
+                let v = stored.iter().find(|v| v.id == vector_id)?;
+                Some(SearchResult {
+                    vector_id: v.id.clone(),
+                    text: v.text.text().into_owned(),
+                    score,
+                    metadata: v.metadata(),
+                })
             })
             .collect())
     }
 
     async fn delete(&mut self, namespace: &str, vector_id: &str) -> Result<()> {
-        let mut vectors = self.vectors.write().unwrap();
-        
-        if let Some(namespace_vectors) = vectors.get_mut(namespace) {
-            namespace_vectors.retain(|v| v.id != vector_id);
-        }
-
+        self.remove_where(namespace, true, |v| v.id == vector_id);
         Ok(())
     }
 
     async fn delete_namespace(&mut self, namespace: &str) -> Result<()> {
-        let mut vectors = self.vectors.write().unwrap();
+        let vectors = &mut self.vectors;
         vectors.remove(namespace);
 
-        let mut namespaces = self.namespaces.write().unwrap();
+        let namespaces = &mut self.namespaces;
         namespaces.retain(|ns| ns != namespace);
+        if let Some(keywords) = &mut self.keywords {
+            keywords.remove(namespace);
+        }
+        if let Some(history) = &mut self.history {
+            history.remove(namespace);
+        }
 
         Ok(())
     }
 
+    async fn delete_where(&mut self, namespace: &str, filter: &SearchFilter) -> Result<Vec<String>> {
+        Ok(self.remove_where(namespace, true, |v| filter.matches(&v.metadata())))
+    }
+
+    async fn delete_ttl_expired(&mut self, namespace: &str, now: u64) -> Result<Vec<String>> {
+        let default_ttl = self.ttl_seconds;
+        Ok(self.remove_where(namespace, false, |v| {
+            v.ttl_seconds
+                .or(default_ttl)
+                .is_some_and(|ttl| expiry_timestamp(v.timestamp, ttl) <= now)
+        }))
+    }
+
+    async fn delete_expired(
+        &mut self,
+        namespace: &str,
+        entity_type: &str,
+        cutoff: u64,
+    ) -> Result<Vec<String>> {
+        Ok(self.remove_where(namespace, false, |v| v.entity_type == entity_type && v.timestamp < cutoff))
+    }
+
+    async fn sample(&self, namespace: &str, limit: usize) -> Result<Vec<Vector>> {
+        let vectors = &self.vectors;
+        let namespace_vectors = match vectors.get(namespace) {
+            Some(v) if limit > 0 => v,
+            _ => return Ok(vec![]),
+        };
+
+        let step = (namespace_vectors.len() / limit).max(1);
+        Ok(namespace_vectors
+            .iter()
+            .step_by(step)
+            .take(limit)
+            .map(StoredVector::to_vector)
+            .collect())
+    }
+
+    async fn list_vectors(&self, namespace: &str, offset: usize, limit: usize) -> Result<Vec<Vector>> {
+        Ok(self
+            .vectors
+            .get(namespace)
+            .map(|stored| stored.iter().skip(offset).take(limit).map(StoredVector::to_vector).collect())
+            .unwrap_or_default())
+    }
+
+    async fn get(&self, namespace: &str, vector_id: &str) -> Result<Option<Vector>> {
+        Ok(self
+            .vectors
+            .get(namespace)
+            .and_then(|stored| stored.iter().find(|v| v.id == vector_id))
+            .map(StoredVector::to_vector))
+    }
+
+    async fn get_many(&self, namespace: &str, vector_ids: &[String]) -> Result<Vec<Vector>> {
+        let wanted: HashSet<&str> = vector_ids.iter().map(String::as_str).collect();
+        Ok(self
+            .vectors
+            .get(namespace)
+            .map(|stored| {
+                stored
+                    .iter()
+                    .filter(|v| wanted.contains(v.id.as_str()))
+                    .map(StoredVector::to_vector)
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn get_by_entity(&self, namespace: &str, entity_type: &str, entity_id: &str) -> Result<Vec<Vector>> {
+        let mut chunks: Vec<Vector> = self
+            .vectors
+            .get(namespace)
+            .map(|stored| {
+                stored
+                    .iter()
+                    .filter(|v| v.entity_type == entity_type && v.entity_id == entity_id)
+                    .map(StoredVector::to_vector)
+                    .collect()
+            })
+            .unwrap_or_default();
+        chunks.sort_by_key(|v| v.metadata.chunk_index);
+        Ok(chunks)
+    }
+
     async fn count(&self, namespace: &str) -> Result<usize> {
-        let vectors = self.vectors.read().unwrap();
+        let vectors = &self.vectors;
         Ok(vectors.get(namespace).map(|v| v.len()).unwrap_or(0))
     }
 
+    async fn stats(&self, namespace: &str) -> Result<NamespaceStats> {
+        Ok(self.namespace_stats(namespace))
+    }
+
+    async fn global_stats(&self) -> Result<StoreStats> {
+        Ok(self.store_stats())
+    }
+
+    async fn stats_by_prefix(&self, prefix: &str) -> Result<PrefixStats> {
+        Ok(self.prefix_stats(prefix))
+    }
+
     async fn list_namespaces(&self) -> Result<Vec<String>> {
-        Ok(self.namespaces.read().unwrap().clone())
+        Ok(self.namespaces.clone())
+    }
+
+    async fn list_namespaces_page(&self, request: NamespaceListRequest) -> Result<NamespacePage> {
+        Ok(self.namespace_page(&request))
+    }
+
+    async fn delete_by_prefix(&mut self, cursor: BulkCursor, batch: usize) -> Result<BulkCursor> {
+        let page = self.namespace_page(&prefix_page_request(&cursor, batch));
+        let mut cursor = cursor;
+
+        let vectors = &mut self.vectors;
+        let namespaces = &mut self.namespaces;
+        for info in &page.namespaces {
+            vectors.remove(&info.name);
+            if let Some(keywords) = &mut self.keywords {
+                keywords.remove(&info.name);
+            }
+            if let Some(history) = &mut self.history {
+                history.remove(&info.name);
+            }
+            cursor.namespaces_done += 1;
+            cursor.vectors_done += info.vector_count as u64;
+        }
+        namespaces.retain(|ns| !page.namespaces.iter().any(|info| &info.name == ns));
+
+        advance_cursor(&mut cursor, &page);
+        Ok(cursor)
+    }
+
+    async fn export_namespace(&self, namespace: &str) -> Result<Vec<Vector>> {
+        let vectors = &self.vectors;
+        Ok(vectors
+            .get(namespace)
+            .map(|stored| stored.iter().map(StoredVector::to_vector).collect())
+            .unwrap_or_default())
     }
 }
 
@@ -186,24 +1433,15 @@ pub fn create_vector_store() -> StableMemoryVectorStore {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::VectorMetadata;
+    use crate::types::{test_vector, DedupMode, VectorMetadata};
 
     #[tokio::test]
     async fn test_store_and_search() {
         let mut store = StableMemoryVectorStore::new();
         
         let vector = Vector {
-            id: "test1".to_string(),
-            embedding: vec![1.0, 0.0, 0.0],
             text: "Test text".to_string(),
-            metadata: VectorMetadata {
-                entity_type: "Test".to_string(),
-                entity_id: "1".to_string(),
-                chunk_index: 0,
-                total_chunks: 1,
-                timestamp: 0,
-                custom: None,
-            },
+            ..test_vector("test1", vec![1.0, 0.0, 0.0])
         };
 
         store.store("test_namespace", vector).await.unwrap();
@@ -217,4 +1455,515 @@ mod tests {
         assert_eq!(results[0].vector_id, "test1");
         assert!(results[0].score > 0.99); // Should be very similar
     }
+
+    #[tokio::test]
+    async fn test_min_score_and_normalized_scores() {
+        let mut store = StableMemoryVectorStore::new()
+            .with_metric(DistanceMetric::Euclidean)
+            .with_normalized_scores(true)
+            .with_min_score(Some(0.75));
+        store.store("docs", timed("same", vec![1.0, 0.0], 0)).await.unwrap();
+        store.store("docs", timed("close", vec![0.8, 0.6], 0)).await.unwrap();
+        store.store("docs", timed("orthogonal", vec![0.0, 1.0], 0)).await.unwrap();
+
+        // 0.9 for a cosine of 0.8 under euclidean distance, like cosine itself
+        let results = store.search("docs", vec![1.0, 0.0], 5).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert!((results[1].score - 0.9).abs() < 0.001);
+
+        let results = store
+            .search_with_min_score("docs", vec![1.0, 0.0], 5, Some(0.4))
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_search_keeps_best_k() {
+        let mut store = StableMemoryVectorStore::new();
+        for (i, x) in [0.1, 0.9, 0.5, 0.9, 0.3, 0.7].into_iter().enumerate() {
+            store.store("ns", timed(&format!("v{}", i), vec![x, 1.0 - x], 0)).await.unwrap();
+        }
+
+        let results = store.search("ns", vec![1.0, 0.0], 3).await.unwrap();
+        let ids: Vec<_> = results.iter().map(|r| r.vector_id.as_str()).collect();
+        // Equal scores keep insertion order
+        assert_eq!(ids, ["v1", "v3", "v5"]);
+        assert!(store.search("ns", vec![1.0, 0.0], 0).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_half_precision_storage() {
+        let mut store = StableMemoryVectorStore::new().with_precision(EmbeddingPrecision::F16);
+        store.store("ns", timed("a", vec![0.6, 0.8, 0.0], 0)).await.unwrap();
+        store.store("ns", timed("b", vec![0.0, 0.1, 0.995], 0)).await.unwrap();
+
+        let stats = store.stats("ns").await.unwrap();
+        assert_eq!((stats.dimensions, stats.embedding_bytes), (Some(3), 12));
+
+        let results = store.search("ns", vec![0.6, 0.8, 0.0], 2).await.unwrap();
+        assert_eq!(results[0].vector_id, "a");
+        assert!((results[0].score - 1.0).abs() < 1e-3);
+
+        let a = store.get("ns", "a").await.unwrap().unwrap();
+        assert!(a.embedding.iter().zip([0.6, 0.8, 0.0]).all(|(x, y)| (x - y).abs() < 1e-3));
+    }
+
+    #[tokio::test]
+    async fn test_persist_keeps_half_precision() {
+        use ic_stable_structures::DefaultMemoryImpl;
+
+        let embedding: Vec<f32> = (0..256).map(|i| i as f32 / 256.0).collect();
+        let persisted = |precision| {
+            let embedding = embedding.clone();
+            async move {
+                let mut store = StableMemoryVectorStore::new().with_precision(precision);
+                store.store("ns", timed("a", embedding, 0)).await.unwrap();
+                let memory = DefaultMemoryImpl::default();
+                store.persist(&memory).unwrap();
+                memory
+            }
+        };
+        let half = persisted(EmbeddingPrecision::F16).await;
+        let full = persisted(EmbeddingPrecision::F32).await;
+        assert!(read_blob(&half).unwrap().unwrap().len() < read_blob(&full).unwrap().unwrap().len());
+
+        let mut restored = StableMemoryVectorStore::new().with_precision(EmbeddingPrecision::F16);
+        restored.init(&half).unwrap();
+        assert_eq!(restored.stats("ns").await.unwrap().embedding_bytes, 512);
+        let a = restored.get("ns", "a").await.unwrap().unwrap();
+        assert!(a.embedding.iter().zip(&embedding).all(|(x, y)| (x - y).abs() < 1e-3));
+
+        // Version 1 blobs, with f32 embeddings, are migrated on load
+        let old = SnapshotV1 {
+            namespaces: vec![("ns".to_string(), vec![timed("a", vec![1.0, 0.0], 0)])],
+            acl: NamespaceAcl::default(),
+        };
+        let mut raw = encode_versioned(StorageComponent::Vectors, &candid::encode_one(&old).unwrap());
+        raw[5..9].copy_from_slice(&1u32.to_le_bytes());
+        let memory = DefaultMemoryImpl::default();
+        write_blob(&memory, &raw).unwrap();
+        let migrated = StableMemoryVectorStore::with_memory(memory).unwrap();
+        assert_eq!(migrated.get("ns", "a").await.unwrap().unwrap().embedding, vec![1.0, 0.0]);
+    }
+
+    #[tokio::test]
+    async fn test_target_dimensions() {
+        let mut store = StableMemoryVectorStore::new().with_target_dimensions(Some(2));
+        store.store("ns", timed("a", vec![3.0, 4.0, 12.0], 0)).await.unwrap();
+        store.store("ns", timed("b", vec![0.0, 1.0], 0)).await.unwrap();
+
+        let a = store.get("ns", "a").await.unwrap().unwrap();
+        assert_eq!(a.embedding, [0.6, 0.8]);
+
+        // Full-length queries are cut the same way
+        let results = store.search("ns", vec![6.0, 8.0, -1.0], 1).await.unwrap();
+        assert_eq!(results[0].vector_id, "a");
+        assert!((results[0].score - 1.0).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_versioning_restore_and_vacuum() {
+        let mut store = StableMemoryVectorStore::new().with_versioning(true);
+        store.store("ns", timed("a", vec![1.0, 0.0], 0)).await.unwrap();
+        store.store("ns", timed("b", vec![0.0, 1.0], 0)).await.unwrap();
+        let before_update = store.current_version();
+
+        // Storing an existing ID replaces it and keeps the old version
+        store.store("ns", timed("a", vec![0.6, 0.8], 0)).await.unwrap();
+        assert_eq!(store.count("ns").await.unwrap(), 2);
+        let versions = store.versions("ns", "a");
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].retired_at, Some(versions[1].version));
+
+        store.delete("ns", "b").await.unwrap();
+        assert!(store.get("ns", "b").await.unwrap().is_none());
+        let before_restore = store.current_version();
+        assert!(store.restore("ns", "b").unwrap());
+        assert!(!store.restore("ns", "b").unwrap());
+        assert!(store.get("ns", "b").await.unwrap().is_some());
+
+        // As-of searches see the vectors live at that version
+        let then = store.search_as_of("ns", &[1.0, 0.0], 2, before_update);
+        assert_eq!(then[0].vector_id, "a");
+        assert!((then[0].score - 1.0).abs() < 1e-6);
+        let deleted = store.search_as_of("ns", &[0.0, 1.0], 2, before_restore);
+        assert_eq!(deleted.len(), 1);
+
+        assert_eq!(store.vacuum(), 2);
+        assert_eq!(store.versions("ns", "a").len(), 1);
+        assert_eq!(store.search("ns", vec![0.0, 1.0], 2).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_versions_survive_upgrades() {
+        use ic_stable_structures::DefaultMemoryImpl;
+
+        let mut store = StableMemoryVectorStore::new().with_versioning(true);
+        store.store("ns", timed("a", vec![1.0, 0.0], 0)).await.unwrap();
+        let before_update = store.current_version();
+        store.store("ns", timed("a", vec![0.0, 1.0], 0)).await.unwrap();
+        store.store("ns", timed("b", vec![0.6, 0.8], 0)).await.unwrap();
+        store.delete("ns", "b").await.unwrap();
+
+        let memory = DefaultMemoryImpl::default();
+        store.persist(&memory).unwrap();
+        let mut restored = StableMemoryVectorStore::new().with_versioning(true);
+        restored.init(&memory).unwrap();
+        assert_eq!(restored.current_version(), store.current_version());
+        let versions = |store: &StableMemoryVectorStore| {
+            store.versions("ns", "a").into_iter().map(|v| (v.version, v.retired_at)).collect::<Vec<_>>()
+        };
+        assert_eq!(versions(&restored), versions(&store));
+        let then = restored.search_as_of("ns", &[1.0, 0.0], 1, before_update);
+        assert!((then[0].score - 1.0).abs() < 1e-6);
+        assert!(restored.restore("ns", "b").unwrap());
+        assert_eq!(restored.current_version(), store.current_version() + 1);
+
+        // Without versioning, writes replace the same way and keep no history
+        let mut unversioned = StableMemoryVectorStore::new();
+        unversioned.init(&memory).unwrap();
+        unversioned.store("ns", timed("a", vec![1.0, 0.0], 0)).await.unwrap();
+        assert_eq!(unversioned.count("ns").await.unwrap(), 1);
+        assert_eq!(unversioned.versions("ns", "a").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_restore_respects_quota() {
+        let quota = NamespaceQuota {
+            max_vectors: Some(1),
+            max_bytes: None,
+            eviction: EvictionPolicy::Reject,
+        };
+        let mut store = StableMemoryVectorStore::new().with_versioning(true).with_quota(quota);
+        store.store("ns", timed("a", vec![1.0, 0.0], 0)).await.unwrap();
+        // Replacing a vector doesn't count against the quota
+        store.store("ns", timed("a", vec![0.0, 1.0], 0)).await.unwrap();
+        store.delete("ns", "a").await.unwrap();
+        store.store("ns", timed("b", vec![1.0, 0.0], 0)).await.unwrap();
+
+        assert!(store.restore("ns", "a").is_err());
+        assert!(store.get("ns", "a").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_persist_to_memory_region() {
+        use crate::config::StableMemoryConfig;
+        use crate::storage::memory::{memory, ContragMemory};
+        use ic_stable_structures::memory_manager::{MemoryId, MemoryManager};
+        use ic_stable_structures::DefaultMemoryImpl;
+
+        let manager = MemoryManager::init(DefaultMemoryImpl::default());
+        let config = StableMemoryConfig::default();
+        let host = manager.get(MemoryId::new(0));
+        host.grow(1);
+        host.write(0, b"host");
+
+        let mut store = StableMemoryVectorStore::new();
+        store.store("a", timed("a1", vec![1.0, 0.0], 100)).await.unwrap();
+        store.set_namespace_owner("a", Principal::from_slice(&[1]));
+        store.persist(&memory(&manager, &config, ContragMemory::Vectors)).unwrap();
+
+        let restored = StableMemoryVectorStore::with_memory(memory(&manager, &config, ContragMemory::Vectors)).unwrap();
+        assert_eq!(restored.get("a", "a1").await.unwrap().unwrap().embedding, vec![1.0, 0.0]);
+        assert_eq!(restored.acl(), store.acl());
+        assert_eq!(StableMemoryVectorStore::with_memory(manager.get(MemoryId::new(1))).unwrap().store_stats().vector_count, 0);
+
+        let mut host_bytes = [0; 4];
+        host.read(0, &mut host_bytes);
+        assert_eq!(&host_bytes, b"host");
+    }
+
+    #[tokio::test]
+    async fn test_stats() {
+        let mut store = StableMemoryVectorStore::new();
+        let mut a1 = timed("a1", vec![1.0, 0.0, 0.0], 300);
+        a1.text = "four".to_string();
+        store.store("a", a1).await.unwrap();
+        store.store("a", timed("a2", vec![0.0, 1.0, 0.0], 100)).await.unwrap();
+        store.store("b", timed("b1", vec![1.0, 1.0], 200)).await.unwrap();
+
+        let a = store.stats("a").await.unwrap();
+        assert_eq!(a.vector_count, 2);
+        assert_eq!(a.embedding_bytes, 24);
+        assert_eq!(a.text_bytes, 4);
+        assert_eq!(a.dimensions, Some(3));
+        assert_eq!((a.oldest_timestamp, a.newest_timestamp), (Some(100), Some(300)));
+        assert_eq!(store.stats("missing").await.unwrap(), NamespaceStats::new("missing"));
+
+        let total = store.global_stats().await.unwrap();
+        assert_eq!((total.namespaces, total.vector_count), (2, 3));
+        assert_eq!(total.embedding_bytes, 32);
+        assert_eq!((total.oldest_timestamp, total.newest_timestamp), (Some(100), Some(300)));
+    }
+
+    #[tokio::test]
+    async fn test_delete_by_prefix_in_steps() {
+        let mut store = StableMemoryVectorStore::new();
+        for ns in ["tenant:a:User:1", "tenant:a:User:2", "tenant:a:Order:1", "tenant:b:User:1"] {
+            store.store(ns, test_vector("v", vec![1.0])).await.unwrap();
+        }
+
+        let mut cursor = BulkCursor::new("tenant:a:");
+        let mut steps = 0;
+        while !cursor.finished {
+            cursor = store.delete_by_prefix(cursor, 2).await.unwrap();
+            steps += 1;
+        }
+
+        assert_eq!(steps, 2);
+        assert_eq!(cursor.namespaces_done, 3);
+        assert_eq!(store.list_namespaces().await.unwrap(), vec!["tenant:b:User:1"]);
+        assert_eq!(store.stats_by_prefix("tenant:").await.unwrap().vectors, 1);
+    }
+
+    fn timed(id: &str, embedding: Vec<f32>, timestamp: u64) -> Vector {
+        let mut vector = Vector {
+            text: String::new(),
+            ..test_vector(id, embedding)
+        };
+        vector.metadata.timestamp = timestamp;
+        vector
+    }
+
+    #[tokio::test]
+    async fn test_namespace_quota_eviction() {
+        let quota = |eviction| NamespaceQuota {
+            max_vectors: Some(2),
+            max_bytes: None,
+            eviction,
+        };
+
+        let mut rejecting = StableMemoryVectorStore::new().with_quota(quota(EvictionPolicy::Reject));
+        rejecting.store("docs", timed("a", vec![1.0, 0.0], 1)).await.unwrap();
+        let report = rejecting
+            .store_batch_with(
+                "docs",
+                vec![timed("b", vec![1.0, 0.0], 2), timed("c", vec![1.0, 0.0], 3)],
+                BatchMode::BestEffort,
+            )
+            .await
+            .unwrap();
+        assert_eq!((report.stored, report.failed), (1, 1));
+        assert!(report.results[1].error.is_some());
+        assert!(rejecting.store("docs", timed("d", vec![1.0, 0.0], 4)).await.is_err());
+
+        let mut oldest = StableMemoryVectorStore::new().with_quota(quota(EvictionPolicy::OldestFirst));
+        for (id, timestamp) in [("new", 20), ("old", 10), ("newest", 30)] {
+            oldest.store("docs", timed(id, vec![1.0, 0.0], timestamp)).await.unwrap();
+        }
+        assert!(oldest.get("docs", "old").await.unwrap().is_none());
+        assert_eq!(oldest.count("docs").await.unwrap(), 2);
+
+        let mut lru = StableMemoryVectorStore::new().with_quota(quota(EvictionPolicy::Lru));
+        lru.store("docs", timed("a", vec![1.0, 0.0], 0)).await.unwrap();
+        lru.store("docs", timed("b", vec![0.0, 1.0], 0)).await.unwrap();
+        lru.search("docs", vec![1.0, 0.0], 1).await.unwrap();
+        lru.store("docs", timed("c", vec![1.0, 1.0], 0)).await.unwrap();
+        assert!(lru.get("docs", "b").await.unwrap().is_none());
+        assert!(lru.get("docs", "a").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_keyword_search() {
+        let mut store = StableMemoryVectorStore::new().with_keyword_index(true);
+        for (id, text) in [("a", "Widget SKU-1042 in stock"), ("b", "Gadget SKU-2001 backordered")] {
+            let mut vector = timed(id, vec![1.0, 0.0], 0);
+            vector.text = text.to_string();
+            store.store("products", vector).await.unwrap();
+        }
+
+        let results = store.keyword_search("products", "sku-2001", 5).await.unwrap();
+        assert_eq!(results[0].vector_id, "b");
+        assert_eq!(results[0].text, "Gadget SKU-2001 backordered");
+
+        store.delete("products", "b").await.unwrap();
+        assert!(store.keyword_search("products", "2001", 5).await.unwrap().is_empty());
+        assert!(StableMemoryVectorStore::new().keyword_search("products", "sku", 5).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_paged_search_and_listing() {
+        let mut store = StableMemoryVectorStore::new();
+        for i in 0..5 {
+            store.store("docs", test_vector(&format!("v{}", i), vec![1.0, i as f32])).await.unwrap();
+        }
+
+        let all = store.search("docs", vec![1.0, 0.0], 5).await.unwrap();
+        let page = store.search_paged("docs", vec![1.0, 0.0], 2, 2).await.unwrap();
+        let ids: Vec<&str> = page.iter().map(|r| r.vector_id.as_str()).collect();
+        assert_eq!(ids, [all[2].vector_id.as_str(), all[3].vector_id.as_str()]);
+        assert!(store.search_paged("docs", vec![1.0, 0.0], 2, 5).await.unwrap().is_empty());
+
+        let listed = store.list_vectors("docs", 3, 10).await.unwrap();
+        assert_eq!(listed.iter().map(|v| v.id.as_str()).collect::<Vec<_>>(), ["v3", "v4"]);
+        assert!(store.list_vectors("missing", 0, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_and_get_by_entity() {
+        let mut store = StableMemoryVectorStore::new();
+        for (id, entity_id, chunk_index) in [("o1-1", "1", 1), ("o2-0", "2", 0), ("o1-0", "1", 0)] {
+            let mut vector = test_vector(id, vec![1.0, 0.0]);
+            vector.metadata = VectorMetadata {
+                entity_type: "Order".to_string(),
+                entity_id: entity_id.to_string(),
+                chunk_index,
+                total_chunks: 2,
+                ..vector.metadata
+            };
+            store.store("orders", vector).await.unwrap();
+        }
+
+        assert_eq!(store.get("orders", "o2-0").await.unwrap().unwrap().text, "o2-0");
+        assert!(store.get("orders", "o3-0").await.unwrap().is_none());
+
+        let chunks = store.get_by_entity("orders", "Order", "1").await.unwrap();
+        assert_eq!(chunks.iter().map(|v| v.id.as_str()).collect::<Vec<_>>(), ["o1-0", "o1-1"]);
+        assert!(store.get_by_entity("orders", "User", "1").await.unwrap().is_empty());
+
+        let mut deleted = store.delete_by_entity("orders", "Order", "1").await.unwrap();
+        deleted.sort();
+        assert_eq!(deleted, ["o1-0", "o1-1"]);
+        assert_eq!(store.count("orders").await.unwrap(), 1);
+
+        let filter = SearchFilter::new().with_entity_type("Order").with_since(1);
+        assert!(store.delete_where("orders", &filter).await.unwrap().is_empty());
+        assert_eq!(store.delete_where("orders", &SearchFilter::new()).await.unwrap(), ["o2-0"]);
+
+        let metadata = VectorMetadata {
+            entity_type: "Order".to_string(),
+            custom: Some(r#"{"status":"shipped","items":2}"#.to_string()),
+            ..test_vector("3", vec![]).metadata
+        };
+        assert!(SearchFilter::new().with_custom("status", "shipped").with_custom("items", "2").matches(&metadata));
+        assert!(!SearchFilter::new().with_custom("status", "pending").matches(&metadata));
+    }
+
+    #[tokio::test]
+    async fn test_quantized_prefilter() {
+        let quantization = QuantizationConfig {
+            enabled: true,
+            rerank_factor: 4,
+            min_vectors: 0,
+        };
+        let mut exact = StableMemoryVectorStore::new();
+        let mut quantized = StableMemoryVectorStore::new().with_quantization(quantization);
+
+        let mut seed = 7u32;
+        let mut embeddings = vec![];
+        for _ in 0..200 {
+            let embedding: Vec<f32> = (0..16)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    (seed >> 16) as f32 / 32_768.0 - 1.0
+                })
+                .collect();
+            embeddings.push(embedding);
+        }
+
+        for (i, embedding) in embeddings.iter().enumerate() {
+            let vector = test_vector(&format!("v{}", i), embedding.clone());
+            exact.store("docs", vector.clone()).await.unwrap();
+            quantized.store("docs", vector).await.unwrap();
+        }
+
+        let query = embeddings[42].clone();
+        let expected = exact.search("docs", query.clone(), 3).await.unwrap();
+        let results = quantized.search("docs", query, 3).await.unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].vector_id, "v42");
+        assert_eq!(results[0].vector_id, expected[0].vector_id);
+    }
+
+    #[tokio::test]
+    async fn test_custom_scorer() {
+        let boost_completed = |_: &[f32], _: &[f32], meta: &VectorMetadata, similarity: f32| {
+            if meta.custom.as_deref() == Some(r#"{"status":"completed"}"#) {
+                similarity + 1.0
+            } else {
+                similarity
+            }
+        };
+        let mut store = StableMemoryVectorStore::new().with_scorer(Arc::new(boost_completed));
+
+        for (id, embedding, custom) in [
+            ("pending", vec![1.0, 0.0], None),
+            ("completed", vec![0.6, 0.8], Some(r#"{"status":"completed"}"#.to_string())),
+        ] {
+            let mut vector = test_vector(id, embedding);
+            vector.metadata.entity_type = "Order".to_string();
+            vector.metadata.custom = custom;
+            store.store("orders", vector).await.unwrap();
+        }
+
+        let results = store.search("orders", vec![1.0, 0.0], 2).await.unwrap();
+        assert_eq!(results[0].vector_id, "completed");
+        assert!(results[0].metadata.custom.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_batch_modes() {
+        let batch = || vec![test_vector("a", vec![1.0, 0.0]), test_vector("b", vec![1.0]), test_vector("c", vec![0.0, 1.0])];
+        let mut store = StableMemoryVectorStore::new();
+
+        assert!(store
+            .store_batch_with("docs", batch(), BatchMode::AllOrNothing)
+            .await
+            .is_err());
+        assert_eq!(store.count("docs").await.unwrap(), 0);
+
+        let report = store
+            .store_batch_with("docs", batch(), BatchMode::BestEffort)
+            .await
+            .unwrap();
+        assert_eq!((report.stored, report.failed), (2, 1));
+        assert!(report.results[1].error.as_deref().unwrap().contains("expected 2"));
+        assert_eq!(store.count("docs").await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_store_batch_dedup() {
+        let chunk = |id: &str, entity: &str, text: &str, timestamp: u64| {
+            let mut vector = timed(id, vec![1.0, 0.0], timestamp);
+            vector.text = text.to_string();
+            vector.metadata.entity_id = entity.to_string();
+            vector
+        };
+        let mut store = StableMemoryVectorStore::new();
+        store.store("docs", chunk("a", "order-1042", "Status: shipped", 1)).await.unwrap();
+
+        let report = store
+            .store_batch_dedup(
+                "docs",
+                vec![
+                    chunk("b", "order-1042", "Status: shipped", 2),
+                    chunk("c", "order-2001", "Status: shipped", 2),
+                    chunk("d", "order-2001", "Status: shipped", 2),
+                ],
+                BatchMode::AllOrNothing,
+                DedupMode::Skip,
+            )
+            .await
+            .unwrap();
+        // The same text under another entity is not a duplicate
+        assert_eq!((report.stored, report.skipped), (1, 2));
+        assert_eq!(report.results[0].duplicate_of.as_deref(), Some("a"));
+        assert_eq!(report.results[1].duplicate_of, None);
+        assert_eq!(report.results[2].duplicate_of.as_deref(), Some("c"));
+        assert_eq!(store.count("docs").await.unwrap(), 2);
+
+        let report = store
+            .store_batch_dedup(
+                "docs",
+                vec![chunk("e", "order-1042", "Status: shipped", 3)],
+                BatchMode::AllOrNothing,
+                DedupMode::Update,
+            )
+            .await
+            .unwrap();
+        assert_eq!((report.stored, report.skipped), (1, 0));
+        assert_eq!(store.count("docs").await.unwrap(), 2);
+        assert_eq!(store.get("docs", "a").await.unwrap().unwrap().metadata.timestamp, 3);
+    }
 }
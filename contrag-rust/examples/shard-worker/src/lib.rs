@@ -0,0 +1,138 @@
+//! Worker canister holding one shard of a `ShardedVectorStore`
+//!
+//! Implements the `shard_methods` endpoints `CanisterShard` calls, backed by
+//! a local `StableMemoryVectorStore` that survives upgrades. The coordinator
+//! canister creates its workers, which makes it their controller; only
+//! controllers may call the endpoints.
+
+use ic_cdk_macros::*;
+use ic_stable_structures::memory_manager::MemoryManager;
+use ic_stable_structures::DefaultMemoryImpl;
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut};
+
+use contrag_core::config::StableMemoryConfig;
+use contrag_core::storage::memory::{memory, ContragMemory};
+use contrag_core::types::{BatchMode, BatchWriteReport, SearchFilter, SearchResult, Vector};
+use contrag_core::vector_store::stable_memory_store::StableMemoryVectorStore;
+use contrag_core::vector_store::VectorStore;
+
+thread_local! {
+    static MEMORY_MANAGER: MemoryManager<DefaultMemoryImpl> = MemoryManager::init(DefaultMemoryImpl::default());
+    static STORE: RefCell<Option<StableMemoryVectorStore>> = RefCell::new(Some(StableMemoryVectorStore::new()));
+}
+
+type ShardResult<T> = Result<T, String>;
+
+/// The store for the length of one call, put back when dropped
+///
+/// The local store never waits on another canister, so every call finishes
+/// in one message and no two calls hold the store at once.
+struct StoreLease(Option<StableMemoryVectorStore>);
+
+impl StoreLease {
+    fn new() -> ShardResult<Self> {
+        let caller = ic_cdk::api::caller();
+        if !ic_cdk::api::is_controller(&caller) {
+            return Err(format!("{} may not call this shard", caller));
+        }
+        let store = STORE.with(|store| store.borrow_mut().take()).ok_or_else(|| "Shard is busy".to_string())?;
+        Ok(Self(Some(store)))
+    }
+}
+
+impl Deref for StoreLease {
+    type Target = StableMemoryVectorStore;
+
+    fn deref(&self) -> &StableMemoryVectorStore {
+        self.0.as_ref().expect("Store is held until the lease drops")
+    }
+}
+
+impl DerefMut for StoreLease {
+    fn deref_mut(&mut self) -> &mut StableMemoryVectorStore {
+        self.0.as_mut().expect("Store is held until the lease drops")
+    }
+}
+
+impl Drop for StoreLease {
+    fn drop(&mut self) {
+        if let Some(store) = self.0.take() {
+            STORE.with(|slot| *slot.borrow_mut() = Some(store));
+        }
+    }
+}
+
+fn vector_memory() -> impl ic_stable_structures::Memory {
+    MEMORY_MANAGER.with(|manager| memory(manager, &StableMemoryConfig::default(), ContragMemory::Vectors))
+}
+
+// ============================================================================
+// Canister Lifecycle
+// ============================================================================
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    STORE.with(|store| store.borrow().as_ref().map(|store| store.persist(&vector_memory())))
+        .expect("Shard store is leased")
+        .expect("Failed to persist vectors");
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    STORE.with(|store| store.borrow_mut().as_mut().map(|store| store.init(&vector_memory())))
+        .expect("Shard store is leased")
+        .expect("Failed to restore vectors");
+}
+
+// ============================================================================
+// Shard Endpoints (contrag_core::vector_store::sharded::shard_methods)
+// ============================================================================
+
+#[update]
+async fn shard_store_batch(namespace: String, vectors: Vec<Vector>, mode: BatchMode) -> ShardResult<BatchWriteReport> {
+    let mut store = StoreLease::new()?;
+    store.store_batch_with(&namespace, vectors, mode).await.map_err(|e| e.to_string())
+}
+
+#[update]
+async fn shard_search(namespace: String, query_embedding: Vec<f32>, k: u64) -> ShardResult<Vec<SearchResult>> {
+    let store = StoreLease::new()?;
+    store.search(&namespace, query_embedding, k as usize).await.map_err(|e| e.to_string())
+}
+
+#[update]
+async fn shard_delete(namespace: String, vector_id: String) -> ShardResult<()> {
+    let mut store = StoreLease::new()?;
+    store.delete(&namespace, &vector_id).await.map_err(|e| e.to_string())
+}
+
+#[update]
+async fn shard_delete_namespace(namespace: String) -> ShardResult<()> {
+    let mut store = StoreLease::new()?;
+    store.delete_namespace(&namespace).await.map_err(|e| e.to_string())
+}
+
+#[update]
+async fn shard_delete_where(namespace: String, filter: SearchFilter) -> ShardResult<Vec<String>> {
+    let mut store = StoreLease::new()?;
+    store.delete_where(&namespace, &filter).await.map_err(|e| e.to_string())
+}
+
+#[query]
+async fn shard_count(namespace: String) -> ShardResult<u64> {
+    let store = StoreLease::new()?;
+    store.count(&namespace).await.map(|count| count as u64).map_err(|e| e.to_string())
+}
+
+#[query]
+async fn shard_list_namespaces() -> ShardResult<Vec<String>> {
+    let store = StoreLease::new()?;
+    store.list_namespaces().await.map_err(|e| e.to_string())
+}
+
+#[query]
+async fn shard_export_namespace(namespace: String) -> ShardResult<Vec<Vector>> {
+    let store = StoreLease::new()?;
+    store.export_namespace(&namespace).await.map_err(|e| e.to_string())
+}
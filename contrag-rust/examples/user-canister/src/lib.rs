@@ -1,15 +1,30 @@
 use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::management_canister::http_request::{HttpResponse, TransformArgs};
 use ic_cdk_macros::*;
+use ic_stable_structures::memory_manager::MemoryManager;
+use ic_stable_structures::DefaultMemoryImpl;
 use serde::Serialize;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Deref, DerefMut};
 
 use contrag_core::prelude::*;
-use contrag_core::embedders::openai::OpenAIEmbedder;
-use contrag_core::embedders::Embedder;
+use contrag_core::api::{
+    Answer, AskRequest, ImportVectorsRequest, IngestDocumentResponse, IngestRequest, IngestResponse, JobState,
+    JobStatus, MaintainIndexResponse, SearchRequest, SearchResponse,
+};
+use contrag_core::pipeline::idempotency::{self, Claim, IdempotencyCache};
+use contrag_core::queue::{start_queue_processor, IngestionJob};
+use contrag_core::vector_store::import::{self, DEFAULT_IMPORT_BATCH};
+use contrag_core::{IngestionPriority, IngestionQueue, RagPipeline};
+use contrag_core::embedders::{self, http_client, Embedder};
 use contrag_core::vector_store::stable_memory_store::StableMemoryVectorStore;
 use contrag_core::vector_store::VectorStore;
 use contrag_core::data_sources::canister_state::CanisterStateSource;
+use contrag_core::config::StableMemoryConfig;
+use contrag_core::keys::KeyStore;
+use contrag_core::provenance;
+use contrag_core::storage::memory::{memory, ContragMemory};
 use contrag_core::utils::{generate_vector_id, get_timestamp};
 
 // ============================================================================
@@ -111,11 +126,117 @@ impl RagEntity for Order {
 // ============================================================================
 
 thread_local! {
+    // contrag gets MemoryIds from StableMemoryConfig::first_memory_id on;
+    // the canister's own stable structures would use lower ones
+    static MEMORY_MANAGER: MemoryManager<DefaultMemoryImpl> = MemoryManager::init(DefaultMemoryImpl::default());
     static USERS: RefCell<HashMap<String, User>> = RefCell::new(HashMap::new());
     static ORDERS: RefCell<HashMap<String, Order>> = RefCell::new(HashMap::new());
-    static VECTOR_STORE: RefCell<StableMemoryVectorStore> = RefCell::new(StableMemoryVectorStore::new());
+    // Empty while a pipeline call has the store, see PipelineLease
+    static VECTOR_STORE: RefCell<Option<StableMemoryVectorStore>> = RefCell::new(Some(StableMemoryVectorStore::new()));
     static CONFIG: RefCell<Option<ContragConfig>> = RefCell::new(None);
-    static API_KEY: RefCell<Option<String>> = RefCell::new(None);
+    static KEYS: RefCell<KeyStore> = RefCell::new(KeyStore::new());
+    // Secret the key store is sealed under in stable memory, passed by the
+    // controller as the install or upgrade argument and kept on the heap only
+    static KEY_SECRET: RefCell<Option<Vec<u8>>> = RefCell::new(None);
+    // Entities waiting for `ingest`, drained by a timer started in set_config
+    static QUEUE: RefCell<IngestionQueue> = RefCell::new(IngestionQueue::default());
+    static QUEUE_TIMER: RefCell<Option<ic_cdk_timers::TimerId>> = RefCell::new(None);
+    static JOBS: RefCell<BTreeMap<u64, JobStatus>> = RefCell::new(BTreeMap::new());
+    // Job IDs by the idempotency key of the `ingest` call that queued them
+    static INGEST_KEYS: RefCell<IdempotencyCache<u64>> = RefCell::new(IdempotencyCache::new(24 * 3600, 10_000));
+}
+
+/// Attempts at indexing an entity before its job fails
+const MAX_JOB_ATTEMPTS: u32 = 3;
+
+/// Vectors of index upkeep per `maintain_index` call without a budget
+const DEFAULT_MAINTAIN_BUDGET: u32 = 1_000;
+
+type Pipeline = RagPipeline<Box<dyn Embedder>, StableMemoryVectorStore>;
+
+/// Run `f` on the vector store, unless a pipeline call has it right now
+fn with_store<T>(f: impl FnOnce(&mut StableMemoryVectorStore) -> T) -> std::result::Result<T, String> {
+    VECTOR_STORE.with(|store| store.borrow_mut().as_mut().map(f).ok_or_else(store_busy))
+}
+
+fn store_busy() -> String {
+    "The vector store is in use by another call; retry shortly".to_string()
+}
+
+fn check_access(namespace: &str) -> std::result::Result<(), String> {
+    with_store(|store| store.check_access(namespace, &ic_cdk::api::caller()))?.map_err(|e| e.to_string())
+}
+
+/// The vector store for the length of one call that awaits it, put back
+/// when dropped like [`PipelineLease`]
+struct StoreLease(Option<StableMemoryVectorStore>);
+
+impl StoreLease {
+    fn new() -> std::result::Result<Self, String> {
+        let store = VECTOR_STORE.with(|store| store.borrow_mut().take()).ok_or_else(store_busy)?;
+        Ok(Self(Some(store)))
+    }
+}
+
+impl Deref for StoreLease {
+    type Target = StableMemoryVectorStore;
+
+    fn deref(&self) -> &StableMemoryVectorStore {
+        self.0.as_ref().expect("Store is held until the lease drops")
+    }
+}
+
+impl DerefMut for StoreLease {
+    fn deref_mut(&mut self) -> &mut StableMemoryVectorStore {
+        self.0.as_mut().expect("Store is held until the lease drops")
+    }
+}
+
+impl Drop for StoreLease {
+    fn drop(&mut self) {
+        if let Some(store) = self.0.take() {
+            VECTOR_STORE.with(|slot| *slot.borrow_mut() = Some(store));
+        }
+    }
+}
+
+/// A pipeline over the canister's vector store for the length of one call
+///
+/// Takes the store out of `VECTOR_STORE` and puts it back when dropped,
+/// also when the call traps after an await and ic-cdk drops its future.
+/// Calls arriving meanwhile get a retryable error instead of an empty store.
+struct PipelineLease(Option<Pipeline>);
+
+impl PipelineLease {
+    fn new() -> std::result::Result<Self, String> {
+        let config = CONFIG.with(|c| c.borrow().clone()).ok_or_else(|| "Configuration not set".to_string())?;
+        let embedder = KEYS.with(|k| embedders::from_key_store(&config.embedder, &config.outcalls, &k.borrow(), get_timestamp()))
+            .map_err(|e| e.to_string())?;
+        let store = VECTOR_STORE.with(|store| store.borrow_mut().take()).ok_or_else(store_busy)?;
+        Ok(Self(Some(RagPipeline::new(embedder, store, config.pipeline))))
+    }
+}
+
+impl Deref for PipelineLease {
+    type Target = Pipeline;
+
+    fn deref(&self) -> &Pipeline {
+        self.0.as_ref().expect("Pipeline is held until the lease drops")
+    }
+}
+
+impl DerefMut for PipelineLease {
+    fn deref_mut(&mut self) -> &mut Pipeline {
+        self.0.as_mut().expect("Pipeline is held until the lease drops")
+    }
+}
+
+impl Drop for PipelineLease {
+    fn drop(&mut self) {
+        if let Some(pipeline) = self.0.take() {
+            VECTOR_STORE.with(|store| *store.borrow_mut() = Some(pipeline.into_store()));
+        }
+    }
 }
 
 // ============================================================================
@@ -123,22 +244,55 @@ thread_local! {
 // ============================================================================
 
 #[init]
-fn init() {
+fn init(key_secret: Option<Vec<u8>>) {
+    KEY_SECRET.with(|s| *s.borrow_mut() = key_secret);
     ic_cdk::println!("User canister initialized");
 }
 
+fn vector_memory() -> impl ic_stable_structures::Memory {
+    MEMORY_MANAGER.with(|manager| memory(manager, &StableMemoryConfig::default(), ContragMemory::Vectors))
+}
+
+fn key_memory() -> impl ic_stable_structures::Memory {
+    MEMORY_MANAGER.with(|manager| memory(manager, &StableMemoryConfig::default(), ContragMemory::KeyStore))
+}
+
+fn provenance_memory() -> impl ic_stable_structures::Memory {
+    MEMORY_MANAGER.with(|manager| memory(manager, &StableMemoryConfig::default(), ContragMemory::Provenance))
+}
+
 #[pre_upgrade]
 fn pre_upgrade() {
-    VECTOR_STORE.with(|store| {
-        store.borrow().persist();
-    });
+    // Upgrades wait for the canister to stop, so no call holds the store
+    with_store(|store| store.persist(&vector_memory()))
+        .expect("Vector store is leased")
+        .expect("Failed to persist vectors");
+    provenance::persist_provenance(&provenance_memory()).expect("Failed to persist provenance");
+
+    // Without a secret the keys sealed at the last upgrade stay in place
+    if let Some(secret) = KEY_SECRET.with(|s| s.borrow().clone()) {
+        KEYS.with(|k| k.borrow().persist(&key_memory(), &secret)).expect("Failed to persist API keys");
+    }
 }
 
 #[post_upgrade]
-fn post_upgrade() {
-    VECTOR_STORE.with(|store| {
-        store.borrow().init();
-    });
+fn post_upgrade(key_secret: Option<Vec<u8>>) {
+    with_store(|store| store.init(&vector_memory()))
+        .expect("Vector store is leased")
+        .expect("Failed to restore vectors");
+    provenance::restore_provenance(&provenance_memory()).expect("Failed to restore provenance");
+
+    if let Some(secret) = &key_secret {
+        let keys = KeyStore::restore(&key_memory(), secret).expect("Failed to restore API keys");
+        KEYS.with(|k| *k.borrow_mut() = keys);
+    }
+    KEY_SECRET.with(|s| *s.borrow_mut() = key_secret);
+}
+
+/// Makes embedding API responses identical across replicas
+#[query]
+fn contrag_transform(args: TransformArgs) -> HttpResponse {
+    http_client::transform(args)
 }
 
 // ============================================================================
@@ -153,6 +307,15 @@ fn set_config(config_json: String) -> std::result::Result<String, String> {
     contrag_core::config::validate_config(&config)
         .map_err(|e| format!("Invalid config: {}", e))?;
     
+    contrag_core::concurrency::configure(&config.concurrency);
+    contrag_core::logs::configure(&config.logs);
+
+    // (Re)start draining the ingestion queue at the configured pace
+    let timer = start_queue_processor(&config.queue, process_queue);
+    if let Some(previous) = QUEUE_TIMER.with(|t| t.borrow_mut().replace(timer)) {
+        ic_cdk_timers::clear_timer(previous);
+    }
+
     CONFIG.with(|c| {
         *c.borrow_mut() = Some(config);
     });
@@ -160,12 +323,32 @@ fn set_config(config_json: String) -> std::result::Result<String, String> {
     Ok("Configuration set successfully".to_string())
 }
 
+/// Seconds a replaced API key stays available after a rotation
+const KEY_GRACE_SECS: u64 = 3600;
+
+#[update]
+fn set_api_key(provider: String, key: String) -> std::result::Result<String, String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::api::caller()) {
+        return Err("Only controllers may set API keys".to_string());
+    }
+
+    KEYS.with(|k| k.borrow_mut().rotate(provider, key, KEY_GRACE_SECS, get_timestamp()));
+    Ok("API key set successfully".to_string())
+}
+
 #[update]
-fn set_api_key(key: String) -> String {
-    API_KEY.with(|k| {
-        *k.borrow_mut() = Some(key);
-    });
-    "API key set successfully".to_string()
+async fn verify_embedder() -> std::result::Result<String, String> {
+    let config = CONFIG.with(|c| {
+        c.borrow()
+            .clone()
+            .ok_or_else(|| "Configuration not set".to_string())
+    })?;
+
+    // Catch a model/config dimension mismatch before anything is indexed
+    let embedder = KEYS.with(|k| embedders::from_key_store(&config.embedder, &config.outcalls, &k.borrow(), get_timestamp())).map_err(|e| e.to_string())?;
+    embedder.verify(&config.embedder).await.map_err(|e| e.to_string())?;
+
+    Ok(format!("{} embeddings have {} dimensions", embedder.name(), config.embedder.dimensions))
 }
 
 // ============================================================================
@@ -173,12 +356,26 @@ fn set_api_key(key: String) -> String {
 // ============================================================================
 
 #[update]
-fn create_user(user: User) -> String {
+fn create_user(user: User) -> std::result::Result<String, String> {
     let user_id = user.id.clone();
+    if USERS.with(|users| users.borrow().contains_key(&user_id)) {
+        return Err(format!("User already exists: {}", user_id));
+    }
+
+    // Only the creating principal may read this user's RAG context; a
+    // namespace someone else already owns can't be claimed
+    let namespace = Namespace::entity::<User>(&user_id).map_err(|e| e.to_string())?;
+    let caller = ic_cdk::api::caller();
+    with_store(|store| {
+        store.check_access(&namespace, &caller).map_err(|e| e.to_string())?;
+        store.set_namespace_owner(&namespace, caller);
+        Ok::<(), String>(())
+    })??;
+
     USERS.with(|users| {
         users.borrow_mut().insert(user_id.clone(), user);
     });
-    user_id
+    Ok(user_id)
 }
 
 #[query]
@@ -218,12 +415,6 @@ async fn build_user_rag_context(user_id: String) -> std::result::Result<String,
             .ok_or_else(|| "Configuration not set. Call set_config first.".to_string())
     })?;
 
-    let api_key = API_KEY.with(|k| {
-        k.borrow()
-            .clone()
-            .ok_or_else(|| "API key not set. Call set_api_key first.".to_string())
-    })?;
-
     // Get user
     let user = get_user(user_id.clone())
         .ok_or_else(|| format!("User not found: {}", user_id))?;
@@ -251,7 +442,7 @@ async fn build_user_rag_context(user_id: String) -> std::result::Result<String,
     let chunks = context_builder.chunk_text(&full_context);
     
     // Create embedder
-    let embedder = OpenAIEmbedder::new(api_key, config.embedder.model.clone());
+    let embedder = KEYS.with(|k| embedders::from_key_store(&config.embedder, &config.outcalls, &k.borrow(), get_timestamp())).map_err(|e| e.to_string())?;
     
     // Generate embeddings
     let texts: Vec<String> = chunks.iter().map(|c| c.text.clone()).collect();
@@ -261,12 +452,10 @@ async fn build_user_rag_context(user_id: String) -> std::result::Result<String,
         .map_err(|e| format!("Failed to generate embeddings: {}", e))?;
     
     // Store vectors
-    let namespace = format!("User:{}", user_id);
+    let namespace = Namespace::entity::<User>(&user_id).map_err(|e| e.to_string())?;
     let timestamp = get_timestamp();
     
-    VECTOR_STORE.with(|store| {
-        let mut store = store.borrow_mut();
-        
+    with_store(|store| {
         for (idx, (chunk, embedding)) in chunks.iter().zip(embeddings.iter()).enumerate() {
             let vector = Vector {
                 id: generate_vector_id("User", &user_id, idx),
@@ -279,6 +468,7 @@ async fn build_user_rag_context(user_id: String) -> std::result::Result<String,
                     total_chunks: chunks.len(),
                     timestamp,
                     custom: None,
+                    ttl_seconds: None,
                 },
             };
             
@@ -289,7 +479,7 @@ async fn build_user_rag_context(user_id: String) -> std::result::Result<String,
         }
         
         Ok::<(), String>(())
-    })?;
+    })??;
     
     Ok(format!(
         "Built RAG context for user {} with {} chunks",
@@ -306,16 +496,10 @@ async fn search_user_context(user_id: String, query: String, k: u32) -> std::res
             .ok_or_else(|| "Configuration not set".to_string())
     })?;
 
-    let api_key = API_KEY.with(|k| {
-        k.borrow()
-            .clone()
-            .ok_or_else(|| "API key not set".to_string())
-    })?;
-
     // Generate query embedding
-    let embedder = OpenAIEmbedder::new(api_key, config.embedder.model.clone());
+    let embedder = KEYS.with(|k| embedders::from_key_store(&config.embedder, &config.outcalls, &k.borrow(), get_timestamp())).map_err(|e| e.to_string())?;
     let query_embeddings = embedder
-        .embed(vec![query])
+        .embed_queries(vec![query])
         .await
         .map_err(|e| format!("Failed to generate query embedding: {}", e))?;
     
@@ -324,23 +508,233 @@ async fn search_user_context(user_id: String, query: String, k: u32) -> std::res
         .ok_or_else(|| "No embedding generated".to_string())?;
 
     // Search vector store
-    let namespace = format!("User:{}", user_id);
+    let namespace = Namespace::entity::<User>(&user_id).map_err(|e| e.to_string())?;
+    with_store(|store| store.check_access(&namespace, &ic_cdk::api::caller()))?
+        .map_err(|e| e.to_string())?;
     
-    VECTOR_STORE.with(|store| {
-        let store = store.borrow();
+    with_store(|store| {
         // Note: In real implementation, we'd use async properly
         Ok(vec![]) // Placeholder
+    })?
+}
+
+#[query]
+fn get_rag_stats(user_id: String) -> std::result::Result<NamespaceStats, String> {
+    let namespace = Namespace::entity::<User>(&user_id).map_err(|e| e.to_string())?;
+
+    with_store(|store| {
+        store.check_access(&namespace, &ic_cdk::api::caller()).map_err(|e| e.to_string())?;
+        Ok(store.namespace_stats(&namespace))
+    })?
+}
+
+#[query]
+fn get_global_rag_stats() -> std::result::Result<StoreStats, String> {
+    with_store(|store| store.store_stats())
+}
+
+#[query]
+fn list_namespaces(prefix: Option<String>, cursor: Option<String>, limit: u32) -> std::result::Result<NamespacePage, String> {
+    let request = NamespaceListRequest {
+        prefix,
+        start_after: cursor,
+        limit: limit as usize,
+    };
+
+    with_store(|store| store.namespace_page(&request))
+}
+
+// ============================================================================
+// Standard Endpoints (contrag_core::api)
+// ============================================================================
+
+/// Queue an entity for indexing; poll the returned job with `job_status`
+#[update]
+fn ingest(request: IngestRequest) -> std::result::Result<IngestResponse, String> {
+    let found = match request.entity_type.as_str() {
+        "User" => get_user(request.entity_id.clone()).is_some(),
+        "Order" => get_order(request.entity_id.clone()).is_some(),
+        other => return Err(format!("Unknown entity type: {}", other)),
+    };
+    if !found {
+        return Err(format!("{} not found: {}", request.entity_type, request.entity_id));
+    }
+
+    let namespace = request
+        .namespace
+        .unwrap_or_else(|| format!("{}:{}", request.entity_type, request.entity_id));
+    let namespace = Namespace::parse(&namespace).map_err(|e| e.to_string())?.into_string();
+    check_access(&namespace)?;
+
+    let fingerprint = idempotency::fingerprint(&[&request.entity_type, &request.entity_id, &namespace]);
+    if let Some(key) = &request.idempotency_key {
+        match INGEST_KEYS.with(|keys| keys.borrow_mut().claim(key, fingerprint, get_timestamp())) {
+            Claim::New => {}
+            Claim::Replay(job_id) => return Ok(IngestResponse { job_id }),
+            Claim::InProgress => return Err(format!("The request with idempotency key {} is still running", key)),
+            Claim::Conflict => return Err(format!("Idempotency key {} was used for a different request", key)),
+        }
+    }
+
+    let priority = request.priority.unwrap_or(IngestionPriority::Normal);
+    let job_id = QUEUE.with(|q| {
+        q.borrow_mut()
+            .enqueue(&request.entity_type, &request.entity_id, &namespace, priority)
+    });
+    // An entity already queued for the namespace keeps its job
+    JOBS.with(|jobs| {
+        jobs.borrow_mut().entry(job_id).or_insert_with(|| JobStatus {
+            job_id,
+            state: JobState::Queued,
+            entity_type: request.entity_type.clone(),
+            entity_id: request.entity_id.clone(),
+            namespace,
+            attempts: 0,
+            chunks_stored: 0,
+            error: None,
+            updated_at: get_timestamp(),
+        });
+    });
+    if let Some(key) = &request.idempotency_key {
+        INGEST_KEYS.with(|keys| keys.borrow_mut().complete(key, job_id));
+    }
+
+    Ok(IngestResponse { job_id })
+}
+
+#[query]
+fn job_status(job_id: u64) -> std::result::Result<JobStatus, String> {
+    JOBS.with(|jobs| jobs.borrow().get(&job_id).cloned())
+        .ok_or_else(|| format!("Unknown job: {}", job_id))
+}
+
+/// Index up to `batch_size` queued entities, one after the other
+async fn process_queue(batch_size: usize) {
+    let jobs = QUEUE.with(|q| q.borrow_mut().next_batch(batch_size));
+    for job in jobs {
+        update_job(job.id, |status| {
+            status.state = JobState::Running;
+            status.attempts = job.attempts + 1;
+        });
+
+        match index_entity(&job).await {
+            Ok(chunks) => update_job(job.id, |status| {
+                status.state = JobState::Completed;
+                status.chunks_stored = chunks as u32;
+                status.error = None;
+            }),
+            Err(e) if job.attempts + 1 < MAX_JOB_ATTEMPTS => {
+                update_job(job.id, |status| {
+                    status.state = JobState::Queued;
+                    status.error = Some(e);
+                });
+                QUEUE.with(|q| q.borrow_mut().requeue(job));
+            }
+            Err(e) => update_job(job.id, |status| {
+                status.state = JobState::Failed;
+                status.error = Some(e);
+            }),
+        }
+    }
+}
+
+async fn index_entity(job: &IngestionJob) -> std::result::Result<usize, String> {
+    let mut pipeline = PipelineLease::new()?;
+    let chunks = match job.entity_type.as_str() {
+        "User" => {
+            let user = get_user(job.entity_id.clone()).ok_or_else(|| format!("User not found: {}", job.entity_id))?;
+            pipeline.ingest_entity(&job.namespace, &user).await
+        }
+        "Order" => {
+            let order = get_order(job.entity_id.clone()).ok_or_else(|| format!("Order not found: {}", job.entity_id))?;
+            pipeline.ingest_entity(&job.namespace, &order).await
+        }
+        other => return Err(format!("Unknown entity type: {}", other)),
+    };
+    chunks.map_err(|e| e.to_string())
+}
+
+fn update_job(job_id: u64, f: impl FnOnce(&mut JobStatus)) {
+    JOBS.with(|jobs| {
+        if let Some(status) = jobs.borrow_mut().get_mut(&job_id) {
+            f(status);
+            status.updated_at = get_timestamp();
+        }
+    });
+}
+
+/// Pipelines live for one call here, so there is no result cache to serve
+/// stale entries from: `allow_stale` is accepted and every search is live.
+#[update]
+async fn search(request: SearchRequest) -> std::result::Result<SearchResponse, String> {
+    check_access(&request.namespace)?;
+
+    let pipeline = PipelineLease::new()?;
+    let max_chunks_per_entity = request
+        .max_chunks_per_entity
+        .map(|max| max as usize)
+        .or(pipeline.config().max_chunks_per_entity);
+    let results = pipeline
+        .search_capped(&request.namespace, &request.query, request.k.map(|k| k as usize), max_chunks_per_entity)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(SearchResponse {
+        namespace: request.namespace,
+        results,
+        stale: false,
     })
 }
 
+#[update]
+async fn ask(request: AskRequest) -> std::result::Result<Answer, String> {
+    check_access(&request.namespace)?;
+
+    let pipeline = PipelineLease::new()?;
+    let answer = match (&request.profile, request.k) {
+        (Some(profile), _) => pipeline.answer_with_profile(&request.namespace, &request.question, profile).await,
+        (None, Some(k)) => pipeline.answer(&request.namespace, &request.question, Some(k as usize)).await,
+        (None, None) => pipeline.answer_routed(&request.namespace, &request.question).await,
+    };
+    answer.map_err(|e| e.to_string())
+}
+
 #[query]
-fn get_rag_stats(user_id: String) -> std::result::Result<String, String> {
-    let namespace = format!("User:{}", user_id);
-    
-    VECTOR_STORE.with(|store| {
-        let store = store.borrow();
-        // Would use async count() in real implementation
-        Ok(format!("Stats for namespace: {}", namespace))
+fn stats(prefix: Option<String>) -> std::result::Result<PrefixStats, String> {
+    with_store(|store| store.prefix_stats(prefix.as_deref().unwrap_or_default()))
+}
+
+/// Store vectors embedded off-chain, one `ImportVectorsRequest::split`
+/// request per call
+#[update]
+async fn import_vectors(request: ImportVectorsRequest) -> std::result::Result<BatchWriteReport, String> {
+    check_access(&request.namespace)?;
+
+    let mut store = StoreLease::new()?;
+    let on_duplicate = request.on_duplicate.unwrap_or(DuplicateIdMode::Skip);
+    import::import_vectors(&mut *store, &request.namespace, request.vectors, on_duplicate, DEFAULT_IMPORT_BATCH)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Do a bounded amount of index upkeep; call again until `finished`
+#[update]
+fn maintain_index(budget: Option<u32>) -> std::result::Result<MaintainIndexResponse, String> {
+    let budget = budget.unwrap_or(DEFAULT_MAINTAIN_BUDGET) as usize;
+    let finished = with_store(|store| store.maintain(budget))?.map_err(|e| e.to_string())?;
+    Ok(MaintainIndexResponse { finished })
+}
+
+#[update]
+async fn ingest_document(document: Document) -> std::result::Result<IngestDocumentResponse, String> {
+    let namespace = document.namespace().map_err(|e| e.to_string())?.into_string();
+    check_access(&namespace)?;
+
+    let mut pipeline = PipelineLease::new()?;
+    let chunks = pipeline.ingest_document(&document).await.map_err(|e| e.to_string())?;
+    Ok(IngestDocumentResponse {
+        namespace,
+        chunks: chunks as u32,
     })
 }
 
@@ -381,7 +775,9 @@ fn seed_demo_data() -> String {
         created_at: get_timestamp(),
     };
 
-    create_user(user1);
+    if let Err(e) = create_user(user1) {
+        return e;
+    }
     create_order(order1);
     create_order(order2);
 
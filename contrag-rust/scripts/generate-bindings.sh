@@ -0,0 +1,24 @@
+#!/usr/bin/env bash
+# Generate TypeScript/JavaScript bindings from the canonical candid interface.
+#
+# Requires didc (https://github.com/dfinity/candid/releases). Run from
+# anywhere; output goes to contrag-rust/bindings/. Commit the result so web
+# dapps can use the bindings without installing didc.
+set -euo pipefail
+
+ROOT="$(cd "$(dirname "${BASH_SOURCE[0]}")/.." && pwd)"
+DID="$ROOT/contrag-core/contrag.did"
+OUT="$ROOT/bindings"
+
+if ! command -v didc >/dev/null 2>&1; then
+  echo "didc not found; install it from https://github.com/dfinity/candid/releases" >&2
+  exit 1
+fi
+
+mkdir -p "$OUT"
+didc check "$DID"
+didc bind "$DID" --target ts > "$OUT/contrag.did.d.ts"
+didc bind "$DID" --target js > "$OUT/contrag.did.js"
+cp "$DID" "$OUT/contrag.did"
+
+echo "Bindings written to $OUT"